@@ -0,0 +1,23 @@
+#!/bin/bash
+# Builds freertos-rust without the default `fmt` feature and checks the library for
+# symbols of core::fmt that the feature is there to leave out: the crate's Display
+# impls and the float formatting. Debug derives are left to the linker to drop.
+#
+#     ./check-tiny.sh [target]
+
+set -e
+
+TARGET=${1:-x86_64-unknown-linux-gnu}
+
+cargo build -p freertos-rust --no-default-features --target "$TARGET"
+
+RLIB="target/$TARGET/debug/libfreertos_rust.rlib"
+FOUND=$(nm -C "$RLIB" 2>/dev/null | grep -E "freertos_rust::[^ ]* as core::fmt::Display>|core::fmt::float" || true)
+
+if [ -n "$FOUND" ]; then
+    echo "core::fmt left in the build without the fmt feature:"
+    echo "$FOUND"
+    exit 1
+fi
+
+echo "No Display impls or float formatting without the fmt feature"
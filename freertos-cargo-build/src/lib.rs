@@ -20,6 +20,34 @@ const ENV_KEY_FREERTOS_CONFIG: &str = "DEP_FREERTOS_CONFIG";
 /// This variable is set by freertos-rust build.rs
 const ENV_KEY_FREERTOS_SHIM: &str = "DEP_FREERTOS_SHIM";
 
+/// Set by freertos-rust build.rs when its fault_inject feature is enabled,
+/// compiles the fault injection hooks into shim.c
+const ENV_KEY_FREERTOS_FAULT_INJECT: &str = "DEP_FREERTOS_FAULT_INJECT";
+
+/// Set by freertos-rust build.rs when its heap_4 feature is enabled,
+/// heap_4_realloc.c from the shim folder is compiled instead of heap_4.c
+const ENV_KEY_FREERTOS_HEAP_4_REALLOC: &str = "DEP_FREERTOS_HEAP_4_REALLOC";
+
+/// Set by freertos-rust build.rs when its heap_5 feature is enabled,
+/// heap_5.c becomes the default heap and the heap region shim is compiled
+const ENV_KEY_FREERTOS_HEAP_5: &str = "DEP_FREERTOS_HEAP_5";
+
+/// Set by freertos-rust build.rs when its port_inside_interrupt feature is enabled,
+/// the shim asks the port with xPortIsInsideInterrupt
+const ENV_KEY_FREERTOS_INSIDE_INTERRUPT: &str = "DEP_FREERTOS_INSIDE_INTERRUPT";
+
+/// Set by freertos-rust build.rs when its notify_indexed feature is enabled,
+/// compiles the shims of the indexed notification API of FreeRTOS 10.4
+const ENV_KEY_FREERTOS_NOTIFY_INDEXED: &str = "DEP_FREERTOS_NOTIFY_INDEXED";
+
+/// Set by freertos-rust build.rs when its end_scheduler feature is enabled,
+/// compiles the shims ending the scheduler of the hosted ports
+const ENV_KEY_FREERTOS_END_SCHEDULER: &str = "DEP_FREERTOS_END_SCHEDULER";
+
+/// Set by freertos-rust build.rs when its mpu feature is enabled,
+/// the MPU port of the target and its system call wrappers are compiled
+const ENV_KEY_FREERTOS_MPU: &str = "DEP_FREERTOS_MPU";
+
 #[derive(Clone, Debug)]
 pub struct Builder {
     freertos_dir: PathBuf,
@@ -68,7 +96,11 @@ impl Builder {
             freertos_shim: PathBuf::from(freertos_shim),
             freertos_port: None,
             cc: cc::Build::new(),
-            heap_c: String::from("heap_4.c"),
+            heap_c: String::from(if env::var(ENV_KEY_FREERTOS_HEAP_5).is_ok() {
+                "heap_5.c"
+            } else {
+                "heap_4.c"
+            }),
         };
         return b;
     }
@@ -143,7 +175,7 @@ impl Builder {
     }
 
     /// Set the heap_?.c file to use from the "/portable/MemMang/" folder.
-    /// heap_1.c ... heap_5.c (Default: heap_4.c)
+    /// heap_1.c ... heap_5.c (Default: heap_4.c, heap_5.c with the heap_5 feature of freertos-rust)
     /// see also: https://www.freertos.org/a00111.html
     pub fn heap<P: AsRef<Path>>(&mut self, file_name: String) {
         self.heap_c = file_name;
@@ -180,6 +212,7 @@ impl Builder {
         let port = match (target.as_str(), target_arch.as_str(), target_os.as_str(), target_env.as_str()) {
             (_, "x86_64", "windows", _) => "MSVC-MingW",
             (_, "x86_64", "linux", "gnu") => "GCC/Linux",
+            ("thumbv7m-none-eabi", _, _, _) if self.mpu() => "GCC/ARM_CM3_MPU",
             ("thumbv7m-none-eabi", _, _, _) => "GCC/ARM_CM3",
             // TODO We should support feature "trustzone"
             ("thumbv8m.main-none-eabi", _, _, _) => "GCC/ARM_CM33_NTZ/non_secure",
@@ -194,6 +227,19 @@ impl Builder {
     fn heap_c_file(&self) -> PathBuf {
         self.freertos_dir.join("portable/MemMang").join(self.heap_c.as_str())
     }
+    /// freertos-rust wants heap_4 with in-place realloc
+    fn heap_4_realloc(&self) -> bool {
+        env::var(ENV_KEY_FREERTOS_HEAP_4_REALLOC).is_ok()
+    }
+    /// freertos-rust defines the heap_5 regions itself
+    fn heap_5(&self) -> bool {
+        env::var(ENV_KEY_FREERTOS_HEAP_5).is_ok()
+    }
+    /// freertos-rust creates restricted tasks, which need an MPU port
+    fn mpu(&self) -> bool {
+        env::var(ENV_KEY_FREERTOS_MPU).is_ok()
+    }
+
     fn shim_c_file(&self) -> PathBuf {
         self.freertos_shim.join("shim.c")
     }
@@ -218,6 +264,12 @@ impl Builder {
         if !heap_c.clone().exists() || !heap_c.clone().is_file() {
             return Err(Error::new(&format!("File heap_?.c does not exist: {}", heap_c.to_str().unwrap())));
         }
+        if self.heap_4_realloc() && self.heap_c != "heap_4.c" {
+            return Err(Error::new(&format!("The heap_4 feature of freertos-rust needs heap_4.c, not {}", self.heap_c)));
+        }
+        if self.heap_5() && self.heap_c != "heap_5.c" {
+            return Err(Error::new(&format!("The heap_5 feature of freertos-rust needs heap_5.c, not {}", self.heap_c)));
+        }
 
         // Allows to find the FreeRTOSConfig.h
         if !self.freertos_config_dir.clone().exists() {
@@ -246,7 +298,13 @@ impl Builder {
         // FreeRTOS port header files (e.g. portmacro.h)
         b.include(self.get_freertos_port_dir());
         b.include(self.freertos_config_dir.clone());
-        b.file(self.heap_c_file());
+        if self.heap_4_realloc() {
+            // heap_4_realloc.c in the shim folder includes heap_4.c
+            b.include(self.heap_c_file().parent().unwrap());
+            b.define("FREERTOS_RS_HEAP_4_REALLOC", None);
+        } else {
+            b.file(self.heap_c_file());
+        }
         self.freertos_files().iter().for_each(|f| {
             b.file(f);
         });
@@ -256,6 +314,25 @@ impl Builder {
         self.freertos_shim_files().iter().for_each(|f| {
             b.file(f);
         });
+        if env::var(ENV_KEY_FREERTOS_FAULT_INJECT).is_ok() {
+            b.define("FREERTOS_RS_FAULT_INJECT", None);
+        }
+        if self.heap_5() {
+            b.define("FREERTOS_RS_HEAP_5", None);
+        }
+        if env::var(ENV_KEY_FREERTOS_INSIDE_INTERRUPT).is_ok() {
+            b.define("FREERTOS_RS_INSIDE_INTERRUPT", None);
+        }
+        if env::var(ENV_KEY_FREERTOS_NOTIFY_INDEXED).is_ok() {
+            b.define("FREERTOS_RS_NOTIFY_INDEXED", None);
+        }
+        if env::var(ENV_KEY_FREERTOS_END_SCHEDULER).is_ok() {
+            b.define("FREERTOS_RS_END_SCHEDULER", None);
+        }
+        if self.mpu() {
+            b.file(self.freertos_dir.join("portable/Common/mpu_wrappers.c"));
+            b.define("FREERTOS_RS_MPU", None);
+        }
 
         let res = b.try_compile("freertos");
         if res.is_err() {
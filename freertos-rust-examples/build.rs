@@ -32,10 +32,51 @@ fn main() {
         b.freertos_config("examples/linux");
 
         b.get_cc().file("examples/linux/hooks.c");
+        // Virtual time of the test_support module, the hooks call into it.
+        let shim = env::var("DEP_FREERTOS_SHIM").unwrap();
+        b.get_cc()
+            .file(PathBuf::from(shim).join("ports/linux/virtual_time.c"));
         // b.get_cc().file("examples/linux/Run-time-stats-utils.c"); // Unimplemented yet..
+
+        // The kernel has to agree with FreeRtosStackDepthType.
+        if env::var("CARGO_FEATURE_STACK_DEPTH_U32").is_ok() {
+            b.get_cc().define("configSTACK_DEPTH_TYPE", "uint32_t");
+        }
+
+        // The test harness of the host allocates from the FreeRTOS heap as well.
+        if env::var("CARGO_FEATURE_HOSTED_TESTS").is_ok() {
+            b.get_cc()
+                .define("configTOTAL_HEAP_SIZE", "((size_t)(128 * 1024))");
+        }
+
+        // The queues and semaphores of freertos-rust without the queue sets of the kernel.
+        if env::var("CARGO_FEATURE_NO_QUEUE_SETS").is_ok() {
+            b.get_cc().define("configUSE_QUEUE_SETS", "0");
+        }
+
+        // C middleware calling the CMSIS-RTOS2 functions of freertos-rust.
+        if env::var("CARGO_FEATURE_CMSIS_COMPAT").is_ok() {
+            b.get_cc().include("examples/cmsis");
+            b.get_cc().file("examples/cmsis/cmsis_test.c");
+        }
+
+        // A legacy C driver calling the c_hooks functions of freertos-rust.
+        if env::var("CARGO_FEATURE_C_HOOKS").is_ok() {
+            b.get_cc()
+                .include(env::var("DEP_FREERTOS_C_HOOKS_INCLUDE").unwrap());
+            b.get_cc().file("examples/c_hooks/c_hooks_test.c");
+        }
     }
 
-    if target == "thumbv7m-none-eabi" {
+    // The MPU port runs the mpu_restricted example on QEMU's LM3S6965.
+    if target == "thumbv7m-none-eabi" && env::var("CARGO_FEATURE_MPU").is_ok() {
+        b.freertos_config("examples/mpu_restricted");
+        copy(
+            "examples/mpu_restricted/memory.x",
+            PathBuf::from(out_dir.as_str()).join("memory.x"),
+        )
+        .unwrap();
+    } else if target == "thumbv7m-none-eabi" {
         b.freertos_config("examples/stm32-cortex-m3");
         copy(
             "examples/stm32-cortex-m3/memory.x",
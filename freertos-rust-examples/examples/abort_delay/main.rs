@@ -0,0 +1,87 @@
+//! A shutdown task wakes a housekeeping task from a 10 second delay and a listener from
+//! a queue receive with `TaskRemoteHandle::abort_delay`. The process exits with the
+//! number of failed checks.
+//!
+//!     cargo run --example abort_delay --target x86_64-unknown-linux-gnu
+use freertos_rust::*;
+use std::sync::atomic::{AtomicBool, AtomicU32, Ordering};
+use std::sync::Arc;
+
+#[global_allocator]
+static GLOBAL: FreeRtosAllocator = FreeRtosAllocator;
+
+static SHUTDOWN: AtomicBool = AtomicBool::new(false);
+/// Ticks the housekeeping task slept, `u32::MAX` until it woke up.
+static SLEPT: AtomicU32 = AtomicU32::new(u32::MAX);
+/// `FreeRtosError::code` of the listener's receive, 0 until it returned.
+static RECEIVE_ERROR: AtomicU32 = AtomicU32::new(0);
+
+extern "C" {
+    // `std::process::exit` runs the exit handlers of the POSIX port, which cancel the
+    // threads the tasks run on while they are in Rust code.
+    fn _exit(status: i32) -> !;
+}
+
+fn main() {
+    FreeRTOS::start_scheduler(|os| {
+        let housekeeping = os
+            .new_task("housekeeper", 256, TaskPriority(1), move |_, os| {
+                let start = os.get_tick_count();
+                os.delay(Duration::ms(10_000));
+                if SHUTDOWN.load(Ordering::Relaxed) {
+                    SLEPT.store(os.get_tick_count() - start, Ordering::Relaxed);
+                }
+                loop {
+                    os.delay(Duration::ms(10_000));
+                }
+            })
+            .unwrap();
+
+        let queue = Arc::new(os.new_queue::<u32>(1).unwrap());
+        let listener = os
+            .new_task("listener", 256, TaskPriority(1), move |_, os| {
+                if let Err(e) = queue.receive(Duration::infinite()) {
+                    RECEIVE_ERROR.store(e.code() as u32, Ordering::Relaxed);
+                }
+                loop {
+                    os.delay(Duration::ms(10_000));
+                }
+            })
+            .unwrap();
+
+        os.new_task("shutdown", 512, TaskPriority(2), move |this, os| {
+            let mut failures = 0;
+            os.delay(Duration::ms(100));
+
+            SHUTDOWN.store(true, Ordering::Relaxed);
+            if housekeeping.abort_delay().is_err() || listener.abort_delay().is_err() {
+                println!("abort_delay failed on a blocked task");
+                failures += 1;
+            }
+            os.delay(Duration::ms(10));
+
+            let slept = SLEPT.load(Ordering::Relaxed);
+            if slept > 110 {
+                println!("housekeeping task not woken early: {}", slept);
+                failures += 1;
+            }
+            let receive_error = RECEIVE_ERROR.load(Ordering::Relaxed);
+            if receive_error != FreeRtosError::QueueReceiveTimeout.code() as u32 {
+                println!("listener's receive returned error code {}", receive_error);
+                failures += 1;
+            }
+
+            match this.new_remote_handle().abort_delay() {
+                Err(FreeRtosError::TaskNotBlocked) => {}
+                r => {
+                    println!("abort_delay on a running task: {:?}", r);
+                    failures += 1;
+                }
+            }
+
+            println!("woken after {} ms, {} failures", slept, failures);
+            unsafe { _exit(failures) }
+        })
+        .unwrap();
+    });
+}
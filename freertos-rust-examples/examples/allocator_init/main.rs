@@ -0,0 +1,132 @@
+//! Sets up heap_4 with `allocator::init_with_low_water` and checks that:
+//!
+//! * `is_initialized` tells whether it was called,
+//! * the low-water callback is called once, from the idle task, when the free heap space
+//!   first drops below the threshold,
+//! * `heap_stats` reports heap_4's statistics,
+//! * without `alloc_checks`, allocating through `FreeRtosAllocator` costs at most 25%
+//!   more than calling `pvPortMalloc` and `vPortFree` directly. This is only checked in
+//!   release builds.
+//!
+//! The process exits with the number of failed checks.
+//!
+//!     cargo run --release --example allocator_init --target x86_64-unknown-linux-gnu
+use freertos_rust::*;
+use std::alloc::{GlobalAlloc, Layout};
+use std::sync::atomic::{AtomicU32, AtomicUsize, Ordering};
+use std::time::Instant;
+
+#[global_allocator]
+static GLOBAL: FreeRtosAllocator = FreeRtosAllocator;
+
+const LOW_WATER_MARGIN: usize = 2048;
+const ITERATIONS: u32 = 2000;
+const BATCHES: u32 = 5;
+
+static LOW_WATER_CALLS: AtomicU32 = AtomicU32::new(0);
+static LOWEST: AtomicUsize = AtomicUsize::new(0);
+
+extern "C" {
+    // `std::process::exit` runs the exit handlers of the POSIX port, which cancel the
+    // threads the tasks run on while they are in Rust code.
+    fn _exit(status: i32) -> !;
+}
+
+fn on_low_water(lowest: usize) {
+    LOW_WATER_CALLS.fetch_add(1, Ordering::Relaxed);
+    LOWEST.store(lowest, Ordering::Relaxed);
+}
+
+/// The fastest of a few batches of `ITERATIONS` calls of `f`, in nanoseconds per call.
+fn cost<F: FnMut()>(mut f: F) -> u128 {
+    (0..BATCHES)
+        .map(|_| {
+            let start = Instant::now();
+            for _ in 0..ITERATIONS {
+                f();
+            }
+            start.elapsed().as_nanos() / ITERATIONS as u128
+        })
+        .min()
+        .unwrap()
+}
+
+fn main() {
+    FreeRTOS::start_scheduler(|os| {
+        os.new_task("checks", 512, TaskPriority(2), move |_, os| {
+            let mut failures = 0;
+
+            // Everything so far allocated without it.
+            if allocator::is_initialized() {
+                println!("initialized before init");
+                failures += 1;
+            }
+            let threshold = FreeRtosAllocator::minimum_ever_free_heap_size() - LOW_WATER_MARGIN;
+            allocator::init_with_low_water(threshold, on_low_water);
+            if !allocator::is_initialized() {
+                println!("not initialized after init");
+                failures += 1;
+            }
+
+            let stats = FreeRtosAllocator::heap_stats();
+            match stats {
+                Some(stats)
+                    if stats.available == FreeRtosAllocator::free_heap_size()
+                        && stats.allocations > stats.frees => {}
+                _ => {
+                    println!("heap stats {:?}", stats);
+                    failures += 1;
+                }
+            }
+
+            // Above the threshold, nothing is reported.
+            let small = vec![0u8; LOW_WATER_MARGIN / 2];
+            os.delay(Duration::ms(10));
+            let calls_above = LOW_WATER_CALLS.load(Ordering::Relaxed);
+            drop(small);
+
+            // Below it, once.
+            for _ in 0..3 {
+                let large = vec![0u8; 2 * LOW_WATER_MARGIN];
+                os.delay(Duration::ms(10));
+                drop(large);
+            }
+            let lowest = LOWEST.load(Ordering::Relaxed);
+            if calls_above != 0
+                || LOW_WATER_CALLS.load(Ordering::Relaxed) != 1
+                || lowest >= threshold
+            {
+                println!(
+                    "{} low-water calls above the threshold, {} below, lowest {} for threshold {}",
+                    calls_above,
+                    LOW_WATER_CALLS.load(Ordering::Relaxed),
+                    lowest,
+                    threshold
+                );
+                failures += 1;
+            }
+
+            let layout = Layout::from_size_align(64, 8).unwrap();
+            let direct = cost(|| unsafe {
+                let block = freertos_rs_pvPortMalloc(64);
+                freertos_rs_vPortFree(block);
+            });
+            let global = cost(|| unsafe {
+                let block = GLOBAL.alloc(layout);
+                GLOBAL.dealloc(block, layout);
+            });
+            println!(
+                "ns per allocation and free: {} direct, {} through FreeRtosAllocator",
+                direct, global
+            );
+            if global * 4 > direct * 5 && !cfg!(debug_assertions) {
+                println!("FreeRtosAllocator over budget");
+                failures += 1;
+            }
+
+            println!("{} failures", failures);
+            unsafe { _exit(failures) }
+        })
+        .unwrap();
+    });
+}
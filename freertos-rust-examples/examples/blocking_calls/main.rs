@@ -0,0 +1,290 @@
+//! Checks the error each blocking wrapper returns when it fails, then runs 192 call sites
+//! of them, as a synthetic binary for comparing the code size of the wrappers:
+//!
+//!     cargo build --release --example blocking_calls --target x86_64-unknown-linux-gnu
+//!     size target/x86_64-unknown-linux-gnu/release/examples/blocking_calls
+//!
+//! The process exits with the number of failed checks.
+use freertos_rust::*;
+use std::sync::Arc;
+
+#[global_allocator]
+static GLOBAL: FreeRtosAllocator = FreeRtosAllocator;
+
+extern "C" {
+    // `std::process::exit` runs the exit handlers of the POSIX port, which cancel the
+    // threads the tasks run on while they are in Rust code.
+    fn _exit(status: i32) -> !;
+}
+
+fn check<T: std::fmt::Debug>(
+    failures: &mut i32,
+    what: &str,
+    result: Result<T, FreeRtosError>,
+    expected: FreeRtosError,
+) {
+    match result {
+        Err(e) if e == expected => {}
+        r => {
+            println!("{}: {:?}, expected Err({:?})", what, r, expected);
+            *failures += 1;
+        }
+    }
+}
+
+/// 12 call sites of the wrappers, with four item types and both duration types.
+macro_rules! call_sites {
+    ($queues:expr, $mutex:expr, $recursive:expr, $semaphore:expr, $events:expr, $stream:expr) => {{
+        let (bytes, words, blocks, pairs) = &$queues;
+        let _ = bytes.send(1, Duration::zero());
+        let _ = words.send(2, core::time::Duration::from_millis(0));
+        let _ = blocks.send_to_front([3; 16], Duration::zero());
+        let _ = pairs.receive(Duration::zero());
+        let _ = words.receive(core::time::Duration::from_millis(0));
+        drop($mutex.lock(Duration::zero()));
+        drop($mutex.lock(core::time::Duration::from_millis(0)));
+        drop($recursive.lock(Duration::zero()));
+        let _ = $semaphore.take(Duration::zero());
+        let _ = $events.wait_bits(1, true, false, Duration::zero());
+        let _ = $stream.send(b"x", Duration::zero());
+        let _ = $stream.receive(&mut [0; 1], core::time::Duration::from_millis(0));
+    }};
+}
+
+fn main() {
+    FreeRTOS::start_scheduler(|os| {
+        os.new_task("checks", 1024, TaskPriority(2), move |this, os| {
+            let mut failures = 0;
+
+            let queue = os.new_queue::<u32>(1).unwrap();
+            check(
+                &mut failures,
+                "receive from an empty queue",
+                queue.receive(Duration::ms(2)),
+                FreeRtosError::QueueReceiveTimeout,
+            );
+            queue.send(1, Duration::zero()).unwrap();
+            check(
+                &mut failures,
+                "send to a full queue",
+                queue.send(2, Duration::ms(2)),
+                FreeRtosError::QueueSendTimeout,
+            );
+            check(
+                &mut failures,
+                "send to the front of a full queue",
+                queue.send_to_front(2, Duration::ms(2)),
+                FreeRtosError::QueueSendTimeout,
+            );
+
+            let mutex = Arc::new(Mutex::new(os, 0u32).unwrap());
+            let recursive = Arc::new(RecursiveMutex::new(os, 0u32).unwrap());
+            let semaphore = Arc::new(BinarySemaphore::new(os).unwrap());
+            let (m, r) = (mutex.clone(), recursive.clone());
+            os.new_task("holder", 256, TaskPriority(3), move |_, os| {
+                let _m = m.lock(Duration::infinite()).unwrap();
+                let _r = r.lock(Duration::infinite()).unwrap();
+                loop {
+                    os.delay(Duration::ms(10_000));
+                }
+            })
+            .unwrap();
+            check(
+                &mut failures,
+                "lock a held mutex",
+                mutex.lock(Duration::ms(2)).map(|_| ()),
+                FreeRtosError::MutexTimeout,
+            );
+            check(
+                &mut failures,
+                "lock a held recursive mutex",
+                recursive.lock(Duration::ms(2)).map(|_| ()),
+                FreeRtosError::MutexTimeout,
+            );
+            check(
+                &mut failures,
+                "take an empty semaphore",
+                semaphore.take(Duration::ms(2)),
+                FreeRtosError::Timeout,
+            );
+
+            let events = EventGroup::new(os).unwrap();
+            check(
+                &mut failures,
+                "wait for unset bits",
+                events.wait_bits(1, true, false, Duration::ms(2)),
+                FreeRtosError::Timeout,
+            );
+            check(
+                &mut failures,
+                "sync without the other tasks",
+                events.sync(1, 3, Duration::ms(2)),
+                FreeRtosError::Timeout,
+            );
+
+            let stream = StreamBuffer::new(os, 4, 1).unwrap();
+            check(
+                &mut failures,
+                "receive from an empty stream buffer",
+                stream.receive(&mut [0; 4], Duration::ms(2)),
+                FreeRtosError::Timeout,
+            );
+            stream.send(b"full", Duration::zero()).unwrap();
+            check(
+                &mut failures,
+                "send to a full stream buffer",
+                stream.send(b"x", Duration::ms(2)),
+                FreeRtosError::Timeout,
+            );
+            check(
+                &mut failures,
+                "wait for a notification",
+                this.wait_for_notification(0, 0, Duration::ms(2)),
+                FreeRtosError::Timeout,
+            );
+
+            let queues = (
+                os.new_queue::<u8>(4).unwrap(),
+                os.new_queue::<u32>(4).unwrap(),
+                os.new_queue::<[u8; 16]>(4).unwrap(),
+                os.new_queue::<(u16, u16)>(4).unwrap(),
+            );
+            let free_mutex = Mutex::new(os, 0u32).unwrap();
+            let free_recursive = RecursiveMutex::new(os, 0u32).unwrap();
+            for _ in 0..4 {
+                call_sites!(
+                    queues,
+                    free_mutex,
+                    free_recursive,
+                    semaphore,
+                    events,
+                    stream
+                );
+                call_sites!(
+                    queues,
+                    free_mutex,
+                    free_recursive,
+                    semaphore,
+                    events,
+                    stream
+                );
+                call_sites!(
+                    queues,
+                    free_mutex,
+                    free_recursive,
+                    semaphore,
+                    events,
+                    stream
+                );
+                call_sites!(
+                    queues,
+                    free_mutex,
+                    free_recursive,
+                    semaphore,
+                    events,
+                    stream
+                );
+                call_sites!(
+                    queues,
+                    free_mutex,
+                    free_recursive,
+                    semaphore,
+                    events,
+                    stream
+                );
+                call_sites!(
+                    queues,
+                    free_mutex,
+                    free_recursive,
+                    semaphore,
+                    events,
+                    stream
+                );
+                call_sites!(
+                    queues,
+                    free_mutex,
+                    free_recursive,
+                    semaphore,
+                    events,
+                    stream
+                );
+                call_sites!(
+                    queues,
+                    free_mutex,
+                    free_recursive,
+                    semaphore,
+                    events,
+                    stream
+                );
+                call_sites!(
+                    queues,
+                    free_mutex,
+                    free_recursive,
+                    semaphore,
+                    events,
+                    stream
+                );
+                call_sites!(
+                    queues,
+                    free_mutex,
+                    free_recursive,
+                    semaphore,
+                    events,
+                    stream
+                );
+                call_sites!(
+                    queues,
+                    free_mutex,
+                    free_recursive,
+                    semaphore,
+                    events,
+                    stream
+                );
+                call_sites!(
+                    queues,
+                    free_mutex,
+                    free_recursive,
+                    semaphore,
+                    events,
+                    stream
+                );
+                call_sites!(
+                    queues,
+                    free_mutex,
+                    free_recursive,
+                    semaphore,
+                    events,
+                    stream
+                );
+                call_sites!(
+                    queues,
+                    free_mutex,
+                    free_recursive,
+                    semaphore,
+                    events,
+                    stream
+                );
+                call_sites!(
+                    queues,
+                    free_mutex,
+                    free_recursive,
+                    semaphore,
+                    events,
+                    stream
+                );
+                call_sites!(
+                    queues,
+                    free_mutex,
+                    free_recursive,
+                    semaphore,
+                    events,
+                    stream
+                );
+            }
+
+            println!("{} failures", failures);
+            unsafe { _exit(failures) }
+        })
+        .unwrap();
+    });
+}
@@ -0,0 +1,72 @@
+/*
+Calls the c_hooks functions of freertos-rust the way a legacy C driver would, then
+feeds them bad pointers, lengths and ids. Compiled by build.rs with the c_hooks
+feature, run from examples/c_hooks/main.rs, which checks what reached the Rust side.
+*/
+
+#include <stdio.h>
+#include <string.h>
+
+#include "frrs_c_hooks.h"
+
+#define CHECK(cond)                                                    \
+	do                                                                 \
+	{                                                                  \
+		if (!(cond))                                                   \
+		{                                                              \
+			printf("c_hooks_test.c:%d: %s failed\n", __LINE__, #cond); \
+			failures++;                                                \
+		}                                                              \
+	} while (0)
+
+static int32_t log_str(uint8_t level, const char *module, const char *msg)
+{
+	return frrs_log_write(level, module, (const uint8_t *)msg, strlen(msg));
+}
+
+int c_hooks_test_run(uint32_t counter_id)
+{
+	int failures = 0;
+	const uint8_t frame[4] = {0x55, 0x01, 0x02, 0xAA};
+	uint8_t big[FRRS_LOG_MAX + 1];
+	/* A module name without a NUL in the bytes frrs_log_write may read. */
+	char unterminated[FRRS_LOG_MODULE_MAX + 1];
+	uint32_t baud = 0;
+
+	memset(big, 'x', sizeof(big));
+	memset(unterminated, 'm', sizeof(unterminated));
+
+	CHECK(frrs_counter_increment(counter_id) == FRRS_HOOKS_NOT_INITIALIZED);
+	CHECK(frrs_c_hooks_init(FRRS_C_HOOKS_VERSION + 1) == FRRS_HOOKS_VERSION);
+	CHECK(frrs_c_hooks_init(FRRS_C_HOOKS_VERSION) == FRRS_HOOKS_OK);
+
+	CHECK(log_str(FRRS_LOG_WARN, "uart", "rx overrun") == FRRS_HOOKS_OK);
+	CHECK(frrs_trace_record(7, frame, sizeof(frame)) == FRRS_HOOKS_OK);
+	CHECK(frrs_counter_increment(counter_id) == FRRS_HOOKS_OK);
+	CHECK(frrs_tunable_get_u32("uart.baud", &baud) == FRRS_HOOKS_OK);
+	CHECK(baud == 115200);
+
+	/* The Rust side lets two lines through per window. */
+	CHECK(log_str(FRRS_LOG_INFO, "uart", "tx idle") == FRRS_HOOKS_OK);
+	CHECK(log_str(FRRS_LOG_INFO, "uart", "flood") == FRRS_HOOKS_RATE_LIMITED);
+
+	CHECK(frrs_counter_increment(0) == FRRS_HOOKS_INVALID_ID);
+	CHECK(frrs_counter_increment(counter_id + 1) == FRRS_HOOKS_INVALID_ID);
+	CHECK(frrs_counter_increment(0xFFFFFFFF) == FRRS_HOOKS_INVALID_ID);
+
+	CHECK(log_str(0, "uart", "bad level") == FRRS_HOOKS_INVALID);
+	CHECK(log_str(FRRS_LOG_DEBUG + 1, "uart", "bad level") == FRRS_HOOKS_INVALID);
+	CHECK(log_str(FRRS_LOG_INFO, NULL, "no module") == FRRS_HOOKS_INVALID);
+	CHECK(frrs_log_write(FRRS_LOG_INFO, unterminated, big, 1) == FRRS_HOOKS_INVALID);
+	CHECK(frrs_log_write(FRRS_LOG_INFO, "uart", NULL, 4) == FRRS_HOOKS_INVALID);
+	CHECK(frrs_log_write(FRRS_LOG_INFO, "uart", big, sizeof(big)) == FRRS_HOOKS_INVALID);
+
+	CHECK(frrs_trace_record(7, NULL, 1) == FRRS_HOOKS_INVALID);
+	CHECK(frrs_trace_record(7, big, FRRS_TRACE_MAX + 1) == FRRS_HOOKS_INVALID);
+
+	CHECK(frrs_tunable_get_u32("uart.parity", &baud) == FRRS_HOOKS_NOT_FOUND);
+	CHECK(frrs_tunable_get_u32(NULL, &baud) == FRRS_HOOKS_INVALID);
+	CHECK(frrs_tunable_get_u32("uart.baud", NULL) == FRRS_HOOKS_INVALID);
+
+	return failures;
+}
@@ -0,0 +1,107 @@
+//! Runs the C driver in `c_hooks_test.c` against a log sink, a trace sink, a registered
+//! event counter and a tunable, then checks that its log line, trace record and counter
+//! bump arrived as sent. The process exits with 0 when everything passed.
+//!
+//!     cargo run --example c_hooks --features c_hooks --target x86_64-unknown-linux-gnu
+use freertos_rust::*;
+use std::sync::atomic::{AtomicI32, Ordering};
+use std::sync::Mutex;
+
+#[global_allocator]
+static GLOBAL: FreeRtosAllocator = FreeRtosAllocator;
+
+extern "C" {
+    fn c_hooks_test_run(counter_id: u32) -> i32;
+    // `std::process::exit` runs the exit handlers of the POSIX port, which cancel the
+    // threads the tasks run on while they are in Rust code.
+    fn _exit(status: i32) -> !;
+}
+
+static RX_OVERRUNS: EventCounter = EventCounter::new("uart.rx_overruns");
+static BAUD: Tunable = Tunable::new("uart.baud", 115_200);
+static OTHER_BAUD: Tunable = Tunable::new("uart.baud", 9600);
+
+struct Lines(Mutex<Vec<(LogLevel, String, Vec<u8>)>>);
+
+impl LogSink for Lines {
+    fn write(&self, level: LogLevel, module: &str, message: &[u8]) {
+        let line = (level, module.to_string(), message.to_vec());
+        self.0.lock().unwrap().push(line);
+    }
+}
+
+struct Records(Mutex<Vec<(u16, Vec<u8>)>>);
+
+impl TraceSink for Records {
+    fn record(&self, source: u16, payload: &[u8]) {
+        self.0.lock().unwrap().push((source, payload.to_vec()));
+    }
+}
+
+static FAILED: AtomicI32 = AtomicI32::new(0);
+
+fn check(ok: bool, what: &str) {
+    if !ok {
+        println!("failed: {}", what);
+        FAILED.fetch_add(1, Ordering::SeqCst);
+    }
+}
+
+static LINES: Lines = Lines(Mutex::new(Vec::new()));
+static RECORDS: Records = Records(Mutex::new(Vec::new()));
+
+fn main() {
+    let limit = LogRateLimit {
+        lines: 2,
+        window: Duration::ms(10_000).to_ticks(),
+    };
+
+    FreeRTOS::start_scheduler(|os| {
+        os.new_task("c_hooks", 1024, TaskPriority(2), move |_, _| {
+            unsafe {
+                set_log_sink(&LINES, limit);
+                set_trace_sink(&RECORDS);
+            }
+            let id = RX_OVERRUNS.register().unwrap();
+            BAUD.register().unwrap();
+            check(
+                RX_OVERRUNS.register() == Ok(id),
+                "registering again keeps the id",
+            );
+            check(
+                OTHER_BAUD.register() == Err(FreeRtosError::StorageInUse),
+                "a tunable name is only taken once",
+            );
+
+            let c_failures = unsafe { c_hooks_test_run(id.0) };
+            FAILED.fetch_add(c_failures, Ordering::SeqCst);
+
+            let lines = LINES.0.lock().unwrap().clone();
+            println!("log lines {:?}", lines);
+            let expected = vec![
+                (LogLevel::Warn, "uart".to_string(), b"rx overrun".to_vec()),
+                (LogLevel::Info, "uart".to_string(), b"tx idle".to_vec()),
+            ];
+            check(lines == expected, "the log lines arrived");
+            check(log_lines_dropped() == 1, "the rate limit dropped one line");
+
+            let records = RECORDS.0.lock().unwrap().clone();
+            println!("trace records {:?}", records);
+            check(
+                records == [(7, vec![0x55, 0x01, 0x02, 0xAA])],
+                "the trace record arrived",
+            );
+
+            check(RX_OVERRUNS.get() == 1, "the counter was bumped once");
+            check(
+                event_counter(EventCounterId(0)).is_none(),
+                "0 is no counter id",
+            );
+
+            let failures = FAILED.load(Ordering::SeqCst);
+            println!("{} failures", failures);
+            unsafe { _exit(failures) }
+        })
+        .unwrap();
+    });
+}
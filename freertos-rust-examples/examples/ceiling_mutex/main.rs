@@ -0,0 +1,173 @@
+//! Locks ceiling mutexes from a low priority task, and checks that:
+//!
+//! * the task runs at the ceiling while it holds the lock, and at its own priority again
+//!   after unlocking,
+//! * a task at medium priority, ready the whole time, doesn't run while the low task
+//!   holds the lock, but does once it unlocks,
+//! * a task already above the ceiling keeps its priority,
+//! * a recursive ceiling mutex keeps the task at the ceiling until the outermost guard is
+//!   dropped,
+//! * a lock that times out leaves the priority alone,
+//! * a panic in the critical section still unlocks the mutex and sets the priority back.
+//!
+//! The process exits with the number of failed checks.
+//!
+//!     cargo run --example ceiling_mutex --target x86_64-unknown-linux-gnu
+use freertos_rust::*;
+use std::panic::{self, AssertUnwindSafe};
+use std::sync::atomic::{AtomicBool, AtomicU32, Ordering};
+use std::sync::Arc;
+
+#[global_allocator]
+static GLOBAL: FreeRtosAllocator = FreeRtosAllocator;
+
+const LOW: TaskPriority = TaskPriority(1);
+const MEDIUM: TaskPriority = TaskPriority(2);
+const CEILING: TaskPriority = TaskPriority(4);
+
+static FAILURES: AtomicU32 = AtomicU32::new(0);
+/// Set by the medium task when it runs.
+static MEDIUM_RAN: AtomicBool = AtomicBool::new(false);
+
+extern "C" {
+    // `std::process::exit` runs the exit handlers of the POSIX port, which cancel the
+    // threads the tasks run on while they are in Rust code.
+    fn _exit(status: i32) -> !;
+}
+
+fn fail(message: String) {
+    println!("{}", message);
+    FAILURES.fetch_add(1, Ordering::SeqCst);
+}
+
+/// Keep the CPU for `ticks` without blocking.
+fn spin(os: FreeRTOS, ticks: u32) {
+    let start = os.get_tick_count();
+    while os.get_tick_count() - start < ticks {}
+}
+
+fn main() {
+    FreeRTOS::start_scheduler(|os| {
+        os.new_task("checks", 512, TaskPriority(5), move |this, os| {
+            let mutex = Arc::new(os.new_ceiling_mutex(0u32, CEILING).unwrap());
+            let recursive = Arc::new(os.new_recursive_ceiling_mutex((), CEILING).unwrap());
+            let done = Arc::new(BinarySemaphore::new(os).unwrap());
+
+            // Above the ceiling: nothing changes.
+            let guard = mutex.lock(Duration::zero()).unwrap();
+            if this.get_priority() != TaskPriority(5) {
+                fail(format!(
+                    "task above the ceiling at {:?}",
+                    this.get_priority()
+                ));
+            }
+            drop(guard);
+
+            let (m, r, d) = (mutex.clone(), recursive.clone(), done.clone());
+            let low = os
+                .new_task("low", 512, LOW, move |this, os| {
+                    {
+                        let mut guard = m.lock(Duration::infinite()).unwrap();
+                        *guard += 1;
+                        let held = this.get_priority();
+                        // The medium task is ready now, but must not run.
+                        spin(os, 5);
+                        let ran = MEDIUM_RAN.load(Ordering::SeqCst);
+                        drop(guard);
+                        if held != CEILING || ran {
+                            fail(format!(
+                                "holding the lock: at {:?}, medium ran {}",
+                                held, ran
+                            ));
+                        }
+                    }
+                    let ran = MEDIUM_RAN.load(Ordering::SeqCst);
+                    if this.get_priority() != LOW || !ran {
+                        fail(format!(
+                            "after unlocking: at {:?}, medium ran {}",
+                            this.get_priority(),
+                            ran
+                        ));
+                    }
+
+                    let outer = r.lock(Duration::infinite()).unwrap();
+                    let inner = r.lock(Duration::infinite()).unwrap();
+                    drop(inner);
+                    let after_inner = this.get_priority();
+                    drop(outer);
+                    if after_inner != CEILING || this.get_priority() != LOW {
+                        fail(format!(
+                            "recursive: at {:?} after the inner unlock, {:?} after the outer",
+                            after_inner,
+                            this.get_priority()
+                        ));
+                    }
+
+                    // The default hook's message and backtrace need more heap than the
+                    // example has.
+                    panic::set_hook(Box::new(|_| {}));
+                    let panicked = panic::catch_unwind(AssertUnwindSafe(|| {
+                        let _guard = m.lock(Duration::infinite()).unwrap();
+                        panic!("in the critical section");
+                    }));
+                    let relocked = m.lock(Duration::zero()).is_ok();
+                    if panicked.is_ok() || this.get_priority() != LOW || !relocked {
+                        fail(format!(
+                            "after a panic: at {:?}, lock again {}",
+                            this.get_priority(),
+                            relocked
+                        ));
+                    }
+
+                    d.give();
+                    loop {
+                        os.delay(Duration::infinite());
+                    }
+                })
+                .unwrap();
+
+            // Let the low task lock, then make the medium task ready.
+            os.delay(Duration::ms(1));
+            os.new_task("medium", 256, MEDIUM, move |_, os| {
+                MEDIUM_RAN.store(true, Ordering::SeqCst);
+                loop {
+                    os.delay(Duration::infinite());
+                }
+            })
+            .unwrap();
+
+            if done.take(Duration::ms(200)).is_err() {
+                fail(format!("low task stuck at {:?}", low.get_priority()));
+            }
+
+            // Held by this task, so the waiter times out.
+            let guard = mutex.lock(Duration::zero()).unwrap();
+            let timed_out = Arc::new(BinarySemaphore::new(os).unwrap());
+            let (m, t) = (mutex.clone(), timed_out.clone());
+            os.new_task("waiter", 256, LOW, move |this, os| {
+                let result = m.lock(Duration::ms(3)).map(|_| ());
+                if result != Err(FreeRtosError::MutexTimeout) || this.get_priority() != LOW {
+                    fail(format!(
+                        "timed out lock: {:?}, at {:?}",
+                        result,
+                        this.get_priority()
+                    ));
+                }
+                t.give();
+                loop {
+                    os.delay(Duration::infinite());
+                }
+            })
+            .unwrap();
+            if timed_out.take(Duration::ms(50)).is_err() {
+                fail("waiter didn't time out".into());
+            }
+            drop(guard);
+
+            let failures = FAILURES.load(Ordering::SeqCst) as i32;
+            println!("{} failures", failures);
+            unsafe { _exit(failures) }
+        })
+        .unwrap();
+    });
+}
@@ -0,0 +1,129 @@
+//! Sends values that count their drops through a `Channel`: between two tasks, back to
+//! the sender when the channel is full, and left in a channel that is dropped. Every
+//! value has to be dropped exactly once. The process exits with the number of failed
+//! checks.
+//!
+//!     cargo run --example channel --target x86_64-unknown-linux-gnu
+use freertos_rust::*;
+use std::sync::atomic::{AtomicU32, Ordering};
+use std::sync::Arc;
+
+#[global_allocator]
+static GLOBAL: FreeRtosAllocator = FreeRtosAllocator;
+
+static CREATED: AtomicU32 = AtomicU32::new(0);
+static DROPPED: AtomicU32 = AtomicU32::new(0);
+
+struct Counted {
+    id: u32,
+    label: String,
+}
+
+impl Counted {
+    fn new(id: u32) -> Counted {
+        CREATED.fetch_add(1, Ordering::Relaxed);
+        Counted {
+            id,
+            label: format!("item {}", id),
+        }
+    }
+}
+
+impl Drop for Counted {
+    fn drop(&mut self) {
+        DROPPED.fetch_add(1, Ordering::Relaxed);
+    }
+}
+
+fn live() -> u32 {
+    CREATED.load(Ordering::Relaxed) - DROPPED.load(Ordering::Relaxed)
+}
+
+extern "C" {
+    // `std::process::exit` runs the exit handlers of the POSIX port, which cancel the
+    // threads the tasks run on while they are in Rust code.
+    fn _exit(status: i32) -> !;
+}
+
+fn main() {
+    FreeRTOS::start_scheduler(|os| {
+        os.new_task("checks", 1024, TaskPriority(2), move |_, os| {
+            let mut failures = 0;
+
+            // Between tasks, in order.
+            let channel = Arc::new(os.new_channel::<Counted>(4).unwrap());
+            let received = Arc::new(os.new_queue::<u32>(32).unwrap());
+            let (rx, ids) = (channel.clone(), received.clone());
+            os.new_task("consumer", 512, TaskPriority(1), move |_, os| loop {
+                match rx.receive(Duration::ms(50)) {
+                    Ok(item) => {
+                        let id = if item.label == format!("item {}", item.id) {
+                            item.id
+                        } else {
+                            u32::MAX
+                        };
+                        drop(item);
+                        ids.send(id, Duration::zero()).unwrap();
+                    }
+                    Err(_) => os.delay(Duration::ms(10_000)),
+                }
+            })
+            .unwrap();
+            for id in 0..20 {
+                channel.send(Counted::new(id), Duration::ms(100)).unwrap();
+            }
+            for id in 0..20 {
+                match received.receive(Duration::ms(100)) {
+                    Ok(got) if got == id => {}
+                    r => {
+                        println!("expected item {}, got {:?}", id, r);
+                        failures += 1;
+                    }
+                }
+            }
+            if live() != 0 {
+                println!("{} items alive after they were received", live());
+                failures += 1;
+            }
+
+            // A full channel hands the value back.
+            let full = os.new_channel::<Counted>(2).unwrap();
+            full.send(Counted::new(100), Duration::zero()).unwrap();
+            full.send(Counted::new(101), Duration::zero()).unwrap();
+            match full.send(Counted::new(102), Duration::ms(2)) {
+                Err(ChannelSendError {
+                    error: FreeRtosError::QueueSendTimeout,
+                    item,
+                }) if item.id == 102 => {}
+                r => {
+                    println!("send to a full channel: {:?}", r.map_err(|e| e.error));
+                    failures += 1;
+                }
+            }
+            if live() != 2 || full.len() != 2 {
+                println!(
+                    "{} items alive, {} in the channel, expected 2",
+                    live(),
+                    full.len()
+                );
+                failures += 1;
+            }
+
+            // Dropping the channel drops what is left in it.
+            drop(full);
+            if live() != 0 {
+                println!("{} items leaked by dropping the channel", live());
+                failures += 1;
+            }
+
+            println!(
+                "{} items created, {} dropped",
+                CREATED.load(Ordering::Relaxed),
+                DROPPED.load(Ordering::Relaxed)
+            );
+            println!("{} failures", failures);
+            unsafe { _exit(failures) }
+        })
+        .unwrap();
+    });
+}
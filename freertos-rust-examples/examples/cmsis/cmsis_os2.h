@@ -0,0 +1,121 @@
+/*
+The subset of cmsis_os2.h implemented by freertos-rust with the cmsis-compat feature.
+
+Declarations follow CMSIS-RTOS2 2.1. osErrorNotImplemented is an addition of
+freertos-rust, returned for the CMSIS features it doesn't support.
+*/
+
+#ifndef CMSIS_OS2_H_
+#define CMSIS_OS2_H_
+
+#include <stdint.h>
+
+#define osWaitForever 0xFFFFFFFFU
+
+#define osThreadJoinable 0x00000001U
+#define osMutexRecursive 0x00000001U
+#define osMutexPrioInherit 0x00000002U
+#define osMutexRobust 0x00000008U
+
+typedef enum
+{
+	osOK = 0,
+	osError = -1,
+	osErrorTimeout = -2,
+	osErrorResource = -3,
+	osErrorParameter = -4,
+	osErrorNoMemory = -5,
+	osErrorISR = -6,
+	osErrorNotImplemented = -7,
+	osStatusReserved = 0x7FFFFFFF
+} osStatus_t;
+
+typedef enum
+{
+	osPriorityNone = 0,
+	osPriorityIdle = 1,
+	osPriorityLow = 8,
+	osPriorityBelowNormal = 16,
+	osPriorityNormal = 24,
+	osPriorityAboveNormal = 32,
+	osPriorityHigh = 40,
+	osPriorityRealtime = 48,
+	osPriorityRealtime7 = 48 + 7,
+	osPriorityISR = 56,
+	osPriorityError = -1,
+	osPriorityReserved = 0x7FFFFFFF
+} osPriority_t;
+
+typedef void (*osThreadFunc_t)(void *argument);
+
+typedef void *osThreadId_t;
+typedef void *osMessageQueueId_t;
+typedef void *osSemaphoreId_t;
+typedef void *osMutexId_t;
+
+typedef struct
+{
+	const char *name;
+	uint32_t attr_bits;
+	void *cb_mem;
+	uint32_t cb_size;
+	void *stack_mem;
+	uint32_t stack_size;
+	osPriority_t priority;
+	uint32_t tz_module;
+	uint32_t reserved;
+} osThreadAttr_t;
+
+typedef struct
+{
+	const char *name;
+	uint32_t attr_bits;
+	void *cb_mem;
+	uint32_t cb_size;
+	void *mq_mem;
+	uint32_t mq_size;
+} osMessageQueueAttr_t;
+
+typedef struct
+{
+	const char *name;
+	uint32_t attr_bits;
+	void *cb_mem;
+	uint32_t cb_size;
+} osSemaphoreAttr_t;
+
+typedef struct
+{
+	const char *name;
+	uint32_t attr_bits;
+	void *cb_mem;
+	uint32_t cb_size;
+} osMutexAttr_t;
+
+uint32_t osKernelGetTickCount(void);
+
+osThreadId_t osThreadNew(osThreadFunc_t func, void *argument, const osThreadAttr_t *attr);
+osThreadId_t osThreadGetId(void);
+osStatus_t osDelay(uint32_t ticks);
+
+osMessageQueueId_t osMessageQueueNew(uint32_t msg_count, uint32_t msg_size, const osMessageQueueAttr_t *attr);
+osStatus_t osMessageQueuePut(osMessageQueueId_t mq_id, const void *msg_ptr, uint8_t msg_prio, uint32_t timeout);
+osStatus_t osMessageQueueGet(osMessageQueueId_t mq_id, void *msg_ptr, uint8_t *msg_prio, uint32_t timeout);
+uint32_t osMessageQueueGetCapacity(osMessageQueueId_t mq_id);
+uint32_t osMessageQueueGetMsgSize(osMessageQueueId_t mq_id);
+uint32_t osMessageQueueGetCount(osMessageQueueId_t mq_id);
+uint32_t osMessageQueueGetSpace(osMessageQueueId_t mq_id);
+osStatus_t osMessageQueueDelete(osMessageQueueId_t mq_id);
+
+osSemaphoreId_t osSemaphoreNew(uint32_t max_count, uint32_t initial_count, const osSemaphoreAttr_t *attr);
+osStatus_t osSemaphoreAcquire(osSemaphoreId_t semaphore_id, uint32_t timeout);
+osStatus_t osSemaphoreRelease(osSemaphoreId_t semaphore_id);
+uint32_t osSemaphoreGetCount(osSemaphoreId_t semaphore_id);
+osStatus_t osSemaphoreDelete(osSemaphoreId_t semaphore_id);
+
+osMutexId_t osMutexNew(const osMutexAttr_t *attr);
+osStatus_t osMutexAcquire(osMutexId_t mutex_id, uint32_t timeout);
+osStatus_t osMutexRelease(osMutexId_t mutex_id);
+osStatus_t osMutexDelete(osMutexId_t mutex_id);
+
+#endif
@@ -0,0 +1,120 @@
+/*
+Exercises the CMSIS-RTOS2 functions of freertos-rust the way C middleware would.
+Compiled by build.rs with the cmsis-compat feature, run from examples/cmsis/main.rs.
+*/
+
+#include <stdio.h>
+#include <string.h>
+
+#include "cmsis_os2.h"
+
+#define CHECK(cond)                                                  \
+	do                                                               \
+	{                                                                \
+		if (!(cond))                                                 \
+		{                                                            \
+			printf("cmsis_test.c:%d: %s failed\n", __LINE__, #cond); \
+			failures++;                                              \
+		}                                                            \
+	} while (0)
+
+typedef struct
+{
+	uint16_t endpoint;
+	uint8_t data[6];
+} usb_msg_t;
+
+static osMessageQueueId_t usb_rx;
+static osSemaphoreId_t ble_evt;
+
+static void ble_worker(void *argument)
+{
+	usb_msg_t msg = {.endpoint = 2, .data = {'r', 'e', 'a', 'd', 'y', 0}};
+
+	(void)argument;
+	osDelay(5);
+	osMessageQueuePut(usb_rx, &msg, 0, osWaitForever);
+	osSemaphoreRelease(ble_evt);
+
+	for (;;)
+	{
+		osDelay(1000);
+	}
+}
+
+static void oneshot(void *argument)
+{
+	*(volatile int *)argument = 1;
+}
+
+int cmsis_test_run(void)
+{
+	int failures = 0;
+	usb_msg_t out = {.endpoint = 1, .data = {1, 2, 3, 4, 5, 6}};
+	usb_msg_t in;
+	uint8_t prio = 0xFF;
+	static volatile int oneshot_ran;
+
+	const osMessageQueueAttr_t usb_rx_attr = {.name = "usb.rx"};
+	const osSemaphoreAttr_t ble_evt_attr = {.name = "ble.evt"};
+	const osThreadAttr_t ble_worker_attr = {.name = "ble.worker", .stack_size = 4096, .priority = osPriorityNormal};
+	const osThreadAttr_t oneshot_attr = {.name = "oneshot"};
+
+	/* Queue put and get, with the item size given at runtime. */
+	usb_rx = osMessageQueueNew(4, sizeof(usb_msg_t), &usb_rx_attr);
+	CHECK(usb_rx != NULL);
+	CHECK(osMessageQueueGetCapacity(usb_rx) == 4);
+	CHECK(osMessageQueueGetMsgSize(usb_rx) == sizeof(usb_msg_t));
+	CHECK(osMessageQueuePut(usb_rx, &out, 0, 0) == osOK);
+	out.endpoint = 3;
+	CHECK(osMessageQueuePut(usb_rx, &out, 0, 0) == osOK);
+	CHECK(osMessageQueueGetCount(usb_rx) == 2);
+	CHECK(osMessageQueueGetSpace(usb_rx) == 2);
+	CHECK(osMessageQueueGet(usb_rx, &in, &prio, 0) == osOK);
+	CHECK(in.endpoint == 1 && memcmp(in.data, out.data, sizeof(in.data)) == 0 && prio == 0);
+	CHECK(osMessageQueueGet(usb_rx, &in, NULL, 0) == osOK);
+	CHECK(in.endpoint == 3);
+	CHECK(osMessageQueueGet(usb_rx, &in, NULL, 0) == osErrorResource);
+	CHECK(osMessageQueueGet(usb_rx, &in, NULL, 2) == osErrorTimeout);
+
+	/* A semaphore released by another thread. */
+	ble_evt = osSemaphoreNew(1, 0, &ble_evt_attr);
+	CHECK(ble_evt != NULL);
+	CHECK(osSemaphoreAcquire(ble_evt, 0) == osErrorResource);
+	CHECK(osThreadNew(ble_worker, NULL, &ble_worker_attr) != NULL);
+	CHECK(osSemaphoreAcquire(ble_evt, 1000) == osOK);
+	CHECK(osMessageQueueGet(usb_rx, &in, NULL, 0) == osOK);
+	CHECK(in.endpoint == 2 && strcmp((const char *)in.data, "ready") == 0);
+	CHECK(osSemaphoreGetCount(ble_evt) == 0);
+	CHECK(osSemaphoreRelease(ble_evt) == osOK);
+	CHECK(osSemaphoreRelease(ble_evt) == osErrorResource);
+
+	/* A thread that returns is deleted. */
+	CHECK(osThreadNew(oneshot, (void *)&oneshot_ran, &oneshot_attr) != NULL);
+	osDelay(10);
+	CHECK(oneshot_ran == 1);
+
+	/* A mutex, named after its type. */
+	osMutexId_t mutex = osMutexNew(NULL);
+	CHECK(mutex != NULL);
+	CHECK(osMutexAcquire(mutex, 0) == osOK);
+	CHECK(osMutexRelease(mutex) == osOK);
+	CHECK(osMutexRelease(mutex) == osErrorResource);
+
+	/* Unsupported features fail rather than being ignored. */
+	static uint8_t mq_mem[64];
+	const osMessageQueueAttr_t static_attr = {.mq_mem = mq_mem, .mq_size = sizeof(mq_mem)};
+	const osThreadAttr_t joinable_attr = {.attr_bits = osThreadJoinable};
+	const osMutexAttr_t robust_attr = {.attr_bits = osMutexRobust};
+	CHECK(osMessageQueueNew(4, 4, &static_attr) == NULL);
+	CHECK(osThreadNew(oneshot, NULL, &joinable_attr) == NULL);
+	CHECK(osMutexNew(&robust_attr) == NULL);
+	CHECK(osMessageQueuePut(usb_rx, &out, 1, 0) == osErrorNotImplemented);
+
+	/* Deleted objects leave the registry. */
+	osMessageQueueId_t scratch = osMessageQueueNew(1, 1, NULL);
+	CHECK(scratch != NULL);
+	CHECK(osMessageQueueDelete(scratch) == osOK);
+
+	return failures;
+}
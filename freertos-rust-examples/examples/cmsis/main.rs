@@ -0,0 +1,48 @@
+//! Runs the CMSIS-RTOS2 checks in `cmsis_test.c` and lists the objects they created.
+//! The process exits with 0 when everything passed.
+//!
+//!     cargo run --example cmsis --features cmsis-compat --target x86_64-unknown-linux-gnu
+use freertos_rust::cmsis::{self, CmsisObjectKind};
+use freertos_rust::*;
+
+#[global_allocator]
+static GLOBAL: FreeRtosAllocator = FreeRtosAllocator;
+
+extern "C" {
+    fn cmsis_test_run() -> i32;
+    // `std::process::exit` runs the exit handlers of the POSIX port, which cancel the
+    // threads the tasks run on while they are in Rust code.
+    fn _exit(status: i32) -> !;
+}
+
+fn main() {
+    FreeRTOS::start_scheduler(|os| {
+        os.new_task("cmsis_test", 1024, TaskPriority(2), move |_, _| {
+            let mut failures = unsafe { cmsis_test_run() };
+
+            let objects = cmsis::objects();
+            for object in &objects {
+                println!("{:?} {:?} {:?}", object.kind, object.name, object.handle);
+            }
+
+            let expected = [
+                (CmsisObjectKind::MessageQueue, "usb.rx"),
+                (CmsisObjectKind::Semaphore, "ble.evt"),
+                (CmsisObjectKind::Thread, "ble.worker"),
+                (CmsisObjectKind::Mutex, "osMutex"),
+            ];
+            let names: Vec<_> = objects
+                .iter()
+                .map(|o| (o.kind, o.name.as_str().unwrap()))
+                .collect();
+            if names != expected {
+                println!("expected objects {:?}", expected);
+                failures += 1;
+            }
+
+            println!("{} failures", failures);
+            unsafe { _exit(failures) }
+        })
+        .unwrap();
+    });
+}
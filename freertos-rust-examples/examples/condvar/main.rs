@@ -0,0 +1,185 @@
+//! Waits on a `Condvar` for the state behind a `Mutex`, and checks that:
+//!
+//! * a bounded buffer with producers and consumers waiting on "not full" and "not empty"
+//!   hands every item over once, in order per producer,
+//! * `wait` times out with `Timeout` when nothing notifies, and the mutex is unlocked
+//!   afterwards,
+//! * `notify_one` wakes one waiter and `notify_all` wakes all of them.
+//!
+//! The process exits with the number of failed checks.
+//!
+//!     cargo run --example condvar --target x86_64-unknown-linux-gnu
+use freertos_rust::*;
+use std::collections::VecDeque;
+use std::sync::atomic::{AtomicU32, Ordering};
+use std::sync::Arc;
+
+#[global_allocator]
+static GLOBAL: FreeRtosAllocator = FreeRtosAllocator;
+
+const CAPACITY: usize = 4;
+const PRODUCERS: u32 = 2;
+const CONSUMERS: u32 = 1;
+const ITEMS: u32 = 500;
+
+struct Buffer {
+    items: Mutex<VecDeque<(u32, u32)>>,
+    not_full: Condvar,
+    not_empty: Condvar,
+}
+
+static CONSUMED: AtomicU32 = AtomicU32::new(0);
+static CONSUMED_SUM: AtomicU32 = AtomicU32::new(0);
+static OUT_OF_ORDER: AtomicU32 = AtomicU32::new(0);
+static WOKEN: AtomicU32 = AtomicU32::new(0);
+
+extern "C" {
+    // `std::process::exit` runs the exit handlers of the POSIX port, which cancel the
+    // threads the tasks run on while they are in Rust code.
+    fn _exit(status: i32) -> !;
+}
+
+fn park(os: FreeRTOS) -> ! {
+    loop {
+        os.delay(Duration::infinite());
+    }
+}
+
+fn main() {
+    FreeRTOS::start_scheduler(|os| {
+        os.new_task("checks", 512, TaskPriority(3), move |_, os| {
+            let mut failures = 0;
+
+            let buffer = Arc::new(Buffer {
+                items: Mutex::new(os, VecDeque::with_capacity(CAPACITY)).unwrap(),
+                not_full: os.new_condvar().unwrap(),
+                not_empty: os.new_condvar().unwrap(),
+            });
+            for producer in 0..PRODUCERS {
+                let buffer = buffer.clone();
+                os.new_task("producer", 256, TaskPriority(1), move |_, os| {
+                    for seq in 0..ITEMS {
+                        let mut items = buffer.items.lock(Duration::infinite()).unwrap();
+                        while items.len() == CAPACITY {
+                            items = buffer.not_full.wait(items, Duration::infinite()).unwrap();
+                        }
+                        items.push_back((producer, seq));
+                        drop(items);
+                        buffer.not_empty.notify_one();
+                    }
+                    park(os)
+                })
+                .unwrap();
+            }
+            for _ in 0..CONSUMERS {
+                let buffer = buffer.clone();
+                os.new_task("consumer", 256, TaskPriority(1), move |_, _| {
+                    let mut next = [0; PRODUCERS as usize];
+                    loop {
+                        let mut items = buffer.items.lock(Duration::infinite()).unwrap();
+                        while items.is_empty() {
+                            items = buffer.not_empty.wait(items, Duration::infinite()).unwrap();
+                        }
+                        let (producer, seq) = items.pop_front().unwrap();
+                        drop(items);
+                        buffer.not_full.notify_one();
+
+                        // With more than one consumer, each sees the items of a producer
+                        // in order, with gaps where the others took some.
+                        if seq < next[producer as usize] {
+                            OUT_OF_ORDER.fetch_add(1, Ordering::SeqCst);
+                        }
+                        next[producer as usize] = seq + 1;
+                        CONSUMED_SUM.fetch_add(seq, Ordering::SeqCst);
+                        CONSUMED.fetch_add(1, Ordering::SeqCst);
+                    }
+                })
+                .unwrap();
+            }
+
+            let expected = PRODUCERS * ITEMS;
+            let mut waited = 0;
+            while CONSUMED.load(Ordering::SeqCst) < expected && waited < 5000 {
+                os.delay(Duration::ms(10));
+                waited += 10;
+            }
+            os.delay(Duration::ms(10));
+            let expected_sum = PRODUCERS * (ITEMS * (ITEMS - 1) / 2);
+            if CONSUMED.load(Ordering::SeqCst) != expected
+                || CONSUMED_SUM.load(Ordering::SeqCst) != expected_sum
+                || OUT_OF_ORDER.load(Ordering::SeqCst) != 0
+            {
+                println!(
+                    "consumed {} of {} items, sequence sum {} of {}, {} out of order",
+                    CONSUMED.load(Ordering::SeqCst),
+                    expected,
+                    CONSUMED_SUM.load(Ordering::SeqCst),
+                    expected_sum,
+                    OUT_OF_ORDER.load(Ordering::SeqCst)
+                );
+                failures += 1;
+            }
+
+            // Nothing notifies this one.
+            let flag = Arc::new(Mutex::new(os, 0u32).unwrap());
+            let condvar = Arc::new(os.new_condvar().unwrap());
+            let start = os.get_tick_count();
+            let result = condvar.wait(flag.lock(Duration::zero()).unwrap(), Duration::ms(20));
+            let waited = os.get_tick_count() - start;
+            if !matches!(result, Err(FreeRtosError::Timeout))
+                || waited < Duration::ms(20).to_ticks()
+                || flag.try_lock().is_none()
+            {
+                println!(
+                    "wait without a notification: {:?} after {} ticks, mutex locked {}",
+                    result.map(|_| ()),
+                    waited,
+                    flag.try_lock().is_none()
+                );
+                failures += 1;
+            }
+
+            const WAITERS: u32 = 2;
+            for _ in 0..WAITERS {
+                let (flag, condvar) = (flag.clone(), condvar.clone());
+                os.new_task("waiter", 256, TaskPriority(2), move |_, os| {
+                    let mut generation = flag.lock(Duration::infinite()).unwrap();
+                    let mut seen = *generation;
+                    // Woken by `notify_one` or not, then by `notify_all`.
+                    while seen < 2 {
+                        while *generation == seen {
+                            generation = condvar.wait(generation, Duration::infinite()).unwrap();
+                        }
+                        seen = *generation;
+                        WOKEN.fetch_add(1, Ordering::SeqCst);
+                    }
+                    drop(generation);
+                    park(os)
+                })
+                .unwrap();
+            }
+            os.delay(Duration::ms(5));
+
+            // Only one waiter gets to see the first generation.
+            *flag.lock(Duration::zero()).unwrap() = 1;
+            condvar.notify_one();
+            os.delay(Duration::ms(5));
+            let after_one = WOKEN.load(Ordering::SeqCst);
+            *flag.lock(Duration::zero()).unwrap() = 2;
+            condvar.notify_all();
+            os.delay(Duration::ms(5));
+            let after_all = WOKEN.load(Ordering::SeqCst) - after_one;
+            if after_one != 1 || after_all != WAITERS {
+                println!(
+                    "{} waiters woken by notify_one, {} by notify_all",
+                    after_one, after_all
+                );
+                failures += 1;
+            }
+
+            println!("{} failures", failures);
+            unsafe { _exit(failures) }
+        })
+        .unwrap();
+    });
+}
@@ -0,0 +1,179 @@
+//! Reads per-task CPU usage from `FreeRtosSchedulerState`, and checks that:
+//!
+//! * `delta` gives each task's share of an interval, not of the time since boot,
+//! * `find_task` finds tasks by name, and `cpu_usage` lists every task, or none without
+//!   run time stats,
+//! * `delta` handles run time counters that wrapped around between the two states, and
+//!   counts all of the run time of tasks created in between,
+//! * the percentages in the `Display` table are the permille values, rounded down, and
+//!   the run times are the shares of the ticks the scheduler ran for.
+//!
+//! The process exits with the number of failed checks.
+//!
+//!     cargo run --example cpu_usage --target x86_64-unknown-linux-gnu
+use freertos_rust::*;
+
+#[global_allocator]
+static GLOBAL: FreeRtosAllocator = FreeRtosAllocator;
+
+extern "C" {
+    // `std::process::exit` runs the exit handlers of the POSIX port, which cancel the
+    // threads the tasks run on while they are in Rust code.
+    fn _exit(status: i32) -> !;
+}
+
+fn status(name: &str, task_number: u32, run_time_counter: u32) -> FreeRtosTaskStatus {
+    FreeRtosTaskStatus {
+        task: unsafe { TaskRemoteHandle::from_raw(freertos_rs_get_current_task()) },
+        name: name.to_string(),
+        task_number: task_number as FreeRtosUBaseType,
+        task_state: FreeRtosTaskState::Ready,
+        current_priority: TaskPriority(1),
+        base_priority: TaskPriority(1),
+        run_time_counter,
+        stack_high_water_mark: 100,
+    }
+}
+
+fn main() {
+    FreeRTOS::start_scheduler(|os| {
+        os.new_task("checks", 1024, TaskPriority(2), move |_, _| {
+            let mut failures = 0;
+
+            // `get_all_tasks` needs Rust and the C shim to agree on the kernel type sizes,
+            // which they don't with the 64-bit `BaseType_t` of the Linux port, so the
+            // states are made up.
+            let before = FreeRtosSchedulerState {
+                tasks: vec![
+                    status("checks", 1, 40),
+                    status("high", 2, 3000),
+                    status("low", 3, 1000),
+                    status("IDLE", 4, 500),
+                ],
+                total_run_time: 4540,
+                tick_count: 4540,
+            };
+            let after = FreeRtosSchedulerState {
+                tasks: vec![
+                    status("checks", 1, 50),
+                    status("high", 2, 10500),
+                    status("low", 3, 3480),
+                    status("IDLE", 4, 500),
+                ],
+                total_run_time: 14540,
+                tick_count: 14540,
+            };
+            let delta = after.delta(&before);
+            let shares: Vec<Option<u32>> = ["checks", "high", "low", "IDLE"]
+                .iter()
+                .map(|name| delta.find_task(name).and_then(|t| delta.cpu_permille(t)))
+                .collect();
+            if delta.total_run_time != 10000
+                || shares != [Some(1), Some(750), Some(248), Some(0)]
+                || delta.find_task("nobody").is_some()
+            {
+                println!(
+                    "over the interval: total {}, shares {:?}",
+                    delta.total_run_time, shares
+                );
+                failures += 1;
+            }
+
+            let usage = after.cpu_usage();
+            let high = after.find_task("high").map(|t| t.task_number);
+            if high != Some(2)
+                || after.find_task("nobody").is_some()
+                || usage
+                    .iter()
+                    .map(|(name, _)| name.as_str())
+                    .collect::<Vec<_>>()
+                    != ["checks", "high", "low", "IDLE"]
+                || usage.iter().map(|(_, p)| *p).collect::<Vec<_>>() != [3, 722, 239, 34]
+            {
+                println!("since boot: high is task {:?}, usage {:?}", high, usage);
+                failures += 1;
+            }
+
+            let no_stats = FreeRtosSchedulerState {
+                tasks: vec![status("checks", 1, 0)],
+                total_run_time: 0,
+                tick_count: 100,
+            };
+            if !no_stats.cpu_usage().is_empty() || !no_stats.delta(&no_stats).cpu_usage().is_empty()
+            {
+                println!("usage without run time stats: {:?}", no_stats.cpu_usage());
+                failures += 1;
+            }
+
+            let previous = FreeRtosSchedulerState {
+                tasks: vec![status("a", 1, u32::MAX - 99), status("b", 2, 500)],
+                total_run_time: u32::MAX - 199,
+                tick_count: 1000,
+            };
+            let current = FreeRtosSchedulerState {
+                tasks: vec![
+                    status("a", 1, 100),
+                    status("b", 2, 600),
+                    status("c", 3, 200),
+                ],
+                total_run_time: 800,
+                tick_count: 2000,
+            };
+            let wrapped = current.delta(&previous);
+            let run_times: Vec<u32> = wrapped.tasks.iter().map(|t| t.run_time).collect();
+            if wrapped.total_run_time != 1000
+                || run_times != [200, 100, 200]
+                || wrapped.cpu_usage()
+                    != [
+                        ("a".to_string(), 200),
+                        ("b".to_string(), 100),
+                        ("c".to_string(), 200),
+                    ]
+            {
+                println!(
+                    "across the wraparound: total {}, run times {:?}, usage {:?}",
+                    wrapped.total_run_time,
+                    run_times,
+                    wrapped.cpu_usage()
+                );
+                failures += 1;
+            }
+
+            let table = FreeRtosSchedulerState {
+                tasks: vec![
+                    status("idle", 1, 0),
+                    status("tiny", 2, 9),
+                    status("some", 3, 259),
+                    status("most", 4, 732),
+                ],
+                total_run_time: 1000,
+                tick_count: 2000,
+            };
+            let text = format!("{}", table);
+            let expected = [
+                ("idle", "     0ms |   0%"),
+                ("tiny", "    18ms |  <1%"),
+                ("some", "   518ms |  25%"),
+                ("most", "    1.4s |  73%"),
+            ];
+            for &(name, end) in expected.iter() {
+                let line = text.lines().find(|l| l.contains(name)).unwrap_or("");
+                if !line.ends_with(end) {
+                    println!(
+                        "row of {}: {:?}, expected it to end with {:?}",
+                        name, line, end
+                    );
+                    failures += 1;
+                }
+            }
+            if !text.contains("Total run time: 2s") {
+                println!("total: {:?}", text.lines().last());
+                failures += 1;
+            }
+
+            println!("{} failures", failures);
+            unsafe { _exit(failures) }
+        })
+        .unwrap();
+    });
+}
@@ -0,0 +1,57 @@
+//! Creates the application's queues in the daemon task startup hook, once the scheduler
+//! runs, and hands them to the tasks through a static cell. Needs
+//! `configUSE_DAEMON_TASK_STARTUP_HOOK` set to 1.
+//!
+//!     cargo run --example daemon_startup --target x86_64-unknown-linux-gnu
+use freertos_rust::*;
+use std::sync::OnceLock;
+
+#[global_allocator]
+static GLOBAL: FreeRtosAllocator = FreeRtosAllocator;
+
+struct AppQueues {
+    readings: Queue<u32>,
+}
+
+static QUEUES: OnceLock<AppQueues> = OnceLock::new();
+
+/// The daemon task runs at `configTIMER_TASK_PRIORITY`, above the application tasks, so
+/// the hook has run before they get here.
+fn queues() -> &'static AppQueues {
+    QUEUES.get().expect("daemon startup hook didn't run")
+}
+
+fn main() {
+    unsafe {
+        FREERTOS_HOOKS.set_on_daemon_startup(|os| {
+            println!("Daemon startup: creating queues");
+            let queues = AppQueues {
+                readings: Queue::new(os, 8).unwrap(),
+            };
+            if QUEUES.set(queues).is_err() {
+                panic!("queues created twice");
+            }
+        });
+    }
+
+    let error = FreeRTOS::try_start_scheduler(|os| {
+        os.new_task("producer", 128, TaskPriority(2), move |_, os| {
+            let mut reading = 0;
+            loop {
+                queues()
+                    .readings
+                    .send(reading, Duration::infinite())
+                    .unwrap();
+                reading += 1;
+                os.delay(Duration::ms(500));
+            }
+        })?;
+
+        os.new_task("consumer", 128, TaskPriority(2), move |_, _| loop {
+            let reading = queues().readings.receive(Duration::infinite()).unwrap();
+            println!("Reading {}", reading);
+        })?;
+        Ok(())
+    });
+    println!("Scheduler failed to start: {}", error);
+}
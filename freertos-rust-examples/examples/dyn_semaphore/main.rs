@@ -0,0 +1,101 @@
+//! Uses binary and counting semaphores through `dyn Semaphore`, and checks that:
+//!
+//! * semaphores of both kinds can be kept in a `Vec<&dyn Semaphore>` and a
+//!   `Vec<Box<dyn Semaphore>>`, and given and taken through them,
+//! * `take` on a trait object takes any `DurationTicks` and fails with `Timeout`,
+//! * `lock` on a trait object gives the semaphore back when its guard is dropped,
+//! * code generic over `S: Semaphore` isn't generic over the duration type any more.
+//!
+//! The process exits with the number of failed checks.
+//!
+//!     cargo run --example dyn_semaphore --target x86_64-unknown-linux-gnu
+use freertos_rust::*;
+
+#[global_allocator]
+static GLOBAL: FreeRtosAllocator = FreeRtosAllocator;
+
+extern "C" {
+    // `std::process::exit` runs the exit handlers of the POSIX port, which cancel the
+    // threads the tasks run on while they are in Rust code.
+    fn _exit(status: i32) -> !;
+}
+
+/// Take `semaphore` until it runs out, with a `core::time::Duration` wait.
+fn drain<S: Semaphore>(semaphore: &S) -> u32 {
+    let mut taken = 0;
+    while semaphore.take(core::time::Duration::from_millis(0)).is_ok() {
+        taken += 1;
+    }
+    taken
+}
+
+fn main() {
+    FreeRTOS::start_scheduler(|os| {
+        os.new_task("checks", 512, TaskPriority(2), move |_, os| {
+            let mut failures = 0;
+
+            let binary = BinarySemaphore::new(os).unwrap();
+            let counting = CountingSemaphore::new(os, 3, 0).unwrap();
+            let semaphores: Vec<&dyn Semaphore> = vec![&binary, &counting];
+            for semaphore in &semaphores {
+                semaphore.give();
+                semaphore.give();
+            }
+            let taken: Vec<bool> = semaphores
+                .iter()
+                .map(|semaphore| semaphore.take(Duration::zero()).is_ok())
+                .collect();
+            let counts = (binary.is_taken(), counting.get_count());
+            let timeout = semaphores[0].take(Duration::ms(2));
+            if taken != [true, true]
+                || counts != (true, 1)
+                || timeout != Err(FreeRtosError::Timeout)
+            {
+                println!(
+                    "through &dyn Semaphore: taken {:?}, binary taken {}, count {}, then {:?}",
+                    taken, counts.0, counts.1, timeout
+                );
+                failures += 1;
+            }
+
+            {
+                let guard = semaphores[1].lock(Duration::zero());
+                let locked_count = counting.get_count();
+                drop(guard);
+                if locked_count != 0 || counting.get_count() != 1 {
+                    println!(
+                        "lock through &dyn Semaphore: count {} while locked, {} after",
+                        locked_count,
+                        counting.get_count()
+                    );
+                    failures += 1;
+                }
+            }
+
+            let boxed: Vec<Box<dyn Semaphore>> = vec![
+                Box::new(BinarySemaphore::new(os).unwrap()),
+                Box::new(CountingSemaphore::new(os, 2, 2).unwrap()),
+            ];
+            boxed[0].give();
+            let taken: Vec<bool> = boxed
+                .iter()
+                .map(|semaphore| semaphore.take_ticks(0).is_ok())
+                .collect();
+            if taken != [true, true] || boxed[0].take(Duration::zero()).is_ok() {
+                println!("through Box<dyn Semaphore>: taken {:?}", taken);
+                failures += 1;
+            }
+
+            counting.give();
+            let drained = (drain(&binary), drain(&counting));
+            if drained != (0, 2) {
+                println!("drained {:?}", drained);
+                failures += 1;
+            }
+
+            println!("{} failures", failures);
+            unsafe { _exit(failures) }
+        })
+        .unwrap();
+    });
+}
@@ -0,0 +1,219 @@
+//! Triggers an `EmergencyBroadcast` in deterministic virtual time, with the
+//! `emergency_abort` feature, and checks that:
+//!
+//! * tasks blocked in a delay, a queue receive and a mutex lock are woken within a tick
+//!   of the trigger, and the receive and the lock fail with `Emergency`,
+//! * the woken tasks read the reason of the trigger from the broadcast, and the latency
+//!   until all of them observed it is measured,
+//! * once rearmed, a receive times out with its own error again,
+//! * a trigger from an ISR wakes a receive through the timer daemon within a tick.
+//!
+//! The process exits with the number of failed checks.
+//!
+//!     cargo run --example emergency --features emergency_abort,test_support --target x86_64-unknown-linux-gnu
+use freertos_rust::test_support::*;
+use freertos_rust::*;
+use std::sync::{Arc, OnceLock};
+
+#[global_allocator]
+static GLOBAL: FreeRtosAllocator = FreeRtosAllocator;
+
+extern "C" {
+    // `std::process::exit` runs the exit handlers of the POSIX port, which cancel the
+    // threads the tasks run on while they are in Rust code.
+    fn _exit(status: i32) -> !;
+}
+
+const DELAYER: u8 = 0;
+const RECEIVER: u8 = 1;
+const LOCKER: u8 = 2;
+
+/// What a task saw when its blocking call returned.
+#[derive(Copy, Clone, Debug)]
+struct Report {
+    task: u8,
+    tick: FreeRtosTickType,
+    result: Result<(), FreeRtosError>,
+    reason: Option<u8>,
+}
+
+static ISR_BROADCAST: OnceLock<EmergencyBroadcastISRHandle> = OnceLock::new();
+
+fn isr(context: &mut InterruptContext) {
+    ISR_BROADCAST.get().unwrap().trigger(context, 7);
+}
+
+fn main() {
+    enable_deterministic_virtual_time();
+
+    FreeRTOS::start_scheduler(|os| {
+        os.new_task("checks", 1024, TaskPriority(3), move |_, os| {
+            let mut failures = 0;
+            let mut check = |ok: bool, what: &str| {
+                if !ok {
+                    println!("failed: {}", what);
+                    failures += 1;
+                }
+            };
+
+            let broadcast = Arc::new(os.new_emergency_broadcast(4));
+            let reports = Arc::new(os.new_queue::<Report>(4).unwrap());
+            let work = Arc::new(os.new_queue::<u32>(1).unwrap());
+            let mutex = Arc::new(os.new_mutex(()).unwrap());
+
+            // Each task registers itself, with the handle it is given.
+            let (b, r) = (broadcast.clone(), reports.clone());
+            os.new_task("delayer", 256, TaskPriority(2), move |this, os| {
+                b.register(&os, this, 0, true).unwrap();
+                os.delay(Duration::ms(10_000));
+                b.observe(&os);
+                let report = Report {
+                    task: DELAYER,
+                    tick: os.get_tick_count(),
+                    result: Ok(()),
+                    reason: b.last_event().map(|e| e.reason),
+                };
+                r.send(report, Duration::infinite()).unwrap();
+                b.unregister(&os, this).unwrap();
+                loop {
+                    os.delay(Duration::infinite());
+                }
+            })
+            .unwrap();
+
+            let (b, r, w) = (broadcast.clone(), reports.clone(), work.clone());
+            os.new_task("receiver", 256, TaskPriority(2), move |this, os| {
+                b.register(&os, this, 0, true).unwrap();
+                loop {
+                    let received = w.receive(Duration::ms(1_000));
+                    b.observe(&os);
+                    let report = Report {
+                        task: RECEIVER,
+                        tick: os.get_tick_count(),
+                        result: received.map(|_| ()),
+                        reason: b.last_event().map(|e| e.reason),
+                    };
+                    r.send(report, Duration::infinite()).unwrap();
+                }
+            })
+            .unwrap();
+
+            let (b, r, m) = (broadcast.clone(), reports.clone(), mutex.clone());
+            os.new_task("locker", 256, TaskPriority(2), move |this, os| {
+                b.register(&os, this, 0, true).unwrap();
+                let locked = m.lock(Duration::ms(10_000)).map(|_| ());
+                b.observe(&os);
+                let report = Report {
+                    task: LOCKER,
+                    tick: os.get_tick_count(),
+                    result: locked,
+                    reason: b.last_event().map(|e| e.reason),
+                };
+                r.send(report, Duration::infinite()).unwrap();
+                b.unregister(&os, this).unwrap();
+                loop {
+                    os.delay(Duration::infinite());
+                }
+            })
+            .unwrap();
+
+            // Everyone registers and blocks, the locker on the mutex this task holds.
+            let guard = mutex.lock(Duration::infinite()).unwrap();
+            os.delay(Duration::ms(50));
+            check(broadcast.check().is_ok(), "the broadcast starts armed");
+
+            broadcast.trigger(&os, 42);
+            let triggered_at = os.get_tick_count();
+            check(
+                broadcast.check() == Err(FreeRtosError::Emergency),
+                "check fails",
+            );
+            check(
+                broadcast.status().get()
+                    == Some(EmergencyEvent {
+                        reason: 42,
+                        tick: triggered_at,
+                    }),
+                "the status cell holds the reason and tick",
+            );
+
+            let mut seen = [false; 3];
+            for _ in 0..3 {
+                let report = match reports.receive(Duration::ms(100)) {
+                    Ok(report) => report,
+                    Err(_) => break,
+                };
+                println!("{:?}", report);
+                seen[report.task as usize] = true;
+                check(report.tick - triggered_at <= 1, "woken within a tick");
+                check(report.reason == Some(42), "the woken task read the reason");
+                let expected = if report.task == DELAYER {
+                    Ok(())
+                } else {
+                    Err(FreeRtosError::Emergency)
+                };
+                check(report.result == expected, "the wait failed with Emergency");
+            }
+            check(seen == [true; 3], "every registered task was woken");
+            let latency = broadcast.latency(&os);
+            println!(
+                "ticks until every task observed the trigger: {:?}",
+                latency.map(|l| l.to_ticks())
+            );
+            check(
+                latency.map_or(false, |l| l.to_ticks() <= 1),
+                "every task observed the trigger within a tick",
+            );
+            drop(guard);
+            // The delayer and the locker unregister.
+            os.delay(Duration::ms(10));
+
+            // The receiver waits again. Rearmed, it times out as usual.
+            broadcast.rearm(&os);
+            check(!broadcast.is_triggered(), "rearming clears the trigger");
+            check(
+                broadcast.status().get().is_none(),
+                "rearming clears the status",
+            );
+            check(
+                broadcast.latency(&os).is_none(),
+                "rearming clears the latency",
+            );
+            let report = reports.receive(Duration::ms(2_000)).unwrap();
+            check(
+                report.result == Err(FreeRtosError::QueueReceiveTimeout),
+                "a rearmed receive times out",
+            );
+
+            let _ = ISR_BROADCAST.set(unsafe { broadcast.new_isr_safe_handle() });
+            let at = schedule_isr(Duration::ms(100), isr).unwrap();
+            let report = reports.receive(Duration::ms(1_000)).unwrap();
+            println!("{:?}, the ISR ran at {}", report, at);
+            check(
+                report.result == Err(FreeRtosError::Emergency),
+                "the ISR trigger aborts the receive",
+            );
+            check(report.tick - at <= 1, "the ISR trigger wakes within a tick");
+            check(
+                report.reason == Some(7),
+                "the reason of the ISR round-trips",
+            );
+            check(
+                broadcast.last_event()
+                    == Some(EmergencyEvent {
+                        reason: 7,
+                        tick: at,
+                    }),
+                "the ISR recorded its tick",
+            );
+            check(
+                broadcast.latency(&os).map_or(false, |l| l.to_ticks() <= 1),
+                "the receiver observed the ISR trigger within a tick",
+            );
+
+            println!("{} failures", failures);
+            unsafe { _exit(failures) }
+        })
+        .unwrap();
+    });
+}
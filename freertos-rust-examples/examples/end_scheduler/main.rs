@@ -0,0 +1,66 @@
+//! Runs the scheduler with `run_scheduler_until` and returns to `main`, and checks that:
+//!
+//! * the result of the test task comes back to `main`, here worked out with a task it
+//!   created,
+//! * `main` can still allocate afterwards, and exit normally.
+//!
+//! The scheduler ends with one task blocked on a queue and another spinning in Rust code,
+//! neither of which may take the process down.
+//!
+//! The process exits with the number of failed checks.
+//!
+//!     cargo run --example end_scheduler --features end_scheduler --target x86_64-unknown-linux-gnu
+use freertos_rust::*;
+use std::sync::atomic::{AtomicU32, Ordering};
+
+#[global_allocator]
+static GLOBAL: FreeRtosAllocator = FreeRtosAllocator;
+
+/// Counted up by a task that never blocks.
+static SPINS: AtomicU32 = AtomicU32::new(0);
+
+fn main() {
+    let mut failures = 0;
+
+    let result = FreeRTOS::run_scheduler_until(512, TaskPriority(2), |os| {
+        let requests = std::sync::Arc::new(os.new_queue::<u32>(1).unwrap());
+        let replies = std::sync::Arc::new(os.new_queue::<u32>(1).unwrap());
+        let (q, r) = (requests.clone(), replies.clone());
+        os.new_task("squarer", 256, TaskPriority(3), move |_, _| loop {
+            let n = q.receive(Duration::infinite()).unwrap();
+            r.send(n * n, Duration::infinite()).unwrap();
+        })
+        .unwrap();
+        os.new_task("spinner", 256, TaskPriority(1), |_, _| loop {
+            SPINS.fetch_add(1, Ordering::SeqCst);
+        })
+        .unwrap();
+
+        // Let the spinner run, so it is in Rust code when the scheduler ends.
+        os.delay(Duration::ms(5));
+        let mut squares = Vec::new();
+        for n in 1..=4 {
+            requests.send(n, Duration::infinite()).unwrap();
+            squares.push(replies.receive(Duration::ms(100)).unwrap());
+        }
+        squares
+    });
+
+    if result != Ok(vec![1, 4, 9, 16]) {
+        println!("run_scheduler_until returned {:?}", result);
+        failures += 1;
+    }
+    if SPINS.load(Ordering::SeqCst) == 0 {
+        println!("the spinning task didn't run");
+        failures += 1;
+    }
+
+    let after: Vec<u32> = (0..100).collect();
+    if after.iter().sum::<u32>() != 4950 {
+        println!("allocating after the scheduler ended");
+        failures += 1;
+    }
+
+    println!("{} failures", failures);
+    std::process::exit(failures)
+}
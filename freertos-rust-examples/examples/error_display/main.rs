@@ -0,0 +1,100 @@
+//! Formats `FreeRtosError`s and uses them as `std::error::Error`s, and checks that:
+//!
+//! * every error has its own message, `RegistryFull` names the registry and
+//!   `MutexTimeoutHeldBy` the task,
+//! * errors convert into `Box<dyn Error>` with `?`, keeping the message,
+//! * `TaskHandle::get_name` returns the name with `FreeRtosError` as its error type.
+//!
+//! The process exits with the number of failed checks.
+//!
+//!     cargo run --example error_display --target x86_64-unknown-linux-gnu
+use freertos_rust::*;
+use std::collections::HashSet;
+use std::error::Error;
+
+#[global_allocator]
+static GLOBAL: FreeRtosAllocator = FreeRtosAllocator;
+
+extern "C" {
+    // `std::process::exit` runs the exit handlers of the POSIX port, which cancel the
+    // threads the tasks run on while they are in Rust code.
+    fn _exit(status: i32) -> !;
+}
+
+fn receive_now(queue: &Queue<u32>) -> Result<u32, Box<dyn Error>> {
+    Ok(queue.receive(Duration::zero())?)
+}
+
+fn main() {
+    FreeRTOS::start_scheduler(|os| {
+        os.new_task("checks", 512, TaskPriority(2), move |this, os| {
+            let mut failures = 0;
+
+            let errors = [
+                FreeRtosError::OutOfMemory,
+                FreeRtosError::QueueSendTimeout,
+                FreeRtosError::QueueReceiveTimeout,
+                FreeRtosError::MutexTimeout,
+                FreeRtosError::Timeout,
+                FreeRtosError::QueueFull,
+                FreeRtosError::StringConversionError,
+                FreeRtosError::TaskNotFound,
+                FreeRtosError::InvalidQueueSize,
+                FreeRtosError::ProcessorHasShutDown,
+                FreeRtosError::Emergency,
+                FreeRtosError::StorageInUse,
+                FreeRtosError::RegistryFull(RegistryKind::HandleTable),
+                FreeRtosError::TaskNotBlocked,
+                FreeRtosError::RendezvousSendTimeout,
+                FreeRtosError::RendezvousReceiveTimeout,
+                FreeRtosError::NameTooLong,
+                FreeRtosError::InvalidName,
+                FreeRtosError::StackTooLarge,
+                FreeRtosError::MutexTimeoutHeldBy(TaskName::from_bytes(b"holder")),
+                FreeRtosError::TooManyRegions,
+                FreeRtosError::InQueueSet,
+                FreeRtosError::QueueSetMemberNotEmpty,
+                FreeRtosError::InterruptAlreadyEnabled,
+            ];
+            let messages: HashSet<String> = errors.iter().map(|e| e.to_string()).collect();
+            let registry = FreeRtosError::RegistryFull(RegistryKind::HandleTable).to_string();
+            let held =
+                FreeRtosError::MutexTimeoutHeldBy(TaskName::from_bytes(b"holder")).to_string();
+            if messages.len() != errors.len()
+                || messages.iter().any(|m| m.is_empty())
+                || !registry.contains("HandleTable")
+                || !held.contains("holder")
+            {
+                println!(
+                    "{} distinct messages for {} errors: {:?}",
+                    messages.len(),
+                    errors.len(),
+                    messages
+                );
+                failures += 1;
+            }
+
+            let queue = Queue::new(os, 1).unwrap();
+            match receive_now(&queue) {
+                Err(e)
+                    if e.to_string() == FreeRtosError::QueueReceiveTimeout.to_string()
+                        && e.downcast_ref::<FreeRtosError>()
+                            == Some(&FreeRtosError::QueueReceiveTimeout) => {}
+                result => {
+                    println!("receiving from an empty queue: {:?}", result);
+                    failures += 1;
+                }
+            }
+
+            let name: Result<String, FreeRtosError> = this.get_name();
+            if name.as_deref() != Ok("checks") {
+                println!("task name {:?}", name);
+                failures += 1;
+            }
+
+            println!("{} failures", failures);
+            unsafe { _exit(failures) }
+        })
+        .unwrap();
+    });
+}
@@ -0,0 +1,208 @@
+//! Gives heap_5 two separate regions with `allocator::init_with_regions`, and checks that:
+//!
+//! * `regions` lists them by address, however they were passed,
+//! * every block comes from within one region, and both regions are used up before
+//!   allocations fail,
+//! * `heap_stats` covers the regions, and all of their space comes back when the blocks
+//!   are freed,
+//! * with `alloc_checks`, an allocation before `init_with_regions` calls the premature
+//!   allocation hook, checked in a second run of this example.
+//!
+//! The process exits with the number of failed checks.
+//!
+//!     cargo run --example heap_regions --features heap_5,alloc_checks --target x86_64-unknown-linux-gnu
+use freertos_rust::*;
+use std::alloc::{GlobalAlloc, Layout};
+use std::os::raw::c_char;
+use std::process::Command;
+use std::sync::atomic::{AtomicBool, Ordering};
+
+#[global_allocator]
+static GLOBAL: FreeRtosAllocator = FreeRtosAllocator;
+
+const REGION_SIZE: usize = 32 * 1024;
+const BLOCK_SIZE: usize = 1000;
+const MAX_BLOCKS: usize = 2 * REGION_SIZE / BLOCK_SIZE;
+/// Set for the run allocating before the regions are defined.
+const PREMATURE: &[u8] = b"HEAP_REGIONS_PREMATURE\0";
+
+#[repr(align(16))]
+struct Region([u8; REGION_SIZE]);
+
+static mut LOW: Region = Region([0; REGION_SIZE]);
+static mut HIGH: Region = Region([0; REGION_SIZE]);
+
+static INITIALIZED_EARLY: AtomicBool = AtomicBool::new(false);
+
+/// The standard library allocates before `main`, so the regions are defined by a
+/// constructor, like the startup code of a firmware would.
+#[used]
+#[link_section = ".init_array"]
+static DEFINE_REGIONS: extern "C" fn() = define_regions;
+
+extern "C" {
+    fn getenv(name: *const c_char) -> *const c_char;
+    fn write(fd: i32, buf: *const u8, count: usize) -> isize;
+    // `std::process::exit` runs the exit handlers of the POSIX port, which cancel the
+    // threads the tasks run on while they are in Rust code.
+    fn _exit(status: i32) -> !;
+}
+
+/// The hooks of the premature run. Nothing may allocate before the regions are defined,
+/// so no `println!`.
+fn premature_allocation_detected(_size: usize) {
+    let message = b"premature allocation detected\n";
+    unsafe {
+        write(2, message.as_ptr(), message.len());
+        _exit(0)
+    }
+}
+
+/// heap_5 asserts on its own when it has no regions, which only happens without
+/// `alloc_checks`.
+fn kernel_assert() {
+    let message = b"premature allocation only caught by the kernel\n";
+    unsafe {
+        write(2, message.as_ptr(), message.len());
+        _exit(2)
+    }
+}
+
+fn premature_run() -> ! {
+    unsafe {
+        FREERTOS_HOOKS.set_on_premature_alloc(premature_allocation_detected);
+        FREERTOS_HOOKS.set_on_assert(kernel_assert);
+        let block = Box::new(1u32);
+        std::hint::black_box(&block);
+        let message = b"allocation before init_with_regions wasn't detected\n";
+        write(2, message.as_ptr(), message.len());
+        _exit(1)
+    }
+}
+
+fn regions_by_address() -> [HeapRegion; 2] {
+    let (low, high) = unsafe {
+        (
+            std::ptr::addr_of_mut!(LOW.0) as *mut u8,
+            std::ptr::addr_of_mut!(HIGH.0) as *mut u8,
+        )
+    };
+    let (low, high) = if low < high { (low, high) } else { (high, low) };
+    [
+        HeapRegion {
+            start: low,
+            size: REGION_SIZE,
+        },
+        HeapRegion {
+            start: high,
+            size: REGION_SIZE,
+        },
+    ]
+}
+
+extern "C" fn define_regions() {
+    if unsafe { !getenv(PREMATURE.as_ptr() as *const c_char).is_null() } {
+        premature_run();
+    }
+
+    INITIALIZED_EARLY.store(allocator::is_initialized(), Ordering::Relaxed);
+    let [low, high] = regions_by_address();
+    // The higher one first, to be sorted.
+    unsafe { allocator::init_with_regions(&[high, low]) };
+}
+
+fn main() {
+    let initialized_early = INITIALIZED_EARLY.load(Ordering::Relaxed);
+    let [low, high] = regions_by_address();
+    let mut failures = 0;
+
+    if initialized_early || !allocator::is_initialized() {
+        println!(
+            "is_initialized: {} before, {} after",
+            initialized_early,
+            allocator::is_initialized()
+        );
+        failures += 1;
+    }
+    if allocator::regions() != [low, high] {
+        println!(
+            "regions {:?}, expected {:?}",
+            allocator::regions(),
+            [low, high]
+        );
+        failures += 1;
+    }
+
+    let before = FreeRtosAllocator::heap_stats().unwrap();
+    if before.available > 2 * REGION_SIZE || before.available < 2 * REGION_SIZE - 1024 {
+        println!(
+            "{} bytes available in {} of regions",
+            before.available,
+            2 * REGION_SIZE
+        );
+        failures += 1;
+    }
+
+    // Allocate until the heap is exhausted.
+    let layout = Layout::from_size_align(BLOCK_SIZE, 8).unwrap();
+    let mut blocks = [std::ptr::null_mut::<u8>(); MAX_BLOCKS];
+    let mut count = 0;
+    while count < MAX_BLOCKS {
+        let block = unsafe { GLOBAL.alloc(layout) };
+        if block.is_null() {
+            break;
+        }
+        blocks[count] = block;
+        count += 1;
+    }
+    let outside = blocks[..count]
+        .iter()
+        .filter(|&&b| {
+            let end = unsafe { b.add(BLOCK_SIZE - 1) };
+            !(low.contains(b) && low.contains(end)) && !(high.contains(b) && high.contains(end))
+        })
+        .count();
+    let in_low = blocks[..count].iter().filter(|&&b| low.contains(b)).count();
+    if outside != 0 || in_low == 0 || in_low == count || count < MAX_BLOCKS - 6 {
+        println!(
+            "{} blocks, {} in the low region, {} outside of the regions",
+            count, in_low, outside
+        );
+        failures += 1;
+    }
+    for &block in &blocks[..count] {
+        unsafe { GLOBAL.dealloc(block, layout) };
+    }
+    let after = FreeRtosAllocator::heap_stats().unwrap();
+    if after.available != before.available || after.allocations < count {
+        println!("heap stats {:?} after freeing, {:?} before", after, before);
+        failures += 1;
+    }
+
+    // Before the scheduler starts, so no task thread is around for the fork.
+    let exe = std::env::current_exe().unwrap();
+    let premature = Command::new(exe)
+        .env("HEAP_REGIONS_PREMATURE", "1")
+        .status()
+        .unwrap();
+    if !premature.success() {
+        println!("premature allocation run: {}", premature);
+        failures += 1;
+    }
+
+    FreeRTOS::start_scheduler(move |os| {
+        os.new_task("checks", 512, TaskPriority(2), move |_, _| {
+            let mut failures = failures;
+            let boxed = Box::new([0u8; 64]);
+            let address = &*boxed as *const _ as *const u8;
+            if !low.contains(address) && !high.contains(address) {
+                println!("task allocation at {:?} outside of the regions", address);
+                failures += 1;
+            }
+
+            println!("{} failures", failures);
+            unsafe { _exit(failures) }
+        })
+        .unwrap();
+    });
+}
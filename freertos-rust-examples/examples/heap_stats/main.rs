@@ -0,0 +1,90 @@
+//! Reads heap_4's statistics through `FreeRTOS::heap_stats`, and checks that:
+//!
+//! * allocating a large block shrinks the largest free block, and freeing it gives the
+//!   space back,
+//! * freeing every other one of a row of blocks of varying sizes leaves more free
+//!   blocks, and the allocation and free counts follow,
+//! * `available` matches `FreeRtosAllocator::free_heap_size`,
+//! * the `Display` table shows every field.
+//!
+//! The process exits with the number of failed checks.
+//!
+//!     cargo run --example heap_stats --target x86_64-unknown-linux-gnu
+use freertos_rust::*;
+
+#[global_allocator]
+static GLOBAL: FreeRtosAllocator = FreeRtosAllocator;
+
+extern "C" {
+    // `std::process::exit` runs the exit handlers of the POSIX port, which cancel the
+    // threads the tasks run on while they are in Rust code.
+    fn _exit(status: i32) -> !;
+}
+
+fn main() {
+    FreeRTOS::start_scheduler(|os| {
+        os.new_task("checks", 512, TaskPriority(2), move |_, os| {
+            let mut failures = 0;
+
+            let before = os.heap_stats().unwrap();
+            let large = vec![0u8; before.largest_free_block / 2];
+            let during = os.heap_stats().unwrap();
+            drop(large);
+            let after = os.heap_stats().unwrap();
+            if during.largest_free_block >= before.largest_free_block
+                || during.available >= before.available
+                || after.largest_free_block != before.largest_free_block
+                || after.available != before.available
+                || during.allocations != before.allocations + 1
+                || after.frees != before.frees + 1
+            {
+                println!("before a large block:\n{}", before);
+                println!("with it:\n{}", during);
+                println!("after freeing it:\n{}", after);
+                failures += 1;
+            }
+
+            let mut blocks: Vec<Option<Vec<u8>>> =
+                (0..16).map(|i| Some(vec![0u8; 64 + 48 * i])).collect();
+            let allocated = os.heap_stats().unwrap();
+            for block in blocks.iter_mut().step_by(2) {
+                *block = None;
+            }
+            let fragmented = os.heap_stats().unwrap();
+            if fragmented.free_blocks < allocated.free_blocks + 7
+                || fragmented.frees != allocated.frees + 8
+                || fragmented.smallest_free_block > 64 + 16
+                || fragmented.available != FreeRtosAllocator::free_heap_size()
+            {
+                println!("with 16 blocks:\n{}", allocated);
+                println!("with every other one freed:\n{}", fragmented);
+                failures += 1;
+            }
+            drop(blocks);
+
+            let table = format!("{}", fragmented);
+            let fields = [
+                "Available",
+                "Largest free block",
+                "Smallest free block",
+                "Free blocks",
+                "Minimum ever available",
+                "Allocations",
+                "Frees",
+            ];
+            let missing: Vec<&str> = fields
+                .iter()
+                .filter(|field| !table.lines().any(|l| l.starts_with(*field)))
+                .copied()
+                .collect();
+            if table.lines().count() != 1 + fields.len() || !missing.is_empty() {
+                println!("table without {:?}:\n{}", missing, table);
+                failures += 1;
+            }
+
+            println!("{} failures", failures);
+            unsafe { _exit(failures) }
+        })
+        .unwrap();
+    });
+}
@@ -0,0 +1,165 @@
+//! Runs an interrupt callback with resources owned by an `InterruptScope`, and checks
+//! that:
+//!
+//! * the callback gives the semaphores it borrows through `BorrowedISRHandle`s, waking
+//!   the task waiting for them,
+//! * the scope lends its resources to tasks as well, here a count of the interrupts,
+//! * the interrupt isn't called once the scope returned, and can be opened again.
+//!
+//! The POSIX port has no interrupts, so `SimulatedInterrupt` is a controller whose
+//! interrupt is raised by calling `raise` from a task at the highest priority of the
+//! example, like an interrupt preempting the other tasks.
+//!
+//! The process exits with the number of failed checks.
+//!
+//!     cargo run --example interrupt_scope --target x86_64-unknown-linux-gnu
+use freertos_rust::*;
+use std::cell::UnsafeCell;
+use std::sync::atomic::{AtomicBool, AtomicU32, Ordering};
+use std::sync::Arc;
+
+#[global_allocator]
+static GLOBAL: FreeRtosAllocator = FreeRtosAllocator;
+
+extern "C" {
+    // `std::process::exit` runs the exit handlers of the POSIX port, which cancel the
+    // threads the tasks run on while they are in Rust code.
+    fn _exit(status: i32) -> !;
+}
+
+type Handler = Box<dyn Fn(&mut InterruptContext<KernelIsr>)>;
+
+/// The callback of the enabled interrupt. Only the task raising the interrupt and the
+/// scope touch it, never at the same time.
+struct HandlerSlot(UnsafeCell<Option<Handler>>);
+
+unsafe impl Sync for HandlerSlot {}
+
+static HANDLER: HandlerSlot = HandlerSlot(UnsafeCell::new(None));
+
+/// An interrupt controller for the simulator, raised by `raise`.
+struct SimulatedInterrupt;
+
+impl SimulatedInterrupt {
+    /// Call the callback of the interrupt in an interrupt context, if it is enabled.
+    /// Returns whether it was.
+    fn raise() -> bool {
+        match unsafe { &*HANDLER.0.get() } {
+            Some(handler) => {
+                let mut context = InterruptContext::new(unsafe { KernelIsr::claim() });
+                handler(&mut context);
+                true
+            }
+            None => false,
+        }
+    }
+}
+
+impl InterruptController for SimulatedInterrupt {
+    type Class = KernelIsr;
+
+    fn enabled_flag() -> &'static AtomicBool {
+        static ENABLED: AtomicBool = AtomicBool::new(false);
+        &ENABLED
+    }
+
+    unsafe fn enable(callback: Handler) {
+        *HANDLER.0.get() = Some(callback);
+    }
+
+    unsafe fn disable() {
+        *HANDLER.0.get() = None;
+    }
+}
+
+/// What the interrupt uses: two semaphores it borrows, and a count of its own.
+struct Resources<'a> {
+    data_ready: BorrowedISRHandle<'a, ISRBinarySemaphore>,
+    buffers: BorrowedISRHandle<'a, ISRCountingSemaphore>,
+    raised: AtomicU32,
+}
+
+impl ISRResources for Resources<'_> {}
+
+const RAISES: u32 = 3;
+
+fn main() {
+    FreeRTOS::start_scheduler(|os| {
+        os.new_task("isr", 512, TaskPriority(3), move |_, os| {
+            let mut failures = 0;
+
+            let data_ready = Arc::new(os.new_binary_semaphore().unwrap());
+            let buffers = os.new_counting_semaphore(RAISES, 0).unwrap();
+            let woken = Arc::new(AtomicU32::new(0));
+
+            let (d, w) = (data_ready.clone(), woken.clone());
+            os.new_task("reader", 256, TaskPriority(2), move |_, _| loop {
+                d.take(Duration::infinite()).unwrap();
+                w.fetch_add(1, Ordering::SeqCst);
+            })
+            .unwrap();
+            os.delay(Duration::ms(5));
+
+            let raised = InterruptScope::<SimulatedInterrupt, _>::scope(
+                Resources {
+                    data_ready: BorrowedISRHandle::new(&*data_ready),
+                    buffers: BorrowedISRHandle::new(&buffers),
+                    raised: AtomicU32::new(0),
+                },
+                |context, resources: &Resources| {
+                    resources.raised.fetch_add(1, Ordering::SeqCst);
+                    resources.buffers.give(context);
+                    resources.data_ready.give(context);
+                },
+                |scope| {
+                    for _ in 0..RAISES {
+                        if !SimulatedInterrupt::raise() {
+                            println!("the interrupt wasn't enabled by the scope");
+                            failures += 1;
+                        }
+                        // The reader runs once this task waits.
+                        os.delay(Duration::ms(5));
+                    }
+                    scope.resources().raised.load(Ordering::SeqCst)
+                },
+            )
+            .unwrap();
+
+            let woken_count = woken.load(Ordering::SeqCst);
+            if raised != RAISES || woken_count != RAISES || buffers.get_count() != RAISES {
+                println!(
+                    "{} raised, reader woken {} times, {} buffers for {} interrupts",
+                    raised,
+                    woken_count,
+                    buffers.get_count(),
+                    RAISES
+                );
+                failures += 1;
+            }
+
+            if SimulatedInterrupt::raise() {
+                println!("the interrupt was called after the scope returned");
+                failures += 1;
+            }
+
+            // Only borrows now, as the first scope is gone.
+            InterruptScope::<SimulatedInterrupt, _>::scope(
+                BorrowedISRHandle::new(&buffers),
+                |context, buffers: &BorrowedISRHandle<ISRCountingSemaphore>| {
+                    buffers.take(context);
+                },
+                |_| SimulatedInterrupt::raise(),
+            )
+            .unwrap();
+            if buffers.get_count() != RAISES - 1 {
+                println!("the reopened interrupt didn't take a buffer");
+                failures += 1;
+            }
+            drop(buffers);
+
+            println!("{} failures", failures);
+            unsafe { _exit(failures) }
+        })
+        .unwrap();
+    });
+}
@@ -0,0 +1,162 @@
+//! Gives and takes semaphores from an interrupt context with `ISRBinarySemaphore::give`
+//! and `ISRCountingSemaphore`, and checks that:
+//!
+//! * a task blocked on `lock` of a binary semaphore is woken by the give,
+//! * giving a binary semaphore that is already available returns false,
+//! * a counting semaphore counts gives from an interrupt up to its maximum, and returns
+//!   false for gives above it,
+//! * `count_from_isr` follows gives and takes from an interrupt, and `take` returns
+//!   false once the count is zero,
+//! * a task drains exactly as many as an interrupt gave, with DMA buffers as the use case.
+//!
+//! The POSIX port has no interrupts, so a task at the highest priority of the example
+//! plays the interrupt handler.
+//!
+//! The process exits with the number of failed checks.
+//!
+//!     cargo run --example isr_give --target x86_64-unknown-linux-gnu
+use freertos_rust::*;
+use std::sync::atomic::{AtomicU32, Ordering};
+use std::sync::Arc;
+
+#[global_allocator]
+static GLOBAL: FreeRtosAllocator = FreeRtosAllocator;
+
+const COUNTING_MAX: u32 = 3;
+/// The number of buffers the interrupt hands to the draining task.
+const BUFFERS: u32 = 5;
+
+/// The tick count the waiter was woken at, plus one so 0 means it wasn't.
+static WOKEN_AT: AtomicU32 = AtomicU32::new(0);
+static TAKEN: AtomicU32 = AtomicU32::new(0);
+
+extern "C" {
+    // `std::process::exit` runs the exit handlers of the POSIX port, which cancel the
+    // threads the tasks run on while they are in Rust code.
+    fn _exit(status: i32) -> !;
+}
+
+fn main() {
+    FreeRTOS::start_scheduler(|os| {
+        os.new_task("isr", 512, TaskPriority(3), move |_, os| {
+            let mut failures = 0;
+
+            let binary = Arc::new(os.new_binary_semaphore().unwrap());
+            let counting = Arc::new(os.new_counting_semaphore(COUNTING_MAX, 0).unwrap());
+
+            let b = binary.clone();
+            os.new_task("waiter", 256, TaskPriority(2), move |_, os| {
+                let guard = b.lock(Duration::infinite()).unwrap();
+                WOKEN_AT.store(os.get_tick_count() + 1, Ordering::Relaxed);
+                // Leaves the semaphore available.
+                drop(guard);
+                loop {
+                    os.delay(Duration::ms(10_000));
+                }
+            })
+            .unwrap();
+            let c = counting.clone();
+            os.new_task("counter", 256, TaskPriority(2), move |_, _| loop {
+                c.take(Duration::infinite()).unwrap();
+                TAKEN.fetch_add(1, Ordering::Relaxed);
+            })
+            .unwrap();
+            os.delay(Duration::ms(5));
+
+            let binary_handle = unsafe { binary.new_isr_safe_handle() };
+            let counting_handle = unsafe { counting.new_isr_safe_handle() };
+
+            let given_at = os.get_tick_count();
+            let given = {
+                let mut context = InterruptContext::new(unsafe { KernelIsr::claim() });
+                binary_handle.give(&mut context)
+            };
+            os.delay(Duration::ms(5));
+            let woken_at = WOKEN_AT.load(Ordering::Relaxed);
+            if !given || woken_at == 0 || woken_at - 1 - given_at > 1 {
+                println!(
+                    "give returned {}, waiter woken at {:?} for a give at {}",
+                    given,
+                    woken_at.checked_sub(1),
+                    given_at
+                );
+                failures += 1;
+            }
+
+            // The waiter gave it back.
+            let given_again = {
+                let mut context = InterruptContext::new(unsafe { KernelIsr::claim() });
+                binary_handle.give(&mut context)
+            };
+            if given_again {
+                println!("give of an available binary semaphore returned true");
+                failures += 1;
+            }
+
+            let gives: Vec<bool> = {
+                let mut context = InterruptContext::new(unsafe { KernelIsr::claim() });
+                (0..COUNTING_MAX + 1)
+                    .map(|_| counting_handle.give(&mut context))
+                    .collect()
+            };
+            os.delay(Duration::ms(5));
+            if gives != [true, true, true, false] || TAKEN.load(Ordering::Relaxed) != COUNTING_MAX {
+                println!(
+                    "counting gives {:?}, {} taken",
+                    gives,
+                    TAKEN.load(Ordering::Relaxed)
+                );
+                failures += 1;
+            }
+
+            let buffers = Arc::new(os.new_counting_semaphore(BUFFERS + 2, 0).unwrap());
+            let buffers_handle = unsafe { buffers.new_isr_safe_handle() };
+            let (counts, takes) = {
+                let mut context = InterruptContext::new(unsafe { KernelIsr::claim() });
+                let empty_take = buffers_handle.take(&mut context);
+                let mut counts = vec![];
+                for _ in 0..BUFFERS + 1 {
+                    buffers_handle.give(&mut context);
+                    counts.push(buffers_handle.count_from_isr(&context));
+                }
+                let take = buffers_handle.take(&mut context);
+                counts.push(buffers_handle.count_from_isr(&context));
+                (counts, (empty_take, take))
+            };
+            if counts != [1, 2, 3, 4, 5, 6, 5] || takes != (false, true) {
+                println!("counts from the interrupt {:?}, takes {:?}", counts, takes);
+                failures += 1;
+            }
+
+            let drained = Arc::new(AtomicU32::new(0));
+            let (b, d) = (buffers.clone(), drained.clone());
+            os.new_task("drainer", 256, TaskPriority(2), move |_, os| {
+                while b.take(Duration::zero()).is_ok() {
+                    d.fetch_add(1, Ordering::Relaxed);
+                }
+                loop {
+                    os.delay(Duration::ms(10_000));
+                }
+            })
+            .unwrap();
+            os.delay(Duration::ms(5));
+            let left = {
+                let context = InterruptContext::new(unsafe { KernelIsr::claim() });
+                buffers_handle.count_from_isr(&context)
+            };
+            if drained.load(Ordering::Relaxed) != BUFFERS || left != 0 {
+                println!(
+                    "drained {} of {} buffers, {} left",
+                    drained.load(Ordering::Relaxed),
+                    BUFFERS,
+                    left
+                );
+                failures += 1;
+            }
+
+            println!("{} failures", failures);
+            unsafe { _exit(failures) }
+        })
+        .unwrap();
+    });
+}
@@ -0,0 +1,119 @@
+//! Gives notifications from interrupt handles with `TaskISRHandle::notify_give`, and
+//! checks that:
+//!
+//! * a task blocked in `try_take_notification` wakes once per give, right away when it
+//!   has the higher priority,
+//! * gives add up: taking with `clear` false gets 3, 2 and 1 after three gives, taking
+//!   with `clear` true gets 3 and clears them all,
+//! * `try_take_notification` fails with `Timeout` when nothing was given, after waiting.
+//!
+//! The POSIX port has no interrupts, so a task plays the interrupt handler.
+//!
+//! The process exits with the number of failed checks.
+//!
+//!     cargo run --example isr_notify_give --target x86_64-unknown-linux-gnu
+use freertos_rust::*;
+use std::sync::atomic::{AtomicU32, Ordering};
+
+#[global_allocator]
+static GLOBAL: FreeRtosAllocator = FreeRtosAllocator;
+
+const GIVES: u32 = 10;
+
+static WAKES: AtomicU32 = AtomicU32::new(0);
+/// The values the waiter took, added up.
+static TAKEN: AtomicU32 = AtomicU32::new(0);
+
+extern "C" {
+    // `std::process::exit` runs the exit handlers of the POSIX port, which cancel the
+    // threads the tasks run on while they are in Rust code.
+    fn _exit(status: i32) -> !;
+}
+
+fn give_from_isr(task: &TaskISRHandle) {
+    let mut context = InterruptContext::new(unsafe { KernelIsr::claim() });
+    task.notify_give(&mut context);
+}
+
+fn main() {
+    FreeRTOS::start_scheduler(|os| {
+        os.new_task("checks", 512, TaskPriority(2), move |this, os| {
+            let mut failures = 0;
+
+            let waiter = os
+                .new_task("waiter", 256, TaskPriority(3), move |this, _| loop {
+                    let value = this
+                        .try_take_notification(false, Duration::infinite())
+                        .unwrap();
+                    TAKEN.fetch_add(value, Ordering::SeqCst);
+                    WAKES.fetch_add(1, Ordering::SeqCst);
+                })
+                .unwrap();
+            os.delay(Duration::ms(2));
+            let waiter = waiter.new_isr_safe_handle();
+            let mut late = 0;
+            for give in 1..=GIVES {
+                give_from_isr(&waiter);
+                // The context yielded to the waiter when it was dropped.
+                if WAKES.load(Ordering::SeqCst) != give {
+                    late += 1;
+                }
+            }
+            if late != 0
+                || WAKES.load(Ordering::SeqCst) != GIVES
+                || TAKEN.load(Ordering::SeqCst) != GIVES
+            {
+                println!(
+                    "{} gives: {} wakes, {} of them late, values taken add up to {}",
+                    GIVES,
+                    WAKES.load(Ordering::SeqCst),
+                    late,
+                    TAKEN.load(Ordering::SeqCst)
+                );
+                failures += 1;
+            }
+
+            let own = this.new_isr_safe_handle();
+            let take_all = |clear| {
+                (0..4)
+                    .map(|_| this.try_take_notification(clear, Duration::zero()))
+                    .collect::<Vec<_>>()
+            };
+            for _ in 0..3 {
+                give_from_isr(&own);
+            }
+            let counted = take_all(false);
+            for _ in 0..3 {
+                give_from_isr(&own);
+            }
+            let cleared = take_all(true);
+            let ok = |taken: &[Result<u32, FreeRtosError>]| -> Vec<Option<u32>> {
+                taken.iter().map(|t| t.as_ref().ok().copied()).collect()
+            };
+            if ok(&counted) != [Some(3), Some(2), Some(1), None]
+                || ok(&cleared) != [Some(3), None, None, None]
+                || !matches!(counted[3], Err(FreeRtosError::Timeout))
+            {
+                println!(
+                    "takes after three gives: {:?} without clearing, {:?} clearing",
+                    counted, cleared
+                );
+                failures += 1;
+            }
+
+            let start = os.get_tick_count();
+            let nothing = this.try_take_notification(true, Duration::ms(10));
+            let waited = os.get_tick_count() - start;
+            if !matches!(nothing, Err(FreeRtosError::Timeout))
+                || waited < Duration::ms(10).to_ticks()
+            {
+                println!("take without a give: {:?} after {} ticks", nothing, waited);
+                failures += 1;
+            }
+
+            println!("{} failures", failures);
+            unsafe { _exit(failures) }
+        })
+        .unwrap();
+    });
+}
@@ -0,0 +1,69 @@
+//! Reads the tick count from interrupt contexts, and checks that:
+//!
+//! * `InterruptContext::tick_count` is the tick count tasks see, and follows it as time
+//!   passes,
+//! * timestamps taken in an interrupt order the events like the tasks see them,
+//! * `FreeRTOS::is_inside_interrupt` is false in tasks. The POSIX port has no
+//!   `xPortIsInsideInterrupt`, so without the `port_inside_interrupt` feature it is
+//!   false everywhere.
+//!
+//! The POSIX port has no interrupts, so a task plays the interrupt handler.
+//!
+//! The process exits with the number of failed checks.
+//!
+//!     cargo run --example isr_tick --target x86_64-unknown-linux-gnu
+use freertos_rust::*;
+
+#[global_allocator]
+static GLOBAL: FreeRtosAllocator = FreeRtosAllocator;
+
+extern "C" {
+    // `std::process::exit` runs the exit handlers of the POSIX port, which cancel the
+    // threads the tasks run on while they are in Rust code.
+    fn _exit(status: i32) -> !;
+}
+
+/// The tick count, taken as an interrupt handler would.
+fn isr_timestamp() -> FreeRtosTickType {
+    let context = InterruptContext::new(unsafe { KernelIsr::claim() });
+    context.tick_count()
+}
+
+fn main() {
+    FreeRTOS::start_scheduler(|os| {
+        os.new_task("checks", 512, TaskPriority(2), move |_, os| {
+            let mut failures = 0;
+
+            // Right after a tick, so none comes between the two reads.
+            os.delay(Duration::ticks(1));
+            let (task, isr) = (os.get_tick_count(), isr_timestamp());
+            if task != isr {
+                println!(
+                    "tick count {} in the task, {} from the interrupt",
+                    task, isr
+                );
+                failures += 1;
+            }
+
+            let mut stamps = Vec::new();
+            for _ in 0..5 {
+                os.delay(Duration::ticks(3));
+                stamps.push(isr_timestamp());
+            }
+            let steps: Vec<u32> = stamps.windows(2).map(|w| w[1] - w[0]).collect();
+            if steps.iter().any(|&step| step != 3) || stamps[0] - isr < 3 {
+                println!("timestamps {:?} after {}, 3 ticks apart", stamps, isr);
+                failures += 1;
+            }
+
+            if FreeRTOS::is_inside_interrupt() {
+                println!("is_inside_interrupt in a task");
+                failures += 1;
+            }
+
+            println!("{} failures", failures);
+            unsafe { _exit(failures) }
+        })
+        .unwrap();
+    });
+}
@@ -0,0 +1,149 @@
+//! Ends interrupt contexts with `InterruptContext::into_woken` and `yield_now`, and checks
+//! that:
+//!
+//! * `higher_priority_task_woken` is false until a send wakes a higher priority task,
+//! * `into_woken` returns true then, and doesn't yield: the woken task only runs when the
+//!   caller yields itself, once,
+//! * `yield_now` yields once, right away, and dropping a context still yields once,
+//! * neither yields when no task was woken.
+//!
+//! The POSIX port has no interrupts, so a task plays the interrupt handler. The yields
+//! are counted by a task of the same priority, which runs once for each yield and
+//! yields back.
+//!
+//! The process exits with the number of failed checks.
+//!
+//!     cargo run --example isr_woken --target x86_64-unknown-linux-gnu
+use freertos_rust::*;
+use std::sync::atomic::{AtomicU32, Ordering};
+
+#[global_allocator]
+static GLOBAL: FreeRtosAllocator = FreeRtosAllocator;
+
+static RECEIVED: AtomicU32 = AtomicU32::new(0);
+static PEER_RUNS: AtomicU32 = AtomicU32::new(0);
+
+extern "C" {
+    // `std::process::exit` runs the exit handlers of the POSIX port, which cancel the
+    // threads the tasks run on while they are in Rust code.
+    fn _exit(status: i32) -> !;
+}
+
+/// How a trial ends its context.
+#[derive(Debug, Copy, Clone)]
+enum End {
+    Drop,
+    IntoWoken,
+    YieldNow,
+}
+
+struct Trial {
+    woken_before: bool,
+    woken_after: bool,
+    /// Whether the receiver ran before the context ended, and right after.
+    received_early: bool,
+    received_at_end: bool,
+    yields: u32,
+}
+
+fn trial(os: &FreeRTOS, handle: &QueueISRHandle<u32>, value: u32, wake: bool, end: End) -> Trial {
+    // Right after a tick, so no time slice ends during the trial.
+    os.delay(Duration::ticks(1));
+    let peer_runs = PEER_RUNS.load(Ordering::SeqCst);
+
+    let mut context = InterruptContext::new(unsafe { KernelIsr::claim() });
+    let woken_before = context.higher_priority_task_woken();
+    if wake {
+        handle.send(&mut context, value).unwrap();
+    }
+    let woken_after = context.higher_priority_task_woken();
+    let received_early = RECEIVED.load(Ordering::SeqCst) == value;
+    let mut received_at_end = false;
+    match end {
+        End::Drop => drop(context),
+        End::YieldNow => context.yield_now(),
+        End::IntoWoken => {
+            let woken = context.into_woken();
+            received_at_end = RECEIVED.load(Ordering::SeqCst) == value;
+            // What the C side's portYIELD_FROM_ISR does with it.
+            if woken {
+                unsafe { freertos_rs_isr_yield() };
+            }
+        }
+    }
+    if !matches!(end, End::IntoWoken) {
+        received_at_end = RECEIVED.load(Ordering::SeqCst) == value;
+    }
+
+    Trial {
+        woken_before,
+        woken_after,
+        received_early,
+        received_at_end,
+        yields: PEER_RUNS.load(Ordering::SeqCst) - peer_runs,
+    }
+}
+
+fn main() {
+    FreeRTOS::start_scheduler(|os| {
+        os.new_task("isr", 512, TaskPriority(2), move |_, os| {
+            let mut failures = 0;
+
+            let queue = std::sync::Arc::new(os.new_queue::<u32>(1).unwrap());
+            let q = queue.clone();
+            os.new_task("receiver", 256, TaskPriority(3), move |_, _| loop {
+                let value = q.receive(Duration::infinite()).unwrap();
+                RECEIVED.store(value, Ordering::SeqCst);
+            })
+            .unwrap();
+            os.new_task("peer", 256, TaskPriority(2), move |_, _| loop {
+                PEER_RUNS.fetch_add(1, Ordering::SeqCst);
+                unsafe { freertos_rs_isr_yield() };
+            })
+            .unwrap();
+            os.delay(Duration::ms(5));
+            let handle = unsafe { queue.new_isr_safe_handle() };
+
+            let mut value = 0;
+            for &end in &[End::Drop, End::IntoWoken, End::YieldNow] {
+                value += 1;
+                let t = trial(&os, &handle, value, true, end);
+                // A context ended with `into_woken` doesn't yield, the yield after it does.
+                let received_at_end = !matches!(end, End::IntoWoken);
+                if t.woken_before
+                    || !t.woken_after
+                    || t.received_early
+                    || t.received_at_end != received_at_end
+                    || RECEIVED.load(Ordering::SeqCst) != value
+                    || t.yields != 1
+                {
+                    println!(
+                        "{:?} after a wake: woken {} before the send, {} after, received {} early, {} at the end, {} yields",
+                        end, t.woken_before, t.woken_after, t.received_early, t.received_at_end, t.yields
+                    );
+                    failures += 1;
+                }
+
+                value += 1;
+                let t = trial(&os, &handle, value, false, end);
+                if t.woken_before || t.woken_after || t.yields != 0 {
+                    println!(
+                        "{:?} without a wake: woken {} before, {} after, {} yields",
+                        end, t.woken_before, t.woken_after, t.yields
+                    );
+                    failures += 1;
+                }
+            }
+
+            let context = InterruptContext::new(ZeroLatencyIsr::new());
+            if context.higher_priority_task_woken() || context.into_woken() {
+                println!("a zero latency context reports a woken task");
+                failures += 1;
+            }
+
+            println!("{} failures", failures);
+            unsafe { _exit(failures) }
+        })
+        .unwrap();
+    });
+}
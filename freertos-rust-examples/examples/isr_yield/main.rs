@@ -0,0 +1,122 @@
+//! Sends to queues from an interrupt context, and checks that a higher priority task
+//! waiting for the queue runs as soon as the `InterruptContext` is dropped, instead of
+//! at the next tick:
+//!
+//! * with `QueueISRHandle::send`, for every one of many sends,
+//! * with `TaskISRHandle::notify`, which reports the woken task the same way,
+//! * not while the context is still alive, and not when the woken task has a lower
+//!   priority.
+//!
+//! The POSIX port has no interrupts, so a task of middle priority plays the interrupt
+//! handler.
+//!
+//! The process exits with the number of failed checks.
+//!
+//!     cargo run --example isr_yield --target x86_64-unknown-linux-gnu
+use freertos_rust::*;
+use std::sync::atomic::{AtomicU32, Ordering};
+use std::sync::Arc;
+
+#[global_allocator]
+static GLOBAL: FreeRtosAllocator = FreeRtosAllocator;
+
+const SENDS: u32 = 100;
+
+static RECEIVED: AtomicU32 = AtomicU32::new(0);
+static NOTIFIED: AtomicU32 = AtomicU32::new(0);
+static LOW_RECEIVED: AtomicU32 = AtomicU32::new(0);
+
+extern "C" {
+    // `std::process::exit` runs the exit handlers of the POSIX port, which cancel the
+    // threads the tasks run on while they are in Rust code.
+    fn _exit(status: i32) -> !;
+}
+
+fn main() {
+    FreeRTOS::start_scheduler(|os| {
+        os.new_task("isr", 512, TaskPriority(2), move |_, os| {
+            let mut failures = 0;
+
+            let queue = Arc::new(os.new_queue::<u32>(1).unwrap());
+            let q = queue.clone();
+            os.new_task("receiver", 256, TaskPriority(3), move |_, _| loop {
+                let value = q.receive(Duration::infinite()).unwrap();
+                RECEIVED.store(value, Ordering::Relaxed);
+            })
+            .unwrap();
+            let notified = os
+                .new_task("notified", 256, TaskPriority(3), move |task, _| loop {
+                    let value = task
+                        .wait_for_notification(0, 0, Duration::infinite())
+                        .unwrap();
+                    NOTIFIED.store(value, Ordering::Relaxed);
+                })
+                .unwrap();
+            let low_queue = Arc::new(os.new_queue::<u32>(1).unwrap());
+            let q = low_queue.clone();
+            os.new_task("low", 256, TaskPriority(1), move |_, _| loop {
+                let value = q.receive(Duration::infinite()).unwrap();
+                LOW_RECEIVED.store(value, Ordering::Relaxed);
+            })
+            .unwrap();
+            os.delay(Duration::ms(5));
+
+            let handle = unsafe { queue.new_isr_safe_handle() };
+            let mut late = 0;
+            let mut early = 0;
+            for value in 1..=SENDS {
+                let mut context = InterruptContext::new(unsafe { KernelIsr::claim() });
+                // Fails with the queue still full if the receiver didn't run last time.
+                if handle.send(&mut context, value).is_err() {
+                    late += 1;
+                    continue;
+                }
+                if RECEIVED.load(Ordering::Relaxed) == value {
+                    early += 1;
+                }
+                drop(context);
+                if RECEIVED.load(Ordering::Relaxed) != value {
+                    late += 1;
+                }
+            }
+            if late != 0 || early != 0 {
+                println!(
+                    "of {} sends, {} weren't received right when the context ended, {} were before",
+                    SENDS, late, early
+                );
+                failures += 1;
+            }
+
+            let task_handle = notified.new_isr_safe_handle();
+            {
+                let mut context = InterruptContext::new(unsafe { KernelIsr::claim() });
+                let _ = task_handle.notify(&mut context, TaskNotification::SetValue(42));
+            }
+            if NOTIFIED.load(Ordering::Relaxed) != 42 {
+                println!("notified task didn't run right after the context");
+                failures += 1;
+            }
+
+            // A lower priority task waits for this one.
+            let low_handle = unsafe { low_queue.new_isr_safe_handle() };
+            {
+                let mut context = InterruptContext::new(unsafe { KernelIsr::claim() });
+                low_handle.send(&mut context, 7).unwrap();
+            }
+            let low_before_delay = LOW_RECEIVED.load(Ordering::Relaxed);
+            os.delay(Duration::ms(2));
+            if low_before_delay != 0 || LOW_RECEIVED.load(Ordering::Relaxed) != 7 {
+                println!(
+                    "lower priority task received {} before this task blocked, {} after",
+                    low_before_delay,
+                    LOW_RECEIVED.load(Ordering::Relaxed)
+                );
+                failures += 1;
+            }
+
+            println!("{} failures", failures);
+            unsafe { _exit(failures) }
+        })
+        .unwrap();
+    });
+}
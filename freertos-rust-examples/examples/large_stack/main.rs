@@ -0,0 +1,140 @@
+//! Spawns tasks with stacks of more than `u16::MAX` words, from a heap_5 region large
+//! enough for them, and checks that:
+//!
+//! * with `configSTACK_DEPTH_TYPE` as `uint32_t`, the crate and the kernel agree on
+//!   `FreeRtosStackDepthType`,
+//! * a task with such a stack runs, its whole stack comes out of the heap and its high
+//!   water mark is above `u16::MAX`, so the depth wasn't cut to 16 bits on the way,
+//! * `TaskBuilder::stack_size` takes such a depth too,
+//! * a depth that doesn't fit in `FreeRtosStackDepthType` fails with `StackTooLarge`,
+//!   handing the task closure back.
+//!
+//! The process exits with the number of failed checks.
+//!
+//!     cargo run --example large_stack --features heap_5,stack_depth_u32 --target x86_64-unknown-linux-gnu
+use freertos_rust::*;
+use std::sync::atomic::{AtomicU32, Ordering};
+
+#[global_allocator]
+static GLOBAL: FreeRtosAllocator = FreeRtosAllocator;
+
+/// In words, above `u16::MAX`.
+const LARGE_STACK: usize = 70_000;
+/// The id of `configSTACK_DEPTH_TYPE` for `freertos_rs_sizeof`.
+const STACK_DEPTH_TYPE_ID: u8 = 34;
+const REGION_SIZE: usize = 2 * 1024 * 1024;
+
+#[repr(align(16))]
+struct Region([u8; REGION_SIZE]);
+
+static mut REGION: Region = Region([0; REGION_SIZE]);
+
+/// The number of large stack tasks that ran.
+static RAN: AtomicU32 = AtomicU32::new(0);
+
+/// The standard library allocates before `main`, so the region is defined by a
+/// constructor, like the startup code of a firmware would.
+#[used]
+#[link_section = ".init_array"]
+static DEFINE_REGION: extern "C" fn() = define_region;
+
+extern "C" fn define_region() {
+    let region = HeapRegion {
+        start: unsafe { std::ptr::addr_of_mut!(REGION.0) as *mut u8 },
+        size: REGION_SIZE,
+    };
+    unsafe { FreeRtosAllocator::define_heap_regions(&[region]) };
+}
+
+extern "C" {
+    // `std::process::exit` runs the exit handlers of the POSIX port, which cancel the
+    // threads the tasks run on while they are in Rust code.
+    fn _exit(status: i32) -> !;
+}
+
+fn large(_: &TaskSelfHandle, os: FreeRTOS) -> ! {
+    RAN.fetch_add(1, Ordering::SeqCst);
+    loop {
+        os.delay(Duration::infinite());
+    }
+}
+
+fn main() {
+    FreeRTOS::start_scheduler(|os| {
+        os.new_task("checks", 512, TaskPriority(2), move |_, os| {
+            let mut failures = 0;
+
+            // Not all of `shim_sanity_check`: the Linux port's `BaseType_t` is 64 bits.
+            let depth_size = unsafe { freertos_rs_sizeof(STACK_DEPTH_TYPE_ID) } as usize;
+            if depth_size != std::mem::size_of::<FreeRtosStackDepthType>() {
+                println!("configSTACK_DEPTH_TYPE has {} bytes", depth_size);
+                failures += 1;
+            }
+
+            let stack_bytes = LARGE_STACK * unsafe { freertos_rs_stack_type_size() } as usize;
+            let free_before = unsafe { freertos_rs_xPortGetFreeHeapSize() };
+            let task = os.new_task("large", LARGE_STACK, TaskPriority(1), large);
+            let free_after = unsafe { freertos_rs_xPortGetFreeHeapSize() };
+            os.delay(Duration::ms(5));
+            match task {
+                Ok(task) => {
+                    let high_water_mark = task.get_stack_high_water_mark();
+                    if RAN.load(Ordering::SeqCst) != 1
+                        || free_before - free_after < stack_bytes
+                        || high_water_mark <= u16::MAX as u32
+                    {
+                        println!(
+                            "large stack task: ran {}, {} bytes of heap for a {} byte stack, high water mark {}",
+                            RAN.load(Ordering::SeqCst),
+                            free_before - free_after,
+                            stack_bytes,
+                            high_water_mark
+                        );
+                        failures += 1;
+                    }
+                }
+                Err(e) => {
+                    println!("large stack task: {:?}", e);
+                    failures += 1;
+                }
+            }
+
+            let mut builder = os.new_task_builder();
+            builder.name("built").stack_size(LARGE_STACK);
+            let built = builder.start(large);
+            os.delay(Duration::ms(5));
+            if builder.get_stack_size() != LARGE_STACK
+                || built.is_err()
+                || RAN.load(Ordering::SeqCst) != 2
+            {
+                println!(
+                    "builder: stack size {}, {:?}, {} ran",
+                    builder.get_stack_size(),
+                    built.err(),
+                    RAN.load(Ordering::SeqCst)
+                );
+                failures += 1;
+            }
+
+            let captured = String::from("handed back");
+            let too_large = FreeRtosStackDepthType::MAX as usize + 1;
+            match TaskRemoteHandle::try_new(os, "too large", too_large, TaskPriority(1), move |this, os| {
+                let _ = &captured;
+                large(this, os)
+            }) {
+                Err(TaskSpawnError {
+                    error: FreeRtosError::StackTooLarge,
+                    func,
+                }) => drop(func),
+                result => {
+                    println!("stack of {} words: {:?}", too_large, result.map(|_| ()));
+                    failures += 1;
+                }
+            }
+
+            println!("{} failures", failures);
+            unsafe { _exit(failures) }
+        })
+        .unwrap();
+    });
+}
@@ -73,11 +73,14 @@ extern "C" {
 
 #define configUSE_PREEMPTION					1
 #define configUSE_PORT_OPTIMISED_TASK_SELECTION	0
-#define configUSE_IDLE_HOOK						0
-#define configUSE_TICK_HOOK						0
+#define configUSE_IDLE_HOOK						1
+#define configUSE_DAEMON_TASK_STARTUP_HOOK		1
+#define configUSE_TICK_HOOK						1
 #define configTICK_RATE_HZ						( 1000 ) 
 #define configMINIMAL_STACK_SIZE				( ( unsigned short ) 50 ) /* In this simulated case, the stack only has to hold one small structure as the real stack is part of the win32 thread. */
+#ifndef configTOTAL_HEAP_SIZE
 #define configTOTAL_HEAP_SIZE					( ( size_t ) ( 23 * 1024 ) )
+#endif
 #define configMAX_TASK_NAME_LEN					( 12 )
 #define configUSE_TRACE_FACILITY				1
 #define configUSE_16_BIT_TICKS					0
@@ -89,7 +92,9 @@ extern "C" {
 #define configUSE_MALLOC_FAILED_HOOK			1
 #define configUSE_APPLICATION_TASK_TAG			1
 #define configUSE_COUNTING_SEMAPHORES			1
+#ifndef configUSE_QUEUE_SETS
 #define configUSE_QUEUE_SETS					1
+#endif
 #define configUSE_TASK_NOTIFICATIONS			1
 
 /* Software timer related configuration options. */
@@ -109,6 +114,12 @@ extern void vPortFindTicksPerSecond( void );
 extern unsigned long ulPortGetTimerValue( void );
 #define portGET_RUN_TIME_COUNTER_VALUE() ulPortGetTimerValue()
 
+/* Tickless idle only steps the tick in the virtual time of freertos-rust's
+test_support, see freertos-rust/src/freertos/ports/linux/virtual_time.c. */
+#define configUSE_TICKLESS_IDLE					2
+extern void vPortVirtualTimeSuppressTicks( uint32_t xExpectedIdleTime );
+#define portSUPPRESS_TICKS_AND_SLEEP( xExpectedIdleTime ) vPortVirtualTimeSuppressTicks( xExpectedIdleTime )
+
 /* Co-routine related configuration options. */
 #define configUSE_CO_ROUTINES 					1
 #define configMAX_CO_ROUTINE_PRIORITIES			( 2 )
@@ -137,6 +148,7 @@ functions anyway. */
 #define INCLUDE_eTaskGetState					1
 #define INCLUDE_xSemaphoreGetMutexHolder		1
 #define INCLUDE_xTimerPendFunctionCall			1
+#define INCLUDE_xTaskAbortDelay					1
 
 /* It is a good idea to define configASSERT() while developing.  configASSERT()
 uses the same semantics as the standard C assert() macro. */
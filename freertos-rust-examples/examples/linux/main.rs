@@ -1,9 +1,11 @@
 use freertos_rust::*;
-use std::sync::Arc;
 
 #[global_allocator]
 static GLOBAL: FreeRtosAllocator = FreeRtosAllocator;
 
+/// Shared by both tasks, created in the setup closure.
+static VALUE: StaticFreeRtosCell<Mutex<u32>> = StaticFreeRtosCell::new();
+
 fn main() {
     let x = Box::new(15);
     println!("Boxed int '{}' (allocator test)", x);
@@ -13,48 +15,44 @@ fn main() {
     }
 
     println!("Starting scheduler");
-    FreeRTOS::start_scheduler(|os| {
+    let error = FreeRTOS::try_start_scheduler(|os| {
         //println!("Calling assert ...");
         //FreeRTOS::invoke_assert();
 
-        let value = Arc::new(os.new_mutex(0).unwrap());
-
-        {
-            let value = value.clone();
+        VALUE.init(os, |os| os.new_mutex(0))?;
 
-            println!("Starting FreeRTOS app ...");
-            os.new_task("A", 128, TaskPriority(2), move |_self_handle, os| loop {
-                {
-                    let mut value = value.lock(Duration::infinite()).unwrap();
-                    *value += 1;
-                    println!("A: {}", *value);
-                }
-                os.delay(Duration::ms(1000));
-            })
-            .unwrap();
-        }
+        println!("Starting FreeRTOS app ...");
+        os.new_task("A", 128, TaskPriority(2), move |_self_handle, os| loop {
+            {
+                let mut value = VALUE.lock(Duration::infinite()).unwrap();
+                *value += 1;
+                println!("A: {}", *value);
+            }
+            os.delay(Duration::ms(1000));
+        })?;
 
         os.new_task("B", 128, TaskPriority(3), move |_self_handle, os| loop {
             // Error shows up on this line "TaskSelfHandle is not sync"
 
             {
-                let mut value = value.lock(Duration::infinite()).unwrap();
+                let mut value = VALUE.lock(Duration::infinite()).unwrap();
                 *value += 1;
                 println!("B: {}", *value);
             }
             os.delay(Duration::ms(1000));
-        })
-        .unwrap();
+        })?;
 
         println!("Task registered");
         //let free = freertos_rs_xPortGetFreeHeapSize();
         // println!("Free Memory: {}!", free);
+        Ok(())
     });
+    println!("Scheduler failed to start: {}", error);
 }
 
 #[test]
 fn many_boxes() {
-    init_allocator();
+    allocator::init();
     println!("many_boxes... ");
     for i in 0..10 {
         // .. HEAP_SIZE
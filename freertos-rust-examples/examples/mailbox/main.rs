@@ -0,0 +1,139 @@
+//! Keeps the latest reading of a fast producer in a mailbox, a queue of one item that
+//! `Queue::overwrite` replaces, and checks that:
+//!
+//! * the producer never fails or waits, and the mailbox never holds more than one item,
+//! * a slow consumer only ever reads the latest reading,
+//! * `peek` leaves the reading in the mailbox for the next `receive`,
+//! * overwriting from an interrupt handle replaces the reading too,
+//! * queues of more than one item refuse to be overwritten.
+//!
+//! The process exits with the number of failed checks.
+//!
+//!     cargo run --example mailbox --target x86_64-unknown-linux-gnu
+use freertos_rust::*;
+use std::sync::atomic::{AtomicBool, AtomicU32, Ordering};
+use std::sync::Arc;
+
+#[global_allocator]
+static GLOBAL: FreeRtosAllocator = FreeRtosAllocator;
+
+const READS: u32 = 10;
+
+/// The last reading the producer wrote.
+static LATEST: AtomicU32 = AtomicU32::new(0);
+static PRODUCER_FAILURES: AtomicU32 = AtomicU32::new(0);
+static OVERFULL: AtomicU32 = AtomicU32::new(0);
+static STOP: AtomicBool = AtomicBool::new(false);
+
+extern "C" {
+    // `std::process::exit` runs the exit handlers of the POSIX port, which cancel the
+    // threads the tasks run on while they are in Rust code.
+    fn _exit(status: i32) -> !;
+}
+
+fn main() {
+    FreeRTOS::start_scheduler(|os| {
+        os.new_task("checks", 512, TaskPriority(2), move |_, os| {
+            let mut failures = 0;
+
+            let mailbox = Arc::new(os.new_mailbox::<u32>().unwrap());
+            let outbox = mailbox.clone();
+            // At the consumer's priority, so it runs whenever the consumer waits.
+            os.new_task("producer", 256, TaskPriority(2), move |_, os| {
+                let mut reading = 0;
+                while !STOP.load(Ordering::Relaxed) {
+                    reading += 1;
+                    if outbox.overwrite(reading).is_err() {
+                        PRODUCER_FAILURES.fetch_add(1, Ordering::Relaxed);
+                    }
+                    if outbox.len() > 1 {
+                        OVERFULL.fetch_add(1, Ordering::Relaxed);
+                    }
+                    LATEST.store(reading, Ordering::Relaxed);
+                }
+                loop {
+                    os.delay(Duration::ms(10_000));
+                }
+            })
+            .unwrap();
+
+            let mut previous = 0;
+            let mut stale = 0;
+            for _ in 0..READS {
+                os.delay(Duration::ms(10));
+                // Keep the producer from writing between the two reads.
+                let (peeked, received, latest) = {
+                    let _region = CriticalRegion::enter();
+                    (
+                        mailbox.peek(Duration::zero()),
+                        mailbox.receive(Duration::zero()),
+                        LATEST.load(Ordering::Relaxed),
+                    )
+                };
+                match (peeked, received) {
+                    (Ok(peeked), Ok(received)) if peeked == received => {
+                        if received != latest && received != latest + 1 {
+                            stale += 1;
+                        }
+                        if received < previous + 100 {
+                            println!("only {} readings since the last read", received - previous);
+                            failures += 1;
+                        }
+                        previous = received;
+                    }
+                    r => {
+                        println!("peek and receive: {:?}", r);
+                        failures += 1;
+                    }
+                }
+            }
+            STOP.store(true, Ordering::Relaxed);
+            os.delay(Duration::ms(2));
+            if stale != 0 {
+                println!("{} of {} reads weren't the latest reading", stale, READS);
+                failures += 1;
+            }
+            if PRODUCER_FAILURES.load(Ordering::Relaxed) + OVERFULL.load(Ordering::Relaxed) != 0 {
+                println!(
+                    "{} failed overwrites, {} times more than one item",
+                    PRODUCER_FAILURES.load(Ordering::Relaxed),
+                    OVERFULL.load(Ordering::Relaxed)
+                );
+                failures += 1;
+            }
+
+            // From an interrupt.
+            let _ = mailbox.receive(Duration::zero());
+            let handle = unsafe { mailbox.new_isr_safe_handle() };
+            {
+                let mut context = InterruptContext::new(unsafe { KernelIsr::claim() });
+                for reading in 1000..1010 {
+                    if handle.overwrite(&mut context, reading).is_err() {
+                        failures += 1;
+                    }
+                }
+            }
+            match (mailbox.len(), mailbox.receive(Duration::zero())) {
+                (1, Ok(1009)) => {}
+                r => {
+                    println!("after overwriting from an interrupt: {:?}", r);
+                    failures += 1;
+                }
+            }
+
+            // Only for mailboxes.
+            let queue = os.new_queue::<u32>(2).unwrap();
+            match queue.overwrite(1) {
+                Err(FreeRtosError::InvalidQueueSize) if queue.is_empty() => {}
+                r => {
+                    println!("overwrite of a queue of two items: {:?}", r);
+                    failures += 1;
+                }
+            }
+
+            println!("{} failures", failures);
+            unsafe { _exit(failures) }
+        })
+        .unwrap();
+    });
+}
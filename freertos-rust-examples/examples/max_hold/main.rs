@@ -0,0 +1,198 @@
+//! Puts a maximum hold time on mutexes with `Mutex::with_max_hold` and checks that:
+//!
+//! * holds within the limit never report a violation,
+//! * a task holding the mutex too long is reported, with its handle,
+//! * `HoldPolicy::AbortDelayAndNotify` wakes a holder stuck in a long delay, which then
+//!   unlocks the mutex,
+//! * an uncontended lock and unlock costs at most 25% more than the bare kernel calls
+//!   without a maximum hold time, and than the bare kernel calls plus a timer reset and
+//!   stop with one. This is only checked in release builds.
+//!
+//! The process exits with the number of failed checks.
+//!
+//!     cargo run --release --example max_hold --target x86_64-unknown-linux-gnu
+use freertos_rust::*;
+use std::sync::atomic::{AtomicBool, AtomicU32, AtomicUsize, Ordering};
+use std::sync::Arc;
+use std::time::Instant;
+
+#[global_allocator]
+static GLOBAL: FreeRtosAllocator = FreeRtosAllocator;
+
+const MAX_HOLD_MS: u32 = 20;
+const ITERATIONS: u32 = 2000;
+const BATCHES: u32 = 5;
+
+static VIOLATIONS: AtomicU32 = AtomicU32::new(0);
+static HOLDER: AtomicUsize = AtomicUsize::new(0);
+static WOKEN: AtomicBool = AtomicBool::new(false);
+/// Set by the holder stuck in a delay once the delay ended.
+static DELAY_ENDED: AtomicBool = AtomicBool::new(false);
+
+extern "C" {
+    // `std::process::exit` runs the exit handlers of the POSIX port, which cancel the
+    // threads the tasks run on while they are in Rust code.
+    fn _exit(status: i32) -> !;
+}
+
+fn record(violation: &HoldViolation) {
+    VIOLATIONS.fetch_add(1, Ordering::Relaxed);
+    HOLDER.store(violation.holder.raw_handle() as usize, Ordering::Relaxed);
+    WOKEN.store(violation.woken, Ordering::Relaxed);
+}
+
+fn limited(os: FreeRTOS, policy: HoldPolicy) -> Arc<Mutex<u32>> {
+    Arc::new(
+        Mutex::new(os, 0)
+            .unwrap()
+            .with_max_hold(Duration::ms(MAX_HOLD_MS), policy, record)
+            .unwrap(),
+    )
+}
+
+/// The fastest of a few batches of `ITERATIONS` calls of `f`, in nanoseconds per call.
+fn cost<F: FnMut()>(mut f: F) -> u128 {
+    (0..BATCHES)
+        .map(|_| {
+            let start = Instant::now();
+            for _ in 0..ITERATIONS {
+                f();
+            }
+            start.elapsed().as_nanos() / ITERATIONS as u128
+        })
+        .min()
+        .unwrap()
+}
+
+fn main() {
+    FreeRTOS::start_scheduler(|os| {
+        os.new_task("checks", 1024, TaskPriority(2), move |_, os| {
+            let mut failures = 0;
+
+            // Short holds, some of them close to the limit.
+            let mutex = limited(os, HoldPolicy::NotifyOnly);
+            for i in 0..100 {
+                let mut value = mutex.lock(Duration::infinite()).unwrap();
+                *value += 1;
+                if i % 20 == 0 {
+                    os.delay(Duration::ms(MAX_HOLD_MS / 2));
+                }
+            }
+            os.delay(Duration::ms(2 * MAX_HOLD_MS));
+            if VIOLATIONS.load(Ordering::Relaxed) != 0 {
+                println!("{} violations for short holds", VIOLATIONS.load(Ordering::Relaxed));
+                failures += 1;
+            }
+
+            // A holder over the limit is reported and left alone.
+            let m = mutex.clone();
+            let holder = os
+                .new_task("holder", 256, TaskPriority(1), move |_, os| {
+                    let _value = m.lock(Duration::infinite()).unwrap();
+                    os.delay(Duration::ms(3 * MAX_HOLD_MS));
+                    drop(_value);
+                    loop {
+                        os.delay(Duration::ms(10_000));
+                    }
+                })
+                .unwrap();
+            os.delay(Duration::ms(2 * MAX_HOLD_MS));
+            if VIOLATIONS.load(Ordering::Relaxed) != 1
+                || HOLDER.load(Ordering::Relaxed) != holder.raw_handle() as usize
+                || WOKEN.load(Ordering::Relaxed)
+            {
+                println!(
+                    "{} violations, holder {:?}, expected {:?}, woken {}",
+                    VIOLATIONS.load(Ordering::Relaxed),
+                    HOLDER.load(Ordering::Relaxed) as *const (),
+                    holder.raw_handle(),
+                    WOKEN.load(Ordering::Relaxed)
+                );
+                failures += 1;
+            }
+            if mutex.lock(Duration::ms(MAX_HOLD_MS / 2)).is_ok() {
+                println!("mutex released before the holder was done");
+                failures += 1;
+            }
+            if mutex.lock(Duration::ms(5 * MAX_HOLD_MS)).is_err() {
+                println!("holder never released the mutex");
+                failures += 1;
+            }
+
+            // A holder stuck in a delay is woken up and releases the mutex.
+            let stuck = limited(os, HoldPolicy::AbortDelayAndNotify);
+            let m = stuck.clone();
+            let sleeper = os
+                .new_task("sleeper", 256, TaskPriority(1), move |_, os| {
+                    let _value = m.lock(Duration::infinite()).unwrap();
+                    os.delay(Duration::ms(60_000));
+                    DELAY_ENDED.store(true, Ordering::Relaxed);
+                    drop(_value);
+                    loop {
+                        os.delay(Duration::ms(10_000));
+                    }
+                })
+                .unwrap();
+            os.delay(Duration::ms(2));
+            let start = os.get_tick_count();
+            match stuck.lock(Duration::ms(10 * MAX_HOLD_MS)) {
+                Ok(_) if DELAY_ENDED.load(Ordering::Relaxed) => {}
+                r => {
+                    println!("lock of the stuck mutex: {:?}", r.map(|_| ()));
+                    failures += 1;
+                }
+            }
+            let waited = os.get_tick_count() - start;
+            if VIOLATIONS.load(Ordering::Relaxed) != 2
+                || HOLDER.load(Ordering::Relaxed) != sleeper.raw_handle() as usize
+                || !WOKEN.load(Ordering::Relaxed)
+                || waited > Duration::ms(2 * MAX_HOLD_MS).to_ticks()
+            {
+                println!(
+                    "{} violations, woken {}, lock after {} ticks",
+                    VIOLATIONS.load(Ordering::Relaxed),
+                    WOKEN.load(Ordering::Relaxed),
+                    waited
+                );
+                failures += 1;
+            }
+
+            // Overhead of uncontended locks.
+            let raw = unsafe { freertos_rs_create_semaphore() };
+            let bare = cost(|| unsafe {
+                freertos_rs_take_semaphore(raw, 0);
+                freertos_rs_give_semaphore(raw);
+            });
+            let timer = os
+                .new_timer(Duration::ms(MAX_HOLD_MS))
+                .one_shot()
+                .create(|_| {})
+                .unwrap();
+            let timer_pair = cost(|| {
+                let _ = timer.reset(Duration::zero());
+                let _ = timer.stop(Duration::zero());
+            });
+            let plain = Mutex::new(os, 0u32).unwrap();
+            let unlimited = cost(|| drop(plain.lock(Duration::zero())));
+            let with_limit = cost(|| drop(mutex.lock(Duration::zero())));
+            println!(
+                "ns per lock and unlock: {} bare, {} without a limit, {} with one, {} per timer reset and stop",
+                bare, unlimited, with_limit, timer_pair
+            );
+            let over_budget =
+                unlimited * 4 > bare * 5 || with_limit * 4 > (bare + timer_pair) * 5;
+            if over_budget && !cfg!(debug_assertions) {
+                println!("uncontended locks over budget");
+                failures += 1;
+            }
+            if VIOLATIONS.load(Ordering::Relaxed) != 2 {
+                println!("violations while measuring");
+                failures += 1;
+            }
+
+            println!("{} failures", failures);
+            unsafe { _exit(failures) }
+        })
+        .unwrap();
+    });
+}
@@ -0,0 +1,194 @@
+//! Runs an unprivileged task with `RestrictedTaskBuilder` on the ARM_CM3_MPU port, and
+//! checks that:
+//!
+//! * the task writes the buffer it was granted when it was created,
+//! * it writes another buffer once `allocate_mpu_regions` granted that one instead,
+//! * granting more regions than the port configures fails with `TooManyRegions`.
+//!
+//! A write outside its regions would take the task down with a MemManage fault, so
+//! the task doesn't try one. The results are printed with semihosting, and QEMU exits
+//! with a failure status if a check failed.
+//!
+//!     cargo build --example mpu_restricted --features mpu --target thumbv7m-none-eabi
+//!     qemu-system-arm -machine lm3s6965evb -nographic -semihosting \
+//!         -kernel target/thumbv7m-none-eabi/debug/examples/mpu_restricted
+#![no_std]
+#![no_main]
+
+extern crate alloc;
+
+use alloc::format;
+use core::cell::UnsafeCell;
+use core::panic::PanicInfo;
+use core::ptr;
+use cortex_m::asm;
+use cortex_m_rt::{entry, exception, pre_init, ExceptionFrame};
+use freertos_rust::*;
+
+#[global_allocator]
+static GLOBAL: FreeRtosAllocator = FreeRtosAllocator;
+
+/// `portMPU_REGION_READ_WRITE | portMPU_REGION_EXECUTE_NEVER` of the port.
+const READ_WRITE: u32 = (0x03 << 24) | (0x01 << 28);
+
+/// What the restricted task writes, in a region of its own. The smallest ARMv7-M region
+/// is 32 bytes, aligned to its size.
+#[repr(C, align(32))]
+struct Buffer(UnsafeCell<[u32; 8]>);
+
+unsafe impl Sync for Buffer {}
+
+impl Buffer {
+    const fn new() -> Self {
+        Buffer(UnsafeCell::new([0; 8]))
+    }
+
+    fn write(&self, value: u32) {
+        unsafe { ptr::write_volatile(self.0.get() as *mut u32, value) }
+    }
+
+    fn read(&self) -> u32 {
+        unsafe { ptr::read_volatile(self.0.get() as *const u32) }
+    }
+
+    fn region(&'static self) -> MemoryRegion {
+        MemoryRegion {
+            base: self as *const Buffer as FreeRtosMutVoidPtr,
+            length: core::mem::size_of::<Buffer>() as u32,
+            parameters: READ_WRITE,
+        }
+    }
+}
+
+/// The stack of the restricted task, 2 KiB aligned to its size.
+#[repr(C, align(2048))]
+struct Stack([usize; 512]);
+
+static BUFFER_A: Buffer = Buffer::new();
+static BUFFER_B: Buffer = Buffer::new();
+static mut STACK: Stack = Stack([0; 512]);
+
+/// The context of the restricted task. Without interior mutability it is placed in the
+/// flash, which the task can read.
+struct Writer {
+    first: &'static Buffer,
+    second: &'static Buffer,
+}
+
+static WRITER: Writer = Writer {
+    first: &BUFFER_A,
+    second: &BUFFER_B,
+};
+
+impl RestrictedTaskEntry for Writer {
+    fn run(&'static self, this: &TaskSelfHandle, os: FreeRTOS) -> ! {
+        self.first.write(1);
+        // Woken once the second buffer is granted.
+        this.take_notification(true, Duration::infinite());
+        self.second.write(2);
+        loop {
+            os.delay(Duration::infinite());
+        }
+    }
+}
+
+/// Prints `line` to the debugger's console with the `SYS_WRITE0` semihosting call.
+fn print(line: &str) {
+    let line = format!("{}\n\0", line);
+    unsafe { asm::semihosting_syscall(0x04, line.as_ptr() as u32) };
+}
+
+/// Ends the session with the `SYS_EXIT` semihosting call, which QEMU turns into its exit
+/// status.
+fn exit(success: bool) -> ! {
+    // ADP_Stopped_ApplicationExit and ADP_Stopped_RunTimeErrorUnknown.
+    let reason = if success { 0x20026 } else { 0x20023 };
+    unsafe { asm::semihosting_syscall(0x18, reason) };
+    loop {
+        asm::bkpt();
+    }
+}
+
+fn check(failures: &mut u8, name: &str, ok: bool) {
+    if ok {
+        print(&format!("[ok] {}", name));
+    } else {
+        print(&format!("[failed] {}", name));
+        *failures += 1;
+    }
+}
+
+fn checks(os: FreeRTOS) -> u8 {
+    let mut failures = 0;
+
+    let stack = unsafe { &mut (*ptr::addr_of_mut!(STACK)).0[..] };
+    let writer = RestrictedTaskBuilder::new(os, stack)
+        .name("writer")
+        .priority(TaskPriority(3))
+        .region(BUFFER_A.region())
+        .start(&WRITER)
+        .unwrap();
+    os.delay(Duration::ms(10));
+    check(
+        &mut failures,
+        "granted buffer written",
+        BUFFER_A.read() == 1,
+    );
+
+    writer.allocate_mpu_regions(&[BUFFER_B.region()]).unwrap();
+    writer.notify(TaskNotification::NoAction);
+    os.delay(Duration::ms(10));
+    check(
+        &mut failures,
+        "regranted buffer written",
+        BUFFER_B.read() == 2,
+    );
+
+    let too_many = alloc::vec![BUFFER_A.region(); max_mpu_regions() + 1];
+    check(
+        &mut failures,
+        "too many regions rejected",
+        writer.allocate_mpu_regions(&too_many) == Err(FreeRtosError::TooManyRegions),
+    );
+
+    failures
+}
+
+#[entry]
+fn main() -> ! {
+    FreeRTOS::start_scheduler(|os| {
+        os.new_task("checks", 1024, TaskPriority(2), |_, os| {
+            let failures = checks(os);
+            print(&format!("{} failed", failures));
+            exit(failures == 0)
+        })
+        .unwrap();
+    })
+}
+
+extern "C" {
+    static mut __privileged_data_start__: u32;
+    static mut __privileged_data_end__: u32;
+}
+
+/// Zeroes the kernel data, which cortex-m-rt doesn't know of, see memory.x.
+#[pre_init]
+unsafe fn zero_privileged_data() {
+    let mut word = ptr::addr_of_mut!(__privileged_data_start__);
+    while word < ptr::addr_of_mut!(__privileged_data_end__) {
+        ptr::write_volatile(word, 0);
+        word = word.add(1);
+    }
+}
+
+#[exception]
+fn HardFault(ef: &ExceptionFrame) -> ! {
+    print(&format!("HardFault: {:?}", ef));
+    exit(false)
+}
+
+#[panic_handler]
+fn panic(info: &PanicInfo) -> ! {
+    print(&format!("{}", info));
+    exit(false)
+}
@@ -0,0 +1,40 @@
+/* LM3S6965, as emulated by QEMU's lm3s6965evb machine */
+MEMORY
+{
+  FLASH : ORIGIN = 0x00000000, LENGTH = 256K
+  RAM : ORIGIN = 0x20000000, LENGTH = 64K
+}
+
+/* The memory the ARM_CM3_MPU port grants tasks in prvSetupMPU. */
+__FLASH_segment_start__ = ORIGIN(FLASH);
+__FLASH_segment_end__ = ORIGIN(FLASH) + LENGTH(FLASH);
+__SRAM_segment_start__ = ORIGIN(RAM);
+__SRAM_segment_end__ = ORIGIN(RAM) + LENGTH(RAM);
+
+SECTIONS
+{
+  /* The kernel code, executable in privileged mode only. Its region starts at the
+     beginning of the flash, so it follows the vector table. */
+  privileged_functions :
+  {
+    *(privileged_functions)
+    . = ALIGN(4);
+    __privileged_functions_end__ = .;
+  } > FLASH
+}
+INSERT AFTER .vector_table;
+
+SECTIONS
+{
+  /* The kernel data, accessible in privileged mode only. Its region has to be aligned
+     to its size, so it goes first in the RAM. All of it starts zeroed, which the
+     pre_init function of the example does. */
+  privileged_data (NOLOAD) :
+  {
+    __privileged_data_start__ = .;
+    *(privileged_data)
+    . = ALIGN(4);
+    __privileged_data_end__ = .;
+  } > RAM
+}
+INSERT BEFORE .data;
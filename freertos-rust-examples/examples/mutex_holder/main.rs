@@ -0,0 +1,103 @@
+//! Asks mutexes which task holds them, with the `debug_sync` feature, and checks that:
+//!
+//! * `holder` is `None` for a mutex nobody holds, and the locking task while one does,
+//! * a recursive mutex locked twice reports its holder until it is unlocked as often,
+//! * a lock that times out on a held mutex fails with `MutexTimeoutHeldBy`, naming the
+//!   holder, and its message names it too,
+//! * once the holder unlocks, the mutex can be locked again and has no holder.
+//!
+//! The process exits with the number of failed checks.
+//!
+//!     cargo run --example mutex_holder --features debug_sync --target x86_64-unknown-linux-gnu
+use freertos_rust::*;
+use std::sync::Arc;
+
+#[global_allocator]
+static GLOBAL: FreeRtosAllocator = FreeRtosAllocator;
+
+extern "C" {
+    // `std::process::exit` runs the exit handlers of the POSIX port, which cancel the
+    // threads the tasks run on while they are in Rust code.
+    fn _exit(status: i32) -> !;
+}
+
+fn holder_name(holder: Option<TaskRemoteHandle>) -> Option<String> {
+    holder.map(|task| task.get_name().unwrap())
+}
+
+fn main() {
+    FreeRTOS::start_scheduler(|os| {
+        os.new_task("checks", 512, TaskPriority(2), move |this, os| {
+            let mut failures = 0;
+
+            let mutex = Arc::new(Mutex::new(os, 0u32).unwrap());
+            let release = Arc::new(BinarySemaphore::new(os).unwrap());
+            if mutex.holder().is_some() {
+                println!("unlocked mutex held by {:?}", holder_name(mutex.holder()));
+                failures += 1;
+            }
+
+            let (m, r) = (mutex.clone(), release.clone());
+            os.new_task("holder", 256, TaskPriority(3), move |_, os| {
+                let guard = m.lock(Duration::infinite()).unwrap();
+                r.take(Duration::infinite()).unwrap();
+                drop(guard);
+                loop {
+                    os.delay(Duration::infinite());
+                }
+            })
+            .unwrap();
+            os.delay(Duration::ms(2));
+
+            let held_by = holder_name(mutex.holder());
+            if held_by.as_deref() != Some("holder") {
+                println!("locked mutex held by {:?}", held_by);
+                failures += 1;
+            }
+
+            match mutex.lock(Duration::ms(5)) {
+                Err(e @ FreeRtosError::MutexTimeoutHeldBy(name))
+                    if name.as_str() == Ok("holder") && e.to_string().contains("holder") => {}
+                result => {
+                    println!("lock of a held mutex: {:?}", result.map(|_| ()));
+                    failures += 1;
+                }
+            }
+
+            release.give();
+            os.delay(Duration::ms(2));
+            let relocked = mutex.lock(Duration::zero()).map(|_| ());
+            if relocked.is_err() || mutex.holder().is_some() {
+                println!(
+                    "after the holder unlocked: {:?}, held by {:?}",
+                    relocked,
+                    holder_name(mutex.holder())
+                );
+                failures += 1;
+            }
+
+            let recursive = RecursiveMutex::new(os, ()).unwrap();
+            let outer = recursive.lock(Duration::zero()).unwrap();
+            let inner = recursive.lock(Duration::zero()).unwrap();
+            let twice = recursive.holder().map(|t| t.raw_handle());
+            drop(inner);
+            let once = recursive.holder().map(|t| t.raw_handle());
+            drop(outer);
+            let me = Some(this.raw_handle());
+            if twice != me || once != me || recursive.holder().is_some() {
+                println!(
+                    "recursive mutex held by {:?} twice, {:?} once, {:?} after, not {:?}",
+                    twice,
+                    once,
+                    recursive.holder().map(|t| t.raw_handle()),
+                    me
+                );
+                failures += 1;
+            }
+
+            println!("{} failures", failures);
+            unsafe { _exit(failures) }
+        })
+        .unwrap();
+    });
+}
@@ -0,0 +1,172 @@
+//! Narrows mutex guards down to one field with `MutexGuard::map`, and checks that:
+//!
+//! * the mapped guard reads and writes the field it was mapped to, also mapped again,
+//! * the mutex stays locked while the mapped guard lives, and is unlocked when it is
+//!   dropped,
+//! * the mutex is given back exactly once per lock whether or not the guard was mapped,
+//!   by a recursive mutex locked twice that stays locked after one mapped guard is dropped,
+//! * a hold limit is still tracked through the mapped guard.
+//!
+//! Whether a mutex is locked is probed by a higher priority task.
+//!
+//! The process exits with the number of failed checks.
+//!
+//!     cargo run --example mutex_map --target x86_64-unknown-linux-gnu
+use freertos_rust::*;
+use std::sync::atomic::{AtomicU32, Ordering};
+use std::sync::Arc;
+
+#[global_allocator]
+static GLOBAL: FreeRtosAllocator = FreeRtosAllocator;
+
+#[derive(Debug, Default, Clone, PartialEq)]
+struct Network {
+    address: [u8; 4],
+    port: u16,
+}
+
+#[derive(Debug, Default, Clone, PartialEq)]
+struct Config {
+    name: [u8; 8],
+    network: Network,
+    retries: u32,
+}
+
+/// Bit 0: the probe could lock the mutex, bit 1: the recursive mutex.
+static PROBED: AtomicU32 = AtomicU32::new(0);
+static VIOLATIONS: AtomicU32 = AtomicU32::new(0);
+
+extern "C" {
+    // `std::process::exit` runs the exit handlers of the POSIX port, which cancel the
+    // threads the tasks run on while they are in Rust code.
+    fn _exit(status: i32) -> !;
+}
+
+/// Which of the two mutexes the probe task could lock, as (mutex, recursive).
+fn probe(prober: &TaskRemoteHandle) -> (bool, bool) {
+    PROBED.store(u32::MAX, Ordering::SeqCst);
+    prober.notify(TaskNotification::Increment);
+    let probed = PROBED.load(Ordering::SeqCst);
+    (probed & 1 != 0, probed & 2 != 0)
+}
+
+fn main() {
+    FreeRTOS::start_scheduler(|os| {
+        os.new_task("checks", 512, TaskPriority(2), move |_, os| {
+            let mut failures = 0;
+
+            let mutex = Arc::new(Mutex::new(os, Config::default()).unwrap());
+            let recursive = Arc::new(RecursiveMutex::new(os, Config::default()).unwrap());
+            let prober = {
+                let (mutex, recursive) = (mutex.clone(), recursive.clone());
+                os.new_task("probe", 256, TaskPriority(3), move |task, _| loop {
+                    task.take_notification(true, Duration::infinite());
+                    let mut probed = 0;
+                    if mutex.try_lock().is_some() {
+                        probed |= 1;
+                    }
+                    if recursive.try_lock().is_some() {
+                        probed |= 2;
+                    }
+                    PROBED.store(probed, Ordering::SeqCst);
+                })
+                .unwrap()
+            };
+            os.delay(Duration::ms(2));
+
+            if probe(&prober) != (true, true) {
+                println!("mutexes locked before the checks: {:?}", probe(&prober));
+                failures += 1;
+            }
+
+            {
+                let mut port = mutex.lock(Duration::zero()).unwrap().map(|c| &mut c.network.port);
+                *port = 8080;
+                if probe(&prober).0 {
+                    println!("mutex unlocked while a mapped guard lives");
+                    failures += 1;
+                }
+            }
+            if !probe(&prober).0 {
+                println!("mutex still locked after the mapped guard was dropped");
+                failures += 1;
+            }
+
+            {
+                let network = mutex.lock(Duration::zero()).unwrap().map(|c| &mut c.network);
+                let mut address = network.map(|n| &mut n.address);
+                address.copy_from_slice(&[10, 0, 0, 1]);
+            }
+            {
+                let mut retries = mutex.lock(Duration::zero()).unwrap().map(|c| &mut c.retries);
+                *retries += 3;
+            }
+            let expected = Config {
+                name: [0; 8],
+                network: Network {
+                    address: [10, 0, 0, 1],
+                    port: 8080,
+                },
+                retries: 3,
+            };
+            match mutex.try_lock() {
+                Some(config) if *config == expected => {}
+                config => {
+                    println!("config {:?}, expected {:?}", config.as_deref(), expected);
+                    failures += 1;
+                }
+            }
+
+            // Mapped and unmapped guards each give the recursive mutex back once.
+            for &map_outer in &[false, true] {
+                let outer = recursive.lock(Duration::zero()).unwrap();
+                let inner = recursive.lock(Duration::zero()).unwrap().map(|c| &mut c.retries);
+                drop(inner);
+                let after_inner = probe(&prober).1;
+                if map_outer {
+                    drop(outer.map(|c| &mut c.name));
+                } else {
+                    drop(outer);
+                }
+                let after_outer = probe(&prober).1;
+                if after_inner || !after_outer {
+                    println!(
+                        "recursive mutex, outer guard mapped {}: unlocked {} after the inner guard, {} after the outer",
+                        map_outer, after_inner, after_outer
+                    );
+                    failures += 1;
+                }
+            }
+
+            let limited = Mutex::new(os, Config::default())
+                .unwrap()
+                .with_max_hold(Duration::ms(20), HoldPolicy::NotifyOnly, |_| {
+                    VIOLATIONS.fetch_add(1, Ordering::SeqCst);
+                })
+                .unwrap();
+            {
+                let _name = limited.lock(Duration::zero()).unwrap().map(|c| &mut c.name);
+                os.delay(Duration::ms(2));
+            }
+            os.delay(Duration::ms(50));
+            let short_hold = VIOLATIONS.load(Ordering::SeqCst);
+            {
+                let _name = limited.lock(Duration::zero()).unwrap().map(|c| &mut c.name);
+                os.delay(Duration::ms(50));
+            }
+            os.delay(Duration::ms(50));
+            let long_hold = VIOLATIONS.load(Ordering::SeqCst) - short_hold;
+            if short_hold != 0 || long_hold != 1 {
+                println!(
+                    "{} hold violations for a short mapped hold, {} for a long one",
+                    short_hold, long_hold
+                );
+                failures += 1;
+            }
+
+            println!("{} failures", failures);
+            unsafe { _exit(failures) }
+        })
+        .unwrap();
+    });
+}
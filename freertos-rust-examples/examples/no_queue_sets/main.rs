@@ -0,0 +1,58 @@
+//! Builds the kernel with `configUSE_QUEUE_SETS` set to 0, and checks that:
+//!
+//! * queues and semaphores are created, used and dropped as usual, giving their memory
+//!   back, as they are never members of a set,
+//! * `QueueSet::new` fails with `OutOfMemory`.
+//!
+//! The process exits with the number of failed checks.
+//!
+//!     cargo run --example no_queue_sets --features no_queue_sets --target x86_64-unknown-linux-gnu
+use freertos_rust::*;
+
+#[global_allocator]
+static GLOBAL: FreeRtosAllocator = FreeRtosAllocator;
+
+extern "C" {
+    // `std::process::exit` runs the exit handlers of the POSIX port, which cancel the
+    // threads the tasks run on while they are in Rust code.
+    fn _exit(status: i32) -> !;
+}
+
+fn main() {
+    FreeRTOS::start_scheduler(|os| {
+        os.new_task("checks", 512, TaskPriority(2), move |_, os| {
+            let mut failures = 0;
+
+            let free_before = unsafe { freertos_rs_xPortGetFreeHeapSize() };
+            let queue = os.new_queue::<u32>(4).unwrap();
+            queue.send(7, Duration::zero()).unwrap();
+            let received = queue.receive(Duration::zero());
+            queue.send(8, Duration::zero()).unwrap();
+            drop(queue);
+            let semaphore = os.new_binary_semaphore().unwrap();
+            semaphore.give();
+            let taken = semaphore.take(Duration::zero());
+            drop(semaphore);
+            let free_after = unsafe { freertos_rs_xPortGetFreeHeapSize() };
+            if received != Ok(7) || taken.is_err() || free_after != free_before {
+                println!(
+                    "queue and semaphore: received {:?}, taken {:?}, {} bytes missing",
+                    received,
+                    taken,
+                    free_before - free_after
+                );
+                failures += 1;
+            }
+
+            let set = os.new_queue_set(4);
+            if set.as_ref().err() != Some(&FreeRtosError::OutOfMemory) {
+                println!("queue set: {:?}", set);
+                failures += 1;
+            }
+
+            println!("{} failures", failures);
+            unsafe { _exit(failures) }
+        })
+        .unwrap();
+    });
+}
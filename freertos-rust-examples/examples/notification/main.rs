@@ -0,0 +1,163 @@
+//! Signals tasks through `NotificationSemaphore` and `NotificationMailbox`, next to the
+//! same code on a `BinarySemaphore`, and checks that:
+//!
+//! * a worker woken by a `NotificationGiver` runs once per give, like one woken by a
+//!   binary semaphore, also when the gives come from an interrupt handle,
+//! * `take` times out with `Timeout` when nothing was given, like the semaphore,
+//! * gives made before the takes add up, where the binary semaphore keeps one,
+//! * a mailbox only holds the latest value sent, including 0, and is empty after a
+//!   `receive`.
+//!
+//! The POSIX port has no interrupts, so a task plays the interrupt handler.
+//!
+//! The process exits with the number of failed checks.
+//!
+//!     cargo run --example notification --target x86_64-unknown-linux-gnu
+use freertos_rust::*;
+use std::sync::atomic::{AtomicU32, Ordering};
+use std::sync::Arc;
+
+#[global_allocator]
+static GLOBAL: FreeRtosAllocator = FreeRtosAllocator;
+
+const ROUNDS: u32 = 20;
+
+static NOTIFIED_RUNS: AtomicU32 = AtomicU32::new(0);
+static SEMAPHORE_RUNS: AtomicU32 = AtomicU32::new(0);
+
+extern "C" {
+    // `std::process::exit` runs the exit handlers of the POSIX port, which cancel the
+    // threads the tasks run on while they are in Rust code.
+    fn _exit(status: i32) -> !;
+}
+
+/// Gives `rounds` times, once the worker counted the one before.
+fn drive<G: Fn()>(os: FreeRTOS, give: G, runs: &AtomicU32, rounds: u32) -> u32 {
+    let start = runs.load(Ordering::SeqCst);
+    for round in 1..=rounds {
+        give();
+        let mut waited = 0;
+        while runs.load(Ordering::SeqCst) - start < round && waited < 100 {
+            os.delay(Duration::ms(1));
+            waited += 1;
+        }
+    }
+    os.delay(Duration::ms(5));
+    runs.load(Ordering::SeqCst) - start
+}
+
+/// How many of `attempts` takes succeed, and whether the first failing one timed out.
+fn takes<T: FnMut() -> Result<(), FreeRtosError>>(attempts: u32, mut take: T) -> (u32, bool) {
+    let mut taken = 0;
+    for _ in 0..attempts {
+        match take() {
+            Ok(()) => taken += 1,
+            Err(e) => return (taken, matches!(e, FreeRtosError::Timeout)),
+        }
+    }
+    (taken, false)
+}
+
+fn main() {
+    FreeRTOS::start_scheduler(|os| {
+        os.new_task("checks", 512, TaskPriority(2), move |this, os| {
+            let mut failures = 0;
+
+            let givers = Arc::new(Mutex::new(os, None).unwrap());
+            let shared = givers.clone();
+            os.new_task("notified", 256, TaskPriority(3), move |this, _| {
+                let (giver, semaphore) = NotificationSemaphore::new(this);
+                *shared.lock(Duration::infinite()).unwrap() = Some(giver);
+                loop {
+                    semaphore.take(Duration::infinite()).unwrap();
+                    NOTIFIED_RUNS.fetch_add(1, Ordering::SeqCst);
+                }
+            })
+            .unwrap();
+            let semaphore = Arc::new(BinarySemaphore::new(os).unwrap());
+            let shared = semaphore.clone();
+            os.new_task("semaphore", 256, TaskPriority(3), move |_, _| loop {
+                shared.take(Duration::infinite()).unwrap();
+                SEMAPHORE_RUNS.fetch_add(1, Ordering::SeqCst);
+            })
+            .unwrap();
+            os.delay(Duration::ms(5));
+
+            let giver = givers.lock(Duration::zero()).unwrap().take().unwrap();
+            let notified = drive(os, || giver.give(), &NOTIFIED_RUNS, ROUNDS);
+            let isr_giver = unsafe { giver.new_isr_safe_handle() };
+            let from_isr = drive(
+                os,
+                || {
+                    let mut context = InterruptContext::new(unsafe { KernelIsr::claim() });
+                    isr_giver.give(&mut context);
+                },
+                &NOTIFIED_RUNS,
+                ROUNDS,
+            );
+            let semaphore_runs = drive(os, || semaphore.give(), &SEMAPHORE_RUNS, ROUNDS);
+            if notified != semaphore_runs || from_isr != semaphore_runs {
+                println!(
+                    "worker runs for {} gives: {} notified, {} notified from an interrupt, {} on the binary semaphore",
+                    ROUNDS, notified, from_isr, semaphore_runs
+                );
+                failures += 1;
+            }
+
+            let (giver, own) = NotificationSemaphore::new(this);
+            let local = BinarySemaphore::new(os).unwrap();
+            let start = os.get_tick_count();
+            let notified = own.take(Duration::ms(10));
+            let notified_wait = os.get_tick_count() - start;
+            let start = os.get_tick_count();
+            let binary = local.take(Duration::ms(10));
+            let binary_wait = os.get_tick_count() - start;
+            let wait = Duration::ms(10).to_ticks();
+            if !matches!(notified, Err(FreeRtosError::Timeout))
+                || !matches!(binary, Err(FreeRtosError::Timeout))
+                || notified_wait < wait
+                || binary_wait < wait
+            {
+                println!(
+                    "takes without a give: {:?} after {} ticks, binary semaphore {:?} after {}",
+                    notified, notified_wait, binary, binary_wait
+                );
+                failures += 1;
+            }
+
+            for _ in 0..3 {
+                giver.give();
+                local.give();
+            }
+            let notified = takes(5, || own.take(Duration::zero()));
+            let binary = takes(5, || local.take(Duration::zero()));
+            if notified != (3, true) || binary != (1, true) {
+                println!(
+                    "three gives, then takes: {:?} notified, {:?} on the binary semaphore",
+                    notified, binary
+                );
+                failures += 1;
+            }
+
+            let (sender, mailbox) = NotificationMailbox::new(this);
+            for value in 1..=3 {
+                sender.send(value);
+            }
+            let latest = mailbox.receive(Duration::zero());
+            let empty = mailbox.receive(Duration::ms(5));
+            sender.send(0);
+            let zero = mailbox.receive(Duration::zero());
+            if latest.ok() != Some(3) || !matches!(empty, Err(FreeRtosError::Timeout)) || zero.ok() != Some(0) {
+                println!(
+                    "mailbox: latest {:?}, then {:?}, after sending 0 {:?}",
+                    latest, empty, zero
+                );
+                failures += 1;
+            }
+
+            println!("{} failures", failures);
+            unsafe { _exit(failures) }
+        })
+        .unwrap();
+    });
+}
@@ -0,0 +1,210 @@
+//! Passes payloads by index between tasks and checks that each one arrives whole: a
+//! producer fills the buffers of a ring that aren't atomic, and passes how far it got
+//! to a consumer of the same priority, which checks the checksum of each buffer it
+//! reads. The index goes through a task notification first, then through a relaxed
+//! atomic with `publish_with_payload` and `consume_payload`, then through a `StatusCell`.
+//!
+//! The process exits with the number of corrupt buffers.
+//!
+//!     cargo run --example notification_payload --target x86_64-unknown-linux-gnu
+use freertos_rust::*;
+use std::cell::UnsafeCell;
+use std::sync::atomic::{AtomicU32, AtomicUsize, Ordering};
+use std::sync::Arc;
+
+#[global_allocator]
+static GLOBAL: FreeRtosAllocator = FreeRtosAllocator;
+
+extern "C" {
+    // `std::process::exit` runs the exit handlers of the POSIX port, which cancel the
+    // threads the tasks run on while they are in Rust code.
+    fn _exit(status: i32) -> !;
+}
+
+const ROUNDS: u32 = 500;
+const SLOTS: usize = 4;
+const WORDS: usize = 16;
+
+/// The buffers of a ring. A slot is only written by the producer while it holds a free
+/// slot, and read by the consumer once the producer published it.
+struct Ring([UnsafeCell<[u32; WORDS]>; SLOTS]);
+
+unsafe impl Sync for Ring {}
+
+impl Ring {
+    const fn new() -> Ring {
+        const EMPTY: UnsafeCell<[u32; WORDS]> = UnsafeCell::new([0; WORDS]);
+        Ring([EMPTY; SLOTS])
+    }
+
+    fn fill(&self, round: u32) {
+        let words = unsafe { &mut *self.0[round as usize % SLOTS].get() };
+        words[0] = round;
+        for (i, word) in words.iter_mut().enumerate().take(WORDS - 1).skip(1) {
+            *word = round.wrapping_mul(0x9e37_79b9) ^ i as u32;
+        }
+        words[WORDS - 1] = checksum(&words[..WORDS - 1]);
+    }
+
+    /// Whether the slot of `round` holds what `fill` wrote for it.
+    fn check(&self, round: u32) -> bool {
+        let words = unsafe { &*self.0[round as usize % SLOTS].get() };
+        words[0] == round && words[WORDS - 1] == checksum(&words[..WORDS - 1])
+    }
+}
+
+fn checksum(words: &[u32]) -> u32 {
+    words
+        .iter()
+        .fold(0u32, |sum, word| sum.rotate_left(5) ^ word)
+}
+
+static NOTIFIED_RING: Ring = Ring::new();
+static ATOMIC_RING: Ring = Ring::new();
+static STATUS_RING: Ring = Ring::new();
+/// The number of rounds the producer published to `ATOMIC_RING`.
+static PUBLISHED: AtomicU32 = AtomicU32::new(0);
+/// The number of rounds the consumer read, and how many of them were corrupt.
+static READ: AtomicU32 = AtomicU32::new(0);
+static CORRUPT: AtomicU32 = AtomicU32::new(0);
+/// The handle of the consumer, which it stores once it runs.
+static CONSUMER: AtomicUsize = AtomicUsize::new(0);
+
+/// Read the rounds from `*read` up to `published`, giving their slots back to `free`.
+fn consume(ring: &Ring, read: &mut u32, published: u32, free: &CountingSemaphore) {
+    while *read < published {
+        if !consume_payload(|| ring.check(*read)) {
+            CORRUPT.fetch_add(1, Ordering::SeqCst);
+        }
+        *read += 1;
+        free.try_give();
+    }
+    READ.store(*read, Ordering::SeqCst);
+}
+
+/// Wait for the consumer to read every round.
+fn wait_for_rounds(os: FreeRTOS) {
+    while READ.load(Ordering::SeqCst) < ROUNDS {
+        os.delay(Duration::ms(5));
+    }
+}
+
+fn main() {
+    FreeRTOS::start_scheduler(|os| {
+        os.new_task("checks", 512, TaskPriority(2), move |_, os| {
+            let free = Arc::new(
+                os.new_counting_semaphore(SLOTS as u32, SLOTS as u32)
+                    .unwrap(),
+            );
+
+            let f = free.clone();
+            os.new_task("consumer", 256, TaskPriority(1), move |this, _| {
+                CONSUMER.store(this.raw_handle() as usize, Ordering::SeqCst);
+                let mut next = 0;
+                loop {
+                    // The notification orders the payload already, the fence of
+                    // `consume` is spare.
+                    let published = this
+                        .wait_for_notification(0, u32::MAX, Duration::infinite())
+                        .unwrap();
+                    consume(&NOTIFIED_RING, &mut next, published, &f);
+                }
+            })
+            .unwrap();
+            while CONSUMER.load(Ordering::SeqCst) == 0 {
+                os.delay(Duration::ms(1));
+            }
+            let consumer = unsafe {
+                TaskRemoteHandle::from_raw(CONSUMER.load(Ordering::SeqCst) as FreeRtosTaskHandle)
+            };
+            let f = free.clone();
+            os.new_task("producer", 256, TaskPriority(1), move |_, os| {
+                for round in 0..ROUNDS {
+                    f.take(Duration::infinite()).unwrap();
+                    NOTIFIED_RING.fill(round);
+                    consumer.notify(TaskNotification::OverwriteValue(round + 1));
+                }
+                loop {
+                    os.delay(Duration::infinite());
+                }
+            })
+            .unwrap();
+            wait_for_rounds(os);
+            println!(
+                "{} rounds through a notification",
+                READ.load(Ordering::SeqCst)
+            );
+
+            // The same ring, published through an atomic the crate doesn't order.
+            READ.store(0, Ordering::SeqCst);
+            let f = free.clone();
+            os.new_task("poller", 256, TaskPriority(1), move |_, os| {
+                let mut next = 0;
+                loop {
+                    let published = PUBLISHED.load(Ordering::Relaxed);
+                    if published == next {
+                        os.delay(Duration::ms(1));
+                        continue;
+                    }
+                    consume(&ATOMIC_RING, &mut next, published, &f);
+                }
+            })
+            .unwrap();
+            let f = free.clone();
+            os.new_task("publisher", 256, TaskPriority(1), move |_, os| {
+                for round in 0..ROUNDS {
+                    f.take(Duration::infinite()).unwrap();
+                    publish_with_payload(|| ATOMIC_RING.fill(round));
+                    PUBLISHED.store(round + 1, Ordering::Relaxed);
+                }
+                loop {
+                    os.delay(Duration::infinite());
+                }
+            })
+            .unwrap();
+            wait_for_rounds(os);
+            println!("{} rounds through an atomic", READ.load(Ordering::SeqCst));
+
+            // The same ring, published through a `StatusCell`.
+            READ.store(0, Ordering::SeqCst);
+            let status = Arc::new(StatusCell::new(0u32));
+            let (s, f) = (status.clone(), free.clone());
+            os.new_task("reader", 256, TaskPriority(1), move |_, os| {
+                let mut next = 0;
+                loop {
+                    // The cell orders the payload already, the fence of `consume` is
+                    // spare.
+                    let published = s.get();
+                    if published == next {
+                        os.delay(Duration::ms(1));
+                        continue;
+                    }
+                    consume(&STATUS_RING, &mut next, published, &f);
+                }
+            })
+            .unwrap();
+            let (s, f) = (status.clone(), free.clone());
+            os.new_task("writer", 256, TaskPriority(1), move |_, os| {
+                for round in 0..ROUNDS {
+                    f.take(Duration::infinite()).unwrap();
+                    STATUS_RING.fill(round);
+                    s.set(round + 1);
+                }
+                loop {
+                    os.delay(Duration::infinite());
+                }
+            })
+            .unwrap();
+            wait_for_rounds(os);
+            println!(
+                "{} rounds through a status cell",
+                READ.load(Ordering::SeqCst)
+            );
+
+            let corrupt = CORRUPT.load(Ordering::SeqCst);
+            println!("{} corrupt", corrupt);
+            unsafe { _exit(corrupt as i32) }
+        })
+        .unwrap();
+    });
+}
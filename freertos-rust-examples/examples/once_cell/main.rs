@@ -0,0 +1,133 @@
+//! Initializes `FreeRtosOnceCell` statics from racing tasks, and checks that:
+//!
+//! * the initializer runs exactly once, while the other tasks wait, and all of them get
+//!   the same value,
+//! * `get` is `None` before and the value after, and `set` on a set cell gives the value
+//!   back,
+//! * `set` on an empty cell sets it, and `get_or_init` doesn't run its initializer then,
+//! * an empty cell doesn't allocate until the first initialization.
+//!
+//! The process exits with the number of failed checks.
+//!
+//!     cargo run --example once_cell --target x86_64-unknown-linux-gnu
+use freertos_rust::*;
+use std::sync::atomic::{AtomicU32, AtomicUsize, Ordering};
+
+#[global_allocator]
+static GLOBAL: FreeRtosAllocator = FreeRtosAllocator;
+
+const RACERS: usize = 4;
+
+#[derive(Debug, PartialEq)]
+struct Config {
+    id: u32,
+    name: String,
+}
+
+static CONFIG: FreeRtosOnceCell<Config> = FreeRtosOnceCell::new();
+static PRESET: FreeRtosOnceCell<u32> = FreeRtosOnceCell::new();
+
+static INIT_RUNS: AtomicU32 = AtomicU32::new(0);
+static DONE: AtomicU32 = AtomicU32::new(0);
+/// The address of the value each racer got.
+static SEEN: [AtomicUsize; RACERS] = [
+    AtomicUsize::new(0),
+    AtomicUsize::new(0),
+    AtomicUsize::new(0),
+    AtomicUsize::new(0),
+];
+
+extern "C" {
+    // `std::process::exit` runs the exit handlers of the POSIX port, which cancel the
+    // threads the tasks run on while they are in Rust code.
+    fn _exit(status: i32) -> !;
+}
+
+fn main() {
+    FreeRTOS::start_scheduler(|os| {
+        os.new_task("checks", 512, TaskPriority(3), move |_, os| {
+            let mut failures = 0;
+
+            let free_before = unsafe { freertos_rs_xPortGetFreeHeapSize() };
+            let empty = CONFIG.get().is_none();
+            let free_after = unsafe { freertos_rs_xPortGetFreeHeapSize() };
+            if !empty || free_before != free_after {
+                println!(
+                    "empty cell: get is none {}, heap {} bytes before, {} after",
+                    empty, free_before, free_after
+                );
+                failures += 1;
+            }
+
+            for racer in 0..RACERS {
+                os.new_task("racer", 256, TaskPriority(2), move |_, os| {
+                    let config = CONFIG.get_or_init(os, || {
+                        INIT_RUNS.fetch_add(1, Ordering::SeqCst);
+                        // Long enough for the other racers to arrive and wait.
+                        os.delay(Duration::ms(10));
+                        Config {
+                            id: racer as u32,
+                            name: format!("racer {}", racer),
+                        }
+                    });
+                    SEEN[racer].store(config as *const Config as usize, Ordering::SeqCst);
+                    DONE.fetch_add(1, Ordering::SeqCst);
+                    loop {
+                        os.delay(Duration::infinite());
+                    }
+                })
+                .unwrap();
+            }
+            os.delay(Duration::ms(50));
+
+            let seen: Vec<usize> = SEEN.iter().map(|s| s.load(Ordering::SeqCst)).collect();
+            let config = CONFIG.get();
+            let same = config.map_or(false, |c| {
+                seen.iter().all(|&s| s == c as *const Config as usize)
+            });
+            if DONE.load(Ordering::SeqCst) != RACERS as u32
+                || INIT_RUNS.load(Ordering::SeqCst) != 1
+                || !same
+            {
+                println!(
+                    "{} of {} racers done, initializer ran {} times, same value for all {}",
+                    DONE.load(Ordering::SeqCst),
+                    RACERS,
+                    INIT_RUNS.load(Ordering::SeqCst),
+                    same
+                );
+                failures += 1;
+            }
+
+            let late = Config {
+                id: 99,
+                name: "late".to_string(),
+            };
+            match CONFIG.set(late) {
+                Err(back) if back.id == 99 && CONFIG.get().map(|c| c.id) != Some(99) => {}
+                result => {
+                    println!("set on a set cell: {:?}", result);
+                    failures += 1;
+                }
+            }
+
+            let mut ran = false;
+            let set = PRESET.set(7);
+            let value = *PRESET.get_or_init(os, || {
+                ran = true;
+                8
+            });
+            if set.is_err() || value != 7 || ran || PRESET.set(9) != Err(9) {
+                println!(
+                    "preset cell: set {:?}, value {}, initializer ran {}",
+                    set, value, ran
+                );
+                failures += 1;
+            }
+
+            println!("{} failures", failures);
+            unsafe { _exit(failures) }
+        })
+        .unwrap();
+    });
+}
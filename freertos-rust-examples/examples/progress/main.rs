@@ -0,0 +1,135 @@
+//! A worker reports a two phase operation through a `ProgressTracker` while a watcher
+//! checks the ETA, waits for the phase change and cancels the operation. The process exits
+//! with the number of failed checks.
+//!
+//!     cargo run --example progress --target x86_64-unknown-linux-gnu
+use freertos_rust::*;
+use std::alloc::{GlobalAlloc, Layout};
+use std::sync::atomic::{AtomicU32, Ordering};
+
+/// Counts the allocations, to check that snapshots don't allocate.
+struct CountingAllocator;
+
+static ALLOCATIONS: AtomicU32 = AtomicU32::new(0);
+
+unsafe impl GlobalAlloc for CountingAllocator {
+    unsafe fn alloc(&self, layout: Layout) -> *mut u8 {
+        ALLOCATIONS.fetch_add(1, Ordering::Relaxed);
+        FreeRtosAllocator.alloc(layout)
+    }
+
+    unsafe fn dealloc(&self, ptr: *mut u8, layout: Layout) {
+        FreeRtosAllocator.dealloc(ptr, layout)
+    }
+}
+
+#[global_allocator]
+static GLOBAL: CountingAllocator = CountingAllocator;
+
+/// Units done per tick by the worker.
+const STEP: u32 = 4;
+const TOTAL: u32 = 2000;
+
+static FINAL_DONE: AtomicU32 = AtomicU32::new(u32::MAX);
+
+extern "C" {
+    // `std::process::exit` runs the exit handlers of the POSIX port, which cancel the
+    // threads the tasks run on while they are in Rust code.
+    fn _exit(status: i32) -> !;
+}
+
+fn main() {
+    FreeRTOS::start_scheduler(|os| {
+        let tracker = os.new_progress_tracker();
+        let mut watcher = tracker.watcher().unwrap();
+        let reporter = tracker.reporter().unwrap();
+        let second_reporter = tracker.reporter().is_none();
+
+        os.new_task("worker", 256, TaskPriority(1), move |_, os| {
+            'phases: for phase in ["write", "verify"] {
+                reporter.set_phase(phase);
+                reporter.set_total(TOTAL);
+                for _ in 0..TOTAL / STEP {
+                    if reporter.advance(STEP) {
+                        break 'phases;
+                    }
+                    os.delay(Duration::ticks(1));
+                }
+            }
+            FINAL_DONE.store(tracker.snapshot().done, Ordering::Relaxed);
+            loop {
+                os.delay(Duration::ms(1000));
+            }
+        })
+        .unwrap();
+
+        os.new_task("watcher", 512, TaskPriority(2), move |_, os| {
+            let mut failures = 0;
+            if !second_reporter {
+                println!("a second reporter was handed out");
+                failures += 1;
+            }
+
+            // Let the rate settle with a snapshot every 10 ticks.
+            for _ in 0..20 {
+                os.delay(Duration::ticks(10));
+                watcher.snapshot();
+            }
+            let allocations = ALLOCATIONS.load(Ordering::Relaxed);
+            let progress = watcher.snapshot();
+            if ALLOCATIONS.load(Ordering::Relaxed) != allocations {
+                println!("snapshot allocated");
+                failures += 1;
+            }
+            println!("{}", progress);
+
+            let expected = Duration::ticks((TOTAL - progress.done) / STEP).to_ms();
+            match progress.eta {
+                Some(eta)
+                    if eta.to_ms() * 10 >= expected * 9 && eta.to_ms() * 10 <= expected * 11 => {}
+                eta => {
+                    println!("ETA {:?}, expected about {} ms", eta, expected);
+                    failures += 1;
+                }
+            }
+
+            loop {
+                match watcher.wait_change(Duration::ms(1000)) {
+                    Ok(progress) if progress.phase == "verify" => break,
+                    Ok(_) => {}
+                    Err(e) => {
+                        println!("no phase change: {:?}", e);
+                        failures += 1;
+                        break;
+                    }
+                }
+            }
+
+            os.delay(Duration::ticks(50));
+            let at_cancel = watcher.snapshot().done;
+            watcher.cancel();
+            if !watcher
+                .wait_change(Duration::ticks(0))
+                .map(|p| p.cancelled)
+                .unwrap_or(false)
+            {
+                println!("cancel not reported");
+                failures += 1;
+            }
+            os.delay(Duration::ticks(20));
+            let final_done = FINAL_DONE.load(Ordering::Relaxed);
+            if final_done > at_cancel + STEP {
+                println!(
+                    "worker went on to {} after cancel at {}",
+                    final_done, at_cancel
+                );
+                failures += 1;
+            }
+            println!("{}", watcher.snapshot());
+
+            println!("{} failures", failures);
+            unsafe { _exit(failures) }
+        })
+        .unwrap();
+    });
+}
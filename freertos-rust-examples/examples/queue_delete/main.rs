@@ -0,0 +1,170 @@
+//! Deletes queues that tasks are blocked on, and checks that:
+//!
+//! * dropping a queue a task is blocked sending to calls the assert hook and leaks the
+//!   queue, so the task times out normally instead of touching freed memory,
+//! * `delete` of a queue nobody waits on gives its memory back right away,
+//! * `delete` throws away the items, so a task blocked sending gets through, and waits
+//!   for a task blocked receiving to time out,
+//! * `delete` fails with `Timeout` when a task stays blocked, and the queue stays usable
+//!   for it.
+//!
+//! A queue can only be deleted under a blocked task when something else than a Rust
+//! reference holds on to it, like C code. The tasks here get a reference the borrow
+//! checker doesn't see instead.
+//!
+//! The process exits with the number of failed checks.
+//!
+//!     cargo run --example queue_delete --target x86_64-unknown-linux-gnu
+use freertos_rust::*;
+use std::ptr;
+use std::sync::atomic::{AtomicU32, Ordering};
+use std::sync::Arc;
+
+#[global_allocator]
+static GLOBAL: FreeRtosAllocator = FreeRtosAllocator;
+
+static ASSERTS: AtomicU32 = AtomicU32::new(0);
+
+extern "C" {
+    // `std::process::exit` runs the exit handlers of the POSIX port, which cancel the
+    // threads the tasks run on while they are in Rust code.
+    fn _exit(status: i32) -> !;
+}
+
+/// A queue, and a reference to it that stays valid when the queue is dropped.
+fn shared_queue(os: FreeRTOS) -> (Queue<u32>, &'static Queue<u32>) {
+    let leaked: &'static Queue<u32> = Box::leak(Box::new(Queue::new(os, 1).unwrap()));
+    (unsafe { ptr::read(leaked) }, leaked)
+}
+
+/// Run `f` on the queue in a task, and put what it returned in the mailbox.
+fn blocked_on<F>(os: FreeRTOS, queue: &'static Queue<u32>, f: F) -> Arc<Mutex<Option<String>>>
+where
+    F: FnOnce(&Queue<u32>) -> String + Send + 'static,
+{
+    let mailbox = Arc::new(Mutex::new(os, None).unwrap());
+    let result = mailbox.clone();
+    os.new_task("blocked", 256, TaskPriority(3), move |_, os| {
+        let outcome = f(queue);
+        *result.lock(Duration::infinite()).unwrap() = Some(outcome);
+        loop {
+            os.delay(Duration::infinite());
+        }
+    })
+    .unwrap();
+    mailbox
+}
+
+fn outcome(mailbox: &Mutex<Option<String>>) -> Option<String> {
+    mailbox.lock(Duration::infinite()).unwrap().clone()
+}
+
+fn main() {
+    FreeRTOS::start_scheduler(|os| {
+        os.new_task("checks", 512, TaskPriority(2), move |_, os| {
+            let mut failures = 0;
+            unsafe {
+                FREERTOS_HOOKS.set_on_assert(|| {
+                    ASSERTS.fetch_add(1, Ordering::SeqCst);
+                })
+            };
+
+            let (queue, shared) = shared_queue(os);
+            queue.send(1, Duration::zero()).unwrap();
+            let sender = blocked_on(os, shared, |queue| {
+                format!("{:?}", queue.send(2, Duration::ms(30)))
+            });
+            let blocked = queue.blocked_tasks();
+            let free_before = unsafe { freertos_rs_xPortGetFreeHeapSize() };
+            drop(queue);
+            let free_after = unsafe { freertos_rs_xPortGetFreeHeapSize() };
+            os.delay(Duration::ms(50));
+            let sent = outcome(&sender);
+            if blocked != 1
+                || ASSERTS.load(Ordering::SeqCst) != 1
+                || free_after != free_before
+                || sent.as_deref() != Some("Err(QueueSendTimeout)")
+            {
+                println!(
+                    "drop with a blocked sender: {} blocked, {} asserts, {} bytes freed, sender {:?}",
+                    blocked,
+                    ASSERTS.load(Ordering::SeqCst),
+                    free_after - free_before,
+                    sent
+                );
+                failures += 1;
+            }
+
+            let free_before = unsafe { freertos_rs_xPortGetFreeHeapSize() };
+            let queue: Queue<u32> = Queue::new(os, 4).unwrap();
+            queue.send(1, Duration::zero()).unwrap();
+            let deleted = queue.delete(Duration::zero());
+            let free_after = unsafe { freertos_rs_xPortGetFreeHeapSize() };
+            if deleted.is_err() || free_after != free_before {
+                println!(
+                    "delete without blocked tasks: {:?}, {} bytes missing",
+                    deleted,
+                    free_before - free_after
+                );
+                failures += 1;
+            }
+
+            let (queue, shared) = shared_queue(os);
+            queue.send(1, Duration::zero()).unwrap();
+            let sender = blocked_on(os, shared, |queue| {
+                format!("{:?}", queue.send(2, Duration::infinite()))
+            });
+            let deleted = queue.delete(Duration::ms(100));
+            os.delay(Duration::ms(5));
+            let sent = outcome(&sender);
+            if deleted.is_err() || sent.as_deref() != Some("Ok(())") {
+                println!("delete with a blocked sender: {:?}, sender {:?}", deleted, sent);
+                failures += 1;
+            }
+
+            let (queue, shared) = shared_queue(os);
+            let receiver = blocked_on(os, shared, |queue| {
+                format!("{:?}", queue.receive(Duration::ms(20)))
+            });
+            let start = os.get_tick_count();
+            let deleted = queue.delete(Duration::ms(100));
+            let waited = os.get_tick_count() - start;
+            let received = outcome(&receiver);
+            if deleted.is_err()
+                || waited < 15
+                || received.as_deref() != Some("Err(QueueReceiveTimeout)")
+            {
+                println!(
+                    "delete with a blocked receiver: {:?} after {} ticks, receiver {:?}",
+                    deleted, waited, received
+                );
+                failures += 1;
+            }
+
+            let (queue, shared) = shared_queue(os);
+            let receiver = blocked_on(os, shared, |queue| {
+                format!("{:?}", queue.receive(Duration::infinite()))
+            });
+            let deleted = queue.delete(Duration::ms(10));
+            shared.send(7, Duration::zero()).unwrap();
+            os.delay(Duration::ms(5));
+            let received = outcome(&receiver);
+            if deleted != Err(FreeRtosError::Timeout)
+                || received.as_deref() != Some("Ok(7)")
+                || ASSERTS.load(Ordering::SeqCst) != 1
+            {
+                println!(
+                    "delete with a receiver blocked forever: {:?}, receiver {:?}, {} asserts",
+                    deleted,
+                    received,
+                    ASSERTS.load(Ordering::SeqCst)
+                );
+                failures += 1;
+            }
+
+            println!("{} failures", failures);
+            unsafe { _exit(failures) }
+        })
+        .unwrap();
+    });
+}
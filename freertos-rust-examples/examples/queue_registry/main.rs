@@ -0,0 +1,84 @@
+//! Adds queues and semaphores to the kernel queue registry with the `new_named`
+//! constructors, and checks that:
+//!
+//! * `registered_name` reports the name they were created with, also after the string it
+//!   came from was dropped,
+//! * objects created without a name aren't in the registry,
+//! * dropping a named object takes it out of the registry again, so creating and dropping
+//!   many more named objects than `configQUEUE_REGISTRY_SIZE` never fills it up.
+//!
+//! The process exits with the number of failed checks.
+//!
+//!     cargo run --example queue_registry --target x86_64-unknown-linux-gnu
+use freertos_rust::*;
+
+#[global_allocator]
+static GLOBAL: FreeRtosAllocator = FreeRtosAllocator;
+
+/// `configQUEUE_REGISTRY_SIZE` of the example configuration.
+const REGISTRY_SIZE: usize = 20;
+
+extern "C" {
+    // `std::process::exit` runs the exit handlers of the POSIX port, which cancel the
+    // threads the tasks run on while they are in Rust code.
+    fn _exit(status: i32) -> !;
+}
+
+fn main() {
+    FreeRTOS::start_scheduler(|os| {
+        os.new_task("checks", 512, TaskPriority(2), move |_, os| {
+            let mut failures = 0;
+
+            let name = String::from("telemetry");
+            let telemetry = os.new_queue_named::<u32>(4, &name).unwrap();
+            drop(name);
+            let anonymous = os.new_queue::<u32>(4).unwrap();
+            let binary = BinarySemaphore::new_named(os, "ready").unwrap();
+            let counting = CountingSemaphore::new_named(os, 4, 0, "slots").unwrap();
+            let names = (
+                telemetry.registered_name(),
+                anonymous.registered_name(),
+                binary.registered_name(),
+                counting.registered_name(),
+            );
+            if names
+                != (
+                    Some("telemetry".into()),
+                    None,
+                    Some("ready".into()),
+                    Some("slots".into()),
+                )
+            {
+                println!("registered names {:?}", names);
+                failures += 1;
+            }
+
+            let mut unregistered = 0;
+            for i in 0..5 * REGISTRY_SIZE {
+                let name = format!("queue {}", i);
+                let queue = Queue::<u8>::new_named(os, 1, &name).unwrap();
+                if queue.registered_name() != Some(name) {
+                    unregistered += 1;
+                }
+                if i % 2 == 0 {
+                    let semaphore = BinarySemaphore::new_named(os, "scratch").unwrap();
+                    if semaphore.registered_name().as_deref() != Some("scratch") {
+                        unregistered += 1;
+                    }
+                }
+            }
+            if unregistered != 0 || telemetry.registered_name().as_deref() != Some("telemetry") {
+                println!(
+                    "{} of the short lived objects weren't registered, telemetry is {:?}",
+                    unregistered,
+                    telemetry.registered_name()
+                );
+                failures += 1;
+            }
+
+            println!("{} failures", failures);
+            unsafe { _exit(failures) }
+        })
+        .unwrap();
+    });
+}
@@ -0,0 +1,87 @@
+//! Compares `Vec` growth with and without in-place realloc. Run it both ways:
+//!
+//!     cargo run --example realloc_bench --target x86_64-unknown-linux-gnu
+//!     cargo run --example realloc_bench --target x86_64-unknown-linux-gnu --features heap_4
+use freertos_rust::*;
+use std::time::Instant;
+
+#[global_allocator]
+static GLOBAL: FreeRtosAllocator = FreeRtosAllocator;
+
+const ROUNDS: u32 = 200;
+const ITEMS: u32 = 1000;
+
+/// Push `ITEMS` values one by one, counting how often the buffer moved.
+fn grow(moves: &mut u32) -> Vec<u32> {
+    let mut v = Vec::new();
+    let mut last = v.as_ptr();
+    for i in 0..ITEMS {
+        v.push(i);
+        if v.as_ptr() != last {
+            *moves += 1;
+            last = v.as_ptr();
+        }
+    }
+    v
+}
+
+fn check(v: &[u32], len: u32) {
+    assert_eq!(v.len(), len as usize);
+    assert!(v.iter().enumerate().all(|(i, x)| *x == i as u32));
+}
+
+fn main() {
+    let os = unsafe { FreeRTOS::assume_init() };
+
+    os.new_task("bench", 512, TaskPriority(2), move |_, os| {
+        println!(
+            "realloc_bench, in-place realloc {}",
+            if cfg!(feature = "heap_4") {
+                "on"
+            } else {
+                "off"
+            }
+        );
+
+        let mut moves = 0;
+        let start = Instant::now();
+        for _ in 0..ROUNDS {
+            check(&grow(&mut moves), ITEMS);
+        }
+        println!(
+            "push x{}: {:?} per round, buffer moved {} times per round",
+            ITEMS,
+            start.elapsed() / ROUNDS,
+            moves / ROUNDS
+        );
+
+        // Two vectors growing in turn can't extend in place, the fallback has to copy.
+        let (mut a, mut b) = (Vec::new(), Vec::new());
+        for i in 0..ITEMS / 2 {
+            a.push(i);
+            b.push(i);
+        }
+        check(&a, ITEMS / 2);
+        check(&b, ITEMS / 2);
+
+        // Shrinking keeps the contents and gives memory back.
+        let mut v = grow(&mut moves);
+        v.truncate(100);
+        let before = FreeRtosAllocator::free_heap_size();
+        v.shrink_to_fit();
+        check(&v, 100);
+        println!(
+            "shrink_to_fit freed {} bytes",
+            FreeRtosAllocator::free_heap_size() as isize - before as isize
+        );
+        drop((a, b, v));
+
+        println!("done");
+        loop {
+            os.delay(Duration::ms(1000));
+        }
+    })
+    .unwrap();
+
+    FreeRTOS::start_scheduler(|_| {});
+}
@@ -0,0 +1,200 @@
+//! Renders a `FreeRtosSchedulerState` a chunk at a time with `render_next`, and checks
+//! that:
+//!
+//! * the chunks add up to the `Display` output byte for byte, with and without
+//!   `alternate` and run time stats, for chunk sizes from 1 byte up to the whole table,
+//! * rendering resumes where it left off when the chunk size changes from call to call,
+//! * a snapshot renders the same while tasks come and go,
+//! * `estimate_rendered_size` is the size of the rendered table,
+//! * `render_next` never allocates.
+//!
+//! The process exits with the number of failed checks.
+//!
+//!     cargo run --example render_chunks --target x86_64-unknown-linux-gnu
+use freertos_rust::*;
+use std::alloc::{GlobalAlloc, Layout};
+use std::sync::atomic::{AtomicBool, AtomicU32, Ordering};
+
+/// Counts the allocations made while `COUNTING` is set.
+struct CountingAllocator;
+
+static COUNTING: AtomicBool = AtomicBool::new(false);
+static ALLOCATIONS: AtomicU32 = AtomicU32::new(0);
+
+unsafe impl GlobalAlloc for CountingAllocator {
+    unsafe fn alloc(&self, layout: Layout) -> *mut u8 {
+        if COUNTING.load(Ordering::Relaxed) {
+            ALLOCATIONS.fetch_add(1, Ordering::Relaxed);
+        }
+        FreeRtosAllocator.alloc(layout)
+    }
+
+    unsafe fn dealloc(&self, ptr: *mut u8, layout: Layout) {
+        FreeRtosAllocator.dealloc(ptr, layout)
+    }
+}
+
+#[global_allocator]
+static GLOBAL: CountingAllocator = CountingAllocator;
+
+/// The largest is bigger than the table.
+const CHUNK_SIZES: [usize; 7] = [1, 2, 3, 7, 16, 100, 1024];
+
+extern "C" {
+    // `std::process::exit` runs the exit handlers of the POSIX port, which cancel the
+    // threads the tasks run on while they are in Rust code.
+    fn _exit(status: i32) -> !;
+}
+
+/// Render `state` into `out`, which must have room for it, with chunks of the sizes
+/// `chunk_size` returns for each call.
+fn render<F: FnMut(usize) -> usize>(
+    state: &FreeRtosSchedulerState,
+    opts: RenderOptions,
+    out: &mut Vec<u8>,
+    mut chunk_size: F,
+) {
+    let mut cursor = RenderCursor::new();
+    let mut chunk = vec![0u8; 1024];
+    let mut call = 0;
+    COUNTING.store(true, Ordering::Relaxed);
+    loop {
+        let size = chunk_size(call);
+        match state.render_next(opts, &mut cursor, &mut chunk[..size]) {
+            Some(n) => out.extend_from_slice(&chunk[..n]),
+            None => break,
+        }
+        call += 1;
+    }
+    COUNTING.store(false, Ordering::Relaxed);
+}
+
+fn check(state: &FreeRtosSchedulerState, what: &str) -> i32 {
+    let mut failures = 0;
+    for &opts in &[RenderOptions::default(), RenderOptions { alternate: true }] {
+        let expected = if opts.alternate {
+            format!("{:#}", state)
+        } else {
+            format!("{}", state)
+        };
+        if state.estimate_rendered_size(opts) != expected.len() {
+            println!(
+                "{}, {:?}: estimated {} bytes, rendered {}",
+                what,
+                opts,
+                state.estimate_rendered_size(opts),
+                expected.len()
+            );
+            failures += 1;
+        }
+
+        let mut out = Vec::with_capacity(expected.len() + 1024);
+        for &size in &CHUNK_SIZES {
+            out.clear();
+            render(state, opts, &mut out, |_| size);
+            if out != expected.as_bytes() {
+                println!(
+                    "{}, {:?}, {} byte chunks:\n{}\nexpected:\n{}",
+                    what,
+                    opts,
+                    size,
+                    String::from_utf8_lossy(&out),
+                    expected
+                );
+                failures += 1;
+            }
+        }
+
+        out.clear();
+        let mut seed = 12345u32;
+        render(state, opts, &mut out, |_| {
+            seed = seed.wrapping_mul(1103515245).wrapping_add(12345);
+            1 + (seed >> 16) as usize % 40
+        });
+        if out != expected.as_bytes() {
+            println!("{}, {:?}: chunks of changing sizes differ", what, opts);
+            failures += 1;
+        }
+    }
+    failures
+}
+
+fn main() {
+    FreeRTOS::start_scheduler(|os| {
+        os.new_task("checks", 1024, TaskPriority(2), move |_, os| {
+            let mut failures = 0;
+
+            // `get_all_tasks` needs Rust and the C shim to agree on the kernel type sizes,
+            // which they don't with the 64-bit `BaseType_t` of the Linux port, so the
+            // snapshot is made up.
+            let mut tasks = Vec::new();
+            for (i, name) in ["checks", "a task with a long name", "", "x"]
+                .iter()
+                .enumerate()
+            {
+                tasks.push(FreeRtosTaskStatus {
+                    task: unsafe { TaskRemoteHandle::from_raw(freertos_rs_get_current_task()) },
+                    name: name.to_string(),
+                    task_number: 10 + i as FreeRtosUBaseType,
+                    task_state: [
+                        FreeRtosTaskState::Running,
+                        FreeRtosTaskState::Ready,
+                        FreeRtosTaskState::Blocked,
+                        FreeRtosTaskState::Suspended,
+                    ][i],
+                    current_priority: TaskPriority(i as u8),
+                    base_priority: TaskPriority(i as u8),
+                    run_time_counter: [0, 1, 250, 749][i],
+                    stack_high_water_mark: 100 * i as FreeRtosUnsignedShort,
+                });
+            }
+            let mut state = FreeRtosSchedulerState {
+                tasks,
+                total_run_time: 1000,
+                tick_count: 5000,
+            };
+            failures += check(&state, "with run time stats");
+
+            // Snapshots render the same over time.
+            let expected = format!("{}", state);
+            let mut out = Vec::with_capacity(expected.len());
+            let mut cursor = RenderCursor::new();
+            let mut chunk = [0u8; 16];
+            let mut spawned = Vec::new();
+            while let Some(n) = state.render_next(RenderOptions::default(), &mut cursor, &mut chunk)
+            {
+                out.extend_from_slice(&chunk[..n]);
+                if spawned.len() < 2 {
+                    spawned.push(
+                        os.new_task("extra", 256, TaskPriority(1), |_, os| loop {
+                            os.delay(Duration::ms(1));
+                        })
+                        .unwrap(),
+                    );
+                }
+                os.delay(Duration::ms(1));
+            }
+            if out != expected.as_bytes() {
+                println!("snapshot rendered over time differs");
+                failures += 1;
+            }
+
+            state.total_run_time = 0;
+            failures += check(&state, "without run time stats");
+            state.tasks.clear();
+            failures += check(&state, "without tasks");
+
+            if ALLOCATIONS.load(Ordering::Relaxed) != 0 {
+                println!(
+                    "{} allocations while rendering",
+                    ALLOCATIONS.load(Ordering::Relaxed)
+                );
+                failures += 1;
+            }
+
+            println!("{} failures", failures);
+            unsafe { _exit(failures) }
+        })
+        .unwrap();
+    });
+}
@@ -0,0 +1,138 @@
+//! Hands values between two tasks through a `Rendezvous` and checks that:
+//!
+//! * `send` only returns once the receiver is waiting and has taken the value,
+//! * a `send` nobody receives fails with `RendezvousSendTimeout` and leaves nothing
+//!   behind for a later `receive`,
+//! * a `receive` nobody sends to fails with `RendezvousReceiveTimeout`,
+//! * two senders take turns, each `send` completing with its own value.
+//!
+//! The process exits with the number of failed checks.
+//!
+//!     cargo run --example rendezvous --target x86_64-unknown-linux-gnu
+use freertos_rust::*;
+use std::sync::atomic::{AtomicU32, Ordering};
+use std::sync::Arc;
+
+#[global_allocator]
+static GLOBAL: FreeRtosAllocator = FreeRtosAllocator;
+
+const ROUNDS: u32 = 5;
+const RECEIVER_DELAY: u32 = 10;
+
+/// Number of receives the receiver started waiting in.
+static WAITING: AtomicU32 = AtomicU32::new(0);
+/// Number of sends that returned, over both senders.
+static SENT: AtomicU32 = AtomicU32::new(0);
+
+extern "C" {
+    // `std::process::exit` runs the exit handlers of the POSIX port, which cancel the
+    // threads the tasks run on while they are in Rust code.
+    fn _exit(status: i32) -> !;
+}
+
+fn main() {
+    FreeRTOS::start_scheduler(|os| {
+        os.new_task("checks", 512, TaskPriority(2), move |_, os| {
+            let mut failures = 0;
+
+            // Handshake with a slower receiver.
+            let point = Arc::new(os.new_rendezvous::<u32>().unwrap());
+            let received = Arc::new(os.new_queue::<u32>(ROUNDS as usize).unwrap());
+            let (rx, values) = (point.clone(), received.clone());
+            os.new_task("receiver", 256, TaskPriority(1), move |_, os| {
+                for _ in 0..ROUNDS {
+                    os.delay(Duration::ticks(RECEIVER_DELAY));
+                    WAITING.fetch_add(1, Ordering::Relaxed);
+                    let value = rx.receive(Duration::infinite()).unwrap();
+                    values.send(value, Duration::zero()).unwrap();
+                }
+                loop {
+                    os.delay(Duration::ms(10_000));
+                }
+            })
+            .unwrap();
+
+            for round in 0..ROUNDS {
+                let start = os.get_tick_count();
+                point.send(round * 7, Duration::ms(1000)).unwrap();
+                let waited = os.get_tick_count() - start;
+                let waiting = WAITING.load(Ordering::Relaxed);
+                if waiting != round + 1 || waited + 1 < RECEIVER_DELAY {
+                    println!(
+                        "send {} returned after {} ticks with {} receives waiting",
+                        round, waited, waiting
+                    );
+                    failures += 1;
+                }
+            }
+            for round in 0..ROUNDS {
+                match received.receive(Duration::ms(100)) {
+                    Ok(value) if value == round * 7 => {}
+                    r => {
+                        println!("receiver got {:?}, expected {}", r, round * 7);
+                        failures += 1;
+                    }
+                }
+            }
+
+            // The receiver is done, so nobody takes this one.
+            match point.send(99, Duration::ms(2)) {
+                Err(FreeRtosError::RendezvousSendTimeout) => {}
+                r => {
+                    println!("send without a receiver: {:?}", r);
+                    failures += 1;
+                }
+            }
+            match point.receive(Duration::ms(2)) {
+                Err(FreeRtosError::RendezvousReceiveTimeout) => {}
+                r => {
+                    println!("receive after a failed send: {:?}", r);
+                    failures += 1;
+                }
+            }
+
+            // Two senders, each send returning only after its value was received.
+            let shared = Arc::new(os.new_rendezvous::<u32>().unwrap());
+            for sender in 0..2 {
+                let tx = shared.clone();
+                os.new_task("sender", 256, TaskPriority(3), move |_, os| {
+                    for i in 0..3 {
+                        tx.send(sender * 10 + i, Duration::infinite()).unwrap();
+                        SENT.fetch_add(1, Ordering::Relaxed);
+                    }
+                    loop {
+                        os.delay(Duration::ms(10_000));
+                    }
+                })
+                .unwrap();
+            }
+            let mut got = Vec::new();
+            for taken in 0..6 {
+                if SENT.load(Ordering::Relaxed) > taken {
+                    println!(
+                        "{} sends returned after {} receives",
+                        SENT.load(Ordering::Relaxed),
+                        taken
+                    );
+                    failures += 1;
+                }
+                match shared.receive(Duration::ms(100)) {
+                    Ok(value) => got.push(value),
+                    Err(e) => {
+                        println!("receive {} from two senders: {:?}", taken, e);
+                        failures += 1;
+                    }
+                }
+            }
+            got.sort();
+            if got != [0, 1, 2, 10, 11, 12] {
+                println!("received {:?} from two senders", got);
+                failures += 1;
+            }
+
+            println!("{} failures", failures);
+            unsafe { _exit(failures) }
+        })
+        .unwrap();
+    });
+}
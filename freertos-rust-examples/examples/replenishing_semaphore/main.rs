@@ -0,0 +1,117 @@
+//! Runs a `ReplenishingSemaphore` in deterministic virtual time and checks that:
+//!
+//! * over many periods the credit handed out is exactly the amount per period,
+//! * the credit never grows past the capacity,
+//! * a new rate takes effect at the next period boundary, not when it is set,
+//! * a task waiting for credit wakes at the replenish.
+//!
+//! The process exits with the number of failed checks.
+//!
+//!     cargo run --example replenishing_semaphore --features test_support --target x86_64-unknown-linux-gnu
+use freertos_rust::test_support::*;
+use freertos_rust::*;
+
+#[global_allocator]
+static GLOBAL: FreeRtosAllocator = FreeRtosAllocator;
+
+extern "C" {
+    // `std::process::exit` runs the exit handlers of the POSIX port, which cancel the
+    // threads the tasks run on while they are in Rust code.
+    fn _exit(status: i32) -> !;
+}
+
+const CAPACITY: u32 = 5;
+const AMOUNT: u32 = 2;
+const PERIOD_MS: u32 = 100;
+const PERIODS: u32 = 50;
+
+fn drain(semaphore: &ReplenishingSemaphore) -> u32 {
+    let mut taken = 0;
+    while semaphore.try_acquire() {
+        taken += 1;
+    }
+    taken
+}
+
+/// Wait until the replenish at the end of the current period ran. The timer service
+/// runs above this task, so it replenished by the time the delay ends.
+fn next_boundary(os: &FreeRTOS, semaphore: &ReplenishingSemaphore) {
+    os.delay(semaphore.next_replenish_in(os));
+}
+
+fn main() {
+    enable_deterministic_virtual_time();
+
+    FreeRTOS::start_scheduler(|os| {
+        os.new_task("checks", 512, TaskPriority(3), move |_, os| {
+            let mut failures = 0;
+            let mut check = |ok: bool, what: &str| {
+                if !ok {
+                    println!("failed: {}", what);
+                    failures += 1;
+                }
+            };
+
+            let semaphore =
+                ReplenishingSemaphore::new(os, CAPACITY, AMOUNT, Duration::ms(PERIOD_MS)).unwrap();
+            check(drain(&semaphore) == CAPACITY, "the semaphore starts full");
+
+            let mut credited = 0;
+            for _ in 0..PERIODS {
+                next_boundary(&os, &semaphore);
+                credited += drain(&semaphore);
+            }
+            check(credited == AMOUNT * PERIODS, "the credit is exact");
+
+            for _ in 0..CAPACITY {
+                next_boundary(&os, &semaphore);
+            }
+            check(
+                semaphore.available() == CAPACITY,
+                "the credit stops at the capacity",
+            );
+            drain(&semaphore);
+
+            // Halfway through a period, switch to 3 credits every 40 ms.
+            os.delay(Duration::ms(PERIOD_MS / 2));
+            let boundary = os.get_tick_count() + semaphore.next_replenish_in(&os).to_ticks();
+            semaphore.set_replenish(3, Duration::ms(40)).unwrap();
+            check(
+                os.get_tick_count() + semaphore.next_replenish_in(&os).to_ticks() == boundary,
+                "setting the rate keeps the current period",
+            );
+            next_boundary(&os, &semaphore);
+            check(
+                drain(&semaphore) == AMOUNT,
+                "the period ending after the change gives the old amount",
+            );
+            check(
+                semaphore.next_replenish_in(&os).to_ticks() == Duration::ms(40).to_ticks(),
+                "the new period starts at the boundary",
+            );
+            next_boundary(&os, &semaphore);
+            check(
+                os.get_tick_count() == boundary + Duration::ms(40).to_ticks(),
+                "the first new period is 40 ms",
+            );
+            check(
+                drain(&semaphore) == 3,
+                "the first new period gives the new amount",
+            );
+
+            let expected = os.get_tick_count() + semaphore.next_replenish_in(&os).to_ticks();
+            check(
+                semaphore.acquire(Duration::ms(1000)).is_ok(),
+                "a waiting task gets the replenished credit",
+            );
+            check(
+                os.get_tick_count() == expected,
+                "the waiting task wakes at the replenish",
+            );
+
+            println!("{} failures", failures);
+            unsafe { _exit(failures) }
+        })
+        .unwrap();
+    });
+}
@@ -0,0 +1,91 @@
+//! Starts the scheduler with `try_start_scheduler` and setup closures that fail, then with
+//! one that succeeds, and checks that:
+//!
+//! * a failing setup returns its error without starting the scheduler,
+//! * without a startup failure hook the assert hook is called, with one only that hook,
+//!   with the error,
+//! * tasks created before the setup failed are kept, and run once a later setup starts
+//!   the scheduler.
+//!
+//! The process exits with the number of failed checks.
+//!
+//!     cargo run --example startup_failure --target x86_64-unknown-linux-gnu
+use freertos_rust::*;
+use std::sync::atomic::{AtomicBool, AtomicU32, Ordering};
+use std::sync::Mutex;
+
+#[global_allocator]
+static GLOBAL: FreeRtosAllocator = FreeRtosAllocator;
+
+static ASSERTS: AtomicU32 = AtomicU32::new(0);
+static FAILURES_SEEN: Mutex<Vec<FreeRtosError>> = Mutex::new(Vec::new());
+static LEFTOVER_RAN: AtomicBool = AtomicBool::new(false);
+
+extern "C" {
+    // `std::process::exit` runs the exit handlers of the POSIX port, which cancel the
+    // threads the tasks run on while they are in Rust code.
+    fn _exit(status: i32) -> !;
+}
+
+fn main() {
+    let mut failures = 0;
+    unsafe {
+        FREERTOS_HOOKS.set_on_assert(|| {
+            ASSERTS.fetch_add(1, Ordering::SeqCst);
+        })
+    };
+
+    let error = FreeRTOS::try_start_scheduler(|_| Err(FreeRtosError::QueueFull));
+    if error != FreeRtosError::QueueFull || ASSERTS.load(Ordering::SeqCst) != 1 {
+        println!(
+            "without a hook: {:?}, {} asserts",
+            error,
+            ASSERTS.load(Ordering::SeqCst)
+        );
+        failures += 1;
+    }
+
+    unsafe {
+        FREERTOS_HOOKS.set_on_startup_failure(|error| FAILURES_SEEN.lock().unwrap().push(error))
+    };
+    let error = FreeRTOS::try_start_scheduler(|os| {
+        os.new_task("leftover", 256, TaskPriority(1), |_, os| {
+            LEFTOVER_RAN.store(true, Ordering::SeqCst);
+            loop {
+                os.delay(Duration::infinite());
+            }
+        })?;
+        os.new_queue_named::<u32>(1, "bad\0name")?;
+        Ok(())
+    });
+    let seen = FAILURES_SEEN.lock().unwrap().clone();
+    if error != FreeRtosError::InvalidName
+        || seen != [FreeRtosError::InvalidName]
+        || ASSERTS.load(Ordering::SeqCst) != 1
+    {
+        println!(
+            "with a hook: {:?}, hook saw {:?}, {} asserts",
+            error,
+            seen,
+            ASSERTS.load(Ordering::SeqCst)
+        );
+        failures += 1;
+    }
+
+    let error = FreeRTOS::try_start_scheduler(move |os| {
+        os.new_task("checks", 512, TaskPriority(2), move |_, os| {
+            let mut failures = failures;
+            os.delay(Duration::ms(5));
+            if !LEFTOVER_RAN.load(Ordering::SeqCst) {
+                println!("task from the failed setup didn't run");
+                failures += 1;
+            }
+
+            println!("{} failures", failures);
+            unsafe { _exit(failures) }
+        })?;
+        Ok(())
+    });
+    println!("scheduler failed to start: {:?}", error);
+    unsafe { _exit(100) }
+}
@@ -0,0 +1,45 @@
+//! A blinking task created with `StaticTask`, so neither its stack nor its control block
+//! comes from the FreeRTOS heap. Uses the win configuration, which sets
+//! `configSUPPORT_STATIC_ALLOCATION`.
+//!
+//!     cargo run --example static_blinky --features static_allocation
+
+use freertos_rust::*;
+use std::sync::atomic::{AtomicBool, Ordering};
+
+#[global_allocator]
+static GLOBAL: FreeRtosAllocator = FreeRtosAllocator;
+
+struct Led {
+    on: AtomicBool,
+}
+
+impl Led {
+    const fn new() -> Led {
+        Led {
+            on: AtomicBool::new(false),
+        }
+    }
+
+    fn toggle(&self) -> bool {
+        !self.on.fetch_xor(true, Ordering::Relaxed)
+    }
+}
+
+static LED: Led = Led::new();
+static BLINKY: StaticTask<256> = StaticTask::new();
+
+fn blink(_this: &TaskSelfHandle, os: FreeRTOS, led: &'static Led) -> ! {
+    loop {
+        println!("LED {}", if led.toggle() { "on" } else { "off" });
+        os.delay(Duration::ms(500));
+    }
+}
+
+fn main() {
+    let error = FreeRTOS::try_start_scheduler(|os| {
+        BLINKY.spawn_static(&os, "blinky", TaskPriority(2), blink, &LED)?;
+        Ok(())
+    });
+    println!("Scheduler failed to start: {}", error);
+}
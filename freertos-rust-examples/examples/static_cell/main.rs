@@ -0,0 +1,127 @@
+//! Keeps kernel objects in `StaticFreeRtosCell` statics set in the setup closure of
+//! `start_scheduler`, and checks that:
+//!
+//! * a cell set before the scheduler starts is usable from every task, without `Arc`s,
+//! * an interrupt reaches the object through `isr_handle`, a handle of the `static`,
+//! * reading a cell that wasn't set panics, and `try_get` is `None`,
+//! * an `init` that fails leaves the cell empty, and a later one sets it,
+//! * `set` on a set cell gives the value back, and a second `init` panics.
+//!
+//! The POSIX port has no interrupts, so a task at the highest priority of the example
+//! plays the interrupt handler.
+//!
+//! The process exits with the number of failed checks.
+//!
+//!     cargo run --example static_cell --target x86_64-unknown-linux-gnu
+use freertos_rust::*;
+use std::panic::{self, AssertUnwindSafe};
+
+#[global_allocator]
+static GLOBAL: FreeRtosAllocator = FreeRtosAllocator;
+
+const PRODUCERS: u32 = 3;
+
+static EVENTS: StaticFreeRtosCell<Queue<u32>> = StaticFreeRtosCell::new();
+static DONE: StaticFreeRtosCell<CountingSemaphore> = StaticFreeRtosCell::new();
+static NEVER_SET: StaticFreeRtosCell<Queue<u32>> = StaticFreeRtosCell::new();
+static RETRIED: StaticFreeRtosCell<Queue<u32>> = StaticFreeRtosCell::new();
+
+extern "C" {
+    // `std::process::exit` runs the exit handlers of the POSIX port, which cancel the
+    // threads the tasks run on while they are in Rust code.
+    fn _exit(status: i32) -> !;
+}
+
+fn panics<F: FnOnce()>(f: F) -> bool {
+    panic::catch_unwind(AssertUnwindSafe(f)).is_err()
+}
+
+fn main() {
+    // The default hook's message and backtrace need more heap than the example has.
+    panic::set_hook(Box::new(|_| {}));
+
+    FreeRTOS::start_scheduler(|os| {
+        EVENTS.init(os, |os| os.new_queue(8)).unwrap();
+        DONE.init(os, |os| os.new_counting_semaphore(PRODUCERS, 0))
+            .unwrap();
+
+        for id in 0..PRODUCERS {
+            os.new_task("producer", 256, TaskPriority(2), move |_, os| {
+                EVENTS.send(id, Duration::infinite()).unwrap();
+                DONE.give();
+                loop {
+                    os.delay(Duration::infinite());
+                }
+            })
+            .unwrap();
+        }
+
+        os.new_task("checks", 512, TaskPriority(3), move |_, os| {
+            let mut failures = 0;
+
+            for _ in 0..PRODUCERS {
+                DONE.take(Duration::ms(100)).unwrap();
+            }
+            let mut received: Vec<u32> = (0..PRODUCERS)
+                .filter_map(|_| EVENTS.receive(Duration::zero()).ok())
+                .collect();
+            received.sort();
+            if received != (0..PRODUCERS).collect::<Vec<_>>() {
+                println!("received {:?} from the producers", received);
+                failures += 1;
+            }
+
+            let sent = {
+                let handle = EVENTS.isr_handle();
+                let mut context = InterruptContext::new(unsafe { KernelIsr::claim() });
+                handle.send(&mut context, 42)
+            };
+            let received = EVENTS.receive(Duration::zero());
+            if sent.is_err() || received != Ok(42) {
+                println!(
+                    "from an interrupt: sent {:?}, received {:?}",
+                    sent, received
+                );
+                failures += 1;
+            }
+
+            let read_unset = panics(|| {
+                let _ = NEVER_SET.len();
+            });
+            if !read_unset || NEVER_SET.try_get().is_some() {
+                println!("reading an unset cell panicked: {}", read_unset);
+                failures += 1;
+            }
+
+            let failed = RETRIED
+                .init(os, |_| Err(FreeRtosError::OutOfMemory))
+                .map(|_| ());
+            let empty_after = RETRIED.try_get().is_none();
+            let retried = RETRIED.init(os, |os| os.new_queue(1)).map(|_| ());
+            if failed != Err(FreeRtosError::OutOfMemory) || !empty_after || retried.is_err() {
+                println!(
+                    "failed init {:?}, empty after {}, then {:?}",
+                    failed, empty_after, retried
+                );
+                failures += 1;
+            }
+
+            let again = Queue::new(os, 1).unwrap();
+            let given_back = RETRIED.set(again).is_err();
+            let init_twice = panics(|| {
+                let _ = RETRIED.init(os, |os| os.new_queue(1));
+            });
+            if !given_back || !init_twice {
+                println!(
+                    "set cell: set gave back {}, init panicked {}",
+                    given_back, init_twice
+                );
+                failures += 1;
+            }
+
+            println!("{} failures", failures);
+            unsafe { _exit(failures) }
+        })
+        .unwrap();
+    });
+}
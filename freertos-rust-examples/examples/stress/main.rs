@@ -0,0 +1,74 @@
+//! Runs a `StressScenario` over queues, mutexes, semaphores, notifications and timers,
+//! and checks that:
+//!
+//! * the smoke scenario passes and gets through a fair number of operations,
+//! * the report of a run names the seed it was made with.
+//!
+//! Pass a seed to run with it instead of the default, and `soak` before it to run the
+//! ten minute soak scenario, which takes about that long on the simulator too.
+//!
+//! The process exits with the number of failed checks.
+//!
+//!     cargo run --example stress --features test_support --target x86_64-unknown-linux-gnu -- [soak] [seed]
+use freertos_rust::test_support::stress::*;
+use freertos_rust::*;
+
+#[global_allocator]
+static GLOBAL: FreeRtosAllocator = FreeRtosAllocator;
+
+const DEFAULT_SEED: u32 = 0x5eed_0001;
+
+extern "C" {
+    // `std::process::exit` runs the exit handlers of the POSIX port, which cancel the
+    // threads the tasks run on while they are in Rust code.
+    fn _exit(status: i32) -> !;
+}
+
+fn parse_seed(arg: &str) -> Option<u32> {
+    match arg.strip_prefix("0x") {
+        Some(hex) => u32::from_str_radix(hex, 16).ok(),
+        None => arg.parse().ok(),
+    }
+}
+
+fn main() {
+    let mut soak = false;
+    let mut seed = DEFAULT_SEED;
+    for arg in std::env::args().skip(1) {
+        if arg == "soak" {
+            soak = true;
+        } else {
+            seed = parse_seed(&arg).expect("the seed must be a number");
+        }
+    }
+
+    FreeRTOS::start_scheduler(move |os| {
+        os.new_task("checks", 512, TaskPriority(3), move |_, os| {
+            let mut failures = 0;
+
+            let scenario = if soak {
+                StressScenario::soak(seed, [4, 2, 2, 1, 1])
+            } else {
+                StressScenario::smoke(seed)
+            };
+            let report = scenario.run(os).unwrap();
+            println!("{}", report);
+            if !report.passed() {
+                failures += 1;
+            }
+            if report.seed != seed || !format!("{}", report).contains(&format!("{:#010x}", seed)) {
+                println!("report doesn't name seed {:#010x}", seed);
+                failures += 1;
+            }
+            // Every operation takes a handful of ticks at most.
+            if report.operations < 100 {
+                println!("only {} operations ran", report.operations);
+                failures += 1;
+            }
+
+            println!("{} failures", failures);
+            unsafe { _exit(failures) }
+        })
+        .unwrap();
+    });
+}
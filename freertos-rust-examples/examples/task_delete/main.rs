@@ -0,0 +1,65 @@
+//! A supervisor deletes a runaway task with `TaskRemoteHandle::delete` and checks that it
+//! is gone from the task list and stopped running. The process exits with 0 if so.
+//!
+//!     cargo run --example task_delete --target x86_64-unknown-linux-gnu
+use freertos_rust::*;
+use std::sync::atomic::{AtomicU32, Ordering};
+
+#[global_allocator]
+static GLOBAL: FreeRtosAllocator = FreeRtosAllocator;
+
+static SPINS: AtomicU32 = AtomicU32::new(0);
+
+extern "C" {
+    // `std::process::exit` runs the exit handlers of the POSIX port, which cancel the
+    // threads the tasks run on while they are in Rust code.
+    fn _exit(status: i32) -> !;
+}
+
+/// `get_all_tasks` needs Rust and the C shim to agree on the kernel type sizes, which
+/// they don't with the 64-bit `BaseType_t` of the Linux port. The task count works
+/// everywhere.
+fn has_task(os: &FreeRTOS, name: &str) -> Option<bool> {
+    if shim_sanity_check().is_err() {
+        return None;
+    }
+    Some(os.get_all_tasks(None).tasks.iter().any(|t| t.name == name))
+}
+
+fn main() {
+    FreeRTOS::start_scheduler(|os| {
+        let runaway = os
+            .new_task("runaway", 256, TaskPriority(1), move |_, os| loop {
+                SPINS.fetch_add(1, Ordering::Relaxed);
+                os.delay(Duration::ticks(1));
+            })
+            .unwrap();
+
+        os.new_task("supervisor", 512, TaskPriority(3), move |_, os| {
+            os.delay(Duration::ticks(20));
+            let mut failures = 0;
+            if has_task(&os, "runaway") == Some(false) || SPINS.load(Ordering::Relaxed) == 0 {
+                println!("runaway task didn't run");
+                failures += 1;
+            }
+
+            let tasks = os.get_number_of_tasks();
+            unsafe { runaway.delete() };
+
+            if has_task(&os, "runaway") == Some(true) || os.get_number_of_tasks() != tasks - 1 {
+                println!("runaway task still listed");
+                failures += 1;
+            }
+            let spins = SPINS.load(Ordering::Relaxed);
+            os.delay(Duration::ticks(20));
+            if SPINS.load(Ordering::Relaxed) != spins {
+                println!("runaway task still running");
+                failures += 1;
+            }
+
+            println!("{} failures", failures);
+            unsafe { _exit(failures) }
+        })
+        .unwrap();
+    });
+}
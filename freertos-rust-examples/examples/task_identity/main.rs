@@ -0,0 +1,136 @@
+//! Compares task handles and looks up tasks by handle, and checks that:
+//!
+//! * two handles to the same task compare equal and hash the same, handles to different
+//!   tasks don't,
+//! * `FreeRTOS::current_task` in a spawned task equals the handle `new_task` returned,
+//! * task handles work as keys of a map of application data,
+//! * `get_task_number` is the same through every handle to a task, and grows with each
+//!   task created.
+//!
+//! The process exits with the number of failed checks.
+//!
+//!     cargo run --example task_identity --target x86_64-unknown-linux-gnu
+use freertos_rust::*;
+use std::collections::hash_map::DefaultHasher;
+use std::collections::HashMap;
+use std::hash::{Hash, Hasher};
+use std::sync::Arc;
+
+#[global_allocator]
+static GLOBAL: FreeRtosAllocator = FreeRtosAllocator;
+
+extern "C" {
+    // `std::process::exit` runs the exit handlers of the POSIX port, which cancel the
+    // threads the tasks run on while they are in Rust code.
+    fn _exit(status: i32) -> !;
+}
+
+fn hash_of(handle: &TaskRemoteHandle) -> u64 {
+    let mut hasher = DefaultHasher::new();
+    handle.hash(&mut hasher);
+    hasher.finish()
+}
+
+/// Spawn a task that reports what `current_task` says, and whether it equals its own
+/// handle. Handles aren't `Sync`, so the raw one is reported, as an address.
+fn spawn(os: FreeRTOS, name: &str, reports: &Arc<Mutex<Vec<(usize, bool)>>>) -> TaskRemoteHandle {
+    let reports = reports.clone();
+    os.new_task(name, 256, TaskPriority(1), move |task, os| {
+        let current = os.current_task();
+        let matches_self = current == task.new_remote_handle();
+        reports
+            .lock(Duration::infinite())
+            .unwrap()
+            .push((current.raw_handle() as usize, matches_self));
+        loop {
+            os.delay(Duration::infinite());
+        }
+    })
+    .unwrap()
+}
+
+fn main() {
+    FreeRTOS::start_scheduler(|os| {
+        os.new_task("checks", 512, TaskPriority(2), move |task, os| {
+            let mut failures = 0;
+
+            let reports = Arc::new(Mutex::new(os, Vec::new()).unwrap());
+            let first = spawn(os, "first", &reports);
+            let second = spawn(os, "second", &reports);
+            os.delay(Duration::ms(5));
+
+            let again = unsafe { TaskRemoteHandle::from_raw(first.raw_handle()) };
+            if first != again || hash_of(&first) != hash_of(&again) || first == second {
+                println!(
+                    "first == again {}, same hash {}, first == second {}",
+                    first == again,
+                    hash_of(&first) == hash_of(&again),
+                    first == second
+                );
+                failures += 1;
+            }
+            if os.current_task() != task.new_remote_handle() || os.current_task() == first {
+                println!("current_task of the checking task is wrong");
+                failures += 1;
+            }
+
+            {
+                let reports = reports.lock(Duration::zero()).unwrap();
+                let found: Vec<bool> = [&first, &second]
+                    .iter()
+                    .map(|spawned| {
+                        reports.iter().any(|&(raw, own)| {
+                            own && unsafe { TaskRemoteHandle::from_raw(raw as FreeRtosTaskHandle) }
+                                == **spawned
+                        })
+                    })
+                    .collect();
+                if reports.len() != 2 || found != [true, true] {
+                    println!(
+                        "{} reports, current_task matched the spawned handles {:?}",
+                        reports.len(),
+                        found
+                    );
+                    failures += 1;
+                }
+            }
+
+            let mut roles = HashMap::new();
+            roles.insert(
+                unsafe { TaskRemoteHandle::from_raw(first.raw_handle()) },
+                "sensor",
+            );
+            roles.insert(
+                unsafe { TaskRemoteHandle::from_raw(second.raw_handle()) },
+                "logger",
+            );
+            roles.insert(again, "sensor, again");
+            if roles.len() != 2
+                || roles.get(&first) != Some(&"sensor, again")
+                || roles.get(&second) != Some(&"logger")
+                || roles.get(&os.current_task()).is_some()
+            {
+                println!("task map: {:?}", roles.values().collect::<Vec<_>>());
+                failures += 1;
+            }
+
+            let numbers = (
+                os.current_task().get_task_number(),
+                task.get_task_number(),
+                first.get_task_number(),
+                second.get_task_number(),
+            );
+            if numbers.0 != numbers.1 || !(numbers.1 < numbers.2 && numbers.2 < numbers.3) {
+                println!(
+                    "task numbers: checks {} and {}, first {}, second {}",
+                    numbers.0, numbers.1, numbers.2, numbers.3
+                );
+                failures += 1;
+            }
+
+            println!("{} failures", failures);
+            unsafe { _exit(failures) }
+        })
+        .unwrap();
+    });
+}
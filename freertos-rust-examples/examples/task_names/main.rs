@@ -0,0 +1,149 @@
+//! Creates tasks, timers and queues with names at and over the kernel's limit, and checks
+//! that:
+//!
+//! * `max_name_len` is `configMAX_TASK_NAME_LEN` less the nul terminator,
+//! * a task or timer name of exactly `max_name_len` bytes is kept whole,
+//! * one byte more fails with `NameTooLong`, handing the task closure back, instead of
+//!   being cut short,
+//! * a name with a nul byte fails with `InvalidName`, also for a named queue,
+//! * `truncate_name` stops at the limit and at a nul byte, without splitting a char.
+//!
+//! The process exits with the number of failed checks.
+//!
+//!     cargo run --example task_names --target x86_64-unknown-linux-gnu
+use freertos_rust::*;
+
+#[global_allocator]
+static GLOBAL: FreeRtosAllocator = FreeRtosAllocator;
+
+extern "C" {
+    // `std::process::exit` runs the exit handlers of the POSIX port, which cancel the
+    // threads the tasks run on while they are in Rust code.
+    fn _exit(status: i32) -> !;
+}
+
+/// `configMAX_TASK_NAME_LEN` of the example's FreeRTOSConfig.h.
+const CONFIG_MAX_TASK_NAME_LEN: usize = 12;
+
+fn parked(_: &TaskSelfHandle, os: FreeRTOS) -> ! {
+    loop {
+        os.delay(Duration::infinite());
+    }
+}
+
+fn main() {
+    FreeRTOS::start_scheduler(|os| {
+        os.new_task("checks", 512, TaskPriority(2), move |_, os| {
+            let mut failures = 0;
+
+            let max = max_name_len();
+            if max != CONFIG_MAX_TASK_NAME_LEN - 1 {
+                println!("max_name_len {}", max);
+                failures += 1;
+            }
+            let exact = "x".repeat(max);
+            let over = "y".repeat(max + 1);
+
+            match os.new_task(&exact, 256, TaskPriority(1), parked) {
+                Ok(task) if task.get_name().as_deref() == Ok(exact.as_str()) => {}
+                Ok(task) => {
+                    println!("task with an exact length name is {:?}", task.get_name());
+                    failures += 1;
+                }
+                Err(e) => {
+                    println!("task with an exact length name: {:?}", e);
+                    failures += 1;
+                }
+            }
+
+            let captured = String::from("handed back");
+            match TaskRemoteHandle::try_new(os, &over, 256, TaskPriority(1), move |this, os| {
+                let _ = &captured;
+                parked(this, os)
+            }) {
+                Err(TaskSpawnError {
+                    error: FreeRtosError::NameTooLong,
+                    func,
+                }) => drop(func),
+                Err(e) => {
+                    println!("task with a name over the limit: {:?}", e);
+                    failures += 1;
+                }
+                Ok(task) => {
+                    println!("task with a name over the limit is {:?}", task.get_name());
+                    failures += 1;
+                }
+            }
+
+            let results = [
+                os.new_task("ab\0cd", 256, TaskPriority(1), parked).err(),
+                os.new_timer(Duration::ms(10))
+                    .set_name("ab\0cd")
+                    .create(|_| {})
+                    .err(),
+                Queue::<u32>::new_named(os, 1, "ab\0cd").err(),
+            ];
+            if results
+                .iter()
+                .any(|e| *e != Some(FreeRtosError::InvalidName))
+            {
+                println!("names with a nul byte: {:?}", results);
+                failures += 1;
+            }
+
+            match os
+                .new_timer(Duration::ms(10))
+                .set_name(&exact)
+                .create(|_| {})
+            {
+                Ok(timer) if timer.get_name().as_deref() == Ok(exact.as_str()) => {}
+                Ok(timer) => {
+                    println!("timer with an exact length name is {:?}", timer.get_name());
+                    failures += 1;
+                }
+                Err(e) => {
+                    println!("timer with an exact length name: {:?}", e);
+                    failures += 1;
+                }
+            }
+            match os
+                .new_timer(Duration::ms(10))
+                .set_name(&over)
+                .create(|_| {})
+            {
+                Err(FreeRtosError::NameTooLong) => {}
+                result => {
+                    println!(
+                        "timer with a name over the limit: {:?}",
+                        result.map(|timer| timer.get_name())
+                    );
+                    failures += 1;
+                }
+            }
+
+            let long_queue = "a queue name longer than a task name";
+            let queue = Queue::<u32>::new_named(os, 1, long_queue).unwrap();
+            if queue.registered_name().as_deref() != Some(long_queue) {
+                println!("queue registered as {:?}", queue.registered_name());
+                failures += 1;
+            }
+
+            // 'é' is two bytes, so a cut at an odd length would split one.
+            let truncated = [
+                truncate_name(&exact),
+                truncate_name(&over),
+                truncate_name("ab\0cd"),
+                truncate_name("éééééééé"),
+            ];
+            let expected = [exact.as_str(), &over[..max], "ab", "ééééé"];
+            if truncated != expected {
+                println!("truncate_name gave {:?}", truncated);
+                failures += 1;
+            }
+
+            println!("{} failures", failures);
+            unsafe { _exit(failures) }
+        })
+        .unwrap();
+    });
+}
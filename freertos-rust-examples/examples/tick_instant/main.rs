@@ -0,0 +1,91 @@
+//! Measures time with `TickInstant`, and checks that:
+//!
+//! * `saturating_duration_since` is the wrapping distance for instants straddling the
+//!   tick count wraparound, e.g. from `u32::MAX - 5` to `10`,
+//! * it is zero when the "earlier" instant is later, also across the wraparound,
+//! * `elapsed` after a delay is the delay, and `FreeRTOS::now` follows the tick count,
+//! * `TaskDelayPeriodic::should_run` fires once per period.
+//!
+//! The process exits with the number of failed checks.
+//!
+//!     cargo run --example tick_instant --target x86_64-unknown-linux-gnu
+use freertos_rust::*;
+
+#[global_allocator]
+static GLOBAL: FreeRtosAllocator = FreeRtosAllocator;
+
+extern "C" {
+    // `std::process::exit` runs the exit handlers of the POSIX port, which cancel the
+    // threads the tasks run on while they are in Rust code.
+    fn _exit(status: i32) -> !;
+}
+
+fn since(now: u32, earlier: u32) -> u32 {
+    TickInstant::from_ticks(now)
+        .saturating_duration_since(TickInstant::from_ticks(earlier))
+        .to_ticks()
+}
+
+fn main() {
+    FreeRTOS::start_scheduler(|os| {
+        os.new_task("checks", 512, TaskPriority(2), move |_, os| {
+            let mut failures = 0;
+
+            // (now, earlier, expected)
+            let cases = [
+                (10, u32::MAX - 5, 16),
+                (0, u32::MAX, 1),
+                (u32::MAX, u32::MAX - 1, 1),
+                (5, 5, 0),
+                (1000, 10, 990),
+                (u32::MAX - 5, 10, 0),
+                (u32::MAX, 0, 0),
+                (10, 1000, 0),
+            ];
+            for &(now, earlier, expected) in cases.iter() {
+                let got = since(now, earlier);
+                if got != expected {
+                    println!(
+                        "{} ticks from {} to {}, expected {}",
+                        got, earlier, now, expected
+                    );
+                    failures += 1;
+                }
+            }
+
+            let start = os.now();
+            os.delay(Duration::ms(20));
+            let elapsed = start.elapsed(&os).to_ticks();
+            let delay = Duration::ms(20).to_ticks();
+            let ticks = os.get_tick_count();
+            let now = os.now().ticks();
+            if elapsed < delay || elapsed > delay + 1 || now.wrapping_sub(ticks) > 1 {
+                println!(
+                    "elapsed {} ticks after a {} tick delay, now {} at tick count {}",
+                    elapsed, delay, now, ticks
+                );
+                failures += 1;
+            }
+
+            let mut periodic = TaskDelayPeriodic::new(os, Duration::ms(10));
+            let mut runs = 0;
+            for _ in 0..50 {
+                os.delay(Duration::ms(1));
+                if periodic.should_run() {
+                    runs += 1;
+                }
+            }
+            if !(4..=5).contains(&runs) {
+                println!(
+                    "should_run fired {} times in 50 ms with a 10 ms period",
+                    runs
+                );
+                failures += 1;
+            }
+
+            println!("{} failures", failures);
+            unsafe { _exit(failures) }
+        })
+        .unwrap();
+    });
+}
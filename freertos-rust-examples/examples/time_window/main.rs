@@ -0,0 +1,181 @@
+//! Restricts two housekeeping tasks to 5 tick windows every 20 ticks with an enforced
+//! `WindowGroup`, and checks from a cooperative group on the same windows that:
+//!
+//! * a busy member makes no progress outside its windows, and some in them,
+//! * a member blocked on a queue only receives what was sent while it was suspended
+//!   once its window opens,
+//! * `wait_for_window` returns within a tick of the window start,
+//! * window boundaries stay aligned across a tick counter wrap.
+//!
+//! The process exits with the number of failed checks.
+//!
+//!     cargo run --example time_window --target x86_64-unknown-linux-gnu
+use freertos_rust::*;
+use std::sync::atomic::{AtomicU32, Ordering};
+use std::sync::Arc;
+
+#[global_allocator]
+static GLOBAL: FreeRtosAllocator = FreeRtosAllocator;
+
+const OFFSET: u32 = 3;
+const DURATION: u32 = 5;
+const PERIOD: u32 = 20;
+const CYCLES: u32 = 10;
+
+static SPINS: AtomicU32 = AtomicU32::new(0);
+/// Tick at which the listener received, `u32::MAX` until it did.
+static RECEIVED_AT: AtomicU32 = AtomicU32::new(u32::MAX);
+
+extern "C" {
+    // `std::process::exit` runs the exit handlers of the POSIX port, which cancel the
+    // threads the tasks run on while they are in Rust code.
+    fn _exit(status: i32) -> !;
+}
+
+fn window() -> TimeWindow {
+    TimeWindow::new(
+        Duration::ticks(OFFSET),
+        Duration::ticks(DURATION),
+        Duration::ticks(PERIOD),
+    )
+}
+
+/// Boundaries computed from a window start just before the wrap have to match the ones
+/// computed without wrapping.
+fn check_wrap(failures: &mut i32) {
+    let window = window();
+    let start = 0u32.wrapping_sub(7 * PERIOD + 1);
+
+    for step in 0..20 * PERIOD {
+        let now = start.wrapping_add(step);
+        let phase = step % PERIOD;
+        let expected = if phase < DURATION {
+            WindowPhase::Open {
+                closes_in: DURATION - phase,
+            }
+        } else {
+            WindowPhase::Closed {
+                opens_in: PERIOD - phase,
+            }
+        };
+        if window.phase(start, now) != expected {
+            println!(
+                "tick {}: {:?}, expected {:?}",
+                now,
+                window.phase(start, now),
+                expected
+            );
+            *failures += 1;
+            return;
+        }
+    }
+}
+
+fn main() {
+    FreeRTOS::start_scheduler(|os| {
+        let enforced = Arc::new(
+            os.new_window_group(window(), WindowPolicy::Enforced)
+                .unwrap(),
+        );
+
+        let busy = os
+            .new_task("busy", 256, TaskPriority(1), move |_, _| loop {
+                SPINS.fetch_add(1, Ordering::Relaxed);
+            })
+            .unwrap();
+        enforced.add(&busy).unwrap();
+
+        let queue = Arc::new(os.new_queue::<u32>(1).unwrap());
+        let inbox = queue.clone();
+        let listener = os
+            .new_task("listener", 256, TaskPriority(2), move |_, os| {
+                if inbox.receive(Duration::infinite()).is_ok() {
+                    RECEIVED_AT.store(os.get_tick_count(), Ordering::Relaxed);
+                }
+                loop {
+                    os.delay(Duration::ms(10_000));
+                }
+            })
+            .unwrap();
+        enforced.add(&listener).unwrap();
+
+        os.new_task("checker", 512, TaskPriority(3), move |_, os| {
+            let mut failures = 0;
+            let cooperative = os
+                .new_window_group(window(), WindowPolicy::Cooperative)
+                .unwrap();
+
+            let mut late = 0;
+            let mut idle_progress = 0;
+            let mut open_without_progress = 0;
+            let mut closed_at = SPINS.load(Ordering::Relaxed);
+            for _ in 0..CYCLES {
+                cooperative
+                    .wait_for_window(Duration::ticks(PERIOD))
+                    .unwrap();
+                let start = window().first_start(os.get_tick_count());
+                if os.get_tick_count() - start > 1 {
+                    late += 1;
+                }
+
+                os.delay(Duration::ticks(DURATION + 1));
+                let open_spins = SPINS.load(Ordering::Relaxed);
+                if open_spins == closed_at {
+                    open_without_progress += 1;
+                }
+                os.delay(Duration::ticks(PERIOD - DURATION - 2));
+                closed_at = SPINS.load(Ordering::Relaxed);
+                if closed_at != open_spins {
+                    idle_progress += 1;
+                }
+            }
+            if late + idle_progress + open_without_progress != 0 {
+                println!(
+                    "{} late wake ups, {} closed windows with progress, {} open without",
+                    late, idle_progress, open_without_progress
+                );
+                failures += 1;
+            }
+
+            // Send to the listener while it is suspended.
+            cooperative
+                .wait_for_window(Duration::ticks(PERIOD))
+                .unwrap();
+            os.delay(Duration::ticks(DURATION + 2));
+            let sent_at = os.get_tick_count();
+            queue.send(1, Duration::zero()).unwrap();
+            os.delay(Duration::ticks(PERIOD));
+            let received_at = RECEIVED_AT.load(Ordering::Relaxed);
+            let opened_at = window().first_start(sent_at) + PERIOD;
+            if received_at < opened_at || received_at > opened_at + 1 {
+                println!(
+                    "sent at {}, received at {}, window opened at {}",
+                    sent_at, received_at, opened_at
+                );
+                failures += 1;
+            }
+
+            check_wrap(&mut failures);
+
+            // Out of the group, the busy task runs all the time. The group isn't dropped:
+            // that stops the enforcer, which the Linux port ends with `pthread_exit`, and
+            // that can't unwind through the Rust frames of the task.
+            enforced.remove(&listener).unwrap();
+            enforced.remove(&busy).unwrap();
+            cooperative
+                .wait_for_window(Duration::ticks(PERIOD))
+                .unwrap();
+            os.delay(Duration::ticks(DURATION + 1));
+            let spins = SPINS.load(Ordering::Relaxed);
+            os.delay(Duration::ticks(PERIOD - DURATION - 2));
+            if SPINS.load(Ordering::Relaxed) == spins {
+                println!("busy task still suspended after it left the group");
+                failures += 1;
+            }
+
+            println!("{} failures", failures);
+            unsafe { _exit(failures) }
+        })
+        .unwrap();
+    });
+}
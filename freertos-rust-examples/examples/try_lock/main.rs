@@ -0,0 +1,85 @@
+//! Locks mutexes with `try_lock`, and checks that:
+//!
+//! * a free mutex is locked,
+//! * a mutex held by another task isn't, and `try_lock` returns `None` without waiting,
+//! * the mutex is locked again once the other task released it,
+//! * a recursive mutex held by the calling task is locked again.
+//!
+//! The process exits with the number of failed checks.
+//!
+//!     cargo run --example try_lock --target x86_64-unknown-linux-gnu
+use freertos_rust::*;
+use std::sync::Arc;
+
+#[global_allocator]
+static GLOBAL: FreeRtosAllocator = FreeRtosAllocator;
+
+extern "C" {
+    // `std::process::exit` runs the exit handlers of the POSIX port, which cancel the
+    // threads the tasks run on while they are in Rust code.
+    fn _exit(status: i32) -> !;
+}
+
+fn main() {
+    FreeRTOS::start_scheduler(|os| {
+        os.new_task("checks", 512, TaskPriority(2), move |_, os| {
+            let mut failures = 0;
+
+            let mutex = Arc::new(os.new_mutex(0u32).unwrap());
+            match mutex.try_lock() {
+                Some(mut value) => *value += 1,
+                None => {
+                    println!("a free mutex wasn't locked");
+                    failures += 1;
+                }
+            }
+
+            // The holder runs first, and holds the mutex while it waits.
+            let m = mutex.clone();
+            os.new_task("holder", 256, TaskPriority(3), move |_, os| {
+                {
+                    let mut value = m.lock(Duration::infinite()).unwrap();
+                    *value += 1;
+                    os.delay(Duration::ms(50));
+                }
+                loop {
+                    os.delay(Duration::infinite());
+                }
+            })
+            .unwrap();
+
+            let start = os.get_tick_count();
+            if mutex.try_lock().is_some() {
+                println!("a mutex held by another task was locked");
+                failures += 1;
+            }
+            if os.get_tick_count() != start {
+                println!("try_lock waited {} ticks", os.get_tick_count() - start);
+                failures += 1;
+            }
+
+            os.delay(Duration::ms(100));
+            match mutex.try_lock() {
+                Some(value) if *value == 2 => {}
+                value => {
+                    println!("a released mutex: {:?}", value.as_deref());
+                    failures += 1;
+                }
+            }
+
+            let recursive = os.new_recursive_mutex(0u32).unwrap();
+            let outer = recursive.lock(Duration::infinite()).unwrap();
+            let inner = recursive.try_lock();
+            if inner.is_none() {
+                println!("a recursive mutex held by the task wasn't locked again");
+                failures += 1;
+            }
+            drop(inner);
+            drop(outer);
+
+            println!("{} failures", failures);
+            unsafe { _exit(failures) }
+        })
+        .unwrap();
+    });
+}
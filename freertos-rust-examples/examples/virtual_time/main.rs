@@ -0,0 +1,111 @@
+//! Runs seconds of delays in deterministic virtual time: two periodic tasks check that
+//! they wake at exactly their period, and an ISR scheduled 500 ms ahead checks that it
+//! runs at exactly that tick. It then checks that the delays took a fraction of the
+//! time on the wall clock.
+//!
+//! The process exits with the number of failed checks.
+//!
+//!     cargo run --example virtual_time --features test_support --target x86_64-unknown-linux-gnu
+use freertos_rust::test_support::*;
+use freertos_rust::*;
+use std::sync::atomic::{AtomicU32, Ordering};
+use std::time::Instant;
+
+#[global_allocator]
+static GLOBAL: FreeRtosAllocator = FreeRtosAllocator;
+
+extern "C" {
+    // `std::process::exit` runs the exit handlers of the POSIX port, which cancel the
+    // threads the tasks run on while they are in Rust code.
+    fn _exit(status: i32) -> !;
+}
+
+const PERIODS: u32 = 200;
+
+static FAILED: AtomicU32 = AtomicU32::new(0);
+static DONE: AtomicU32 = AtomicU32::new(0);
+/// The tick the scheduled ISR ran at, plus one so zero means it didn't run.
+static ISR_RAN_AT: AtomicU32 = AtomicU32::new(0);
+
+fn check(ok: bool, what: &str) {
+    if !ok {
+        println!("failed: {}", what);
+        FAILED.fetch_add(1, Ordering::SeqCst);
+    }
+}
+
+fn isr(_context: &mut InterruptContext) {
+    let now = unsafe { freertos_rs_xTaskGetTickCountFromISR() };
+    ISR_RAN_AT.store(now + 1, Ordering::SeqCst);
+}
+
+/// Wake `PERIODS` times every `period_ms`, checking each wake-up is on time.
+fn periodic(os: FreeRTOS, period_ms: u32) {
+    let mut delay = os.new_delay();
+    let mut expected = os.get_tick_count();
+    let mut late = 0;
+    for _ in 0..PERIODS {
+        delay.delay_until(Duration::ms(period_ms));
+        expected += Duration::ms(period_ms).to_ticks();
+        if os.get_tick_count() != expected {
+            late += 1;
+        }
+    }
+    check(late == 0, "periodic tasks woke on time");
+    DONE.fetch_add(1, Ordering::SeqCst);
+}
+
+fn main() {
+    enable_deterministic_virtual_time();
+
+    FreeRTOS::start_scheduler(|os| {
+        os.new_task("checks", 512, TaskPriority(3), move |_, os| {
+            check(is_virtual_time(), "virtual time is enabled");
+            let started = Instant::now();
+            let virtual_start = virtual_now();
+
+            os.new_task("fast", 256, TaskPriority(2), move |_, os| {
+                periodic(os, 10);
+                loop {
+                    os.delay(Duration::infinite());
+                }
+            })
+            .unwrap();
+            os.new_task("slow", 256, TaskPriority(1), move |_, os| {
+                periodic(os, 15);
+                loop {
+                    os.delay(Duration::infinite());
+                }
+            })
+            .unwrap();
+
+            let at = schedule_isr(Duration::ms(500), isr).unwrap();
+            check(
+                at == virtual_start.to_ticks() + Duration::ms(500).to_ticks(),
+                "the ISR is scheduled 500 ms ahead",
+            );
+            os.delay(Duration::ms(600));
+            check(
+                ISR_RAN_AT.load(Ordering::SeqCst) == at + 1,
+                "the ISR ran at its tick",
+            );
+
+            while DONE.load(Ordering::SeqCst) < 2 {
+                os.delay(Duration::ms(100));
+            }
+            let elapsed = virtual_now().to_ms() - virtual_start.to_ms();
+            let wall = started.elapsed().as_millis() as u32;
+            println!("{} ms of virtual time in {} ms", elapsed, wall);
+            check(elapsed >= 15 * PERIODS, "the delays passed");
+            check(
+                wall < elapsed / 4,
+                "virtual time ran ahead of the wall clock",
+            );
+
+            let failed = FAILED.load(Ordering::SeqCst);
+            println!("{} failed", failed);
+            unsafe { _exit(failed as i32) }
+        })
+        .unwrap();
+    });
+}
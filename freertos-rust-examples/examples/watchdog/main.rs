@@ -0,0 +1,135 @@
+//! Registers tasks with a `TaskWatchdog`, and checks that:
+//!
+//! * a task that keeps feeding its handle is never reported,
+//! * a task that stops feeding is reported once, by name, within a tick or two of its
+//!   deadline, and once more when it recovers and then stops again,
+//! * a task that unregistered is not reported, and isn't counted any more.
+//!
+//! The process exits with the number of failed checks.
+//!
+//!     cargo run --example watchdog --target x86_64-unknown-linux-gnu
+use freertos_rust::*;
+use std::sync::atomic::{AtomicU32, Ordering};
+
+#[global_allocator]
+static GLOBAL: FreeRtosAllocator = FreeRtosAllocator;
+
+const PERIOD_MS: u32 = 20;
+
+static FEEDER_REPORTS: AtomicU32 = AtomicU32::new(0);
+static STALLER_REPORTS: AtomicU32 = AtomicU32::new(0);
+static QUITTER_REPORTS: AtomicU32 = AtomicU32::new(0);
+static OTHER_REPORTS: AtomicU32 = AtomicU32::new(0);
+/// The tick the staller stopped feeding at, and the one it was first reported at.
+static STALLED_AT: AtomicU32 = AtomicU32::new(0);
+static REPORTED_AT: AtomicU32 = AtomicU32::new(0);
+
+extern "C" {
+    // `std::process::exit` runs the exit handlers of the POSIX port, which cancel the
+    // threads the tasks run on while they are in Rust code.
+    fn _exit(status: i32) -> !;
+}
+
+fn park(os: FreeRTOS) -> ! {
+    loop {
+        os.delay(Duration::infinite());
+    }
+}
+
+fn main() {
+    FreeRTOS::start_scheduler(|os| {
+        os.new_task("checks", 512, TaskPriority(3), move |_, os| {
+            let mut failures = 0;
+
+            let watchdog = TaskWatchdog::start_monitor(os, TaskPriority(4), |name| {
+                let counter = match name {
+                    "feeder" => &FEEDER_REPORTS,
+                    "staller" => &STALLER_REPORTS,
+                    "quitter" => &QUITTER_REPORTS,
+                    _ => &OTHER_REPORTS,
+                };
+                if name == "staller" && REPORTED_AT.load(Ordering::SeqCst) == 0 {
+                    REPORTED_AT.store(unsafe { freertos_rs_xTaskGetTickCount() }, Ordering::SeqCst);
+                }
+                counter.fetch_add(1, Ordering::SeqCst);
+            })
+            .unwrap();
+
+            let dog = watchdog.clone();
+            os.new_task("feeder", 256, TaskPriority(2), move |task, os| {
+                let handle = dog.register(task, Duration::ms(PERIOD_MS)).unwrap();
+                loop {
+                    handle.feed();
+                    os.delay(Duration::ms(PERIOD_MS / 4));
+                }
+            })
+            .unwrap();
+
+            let dog = watchdog.clone();
+            os.new_task("staller", 256, TaskPriority(2), move |task, os| {
+                let handle = dog.register(task, Duration::ms(PERIOD_MS)).unwrap();
+                for _ in 0..5 {
+                    handle.feed();
+                    os.delay(Duration::ms(PERIOD_MS / 4));
+                }
+                handle.feed();
+                STALLED_AT.store(os.get_tick_count(), Ordering::SeqCst);
+                // Stuck for five periods, then back for a while, then stuck again.
+                os.delay(Duration::ms(PERIOD_MS * 5));
+                for _ in 0..5 {
+                    handle.feed();
+                    os.delay(Duration::ms(PERIOD_MS / 4));
+                }
+                park(os)
+            })
+            .unwrap();
+
+            let dog = watchdog.clone();
+            os.new_task("quitter", 256, TaskPriority(2), move |task, os| {
+                let handle = dog.register(task, Duration::ms(PERIOD_MS)).unwrap();
+                handle.feed();
+                handle.unregister();
+                park(os)
+            })
+            .unwrap();
+
+            os.delay(Duration::ms(PERIOD_MS * 20));
+
+            let reports = (
+                FEEDER_REPORTS.load(Ordering::SeqCst),
+                STALLER_REPORTS.load(Ordering::SeqCst),
+                QUITTER_REPORTS.load(Ordering::SeqCst),
+                OTHER_REPORTS.load(Ordering::SeqCst),
+            );
+            if reports != (0, 2, 0, 0) {
+                println!(
+                    "reports: feeder {}, staller {}, quitter {}, other names {}",
+                    reports.0, reports.1, reports.2, reports.3
+                );
+                failures += 1;
+            }
+
+            let late = tick_elapsed(
+                REPORTED_AT.load(Ordering::SeqCst),
+                STALLED_AT.load(Ordering::SeqCst),
+            );
+            let deadline = Duration::ms(PERIOD_MS).to_ticks();
+            if late <= deadline || late > deadline + 2 {
+                println!(
+                    "staller reported {} ticks after it stopped feeding, the period is {}",
+                    late, deadline
+                );
+                failures += 1;
+            }
+
+            if watchdog.registered() != 2 {
+                println!("{} tasks registered, expected 2", watchdog.registered());
+                failures += 1;
+            }
+
+            println!("{} failures", failures);
+            unsafe { _exit(failures) }
+        })
+        .unwrap();
+    });
+}
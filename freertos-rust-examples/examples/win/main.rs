@@ -13,12 +13,12 @@ fn main() {
     }
 
     println!("Starting scheduler");
-    FreeRTOS::start_scheduler(|os| {
+    let error = FreeRTOS::try_start_scheduler(|os| {
         //println!("Calling assert ...");
         //FreeRtosUtils::invoke_assert();
 
         println!("Starting FreeRTOS app ...");
-        let value = Arc::new(os.new_mutex(0).unwrap());
+        let value = Arc::new(os.new_mutex(0)?);
 
         {
             let value = value.clone();
@@ -31,8 +31,7 @@ fn main() {
                     println!("A: {}", *value);
                 }
                 os.delay(Duration::ms(1000));
-            })
-            .unwrap();
+            })?;
         }
 
         os.new_task("B", 128, TaskPriority(3), move |_self_handle, os| loop {
@@ -44,17 +43,18 @@ fn main() {
                 println!("B: {}", *value);
             }
             os.delay(Duration::ms(1000));
-        })
-        .unwrap();
+        })?;
         println!("Task registered");
         //let free = freertos_rs_xPortGetFreeHeapSize();
         // println!("Free Memory: {}!", free);
+        Ok(())
     });
+    println!("Scheduler failed to start: {}", error);
 }
 
 #[test]
 fn many_boxes() {
-    init_allocator();
+    allocator::init();
     println!("many_boxes... ");
     for i in 0..10 {
         // .. HEAP_SIZE
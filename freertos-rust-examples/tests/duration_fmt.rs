@@ -0,0 +1,115 @@
+//! Formatting durations: the adaptive units of `Display`, the fixed width column of
+//! `display_compact`, and the conversion from ticks at other tick rates.
+//!
+//!     cargo test --test duration_fmt --features hosted_tests --target x86_64-unknown-linux-gnu
+use freertos_rust::*;
+
+#[global_allocator]
+static GLOBAL: FreeRtosAllocator = FreeRtosAllocator;
+
+/// A 100 Hz tick.
+#[derive(Copy, Clone, Debug)]
+struct TenMsTicks;
+
+impl FreeRtosTimeUnits for TenMsTicks {
+    fn get_tick_period_ms() -> u32 {
+        10
+    }
+    fn get_max_wait() -> u32 {
+        u32::MAX
+    }
+}
+
+/// A 100 Hz tick with the 16 bit `TickType_t` of `configUSE_16_BIT_TICKS`.
+#[derive(Copy, Clone, Debug)]
+struct SixteenBitTicks;
+
+impl FreeRtosTimeUnits for SixteenBitTicks {
+    fn get_tick_period_ms() -> u32 {
+        10
+    }
+    fn get_max_wait() -> u32 {
+        0xffff
+    }
+}
+
+#[test]
+fn adaptive_units() {
+    // The simulator ticks at 1 kHz.
+    let cases = [
+        (Duration::zero(), "0ms"),
+        (Duration::eps(), "1ms"),
+        (Duration::ms(350), "350ms"),
+        (Duration::ms(999), "999ms"),
+        (Duration::ms(1000), "1s"),
+        (Duration::ms(2500), "2.5s"),
+        (Duration::ms(2599), "2.5s"),
+        (Duration::ms(59_999), "59.9s"),
+        (Duration::ms(60_000), "1m00s"),
+        (Duration::ms(65_000), "1m05s"),
+        (Duration::ms(3_599_999), "59m59s"),
+        (Duration::ms(7_800_000), "2h10m"),
+        (Duration::infinite(), "∞"),
+        (Duration::ticks(Duration::infinite().to_ticks() - 1), "1193h02m"),
+    ];
+    for (duration, expected) in cases.iter() {
+        assert_eq!(&format!("{}", duration), expected, "{:?}", duration);
+    }
+}
+
+#[test]
+fn padding() {
+    assert_eq!(format!("{:>7}|", Duration::ms(350)), "  350ms|");
+    assert_eq!(format!("{:<7}|", Duration::ms(2500)), "2.5s   |");
+    assert_eq!(format!("{:^3}|", Duration::infinite()), " ∞ |");
+}
+
+#[test]
+fn compact() {
+    let cases = [
+        (Duration::ms(350), "   350ms"),
+        (Duration::ms(2500), "    2.5s"),
+        (Duration::ms(65_000), "   1m05s"),
+        (Duration::infinite(), "       ∞"),
+    ];
+    for (duration, expected) in cases.iter() {
+        let text = format!("{}", duration.display_compact());
+        assert_eq!(&text, expected);
+        assert_eq!(
+            text.chars().count(),
+            CompactDuration::<FreeRtosTimeUnitsShimmed>::WIDTH
+        );
+    }
+}
+
+#[test]
+fn tick_rate_conversion() {
+    let cases = [
+        (DurationImpl::<TenMsTicks>::ticks(35), "350ms"),
+        (DurationImpl::<TenMsTicks>::ticks(250), "2.5s"),
+        (DurationImpl::<TenMsTicks>::ticks(6500), "1m05s"),
+        (DurationImpl::<TenMsTicks>::infinite(), "∞"),
+    ];
+    for (duration, expected) in cases.iter() {
+        assert_eq!(&format!("{}", duration), expected, "{:?}", duration);
+    }
+
+    // Milliseconds are rounded down to whole ticks, so less than a tick is none.
+    assert_eq!(DurationImpl::<TenMsTicks>::ms(2500).to_ticks(), 250);
+    assert_eq!(DurationImpl::<TenMsTicks>::ms(9).to_ticks(), 0);
+    assert_eq!(format!("{}", DurationImpl::<TenMsTicks>::ms(9)), "0ms");
+    assert_eq!(format!("{}", DurationImpl::<TenMsTicks>::ms(19)), "10ms");
+
+    // The longest finite wait of a 16 bit tick, and its infinite one.
+    assert_eq!(
+        format!("{}", DurationImpl::<SixteenBitTicks>::ticks(0xfffe)),
+        "10m55s"
+    );
+    assert_eq!(format!("{}", DurationImpl::<SixteenBitTicks>::infinite()), "∞");
+
+    // Without overflowing, at the widest tick count and slowest tick.
+    assert_eq!(
+        format!("{}", DurationImpl::<TenMsTicks>::ticks(u32::MAX - 1)),
+        "11930h27m"
+    );
+}
@@ -0,0 +1,76 @@
+//! The reporting that works without the `fmt` feature: the numeric codes of
+//! `FreeRtosError`, which must never change, and task names as bytes.
+//!
+//!     cargo test --test fmt_free --features hosted_tests --target x86_64-unknown-linux-gnu
+use freertos_rust::freertos_test::run_freertos_test;
+use freertos_rust::*;
+
+#[global_allocator]
+static GLOBAL: FreeRtosAllocator = FreeRtosAllocator;
+
+/// Every code ever given out. Codes are logged and compared by tools outside the crate,
+/// so a variant keeps its code and new variants take new ones.
+const CODES: &[(FreeRtosError, u8)] = &[
+    (FreeRtosError::OutOfMemory, 1),
+    (FreeRtosError::QueueSendTimeout, 2),
+    (FreeRtosError::QueueReceiveTimeout, 3),
+    (FreeRtosError::MutexTimeout, 4),
+    (FreeRtosError::Timeout, 5),
+    (FreeRtosError::QueueFull, 6),
+    (FreeRtosError::StringConversionError, 7),
+    (FreeRtosError::TaskNotFound, 8),
+    (FreeRtosError::InvalidQueueSize, 9),
+    (FreeRtosError::ProcessorHasShutDown, 10),
+    (FreeRtosError::Emergency, 11),
+    (FreeRtosError::StorageInUse, 12),
+    (FreeRtosError::RegistryFull(RegistryKind::HandleTable), 13),
+    (FreeRtosError::TaskNotBlocked, 14),
+    (FreeRtosError::RendezvousSendTimeout, 15),
+    (FreeRtosError::RendezvousReceiveTimeout, 16),
+    (FreeRtosError::NameTooLong, 17),
+    (FreeRtosError::InvalidName, 18),
+    (FreeRtosError::StackTooLarge, 19),
+    (FreeRtosError::TooManyRegions, 21),
+    (FreeRtosError::InQueueSet, 22),
+    (FreeRtosError::QueueSetMemberNotEmpty, 23),
+    (FreeRtosError::InterruptAlreadyEnabled, 24),
+];
+
+#[test]
+fn error_codes() {
+    for (error, code) in CODES.iter() {
+        assert_eq!(error.code(), *code, "{:?}", error);
+    }
+    assert_eq!(
+        FreeRtosError::MutexTimeoutHeldBy(TaskName::from_bytes(b"holder")).code(),
+        20
+    );
+
+    // The payload doesn't change the code.
+    assert_eq!(
+        FreeRtosError::RegistryFull(RegistryKind::Infrastructure).code(),
+        FreeRtosError::RegistryFull(RegistryKind::CmsisObjects).code()
+    );
+}
+
+#[test]
+fn name_bytes() {
+    run_freertos_test(|os| {
+        let current = os.current_task();
+        assert_eq!(
+            current.get_name_bytes(),
+            current.get_name().unwrap().as_bytes()
+        );
+
+        // Up to the longest name the simulator keeps, without the nul.
+        for name in ["n", "eleven_char"].iter() {
+            let task = os
+                .new_task(name, 256, TaskPriority(1), |_, os| loop {
+                    os.delay(Duration::infinite());
+                })
+                .unwrap();
+            assert_eq!(task.get_name_bytes(), name.as_bytes());
+            assert_eq!(task.get_name(), Ok(name.to_string()));
+        }
+    });
+}
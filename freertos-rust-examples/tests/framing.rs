@@ -0,0 +1,97 @@
+//! Framed packets over a byte queue on the simulator, with each codec: payloads round
+//! trip, the receiver resynchronizes after corrupted and oversized frames, and sends and
+//! receives time out on a full or empty link.
+//!
+//!     cargo test --test framing --features hosted_tests --target x86_64-unknown-linux-gnu
+use freertos_rust::freertos_test::run_freertos_test;
+use freertos_rust::*;
+use std::sync::Arc;
+
+#[global_allocator]
+static GLOBAL: FreeRtosAllocator = FreeRtosAllocator;
+
+/// One end of a byte queue, so the sender and receiver can share it.
+#[derive(Clone)]
+struct Link(Arc<Queue<u8>>);
+
+impl ByteTransport for Link {
+    fn write(&self, bytes: &[u8], max_wait: Duration) -> Result<(), FreeRtosError> {
+        ByteTransport::write(&*self.0, bytes, max_wait)
+    }
+
+    fn read(&self, buf: &mut [u8], max_wait: Duration) -> Result<usize, FreeRtosError> {
+        ByteTransport::read(&*self.0, buf, max_wait)
+    }
+}
+
+/// Check `codec`, with `corrupted` a frame its decoder drops.
+fn check_codec<C: FrameCodec + Copy>(os: FreeRTOS, codec: C, corrupted: &[u8]) {
+    let link = Link(Arc::new(os.new_queue::<u8>(1024).unwrap()));
+    let mut sender = FramedSender::new(codec, link.clone(), 300);
+    let mut receiver = FramedReceiver::new(codec, link.clone(), 300);
+    let mut out = [0; 300];
+
+    // Zeros for COBS, END and ESC for SLIP, the sync byte for the length framing, and a
+    // payload longer than a COBS block.
+    let long = [0x5a; 300];
+    let payloads: [&[u8]; 4] = [b"abc", &[0, 1, 0, 0], &[0xc0, 0xdb, 0xa5, 0xdc], &long];
+    for payload in payloads.iter() {
+        sender.send(payload, Duration::zero()).unwrap();
+        let len = receiver.recv(&mut out, Duration::zero()).unwrap();
+        assert_eq!(&out[..len], *payload);
+    }
+    assert_eq!((receiver.frames(), receiver.errors()), (4, 0));
+
+    // A corrupted frame is dropped and the next one received.
+    ByteTransport::write(&link, corrupted, Duration::zero()).unwrap();
+    sender.send(b"world", Duration::zero()).unwrap();
+    let len = receiver.recv(&mut out, Duration::zero()).unwrap();
+    assert_eq!(&out[..len], b"world");
+    assert_eq!((receiver.frames(), receiver.errors()), (5, 1));
+
+    // So is one longer than the receiver's frames, or the caller's buffer.
+    let mut small = FramedReceiver::new(codec, link.clone(), 4);
+    sender.send(b"too long", Duration::zero()).unwrap();
+    sender.send(b"ok", Duration::zero()).unwrap();
+    let len = small.recv(&mut out, Duration::zero()).unwrap();
+    assert_eq!(&out[..len], b"ok");
+    assert_eq!((small.frames(), small.errors()), (1, 1));
+    sender.send(b"four", Duration::zero()).unwrap();
+    assert_eq!(
+        small.recv(&mut out[..3], Duration::zero()),
+        Err(FrameError::BufferTooSmall)
+    );
+    assert_eq!(small.errors(), 2);
+    assert_eq!(
+        sender.send(&[1; 301], Duration::zero()),
+        Err(FrameError::TooLong)
+    );
+
+    // Nothing to receive times out.
+    let start = os.get_tick_count();
+    assert_eq!(
+        receiver.recv(&mut out, Duration::ms(20)),
+        Err(FrameError::Transport(FreeRtosError::QueueReceiveTimeout))
+    );
+    assert!(os.get_tick_count() - start >= 20);
+
+    // Neither does a frame that doesn't fit the link.
+    let narrow = Link(Arc::new(os.new_queue::<u8>(4).unwrap()));
+    let mut sender = FramedSender::new(codec, narrow, 300);
+    assert_eq!(
+        sender.send(b"longer than the queue", Duration::ms(5)),
+        Err(FrameError::Transport(FreeRtosError::QueueSendTimeout))
+    );
+}
+
+#[test]
+fn framing() {
+    run_freertos_test(|os| {
+        // A block cut short.
+        check_codec(os, Cobs, &[5, b'a', b'b', 0]);
+        // An escape of a byte that needs none.
+        check_codec(os, Slip, &[0xc0, 0xdb, 0x01, 0xc0]);
+        // A wrong CRC.
+        check_codec(os, LengthCrc16, &[0xa5, 2, 0, b'h', b'i', 0, 0]);
+    });
+}
@@ -0,0 +1,136 @@
+//! Handle tables on the simulator: ids resolve to their objects only as the kind and item
+//! size they were registered with, revoked and reused ids fail, revoking waits for
+//! resolved references, and the `frrs_*` C functions report the same errors as codes.
+//!
+//!     cargo test --test handle_table --features hosted_tests,c_api --target x86_64-unknown-linux-gnu
+use freertos_rust::freertos_test::run_freertos_test;
+use freertos_rust::*;
+use std::sync::Arc;
+
+#[global_allocator]
+static GLOBAL: FreeRtosAllocator = FreeRtosAllocator;
+
+#[test]
+fn handle_table() {
+    run_freertos_test(|os| {
+        let table: &'static HandleTable = Box::leak(Box::new(HandleTable::new(os, 2)));
+        let queue = os.new_queue::<u32>(4).unwrap();
+        let semaphore = os.new_binary_semaphore().unwrap();
+        let queue_id = unsafe { table.register(&queue) }.unwrap();
+        let semaphore_id = unsafe { table.register(&semaphore) }.unwrap();
+
+        // Resolved as what they are, they work, timeouts included.
+        {
+            let queue = table.resolve_queue::<u32>(queue_id).unwrap();
+            queue.send(7, Duration::zero()).unwrap();
+            assert_eq!(queue.receive(Duration::zero()), Ok(7));
+            assert_eq!(
+                queue.receive(Duration::ms(5)),
+                Err(FreeRtosError::QueueReceiveTimeout)
+            );
+            let semaphore = table.resolve_semaphore(semaphore_id).unwrap();
+            assert_eq!(semaphore.take(Duration::ms(5)), Err(FreeRtosError::Timeout));
+            semaphore.give();
+            assert_eq!(semaphore.take(Duration::zero()), Ok(()));
+        }
+
+        // Resolved as anything else, they don't.
+        assert_eq!(
+            table.resolve_semaphore(queue_id).err(),
+            Some(HandleError::WrongType)
+        );
+        assert_eq!(
+            table.resolve_queue::<u32>(semaphore_id).err(),
+            Some(HandleError::WrongType)
+        );
+        assert_eq!(
+            table.resolve_queue::<u16>(queue_id).err(),
+            Some(HandleError::ItemSize)
+        );
+        for raw in [0, (5 << 16) | 99] {
+            assert_eq!(
+                table.resolve_semaphore(ObjectId::from_raw(raw)).err(),
+                Some(HandleError::Invalid)
+            );
+        }
+        let spare = os.new_binary_semaphore().unwrap();
+        assert_eq!(
+            unsafe { table.register(&spare) }.err(),
+            Some(HandleError::Full)
+        );
+
+        // Revoking waits for the reference another task resolved.
+        let resolved = Arc::new(os.new_binary_semaphore().unwrap());
+        let r = resolved.clone();
+        os.new_task("user", 256, TaskPriority(1), move |_, os| {
+            let semaphore = table.resolve_semaphore(semaphore_id).unwrap();
+            r.give();
+            os.delay(Duration::ms(30));
+            drop(semaphore);
+            loop {
+                os.delay(Duration::infinite());
+            }
+        })
+        .unwrap();
+        resolved.take(Duration::ms(100)).unwrap();
+        let start = os.get_tick_count();
+        assert_eq!(table.revoke(semaphore_id), Ok(()));
+        assert!(os.get_tick_count() - start >= 25);
+        assert_eq!(
+            table.resolve_semaphore(semaphore_id).err(),
+            Some(HandleError::Revoked)
+        );
+        assert_eq!(table.revoke(semaphore_id), Err(HandleError::Revoked));
+
+        // Its slot is reused, with a new generation.
+        let spare_id = unsafe { table.register(&spare) }.unwrap();
+        assert_eq!(spare_id.index(), semaphore_id.index());
+        assert_ne!(spare_id, semaphore_id);
+        assert_eq!(
+            table.resolve_semaphore(semaphore_id).err(),
+            Some(HandleError::Stale)
+        );
+
+        // The C functions, over the same table.
+        let (queue_raw, spare_raw) = (queue_id.as_raw(), spare_id.as_raw());
+        let mut item = 0u32;
+        unsafe {
+            assert_eq!(frrs_semaphore_give(spare_raw), c_api_status::NO_TABLE);
+            set_c_api_table(table);
+
+            let sent = 42u32;
+            assert_eq!(
+                frrs_queue_send(queue_raw, &sent as *const u32 as *const u8, 4, 0),
+                c_api_status::OK
+            );
+            assert_eq!(
+                frrs_queue_receive(queue_raw, &mut item as *mut u32 as *mut u8, 4, 0),
+                c_api_status::OK
+            );
+            assert_eq!(item, 42);
+            assert_eq!(
+                frrs_queue_receive(queue_raw, &mut item as *mut u32 as *mut u8, 4, 5),
+                c_api_status::TIMEOUT
+            );
+            assert_eq!(
+                frrs_queue_send(queue_raw, &sent as *const u32 as *const u8, 2, 0),
+                c_api_status::ITEM_SIZE
+            );
+            assert_eq!(frrs_semaphore_take(queue_raw, 0), c_api_status::WRONG_TYPE);
+            assert_eq!(frrs_semaphore_give(spare_raw), c_api_status::OK);
+            assert_eq!(frrs_semaphore_take(spare_raw, 0), c_api_status::OK);
+            assert_eq!(frrs_semaphore_take(spare_raw, 5), c_api_status::TIMEOUT);
+            assert_eq!(
+                frrs_semaphore_give(semaphore_id.as_raw()),
+                c_api_status::STALE
+            );
+            assert_eq!(frrs_semaphore_give(0), c_api_status::INVALID);
+        }
+        table.revoke(queue_id).unwrap();
+        assert_eq!(
+            unsafe { frrs_queue_receive(queue_raw, &mut item as *mut u32 as *mut u8, 4, 0) },
+            c_api_status::REVOKED
+        );
+        table.revoke(spare_id).unwrap();
+    });
+}
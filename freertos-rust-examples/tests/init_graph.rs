@@ -0,0 +1,121 @@
+//! Init graphs on the simulator: declaration errors, nodes running in dependency order on
+//! several workers, a failed node skipping its dependents, nodes timing out, and a
+//! fail-fast graph skipping everything after the first failure.
+//!
+//! The workers delete themselves once a graph is done, which the simulator can't do, so
+//! each graph ends with one node per worker that never returns and times out.
+//!
+//!     cargo test --test init_graph --features hosted_tests --target x86_64-unknown-linux-gnu
+use freertos_rust::freertos_test::run_freertos_test;
+use freertos_rust::*;
+use std::sync::Arc;
+
+#[global_allocator]
+static GLOBAL: FreeRtosAllocator = FreeRtosAllocator;
+
+fn ok(_: &InitCtx) -> Result<(), InitError> {
+    Ok(())
+}
+
+fn hang(ctx: &InitCtx) -> Result<(), InitError> {
+    loop {
+        ctx.os().delay(Duration::infinite());
+    }
+}
+
+/// A runner noting that it started.
+fn record(
+    started: &Arc<Mutex<Vec<&'static str>>>,
+) -> impl FnOnce(&InitCtx) -> Result<(), InitError> + Send + 'static {
+    let started = started.clone();
+    move |ctx| {
+        started.lock(Duration::infinite()).unwrap().push(ctx.name());
+        Ok(())
+    }
+}
+
+#[test]
+fn init_graph() {
+    run_freertos_test(|os| {
+        // Declaration errors.
+        let mut graph = InitGraph::new(3);
+        graph.node("a", &["b"], ok).unwrap();
+        assert_eq!(
+            graph.node("a", &[], ok).err(),
+            Some(InitGraphError::DuplicateNode("a"))
+        );
+        assert_eq!(
+            graph.node("b", &["a"], ok).err(),
+            Some(InitGraphError::Cycle("b"))
+        );
+        assert_eq!(
+            graph.node("c", &["c"], ok).err(),
+            Some(InitGraphError::Cycle("c"))
+        );
+        graph.node("b", &["nowhere"], ok).unwrap();
+        graph.node("c", &[], ok).unwrap();
+        assert_eq!(graph.node("d", &[], ok).err(), Some(InitGraphError::Full));
+        assert_eq!(
+            graph.execute(&os, 2, Duration::ms(30)).err(),
+            Some(InitGraphError::UnknownDependency("nowhere"))
+        );
+
+        // Dependencies first, the dependents of a failed node skipped, and the rest run.
+        let started = Arc::new(os.new_mutex(Vec::new()).unwrap());
+        let mut graph = InitGraph::new(8);
+        graph
+            .worker_stack_size(256)
+            .node("spi", &["clocks"], record(&started))
+            .unwrap()
+            .node("clocks", &[], record(&started))
+            .unwrap()
+            .node("uart", &["clocks"], |_| Err(InitError::Failed(7)))
+            .unwrap()
+            .node("modem", &["uart"], record(&started))
+            .unwrap()
+            .node("flash", &["spi"], record(&started))
+            .unwrap()
+            .node("hang 1", &["flash"], hang)
+            .unwrap()
+            .node("hang 2", &["flash"], hang)
+            .unwrap();
+        let start = os.get_tick_count();
+        let report = graph.execute(&os, 2, Duration::ms(30)).unwrap();
+        assert!(os.get_tick_count() - start >= 30);
+
+        assert_eq!(
+            *started.lock(Duration::zero()).unwrap(),
+            ["clocks", "spi", "flash"]
+        );
+
+        let result = |name| report.node(name).unwrap().result();
+        assert_eq!(result("clocks"), Ok(()));
+        assert_eq!(result("spi"), Ok(()));
+        assert_eq!(result("flash"), Ok(()));
+        assert_eq!(result("uart"), Err(InitError::Failed(7)));
+        assert_eq!(result("modem"), Err(InitError::Skipped));
+        assert_eq!(report.node("modem").unwrap().duration.to_ticks(), 0);
+        for name in ["hang 1", "hang 2"] {
+            let node = report.node(name).unwrap();
+            assert_eq!(node.result(), Err(InitError::Timeout));
+            assert!(node.duration.to_ticks() >= 30);
+        }
+        assert!(!report.is_success());
+        assert_eq!(report.nodes[0].name, "spi");
+        assert_eq!(report.nodes[0].deps, ["clocks"]);
+
+        // Failing fast, a node that times out stops the graph, and the rest is skipped
+        // although nothing depends on it.
+        let mut graph = InitGraph::new(2);
+        graph
+            .policy(InitPolicy::FailFast)
+            .worker_stack_size(256)
+            .node("slow", &[], hang)
+            .unwrap()
+            .node("later", &[], ok)
+            .unwrap();
+        let report = graph.execute(&os, 1, Duration::ms(20)).unwrap();
+        assert_eq!(report.nodes[0].result(), Err(InitError::Timeout));
+        assert_eq!(report.nodes[1].result(), Err(InitError::Skipped));
+    });
+}
@@ -0,0 +1,74 @@
+//! Interrupt scopes on the simulator: one scope of a controller is open at a time, and
+//! the controller can be opened again once its scope is dropped. A scope over borrowed
+//! resources is disabled before `scope` returns.
+//!
+//!     cargo test --test interrupt_scope --features hosted_tests --target x86_64-unknown-linux-gnu
+use freertos_rust::freertos_test::run_freertos_test;
+use freertos_rust::*;
+use std::sync::atomic::{AtomicBool, AtomicU32, Ordering};
+
+#[global_allocator]
+static GLOBAL: FreeRtosAllocator = FreeRtosAllocator;
+
+/// How often the controller was enabled and disabled.
+static ENABLES: AtomicU32 = AtomicU32::new(0);
+static DISABLES: AtomicU32 = AtomicU32::new(0);
+
+/// A controller that only counts, its interrupt is never raised.
+struct Counted;
+
+impl InterruptController for Counted {
+    type Class = KernelIsr;
+
+    fn enabled_flag() -> &'static AtomicBool {
+        static ENABLED: AtomicBool = AtomicBool::new(false);
+        &ENABLED
+    }
+
+    unsafe fn enable(_callback: Box<dyn Fn(&mut InterruptContext<KernelIsr>)>) {
+        ENABLES.fetch_add(1, Ordering::SeqCst);
+    }
+
+    unsafe fn disable() {
+        DISABLES.fetch_add(1, Ordering::SeqCst);
+    }
+}
+
+#[test]
+fn interrupt_scope() {
+    run_freertos_test(|os| {
+        assert!(!InterruptScope::<Counted>::is_open());
+
+        let scope = InterruptScope::<Counted>::open((), |_, _| {}).unwrap();
+        assert!(InterruptScope::<Counted>::is_open());
+
+        // The second scope is rejected without enabling the interrupt again.
+        let second = InterruptScope::<Counted>::open((), |_, _| {});
+        assert_eq!(second.err(), Some(FreeRtosError::InterruptAlreadyEnabled));
+        assert!(InterruptScope::<Counted>::is_open());
+        assert_eq!(ENABLES.load(Ordering::SeqCst), 1);
+        assert_eq!(DISABLES.load(Ordering::SeqCst), 0);
+
+        drop(scope);
+        assert!(!InterruptScope::<Counted>::is_open());
+        assert_eq!(DISABLES.load(Ordering::SeqCst), 1);
+
+        let reopened = InterruptScope::<Counted>::open((), |_, _| {}).unwrap();
+        assert!(InterruptScope::<Counted>::is_open());
+        assert_eq!(ENABLES.load(Ordering::SeqCst), 2);
+        drop(reopened);
+        assert!(!InterruptScope::<Counted>::is_open());
+        assert_eq!(DISABLES.load(Ordering::SeqCst), 2);
+
+        let semaphore = os.new_binary_semaphore().unwrap();
+        let open = InterruptScope::<Counted, _>::scope(
+            BorrowedISRHandle::new(&semaphore),
+            |_, _: &BorrowedISRHandle<ISRBinarySemaphore>| {},
+            |_| InterruptScope::<Counted, BorrowedISRHandle<ISRBinarySemaphore>>::is_open(),
+        );
+        assert_eq!(open, Ok(true));
+        assert!(!InterruptScope::<Counted>::is_open());
+        assert_eq!(DISABLES.load(Ordering::SeqCst), 3);
+        drop(semaphore);
+    });
+}
@@ -0,0 +1,63 @@
+//! Mutexes on the simulator: exclusion between tasks, lock timeouts, and the holder
+//! giving the mutex back when its guard is dropped.
+//!
+//!     cargo test --test mutex --features hosted_tests --target x86_64-unknown-linux-gnu
+use freertos_rust::freertos_test::run_freertos_test;
+use freertos_rust::*;
+use std::sync::Arc;
+
+#[global_allocator]
+static GLOBAL: FreeRtosAllocator = FreeRtosAllocator;
+
+const WORKERS: u32 = 3;
+const ROUNDS: u32 = 50;
+
+#[test]
+fn mutex() {
+    run_freertos_test(|os| {
+        let counter = Arc::new(os.new_mutex((0u32, false)).unwrap());
+        let done = Arc::new(os.new_counting_semaphore(WORKERS, 0).unwrap());
+
+        // Every worker checks that it is alone inside, and yields there.
+        for _ in 0..WORKERS {
+            let (counter, done) = (counter.clone(), done.clone());
+            os.new_task("worker", 256, TaskPriority(1), move |_, os| {
+                for _ in 0..ROUNDS {
+                    let mut guard = counter.lock(Duration::infinite()).unwrap();
+                    assert!(!guard.1, "two tasks inside the mutex");
+                    guard.1 = true;
+                    os.delay(Duration::ticks(0));
+                    guard.0 += 1;
+                    guard.1 = false;
+                }
+                done.give();
+                loop {
+                    os.delay(Duration::infinite());
+                }
+            })
+            .unwrap();
+        }
+        for _ in 0..WORKERS {
+            done.take(Duration::ms(1000)).unwrap();
+        }
+        assert_eq!(counter.lock(Duration::zero()).unwrap().0, WORKERS * ROUNDS);
+
+        // Held here, so a lock from another task times out, and succeeds once dropped.
+        let guard = counter.lock(Duration::zero()).unwrap();
+        let result = Arc::new(os.new_queue(2).unwrap());
+        let (c, r) = (counter.clone(), result.clone());
+        os.new_task("waiter", 256, TaskPriority(1), move |_, os| {
+            r.send(c.lock(Duration::ms(5)).is_ok(), Duration::zero())
+                .unwrap();
+            r.send(c.lock(Duration::ms(50)).is_ok(), Duration::zero())
+                .unwrap();
+            loop {
+                os.delay(Duration::infinite());
+            }
+        })
+        .unwrap();
+        assert_eq!(result.receive(Duration::ms(50)), Ok(false));
+        drop(guard);
+        assert_eq!(result.receive(Duration::ms(50)), Ok(true));
+    });
+}
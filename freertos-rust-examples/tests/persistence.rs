@@ -0,0 +1,146 @@
+//! Persisted cells on the simulator, over a backend in memory: coalesced writes, a
+//! backend rejecting writes, a flush timing out on a slow backend, the minimum interval
+//! between writes, and loading the older copy of a cell after a torn write.
+//!
+//!     cargo test --test persistence --features hosted_tests --target x86_64-unknown-linux-gnu
+use freertos_rust::freertos_test::run_freertos_test;
+use freertos_rust::*;
+use std::collections::HashMap;
+use std::sync::atomic::{AtomicBool, AtomicU32, Ordering};
+use std::sync::Arc;
+
+#[global_allocator]
+static GLOBAL: FreeRtosAllocator = FreeRtosAllocator;
+
+/// The flash the backend writes to, shared with the test to look at and break.
+struct Flash {
+    records: Mutex<HashMap<String, Vec<u8>>>,
+    failing: AtomicBool,
+    slow: AtomicBool,
+    saves: AtomicU32,
+}
+
+struct FlashBackend {
+    flash: Arc<Flash>,
+    os: FreeRTOS,
+}
+
+impl PersistBackend for FlashBackend {
+    type Error = ();
+
+    fn save(&mut self, key: &str, bytes: &[u8]) -> Result<(), ()> {
+        if self.flash.slow.load(Ordering::Relaxed) {
+            self.os.delay(Duration::ms(50));
+        }
+        if self.flash.failing.load(Ordering::Relaxed) {
+            return Err(());
+        }
+        self.flash.saves.fetch_add(1, Ordering::Relaxed);
+        self.flash
+            .records
+            .lock(Duration::infinite())
+            .unwrap()
+            .insert(key.to_string(), bytes.to_vec());
+        Ok(())
+    }
+
+    fn load(&mut self, key: &str, buf: &mut [u8]) -> Result<usize, ()> {
+        let records = self.flash.records.lock(Duration::infinite()).unwrap();
+        let record = records.get(key).ok_or(())?;
+        let len = record.len().min(buf.len());
+        buf[..len].copy_from_slice(&record[..len]);
+        Ok(len)
+    }
+}
+
+#[test]
+fn persistence() {
+    run_freertos_test(|os| {
+        let flash = Arc::new(Flash {
+            records: os.new_mutex(HashMap::new()).unwrap(),
+            failing: AtomicBool::new(false),
+            slow: AtomicBool::new(false),
+            saves: AtomicU32::new(0),
+        });
+        let backend = FlashBackend {
+            flash: flash.clone(),
+            os,
+        };
+        let persistence =
+            Persistence::new(os, backend, Duration::ms(20), 1024, TaskPriority(1)).unwrap();
+        let saves = || flash.saves.load(Ordering::Relaxed);
+
+        let counter = persistence
+            .register(&os, "counter", 0u32, Duration::ms(1000))
+            .unwrap();
+        assert_eq!(counter.get(&os), Ok(0));
+        assert!(!counter.is_dirty());
+
+        // Sets between two passes are written once.
+        for value in 1..=3 {
+            counter.set(&os, value).unwrap();
+        }
+        assert!(counter.is_dirty());
+        persistence.flush_now(Duration::ms(100)).unwrap();
+        assert!(!counter.is_dirty());
+        assert_eq!(saves(), 1);
+
+        // A rejected write is counted and the cell stays dirty, to be written later.
+        flash.failing.store(true, Ordering::Relaxed);
+        counter.set(&os, 4).unwrap();
+        persistence.flush_now(Duration::ms(100)).unwrap();
+        assert_eq!(persistence.write_errors(), 1);
+        assert!(counter.is_dirty());
+        flash.failing.store(false, Ordering::Relaxed);
+        persistence.flush_now(Duration::ms(100)).unwrap();
+        assert!(!counter.is_dirty());
+        assert_eq!((saves(), persistence.write_errors()), (2, 1));
+
+        // A flush that takes longer than its timeout fails, and still finishes.
+        flash.slow.store(true, Ordering::Relaxed);
+        counter.set(&os, 5).unwrap();
+        assert_eq!(
+            persistence.flush_now(Duration::ms(10)),
+            Err(FreeRtosError::Timeout)
+        );
+        os.delay(Duration::ms(100));
+        assert!(!counter.is_dirty());
+        assert_eq!(saves(), 3);
+        flash.slow.store(false, Ordering::Relaxed);
+
+        // The periodic passes write a cell as soon as it changes without a minimum
+        // interval, and hold the counter back for its second.
+        let energy = persistence
+            .register(&os, "energy", 0u64, Duration::zero())
+            .unwrap();
+        energy.set(&os, 1_000).unwrap();
+        counter.set(&os, 6).unwrap();
+        os.delay(Duration::ms(60));
+        assert!(!energy.is_dirty());
+        assert!(counter.is_dirty());
+        persistence.flush_now(Duration::ms(100)).unwrap();
+
+        // Loaded again, the newest intact copy wins. Written alternately, the two
+        // copies hold 6 and 5.
+        let reloaded = persistence
+            .register(&os, "counter", 0u32, Duration::ms(1000))
+            .unwrap();
+        assert_eq!(reloaded.get(&os), Ok(6));
+        {
+            let mut records = flash.records.lock(Duration::infinite()).unwrap();
+            let newest = ["counter.0", "counter.1"]
+                .iter()
+                .find(|key| records[**key][4..8] == 6u32.to_le_bytes())
+                .unwrap();
+            records.get_mut(*newest).unwrap()[4] ^= 0xff;
+        }
+        let torn = persistence
+            .register(&os, "counter", 0u32, Duration::ms(1000))
+            .unwrap();
+        assert_eq!(torn.get(&os), Ok(5));
+        let fresh = persistence
+            .register(&os, "missing", 42u32, Duration::ms(1000))
+            .unwrap();
+        assert_eq!(fresh.get(&os), Ok(42));
+    });
+}
@@ -0,0 +1,109 @@
+//! Priority plans on the simulator: deadline-monotonic order, tasks sharing a level when
+//! there are fewer levels than tasks, response times and an overloaded set, and the
+//! errors for bad input.
+//!
+//!     cargo test --test priority_plan --features hosted_tests --target x86_64-unknown-linux-gnu
+use freertos_rust::freertos_test::run_freertos_test;
+use freertos_rust::*;
+
+#[global_allocator]
+static GLOBAL: FreeRtosAllocator = FreeRtosAllocator;
+
+fn timing(name: &'static str, period: u32, deadline: u32, wcet: u32) -> TaskTiming {
+    TaskTiming::new(
+        name,
+        Duration::ticks(period),
+        Duration::ticks(deadline),
+        Duration::ticks(wcet),
+    )
+}
+
+fn priorities(plan: &AssignedPriorities) -> Vec<u8> {
+    plan.tasks().iter().map(|t| t.priority.0).collect()
+}
+
+fn response_times(plan: &AssignedPriorities) -> Vec<Option<FreeRtosTickType>> {
+    plan.tasks().iter().map(|t| t.response_time).collect()
+}
+
+#[test]
+fn priority_plan() {
+    run_freertos_test(|os| {
+        let mut out = [PlannedTask::default(); 4];
+
+        // Declared out of deadline order, planned shortest deadline first.
+        let declared = [
+            timing("logger", 50, 40, 10),
+            timing("control", 10, 10, 2),
+            timing("comms", 20, 20, 5),
+        ];
+        let plan = PriorityPlan::with_band(TaskPriority(1), TaskPriority(5))
+            .compute(&declared, &mut out)
+            .unwrap();
+        assert_eq!(priorities(&plan), [3, 5, 4]);
+        assert_eq!(response_times(&plan), [Some(19), Some(2), Some(7)]);
+        assert!(plan.is_feasible());
+        assert_eq!(plan.priority_of("comms"), Some(TaskPriority(4)));
+        assert_eq!(plan.priority_of("nobody"), None);
+
+        // Two levels for three tasks: the pair with the closest deadlines shares one, and
+        // each counts as interference for the other.
+        let declared = [
+            timing("control", 10, 10, 2),
+            timing("comms", 40, 30, 5),
+            timing("logger", 40, 40, 5),
+        ];
+        let plan = PriorityPlan::with_band(TaskPriority(1), TaskPriority(2))
+            .compute(&declared, &mut out)
+            .unwrap();
+        assert_eq!(priorities(&plan), [2, 1, 1]);
+        assert_eq!(response_times(&plan), [Some(2), Some(14), Some(14)]);
+
+        // More work than time: the lower task misses its deadline.
+        let declared = [timing("a", 10, 10, 6), timing("b", 10, 10, 6)];
+        let plan = PriorityPlan::with_band(TaskPriority(1), TaskPriority(5))
+            .compute(&declared, &mut out)
+            .unwrap();
+        assert_eq!(response_times(&plan), [Some(6), None]);
+        assert!(!plan.is_feasible());
+        assert!(!plan.tasks()[1].is_feasible());
+
+        // The whole range of the kernel, above the idle task.
+        let declared = [timing("only", 10, 10, 1)];
+        let plan = PriorityPlan::new(os).compute(&declared, &mut out).unwrap();
+        assert_eq!(
+            plan.tasks()[0].priority.0 as usize,
+            os.get_max_priorities() - 1
+        );
+
+        // Bad input.
+        let declared = [timing("a", 10, 10, 1), timing("b", 10, 10, 1)];
+        let plan = PriorityPlan::with_band(TaskPriority(1), TaskPriority(5));
+        assert_eq!(
+            plan.compute(&declared, &mut out[..1]).err(),
+            Some(PlanError::OutputTooSmall)
+        );
+        assert_eq!(
+            PriorityPlan::with_band(TaskPriority(5), TaskPriority(3))
+                .compute(&declared, &mut out)
+                .err(),
+            Some(PlanError::NoPriorities)
+        );
+        for (index, bad) in [
+            timing("no period", 0, 0, 1),
+            timing("no wcet", 10, 10, 0),
+            timing("late", 10, 20, 1),
+        ]
+        .iter()
+        .enumerate()
+        {
+            let declared = [timing("fine", 10, 10, 1), *bad];
+            assert_eq!(
+                plan.compute(&declared, &mut out).err(),
+                Some(PlanError::InvalidTiming { index: 1 }),
+                "case {}",
+                index
+            );
+        }
+    });
+}
@@ -0,0 +1,46 @@
+//! Queues on the simulator: FIFO order between tasks, and the timeouts of a full and an
+//! empty queue.
+//!
+//!     cargo test --test queue --features hosted_tests --target x86_64-unknown-linux-gnu
+use freertos_rust::freertos_test::run_freertos_test;
+use freertos_rust::*;
+use std::sync::Arc;
+
+#[global_allocator]
+static GLOBAL: FreeRtosAllocator = FreeRtosAllocator;
+
+#[test]
+fn queue() {
+    run_freertos_test(|os| {
+        let queue = Arc::new(os.new_queue::<u32>(4).unwrap());
+
+        // Filled by a lower priority task, which blocks while the queue is full.
+        let q = queue.clone();
+        os.new_task("producer", 256, TaskPriority(1), move |_, os| {
+            for n in 0..20 {
+                q.send(n, Duration::infinite()).unwrap();
+            }
+            loop {
+                os.delay(Duration::infinite());
+            }
+        })
+        .unwrap();
+        let received: Vec<u32> = (0..20)
+            .map(|_| queue.receive(Duration::ms(100)).unwrap())
+            .collect();
+        assert_eq!(received, (0..20).collect::<Vec<_>>());
+
+        assert_eq!(
+            queue.receive(Duration::ms(5)),
+            Err(FreeRtosError::QueueReceiveTimeout)
+        );
+        for n in 0..4 {
+            queue.send(n, Duration::zero()).unwrap();
+        }
+        assert_eq!(
+            queue.send(4, Duration::ms(5)),
+            Err(FreeRtosError::QueueSendTimeout)
+        );
+        assert_eq!(queue.len(), 4);
+    });
+}
@@ -0,0 +1,108 @@
+//! Queue sets on the simulator: a task waiting on two queues and a semaphore that three
+//! tasks race to fill, and the rules for members.
+//!
+//!     cargo test --test queue_set --features hosted_tests --target x86_64-unknown-linux-gnu
+use freertos_rust::freertos_test::run_freertos_test;
+use freertos_rust::*;
+use std::sync::Arc;
+
+#[global_allocator]
+static GLOBAL: FreeRtosAllocator = FreeRtosAllocator;
+
+const ROUNDS: u32 = 20;
+
+#[test]
+fn queue_set() {
+    run_freertos_test(|os| {
+        let numbers = Arc::new(os.new_queue::<u32>(4).unwrap());
+        let letters = Arc::new(os.new_queue::<u8>(4).unwrap());
+        let ticks = Arc::new(os.new_binary_semaphore().unwrap());
+        let spare = os.new_queue::<u32>(4).unwrap();
+        let set = os.new_queue_set(13).unwrap();
+        set.add(&*numbers).unwrap();
+        set.add(&*letters).unwrap();
+        set.add(&*ticks).unwrap();
+        set.add(&spare).unwrap();
+
+        // The rules for members.
+        assert_eq!(set.add(&*numbers), Err(FreeRtosError::InQueueSet));
+        assert_eq!(
+            numbers.receive(Duration::ms(1)),
+            Err(FreeRtosError::InQueueSet)
+        );
+        assert_eq!(ticks.take(Duration::ms(1)), Err(FreeRtosError::InQueueSet));
+        let full = os.new_queue::<u32>(1).unwrap();
+        full.send(1, Duration::zero()).unwrap();
+        let other = os.new_queue_set(1).unwrap();
+        assert_eq!(other.add(&full), Err(FreeRtosError::QueueSetMemberNotEmpty));
+        assert_eq!(set.add(&full), Err(FreeRtosError::InvalidQueueSize));
+        assert_eq!(set.select(Duration::ms(5)), Err(FreeRtosError::Timeout));
+
+        // Three producers of the same priority, below this task, race each other.
+        let n = numbers.clone();
+        os.new_task("numbers", 256, TaskPriority(1), move |_, os| {
+            for i in 0..ROUNDS {
+                n.send(i, Duration::infinite()).unwrap();
+                os.delay(Duration::ms(1));
+            }
+            loop {
+                os.delay(Duration::infinite());
+            }
+        })
+        .unwrap();
+        let l = letters.clone();
+        os.new_task("letters", 256, TaskPriority(1), move |_, os| {
+            for i in 0..ROUNDS {
+                l.send(b'a' + i as u8, Duration::infinite()).unwrap();
+                os.delay(Duration::ms(1));
+            }
+            loop {
+                os.delay(Duration::infinite());
+            }
+        })
+        .unwrap();
+        let t = ticks.clone();
+        os.new_task("ticks", 256, TaskPriority(1), move |_, os| {
+            for _ in 0..ROUNDS {
+                t.give();
+                os.delay(Duration::ms(1));
+            }
+            loop {
+                os.delay(Duration::infinite());
+            }
+        })
+        .unwrap();
+
+        let mut received_numbers = Vec::new();
+        let mut received_letters = Vec::new();
+        let mut taken = 0;
+        while received_numbers.len() + received_letters.len() + taken < 3 * ROUNDS as usize {
+            let ready = set.select(Duration::ms(100)).unwrap();
+            if ready.is(&*numbers) {
+                received_numbers.push(numbers.receive(Duration::zero()).unwrap());
+            } else if ready.is(&*letters) {
+                received_letters.push(letters.receive(Duration::zero()).unwrap());
+            } else if ready.is(&*ticks) {
+                ticks.take(Duration::zero()).unwrap();
+                taken += 1;
+            } else {
+                panic!("select returned a handle of no member");
+            }
+        }
+        assert_eq!(received_numbers, (0..ROUNDS).collect::<Vec<_>>());
+        assert_eq!(
+            received_letters,
+            (0..ROUNDS).map(|i| b'a' + i as u8).collect::<Vec<_>>()
+        );
+        assert_eq!(taken, ROUNDS as usize);
+        assert_eq!(set.select(Duration::ms(5)), Err(FreeRtosError::Timeout));
+
+        // A dropped member leaves the set, and returns its capacity.
+        drop(spare);
+        let more = os.new_queue::<u32>(4).unwrap();
+        set.add(&more).unwrap();
+        more.send(7, Duration::zero()).unwrap();
+        assert!(set.select(Duration::zero()).unwrap().is(&more));
+        assert_eq!(more.receive(Duration::zero()), Ok(7));
+    });
+}
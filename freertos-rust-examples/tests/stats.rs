@@ -0,0 +1,193 @@
+//! The fixed-point statistics: rounding of means and averages, counters saturating and
+//! setting the overflow flag, merging, and the encoding, including the encodings it
+//! rejects.
+//!
+//!     cargo test --test stats --features hosted_tests --target x86_64-unknown-linux-gnu
+use freertos_rust::*;
+
+#[global_allocator]
+static GLOBAL: FreeRtosAllocator = FreeRtosAllocator;
+
+#[test]
+fn running_stats() {
+    let mut stats = RunningStats::new();
+    assert_eq!(
+        (stats.count(), stats.mean(), stats.min(), stats.max()),
+        (0, 0, None, None)
+    );
+
+    // Ticks and wider values alike. A mean of 3.5 rounds up.
+    stats.record(3u32);
+    stats.record(4u64);
+    assert_eq!((stats.count(), stats.sum()), (2, 7));
+    assert_eq!((stats.mean_fixed(), stats.mean()), (896, 4));
+    assert_eq!((stats.min(), stats.max()), (Some(3), Some(4)));
+
+    let mut other = RunningStats::new();
+    other.record(11u32);
+    stats.merge(&other);
+    assert_eq!((stats.count(), stats.sum(), stats.mean()), (3, 18, 6));
+    assert_eq!((stats.min(), stats.max()), (Some(3), Some(11)));
+    assert!(!stats.overflowed());
+
+    // A sum that doesn't fit keeps the count and sum, and still moves the minimum.
+    let mut wide = RunningStats::new();
+    wide.record(u64::MAX - 1);
+    wide.record(2u64);
+    assert!(wide.overflowed());
+    assert_eq!((wide.count(), wide.sum()), (1, u64::MAX - 1));
+    assert_eq!((wide.min(), wide.max()), (Some(2), Some(u64::MAX - 1)));
+    stats.merge(&wide);
+    assert!(stats.overflowed());
+    assert_eq!((stats.count(), stats.min()), (3, Some(2)));
+    stats.reset();
+    assert_eq!(stats, RunningStats::new());
+
+    // So does a full count, from an encoding.
+    let mut bytes = [0; RunningStats::ENCODED_LEN];
+    other.encode(&mut bytes).unwrap();
+    bytes[..4].copy_from_slice(&u32::MAX.to_le_bytes());
+    let mut full = RunningStats::decode(&bytes).unwrap();
+    full.record(1u32);
+    assert!(full.overflowed());
+    assert_eq!(
+        (full.count(), full.sum(), full.min()),
+        (u32::MAX, 11, Some(1))
+    );
+}
+
+#[test]
+fn running_stats_encoding() {
+    let mut stats = RunningStats::new();
+    stats.record(7u32);
+    let mut bytes = [0; RunningStats::ENCODED_LEN + 1];
+    assert_eq!(stats.encode(&mut bytes), Some(RunningStats::ENCODED_LEN));
+    assert_eq!(RunningStats::decode(&bytes), Some(stats));
+
+    assert_eq!(
+        stats.encode(&mut bytes[..RunningStats::ENCODED_LEN - 1]),
+        None
+    );
+    assert_eq!(
+        RunningStats::decode(&bytes[..RunningStats::ENCODED_LEN - 1]),
+        None
+    );
+    bytes[RunningStats::ENCODED_LEN - 1] = 2;
+    assert_eq!(RunningStats::decode(&bytes), None);
+}
+
+#[test]
+fn ewma() {
+    let mut average = Ewma::new(1, 4);
+    assert!(!average.is_primed());
+    assert_eq!(average.value(), 0);
+
+    // The first sample sets the average, the next ones move it a quarter of the way.
+    average.record(100u32);
+    assert!(average.is_primed());
+    assert_eq!(average.value(), 100);
+    average.record(200u32);
+    assert_eq!(average.value(), 125);
+    average.record(0u32);
+    assert_eq!((average.value_fixed(), average.value()), (24000, 94));
+
+    // A constant input is reached, from below and from above.
+    let mut average = Ewma::new(1, 8);
+    average.record(0u32);
+    for _ in 0..100 {
+        average.record(1u32);
+    }
+    assert_eq!(average.value(), 1);
+    for _ in 0..100 {
+        average.record(0u32);
+    }
+    assert_eq!(average.value(), 0);
+
+    // A sample too large for the fixed point is clamped.
+    let mut average = Ewma::new(1, 1);
+    average.record(u64::MAX);
+    assert!(average.overflowed());
+    assert_eq!(average.value_fixed(), u64::MAX);
+    assert_eq!(average.value(), u64::MAX >> STATS_FRACTION_BITS);
+    average.reset();
+    assert!(!average.overflowed() && !average.is_primed());
+    assert_eq!(average.value(), 0);
+}
+
+#[test]
+fn ewma_encoding() {
+    let mut average = Ewma::new(3, 10);
+    average.record(42u32);
+    let mut bytes = [0; Ewma::ENCODED_LEN];
+    assert_eq!(average.encode(&mut bytes), Some(Ewma::ENCODED_LEN));
+    assert_eq!(Ewma::decode(&bytes), Some(average));
+    assert_eq!(average.encode(&mut bytes[..Ewma::ENCODED_LEN - 1]), None);
+
+    // An alpha above 1, and unknown flags.
+    let mut bad = bytes;
+    bad[..4].copy_from_slice(&11u32.to_le_bytes());
+    assert_eq!(Ewma::decode(&bad), None);
+    let mut bad = bytes;
+    bad[Ewma::ENCODED_LEN - 1] = 4;
+    assert_eq!(Ewma::decode(&bad), None);
+}
+
+
+#[test]
+fn histogram() {
+    let mut histogram = Histogram::<3>::linear(10);
+    assert_eq!(histogram.bounds(), &[9, 19, 29]);
+    for sample in [0u64, 9, 10, 29, 30, 1000] {
+        histogram.record(sample);
+    }
+    // The last bucket takes everything above its bound.
+    assert_eq!(histogram.counts(), &[2, 1, 3]);
+    assert_eq!(histogram.total(), 6);
+
+    assert_eq!(Histogram::<4>::log2(4).bounds(), &[3, 7, 15, 31]);
+    // Past the end of the range, the bounds stay ascending.
+    assert_eq!(
+        Histogram::<3>::log2(1 << 63).bounds(),
+        &[(1 << 63) - 1, u64::MAX - 1, u64::MAX]
+    );
+
+    let mut other = Histogram::<3>::with_bounds([9, 19, 29]);
+    other.record(15u32);
+    assert!(histogram.merge(&other));
+    assert_eq!(histogram.counts(), &[2, 2, 3]);
+    assert!(!histogram.merge(&Histogram::linear(5)));
+    assert_eq!(histogram.counts(), &[2, 2, 3]);
+
+    // A full bucket saturates, recording or merging.
+    let mut bytes = [0; Histogram::<3>::ENCODED_LEN];
+    histogram.encode(&mut bytes).unwrap();
+    bytes[8..12].copy_from_slice(&u32::MAX.to_le_bytes());
+    let mut full = Histogram::<3>::decode(&bytes).unwrap();
+    full.record(0u32);
+    assert!(full.overflowed());
+    assert_eq!(full.counts(), &[u32::MAX, 2, 3]);
+    assert!(histogram.merge(&full));
+    assert!(histogram.overflowed());
+    assert_eq!(histogram.counts(), &[u32::MAX, 4, 6]);
+    histogram.reset();
+    assert_eq!((histogram.total(), histogram.overflowed()), (0, false));
+}
+
+#[test]
+fn histogram_encoding() {
+    let mut histogram = Histogram::<2>::log2(8);
+    histogram.record(100u32);
+    let mut bytes = [0; Histogram::<2>::ENCODED_LEN];
+    assert_eq!(
+        histogram.encode(&mut bytes),
+        Some(Histogram::<2>::ENCODED_LEN)
+    );
+    assert_eq!(Histogram::<2>::decode(&bytes), Some(histogram));
+    assert_eq!(Histogram::<2>::decode(&bytes[..bytes.len() - 1]), None);
+
+    // Bounds out of order.
+    let mut bad = bytes;
+    bad[12..20].copy_from_slice(&1u64.to_le_bytes());
+    assert_eq!(Histogram::<2>::decode(&bad), None);
+}
+
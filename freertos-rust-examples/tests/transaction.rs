@@ -0,0 +1,127 @@
+//! Transactions over several mutexes on the simulator: a failing stage leaves every value
+//! as it was, a mutex held elsewhere times the commit out before anything is prepared, and
+//! a commit that succeeds applies every stage.
+//!
+//!     cargo test --test transaction --features hosted_tests --target x86_64-unknown-linux-gnu
+use freertos_rust::freertos_test::run_freertos_test;
+use freertos_rust::*;
+use std::cell::Cell;
+use std::sync::Arc;
+
+#[global_allocator]
+static GLOBAL: FreeRtosAllocator = FreeRtosAllocator;
+
+#[test]
+fn transaction() {
+    run_freertos_test(|os| {
+        let routes = Arc::new(os.new_mutex(vec![1u32, 2]).unwrap());
+        let filters = os.new_mutex(vec![10u8]).unwrap();
+        let limit = os.new_mutex(100u32).unwrap();
+
+        // The middle stage fails: the swap before it is dropped, the in-place one after it
+        // never runs, and the in-place stage first is rolled back.
+        let result = Transaction::new()
+            .stage_in_place(
+                &limit,
+                |l| {
+                    *l += 50;
+                    Ok(50)
+                },
+                |l, added| *l -= added,
+            )
+            .stage(&*routes, |r| {
+                let mut r = r.clone();
+                r.push(3);
+                Ok(r)
+            })
+            .stage(&filters, |_| Err("filter list full"))
+            .stage_in_place(
+                &limit,
+                |l| {
+                    *l = 0;
+                    Ok(())
+                },
+                |_, _| (),
+            )
+            .commit(Duration::ms(10));
+        assert_eq!(
+            result,
+            Err(TransactionError::Stage {
+                index: 2,
+                error: "filter list full"
+            })
+        );
+        assert_eq!(*routes.lock(Duration::zero()).unwrap(), [1, 2]);
+        assert_eq!(*filters.lock(Duration::zero()).unwrap(), [10]);
+        assert_eq!(*limit.lock(Duration::zero()).unwrap(), 100);
+
+        // Held by another task, the routes time the commit out. Nothing was prepared, and
+        // the mutexes that were taken are given back.
+        let held = Arc::new(os.new_binary_semaphore().unwrap());
+        let release = Arc::new(os.new_binary_semaphore().unwrap());
+        let (r, h, rel) = (routes.clone(), held.clone(), release.clone());
+        os.new_task("holder", 256, TaskPriority(2), move |_, os| {
+            let guard = r.lock(Duration::infinite()).unwrap();
+            h.give();
+            rel.take(Duration::infinite()).unwrap();
+            drop(guard);
+            loop {
+                os.delay(Duration::infinite());
+            }
+        })
+        .unwrap();
+        held.take(Duration::ms(100)).unwrap();
+
+        let prepared = Cell::new(false);
+        let start = os.get_tick_count();
+        let result = Transaction::<()>::new()
+            .stage(&filters, |f| {
+                prepared.set(true);
+                Ok(f.clone())
+            })
+            .stage(&*routes, |r| {
+                prepared.set(true);
+                Ok(r.clone())
+            })
+            .commit(Duration::ms(20));
+        let waited = os.get_tick_count() - start;
+        assert_eq!(
+            result,
+            Err(TransactionError::Lock(FreeRtosError::MutexTimeout))
+        );
+        assert!(waited >= 20, "gave up after {} ticks", waited);
+        assert!(!prepared.get());
+        assert!(filters.lock(Duration::zero()).is_ok());
+        release.give();
+
+        // Free again, every stage applies, with stages on the same mutex locking it once.
+        let result = Transaction::<()>::new()
+            .stage(&*routes, |r| {
+                let mut r = r.clone();
+                r.push(3);
+                Ok(r)
+            })
+            .stage(&filters, |_| Ok(vec![20]))
+            .stage_in_place(
+                &limit,
+                |l| {
+                    *l += 50;
+                    Ok(())
+                },
+                |_, _| (),
+            )
+            .stage_in_place(
+                &limit,
+                |l| {
+                    *l *= 2;
+                    Ok(())
+                },
+                |_, _| (),
+            )
+            .commit(Duration::ms(100));
+        assert_eq!(result, Ok(()));
+        assert_eq!(*routes.lock(Duration::zero()).unwrap(), [1, 2, 3]);
+        assert_eq!(*filters.lock(Duration::zero()).unwrap(), [20]);
+        assert_eq!(*limit.lock(Duration::zero()).unwrap(), 300);
+    });
+}
@@ -1,6 +1,14 @@
 use std::env;
+use std::fmt::Write as _;
+use std::fs;
 use std::path::PathBuf;
 
+// The constants of the c_hooks header.
+#[allow(dead_code)]
+mod c_hooks_abi {
+    include!("src/c_hooks/abi.rs");
+}
+
 // See: https://doc.rust-lang.org/cargo/reference/build-scripts.html
 fn main() {
     println!("cargo:rerun-if-changed=build.rs");
@@ -12,4 +20,116 @@ fn main() {
             .to_str()
             .unwrap()
     );
+    // Tells freertos-cargo-build to compile the fault injection hooks into shim.c.
+    if env::var("CARGO_FEATURE_FAULT_INJECT").is_ok() {
+        println!("cargo:FAULT_INJECT=1");
+    }
+    // Tells freertos-cargo-build to build heap_4 with in-place realloc.
+    if env::var("CARGO_FEATURE_HEAP_4").is_ok() {
+        println!("cargo:HEAP_4_REALLOC=1");
+    }
+    // Tells freertos-cargo-build to build heap_5 and the shim defining its regions.
+    if env::var("CARGO_FEATURE_HEAP_5").is_ok() {
+        println!("cargo:HEAP_5=1");
+    }
+    // Tells freertos-cargo-build that the port has xPortIsInsideInterrupt.
+    if env::var("CARGO_FEATURE_PORT_INSIDE_INTERRUPT").is_ok() {
+        println!("cargo:INSIDE_INTERRUPT=1");
+    }
+    // Tells freertos-cargo-build to compile the indexed notification shims.
+    if env::var("CARGO_FEATURE_NOTIFY_INDEXED").is_ok() {
+        println!("cargo:NOTIFY_INDEXED=1");
+    }
+    // Tells freertos-cargo-build to compile the scheduler end shims of the hosted ports.
+    if env::var("CARGO_FEATURE_END_SCHEDULER").is_ok() {
+        println!("cargo:END_SCHEDULER=1");
+    }
+    // Tells freertos-cargo-build to build an MPU port, with the restricted task shims.
+    if env::var("CARGO_FEATURE_MPU").is_ok() {
+        println!("cargo:MPU=1");
+    }
+    // C modules include frrs_c_hooks.h from DEP_FREERTOS_C_HOOKS_INCLUDE.
+    if env::var("CARGO_FEATURE_C_HOOKS").is_ok() {
+        println!("cargo:rerun-if-changed=src/c_hooks/abi.rs");
+        let include = PathBuf::from(env::var("OUT_DIR").unwrap()).join("include");
+        fs::create_dir_all(&include).unwrap();
+        fs::write(include.join("frrs_c_hooks.h"), c_hooks_header()).unwrap();
+        println!("cargo:C_HOOKS_INCLUDE={}", include.to_str().unwrap());
+    }
+}
+
+fn c_hooks_header() -> String {
+    use c_hooks_abi::*;
+
+    let mut h = String::new();
+    h.push_str(
+        "/* Written by the freertos-rust build script from src/c_hooks/abi.rs, don't edit. */\n\
+         \n\
+         #ifndef FRRS_C_HOOKS_H\n\
+         #define FRRS_C_HOOKS_H\n\
+         \n\
+         #include <stddef.h>\n\
+         #include <stdint.h>\n\
+         \n\
+         #ifdef __cplusplus\n\
+         extern \"C\" {\n\
+         #endif\n\
+         \n",
+    );
+
+    let defines: &[(&str, i64)] = &[
+        ("FRRS_C_HOOKS_VERSION", C_HOOKS_VERSION as i64),
+        ("FRRS_LOG_MAX", C_HOOKS_LOG_MAX as i64),
+        ("FRRS_LOG_MODULE_MAX", C_HOOKS_MODULE_MAX as i64),
+        ("FRRS_TRACE_MAX", C_HOOKS_TRACE_MAX as i64),
+        ("FRRS_TUNABLE_NAME_MAX", C_HOOKS_TUNABLE_NAME_MAX as i64),
+        ("", 0),
+        ("FRRS_LOG_ERROR", c_hooks_level::ERROR as i64),
+        ("FRRS_LOG_WARN", c_hooks_level::WARN as i64),
+        ("FRRS_LOG_INFO", c_hooks_level::INFO as i64),
+        ("FRRS_LOG_DEBUG", c_hooks_level::DEBUG as i64),
+        ("", 0),
+        ("FRRS_HOOKS_OK", c_hooks_status::OK as i64),
+        ("FRRS_HOOKS_INVALID", c_hooks_status::INVALID as i64),
+        ("FRRS_HOOKS_INVALID_ID", c_hooks_status::INVALID_ID as i64),
+        ("FRRS_HOOKS_NOT_FOUND", c_hooks_status::NOT_FOUND as i64),
+        ("FRRS_HOOKS_NO_SINK", c_hooks_status::NO_SINK as i64),
+        (
+            "FRRS_HOOKS_RATE_LIMITED",
+            c_hooks_status::RATE_LIMITED as i64,
+        ),
+        ("FRRS_HOOKS_VERSION", c_hooks_status::VERSION as i64),
+        (
+            "FRRS_HOOKS_NOT_INITIALIZED",
+            c_hooks_status::NOT_INITIALIZED as i64,
+        ),
+    ];
+    for (name, value) in defines {
+        if name.is_empty() {
+            h.push('\n');
+        } else {
+            writeln!(h, "#define {} ({})", name, value).unwrap();
+        }
+    }
+
+    h.push_str(
+        "\n\
+         /* Call first with FRRS_C_HOOKS_VERSION. The other functions return\n\
+            FRRS_HOOKS_NOT_INITIALIZED until a call passed the version of the crate. */\n\
+         int32_t frrs_c_hooks_init(uint32_t version);\n\
+         \n\
+         /* None of these block. They can be called from ISRs when the Rust side's\n\
+            log and trace sinks can. */\n\
+         int32_t frrs_log_write(uint8_t level, const char *module, const uint8_t *msg, size_t len);\n\
+         int32_t frrs_trace_record(uint16_t source_id, const uint8_t *payload, size_t len);\n\
+         int32_t frrs_counter_increment(uint32_t id);\n\
+         int32_t frrs_tunable_get_u32(const char *name, uint32_t *out);\n\
+         \n\
+         #ifdef __cplusplus\n\
+         }\n\
+         #endif\n\
+         \n\
+         #endif /* FRRS_C_HOOKS_H */\n",
+    );
+    h
 }
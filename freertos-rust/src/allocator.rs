@@ -1,23 +1,341 @@
+//! The global allocator on top of the FreeRTOS heap, and its setup.
+//!
+//! `init` checks that the heap works before anything else allocates, or with the `heap_5`
+//! feature, `init_with_regions` gives heap_5 the memory it allocates from. With the
+//! `alloc_checks` feature, an allocation before either of them calls the premature
+//! allocation hook, or the assert hook without one, and panics, rather than failing
+//! somewhere in the heap.
+
 use crate::base::*;
+#[cfg(feature = "heap_integrity")]
+use crate::heap_integrity::{self, HEAP_CANARY_SIZE};
+use crate::hooks::*;
 use crate::shim::*;
 use core::alloc::{GlobalAlloc, Layout};
+#[cfg(feature = "fmt")]
+use core::fmt;
+use core::mem;
+use core::ptr;
+// The allocator state is in statics, so its atomics have to be const-initialised.
+use core::sync::atomic::{AtomicBool, AtomicUsize, Ordering};
+
+static INITIALIZED: AtomicBool = AtomicBool::new(false);
+
+/// Free heap space in bytes below which `ON_LOW_WATER` is called, 0 without one.
+static LOW_WATER: AtomicUsize = AtomicUsize::new(0);
+static LOW_WATER_REPORTED: AtomicBool = AtomicBool::new(false);
+static mut ON_LOW_WATER: Option<fn(usize)> = None;
+
+/// The most regions `init_with_regions` takes.
+#[cfg(feature = "heap_5")]
+pub const MAX_HEAP_REGIONS: usize = 8;
+
+/// The regions given to heap_5, by address, and the entry of size 0 ending them.
+#[cfg(feature = "heap_5")]
+static mut REGIONS: [HeapRegion; MAX_HEAP_REGIONS + 1] = [HeapRegion::END; MAX_HEAP_REGIONS + 1];
+#[cfg(feature = "heap_5")]
+static REGION_COUNT: AtomicUsize = AtomicUsize::new(0);
+
+/// Check that the heap can allocate, and mark the allocator as initialized. Call it
+/// first thing in `main`.
+///
+/// heap_1 to heap_4 need no setup, so this only matters with the `alloc_checks` feature,
+/// which requires it before the first allocation.
+#[cfg(not(feature = "heap_5"))]
+pub fn init() {
+    let probe = unsafe { freertos_rs_pvPortMalloc(1) };
+    assert!(!probe.is_null(), "FreeRTOS heap can't allocate");
+    unsafe { freertos_rs_vPortFree(probe) };
+
+    INITIALIZED.store(true, Ordering::Release);
+}
+
+/// `init`, and call `on_low_water` once with the lowest free heap space so far when it
+/// first drops below `threshold` bytes.
+///
+/// The check runs in the idle hook, `configUSE_IDLE_HOOK` has to be 1, so the
+/// allocator itself doesn't get any slower. `on_low_water` runs in the idle task and
+/// must not block.
+#[cfg(not(feature = "heap_5"))]
+pub fn init_with_low_water(threshold: usize, on_low_water: fn(usize)) {
+    init();
+
+    unsafe { ON_LOW_WATER = Some(on_low_water) };
+    LOW_WATER.store(threshold, Ordering::Release);
+}
+
+/// Give heap_5 the memory it allocates from, and mark the allocator as initialized.
+/// Call it first thing in `main`: heap_5 can't allocate before.
+///
+/// The regions may come in any order. Panics when called twice, without regions or
+/// with more than `MAX_HEAP_REGIONS`.
+///
+/// Safety:
+/// The regions must not overlap, and their memory must not be used for anything else
+/// for the rest of the program.
+#[cfg(feature = "heap_5")]
+pub unsafe fn init_with_regions(regions: &[HeapRegion]) {
+    assert!(
+        !regions.is_empty() && regions.len() <= MAX_HEAP_REGIONS,
+        "between 1 and MAX_HEAP_REGIONS heap regions needed"
+    );
+    assert!(
+        !INITIALIZED.load(Ordering::Acquire),
+        "heap regions already defined"
+    );
+    debug_assert_eq!(
+        FreeRtosAllocator::heap_stats().map_or(0, |stats| stats.allocations),
+        0,
+        "heap regions defined after the heap allocated"
+    );
+
+    // heap_5 wants them by address. Sorted by insertion, which doesn't allocate.
+    let sorted = &mut *ptr::addr_of_mut!(REGIONS);
+    for (i, region) in regions.iter().enumerate() {
+        let mut j = i;
+        while j > 0 && sorted[j - 1].start > region.start {
+            sorted[j] = sorted[j - 1];
+            j -= 1;
+        }
+        sorted[j] = *region;
+    }
+    REGION_COUNT.store(regions.len(), Ordering::Relaxed);
+
+    freertos_rs_define_heap_regions(sorted.as_ptr());
+    INITIALIZED.store(true, Ordering::Release);
+}
+
+/// Whether `init`, or `init_with_regions` with the `heap_5` feature, was called.
+pub fn is_initialized() -> bool {
+    INITIALIZED.load(Ordering::Acquire)
+}
+
+/// The regions heap_5 allocates from, by address. Empty before `init_with_regions`.
+#[cfg(feature = "heap_5")]
+pub fn regions() -> &'static [HeapRegion] {
+    let count = REGION_COUNT.load(Ordering::Relaxed);
+    let regions = unsafe { &*ptr::addr_of!(REGIONS) };
+    &regions[..count]
+}
+
+/// Called by `freertos_rs_idle_hook`.
+pub(crate) fn low_water_idle_hook() {
+    let threshold = LOW_WATER.load(Ordering::Acquire);
+    if threshold == 0 {
+        return;
+    }
+
+    let lowest = FreeRtosAllocator::minimum_ever_free_heap_size();
+    if lowest < threshold && !LOW_WATER_REPORTED.swap(true, Ordering::Relaxed) {
+        if let Some(c) = unsafe { *ptr::addr_of!(ON_LOW_WATER) } {
+            c(lowest);
+        }
+    }
+}
+
+/// A block of memory for heap_5, laid out like the kernel's `HeapRegion_t`.
+#[repr(C)]
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub struct HeapRegion {
+    pub start: *mut u8,
+    pub size: usize,
+}
+
+// Only an address range, the memory belongs to the heap.
+unsafe impl Send for HeapRegion {}
+unsafe impl Sync for HeapRegion {}
+
+impl HeapRegion {
+    #[cfg(feature = "heap_5")]
+    const END: HeapRegion = HeapRegion {
+        start: ptr::null_mut(),
+        size: 0,
+    };
+
+    /// Whether `ptr` points into the region.
+    pub fn contains(&self, ptr: *const u8) -> bool {
+        let start = self.start as usize;
+        (ptr as usize) >= start && (ptr as usize) - start < self.size
+    }
+}
+
+/// The state of heap_4 or heap_5, laid out like the kernel's `HeapStats_t`.
+#[repr(C)]
+#[derive(Debug, Default, Copy, Clone, PartialEq, Eq)]
+pub struct HeapStats {
+    /// Free bytes over all free blocks, not all of which can be allocated at once.
+    pub available: usize,
+    pub largest_free_block: usize,
+    pub smallest_free_block: usize,
+    pub free_blocks: usize,
+    pub minimum_ever_available: usize,
+    pub allocations: usize,
+    pub frees: usize,
+}
+
+/// A table like the one of `FreeRtosSchedulerState`, a line per field.
+#[cfg(feature = "fmt")]
+impl fmt::Display for HeapStats {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str("FreeRTOS heap\r\n")?;
+        for (name, value) in [
+            ("Available", self.available),
+            ("Largest free block", self.largest_free_block),
+            ("Smallest free block", self.smallest_free_block),
+            ("Free blocks", self.free_blocks),
+            ("Minimum ever available", self.minimum_ever_available),
+            ("Allocations", self.allocations),
+            ("Frees", self.frees),
+        ] {
+            write!(f, "{: <22} | {: >10}\r\n", name, value)?;
+        }
+        Ok(())
+    }
+}
 
 /**
 Use with:
 
     #[global_allocator]
     static GLOBAL: FreeRtosAllocator = FreeRtosAllocator;
+
+Blocks are aligned to `portBYTE_ALIGNMENT` by `pvPortMalloc`. Layouts that need more
+are over-allocated by their alignment plus a pointer, which is stored right before the
+aligned block for `dealloc`.
+
+When the heap is exhausted, the hook set with `FREERTOS_HOOKS.set_on_alloc_failure`
+is called with the requested size before null is returned.
 */
 
 pub struct FreeRtosAllocator;
 
+/// An allocation before `init` with the `alloc_checks` feature.
+#[cfg(feature = "alloc_checks")]
+#[cold]
+fn premature_allocation(size: usize) -> ! {
+    unsafe { (*ptr::addr_of!(FREERTOS_HOOKS)).do_on_premature_alloc(size) };
+    panic!("FreeRtosAllocator used before allocator::init");
+}
+
+#[cfg(feature = "heap_integrity")]
+const EXTRA_SIZE: usize = HEAP_CANARY_SIZE;
+#[cfg(not(feature = "heap_integrity"))]
+const EXTRA_SIZE: usize = 0;
+
+impl FreeRtosAllocator {
+    /// The free heap space right now. 0 with a `heap_?.c` that doesn't track it.
+    pub fn free_heap_size() -> usize {
+        unsafe { freertos_rs_xPortGetFreeHeapSize() }
+    }
+
+    /// The lowest the free heap space has been since boot. Only `heap_4.c` and
+    /// `heap_5.c` track it, it reads 0 with the others.
+    pub fn minimum_ever_free_heap_size() -> usize {
+        unsafe { freertos_rs_xPortGetMinimumEverFreeHeapSize() }
+    }
+
+    /// Give heap_5 the memory it allocates from, see `allocator::init_with_regions`.
+    ///
+    /// Safety:
+    /// The regions must not overlap, and their memory must not be used for anything else
+    /// for the rest of the program.
+    #[cfg(feature = "heap_5")]
+    pub unsafe fn define_heap_regions(regions: &[HeapRegion]) {
+        init_with_regions(regions)
+    }
+
+    /// Statistics of the heap, `None` with a `heap_?.c` that doesn't keep them. Only
+    /// `heap_4.c` and `heap_5.c` do. With heap_5, `regions` tells which memory the heap
+    /// covers.
+    pub fn heap_stats() -> Option<HeapStats> {
+        let mut stats = HeapStats::default();
+        if unsafe { freertos_rs_get_heap_stats(&mut stats) } != 0 {
+            return None;
+        }
+        Some(stats)
+    }
+
+    fn over_aligned(layout: &Layout) -> bool {
+        layout.align() > unsafe { freertos_rs_get_portBYTE_ALIGNMENT() } as usize
+    }
+}
+
 unsafe impl GlobalAlloc for FreeRtosAllocator {
     unsafe fn alloc(&self, layout: Layout) -> *mut u8 {
-        let res = freertos_rs_pvPortMalloc(layout.size() as u32);
-        return res as *mut u8;
+        #[cfg(feature = "alloc_checks")]
+        if !INITIALIZED.load(Ordering::Relaxed) {
+            premature_allocation(layout.size());
+        }
+
+        let over_aligned = Self::over_aligned(&layout);
+        let size = if over_aligned {
+            layout.size() + layout.align() + mem::size_of::<usize>() + EXTRA_SIZE
+        } else {
+            layout.size() + EXTRA_SIZE
+        };
+
+        let raw = freertos_rs_pvPortMalloc(size as u32) as *mut u8;
+        if raw.is_null() {
+            (*ptr::addr_of!(FREERTOS_HOOKS)).do_on_alloc_failure(layout.size());
+            return ptr::null_mut();
+        }
+
+        let res = if over_aligned {
+            let res = raw.add(mem::size_of::<usize>()).add(
+                raw.add(mem::size_of::<usize>())
+                    .align_offset(layout.align()),
+            );
+            (res as *mut usize).sub(1).write(raw as usize);
+            res
+        } else {
+            raw
+        };
+
+        #[cfg(feature = "heap_integrity")]
+        heap_integrity::track(res, layout.size());
+        res
+    }
+
+    unsafe fn dealloc(&self, ptr: *mut u8, layout: Layout) {
+        #[cfg(feature = "heap_integrity")]
+        heap_integrity::untrack(ptr);
+
+        let raw = if Self::over_aligned(&layout) {
+            (ptr as *const usize).sub(1).read() as *mut u8
+        } else {
+            ptr
+        };
+        freertos_rs_vPortFree(raw as FreeRtosVoidPtr)
     }
 
-    unsafe fn dealloc(&self, ptr: *mut u8, _layout: Layout) {
-        freertos_rs_vPortFree(ptr as FreeRtosVoidPtr)
+    /// With the `heap_4` feature, blocks are resized in place when heap_4 can: always
+    /// when shrinking, and when growing into a free block right after. Otherwise, and
+    /// for over-aligned layouts, the block is moved.
+    #[cfg(feature = "heap_4")]
+    unsafe fn realloc(&self, ptr: *mut u8, layout: Layout, new_size: usize) -> *mut u8 {
+        if !Self::over_aligned(&layout) {
+            // The canary moves with the end of the block.
+            #[cfg(feature = "heap_integrity")]
+            heap_integrity::untrack(ptr);
+
+            let resized =
+                freertos_rs_heap_4_resize_in_place(ptr as FreeRtosVoidPtr, new_size + EXTRA_SIZE)
+                    == 0;
+
+            #[cfg(feature = "heap_integrity")]
+            heap_integrity::track(ptr, if resized { new_size } else { layout.size() });
+
+            if resized {
+                return ptr;
+            }
+        }
+
+        let new_layout = Layout::from_size_align_unchecked(new_size, layout.align());
+        let new_ptr = self.alloc(new_layout);
+        if !new_ptr.is_null() {
+            ptr::copy_nonoverlapping(ptr, new_ptr, layout.size().min(new_size));
+            self.dealloc(ptr, layout);
+        }
+        new_ptr
     }
 }
@@ -1,8 +1,19 @@
+use crate::capacities::RegistryKind;
+use crate::task::TaskName;
+#[cfg(feature = "fmt")]
+use core::fmt;
+
 // TODO add some constants like pdPASS, pdFAIL, pdTRUE, and pdFALSE. They'll make it easier to
 // make use of C code with Rust.
 
 /// Basic error type for the library.
+///
+/// New variants may be added, so matches need a wildcard arm. With the `fmt` feature it
+/// implements `Display` and `core::error::Error`, with the `defmt` feature
+/// `defmt::Format`.
 #[derive(Copy, Clone, Debug, Eq, PartialEq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+#[non_exhaustive]
 pub enum FreeRtosError {
     OutOfMemory,
     QueueSendTimeout,
@@ -14,6 +25,121 @@ pub enum FreeRtosError {
     TaskNotFound,
     InvalidQueueSize,
     ProcessorHasShutDown,
+    Emergency,
+    /// Statically allocated storage that is already used by a live object.
+    StorageInUse,
+    /// A crate registry is at capacity, see `Registries`.
+    RegistryFull(RegistryKind),
+    /// The task wasn't in the Blocked state, see `TaskRemoteHandle::abort_delay`.
+    TaskNotBlocked,
+    /// No receiver took the value in time, see `Rendezvous::send`.
+    RendezvousSendTimeout,
+    /// No sender came in time, see `Rendezvous::receive`.
+    RendezvousReceiveTimeout,
+    /// A task or timer name longer than `max_name_len`, which the kernel would cut short.
+    NameTooLong,
+    /// A name with a nul byte, which would end it early as a C string.
+    InvalidName,
+    /// A task stack depth that doesn't fit in `FreeRtosStackDepthType`.
+    StackTooLarge,
+    /// A mutex lock timed out while the named task held the mutex. Replaces
+    /// `MutexTimeout` with the `debug_sync` feature, when the holder is known.
+    MutexTimeoutHeldBy(TaskName),
+    /// More MPU regions than the port's `portNUM_CONFIGURABLE_REGIONS`, see
+    /// `RestrictedTaskBuilder`.
+    TooManyRegions,
+    /// A blocking read of a queue or semaphore in a `QueueSet`, or adding it to a second
+    /// set. Its members are read after `QueueSet::select` returned them.
+    InQueueSet,
+    /// Adding a queue holding items or a semaphore that is available to a `QueueSet`.
+    QueueSetMemberNotEmpty,
+    /// Opening an `InterruptScope` of a controller that has one open already.
+    InterruptAlreadyEnabled,
+}
+
+impl FreeRtosError {
+    /// A stable numeric code for this error, for reporting without `core::fmt`.
+    pub fn code(&self) -> u8 {
+        match self {
+            FreeRtosError::OutOfMemory => 1,
+            FreeRtosError::QueueSendTimeout => 2,
+            FreeRtosError::QueueReceiveTimeout => 3,
+            FreeRtosError::MutexTimeout => 4,
+            FreeRtosError::Timeout => 5,
+            FreeRtosError::QueueFull => 6,
+            FreeRtosError::StringConversionError => 7,
+            FreeRtosError::TaskNotFound => 8,
+            FreeRtosError::InvalidQueueSize => 9,
+            FreeRtosError::ProcessorHasShutDown => 10,
+            FreeRtosError::Emergency => 11,
+            FreeRtosError::StorageInUse => 12,
+            FreeRtosError::RegistryFull(_) => 13,
+            FreeRtosError::TaskNotBlocked => 14,
+            FreeRtosError::RendezvousSendTimeout => 15,
+            FreeRtosError::RendezvousReceiveTimeout => 16,
+            FreeRtosError::NameTooLong => 17,
+            FreeRtosError::InvalidName => 18,
+            FreeRtosError::StackTooLarge => 19,
+            FreeRtosError::MutexTimeoutHeldBy(_) => 20,
+            FreeRtosError::TooManyRegions => 21,
+            FreeRtosError::InQueueSet => 22,
+            FreeRtosError::QueueSetMemberNotEmpty => 23,
+            FreeRtosError::InterruptAlreadyEnabled => 24,
+        }
+    }
+}
+
+#[cfg(feature = "fmt")]
+impl fmt::Display for FreeRtosError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let message = match self {
+            FreeRtosError::OutOfMemory => "out of memory",
+            FreeRtosError::QueueSendTimeout => "timed out sending to a queue",
+            FreeRtosError::QueueReceiveTimeout => "timed out receiving from a queue",
+            FreeRtosError::MutexTimeout => "timed out locking a mutex",
+            FreeRtosError::Timeout => "timed out",
+            FreeRtosError::QueueFull => "queue full",
+            FreeRtosError::StringConversionError => "string is not valid UTF-8",
+            FreeRtosError::TaskNotFound => "task not found",
+            FreeRtosError::InvalidQueueSize => "invalid queue size",
+            FreeRtosError::ProcessorHasShutDown => "processor has shut down",
+            FreeRtosError::Emergency => "emergency triggered",
+            FreeRtosError::StorageInUse => "static storage already in use",
+            FreeRtosError::RegistryFull(kind) => {
+                return write!(f, "{} registry full", kind.name());
+            }
+            FreeRtosError::TaskNotBlocked => "task not blocked",
+            FreeRtosError::RendezvousSendTimeout => "timed out waiting for a rendezvous receiver",
+            FreeRtosError::RendezvousReceiveTimeout => "timed out waiting for a rendezvous sender",
+            FreeRtosError::NameTooLong => "name too long",
+            FreeRtosError::InvalidName => "name contains a nul byte",
+            FreeRtosError::StackTooLarge => "stack depth too large for configSTACK_DEPTH_TYPE",
+            FreeRtosError::MutexTimeoutHeldBy(holder) => {
+                return write!(f, "timed out locking a mutex held by {:?}", holder);
+            }
+            FreeRtosError::TooManyRegions => "more MPU regions than the port can configure",
+            FreeRtosError::InQueueSet => "read through a queue set",
+            FreeRtosError::QueueSetMemberNotEmpty => "queue set member not empty",
+            FreeRtosError::InterruptAlreadyEnabled => "interrupt already enabled",
+        };
+        f.write_str(message)
+    }
+}
+
+#[cfg(feature = "fmt")]
+impl core::error::Error for FreeRtosError {}
+
+/// The `Timeout` error of the blocking wrappers, built out of line to keep it off their
+/// success path. `Emergency` instead in a task an `EmergencyBroadcast` aborted, with the
+/// `emergency_abort` feature.
+#[cold]
+#[inline(never)]
+pub(crate) fn timed_out() -> FreeRtosError {
+    #[cfg(feature = "emergency_abort")]
+    if crate::emergency::emergency_aborted() {
+        return FreeRtosError::Emergency;
+    }
+    FreeRtosError::Timeout
 }
 
 unsafe impl Send for CVoid {}
@@ -38,6 +164,8 @@ pub type FreeRtosTaskHandle = *const CVoid;
 pub type FreeRtosMutTaskHandle = *mut CVoid;
 pub type FreeRtosQueueHandle = *const CVoid;
 pub type FreeRtosSemaphoreHandle = *const CVoid;
+pub type FreeRtosStreamBufferHandle = *const CVoid;
+pub type FreeRtosEventGroupHandle = *const CVoid;
 pub type FreeRtosTaskFunction = *const CVoid;
 pub type FreeRtosTimerHandle = *const CVoid;
 pub type FreeRtosTimerCallback = *const CVoid;
@@ -46,6 +174,12 @@ pub type FreeRtosStackType = *const CVoid;
 
 pub type FreeRtosUnsignedLong = u32;
 pub type FreeRtosUnsignedShort = u16;
+/// `configSTACK_DEPTH_TYPE`, a stack depth in words. The kernel defaults it to `uint16_t`,
+/// the `stack_depth_u32` feature matches a config defining it as `uint32_t`.
+#[cfg(not(feature = "stack_depth_u32"))]
+pub type FreeRtosStackDepthType = u16;
+#[cfg(feature = "stack_depth_u32")]
+pub type FreeRtosStackDepthType = u32;
 
 #[derive(Copy, Clone, Debug)]
 #[repr(C)]
@@ -58,10 +192,39 @@ pub struct FreeRtosTaskStatusFfi {
     pub base_priority: FreeRtosUBaseType,
     pub run_time_counter: FreeRtosUnsignedLong,
     pub stack_base: FreeRtosCharPtr,
-    pub stack_high_water_mark: FreeRtosUnsignedShort,
+    pub stack_high_water_mark: FreeRtosStackDepthType,
 }
 
+/// `MemoryRegion_t`: memory an MPU port grants a restricted task. `parameters` are the
+/// port's attributes, like `portMPU_REGION_READ_WRITE`; a region with a `length` of zero
+/// is unused.
+#[cfg(feature = "mpu")]
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+#[repr(C)]
+pub struct MemoryRegion {
+    pub base: FreeRtosMutVoidPtr,
+    pub length: u32,
+    pub parameters: u32,
+}
+
+/// What `freertos_rs_spawn_task_restricted` in shim.c builds a `TaskParameters_t` from.
+#[cfg(feature = "mpu")]
 #[derive(Copy, Clone, Debug)]
+#[repr(C)]
+pub struct FreeRtosRestrictedTaskFfi {
+    pub entry_point: extern "C" fn(FreeRtosMutVoidPtr) -> FreeRtosMutVoidPtr,
+    pub parameters: FreeRtosMutVoidPtr,
+    pub name: FreeRtosCharPtr,
+    pub name_len: u8,
+    pub stack_depth: FreeRtosStackDepthType,
+    pub priority: FreeRtosUBaseType,
+    pub privileged: u8,
+    pub stack: FreeRtosMutVoidPtr,
+    pub regions: *const MemoryRegion,
+    pub region_count: FreeRtosUBaseType,
+}
+
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
 #[repr(u8)]
 pub enum FreeRtosTaskState {
     /// A task is querying the state of itself, so must be running.
@@ -75,3 +238,16 @@ pub enum FreeRtosTaskState {
     /// The task being queried has been deleted, but its TCB has not yet been freed.
     Deleted = 4,
 }
+
+impl FreeRtosTaskState {
+    /// The name of the state, as shown by `Debug`.
+    pub fn name(&self) -> &'static str {
+        match self {
+            FreeRtosTaskState::Running => "Running",
+            FreeRtosTaskState::Ready => "Ready",
+            FreeRtosTaskState::Blocked => "Blocked",
+            FreeRtosTaskState::Suspended => "Suspended",
+            FreeRtosTaskState::Deleted => "Deleted",
+        }
+    }
+}
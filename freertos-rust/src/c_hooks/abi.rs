@@ -0,0 +1,42 @@
+// The constants of frrs_c_hooks.h. build.rs includes this file to write the header, so
+// it only holds plain constants.
+
+/// Bumped on any change to the C functions or to these constants. C modules pass the
+/// version they were built against to `frrs_c_hooks_init`.
+pub const C_HOOKS_VERSION: u32 = 1;
+
+/// The longest message `frrs_log_write` takes, in bytes.
+pub const C_HOOKS_LOG_MAX: usize = 120;
+/// The longest module name `frrs_log_write` takes, in bytes, without the NUL.
+pub const C_HOOKS_MODULE_MAX: usize = 16;
+/// The longest payload `frrs_trace_record` takes, in bytes.
+pub const C_HOOKS_TRACE_MAX: usize = 32;
+/// The longest tunable name `frrs_tunable_get_u32` takes, in bytes, without the NUL.
+pub const C_HOOKS_TUNABLE_NAME_MAX: usize = 32;
+
+/// The `level` of `frrs_log_write`.
+pub mod c_hooks_level {
+    pub const ERROR: u8 = 1;
+    pub const WARN: u8 = 2;
+    pub const INFO: u8 = 3;
+    pub const DEBUG: u8 = 4;
+}
+
+/// Return codes of the `frrs_*` C hook functions.
+pub mod c_hooks_status {
+    pub const OK: i32 = 0;
+    /// A pointer, length, level or name the function doesn't accept.
+    pub const INVALID: i32 = -1;
+    /// No counter has the id.
+    pub const INVALID_ID: i32 = -2;
+    /// No tunable has the name.
+    pub const NOT_FOUND: i32 = -3;
+    /// No sink is set on the Rust side.
+    pub const NO_SINK: i32 = -4;
+    /// The log line went over the rate limit and was dropped.
+    pub const RATE_LIMITED: i32 = -5;
+    /// `frrs_c_hooks_init` was called with another version.
+    pub const VERSION: i32 = -6;
+    /// `frrs_c_hooks_init` wasn't called yet.
+    pub const NOT_INITIALIZED: i32 = -7;
+}
@@ -0,0 +1,168 @@
+//! `extern "C"` functions letting C modules log, trace, bump event counters and read
+//! tunables.
+//!
+//! The build script writes the matching header, `frrs_c_hooks.h`, and passes its
+//! directory to the build scripts of dependents as `DEP_FREERTOS_C_HOOKS_INCLUDE`. A C
+//! module calls `frrs_c_hooks_init(FRRS_C_HOOKS_VERSION)` first; the other functions
+//! fail with `NOT_INITIALIZED` until one call passed the version this crate was built
+//! with.
+//!
+//! None of the functions block. They can be called from ISRs as long as the log and
+//! trace sinks can. Every pointer, length and id coming from C is checked.
+
+mod abi;
+
+pub use self::abi::*;
+
+use crate::event_counter::*;
+use crate::log_sink::*;
+use crate::prelude::v1::*;
+use crate::trace_sink::*;
+use crate::tunable::*;
+// Set from C code, so it has to be a const-initialised static.
+use core::sync::atomic::{AtomicBool, Ordering};
+
+static INITIALIZED: AtomicBool = AtomicBool::new(false);
+
+fn initialized() -> bool {
+    INITIALIZED.load(Ordering::Acquire)
+}
+
+/// The bytes of the NUL terminated string at `s`, if it's UTF-8 and at most `max` bytes
+/// long. Reads at most `max + 1` bytes.
+unsafe fn bounded_c_str<'a>(s: *const u8, max: usize) -> Option<&'a str> {
+    if s.is_null() {
+        return None;
+    }
+    let len = (0..=max).find(|&i| *s.add(i) == 0)?;
+    core::str::from_utf8(core::slice::from_raw_parts(s, len)).ok()
+}
+
+/// The `len` bytes at `data`, if at most `max`. `data` may be null when `len` is 0.
+unsafe fn bounded_bytes<'a>(data: *const u8, len: usize, max: usize) -> Option<&'a [u8]> {
+    if len > max {
+        None
+    } else if len == 0 {
+        Some(&[])
+    } else if data.is_null() {
+        None
+    } else {
+        Some(core::slice::from_raw_parts(data, len))
+    }
+}
+
+fn log_level(level: u8) -> Option<LogLevel> {
+    match level {
+        c_hooks_level::ERROR => Some(LogLevel::Error),
+        c_hooks_level::WARN => Some(LogLevel::Warn),
+        c_hooks_level::INFO => Some(LogLevel::Info),
+        c_hooks_level::DEBUG => Some(LogLevel::Debug),
+        _ => None,
+    }
+}
+
+/// Check the version the C module was built against.
+#[no_mangle]
+pub extern "C" fn frrs_c_hooks_init(version: u32) -> i32 {
+    if version != C_HOOKS_VERSION {
+        return c_hooks_status::VERSION;
+    }
+    INITIALIZED.store(true, Ordering::Release);
+    c_hooks_status::OK
+}
+
+/// Log the `len` bytes at `msg` under `module`, a NUL terminated name, through the
+/// log sink and its rate limit.
+///
+/// # Safety
+///
+/// `module` must be null or readable up to its NUL or `C_HOOKS_MODULE_MAX + 1` bytes,
+/// `msg` must be null or point to `len` readable bytes.
+#[no_mangle]
+pub unsafe extern "C" fn frrs_log_write(
+    level: u8,
+    module: *const u8,
+    msg: *const u8,
+    len: usize,
+) -> i32 {
+    if !initialized() {
+        return c_hooks_status::NOT_INITIALIZED;
+    }
+    let checked = (
+        log_level(level),
+        bounded_c_str(module, C_HOOKS_MODULE_MAX),
+        bounded_bytes(msg, len, C_HOOKS_LOG_MAX),
+    );
+    let (level, module, message) = match checked {
+        (Some(level), Some(module), Some(message)) => (level, module, message),
+        _ => return c_hooks_status::INVALID,
+    };
+
+    match log_write(level, module, message) {
+        Ok(()) => c_hooks_status::OK,
+        Err(LogDropped::NoSink) => c_hooks_status::NO_SINK,
+        Err(LogDropped::RateLimited) => c_hooks_status::RATE_LIMITED,
+    }
+}
+
+/// Record the `len` bytes at `payload` for `source_id` through the trace sink.
+///
+/// # Safety
+///
+/// `payload` must be null or point to `len` readable bytes.
+#[no_mangle]
+pub unsafe extern "C" fn frrs_trace_record(source_id: u16, payload: *const u8, len: usize) -> i32 {
+    if !initialized() {
+        return c_hooks_status::NOT_INITIALIZED;
+    }
+    let payload = match bounded_bytes(payload, len, C_HOOKS_TRACE_MAX) {
+        Some(payload) => payload,
+        None => return c_hooks_status::INVALID,
+    };
+
+    if trace_record(source_id, payload) {
+        c_hooks_status::OK
+    } else {
+        c_hooks_status::NO_SINK
+    }
+}
+
+/// Count one event on the counter with `id`, as returned by `EventCounter::register`.
+#[no_mangle]
+pub extern "C" fn frrs_counter_increment(id: u32) -> i32 {
+    if !initialized() {
+        return c_hooks_status::NOT_INITIALIZED;
+    }
+    match event_counter(EventCounterId(id)) {
+        Some(counter) => {
+            counter.increment();
+            c_hooks_status::OK
+        }
+        None => c_hooks_status::INVALID_ID,
+    }
+}
+
+/// Read the tunable called `name`, a NUL terminated string, into `out`.
+///
+/// # Safety
+///
+/// `name` must be null or readable up to its NUL or `C_HOOKS_TUNABLE_NAME_MAX + 1` bytes,
+/// `out` must be null or writable.
+#[no_mangle]
+pub unsafe extern "C" fn frrs_tunable_get_u32(name: *const u8, out: *mut u32) -> i32 {
+    if !initialized() {
+        return c_hooks_status::NOT_INITIALIZED;
+    }
+    let name = match bounded_c_str(name, C_HOOKS_TUNABLE_NAME_MAX) {
+        Some(name) if !out.is_null() => name,
+        _ => return c_hooks_status::INVALID,
+    };
+
+    match find_tunable(name) {
+        Some(tunable) => {
+            *out = tunable.get();
+            c_hooks_status::OK
+        }
+        None => c_hooks_status::NOT_FOUND,
+    }
+}
@@ -0,0 +1,420 @@
+use crate::base::*;
+#[cfg(feature = "cmsis-compat")]
+use crate::cmsis::CmsisObject;
+use crate::critical::*;
+use crate::event_counter::event_counter_fill;
+#[cfg(feature = "footprint_diag")]
+use crate::footprint::*;
+use crate::infra::*;
+use crate::prelude::v1::*;
+use crate::tunable::tunable_fill;
+// Counted from any task, so it has to be a const-initialised static.
+use core::sync::atomic::{AtomicU32, AtomicUsize, Ordering};
+
+/// A fixed-capacity registry kept by the crate.
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub enum RegistryKind {
+    /// Infrastructure tasks, see `InfraTask`.
+    Infrastructure,
+    /// The closure footprints recorded with the `footprint_diag` feature.
+    ClosureRecords,
+    /// A `HandleTable`. Its capacity is given when it is created rather than here.
+    HandleTable,
+    /// The objects created through the `cmsis-compat` functions, see `CMSIS_OBJECT_CAPACITY`.
+    CmsisObjects,
+    /// Registered `EventCounter`s, see `EVENT_COUNTER_CAPACITY`.
+    EventCounters,
+    /// Registered `Tunable`s, see `TUNABLE_CAPACITY`.
+    Tunables,
+}
+
+impl RegistryKind {
+    pub const ALL: [RegistryKind; 6] = [
+        RegistryKind::Infrastructure,
+        RegistryKind::ClosureRecords,
+        RegistryKind::HandleTable,
+        RegistryKind::CmsisObjects,
+        RegistryKind::EventCounters,
+        RegistryKind::Tunables,
+    ];
+
+    pub fn name(&self) -> &'static str {
+        match self {
+            RegistryKind::Infrastructure => "Infrastructure",
+            RegistryKind::ClosureRecords => "ClosureRecords",
+            RegistryKind::HandleTable => "HandleTable",
+            RegistryKind::CmsisObjects => "CmsisObjects",
+            RegistryKind::EventCounters => "EventCounters",
+            RegistryKind::Tunables => "Tunables",
+        }
+    }
+}
+
+/// The infrastructure task capacity used when `Registries::install` isn't called.
+#[cfg(feature = "large-targets")]
+pub const DEFAULT_INFRA_CAPACITY: usize = 32;
+#[cfg(all(feature = "small-targets", not(feature = "large-targets")))]
+pub const DEFAULT_INFRA_CAPACITY: usize = 4;
+#[cfg(not(any(feature = "small-targets", feature = "large-targets")))]
+pub const DEFAULT_INFRA_CAPACITY: usize = 8;
+
+/// The closure record capacity used when `Registries::install` isn't called.
+#[cfg(feature = "large-targets")]
+pub const DEFAULT_CLOSURE_RECORD_CAPACITY: usize = 128;
+#[cfg(all(feature = "small-targets", not(feature = "large-targets")))]
+pub const DEFAULT_CLOSURE_RECORD_CAPACITY: usize = 8;
+#[cfg(not(any(feature = "small-targets", feature = "large-targets")))]
+pub const DEFAULT_CLOSURE_RECORD_CAPACITY: usize = 32;
+
+/// How many CMSIS objects can exist at the same time. Not part of `Registries`, the
+/// storage is a static that only exists with the `cmsis-compat` feature.
+#[cfg(feature = "large-targets")]
+pub const CMSIS_OBJECT_CAPACITY: usize = 64;
+#[cfg(all(feature = "small-targets", not(feature = "large-targets")))]
+pub const CMSIS_OBJECT_CAPACITY: usize = 8;
+#[cfg(not(any(feature = "small-targets", feature = "large-targets")))]
+pub const CMSIS_OBJECT_CAPACITY: usize = 24;
+
+/// How many `EventCounter`s can be registered. Like the CMSIS objects, the storage is a
+/// static, so it can be read from ISRs.
+#[cfg(feature = "large-targets")]
+pub const EVENT_COUNTER_CAPACITY: usize = 64;
+#[cfg(all(feature = "small-targets", not(feature = "large-targets")))]
+pub const EVENT_COUNTER_CAPACITY: usize = 8;
+#[cfg(not(any(feature = "small-targets", feature = "large-targets")))]
+pub const EVENT_COUNTER_CAPACITY: usize = 16;
+
+/// How many `Tunable`s can be registered, in a static like the event counters.
+#[cfg(feature = "large-targets")]
+pub const TUNABLE_CAPACITY: usize = 64;
+#[cfg(all(feature = "small-targets", not(feature = "large-targets")))]
+pub const TUNABLE_CAPACITY: usize = 8;
+#[cfg(not(any(feature = "small-targets", feature = "large-targets")))]
+pub const TUNABLE_CAPACITY: usize = 16;
+
+/// How many tasks an `EmergencyBroadcast` can have aborted at the same time with the
+/// `emergency_abort` feature, in a static.
+#[cfg(feature = "large-targets")]
+pub const EMERGENCY_ABORT_CAPACITY: usize = 32;
+#[cfg(all(feature = "small-targets", not(feature = "large-targets")))]
+pub const EMERGENCY_ABORT_CAPACITY: usize = 4;
+#[cfg(not(any(feature = "small-targets", feature = "large-targets")))]
+pub const EMERGENCY_ABORT_CAPACITY: usize = 8;
+
+pub type DefaultRegistries = Registries<DEFAULT_INFRA_CAPACITY, DEFAULT_CLOSURE_RECORD_CAPACITY>;
+
+static OVERFLOWS: [AtomicU32; 6] = [
+    AtomicU32::new(0),
+    AtomicU32::new(0),
+    AtomicU32::new(0),
+    AtomicU32::new(0),
+    AtomicU32::new(0),
+    AtomicU32::new(0),
+];
+
+/// Count a registry overflow and build the error for it.
+pub(crate) fn registry_full(kind: RegistryKind) -> FreeRtosError {
+    OVERFLOWS[kind as usize].fetch_add(1, Ordering::Relaxed);
+    FreeRtosError::RegistryFull(kind)
+}
+
+/// How often `kind` was full when something had to be added to it.
+pub fn registry_overflows(kind: RegistryKind) -> u32 {
+    OVERFLOWS[kind as usize].load(Ordering::Relaxed)
+}
+
+/// The storage of the crate registries, with each capacity chosen by a const generic.
+///
+/// Put it in a static and `install` it before creating any task or timer:
+///
+/// ```rust
+/// # use freertos_rs::*;
+/// static REGISTRIES: Registries<4, 16> = Registries::new();
+/// REGISTRIES.install().unwrap();
+/// ```
+///
+/// Without an explicit call, `DefaultRegistries` are allocated on the heap the first
+/// time a registry is used. The `small-targets` and `large-targets` features pick
+/// smaller or larger defaults.
+///
+/// The closure record capacity only takes RAM with the `footprint_diag` feature.
+pub struct Registries<const INFRA: usize, const CLOSURES: usize> {
+    infra: UnsafeCell<[Option<InfraEntry>; INFRA]>,
+    #[cfg(feature = "footprint_diag")]
+    closures: UnsafeCell<[Option<ClosureRecord>; CLOSURES]>,
+}
+
+// Only accessed in critical sections.
+unsafe impl<const INFRA: usize, const CLOSURES: usize> Sync for Registries<INFRA, CLOSURES> {}
+
+impl<const INFRA: usize, const CLOSURES: usize> Registries<INFRA, CLOSURES> {
+    pub const fn new() -> Self {
+        Registries {
+            infra: UnsafeCell::new([const { None }; INFRA]),
+            #[cfg(feature = "footprint_diag")]
+            closures: UnsafeCell::new([const { None }; CLOSURES]),
+        }
+    }
+
+    /// The RAM these registries take, in bytes.
+    pub const fn ram_usage() -> usize {
+        mem::size_of::<Self>()
+    }
+
+    /// Use these registries from now on. Fails with `StorageInUse` if registries were
+    /// installed before, including the defaults installed by using a registry first.
+    pub fn install(&'static self) -> Result<(), FreeRtosError> {
+        let _lock = CriticalRegion::enter();
+        unsafe {
+            let installed = &mut *ptr::addr_of_mut!(INSTALLED);
+            if installed.is_some() {
+                return Err(FreeRtosError::StorageInUse);
+            }
+            *installed = Some(self);
+        }
+        Ok(())
+    }
+}
+
+impl<const INFRA: usize, const CLOSURES: usize> Default for Registries<INFRA, CLOSURES> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+/// The registries as seen by the rest of the crate, whatever their capacities.
+pub(crate) trait RegistryStorage: Sync {
+    fn infra(&self) -> *mut [Option<InfraEntry>];
+    #[cfg(feature = "footprint_diag")]
+    fn closures(&self) -> *mut [Option<ClosureRecord>];
+}
+
+impl<const INFRA: usize, const CLOSURES: usize> RegistryStorage for Registries<INFRA, CLOSURES> {
+    fn infra(&self) -> *mut [Option<InfraEntry>] {
+        self.infra.get()
+    }
+
+    #[cfg(feature = "footprint_diag")]
+    fn closures(&self) -> *mut [Option<ClosureRecord>] {
+        self.closures.get()
+    }
+}
+
+static mut INSTALLED: Option<&'static dyn RegistryStorage> = None;
+
+fn installed() -> Option<&'static dyn RegistryStorage> {
+    let _lock = CriticalRegion::enter();
+    unsafe { *ptr::addr_of!(INSTALLED) }
+}
+
+fn registries() -> &'static dyn RegistryStorage {
+    if let Some(registries) = installed() {
+        return registries;
+    }
+
+    let defaults: &'static DefaultRegistries = Box::leak(Box::new(DefaultRegistries::new()));
+    if defaults.install().is_err() {
+        // Installed by someone else in the meantime.
+        unsafe {
+            drop(Box::from_raw(
+                defaults as *const _ as *mut DefaultRegistries,
+            ))
+        };
+    }
+    installed().unwrap()
+}
+
+/// A registry's storage, entries kept at the front in the order they were added.
+pub(crate) struct Slots<'a, T> {
+    slots: &'a mut [Option<T>],
+}
+
+impl<'a, T> Slots<'a, T> {
+    pub(crate) fn len(&self) -> usize {
+        self.slots.iter().take_while(|s| s.is_some()).count()
+    }
+
+    pub(crate) fn capacity(&self) -> usize {
+        self.slots.len()
+    }
+
+    pub(crate) fn is_full(&self) -> bool {
+        self.len() == self.capacity()
+    }
+
+    /// Add `item` at the back, or hand it back if the registry is full.
+    pub(crate) fn push(&mut self, item: T) -> Result<(), T> {
+        let len = self.len();
+        match self.slots.get_mut(len) {
+            Some(slot) => {
+                *slot = Some(item);
+                Ok(())
+            }
+            None => Err(item),
+        }
+    }
+
+    pub(crate) fn pop(&mut self) -> Option<T> {
+        let len = self.len();
+        if len == 0 {
+            return None;
+        }
+        self.slots[len - 1].take()
+    }
+
+    pub(crate) fn remove(&mut self, index: usize) -> T {
+        let len = self.len();
+        let item = self.slots[index].take().unwrap();
+        self.slots[index..len].rotate_left(1);
+        item
+    }
+
+    pub(crate) fn iter(&self) -> impl Iterator<Item = &T> {
+        self.slots.iter().map_while(|s| s.as_ref())
+    }
+}
+
+/// Run `f` on the infrastructure task registry, in a critical section.
+pub(crate) fn with_infra_registry<R>(f: impl FnOnce(&mut Slots<InfraEntry>) -> R) -> R {
+    let registries = registries();
+    let _lock = CriticalRegion::enter();
+    f(&mut Slots {
+        slots: unsafe { &mut *registries.infra() },
+    })
+}
+
+/// Run `f` on the closure records, in a critical section.
+#[cfg(feature = "footprint_diag")]
+pub(crate) fn with_closure_records<R>(f: impl FnOnce(&mut Slots<ClosureRecord>) -> R) -> R {
+    let registries = registries();
+    let _lock = CriticalRegion::enter();
+    f(&mut Slots {
+        slots: unsafe { &mut *registries.closures() },
+    })
+}
+
+#[cfg(feature = "cmsis-compat")]
+static mut CMSIS_OBJECTS: [Option<CmsisObject>; CMSIS_OBJECT_CAPACITY] =
+    [const { None }; CMSIS_OBJECT_CAPACITY];
+
+/// Run `f` on the CMSIS object registry, in a critical section.
+#[cfg(feature = "cmsis-compat")]
+pub(crate) fn with_cmsis_registry<R>(f: impl FnOnce(&mut Slots<CmsisObject>) -> R) -> R {
+    let _lock = CriticalRegion::enter();
+    f(&mut Slots {
+        slots: unsafe { &mut *ptr::addr_of_mut!(CMSIS_OBJECTS) },
+    })
+}
+
+/// A registry entries are only ever added to. It is read without a critical section, so
+/// ISRs can look entries up: an entry is written before the length that covers it.
+pub(crate) struct AppendOnlySlots<T: Copy, const N: usize> {
+    slots: UnsafeCell<[Option<T>; N]>,
+    len: AtomicUsize,
+}
+
+// Written in critical sections, entries are never changed once published by `len`.
+unsafe impl<T: Copy + Send, const N: usize> Sync for AppendOnlySlots<T, N> {}
+
+impl<T: Copy, const N: usize> AppendOnlySlots<T, N> {
+    pub(crate) const fn new() -> Self {
+        AppendOnlySlots {
+            slots: UnsafeCell::new([None; N]),
+            len: AtomicUsize::new(0),
+        }
+    }
+
+    pub(crate) fn len(&self) -> usize {
+        self.len.load(Ordering::Acquire)
+    }
+
+    pub(crate) fn capacity(&self) -> usize {
+        N
+    }
+
+    pub(crate) fn get(&self, index: usize) -> Option<T> {
+        if index >= self.len() {
+            return None;
+        }
+        unsafe { (*self.slots.get())[index] }
+    }
+
+    pub(crate) fn iter(&self) -> impl Iterator<Item = T> + '_ {
+        (0..self.len()).filter_map(move |i| self.get(i))
+    }
+
+    /// Add `item`, or find the entry `same` matches, and return its index. Counts an
+    /// overflow of `kind` when full.
+    pub(crate) fn push(
+        &self,
+        kind: RegistryKind,
+        item: T,
+        same: impl Fn(&T) -> bool,
+    ) -> Result<usize, FreeRtosError> {
+        let _lock = CriticalRegion::enter();
+        if let Some(index) = self.iter().position(|t| same(&t)) {
+            return Ok(index);
+        }
+
+        let len = self.len();
+        if len == N {
+            return Err(registry_full(kind));
+        }
+        unsafe { (*self.slots.get())[len] = Some(item) };
+        self.len.store(len + 1, Ordering::Release);
+        Ok(len)
+    }
+}
+
+/// The fill level of a registry, for diagnostics.
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub struct RegistryStats {
+    pub kind: RegistryKind,
+    pub used: usize,
+    pub capacity: usize,
+    pub overflows: u32,
+}
+
+/// The fill level of the installed registries. Handle tables are left out, as each
+/// has a capacity of its own.
+pub fn registry_stats() -> Vec<RegistryStats> {
+    let stats = |kind, (used, capacity)| RegistryStats {
+        kind,
+        used,
+        capacity,
+        overflows: registry_overflows(kind),
+    };
+
+    let mut all = vec![stats(
+        RegistryKind::Infrastructure,
+        with_infra_registry(|r| (r.len(), r.capacity())),
+    )];
+    #[cfg(feature = "footprint_diag")]
+    all.push(stats(
+        RegistryKind::ClosureRecords,
+        with_closure_records(|r| (r.len(), r.capacity())),
+    ));
+    #[cfg(feature = "cmsis-compat")]
+    all.push(stats(
+        RegistryKind::CmsisObjects,
+        with_cmsis_registry(|r| (r.len(), r.capacity())),
+    ));
+    all.push(stats(RegistryKind::EventCounters, event_counter_fill()));
+    all.push(stats(RegistryKind::Tunables, tunable_fill()));
+    all
+}
+
+#[cfg(feature = "fmt")]
+impl fmt::Display for RegistryStats {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "{:<16} {:>5}/{:<5} overflows {}",
+            self.kind.name(),
+            self.used,
+            self.capacity,
+            self.overflows
+        )
+    }
+}
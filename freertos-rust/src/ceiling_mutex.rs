@@ -0,0 +1,135 @@
+use crate::base::*;
+use crate::isr::*;
+use crate::mutex::*;
+use crate::operating_system::*;
+use crate::prelude::v1::*;
+use crate::shim::*;
+use crate::task::*;
+use crate::units::*;
+
+pub type CeilingMutex<T> = CeilingMutexImpl<T, MutexNormal>;
+pub type RecursiveCeilingMutex<T> = CeilingMutexImpl<T, MutexRecursive>;
+
+impl<T, M> !ISRSafe for CeilingMutexImpl<T, M> {}
+
+/// A mutex with the immediate priority ceiling protocol: a task that locks it runs at
+/// the ceiling priority until it unlocks it, so no task up to the ceiling can preempt it
+/// in the critical section.
+///
+/// Plain mutexes only use the kernel's priority inheritance, which raises the holder
+/// once a higher priority task waits, see `Mutex`. The ceiling must be at least the
+/// priority of every task that locks the mutex.
+///
+/// Tasks below the ceiling are raised after they got the lock, and set back to the
+/// priority they had before locking by the guard's `Drop`, also when the critical section
+/// panics. A task locking a `RecursiveCeilingMutex` again is already at the ceiling, so
+/// only the outermost guard sets its priority back.
+pub struct CeilingMutexImpl<T, M> {
+    mutex: MutexImpl<T, M>,
+    ceiling: TaskPriority,
+}
+
+impl<T, M> CeilingMutexImpl<T, M>
+where
+    M: MutexInnerImpl,
+{
+    /// Lock the mutex, waiting up to `max_wait`, and raise the calling task to the
+    /// ceiling. The task's priority is left alone when the lock fails.
+    pub fn lock<D: DurationTicks>(
+        &self,
+        max_wait: D,
+    ) -> Result<CeilingMutexGuard<'_, T, M>, FreeRtosError> {
+        let guard = self.mutex.lock(max_wait)?;
+
+        let task = unsafe { freertos_rs_get_current_task() };
+        let priority = unsafe { freertos_rs_task_priority_get(task) } as u8;
+        let previous = if priority < self.ceiling.0 {
+            unsafe { freertos_rs_task_priority_set(task, self.ceiling.to_freertos()) };
+            Some(TaskPriority(priority))
+        } else {
+            None
+        };
+
+        Ok(CeilingMutexGuard {
+            guard: Some(guard),
+            task,
+            previous,
+        })
+    }
+
+    /// The priority a task holding the mutex runs at.
+    pub fn ceiling(&self) -> TaskPriority {
+        self.ceiling
+    }
+
+    /// Consume the mutex and return its inner value
+    pub fn into_inner(self) -> T {
+        self.mutex.into_inner()
+    }
+}
+
+impl<T> CeilingMutexImpl<T, MutexNormal> {
+    /// Create a new mutex with the given inner value, raising holders to `ceiling`
+    pub fn new(os: FreeRTOS, t: T, ceiling: TaskPriority) -> Result<Self, FreeRtosError> {
+        Ok(CeilingMutexImpl {
+            mutex: Mutex::new(os, t)?,
+            ceiling,
+        })
+    }
+}
+
+impl<T> CeilingMutexImpl<T, MutexRecursive> {
+    /// Create a new recursive mutex with the given inner value, raising holders to
+    /// `ceiling`
+    pub fn new(os: FreeRTOS, t: T, ceiling: TaskPriority) -> Result<Self, FreeRtosError> {
+        Ok(CeilingMutexImpl {
+            mutex: RecursiveMutex::new(os, t)?,
+            ceiling,
+        })
+    }
+}
+
+/// Holds the mutex, and the task at the ceiling, until we are dropped
+pub struct CeilingMutexGuard<'a, T: 'a, M: 'a>
+where
+    M: MutexInnerImpl,
+{
+    guard: Option<MutexGuard<'a, T, M>>,
+    task: FreeRtosTaskHandle,
+    /// The priority to set back, if locking raised the task.
+    previous: Option<TaskPriority>,
+}
+
+impl<'a, T, M> Deref for CeilingMutexGuard<'a, T, M>
+where
+    M: MutexInnerImpl,
+{
+    type Target = T;
+
+    fn deref(&self) -> &T {
+        self.guard.as_ref().unwrap()
+    }
+}
+
+impl<'a, T, M> DerefMut for CeilingMutexGuard<'a, T, M>
+where
+    M: MutexInnerImpl,
+{
+    fn deref_mut(&mut self) -> &mut T {
+        self.guard.as_mut().unwrap()
+    }
+}
+
+impl<'a, T, M> Drop for CeilingMutexGuard<'a, T, M>
+where
+    M: MutexInnerImpl,
+{
+    fn drop(&mut self) {
+        // Unlock at the ceiling, so a task waiting for the mutex below it doesn't run
+        // before the mutex is given back.
+        self.guard = None;
+        if let Some(previous) = self.previous {
+            unsafe { freertos_rs_task_priority_set(self.task, previous.to_freertos()) };
+        }
+    }
+}
@@ -0,0 +1,276 @@
+use crate::base::*;
+use crate::infra::*;
+use crate::isr::*;
+use crate::mutex::*;
+use crate::operating_system::*;
+use crate::prelude::v1::*;
+use crate::queue::*;
+use crate::sync::atomic::{AtomicU32, Ordering};
+use crate::task::*;
+use crate::units::*;
+
+impl !ISRSafe for TaskCensus {}
+impl !ISRSafe for TaskCensusSubscription {}
+
+/// What changed about a task.
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum TaskCensusChange {
+    Priority(TaskPriority),
+    /// Only reported when a task is suspended or resumed, not for ordinary scheduling.
+    State(FreeRtosTaskState),
+    /// Reported when the stack high water mark dropped by at least the census threshold.
+    StackHighWaterMark(FreeRtosStackDepthType),
+}
+
+/// A difference between two task censuses.
+///
+/// Tasks are identified by their handle and their task number. Task numbers are never
+/// reused, so a task created in the memory of a deleted one is reported as a new task.
+#[derive(Debug, Copy, Clone)]
+pub enum TaskCensusEvent {
+    Added {
+        task: FreeRtosTaskHandle,
+        task_number: FreeRtosUBaseType,
+        name: TaskName,
+        priority: TaskPriority,
+    },
+    Removed {
+        task: FreeRtosTaskHandle,
+        task_number: FreeRtosUBaseType,
+    },
+    Changed {
+        task: FreeRtosTaskHandle,
+        task_number: FreeRtosUBaseType,
+        change: TaskCensusChange,
+    },
+}
+
+struct CensusEntry {
+    task: FreeRtosTaskHandle,
+    task_number: FreeRtosUBaseType,
+    name: TaskName,
+    priority: TaskPriority,
+    state: FreeRtosTaskState,
+    stack_high_water_mark: FreeRtosStackDepthType,
+}
+
+unsafe impl Send for CensusEntry {}
+unsafe impl Sync for CensusEntry {}
+
+impl CensusEntry {
+    fn added(&self) -> TaskCensusEvent {
+        TaskCensusEvent::Added {
+            task: self.task,
+            task_number: self.task_number,
+            name: self.name,
+            priority: self.priority,
+        }
+    }
+
+    fn changed(&self, change: TaskCensusChange) -> TaskCensusEvent {
+        TaskCensusEvent::Changed {
+            task: self.task,
+            task_number: self.task_number,
+            change,
+        }
+    }
+}
+
+struct CensusSubscriber {
+    queue: Queue<TaskCensusEvent>,
+    missed: AtomicU32,
+}
+
+impl CensusSubscriber {
+    fn publish(&self, event: TaskCensusEvent) {
+        if self.queue.send(event, Duration::zero()).is_err() {
+            self.missed.fetch_add(1, Ordering::Relaxed);
+        }
+    }
+}
+
+struct CensusModel {
+    tasks: Vec<CensusEntry>,
+    subscribers: Vec<Arc<CensusSubscriber>>,
+}
+
+struct CensusShared {
+    model: Mutex<CensusModel>,
+    stack_threshold: AtomicU32,
+}
+
+impl CensusShared {
+    fn reconcile(&self, os: &FreeRTOS) -> Result<(), FreeRtosError> {
+        // Taken before locking the model, as the scheduler is suspended while it is collected.
+        let snapshot = os.get_all_tasks(None);
+        let stack_threshold = self.stack_threshold.load(Ordering::Relaxed);
+
+        let mut model = self.model.lock(Duration::infinite())?;
+        let mut events = Vec::new();
+
+        model.tasks.retain(|entry| {
+            let alive = snapshot
+                .tasks
+                .iter()
+                .any(|t| t.task.raw_handle() == entry.task && t.task_number == entry.task_number);
+            if !alive {
+                events.push(TaskCensusEvent::Removed {
+                    task: entry.task,
+                    task_number: entry.task_number,
+                });
+            }
+            alive
+        });
+
+        for status in snapshot.tasks.iter() {
+            let handle = status.task.raw_handle();
+            let existing = model
+                .tasks
+                .iter_mut()
+                .find(|e| e.task == handle && e.task_number == status.task_number);
+
+            let entry = match existing {
+                Some(entry) => entry,
+                None => {
+                    let entry = CensusEntry {
+                        task: handle,
+                        task_number: status.task_number,
+                        name: TaskName::from_bytes(status.name.as_bytes()),
+                        priority: status.current_priority,
+                        state: status.task_state,
+                        stack_high_water_mark: status.stack_high_water_mark,
+                    };
+                    events.push(entry.added());
+                    model.tasks.push(entry);
+                    continue;
+                }
+            };
+
+            if entry.priority != status.current_priority {
+                entry.priority = status.current_priority;
+                events.push(entry.changed(TaskCensusChange::Priority(entry.priority)));
+            }
+
+            let was_suspended = entry.state == FreeRtosTaskState::Suspended;
+            let is_suspended = status.task_state == FreeRtosTaskState::Suspended;
+            entry.state = status.task_state;
+            if was_suspended != is_suspended {
+                events.push(entry.changed(TaskCensusChange::State(entry.state)));
+            }
+
+            let stack_drop = entry
+                .stack_high_water_mark
+                .saturating_sub(status.stack_high_water_mark);
+            if stack_drop as u32 >= stack_threshold.max(1) {
+                entry.stack_high_water_mark = status.stack_high_water_mark;
+                events.push(entry.changed(TaskCensusChange::StackHighWaterMark(
+                    entry.stack_high_water_mark,
+                )));
+            }
+        }
+
+        // Subscriptions that were dropped only have our reference left.
+        model.subscribers.retain(|s| Arc::strong_count(s) > 1);
+        for event in events {
+            for subscriber in model.subscribers.iter() {
+                subscriber.publish(event);
+            }
+        }
+
+        Ok(())
+    }
+}
+
+/// Keeps a model of the running tasks and publishes the differences to subscribers,
+/// which is much cheaper for them than polling `get_all_tasks`.
+pub struct TaskCensus {
+    shared: Arc<CensusShared>,
+    task: InfraTask,
+}
+
+impl TaskCensus {
+    /// Stack high water mark drops smaller than this many words are not reported.
+    pub const DEFAULT_STACK_THRESHOLD: u32 = 32;
+
+    /// Start the census task, reconciling the model with the scheduler every `update_period`.
+    pub fn start<D: DurationTicks>(
+        os: FreeRTOS,
+        update_period: D,
+        stack_size: usize,
+        priority: TaskPriority,
+    ) -> Result<TaskCensus, FreeRtosError> {
+        let shared = Arc::new(CensusShared {
+            model: Mutex::new(
+                os,
+                CensusModel {
+                    tasks: Vec::new(),
+                    subscribers: Vec::new(),
+                },
+            )?,
+            stack_threshold: AtomicU32::new(Self::DEFAULT_STACK_THRESHOLD),
+        });
+
+        let task = {
+            let shared = shared.clone();
+            let update_period = Duration::ticks(update_period.to_ticks());
+
+            InfraTask::spawn(os, "census", stack_size, priority, move |ctx, os| {
+                while !ctx.should_stop() {
+                    let _ = shared.reconcile(&os);
+                    ctx.sleep(update_period);
+                }
+            })?
+        };
+
+        Ok(TaskCensus { shared, task })
+    }
+
+    /// Subscribe to census events, buffering up to `depth` of them.
+    ///
+    /// The subscription starts with an `Added` event for every task currently known.
+    pub fn subscribe(
+        &self,
+        os: FreeRTOS,
+        depth: usize,
+    ) -> Result<TaskCensusSubscription, FreeRtosError> {
+        let subscriber = Arc::new(CensusSubscriber {
+            queue: Queue::new(os, depth)?,
+            missed: AtomicU32::new(0),
+        });
+
+        let mut model = self.shared.model.lock(Duration::infinite())?;
+        for entry in model.tasks.iter() {
+            subscriber.publish(entry.added());
+        }
+        model.subscribers.push(subscriber.clone());
+
+        Ok(TaskCensusSubscription { subscriber })
+    }
+
+    /// Set how far, in words, a stack high water mark must drop before it is reported.
+    pub fn set_stack_threshold(&self, words: u32) {
+        self.shared.stack_threshold.store(words, Ordering::Relaxed);
+    }
+
+    /// The task running the census.
+    pub fn task(&self) -> &InfraTask {
+        &self.task
+    }
+}
+
+/// Receives census events. Events that don't fit in the subscription's queue are dropped
+/// and counted, without affecting other subscribers.
+pub struct TaskCensusSubscription {
+    subscriber: Arc<CensusSubscriber>,
+}
+
+impl TaskCensusSubscription {
+    pub fn receive<D: DurationTicks>(&self, max_wait: D) -> Result<TaskCensusEvent, FreeRtosError> {
+        self.subscriber.queue.receive(max_wait)
+    }
+
+    /// How many events were dropped because this subscriber fell behind.
+    pub fn missed(&self) -> u32 {
+        self.subscriber.missed.load(Ordering::Relaxed)
+    }
+}
@@ -0,0 +1,96 @@
+use crate::base::*;
+use crate::isr::*;
+use crate::operating_system::*;
+use crate::prelude::v1::*;
+use crate::queue::*;
+use crate::units::*;
+
+unsafe impl<T: Send> Send for Channel<T> {}
+unsafe impl<T: Send> Sync for Channel<T> {}
+
+impl<T: Send> !ISRSafe for Channel<T> {}
+
+/// A queue for values that aren't `Copy`, like `Box`es, `String`s or anything with a
+/// `Drop` impl.
+///
+/// Each value is moved into a `Box` by `send` and only the pointer goes through the
+/// underlying `Queue`, so `receive` gets the very value that was sent. Values still in
+/// the channel when it is dropped are dropped with it.
+///
+/// As `send` allocates, there is no `ISRSafeHandle` for a channel: interrupts use a
+/// `Queue` of `Copy` values.
+pub struct Channel<T: Send> {
+    queue: Queue<*mut T>,
+}
+
+impl<T: Send> Channel<T> {
+    pub fn new(os: FreeRTOS, max_size: usize) -> Result<Channel<T>, FreeRtosError> {
+        Ok(Channel {
+            queue: Queue::new(os, max_size)?,
+        })
+    }
+
+    /// Send a value, waiting for the channel to have room for it. On failure the value is
+    /// handed back in the error.
+    pub fn send<D: DurationTicks>(&self, item: T, max_wait: D) -> Result<(), ChannelSendError<T>> {
+        let item = Box::into_raw(Box::new(item));
+
+        match self.queue.send(item, max_wait) {
+            Ok(()) => Ok(()),
+            Err(error) => Err(ChannelSendError {
+                error,
+                item: *unsafe { Box::from_raw(item) },
+            }),
+        }
+    }
+
+    /// Wait for a value to be available.
+    pub fn receive<D: DurationTicks>(&self, max_wait: D) -> Result<T, FreeRtosError> {
+        let item = self.queue.receive(max_wait)?;
+        Ok(*unsafe { Box::from_raw(item) })
+    }
+
+    /// The number of values waiting in the channel.
+    pub fn len(&self) -> usize {
+        self.queue.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.queue.is_empty()
+    }
+
+    pub fn max_size(&self) -> usize {
+        self.queue.max_size()
+    }
+}
+
+impl<T: Send> Drop for Channel<T> {
+    fn drop(&mut self) {
+        while let Ok(item) = self.queue.receive(Duration::zero()) {
+            drop(unsafe { Box::from_raw(item) });
+        }
+    }
+}
+
+/// A value that couldn't be sent on a `Channel`, with the reason.
+///
+/// Converts into `FreeRtosError`, dropping the value, so `?` still works in functions
+/// returning `FreeRtosError`.
+pub struct ChannelSendError<T> {
+    pub error: FreeRtosError,
+    pub item: T,
+}
+
+impl<T> fmt::Debug for ChannelSendError<T> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_struct("ChannelSendError")
+            .field("error", &self.error)
+            .finish()
+    }
+}
+
+impl<T> From<ChannelSendError<T>> for FreeRtosError {
+    fn from(e: ChannelSendError<T>) -> FreeRtosError {
+        e.error
+    }
+}
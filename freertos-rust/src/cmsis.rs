@@ -0,0 +1,609 @@
+//! A subset of the CMSIS-RTOS2 C API on top of the crate's types, for vendor middleware
+//! written against `cmsis_os2.h`.
+//!
+//! | CMSIS-RTOS2 | Backed by |
+//! |---|---|
+//! | `osMessageQueue*` | a kernel queue of `msg_size` byte items, like a `Queue<[u8; N]>` |
+//! | `osSemaphore*` | `CountingSemaphore` |
+//! | `osMutex*` | `Mutex<()>`, or `RecursiveMutex<()>` with `osMutexRecursive` |
+//! | `osThreadNew` | `TaskBuilder::start` |
+//! | `osDelay` | `FreeRTOS::delay` |
+//!
+//! Every object is recorded with its kind and name, see `objects`, and queues,
+//! semaphores and mutexes are added to the kernel queue registry when
+//! `configQUEUE_REGISTRY_SIZE` is above 0. Objects created without a name get the name
+//! of their CMSIS type, like `osMessageQueue`. Threads are ordinary tasks, so they also
+//! show up in a `TaskCensus`.
+//!
+//! Timeouts are in ticks, with `osWaitForever` waiting forever. Priorities from
+//! `osPriorityIdle` to `osPriorityRealtime7` are spread over `0..configMAX_PRIORITIES`,
+//! so CMSIS priorities that are close together can end up the same. Stack sizes are in
+//! bytes and default to 512 words.
+//!
+//! Not supported, failing with `osErrorNotImplemented` or, for the `*New` functions,
+//! returning NULL:
+//!
+//! * caller-provided memory: `cb_mem`, `stack_mem` and `mq_mem`;
+//! * joinable threads, TrustZone modules and robust mutexes;
+//! * message priorities other than 0.
+//!
+//! `osErrorNotImplemented` isn't part of CMSIS-RTOS2, it is `-7` here. The functions
+//! can only be called from tasks, not from interrupts.
+
+use crate::base::*;
+use crate::capacities::*;
+use crate::critical::*;
+use crate::mutex::*;
+use crate::operating_system::*;
+use crate::prelude::v1::*;
+use crate::semaphore::*;
+use crate::shim::*;
+use crate::task::*;
+use crate::units::*;
+use crate::utils::*;
+
+pub type osStatus_t = i32;
+pub const osOK: osStatus_t = 0;
+pub const osError: osStatus_t = -1;
+pub const osErrorTimeout: osStatus_t = -2;
+pub const osErrorResource: osStatus_t = -3;
+pub const osErrorParameter: osStatus_t = -4;
+pub const osErrorNoMemory: osStatus_t = -5;
+pub const osErrorISR: osStatus_t = -6;
+/// Returned for the CMSIS features this layer doesn't have, see the module docs.
+pub const osErrorNotImplemented: osStatus_t = -7;
+
+pub const osWaitForever: u32 = 0xFFFF_FFFF;
+
+pub type osPriority_t = i32;
+pub const osPriorityNone: osPriority_t = 0;
+pub const osPriorityIdle: osPriority_t = 1;
+pub const osPriorityNormal: osPriority_t = 24;
+pub const osPriorityRealtime7: osPriority_t = 55;
+
+pub const osThreadJoinable: u32 = 0x1;
+pub const osMutexRecursive: u32 = 0x1;
+pub const osMutexPrioInherit: u32 = 0x2;
+pub const osMutexRobust: u32 = 0x8;
+
+pub type osThreadId_t = *mut CVoid;
+pub type osMessageQueueId_t = *mut CVoid;
+pub type osSemaphoreId_t = *mut CVoid;
+pub type osMutexId_t = *mut CVoid;
+pub type osThreadFunc_t = extern "C" fn(argument: *mut CVoid);
+
+#[repr(C)]
+pub struct osThreadAttr_t {
+    pub name: *const u8,
+    pub attr_bits: u32,
+    pub cb_mem: *mut CVoid,
+    pub cb_size: u32,
+    pub stack_mem: *mut CVoid,
+    pub stack_size: u32,
+    pub priority: osPriority_t,
+    pub tz_module: u32,
+    pub reserved: u32,
+}
+
+#[repr(C)]
+pub struct osMessageQueueAttr_t {
+    pub name: *const u8,
+    pub attr_bits: u32,
+    pub cb_mem: *mut CVoid,
+    pub cb_size: u32,
+    pub mq_mem: *mut CVoid,
+    pub mq_size: u32,
+}
+
+#[repr(C)]
+pub struct osSemaphoreAttr_t {
+    pub name: *const u8,
+    pub attr_bits: u32,
+    pub cb_mem: *mut CVoid,
+    pub cb_size: u32,
+}
+
+#[repr(C)]
+pub struct osMutexAttr_t {
+    pub name: *const u8,
+    pub attr_bits: u32,
+    pub cb_mem: *mut CVoid,
+    pub cb_size: u32,
+}
+
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum CmsisObjectKind {
+    MessageQueue,
+    Semaphore,
+    Mutex,
+    Thread,
+}
+
+/// An object created through the CMSIS-RTOS2 functions.
+#[derive(Debug, Copy, Clone)]
+pub struct CmsisObject {
+    pub kind: CmsisObjectKind,
+    pub name: TaskName,
+    /// The kernel queue handle, or the task handle of a thread.
+    pub handle: *const CVoid,
+}
+
+unsafe impl Send for CmsisObject {}
+
+/// A snapshot of the live CMSIS objects, oldest first.
+pub fn objects() -> Vec<CmsisObject> {
+    with_cmsis_registry(|objects| objects.iter().copied().collect())
+}
+
+fn register(kind: CmsisObjectKind, name: TaskName, handle: *const CVoid) -> bool {
+    let object = CmsisObject { kind, name, handle };
+    if with_cmsis_registry(|objects| objects.push(object)).is_err() {
+        registry_full(RegistryKind::CmsisObjects);
+        return false;
+    }
+    true
+}
+
+fn unregister(handle: *const CVoid) {
+    with_cmsis_registry(|objects| {
+        let index = objects.iter().position(|o| o.handle == handle);
+        if let Some(index) = index {
+            objects.remove(index);
+        }
+    });
+}
+
+/// An object name, NUL terminated for the kernel queue registry.
+struct CName([u8; TaskName::MAX_LEN + 1]);
+
+impl CName {
+    unsafe fn new(name: *const u8, default: &str) -> CName {
+        let name = if name.is_null() {
+            default.as_bytes()
+        } else {
+            bytes_from_c_string(name)
+        };
+        let mut c_name = [0; TaskName::MAX_LEN + 1];
+        let len = name.len().min(TaskName::MAX_LEN);
+        c_name[..len].copy_from_slice(&name[..len]);
+        CName(c_name)
+    }
+
+    fn task_name(&self) -> TaskName {
+        let len = self.0.iter().position(|&b| b == 0).unwrap();
+        TaskName::from_bytes(&self.0[..len])
+    }
+}
+
+/// Box `object`, register it and add it to the kernel queue registry.
+fn new_queue_object<T>(kind: CmsisObjectKind, handle: FreeRtosQueueHandle, object: T) -> *mut CVoid
+where
+    T: QueueObject,
+{
+    let object = Box::new(object);
+    if !register(kind, object.name().task_name(), handle) {
+        return ptr::null_mut();
+    }
+    unsafe { freertos_rs_queue_add_to_registry(handle, object.name().0.as_ptr()) };
+    Box::into_raw(object) as *mut CVoid
+}
+
+/// Unregister and free an object made by `new_queue_object`.
+unsafe fn delete_queue_object<T: QueueObject>(id: *mut CVoid) -> osStatus_t {
+    if id.is_null() {
+        return osErrorParameter;
+    }
+    let object = Box::from_raw(id as *mut T);
+    unregister(object.handle());
+    drop(object);
+    osOK
+}
+
+trait QueueObject {
+    fn name(&self) -> &CName;
+    fn handle(&self) -> FreeRtosQueueHandle;
+}
+
+fn wait_status(timeout: u32) -> osStatus_t {
+    if timeout == 0 {
+        osErrorResource
+    } else {
+        osErrorTimeout
+    }
+}
+
+struct MessageQueue {
+    queue: FreeRtosQueueHandle,
+    msg_size: u32,
+    msg_count: u32,
+    name: CName,
+}
+
+impl QueueObject for MessageQueue {
+    fn name(&self) -> &CName {
+        &self.name
+    }
+
+    fn handle(&self) -> FreeRtosQueueHandle {
+        self.queue
+    }
+}
+
+impl Drop for MessageQueue {
+    fn drop(&mut self) {
+        unsafe { freertos_rs_queue_delete(self.queue) }
+    }
+}
+
+#[no_mangle]
+pub unsafe extern "C" fn osMessageQueueNew(
+    msg_count: u32,
+    msg_size: u32,
+    attr: *const osMessageQueueAttr_t,
+) -> osMessageQueueId_t {
+    if msg_count == 0 || msg_size == 0 {
+        return ptr::null_mut();
+    }
+    let attr = attr.as_ref();
+    if attr.map_or(false, |a| !a.cb_mem.is_null() || !a.mq_mem.is_null()) {
+        return ptr::null_mut();
+    }
+
+    let queue = freertos_rs_queue_create(msg_count, msg_size);
+    if queue.is_null() {
+        return ptr::null_mut();
+    }
+    let queue = MessageQueue {
+        queue,
+        msg_size,
+        msg_count,
+        name: CName::new(attr.map_or(ptr::null(), |a| a.name), "osMessageQueue"),
+    };
+    new_queue_object(CmsisObjectKind::MessageQueue, queue.queue, queue)
+}
+
+/// Copy the `msg_size` bytes at `msg_ptr` to the back of the queue.
+#[no_mangle]
+pub unsafe extern "C" fn osMessageQueuePut(
+    mq_id: osMessageQueueId_t,
+    msg_ptr: *const CVoid,
+    msg_prio: u8,
+    timeout: u32,
+) -> osStatus_t {
+    let queue = match (mq_id as *const MessageQueue).as_ref() {
+        Some(queue) if !msg_ptr.is_null() => queue,
+        _ => return osErrorParameter,
+    };
+    if msg_prio != 0 {
+        return osErrorNotImplemented;
+    }
+
+    match freertos_rs_queue_send(queue.queue, msg_ptr, timeout) {
+        0 => osOK,
+        _ => wait_status(timeout),
+    }
+}
+
+/// Copy the message at the front of the queue to the `msg_size` bytes at `msg_ptr`.
+/// Messages all have priority 0.
+#[no_mangle]
+pub unsafe extern "C" fn osMessageQueueGet(
+    mq_id: osMessageQueueId_t,
+    msg_ptr: *mut CVoid,
+    msg_prio: *mut u8,
+    timeout: u32,
+) -> osStatus_t {
+    let queue = match (mq_id as *const MessageQueue).as_ref() {
+        Some(queue) if !msg_ptr.is_null() => queue,
+        _ => return osErrorParameter,
+    };
+
+    match freertos_rs_queue_receive(queue.queue, msg_ptr, timeout) {
+        0 => {
+            if !msg_prio.is_null() {
+                *msg_prio = 0;
+            }
+            osOK
+        }
+        _ => wait_status(timeout),
+    }
+}
+
+#[no_mangle]
+pub unsafe extern "C" fn osMessageQueueGetCapacity(mq_id: osMessageQueueId_t) -> u32 {
+    (mq_id as *const MessageQueue)
+        .as_ref()
+        .map_or(0, |q| q.msg_count)
+}
+
+#[no_mangle]
+pub unsafe extern "C" fn osMessageQueueGetMsgSize(mq_id: osMessageQueueId_t) -> u32 {
+    (mq_id as *const MessageQueue)
+        .as_ref()
+        .map_or(0, |q| q.msg_size)
+}
+
+#[no_mangle]
+pub unsafe extern "C" fn osMessageQueueGetCount(mq_id: osMessageQueueId_t) -> u32 {
+    (mq_id as *const MessageQueue)
+        .as_ref()
+        .map_or(0, |q| freertos_rs_queue_messages_waiting(q.queue))
+}
+
+#[no_mangle]
+pub unsafe extern "C" fn osMessageQueueGetSpace(mq_id: osMessageQueueId_t) -> u32 {
+    (mq_id as *const MessageQueue)
+        .as_ref()
+        .map_or(0, |q| freertos_rs_queue_spaces_available(q.queue))
+}
+
+/// Delete the queue. Tasks blocked on it must be woken up first.
+#[no_mangle]
+pub unsafe extern "C" fn osMessageQueueDelete(mq_id: osMessageQueueId_t) -> osStatus_t {
+    delete_queue_object::<MessageQueue>(mq_id)
+}
+
+struct CmsisSemaphore {
+    semaphore: CountingSemaphore,
+    name: CName,
+}
+
+impl QueueObject for CmsisSemaphore {
+    fn name(&self) -> &CName {
+        &self.name
+    }
+
+    fn handle(&self) -> FreeRtosQueueHandle {
+        Semaphore::raw_handle(&self.semaphore)
+    }
+}
+
+#[no_mangle]
+pub unsafe extern "C" fn osSemaphoreNew(
+    max_count: u32,
+    initial_count: u32,
+    attr: *const osSemaphoreAttr_t,
+) -> osSemaphoreId_t {
+    if max_count == 0 || initial_count > max_count {
+        return ptr::null_mut();
+    }
+    let attr = attr.as_ref();
+    if attr.map_or(false, |a| !a.cb_mem.is_null()) {
+        return ptr::null_mut();
+    }
+
+    let semaphore = match CountingSemaphore::new(FreeRTOS {}, max_count, initial_count) {
+        Ok(semaphore) => CmsisSemaphore {
+            semaphore,
+            name: CName::new(attr.map_or(ptr::null(), |a| a.name), "osSemaphore"),
+        },
+        Err(_) => return ptr::null_mut(),
+    };
+    new_queue_object(CmsisObjectKind::Semaphore, semaphore.handle(), semaphore)
+}
+
+#[no_mangle]
+pub unsafe extern "C" fn osSemaphoreAcquire(
+    semaphore_id: osSemaphoreId_t,
+    timeout: u32,
+) -> osStatus_t {
+    let semaphore = match (semaphore_id as *const CmsisSemaphore).as_ref() {
+        Some(semaphore) => semaphore,
+        None => return osErrorParameter,
+    };
+
+    match semaphore.semaphore.take(Duration::ticks(timeout)) {
+        Ok(()) => osOK,
+        Err(_) => wait_status(timeout),
+    }
+}
+
+/// Fails with `osErrorResource` when the count is already at its maximum.
+#[no_mangle]
+pub unsafe extern "C" fn osSemaphoreRelease(semaphore_id: osSemaphoreId_t) -> osStatus_t {
+    match (semaphore_id as *const CmsisSemaphore).as_ref() {
+        Some(semaphore) if semaphore.semaphore.try_give() => osOK,
+        Some(_) => osErrorResource,
+        None => osErrorParameter,
+    }
+}
+
+#[no_mangle]
+pub unsafe extern "C" fn osSemaphoreGetCount(semaphore_id: osSemaphoreId_t) -> u32 {
+    (semaphore_id as *const CmsisSemaphore)
+        .as_ref()
+        .map_or(0, |s| s.semaphore.get_count())
+}
+
+#[no_mangle]
+pub unsafe extern "C" fn osSemaphoreDelete(semaphore_id: osSemaphoreId_t) -> osStatus_t {
+    delete_queue_object::<CmsisSemaphore>(semaphore_id)
+}
+
+enum CmsisMutexKind {
+    Normal(Mutex<()>),
+    Recursive(RecursiveMutex<()>),
+}
+
+struct CmsisMutex {
+    mutex: CmsisMutexKind,
+    name: CName,
+}
+
+impl QueueObject for CmsisMutex {
+    fn name(&self) -> &CName {
+        &self.name
+    }
+
+    fn handle(&self) -> FreeRtosQueueHandle {
+        match &self.mutex {
+            CmsisMutexKind::Normal(mutex) => mutex.inner().raw_handle(),
+            CmsisMutexKind::Recursive(mutex) => mutex.inner().raw_handle(),
+        }
+    }
+}
+
+/// Kernel mutexes always inherit priority, with or without `osMutexPrioInherit`.
+#[no_mangle]
+pub unsafe extern "C" fn osMutexNew(attr: *const osMutexAttr_t) -> osMutexId_t {
+    let attr = attr.as_ref();
+    if attr.map_or(false, |a| {
+        !a.cb_mem.is_null() || a.attr_bits & osMutexRobust != 0
+    }) {
+        return ptr::null_mut();
+    }
+
+    let mutex = if attr.map_or(false, |a| a.attr_bits & osMutexRecursive != 0) {
+        RecursiveMutex::new(FreeRTOS {}, ()).map(CmsisMutexKind::Recursive)
+    } else {
+        Mutex::new(FreeRTOS {}, ()).map(CmsisMutexKind::Normal)
+    };
+    let mutex = match mutex {
+        Ok(mutex) => CmsisMutex {
+            mutex,
+            name: CName::new(attr.map_or(ptr::null(), |a| a.name), "osMutex"),
+        },
+        Err(_) => return ptr::null_mut(),
+    };
+    new_queue_object(CmsisObjectKind::Mutex, mutex.handle(), mutex)
+}
+
+#[no_mangle]
+pub unsafe extern "C" fn osMutexAcquire(mutex_id: osMutexId_t, timeout: u32) -> osStatus_t {
+    let result = match (mutex_id as *const CmsisMutex).as_ref() {
+        Some(CmsisMutex {
+            mutex: CmsisMutexKind::Normal(mutex),
+            ..
+        }) => mutex.inner().take(Duration::ticks(timeout)),
+        Some(CmsisMutex {
+            mutex: CmsisMutexKind::Recursive(mutex),
+            ..
+        }) => mutex.inner().take(Duration::ticks(timeout)),
+        None => return osErrorParameter,
+    };
+
+    match result {
+        Ok(()) => osOK,
+        Err(_) => wait_status(timeout),
+    }
+}
+
+/// Fails with `osErrorResource` when the calling thread doesn't hold the mutex.
+#[no_mangle]
+pub unsafe extern "C" fn osMutexRelease(mutex_id: osMutexId_t) -> osStatus_t {
+    let mutex = match (mutex_id as *const CmsisMutex).as_ref() {
+        Some(mutex) => mutex,
+        None => return osErrorParameter,
+    };
+
+    let released = match &mutex.mutex {
+        CmsisMutexKind::Normal(_) => freertos_rs_give_semaphore(mutex.handle()) == 0,
+        CmsisMutexKind::Recursive(_) => freertos_rs_give_recursive_semaphore(mutex.handle()) == 0,
+    };
+    if released {
+        osOK
+    } else {
+        osErrorResource
+    }
+}
+
+#[no_mangle]
+pub unsafe extern "C" fn osMutexDelete(mutex_id: osMutexId_t) -> osStatus_t {
+    delete_queue_object::<CmsisMutex>(mutex_id)
+}
+
+struct ThreadStart {
+    func: osThreadFunc_t,
+    argument: *mut CVoid,
+}
+
+unsafe impl Send for ThreadStart {}
+
+/// `osPriorityIdle..=osPriorityRealtime7` spread over the kernel priorities.
+fn thread_priority(priority: osPriority_t) -> Option<TaskPriority> {
+    let priority = match priority {
+        osPriorityNone => osPriorityNormal,
+        osPriorityIdle..=osPriorityRealtime7 => priority,
+        _ => return None,
+    };
+    let max = unsafe { freertos_rs_get_max_priorities() } as i32;
+    Some(TaskPriority(
+        ((priority - osPriorityIdle) * (max - 1) / (osPriorityRealtime7 - osPriorityIdle)) as u8,
+    ))
+}
+
+/// Start `func` in a new task. The task is deleted when `func` returns.
+#[no_mangle]
+pub unsafe extern "C" fn osThreadNew(
+    func: Option<osThreadFunc_t>,
+    argument: *mut CVoid,
+    attr: *const osThreadAttr_t,
+) -> osThreadId_t {
+    let func = match func {
+        Some(func) => func,
+        None => return ptr::null_mut(),
+    };
+    let attr = attr.as_ref();
+    if let Some(attr) = attr {
+        if !attr.cb_mem.is_null()
+            || !attr.stack_mem.is_null()
+            || attr.attr_bits & osThreadJoinable != 0
+            || attr.tz_module != 0
+        {
+            return ptr::null_mut();
+        }
+    }
+
+    let name = CName::new(attr.map_or(ptr::null(), |a| a.name), "osThread");
+    let priority = match thread_priority(attr.map_or(osPriorityNone, |a| a.priority)) {
+        Some(priority) => priority,
+        None => return ptr::null_mut(),
+    };
+
+    let mut builder = TaskBuilder::new(FreeRTOS {});
+    builder
+        .name(truncate_name(
+            name.task_name().as_str().unwrap_or("osThread"),
+        ))
+        .priority(priority);
+    let stack_size = attr.map_or(0, |a| a.stack_size);
+    if stack_size > 0 {
+        builder.stack_size((stack_size / freertos_rs_stack_type_size()) as usize);
+    }
+
+    let start = ThreadStart { func, argument };
+
+    // Registered before the task can run, so it can't finish before its entry exists.
+    let _suspension = SchedulerSuspension::enter();
+    let task = builder.start(move |this, _| {
+        (start.func)(start.argument);
+        unregister(this.raw_handle());
+        this.delete()
+    });
+    match task {
+        Ok(task) => {
+            if !register(CmsisObjectKind::Thread, name.task_name(), task.raw_handle()) {
+                freertos_rs_delete_task(task.raw_handle());
+                return ptr::null_mut();
+            }
+            task.raw_handle() as osThreadId_t
+        }
+        Err(_) => ptr::null_mut(),
+    }
+}
+
+#[no_mangle]
+pub unsafe extern "C" fn osThreadGetId() -> osThreadId_t {
+    freertos_rs_get_current_task() as osThreadId_t
+}
+
+#[no_mangle]
+pub unsafe extern "C" fn osDelay(ticks: u32) -> osStatus_t {
+    FreeRTOS {}.delay(Duration::ticks(ticks));
+    osOK
+}
+
+#[no_mangle]
+pub unsafe extern "C" fn osKernelGetTickCount() -> u32 {
+    FreeRTOS {}.get_tick_count()
+}
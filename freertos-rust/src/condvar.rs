@@ -0,0 +1,85 @@
+use crate::base::*;
+use crate::isr::*;
+use crate::mutex::*;
+use crate::operating_system::*;
+use crate::semaphore::*;
+use crate::shim::*;
+use crate::units::*;
+
+impl !ISRSafe for Condvar {}
+
+/// Blocks tasks until another task changes the state behind a `Mutex`, like
+/// `std::sync::Condvar`.
+///
+/// Built from a `CountingSemaphore` with one permit per notified waiter, and a `Mutex`
+/// counting the waiters that haven't been notified yet. A permit goes to whichever
+/// waiter takes it first, not necessarily the one waiting longest, and the state may
+/// have changed again by the time a woken waiter has the mutex back. Check the condition
+/// in a loop around `wait`.
+pub struct Condvar {
+    permits: CountingSemaphore,
+    waiters: Mutex<u32>,
+}
+
+impl Condvar {
+    pub fn new(os: FreeRTOS) -> Result<Condvar, FreeRtosError> {
+        Ok(Condvar {
+            permits: CountingSemaphore::new(os, u32::MAX, 0)?,
+            waiters: Mutex::new(os, 0)?,
+        })
+    }
+
+    /// Unlock the mutex of `guard` and wait up to `timeout` for a notification, then lock
+    /// it again in what is left of `timeout`.
+    ///
+    /// Fails with `Timeout` if no notification came, and with `MutexTimeout` if the mutex
+    /// couldn't be locked again in time. The mutex is unlocked either way.
+    pub fn wait<'a, T, D: DurationTicks>(
+        &self,
+        guard: MutexGuard<'a, T, MutexNormal>,
+        timeout: D,
+    ) -> Result<MutexGuard<'a, T, MutexNormal>, FreeRtosError> {
+        let start = unsafe { freertos_rs_xTaskGetTickCount() };
+        let timeout = timeout.to_ticks();
+
+        // Counted before the mutex is unlocked, so a notification sent right after
+        // is for this task.
+        *self.waiters.lock(Duration::infinite())? += 1;
+        let unlocked = guard.unlock();
+
+        if self.permits.take(Duration::ticks(timeout)).is_err() {
+            let mut waiters = self.waiters.lock(Duration::infinite())?;
+            // A notification may have come after the take gave up. Its waiter was
+            // already uncounted.
+            if self.permits.take(Duration::zero()).is_err() {
+                *waiters -= 1;
+                return Err(timed_out());
+            }
+        }
+
+        let left = remaining_wait(start, timeout).unwrap_or_else(Duration::zero);
+        unlocked.lock(left.to_ticks())
+    }
+
+    /// Wake one waiting task, if any.
+    pub fn notify_one(&self) {
+        self.notify(1);
+    }
+
+    /// Wake all waiting tasks.
+    pub fn notify_all(&self) {
+        self.notify(u32::MAX);
+    }
+
+    fn notify(&self, max: u32) {
+        let mut waiters = match self.waiters.lock(Duration::infinite()) {
+            Ok(waiters) => waiters,
+            Err(_) => return,
+        };
+        let woken = (*waiters).min(max);
+        *waiters -= woken;
+        for _ in 0..woken {
+            self.permits.give();
+        }
+    }
+}
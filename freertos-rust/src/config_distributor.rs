@@ -0,0 +1,243 @@
+use crate::base::*;
+use crate::critical::*;
+use crate::isr::*;
+use crate::operating_system::*;
+use crate::owner::*;
+use crate::prelude::v1::*;
+use crate::shim::*;
+use crate::sync::atomic::{fence, AtomicU32, Ordering};
+use crate::task::*;
+use crate::ticks::*;
+use crate::units::*;
+use core::cell::UnsafeCell;
+
+impl<T: Copy + Send> !ISRSafe for ConfigDistributor<T> {}
+impl<T: Copy + Send> !ISRSafe for ConfigConsumer<T> {}
+
+/// The version of a published configuration. Versions count up from 0, the initial value.
+#[derive(Debug, Copy, Clone, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub struct ConfigVersion(pub u32);
+
+/// A consumer that hasn't acknowledged a version yet.
+#[derive(Debug, Copy, Clone)]
+pub struct ConfigLaggard {
+    pub task: FreeRtosTaskHandle,
+    pub name: TaskName,
+    pub acknowledged: ConfigVersion,
+}
+
+struct ConsumerEntry {
+    task: FreeRtosTaskHandle,
+    name: TaskName,
+    bit: u32,
+    acknowledged: AtomicU32,
+}
+
+unsafe impl Send for ConsumerEntry {}
+unsafe impl Sync for ConsumerEntry {}
+
+struct ConfigState<T: Copy> {
+    /// Twice the current version, plus one while a publish is writing `value`.
+    sequence: AtomicU32,
+    value: UnsafeCell<T>,
+    consumers: ExclusiveData<Vec<Arc<ConsumerEntry>>>,
+}
+
+unsafe impl<T: Copy + Send> Send for ConfigState<T> {}
+unsafe impl<T: Copy + Send> Sync for ConfigState<T> {}
+
+impl<T: Copy> ConfigState<T> {
+    fn read(&self) -> (ConfigVersion, T) {
+        loop {
+            let before = self.sequence.load(Ordering::Acquire);
+            if before & 1 != 0 {
+                continue;
+            }
+
+            let value = unsafe { ptr::read_volatile(self.value.get()) };
+            fence(Ordering::Acquire);
+
+            if self.sequence.load(Ordering::Relaxed) == before {
+                return (ConfigVersion(before >> 1), value);
+            }
+        }
+    }
+
+    fn write(&self, value: T) -> ConfigVersion {
+        let _lock = CriticalRegion::enter();
+
+        let sequence = self.sequence.load(Ordering::Relaxed);
+        self.sequence
+            .store(sequence.wrapping_add(1), Ordering::Relaxed);
+        fence(Ordering::Release);
+
+        unsafe { ptr::write_volatile(self.value.get(), value) };
+
+        let sequence = sequence.wrapping_add(2);
+        self.sequence.store(sequence, Ordering::Release);
+        ConfigVersion(sequence >> 1)
+    }
+}
+
+/// Publishes configuration snapshots to registered consumer tasks and tracks which
+/// version each of them has applied.
+///
+/// Publishing stores the new value under a seqlock and sets each consumer's notification
+/// bit. Consumers read the latest snapshot with `current` and `acknowledge` the version
+/// once they applied it. Publishes aren't queued: a consumer that falls behind only ever
+/// sees the latest version, and acknowledging a version acknowledges every older one too.
+pub struct ConfigDistributor<T: Copy + Send> {
+    state: Arc<ConfigState<T>>,
+}
+
+impl<T: Copy + Send> ConfigDistributor<T> {
+    /// Create a distributor holding `initial` as version 0.
+    pub fn new(_os: FreeRTOS, initial: T) -> ConfigDistributor<T> {
+        ConfigDistributor {
+            state: Arc::new(ConfigState {
+                sequence: AtomicU32::new(0),
+                value: UnsafeCell::new(initial),
+                consumers: ExclusiveData::new(Vec::new()),
+            }),
+        }
+    }
+
+    /// Register a task to be notified on `notification_bit` (0 to 31) of its notification
+    /// value whenever a new version is published. It starts out having acknowledged
+    /// nothing newer than version 0.
+    pub fn register<H: TaskHandle>(
+        &self,
+        os: &FreeRTOS,
+        task: &H,
+        notification_bit: u8,
+    ) -> Result<ConfigConsumer<T>, FreeRtosError> {
+        assert!(notification_bit < 32, "Notification bit out of range.");
+
+        let entry = Arc::new(ConsumerEntry {
+            task: task.raw_handle(),
+            name: TaskName::from_bytes(task.get_name_bytes()),
+            bit: 1 << notification_bit,
+            acknowledged: AtomicU32::new(0),
+        });
+
+        self.state.consumers.lock(os)?.push(entry.clone());
+
+        Ok(ConfigConsumer {
+            state: self.state.clone(),
+            owner: TaskOwner::bound_to(entry.task),
+            entry,
+        })
+    }
+
+    /// Store a new snapshot and notify every registered consumer.
+    pub fn publish(&self, os: &FreeRTOS, value: T) -> ConfigVersion {
+        let version = self.state.write(value);
+
+        if let Ok(consumers) = self.state.consumers.lock(os) {
+            for consumer in consumers.iter() {
+                unsafe {
+                    freertos_rs_task_notify(consumer.task, consumer.bit, 1);
+                }
+            }
+        }
+
+        version
+    }
+
+    /// The latest version and its value.
+    pub fn current(&self) -> (ConfigVersion, T) {
+        self.state.read()
+    }
+
+    /// Has every registered consumer acknowledged `version` or a newer one?
+    pub fn all_acknowledged(&self, os: &FreeRTOS, version: ConfigVersion) -> bool {
+        match self.state.consumers.lock(os) {
+            Ok(consumers) => consumers
+                .iter()
+                .all(|c| c.acknowledged.load(Ordering::Acquire) >= version.0),
+            Err(_) => false,
+        }
+    }
+
+    /// Wait until every registered consumer acknowledged `version`, checking once a tick.
+    pub fn wait_all_acknowledged<D: DurationTicks>(
+        &self,
+        os: &FreeRTOS,
+        version: ConfigVersion,
+        timeout: D,
+    ) -> Result<(), FreeRtosError> {
+        let timeout = timeout.to_ticks();
+        let start = os.get_tick_count();
+
+        loop {
+            if self.all_acknowledged(os, version) {
+                return Ok(());
+            }
+            if tick_elapsed(os.get_tick_count(), start) >= timeout {
+                return Err(FreeRtosError::Timeout);
+            }
+            os.delay(Duration::ticks(1));
+        }
+    }
+
+    /// The consumers that haven't acknowledged `version` yet.
+    pub fn laggards(&self, os: &FreeRTOS, version: ConfigVersion) -> Vec<ConfigLaggard> {
+        match self.state.consumers.lock(os) {
+            Ok(consumers) => consumers
+                .iter()
+                .map(|c| ConfigLaggard {
+                    task: c.task,
+                    name: c.name,
+                    acknowledged: ConfigVersion(c.acknowledged.load(Ordering::Acquire)),
+                })
+                .filter(|l| l.acknowledged < version)
+                .collect(),
+            Err(_) => Vec::new(),
+        }
+    }
+
+    pub fn consumer_count(&self, os: &FreeRTOS) -> usize {
+        self.state.consumers.lock(os).map(|c| c.len()).unwrap_or(0)
+    }
+}
+
+/// A task's registration with a `ConfigDistributor`. Dropping it unregisters the task,
+/// which must happen before the task is deleted.
+///
+/// Only the registered task should acknowledge versions with it.
+pub struct ConfigConsumer<T: Copy + Send> {
+    state: Arc<ConfigState<T>>,
+    entry: Arc<ConsumerEntry>,
+    owner: TaskOwner,
+}
+
+impl<T: Copy + Send> ConfigConsumer<T> {
+    /// The latest version and its value.
+    pub fn current(&self) -> (ConfigVersion, T) {
+        self.state.read()
+    }
+
+    /// Record that this consumer applied `version`, and with it every older version.
+    /// Acknowledging an older version than before changes nothing.
+    pub fn acknowledge(&self, version: ConfigVersion) {
+        self.owner.check("ConfigConsumer::acknowledge");
+        let latest = self.state.read().0;
+        self.entry
+            .acknowledged
+            .fetch_max(version.min(latest).0, Ordering::AcqRel);
+    }
+
+    /// The newest version this consumer acknowledged.
+    pub fn acknowledged(&self) -> ConfigVersion {
+        ConfigVersion(self.entry.acknowledged.load(Ordering::Acquire))
+    }
+}
+
+impl<T: Copy + Send> Drop for ConfigConsumer<T> {
+    fn drop(&mut self) {
+        let os = unsafe { FreeRTOS::assume_init() };
+        if let Ok(mut consumers) = self.state.consumers.lock(&os) {
+            consumers.retain(|c| !Arc::ptr_eq(c, &self.entry));
+        }
+    }
+}
@@ -1,4 +1,5 @@
 use crate::base::*;
+use crate::isr::*;
 use crate::operating_system::*;
 use crate::prelude::v1::*;
 use crate::shim::*;
@@ -6,6 +7,8 @@ use crate::shim::*;
 pub struct CriticalRegion;
 impl CriticalRegion {
     pub fn enter() -> Self {
+        // Loom models run without the kernel, see the `sync` module.
+        #[cfg(not(loom))]
         unsafe {
             freertos_rs_enter_critical();
         }
@@ -16,12 +19,60 @@ impl CriticalRegion {
 
 impl Drop for CriticalRegion {
     fn drop(&mut self) {
+        #[cfg(not(loom))]
         unsafe {
             freertos_rs_exit_critical();
         }
     }
 }
 
+/// A critical region entered from an interrupt, which restores the interrupt mask it
+/// saved when dropped. See `InterruptContext::critical_section`.
+pub struct CriticalRegionIsr {
+    saved_interrupt_status: FreeRtosUBaseType,
+}
+
+impl CriticalRegionIsr {
+    pub fn enter(_context: &InterruptContext) -> Self {
+        CriticalRegionIsr {
+            saved_interrupt_status: unsafe { freertos_rs_enter_critical_from_isr() },
+        }
+    }
+}
+
+impl Drop for CriticalRegionIsr {
+    fn drop(&mut self) {
+        unsafe {
+            freertos_rs_exit_critical_from_isr(self.saved_interrupt_status);
+        }
+    }
+}
+
+/// Keeps the scheduler suspended until dropped, see `FreeRTOS::suspend_all_tasks`.
+/// Unlike a `CriticalRegion`, interrupts keep running.
+pub struct SchedulerSuspension {
+    _not_send: PhantomData<*const ()>,
+}
+
+impl SchedulerSuspension {
+    /// Suspend the scheduler. Nothing may block until the guard is dropped.
+    pub unsafe fn enter() -> Self {
+        freertos_rs_vTaskSuspendAll();
+
+        SchedulerSuspension {
+            _not_send: PhantomData,
+        }
+    }
+}
+
+impl Drop for SchedulerSuspension {
+    fn drop(&mut self) {
+        unsafe {
+            freertos_rs_xTaskResumeAll();
+        }
+    }
+}
+
 unsafe impl<T: Sync + Send> Send for ExclusiveData<T> {}
 unsafe impl<T: Sync + Send> Sync for ExclusiveData<T> {}
 
@@ -0,0 +1,88 @@
+use crate::base::*;
+use crate::isr::*;
+use crate::prelude::v1::*;
+use crate::shim::*;
+
+type DeferredFn = Box<dyn FnOnce() + Send>;
+
+/// A closure boxed ahead of time, so an interrupt can hand it to the timer daemon task
+/// without allocating. See `defer_to_daemon_isr`.
+pub struct DeferredCall {
+    /// A `Box<DeferredFn>`, owned until the daemon runs it.
+    call: FreeRtosMutVoidPtr,
+}
+
+unsafe impl Send for DeferredCall {}
+
+impl DeferredCall {
+    pub fn new<F>(f: F) -> DeferredCall
+    where
+        F: FnOnce() + Send + 'static,
+    {
+        let call: Box<DeferredFn> = Box::new(Box::new(f));
+        DeferredCall {
+            call: Box::into_raw(call) as FreeRtosMutVoidPtr,
+        }
+    }
+
+    fn into_raw(self) -> FreeRtosMutVoidPtr {
+        let call = self.call;
+        mem::forget(self);
+        call
+    }
+
+    unsafe fn from_raw(call: FreeRtosMutVoidPtr) -> DeferredCall {
+        DeferredCall { call }
+    }
+}
+
+impl Drop for DeferredCall {
+    fn drop(&mut self) {
+        unsafe { drop(Box::from_raw(self.call as *mut DeferredFn)) }
+    }
+}
+
+/// Run by the timer daemon task. The box is reclaimed before the closure runs, so it is
+/// freed even if the closure panics.
+extern "C" fn run_deferred(call: FreeRtosMutVoidPtr, _: u32) {
+    let call = unsafe { Box::from_raw(call as *mut DeferredFn) };
+    (*call)()
+}
+
+/// Have the timer daemon task run `call`, from an interrupt. The FreeRTOS heaps can't be
+/// used from interrupts, so the closure has to be boxed beforehand with `DeferredCall::new`.
+///
+/// If the timer command queue is full the call is handed back, to be freed or retried
+/// outside the interrupt.
+pub fn defer_to_daemon_isr(
+    context: &mut InterruptContext,
+    call: DeferredCall,
+) -> Result<(), DeferredCall> {
+    let call = call.into_raw();
+
+    unsafe {
+        if freertos_rs_pend_function_call_isr(run_deferred, call, 0, context.get_task_field_mut())
+            == 0
+        {
+            Ok(())
+        } else {
+            Err(DeferredCall::from_raw(call))
+        }
+    }
+}
+
+pub(crate) fn defer_to_daemon_raw(
+    call: DeferredCall,
+    max_wait: FreeRtosTickType,
+) -> Result<(), FreeRtosError> {
+    let call = call.into_raw();
+
+    unsafe {
+        if freertos_rs_pend_function_call(run_deferred, call, 0, max_wait) == 0 {
+            Ok(())
+        } else {
+            drop(DeferredCall::from_raw(call));
+            Err(FreeRtosError::Timeout)
+        }
+    }
+}
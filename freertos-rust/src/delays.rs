@@ -1,12 +1,21 @@
 use crate::base::*;
+use crate::no_block::check_blocking;
 use crate::operating_system::*;
+use crate::owner::*;
+use crate::prelude::v1::*;
 use crate::shim::*;
+use crate::task::*;
+use crate::ticks::*;
 use crate::units::*;
 
 /// Delay the current task by the given duration, minus the
 /// time that was spent processing the last wakeup loop.
+///
+/// Belongs to the first task that delays with it. To hand it to another task, move it
+/// there and `rebind` it.
 pub struct TaskDelay {
-    last_wake_time: FreeRtosTickType,
+    last_wake_time: TickInstant,
+    owner: TaskOwner,
 }
 
 impl TaskDelay {
@@ -14,17 +23,29 @@ impl TaskDelay {
     /// next measurement.
     pub fn new(os: FreeRTOS) -> TaskDelay {
         TaskDelay {
-            last_wake_time: os.get_tick_count(),
+            last_wake_time: os.now(),
+            owner: TaskOwner::new(),
         }
     }
+
+    /// Bind the helper to `this` task after it was moved there.
+    pub fn rebind(&mut self, this: &TaskSelfHandle) {
+        self.owner.rebind(this);
+    }
+
     /// Delay the execution of the current task by the given duration,
     /// minus the time spent in this task since the last delay.
+    #[inline]
     pub fn delay_until<D: DurationTicks>(&mut self, delay: D) {
+        self.delay_until_ticks(delay.to_ticks())
+    }
+
+    fn delay_until_ticks(&mut self, delay: FreeRtosTickType) {
+        check_blocking("TaskDelay::delay_until", ptr::null(), delay);
+        self.owner.check("TaskDelay::delay_until");
+
         unsafe {
-            freertos_rs_vTaskDelayUntil(
-                &mut self.last_wake_time as *mut FreeRtosTickType,
-                delay.to_ticks(),
-            );
+            freertos_rs_vTaskDelayUntil(&mut self.last_wake_time.0 as *mut FreeRtosTickType, delay);
         }
     }
 }
@@ -34,31 +55,40 @@ impl TaskDelay {
 /// Use inside a polling loop, for example: the loop polls this instance every second.
 /// The method `should_run` will return true once 30 seconds or more has elapsed
 /// and it will then reset the timer for that period.
+///
+/// Belongs to the first task that polls it, like `TaskDelay`.
 pub struct TaskDelayPeriodic {
-    last_wake_time: FreeRtosTickType,
+    last_wake_time: TickInstant,
     period_ticks: FreeRtosTickType,
     os: FreeRTOS,
+    owner: TaskOwner,
 }
 
 impl TaskDelayPeriodic {
     /// Create a new timer with the set period.
     pub fn new<D: DurationTicks>(os: FreeRTOS, period: D) -> TaskDelayPeriodic {
-        let l = os.get_tick_count();
-
         TaskDelayPeriodic {
-            last_wake_time: l,
+            last_wake_time: os.now(),
             period_ticks: period.to_ticks(),
             os,
+            owner: TaskOwner::new(),
         }
     }
 
+    /// Bind the helper to `this` task after it was moved there.
+    pub fn rebind(&mut self, this: &TaskSelfHandle) {
+        self.owner.rebind(this);
+    }
+
     /// Has the set period passed? If it has, resets the internal timer.
     pub fn should_run(&mut self) -> bool {
-        let c = self.os.get_tick_count();
-        if (c - self.last_wake_time) < (self.period_ticks) {
+        self.owner.check("TaskDelayPeriodic::should_run");
+        let now = self.os.now();
+        let elapsed = now.saturating_duration_since(self.last_wake_time);
+        if elapsed.to_ticks() < self.period_ticks {
             false
         } else {
-            self.last_wake_time = c;
+            self.last_wake_time = now;
             true
         }
     }
@@ -70,6 +100,6 @@ impl TaskDelayPeriodic {
 
     /// Reset the internal timer to zero.
     pub fn reset(&mut self) {
-        self.last_wake_time = self.os.get_tick_count();
+        self.last_wake_time = self.os.now();
     }
 }
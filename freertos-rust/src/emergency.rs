@@ -0,0 +1,367 @@
+use crate::base::*;
+#[cfg(feature = "emergency_abort")]
+use crate::capacities::*;
+use crate::critical::*;
+use crate::isr::*;
+use crate::operating_system::*;
+use crate::prelude::v1::*;
+use crate::shim::*;
+use crate::status_cell::*;
+use crate::task::*;
+use crate::units::*;
+
+impl !ISRSafe for EmergencyBroadcast {}
+
+/// The reason and time of the last emergency trigger.
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub struct EmergencyEvent {
+    pub reason: u8,
+    pub tick: FreeRtosTickType,
+}
+
+struct EmergencyRegistration {
+    task: FreeRtosTaskHandle,
+    bit: u32,
+    abort_delay: bool,
+    /// Counted in the aborted tasks since the last trigger, with `emergency_abort`.
+    aborted: bool,
+    /// The tick the task called `observe` at since the last trigger.
+    observed: Option<FreeRtosTickType>,
+}
+
+unsafe impl Send for EmergencyRegistration {}
+unsafe impl Sync for EmergencyRegistration {}
+
+struct EmergencyState {
+    registrations: ExclusiveData<Vec<EmergencyRegistration>>,
+    status: StatusCell<Option<EmergencyEvent>>,
+}
+
+#[cfg(feature = "emergency_abort")]
+#[derive(Copy, Clone)]
+struct AbortedTask {
+    task: FreeRtosTaskHandle,
+    /// The triggered broadcasts that aborted the task.
+    broadcasts: u32,
+}
+
+// Written in critical sections by tasks and ISRs, read in one by `emergency_aborted`.
+#[cfg(feature = "emergency_abort")]
+static mut ABORTED: [Option<AbortedTask>; EMERGENCY_ABORT_CAPACITY] =
+    [None; EMERGENCY_ABORT_CAPACITY];
+
+/// Count one more broadcast aborting `task`. False when `EMERGENCY_ABORT_CAPACITY` other
+/// tasks are aborted already, then the blocking calls of this one only time out.
+///
+/// Safety:
+/// Must be called in a critical section.
+#[cfg(feature = "emergency_abort")]
+unsafe fn mark_aborted(task: FreeRtosTaskHandle) -> bool {
+    let aborted = &mut *ptr::addr_of_mut!(ABORTED);
+    if let Some(entry) = aborted.iter_mut().flatten().find(|a| a.task == task) {
+        entry.broadcasts += 1;
+        return true;
+    }
+    match aborted.iter_mut().find(|a| a.is_none()) {
+        Some(slot) => {
+            *slot = Some(AbortedTask {
+                task,
+                broadcasts: 1,
+            });
+            true
+        }
+        None => false,
+    }
+}
+
+/// Undo a `mark_aborted` that returned true.
+///
+/// Safety:
+/// Must be called in a critical section.
+#[cfg(feature = "emergency_abort")]
+unsafe fn unmark_aborted(task: FreeRtosTaskHandle) {
+    let aborted = &mut *ptr::addr_of_mut!(ABORTED);
+    for slot in aborted.iter_mut() {
+        if let Some(entry) = slot {
+            if entry.task == task {
+                entry.broadcasts -= 1;
+                if entry.broadcasts == 0 {
+                    *slot = None;
+                }
+                return;
+            }
+        }
+    }
+}
+
+#[cfg(not(feature = "emergency_abort"))]
+unsafe fn mark_aborted(_task: FreeRtosTaskHandle) -> bool {
+    false
+}
+
+#[cfg(not(feature = "emergency_abort"))]
+unsafe fn unmark_aborted(_task: FreeRtosTaskHandle) {}
+
+/// Whether a triggered broadcast aborted the calling task, so its failed blocking calls
+/// return `Emergency` rather than their timeout error.
+#[cfg(feature = "emergency_abort")]
+pub(crate) fn emergency_aborted() -> bool {
+    let task = unsafe { freertos_rs_get_current_task() };
+    let _lock = CriticalRegion::enter();
+    let aborted = unsafe { &*ptr::addr_of!(ABORTED) };
+    aborted.iter().flatten().any(|a| a.task == task)
+}
+
+/// Broadcasts an emergency stop to every registered task, bypassing queues.
+///
+/// Each registered task reserves one bit of its notification value. Triggering
+/// sets that bit on every registered task and, for tasks that opted in, aborts
+/// whatever delay or kernel wait they are currently blocked in, so they observe
+/// the emergency within a tick regardless of what they were waiting on. The reason
+/// and tick of the trigger are kept in a `StatusCell`, which woken tasks read.
+///
+/// With the `emergency_abort` feature, the blocking calls of the crate that fail in a
+/// task that opted in return `FreeRtosError::Emergency` instead of their timeout error,
+/// until the broadcast is rearmed, so middleware can unwind with `?`.
+///
+/// Woken tasks call `observe`, and `latency` is the time from the trigger until the last
+/// registered task did.
+pub struct EmergencyBroadcast {
+    // Shared with the ISR handles and the abort calls they pend to the timer daemon.
+    state: Arc<EmergencyState>,
+    capacity: usize,
+}
+
+impl EmergencyBroadcast {
+    /// Create a new broadcast that can hold up to `capacity` registered tasks.
+    pub fn new(_os: FreeRTOS, capacity: usize) -> EmergencyBroadcast {
+        EmergencyBroadcast {
+            state: Arc::new(EmergencyState {
+                registrations: ExclusiveData::new(Vec::with_capacity(capacity)),
+                status: StatusCell::new(None),
+            }),
+            capacity,
+        }
+    }
+
+    /// Register a task to be notified on `notification_bit` (0 to 31) of its notification value.
+    ///
+    /// With `abort_delay` set, a trigger also calls `xTaskAbortDelay` on the task, so a task blocked
+    /// in a delay, a queue or a mutex wakes up immediately and sees its blocking call time out,
+    /// or fail with `Emergency` with the `emergency_abort` feature.
+    pub fn register<H: TaskHandle>(
+        &self,
+        os: &FreeRTOS,
+        task: &H,
+        notification_bit: u8,
+        abort_delay: bool,
+    ) -> Result<(), FreeRtosError> {
+        assert!(notification_bit < 32, "Notification bit out of range.");
+
+        let mut registrations = self.state.registrations.lock(os)?;
+        if registrations.len() >= self.capacity {
+            return Err(FreeRtosError::OutOfMemory);
+        }
+
+        registrations.push(EmergencyRegistration {
+            task: task.raw_handle(),
+            bit: 1 << notification_bit,
+            abort_delay,
+            aborted: false,
+            observed: None,
+        });
+
+        Ok(())
+    }
+
+    /// Remove a task from the broadcast. Must be called before the task is deleted.
+    pub fn unregister<H: TaskHandle>(&self, os: &FreeRTOS, task: &H) -> Result<(), FreeRtosError> {
+        let handle = task.raw_handle();
+        let mut registrations = self.state.registrations.lock(os)?;
+        registrations.retain(|r| {
+            if r.task == handle && r.aborted {
+                unsafe { unmark_aborted(r.task) };
+            }
+            r.task != handle
+        });
+
+        Ok(())
+    }
+
+    /// Trigger the emergency from a task.
+    pub fn trigger(&self, os: &FreeRTOS, reason: u8) {
+        let event = EmergencyEvent {
+            reason,
+            tick: os.get_tick_count(),
+        };
+        self.state.status.set(Some(event));
+
+        if let Ok(mut registrations) = self.state.registrations.lock(os) {
+            for registration in registrations.iter_mut() {
+                registration.observed = None;
+                unsafe {
+                    if registration.abort_delay && !registration.aborted {
+                        registration.aborted = mark_aborted(registration.task);
+                    }
+                    freertos_rs_task_notify(registration.task, registration.bit, 1);
+                    if registration.abort_delay {
+                        freertos_rs_task_abort_delay(registration.task);
+                    }
+                }
+            }
+        }
+    }
+
+    /// Has the emergency been triggered since creation or the last `rearm`?
+    pub fn is_triggered(&self) -> bool {
+        self.state.status.get().is_some()
+    }
+
+    /// Return an error if the emergency has been triggered, so middleware can unwind with `?`.
+    pub fn check(&self) -> Result<(), FreeRtosError> {
+        if self.is_triggered() {
+            Err(FreeRtosError::Emergency)
+        } else {
+            Ok(())
+        }
+    }
+
+    /// The reason and tick of the most recent trigger, if any.
+    pub fn last_event(&self) -> Option<EmergencyEvent> {
+        self.state.status.get()
+    }
+
+    /// The cell holding the last event, `None` until a trigger and after `rearm`.
+    pub fn status(&self) -> &StatusCell<Option<EmergencyEvent>> {
+        &self.state.status
+    }
+
+    /// Record that the calling task observed the emergency, for `latency`. Returns the time
+    /// since the trigger, or `None` if the broadcast isn't triggered or the task isn't
+    /// registered.
+    pub fn observe(&self, os: &FreeRTOS) -> Option<Duration> {
+        let event = self.state.status.get()?;
+        let task = unsafe { freertos_rs_get_current_task() };
+        let now = os.get_tick_count();
+
+        let mut registrations = self.state.registrations.lock(os).ok()?;
+        let registration = registrations.iter_mut().find(|r| r.task == task)?;
+        let observed = *registration.observed.get_or_insert(now);
+        Some(Duration::ticks(observed.wrapping_sub(event.tick)))
+    }
+
+    /// The time from the last trigger until every registered task called `observe`, or
+    /// `None` while one hasn't yet.
+    pub fn latency(&self, os: &FreeRTOS) -> Option<Duration> {
+        let event = self.state.status.get()?;
+        let registrations = self.state.registrations.lock(os).ok()?;
+
+        let mut latency = 0;
+        for registration in registrations.iter() {
+            let observed = registration.observed?;
+            latency = latency.max(observed.wrapping_sub(event.tick));
+        }
+        Some(Duration::ticks(latency))
+    }
+
+    /// Clear the triggered state so the broadcast can be used again.
+    ///
+    /// Registered tasks are responsible for clearing their own notification bit.
+    pub fn rearm(&self, os: &FreeRTOS) {
+        self.state.status.set(None);
+
+        if let Ok(mut registrations) = self.state.registrations.lock(os) {
+            for registration in registrations.iter_mut() {
+                if registration.aborted {
+                    unsafe { unmark_aborted(registration.task) };
+                    registration.aborted = false;
+                }
+                registration.observed = None;
+            }
+        }
+    }
+}
+
+/// An ISR safe handle to an emergency broadcast.
+///
+/// It holds a reference to the state of the broadcast, so it stays valid after the
+/// broadcast was dropped. Drop it in a task, as the last reference frees the state.
+pub struct EmergencyBroadcastISRHandle {
+    state: Arc<EmergencyState>,
+}
+
+impl ISRSafe for EmergencyBroadcastISRHandle {}
+
+impl ISRSafeHandle<EmergencyBroadcastISRHandle> for EmergencyBroadcast {
+    unsafe fn new_isr_safe_handle(&self) -> EmergencyBroadcastISRHandle {
+        EmergencyBroadcastISRHandle {
+            state: self.state.clone(),
+        }
+    }
+}
+
+impl EmergencyBroadcastISRHandle {
+    /// Trigger the emergency from an interrupt.
+    ///
+    /// `xTaskAbortDelay` can't be called from an interrupt, so aborting delays is deferred to
+    /// the timer daemon task. Tasks that opted in are woken as soon as the daemon runs.
+    pub fn trigger(&self, context: &mut InterruptContext, reason: u8) {
+        let event = EmergencyEvent {
+            reason,
+            tick: unsafe { freertos_rs_xTaskGetTickCountFromISR() },
+        };
+        self.state.status.set_from_isr(context, Some(event));
+
+        let mut any_abort_delay = false;
+        if let Ok(mut registrations) = self.state.registrations.lock_from_isr(context) {
+            for registration in registrations.iter_mut() {
+                registration.observed = None;
+                if registration.abort_delay && !registration.aborted {
+                    let _lock = CriticalRegionIsr::enter(context);
+                    registration.aborted = unsafe { mark_aborted(registration.task) };
+                }
+                unsafe {
+                    freertos_rs_task_notify_isr(
+                        registration.task,
+                        registration.bit,
+                        1,
+                        context.get_task_field_mut(),
+                    );
+                }
+                any_abort_delay |= registration.abort_delay;
+            }
+        }
+
+        if any_abort_delay {
+            // The daemon may run after the broadcast and this handle were dropped, so the
+            // call holds a reference of its own.
+            let state = Arc::into_raw(self.state.clone()) as FreeRtosMutVoidPtr;
+            unsafe {
+                if freertos_rs_pend_function_call_isr(
+                    abort_delays,
+                    state,
+                    0,
+                    context.get_task_field_mut(),
+                ) != 0
+                {
+                    // The timer command queue is full. This handle holds another
+                    // reference, so this drop doesn't free the state.
+                    drop(Arc::from_raw(state as *const EmergencyState));
+                }
+            }
+        }
+    }
+}
+
+extern "C" fn abort_delays(state: FreeRtosMutVoidPtr, _: u32) {
+    unsafe {
+        let state = Arc::from_raw(state as *const EmergencyState);
+        let os = FreeRTOS::assume_init();
+
+        if let Ok(registrations) = state.registrations.lock(&os) {
+            for registration in registrations.iter().filter(|r| r.abort_delay) {
+                freertos_rs_task_abort_delay(registration.task);
+            }
+        }
+    }
+}
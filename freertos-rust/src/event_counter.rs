@@ -0,0 +1,80 @@
+use crate::base::*;
+use crate::capacities::*;
+use crate::prelude::v1::*;
+// Counters live in statics, so their atomics have to be const-initialised.
+use core::sync::atomic::{AtomicU32, Ordering};
+
+/// A named count of events, bumped from tasks or ISRs without blocking.
+///
+/// Counters are statics. Registering one gives it an id, which C code can bump it by,
+/// see the `c_hooks` feature:
+///
+/// ```rust
+/// # use freertos_rs::*;
+/// static RX_OVERRUNS: EventCounter = EventCounter::new("uart.rx_overruns");
+/// let id = RX_OVERRUNS.register().unwrap();
+/// ```
+pub struct EventCounter {
+    name: &'static str,
+    count: AtomicU32,
+}
+
+impl EventCounter {
+    pub const fn new(name: &'static str) -> Self {
+        EventCounter {
+            name,
+            count: AtomicU32::new(0),
+        }
+    }
+
+    pub fn name(&self) -> &'static str {
+        self.name
+    }
+
+    /// Count one event. Wraps around at `u32::MAX`.
+    pub fn increment(&self) {
+        self.count.fetch_add(1, Ordering::Relaxed);
+    }
+
+    pub fn get(&self) -> u32 {
+        self.count.load(Ordering::Relaxed)
+    }
+
+    /// Register the counter and return its id. Registering it again returns the same id.
+    pub fn register(&'static self) -> Result<EventCounterId, FreeRtosError> {
+        let index =
+            EVENT_COUNTERS.push(RegistryKind::EventCounters, self, |c| ptr::eq(*c, self))?;
+        Ok(EventCounterId(index as u32 + 1))
+    }
+}
+
+impl fmt::Debug for EventCounter {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_struct("EventCounter")
+            .field("name", &self.name)
+            .field("count", &self.get())
+            .finish()
+    }
+}
+
+/// The id of a registered `EventCounter`. Ids start at 1, 0 is never handed out.
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Hash)]
+pub struct EventCounterId(pub u32);
+
+static EVENT_COUNTERS: AppendOnlySlots<&'static EventCounter, EVENT_COUNTER_CAPACITY> =
+    AppendOnlySlots::new();
+
+/// The registered counter with `id`. Can be called from ISRs.
+pub fn event_counter(id: EventCounterId) -> Option<&'static EventCounter> {
+    let index = id.0.checked_sub(1)?;
+    EVENT_COUNTERS.get(index as usize)
+}
+
+/// The registered counters, in the order they were registered.
+pub fn event_counters() -> Vec<&'static EventCounter> {
+    EVENT_COUNTERS.iter().collect()
+}
+
+pub(crate) fn event_counter_fill() -> (usize, usize) {
+    (EVENT_COUNTERS.len(), EVENT_COUNTERS.capacity())
+}
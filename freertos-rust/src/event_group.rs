@@ -0,0 +1,169 @@
+use crate::base::*;
+use crate::isr::*;
+use crate::no_block::check_blocking;
+use crate::operating_system::*;
+use crate::shim::*;
+use crate::units::*;
+
+unsafe impl Send for EventGroup {}
+unsafe impl Sync for EventGroup {}
+
+impl !ISRSafe for EventGroup {}
+
+/// A set of event flags that tasks can wait on, for one or all of several flags at once.
+///
+/// With 32 bit ticks the lower 24 bits are available, with 16 bit ticks the lower 8.
+#[derive(Debug)]
+pub struct EventGroup {
+    event_group: FreeRtosEventGroupHandle,
+}
+
+impl EventGroup {
+    pub fn new(_os: FreeRTOS) -> Result<EventGroup, FreeRtosError> {
+        let handle = unsafe { freertos_rs_event_group_create() };
+
+        if handle.is_null() {
+            Err(FreeRtosError::OutOfMemory)
+        } else {
+            Ok(EventGroup {
+                event_group: handle,
+            })
+        }
+    }
+
+    /// Set bits, unblocking the tasks waiting for them. Returns the bits as they were
+    /// when this call returned, which may already be cleared by a woken task.
+    pub fn set_bits(&self, bits: u32) -> u32 {
+        unsafe { freertos_rs_event_group_set_bits(self.event_group, bits) }
+    }
+
+    /// Clear bits. Returns the bits as they were before they were cleared.
+    pub fn clear_bits(&self, bits: u32) -> u32 {
+        unsafe { freertos_rs_event_group_clear_bits(self.event_group, bits) }
+    }
+
+    pub fn get_bits(&self) -> u32 {
+        unsafe { freertos_rs_event_group_get_bits(self.event_group) }
+    }
+
+    /// Wait for any, or with `wait_for_all` every one, of `bits` to be set.
+    /// Returns the bits as they were when the wait ended, before `clear_on_exit` cleared them.
+    #[inline]
+    pub fn wait_bits<D: DurationTicks>(
+        &self,
+        bits: u32,
+        clear_on_exit: bool,
+        wait_for_all: bool,
+        timeout: D,
+    ) -> Result<u32, FreeRtosError> {
+        self.wait_bits_ticks(bits, clear_on_exit, wait_for_all, timeout.to_ticks())
+    }
+
+    fn wait_bits_ticks(
+        &self,
+        bits: u32,
+        clear_on_exit: bool,
+        wait_for_all: bool,
+        timeout: FreeRtosTickType,
+    ) -> Result<u32, FreeRtosError> {
+        check_blocking("EventGroup::wait_bits", self.event_group, timeout);
+
+        let value = unsafe {
+            freertos_rs_event_group_wait_bits(
+                self.event_group,
+                bits,
+                clear_on_exit as u8,
+                wait_for_all as u8,
+                timeout,
+            )
+        };
+
+        let satisfied = if wait_for_all {
+            value & bits == bits
+        } else {
+            value & bits != 0
+        };
+
+        if !satisfied {
+            return Err(timed_out());
+        }
+        Ok(value)
+    }
+
+    /// Set `set_bits`, then wait for all of `wait_bits` to be set, as a rendezvous between
+    /// several tasks that each set their own bit. The waited bits are cleared on success.
+    #[inline]
+    pub fn sync<D: DurationTicks>(
+        &self,
+        set_bits: u32,
+        wait_bits: u32,
+        timeout: D,
+    ) -> Result<u32, FreeRtosError> {
+        self.sync_ticks(set_bits, wait_bits, timeout.to_ticks())
+    }
+
+    fn sync_ticks(
+        &self,
+        set_bits: u32,
+        wait_bits: u32,
+        timeout: FreeRtosTickType,
+    ) -> Result<u32, FreeRtosError> {
+        check_blocking("EventGroup::sync", self.event_group, timeout);
+
+        let value =
+            unsafe { freertos_rs_event_group_sync(self.event_group, set_bits, wait_bits, timeout) };
+
+        if value & wait_bits != wait_bits {
+            return Err(timed_out());
+        }
+        Ok(value)
+    }
+}
+
+impl Drop for EventGroup {
+    fn drop(&mut self) {
+        unsafe {
+            freertos_rs_event_group_delete(self.event_group);
+        }
+    }
+}
+
+/// An ISR safe handle to an event group.
+pub struct EventGroupISRHandle {
+    event_group: FreeRtosEventGroupHandle,
+}
+
+impl ISRSafeHandle<EventGroupISRHandle> for EventGroup {
+    unsafe fn new_isr_safe_handle(&self) -> EventGroupISRHandle {
+        EventGroupISRHandle {
+            event_group: self.event_group,
+        }
+    }
+}
+
+impl EventGroupISRHandle {
+    /// Set bits from an interrupt. The bits are set by the timer daemon task, so this fails
+    /// if its command queue is full.
+    pub fn set_bits_isr(
+        &self,
+        context: &mut InterruptContext,
+        bits: u32,
+    ) -> Result<(), FreeRtosError> {
+        unsafe {
+            if freertos_rs_event_group_set_bits_isr(
+                self.event_group,
+                bits,
+                context.get_task_field_mut(),
+            ) != 0
+            {
+                Err(FreeRtosError::QueueFull)
+            } else {
+                Ok(())
+            }
+        }
+    }
+
+    pub fn get_bits_isr(&self, _context: &mut InterruptContext) -> u32 {
+        unsafe { freertos_rs_event_group_get_bits_isr(self.event_group) }
+    }
+}
@@ -0,0 +1,482 @@
+//! Makes kernel calls fail on demand, to test the error paths of an application.
+//!
+//! The shims that call the kernel check a hook table first, and fail without calling
+//! the kernel when the hook says so. The failure comes back through the normal crate
+//! error types, like `QueueSendTimeout` from `Queue::send`. The table and the checks
+//! are only compiled into shim.c with the `fault_inject` feature, which also needs
+//! the shim to be built by `freertos-cargo-build`.
+//!
+//! ```rust
+//! # use freertos_rs::*;
+//! use freertos_rs::fault_inject::{self, Failure, Target, Trigger};
+//!
+//! // The third send to any queue times out.
+//! fault_inject::arm(Target::QueueSend { queue: None }, Failure::Timeout, Trigger::Nth(3));
+//! ```
+
+use crate::base::*;
+use crate::critical::*;
+use crate::prelude::v1::*;
+use crate::queue::*;
+use crate::semaphore::*;
+use crate::shim::*;
+use crate::task::*;
+use crate::timers::*;
+use crate::units::*;
+use crate::utils::*;
+
+/// How many faults can be armed at the same time.
+pub const FAULT_CAPACITY: usize = 16;
+/// How many fired faults `report` keeps.
+pub const REPORT_CAPACITY: usize = 32;
+
+/// The interception points, in the order of the `FREERTOS_RS_FAULT_*` ids in shim.c.
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+enum Point {
+    QueueSend = 0,
+    QueueReceive = 1,
+    SemaphoreTake = 2,
+    TaskSpawn = 3,
+    Malloc = 4,
+    TimerCommand = 5,
+}
+
+const POINTS: [Point; 6] = [
+    Point::QueueSend,
+    Point::QueueReceive,
+    Point::SemaphoreTake,
+    Point::TaskSpawn,
+    Point::Malloc,
+    Point::TimerCommand,
+];
+
+/// A kernel call to make fail. `None` matches any object.
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum Target {
+    /// `Queue::send` and `send_to_front`.
+    QueueSend { queue: Option<FreeRtosQueueHandle> },
+    /// `Queue::receive`.
+    QueueReceive { queue: Option<FreeRtosQueueHandle> },
+    /// Taking a semaphore or locking a mutex.
+    SemaphoreTake {
+        semaphore: Option<FreeRtosSemaphoreHandle>,
+    },
+    /// Spawning a task, by name.
+    TaskSpawn { name: Option<&'static str> },
+    /// `FreeRtosAllocator` allocations of more than `size_over` bytes, counting the
+    /// allocator's own overhead. The kernel's allocations aren't affected.
+    Malloc { size_over: usize },
+    /// Timer start, stop, reset, period change and delete commands.
+    TimerCommand { timer: Option<FreeRtosTimerHandle> },
+}
+
+impl Target {
+    /// Sends to `queue`.
+    pub fn queue_send<T: Sized + Copy>(queue: &Queue<T>) -> Target {
+        Target::QueueSend {
+            queue: Some(queue.raw_handle()),
+        }
+    }
+
+    /// Receives from `queue`.
+    pub fn queue_receive<T: Sized + Copy>(queue: &Queue<T>) -> Target {
+        Target::QueueReceive {
+            queue: Some(queue.raw_handle()),
+        }
+    }
+
+    /// Takes of `semaphore`.
+    pub fn semaphore_take<S: Semaphore>(semaphore: &S) -> Target {
+        Target::SemaphoreTake {
+            semaphore: Some(semaphore.raw_handle()),
+        }
+    }
+
+    /// Commands to `timer`.
+    pub fn timer_command(timer: &Timer) -> Target {
+        Target::TimerCommand {
+            timer: Some(timer.raw_handle()),
+        }
+    }
+
+    fn point(&self) -> Point {
+        match self {
+            Target::QueueSend { .. } => Point::QueueSend,
+            Target::QueueReceive { .. } => Point::QueueReceive,
+            Target::SemaphoreTake { .. } => Point::SemaphoreTake,
+            Target::TaskSpawn { .. } => Point::TaskSpawn,
+            Target::Malloc { .. } => Point::Malloc,
+            Target::TimerCommand { .. } => Point::TimerCommand,
+        }
+    }
+
+    /// The failure the intercepted call can report.
+    fn failure(&self) -> Failure {
+        match self {
+            Target::TaskSpawn { .. } | Target::Malloc { .. } => Failure::OutOfMemory,
+            _ => Failure::Timeout,
+        }
+    }
+
+    /// Whether a call with the shim's `object` and `arg` is one of ours.
+    unsafe fn matches(&self, object: FreeRtosVoidPtr, arg: usize) -> bool {
+        match *self {
+            Target::QueueSend { queue } | Target::QueueReceive { queue } => {
+                queue.map_or(true, |q| q == object)
+            }
+            Target::SemaphoreTake { semaphore } => semaphore.map_or(true, |s| s == object),
+            Target::TimerCommand { timer } => timer.map_or(true, |t| t == object),
+            Target::TaskSpawn { name } => name.map_or(true, |name| {
+                core::slice::from_raw_parts(object as *const u8, arg) == name.as_bytes()
+            }),
+            Target::Malloc { size_over } => arg > size_over,
+        }
+    }
+}
+
+/// How an intercepted call fails.
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum Failure {
+    /// The call times out without the kernel being asked. Queue sends and receives
+    /// report `QueueSendTimeout` and `QueueReceiveTimeout`, semaphores `Timeout`,
+    /// mutexes `MutexTimeout` and timer commands `Timeout`.
+    Timeout,
+    /// There is no memory. Spawning reports `OutOfMemory`, allocations return null
+    /// like with an exhausted heap.
+    OutOfMemory,
+}
+
+/// Which matching calls fail.
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum Trigger {
+    /// Every one.
+    Always,
+    /// Only the `n`th, counting from 1. The fault disarms after it.
+    Nth(u32),
+    /// Each with a chance of `percent` in 100. The generator is seeded with `seed`, so
+    /// the same calls fail on every run.
+    Probability { percent: u8, seed: u32 },
+}
+
+/// An armed fault, see `Fault::arm`.
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub struct FaultId(u32);
+
+/// A fault that fired, see `report`.
+#[derive(Debug, Copy, Clone)]
+pub struct Fired {
+    pub fault: FaultId,
+    pub target: Target,
+    /// Which matching call failed, counting from 1.
+    pub call: u32,
+    pub tick: FreeRtosTickType,
+    /// The task making the call, `None` before the scheduler started.
+    pub task: Option<TaskName>,
+}
+
+/// A fault to arm, with the calls it applies to.
+#[derive(Debug, Copy, Clone)]
+pub struct Fault {
+    target: Target,
+    failure: Failure,
+    trigger: Trigger,
+    task: Option<&'static str>,
+    shots: Option<u32>,
+}
+
+impl Fault {
+    /// Fails every call to `target`, until told otherwise.
+    pub fn new(target: Target, failure: Failure) -> Fault {
+        Fault {
+            target,
+            failure,
+            trigger: Trigger::Always,
+            task: None,
+            shots: None,
+        }
+    }
+
+    pub fn trigger(&mut self, trigger: Trigger) -> &mut Self {
+        self.trigger = trigger;
+        self
+    }
+
+    /// Only count calls made by the task with this name, as the kernel stores it.
+    pub fn from_task(&mut self, name: &'static str) -> &mut Self {
+        self.task = Some(name);
+        self
+    }
+
+    /// Disarm after failing `shots` calls.
+    pub fn shots(&mut self, shots: u32) -> &mut Self {
+        self.shots = Some(shots);
+        self
+    }
+
+    /// Start failing calls.
+    ///
+    /// # Panics
+    ///
+    /// If the target can't fail with the chosen `Failure`, like a spawn with
+    /// `Timeout`, or `FAULT_CAPACITY` faults are armed already.
+    pub fn arm(&self) -> FaultId {
+        assert!(
+            self.failure == self.target.failure(),
+            "fault_inject: {:?} can't fail with {:?}",
+            self.target,
+            self.failure
+        );
+
+        with_state(|state| {
+            let slot = state
+                .faults
+                .iter_mut()
+                .find(|f| f.is_none())
+                .expect("fault_inject: too many armed faults");
+
+            state.next_id = state.next_id.wrapping_add(1);
+            let id = FaultId(state.next_id);
+            let rng = match self.trigger {
+                Trigger::Probability { seed, .. } => seed.max(1),
+                _ => 0,
+            };
+            *slot = Some(Armed {
+                id,
+                fault: *self,
+                calls: 0,
+                fired: 0,
+                rng,
+            });
+            state.install_hooks();
+            id
+        })
+    }
+
+    /// Run `scenario` with the `step`th matching call failing, for tests like "this
+    /// scenario must survive a spawn failure at step k". The fault is disarmed
+    /// afterwards, and the result tells whether it fired.
+    pub fn run_failing_at<R>(&self, step: u32, scenario: impl FnOnce() -> R) -> (bool, R) {
+        let id = self.clone().trigger(Trigger::Nth(step)).arm();
+        let result = scenario();
+        // An `Nth` fault disarms itself when it fires.
+        let fired = !disarm(id);
+        (fired, result)
+    }
+
+    /// Run `scenario` with the first matching call failing, then again with the
+    /// second failing and so on, until a run in which the fault didn't fire because the
+    /// scenario made fewer calls. `scenario` gets the step and checks that it survived.
+    /// Returns how many steps were failed.
+    pub fn run_each_step(&self, mut scenario: impl FnMut(u32)) -> u32 {
+        let mut step = 1;
+        loop {
+            let (fired, _) = self.run_failing_at(step, || scenario(step));
+            if !fired {
+                return step - 1;
+            }
+            step += 1;
+        }
+    }
+}
+
+/// Fail calls to `target`, see `Fault` for more control.
+pub fn arm(target: Target, failure: Failure, trigger: Trigger) -> FaultId {
+    Fault::new(target, failure).trigger(trigger).arm()
+}
+
+/// Stop failing calls. `false` if the fault was disarmed already, by hand or because
+/// it ran out of shots.
+pub fn disarm(id: FaultId) -> bool {
+    with_state(|state| {
+        let slot = state
+            .faults
+            .iter_mut()
+            .find(|f| f.as_ref().map_or(false, |f| f.id == id));
+        match slot {
+            Some(slot) => {
+                *slot = None;
+                state.install_hooks();
+                true
+            }
+            None => false,
+        }
+    })
+}
+
+pub fn disarm_all() {
+    with_state(|state| {
+        state.faults = [None; FAULT_CAPACITY];
+        state.install_hooks();
+    })
+}
+
+pub fn is_armed(id: FaultId) -> bool {
+    with_state(|state| state.faults.iter().flatten().any(|f| f.id == id))
+}
+
+/// The faults that fired since the last `clear_report`, oldest first. Only the first
+/// `REPORT_CAPACITY` are kept.
+pub fn report() -> Vec<Fired> {
+    // Copied out first, as building the `Vec` goes through the allocator's hook.
+    let (log, logged) = with_state(|state| (state.log, state.logged));
+    log[..logged].to_vec()
+}
+
+pub fn clear_report() {
+    with_state(|state| state.logged = 0)
+}
+
+#[derive(Copy, Clone)]
+struct Armed {
+    id: FaultId,
+    fault: Fault,
+    /// Matching calls so far.
+    calls: u32,
+    fired: u32,
+    rng: u32,
+}
+
+impl Armed {
+    /// Count a matching call and decide whether it fails. Returns whether the fault is
+    /// spent as well.
+    fn fire(&mut self) -> (bool, bool) {
+        self.calls = self.calls.saturating_add(1);
+        let fire = match self.fault.trigger {
+            Trigger::Always => true,
+            Trigger::Nth(n) => self.calls == n,
+            Trigger::Probability { percent, .. } => {
+                // xorshift32
+                self.rng ^= self.rng << 13;
+                self.rng ^= self.rng >> 17;
+                self.rng ^= self.rng << 5;
+                self.rng % 100 < percent as u32
+            }
+        };
+        if fire {
+            self.fired += 1;
+        }
+
+        let spent = match self.fault.trigger {
+            Trigger::Nth(n) => self.calls >= n,
+            _ => false,
+        } || self.fault.shots.map_or(false, |shots| self.fired >= shots);
+        (fire, spent)
+    }
+}
+
+struct State {
+    faults: [Option<Armed>; FAULT_CAPACITY],
+    log: [Fired; REPORT_CAPACITY],
+    logged: usize,
+    next_id: u32,
+}
+
+impl State {
+    /// Point the hook table at `fault_hook` for the points with armed faults only, so
+    /// other calls stay as cheap as without faults.
+    fn install_hooks(&self) {
+        for point in POINTS {
+            let armed = self
+                .faults
+                .iter()
+                .flatten()
+                .any(|f| f.fault.target.point() == point);
+            let hook = if armed {
+                Some(fault_hook as FaultHook)
+            } else {
+                None
+            };
+            unsafe { freertos_rs_set_fault_hook(point as u8, hook) };
+        }
+    }
+}
+
+const NOT_FIRED: Fired = Fired {
+    fault: FaultId(0),
+    target: Target::Malloc { size_over: 0 },
+    call: 0,
+    tick: 0,
+    task: None,
+};
+
+// Also used from inside the allocator, so it can't allocate.
+static mut STATE: State = State {
+    faults: [None; FAULT_CAPACITY],
+    log: [NOT_FIRED; REPORT_CAPACITY],
+    logged: 0,
+    next_id: 0,
+};
+
+fn with_state<R>(f: impl FnOnce(&mut State) -> R) -> R {
+    let _lock = CriticalRegion::enter();
+    f(unsafe { &mut *ptr::addr_of_mut!(STATE) })
+}
+
+/// The current task's name, `None` before the scheduler started.
+fn current_task_name() -> Option<&'static [u8]> {
+    unsafe {
+        let task = freertos_rs_get_current_task();
+        if task.is_null() {
+            None
+        } else {
+            Some(bytes_from_c_string(freertos_rs_task_get_name(task)))
+        }
+    }
+}
+
+/// Called by the shims at the interception points with armed faults.
+extern "C" fn fault_hook(point: u8, object: FreeRtosVoidPtr, arg: usize) -> u8 {
+    let task = current_task_name();
+
+    with_state(|state| {
+        let mut fired = None;
+        let mut spent_any = false;
+
+        for slot in state.faults.iter_mut() {
+            let armed = match slot {
+                Some(armed) if armed.fault.target.point() as u8 == point => armed,
+                _ => continue,
+            };
+            if !unsafe { armed.fault.target.matches(object, arg) } {
+                continue;
+            }
+            if let Some(name) = armed.fault.task {
+                if task != Some(name.as_bytes()) {
+                    continue;
+                }
+            }
+
+            let (fire, spent) = armed.fire();
+            if fire {
+                fired = Some(Fired {
+                    fault: armed.id,
+                    target: armed.fault.target,
+                    call: armed.calls,
+                    tick: unsafe { freertos_rs_xTaskGetTickCount() },
+                    task: task.map(TaskName::from_bytes),
+                });
+            }
+            if spent {
+                *slot = None;
+                spent_any = true;
+            }
+            if fire {
+                break;
+            }
+        }
+
+        if spent_any {
+            state.install_hooks();
+        }
+        match fired {
+            Some(fired) => {
+                if state.logged < REPORT_CAPACITY {
+                    state.log[state.logged] = fired;
+                    state.logged += 1;
+                }
+                1
+            }
+            None => 0,
+        }
+    })
+}
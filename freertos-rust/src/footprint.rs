@@ -0,0 +1,67 @@
+use crate::base::*;
+use crate::capacities::*;
+use crate::hooks::*;
+use crate::prelude::v1::*;
+use crate::task::*;
+
+/// The size and alignment of a boxed task or timer closure, i.e. of everything it captured.
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub struct ClosureFootprint {
+    pub size: usize,
+    pub align: usize,
+}
+
+impl ClosureFootprint {
+    pub fn of<F>(f: &F) -> ClosureFootprint {
+        ClosureFootprint {
+            size: mem::size_of_val(f),
+            align: mem::align_of_val(f),
+        }
+    }
+}
+
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum ClosureOwner {
+    Task(FreeRtosTaskHandle),
+    Timer(FreeRtosTimerHandle),
+}
+
+/// The closure footprint recorded when a task or timer was created.
+#[derive(Debug, Copy, Clone)]
+pub struct ClosureRecord {
+    pub owner: ClosureOwner,
+    pub name: TaskName,
+    pub footprint: ClosureFootprint,
+}
+
+/// Record the closure of a newly created task or timer, and warn through
+/// `FREERTOS_HOOKS` if it is larger than the configured threshold.
+pub(crate) fn record(owner: ClosureOwner, name: &str, footprint: ClosureFootprint) {
+    let record = ClosureRecord {
+        owner,
+        name: TaskName::from_bytes(name.as_bytes()),
+        footprint,
+    };
+    if with_closure_records(|records| records.push(record)).is_err() {
+        // Only the record is lost, the task or timer itself was created.
+        registry_full(RegistryKind::ClosureRecords);
+    }
+
+    unsafe {
+        (*ptr::addr_of!(FREERTOS_HOOKS)).do_on_closure_footprint(name, footprint);
+    }
+}
+
+/// The closure footprint recorded for a task.
+pub fn task_closure_footprint(task: &dyn TaskHandle) -> Option<ClosureFootprint> {
+    let owner = ClosureOwner::Task(task.raw_handle());
+    closure_records()
+        .iter()
+        .find(|r| r.owner == owner)
+        .map(|r| r.footprint)
+}
+
+/// Every closure footprint recorded since startup.
+pub fn closure_records() -> Vec<ClosureRecord> {
+    with_closure_records(|records| records.iter().cloned().collect())
+}
@@ -0,0 +1,348 @@
+use crate::allocator::*;
+use crate::base::*;
+use crate::isr::*;
+use crate::no_block::in_no_block_section;
+use crate::prelude::v1::*;
+use crate::shim::*;
+use crate::sync::atomic::{fence, AtomicU16, AtomicU32, Ordering};
+use core::alloc::{GlobalAlloc, Layout};
+use core::ops::Deref;
+
+impl<T, C: RefCount> !ISRSafe for FrArc<T, C> {}
+impl<T, C: RefCount> !ISRSafe for FrWeak<T, C> {}
+
+mod private {
+    pub trait Sealed {}
+}
+
+/// The width of the reference counts of an `FrArc`, `u16` or `u32`.
+pub trait RefCount: private::Sealed + 'static {
+    #[doc(hidden)]
+    type Atomic;
+
+    /// The bits below the `LastDrop` kept at the top of the weak count.
+    #[doc(hidden)]
+    const COUNT_MASK: usize;
+
+    #[doc(hidden)]
+    fn new(count: usize) -> Self::Atomic;
+    #[doc(hidden)]
+    fn load(count: &Self::Atomic, order: Ordering) -> usize;
+    #[doc(hidden)]
+    fn increment(count: &Self::Atomic, order: Ordering) -> usize;
+    #[doc(hidden)]
+    fn decrement(count: &Self::Atomic, order: Ordering) -> usize;
+    #[doc(hidden)]
+    fn compare_exchange_weak(
+        count: &Self::Atomic,
+        current: usize,
+        new: usize,
+        success: Ordering,
+        failure: Ordering,
+    ) -> Result<usize, usize>;
+}
+
+macro_rules! ref_count {
+    ($t:ty, $atomic:ty) => {
+        impl private::Sealed for $t {}
+
+        impl RefCount for $t {
+            type Atomic = $atomic;
+
+            const COUNT_MASK: usize = <$t>::MAX as usize >> 2;
+
+            fn new(count: usize) -> $atomic {
+                <$atomic>::new(count as $t)
+            }
+
+            fn load(count: &$atomic, order: Ordering) -> usize {
+                count.load(order) as usize
+            }
+
+            fn increment(count: &$atomic, order: Ordering) -> usize {
+                count.fetch_add(1, order) as usize
+            }
+
+            fn decrement(count: &$atomic, order: Ordering) -> usize {
+                count.fetch_sub(1, order) as usize
+            }
+
+            fn compare_exchange_weak(
+                count: &$atomic,
+                current: usize,
+                new: usize,
+                success: Ordering,
+                failure: Ordering,
+            ) -> Result<usize, usize> {
+                count
+                    .compare_exchange_weak(current as $t, new as $t, success, failure)
+                    .map(|c| c as usize)
+                    .map_err(|c| c as usize)
+            }
+        }
+    };
+}
+
+ref_count!(u16, AtomicU16);
+ref_count!(u32, AtomicU32);
+
+/// Counts stop before this, so racing increments can't reach the `LastDrop` bits.
+fn limit<C: RefCount>() -> usize {
+    C::COUNT_MASK / 2
+}
+
+fn last_drop_shift<C: RefCount>() -> u32 {
+    C::COUNT_MASK.count_ones()
+}
+
+/// Where the value of an `FrArc` is dropped and its memory freed when the last strong
+/// reference goes away.
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum LastDrop {
+    /// Right where the last reference is dropped.
+    Immediate = 0,
+    /// In the timer daemon task, unless the timer command queue is full.
+    Deferred = 1,
+    /// In the timer daemon task when the last reference is dropped with the scheduler
+    /// suspended or, with `rt_checks`, inside a `NoBlockSection`. Right away otherwise.
+    WhenConstrained = 2,
+}
+
+#[repr(C)]
+struct FrArcInner<T, C: RefCount> {
+    strong: C::Atomic,
+    /// One more than the `FrWeak`s while there are strong references, below the
+    /// `LastDrop` in the top two bits.
+    weak: C::Atomic,
+    value: T,
+}
+
+/// A reference counted pointer like `alloc::sync::Arc`, allocated with
+/// `FreeRtosAllocator` whatever the global allocator is.
+///
+/// The counts are `u32` by default, or `u16` with `FrArc<T, u16>`. The header in front of
+/// the value takes 4 bytes with `u16` counts and 8 with `u32`, where `Arc`'s takes two
+/// `usize`s: `FrArc<u32, u16>` takes 8 bytes of heap, `Arc<u32>` 12 on 32-bit targets
+/// and 24 on 64-bit ones.
+///
+/// Counts panic at an eighth of their range, 8191 references with `u16` counts.
+///
+/// `FrWeak` works as `alloc::sync::Weak`: it doesn't keep the value alive and `upgrade`
+/// fails once the last `FrArc` is gone.
+///
+/// `FrArc` isn't `ISRSafe`, because its last drop frees memory. Interrupts read the value
+/// through an `FrArcIsrRef`, which doesn't touch the counts.
+pub struct FrArc<T, C: RefCount = u32> {
+    inner: *mut FrArcInner<T, C>,
+}
+
+unsafe impl<T: Send + Sync, C: RefCount> Send for FrArc<T, C> {}
+unsafe impl<T: Send + Sync, C: RefCount> Sync for FrArc<T, C> {}
+
+impl<T: Send> FrArc<T> {
+    /// Allocate `value` with `u32` counts and `LastDrop::WhenConstrained`.
+    pub fn new(value: T) -> Result<FrArc<T>, FreeRtosError> {
+        Self::with_last_drop(value, LastDrop::WhenConstrained)
+    }
+}
+
+impl<T: Send, C: RefCount> FrArc<T, C> {
+    /// Allocate `value`, e.g. with `u16` counts:
+    /// `FrArc::<_, u16>::with_last_drop(value, LastDrop::WhenConstrained)`.
+    pub fn with_last_drop(value: T, last_drop: LastDrop) -> Result<FrArc<T, C>, FreeRtosError> {
+        let inner = unsafe { FreeRtosAllocator.alloc(Layout::new::<FrArcInner<T, C>>()) }
+            as *mut FrArcInner<T, C>;
+        if inner.is_null() {
+            return Err(FreeRtosError::OutOfMemory);
+        }
+
+        unsafe {
+            inner.write(FrArcInner {
+                strong: C::new(1),
+                weak: C::new(1 | (last_drop as usize) << last_drop_shift::<C>()),
+                value,
+            });
+        }
+
+        Ok(FrArc { inner })
+    }
+}
+
+impl<T, C: RefCount> FrArc<T, C> {
+    fn inner(&self) -> &FrArcInner<T, C> {
+        unsafe { &*self.inner }
+    }
+
+    pub fn downgrade(this: &Self) -> FrWeak<T, C> {
+        increment::<C>(&this.inner().weak);
+        FrWeak { inner: this.inner }
+    }
+
+    pub fn strong_count(this: &Self) -> usize {
+        C::load(&this.inner().strong, Ordering::Acquire)
+    }
+
+    pub fn weak_count(this: &Self) -> usize {
+        (C::load(&this.inner().weak, Ordering::Acquire) & C::COUNT_MASK) - 1
+    }
+
+    pub fn ptr_eq(this: &Self, other: &Self) -> bool {
+        this.inner == other.inner
+    }
+
+    /// Drop the value and give up the weak reference all strong ones hold together.
+    unsafe fn drop_slow(inner: *mut FrArcInner<T, C>) {
+        ptr::drop_in_place(ptr::addr_of_mut!((*inner).value));
+        release_weak(inner);
+    }
+
+    /// Hand the last drop to the timer daemon task. False if its queue is full.
+    fn defer_drop(&self) -> bool {
+        unsafe {
+            freertos_rs_pend_function_call(
+                drop_deferred::<T, C>,
+                self.inner as FreeRtosMutVoidPtr,
+                0,
+                0,
+            ) == 0
+        }
+    }
+
+    fn defer_last_drop(&self) -> bool {
+        let weak = C::load(&self.inner().weak, Ordering::Relaxed);
+        match weak >> last_drop_shift::<C>() {
+            0 => false,
+            1 => true,
+            _ => {
+                let suspended = unsafe { freertos_rs_xTaskGetSchedulerState() } == 0;
+                suspended || in_no_block_section()
+            }
+        }
+    }
+}
+
+extern "C" fn drop_deferred<T, C: RefCount>(inner: FreeRtosMutVoidPtr, _: u32) {
+    unsafe { FrArc::drop_slow(inner as *mut FrArcInner<T, C>) }
+}
+
+fn increment<C: RefCount>(count: &C::Atomic) {
+    if C::increment(count, Ordering::Relaxed) & C::COUNT_MASK >= limit::<C>() {
+        C::decrement(count, Ordering::Relaxed);
+        panic!("FrArc reference count overflow");
+    }
+}
+
+unsafe fn release_weak<T, C: RefCount>(inner: *mut FrArcInner<T, C>) {
+    if C::decrement(&(*inner).weak, Ordering::Release) & C::COUNT_MASK == 1 {
+        fence(Ordering::Acquire);
+        FreeRtosAllocator.dealloc(inner as *mut u8, Layout::new::<FrArcInner<T, C>>());
+    }
+}
+
+impl<T, C: RefCount> Clone for FrArc<T, C> {
+    fn clone(&self) -> Self {
+        increment::<C>(&self.inner().strong);
+        FrArc { inner: self.inner }
+    }
+}
+
+impl<T, C: RefCount> Deref for FrArc<T, C> {
+    type Target = T;
+
+    fn deref(&self) -> &T {
+        &self.inner().value
+    }
+}
+
+impl<T, C: RefCount> Drop for FrArc<T, C> {
+    fn drop(&mut self) {
+        if C::decrement(&self.inner().strong, Ordering::Release) != 1 {
+            return;
+        }
+        fence(Ordering::Acquire);
+
+        if self.defer_last_drop() && self.defer_drop() {
+            return;
+        }
+        unsafe { Self::drop_slow(self.inner) }
+    }
+}
+
+/// A weak reference to the value of an `FrArc`, see `FrArc::downgrade`.
+pub struct FrWeak<T, C: RefCount = u32> {
+    inner: *mut FrArcInner<T, C>,
+}
+
+unsafe impl<T: Send + Sync, C: RefCount> Send for FrWeak<T, C> {}
+unsafe impl<T: Send + Sync, C: RefCount> Sync for FrWeak<T, C> {}
+
+impl<T, C: RefCount> FrWeak<T, C> {
+    fn inner(&self) -> &FrArcInner<T, C> {
+        unsafe { &*self.inner }
+    }
+
+    /// A new strong reference, unless the value was dropped.
+    pub fn upgrade(&self) -> Option<FrArc<T, C>> {
+        let strong = &self.inner().strong;
+        let mut count = C::load(strong, Ordering::Relaxed);
+        loop {
+            if count == 0 {
+                return None;
+            }
+            if count >= limit::<C>() {
+                panic!("FrArc reference count overflow");
+            }
+            match C::compare_exchange_weak(
+                strong,
+                count,
+                count + 1,
+                Ordering::Acquire,
+                Ordering::Relaxed,
+            ) {
+                Ok(_) => return Some(FrArc { inner: self.inner }),
+                Err(current) => count = current,
+            }
+        }
+    }
+
+    pub fn strong_count(&self) -> usize {
+        C::load(&self.inner().strong, Ordering::Acquire)
+    }
+}
+
+impl<T, C: RefCount> Clone for FrWeak<T, C> {
+    fn clone(&self) -> Self {
+        increment::<C>(&self.inner().weak);
+        FrWeak { inner: self.inner }
+    }
+}
+
+impl<T, C: RefCount> Drop for FrWeak<T, C> {
+    fn drop(&mut self) {
+        unsafe { release_weak(self.inner) }
+    }
+}
+
+/// An ISR safe read-only borrow of the value of an `FrArc`. It doesn't hold a reference
+/// count, so the `FrArc` it was created from has to outlive it, see `ISRSafeHandle`.
+pub struct FrArcIsrRef<T> {
+    value: *const T,
+}
+
+unsafe impl<T: Sync> Send for FrArcIsrRef<T> {}
+unsafe impl<T: Sync> Sync for FrArcIsrRef<T> {}
+
+impl<T: Sync + ISRSafe, C: RefCount> ISRSafeHandle<FrArcIsrRef<T>> for FrArc<T, C> {
+    unsafe fn new_isr_safe_handle(&self) -> FrArcIsrRef<T> {
+        FrArcIsrRef {
+            value: &self.inner().value,
+        }
+    }
+}
+
+impl<T> FrArcIsrRef<T> {
+    /// The value, from an interrupt of any class.
+    pub fn get<I: IsrClass>(&self, _context: &InterruptContext<I>) -> &T {
+        unsafe { &*self.value }
+    }
+}
@@ -0,0 +1,564 @@
+use crate::base::*;
+use crate::isr::*;
+use crate::prelude::v1::*;
+use crate::queue::*;
+use crate::shim::*;
+use crate::units::*;
+
+impl<C: FrameCodec, T: ByteTransport> !ISRSafe for FramedSender<C, T> {}
+impl<C: FrameCodec, T: ByteTransport> !ISRSafe for FramedReceiver<C, T> {}
+
+/// Errors while encoding, decoding or moving frames.
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum FrameError {
+    /// The output buffer can't hold the encoded or decoded frame.
+    BufferTooSmall,
+    /// The frame is longer than the maximum frame size.
+    TooLong,
+    /// The byte stream isn't valid for the codec.
+    InvalidEncoding,
+    /// The frame's checksum doesn't match its contents.
+    Checksum,
+    /// The frame ended early.
+    Truncated,
+    /// The underlying transport failed, usually by timing out.
+    Transport(FreeRtosError),
+}
+
+/// The result of pushing a byte into a frame decoder.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum DecodeEvent {
+    None,
+    /// A complete frame is in this range of the decode buffer.
+    Frame(Range<usize>),
+    /// The current frame was dropped. The decoder resynchronizes on the next frame.
+    Error(FrameError),
+}
+
+/// Incremental decoder, fed one byte at a time.
+pub trait FrameDecoder {
+    /// Feed one byte, decoding into `buf`, whose length is the maximum frame size.
+    fn push(&mut self, byte: u8, buf: &mut [u8]) -> DecodeEvent;
+
+    /// Drop any partial frame.
+    fn reset(&mut self);
+}
+
+/// A way to delimit packets in a byte stream.
+pub trait FrameCodec {
+    type Decoder: FrameDecoder;
+
+    /// The largest encoded size of a payload of `len` bytes.
+    fn max_encoded_len(&self, len: usize) -> usize;
+
+    /// Encode `payload` into `out`, returning the encoded length.
+    fn encode_into(&self, payload: &[u8], out: &mut [u8]) -> Result<usize, FrameError>;
+
+    fn decoder(&self) -> Self::Decoder;
+}
+
+/// Consistent Overhead Byte Stuffing, with frames terminated by a zero byte.
+#[derive(Debug, Copy, Clone, Default)]
+pub struct Cobs;
+
+impl FrameCodec for Cobs {
+    type Decoder = CobsDecoder;
+
+    fn max_encoded_len(&self, len: usize) -> usize {
+        len + len / 254 + 2
+    }
+
+    fn encode_into(&self, payload: &[u8], out: &mut [u8]) -> Result<usize, FrameError> {
+        if out.len() < self.max_encoded_len(payload.len()) {
+            return Err(FrameError::BufferTooSmall);
+        }
+
+        let mut code_index = 0;
+        let mut code = 1;
+        let mut o = 1;
+        for &byte in payload {
+            if byte == 0 {
+                out[code_index] = code;
+                code_index = o;
+                o += 1;
+                code = 1;
+            } else {
+                out[o] = byte;
+                o += 1;
+                code += 1;
+                if code == 0xFF {
+                    out[code_index] = code;
+                    code_index = o;
+                    o += 1;
+                    code = 1;
+                }
+            }
+        }
+        out[code_index] = code;
+        out[o] = 0;
+
+        Ok(o + 1)
+    }
+
+    fn decoder(&self) -> CobsDecoder {
+        CobsDecoder::default()
+    }
+}
+
+#[derive(Debug, Default)]
+pub struct CobsDecoder {
+    len: usize,
+    block: u8,
+    remaining: u8,
+    started: bool,
+    error: Option<FrameError>,
+}
+
+impl FrameDecoder for CobsDecoder {
+    fn push(&mut self, byte: u8, buf: &mut [u8]) -> DecodeEvent {
+        if byte == 0 {
+            let event = if let Some(error) = self.error {
+                DecodeEvent::Error(error)
+            } else if self.remaining != 0 {
+                DecodeEvent::Error(FrameError::Truncated)
+            } else if self.started {
+                DecodeEvent::Frame(0..self.len)
+            } else {
+                DecodeEvent::None
+            };
+            self.reset();
+            return event;
+        }
+
+        if self.error.is_some() {
+            return DecodeEvent::None;
+        }
+
+        if self.remaining == 0 {
+            // A block shorter than the maximum stands for a zero, unless it ends the frame.
+            if self.started && self.block != 0xFF && !self.store(0, buf) {
+                return DecodeEvent::None;
+            }
+            self.block = byte;
+            self.remaining = byte - 1;
+            self.started = true;
+        } else {
+            self.store(byte, buf);
+            self.remaining -= 1;
+        }
+
+        DecodeEvent::None
+    }
+
+    fn reset(&mut self) {
+        *self = CobsDecoder::default();
+    }
+}
+
+impl CobsDecoder {
+    fn store(&mut self, byte: u8, buf: &mut [u8]) -> bool {
+        if self.len >= buf.len() {
+            self.error = Some(FrameError::TooLong);
+            return false;
+        }
+        buf[self.len] = byte;
+        self.len += 1;
+        true
+    }
+}
+
+const SLIP_END: u8 = 0xC0;
+const SLIP_ESC: u8 = 0xDB;
+const SLIP_ESC_END: u8 = 0xDC;
+const SLIP_ESC_ESC: u8 = 0xDD;
+
+/// SLIP (RFC 1055) framing. Frames start and end with an END byte, so line noise before a
+/// frame is flushed as an empty frame and ignored. Empty payloads are therefore never delivered.
+#[derive(Debug, Copy, Clone, Default)]
+pub struct Slip;
+
+impl FrameCodec for Slip {
+    type Decoder = SlipDecoder;
+
+    fn max_encoded_len(&self, len: usize) -> usize {
+        len * 2 + 2
+    }
+
+    fn encode_into(&self, payload: &[u8], out: &mut [u8]) -> Result<usize, FrameError> {
+        let mut o = 0;
+        let mut put = |byte: u8| {
+            if o >= out.len() {
+                return Err(FrameError::BufferTooSmall);
+            }
+            out[o] = byte;
+            o += 1;
+            Ok(())
+        };
+
+        put(SLIP_END)?;
+        for &byte in payload {
+            match byte {
+                SLIP_END => {
+                    put(SLIP_ESC)?;
+                    put(SLIP_ESC_END)?;
+                }
+                SLIP_ESC => {
+                    put(SLIP_ESC)?;
+                    put(SLIP_ESC_ESC)?;
+                }
+                _ => put(byte)?,
+            }
+        }
+        put(SLIP_END)?;
+
+        Ok(o)
+    }
+
+    fn decoder(&self) -> SlipDecoder {
+        SlipDecoder::default()
+    }
+}
+
+#[derive(Debug, Default)]
+pub struct SlipDecoder {
+    len: usize,
+    escaped: bool,
+    error: Option<FrameError>,
+}
+
+impl FrameDecoder for SlipDecoder {
+    fn push(&mut self, byte: u8, buf: &mut [u8]) -> DecodeEvent {
+        if byte == SLIP_END {
+            let event = match self.error {
+                Some(error) => DecodeEvent::Error(error),
+                None if self.escaped => DecodeEvent::Error(FrameError::Truncated),
+                None if self.len > 0 => DecodeEvent::Frame(0..self.len),
+                None => DecodeEvent::None,
+            };
+            self.reset();
+            return event;
+        }
+
+        if self.error.is_some() {
+            return DecodeEvent::None;
+        }
+
+        let byte = if self.escaped {
+            self.escaped = false;
+            match byte {
+                SLIP_ESC_END => SLIP_END,
+                SLIP_ESC_ESC => SLIP_ESC,
+                _ => {
+                    self.error = Some(FrameError::InvalidEncoding);
+                    return DecodeEvent::None;
+                }
+            }
+        } else if byte == SLIP_ESC {
+            self.escaped = true;
+            return DecodeEvent::None;
+        } else {
+            byte
+        };
+
+        if self.len >= buf.len() {
+            self.error = Some(FrameError::TooLong);
+        } else {
+            buf[self.len] = byte;
+            self.len += 1;
+        }
+
+        DecodeEvent::None
+    }
+
+    fn reset(&mut self) {
+        *self = SlipDecoder::default();
+    }
+}
+
+const LENGTH_CRC_SYNC: u8 = 0xA5;
+
+/// CRC-16/CCITT-FALSE
+fn crc16(crc: u16, byte: u8) -> u16 {
+    let mut crc = crc ^ ((byte as u16) << 8);
+    for _ in 0..8 {
+        crc = if crc & 0x8000 != 0 {
+            (crc << 1) ^ 0x1021
+        } else {
+            crc << 1
+        };
+    }
+    crc
+}
+
+/// A sync byte, a little endian 16 bit length, the payload and a CRC-16 over the length
+/// and payload. After an error the decoder hunts for the next sync byte.
+#[derive(Debug, Copy, Clone, Default)]
+pub struct LengthCrc16;
+
+impl FrameCodec for LengthCrc16 {
+    type Decoder = LengthCrc16Decoder;
+
+    fn max_encoded_len(&self, len: usize) -> usize {
+        len + 5
+    }
+
+    fn encode_into(&self, payload: &[u8], out: &mut [u8]) -> Result<usize, FrameError> {
+        if payload.len() > u16::MAX as usize {
+            return Err(FrameError::TooLong);
+        }
+        let encoded_len = self.max_encoded_len(payload.len());
+        if out.len() < encoded_len {
+            return Err(FrameError::BufferTooSmall);
+        }
+
+        out[0] = LENGTH_CRC_SYNC;
+        out[1..3].copy_from_slice(&(payload.len() as u16).to_le_bytes());
+        out[3..3 + payload.len()].copy_from_slice(payload);
+        let crc = out[1..3 + payload.len()]
+            .iter()
+            .fold(0xFFFF, |crc, byte| crc16(crc, *byte));
+        out[3 + payload.len()..encoded_len].copy_from_slice(&crc.to_le_bytes());
+
+        Ok(encoded_len)
+    }
+
+    fn decoder(&self) -> LengthCrc16Decoder {
+        LengthCrc16Decoder::default()
+    }
+}
+
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Default)]
+enum LengthCrc16State {
+    #[default]
+    Sync,
+    LengthLow,
+    LengthHigh,
+    Payload,
+    CrcLow,
+    CrcHigh,
+}
+
+#[derive(Debug, Default)]
+pub struct LengthCrc16Decoder {
+    state: LengthCrc16State,
+    expected: usize,
+    len: usize,
+    crc: u16,
+    received_crc: u16,
+}
+
+impl FrameDecoder for LengthCrc16Decoder {
+    fn push(&mut self, byte: u8, buf: &mut [u8]) -> DecodeEvent {
+        match self.state {
+            LengthCrc16State::Sync => {
+                if byte == LENGTH_CRC_SYNC {
+                    self.reset();
+                    self.state = LengthCrc16State::LengthLow;
+                }
+            }
+            LengthCrc16State::LengthLow => {
+                self.crc = crc16(self.crc, byte);
+                self.expected = byte as usize;
+                self.state = LengthCrc16State::LengthHigh;
+            }
+            LengthCrc16State::LengthHigh => {
+                self.crc = crc16(self.crc, byte);
+                self.expected |= (byte as usize) << 8;
+                if self.expected > buf.len() {
+                    self.reset();
+                    return DecodeEvent::Error(FrameError::TooLong);
+                }
+                self.state = if self.expected == 0 {
+                    LengthCrc16State::CrcLow
+                } else {
+                    LengthCrc16State::Payload
+                };
+            }
+            LengthCrc16State::Payload => {
+                self.crc = crc16(self.crc, byte);
+                buf[self.len] = byte;
+                self.len += 1;
+                if self.len == self.expected {
+                    self.state = LengthCrc16State::CrcLow;
+                }
+            }
+            LengthCrc16State::CrcLow => {
+                self.received_crc = byte as u16;
+                self.state = LengthCrc16State::CrcHigh;
+            }
+            LengthCrc16State::CrcHigh => {
+                self.received_crc |= (byte as u16) << 8;
+                let event = if self.received_crc == self.crc {
+                    DecodeEvent::Frame(0..self.len)
+                } else {
+                    DecodeEvent::Error(FrameError::Checksum)
+                };
+                self.reset();
+                return event;
+            }
+        }
+
+        DecodeEvent::None
+    }
+
+    fn reset(&mut self) {
+        *self = LengthCrc16Decoder {
+            crc: 0xFFFF,
+            ..LengthCrc16Decoder::default()
+        };
+    }
+}
+
+/// A byte stream that frames can be sent over.
+pub trait ByteTransport {
+    /// Write all of `bytes`, waiting up to `max_wait` for each chunk to fit.
+    fn write(&self, bytes: &[u8], max_wait: Duration) -> Result<(), FreeRtosError>;
+
+    /// Wait up to `max_wait` for at least one byte and read as many as are available
+    /// into `buf`, returning how many were read.
+    fn read(&self, buf: &mut [u8], max_wait: Duration) -> Result<usize, FreeRtosError>;
+}
+
+impl ByteTransport for Queue<u8> {
+    fn write(&self, bytes: &[u8], max_wait: Duration) -> Result<(), FreeRtosError> {
+        for &byte in bytes {
+            self.send(byte, max_wait)?;
+        }
+        Ok(())
+    }
+
+    fn read(&self, buf: &mut [u8], max_wait: Duration) -> Result<usize, FreeRtosError> {
+        if buf.is_empty() {
+            return Ok(0);
+        }
+
+        buf[0] = self.receive(max_wait)?;
+        let mut len = 1;
+        while len < buf.len() {
+            match self.receive(Duration::zero()) {
+                Ok(byte) => {
+                    buf[len] = byte;
+                    len += 1;
+                }
+                Err(_) => break,
+            }
+        }
+        Ok(len)
+    }
+}
+
+/// Sends whole packets over a byte transport.
+pub struct FramedSender<C: FrameCodec, T: ByteTransport> {
+    codec: C,
+    transport: T,
+    max_frame: usize,
+    buf: Vec<u8>,
+}
+
+impl<C: FrameCodec, T: ByteTransport> FramedSender<C, T> {
+    pub fn new(codec: C, transport: T, max_frame: usize) -> FramedSender<C, T> {
+        let buf = vec![0; codec.max_encoded_len(max_frame)];
+        FramedSender {
+            codec,
+            transport,
+            max_frame,
+            buf,
+        }
+    }
+
+    pub fn send<D: DurationTicks>(&mut self, payload: &[u8], timeout: D) -> Result<(), FrameError> {
+        if payload.len() > self.max_frame {
+            return Err(FrameError::TooLong);
+        }
+
+        let len = self.codec.encode_into(payload, &mut self.buf)?;
+        self.transport
+            .write(&self.buf[..len], Duration::ticks(timeout.to_ticks()))
+            .map_err(FrameError::Transport)
+    }
+
+    pub fn transport(&self) -> &T {
+        &self.transport
+    }
+}
+
+/// Receives whole packets from a byte transport, skipping corrupted frames.
+pub struct FramedReceiver<C: FrameCodec, T: ByteTransport> {
+    decoder: C::Decoder,
+    transport: T,
+    frame: Vec<u8>,
+    rx: [u8; 32],
+    rx_pos: usize,
+    rx_len: usize,
+    frames: u32,
+    errors: u32,
+}
+
+impl<C: FrameCodec, T: ByteTransport> FramedReceiver<C, T> {
+    pub fn new(codec: C, transport: T, max_frame: usize) -> FramedReceiver<C, T> {
+        FramedReceiver {
+            decoder: codec.decoder(),
+            transport,
+            frame: vec![0; max_frame],
+            rx: [0; 32],
+            rx_pos: 0,
+            rx_len: 0,
+            frames: 0,
+            errors: 0,
+        }
+    }
+
+    /// Wait up to `timeout` for the next intact frame and copy it into `out`.
+    pub fn recv<D: DurationTicks>(
+        &mut self,
+        out: &mut [u8],
+        timeout: D,
+    ) -> Result<usize, FrameError> {
+        let start = unsafe { freertos_rs_xTaskGetTickCount() };
+        let timeout = timeout.to_ticks();
+
+        loop {
+            while self.rx_pos < self.rx_len {
+                let byte = self.rx[self.rx_pos];
+                self.rx_pos += 1;
+
+                match self.decoder.push(byte, &mut self.frame) {
+                    DecodeEvent::None => {}
+                    DecodeEvent::Frame(range) => {
+                        let frame = &self.frame[range];
+                        if frame.len() > out.len() {
+                            self.errors += 1;
+                            return Err(FrameError::BufferTooSmall);
+                        }
+                        out[..frame.len()].copy_from_slice(frame);
+                        self.frames += 1;
+                        return Ok(frame.len());
+                    }
+                    DecodeEvent::Error(_) => self.errors += 1,
+                }
+            }
+
+            let wait = remaining_wait(start, timeout)
+                .ok_or(FrameError::Transport(FreeRtosError::Timeout))?;
+            self.rx_len = self
+                .transport
+                .read(&mut self.rx, wait)
+                .map_err(FrameError::Transport)?;
+            self.rx_pos = 0;
+        }
+    }
+
+    /// Frames received intact.
+    pub fn frames(&self) -> u32 {
+        self.frames
+    }
+
+    /// Frames dropped because they were corrupted, too long, or didn't fit the caller's buffer.
+    pub fn errors(&self) -> u32 {
+        self.errors
+    }
+
+    pub fn transport(&self) -> &T {
+        &self.transport
+    }
+}
@@ -0,0 +1,84 @@
+/*
+FreeRTOS.rs heap_4 with in-place resizing.
+
+Built instead of heap_4.c when freertos-rust has the heap_4 feature. It includes the
+kernel's heap_4.c, so the resize can work on its free list directly.
+*/
+
+#ifdef FREERTOS_RS_HEAP_4_REALLOC
+
+#include "heap_4.c"
+
+/* Resize the block at pv to hold xWantedSize bytes without moving it. Shrinking always
+   works, growing only when the block right after is free and large enough. Returns 0
+   on success, 1 if the block has to be moved, with the heap left untouched. */
+uint8_t freertos_rs_heap_4_resize_in_place(void *pv, size_t xWantedSize)
+{
+	BlockLink_t *pxLink = (BlockLink_t *)(((uint8_t *)pv) - xHeapStructSize);
+	BlockLink_t *pxNext, *pxPrevious, *pxRemainder;
+	size_t xCurrentSize, xTotalSize;
+	uint8_t ucResult = 1;
+
+	if ((xWantedSize == 0) || ((xWantedSize & xBlockAllocatedBit) != 0))
+	{
+		return 1;
+	}
+
+	xWantedSize += xHeapStructSize;
+	if ((xWantedSize & portBYTE_ALIGNMENT_MASK) != 0x00)
+	{
+		xWantedSize += (portBYTE_ALIGNMENT - (xWantedSize & portBYTE_ALIGNMENT_MASK));
+	}
+
+	configASSERT((pxLink->xBlockSize & xBlockAllocatedBit) != 0);
+	configASSERT(pxLink->pxNextFreeBlock == NULL);
+
+	vTaskSuspendAll();
+	{
+		xCurrentSize = pxLink->xBlockSize & ~xBlockAllocatedBit;
+		xTotalSize = xCurrentSize;
+
+		if (xWantedSize > xCurrentSize)
+		{
+			/* The free list is sorted by address, find the block that starts where
+			this one ends. */
+			pxNext = (BlockLink_t *)(((uint8_t *)pxLink) + xCurrentSize);
+			for (pxPrevious = &xStart; pxPrevious->pxNextFreeBlock < pxNext; pxPrevious = pxPrevious->pxNextFreeBlock)
+			{
+			}
+
+			if ((pxPrevious->pxNextFreeBlock == pxNext) && (pxNext != pxEnd) && (xCurrentSize + pxNext->xBlockSize >= xWantedSize))
+			{
+				pxPrevious->pxNextFreeBlock = pxNext->pxNextFreeBlock;
+				xFreeBytesRemaining -= pxNext->xBlockSize;
+				xTotalSize += pxNext->xBlockSize;
+			}
+		}
+
+		if (xWantedSize <= xTotalSize)
+		{
+			/* Give back what is left over, as pvPortMalloc() would. */
+			if ((xTotalSize - xWantedSize) > heapMINIMUM_BLOCK_SIZE)
+			{
+				pxRemainder = (void *)(((uint8_t *)pxLink) + xWantedSize);
+				pxRemainder->xBlockSize = xTotalSize - xWantedSize;
+				xFreeBytesRemaining += pxRemainder->xBlockSize;
+				prvInsertBlockIntoFreeList(pxRemainder);
+				xTotalSize = xWantedSize;
+			}
+
+			if (xFreeBytesRemaining < xMinimumEverFreeBytesRemaining)
+			{
+				xMinimumEverFreeBytesRemaining = xFreeBytesRemaining;
+			}
+
+			pxLink->xBlockSize = xTotalSize | xBlockAllocatedBit;
+			ucResult = 0;
+		}
+	}
+	(void)xTaskResumeAll();
+
+	return ucResult;
+}
+
+#endif
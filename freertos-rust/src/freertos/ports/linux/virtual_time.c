@@ -0,0 +1,173 @@
+/*
+ * Virtual time for the Linux (POSIX) port, used by the test_support module of
+ * freertos-rust.
+ *
+ * In virtual time the idle task doesn't wait for the tick when every task is
+ * blocked on time. With the scheduler suspended, portSUPPRESS_TICKS_AND_SLEEP()
+ * steps the tick count to one tick before the earliest unblock time, or before
+ * the next tick the Rust side waits for. The idle hook then raises the tick
+ * signal itself, so the last tick runs like one of the host timer: it unblocks
+ * the tasks, calls the tick hook and switches to the woken task.
+ *
+ * While tasks run, the host timer keeps ticking. In deterministic mode it is
+ * stopped when the timer daemon starts, and the tick only advances while the
+ * idle task runs, so each run unblocks the tasks in the same order however
+ * loaded the host is. A task that spins without blocking then stops the clock.
+ *
+ * FreeRTOSConfig.h needs, as in the linux example:
+ *
+ *   #define configUSE_IDLE_HOOK                 1
+ *   #define configUSE_TICK_HOOK                 1
+ *   #define configUSE_DAEMON_TASK_STARTUP_HOOK  1
+ *   #define configUSE_TICKLESS_IDLE             2
+ *   #define portSUPPRESS_TICKS_AND_SLEEP( x )   vPortVirtualTimeSuppressTicks( x )
+ *
+ * With the test_support feature, the daemon startup, idle and tick hooks of the
+ * shim call vPortVirtualTimeStart() and vPortVirtualTimeIdle(), and run the
+ * scheduled ISRs.
+ *
+ * Then setting FREERTOS_VIRTUAL_TIME=1 in the environment enables virtual time
+ * without changing the application, FREERTOS_VIRTUAL_TIME=deterministic enables
+ * it in deterministic mode.
+ */
+
+#include <pthread.h>
+#include <signal.h>
+#include <stdlib.h>
+#include <string.h>
+#include <sys/time.h>
+
+#include "FreeRTOS.h"
+#include "task.h"
+
+#if ( configUSE_TICKLESS_IDLE == 0 )
+	#error Virtual time needs configUSE_TICKLESS_IDLE, see virtual_time.c
+#endif
+
+static volatile BaseType_t xVirtualTime = pdFALSE;
+static volatile BaseType_t xDeterministic = pdFALSE;
+
+/* The tick count the idle task stepped to, pdFALSE in xStepped once the tick
+after it was raised or came from the host timer. */
+static volatile BaseType_t xStepped = pdFALSE;
+static volatile TickType_t xSteppedTo = 0;
+
+/* The earliest tick the Rust side waits for, portMAX_DELAY for none. */
+static volatile TickType_t xWakeAt = portMAX_DELAY;
+
+void freertos_rs_virtual_time_enable( BaseType_t xDeterministicOrder )
+{
+	xDeterministic = xDeterministicOrder;
+	xVirtualTime = pdTRUE;
+}
+
+BaseType_t freertos_rs_virtual_time_enabled( void )
+{
+	return xVirtualTime;
+}
+
+void freertos_rs_virtual_time_wake_at( TickType_t xTick )
+{
+	xWakeAt = xTick;
+}
+
+/* Called by the timer daemon before anything else, right after the host timer
+was started. */
+void vPortVirtualTimeStart( void )
+{
+	const char *pcMode = getenv( "FREERTOS_VIRTUAL_TIME" );
+	struct itimerval xStop;
+
+	if( pcMode != NULL && strcmp( pcMode, "1" ) == 0 )
+	{
+		freertos_rs_virtual_time_enable( pdFALSE );
+	}
+	else if( pcMode != NULL && strcmp( pcMode, "deterministic" ) == 0 )
+	{
+		freertos_rs_virtual_time_enable( pdTRUE );
+	}
+
+	if( xVirtualTime != pdFALSE && xDeterministic != pdFALSE )
+	{
+		memset( &xStop, 0, sizeof( xStop ) );
+		setitimer( TIMER_TYPE, &xStop, NULL );
+	}
+}
+
+/* portSUPPRESS_TICKS_AND_SLEEP(), called by the idle task with the scheduler
+suspended when no task is ready for at least xExpectedIdleTime ticks. */
+void vPortVirtualTimeSuppressTicks( TickType_t xExpectedIdleTime )
+{
+	TickType_t xNow, xJump, xUntil;
+
+	if( xVirtualTime == pdFALSE )
+	{
+		return;
+	}
+
+	taskENTER_CRITICAL();
+	{
+		xNow = xTaskGetTickCount();
+
+		/* Nothing waits on time when the expected idle time runs to
+		portMAX_DELAY, only step to the tick the Rust side waits for. */
+		if( xNow + xExpectedIdleTime == portMAX_DELAY )
+		{
+			xJump = 0;
+		}
+		else
+		{
+			xJump = xExpectedIdleTime - 1;
+		}
+
+		if( xWakeAt != portMAX_DELAY )
+		{
+			/* Across a wraparound, a tick in the past is more than half the
+			range ahead. */
+			xUntil = xWakeAt - xNow;
+			if( xUntil <= 1 || xUntil > portMAX_DELAY / 2 )
+			{
+				xJump = 0;
+			}
+			else if( xUntil - 1 < xJump || xJump == 0 )
+			{
+				xJump = xUntil - 1;
+			}
+		}
+
+		if( xJump > 0 )
+		{
+			vTaskStepTick( xJump );
+			xSteppedTo = xNow + xJump;
+			xStepped = pdTRUE;
+		}
+	}
+	taskEXIT_CRITICAL();
+}
+
+void vPortVirtualTimeIdle( void )
+{
+	BaseType_t xRaise;
+
+	if( xVirtualTime == pdFALSE )
+	{
+		return;
+	}
+
+	/* After a step, raise the tick only if the host timer didn't already. In
+	deterministic mode every turn of the idle task is a tick. */
+	xRaise = xDeterministic;
+	if( xStepped != pdFALSE )
+	{
+		xStepped = pdFALSE;
+		if( xTaskGetTickCount() == xSteppedTo )
+		{
+			xRaise = pdTRUE;
+		}
+	}
+
+	if( xRaise != pdFALSE )
+	{
+		pthread_kill( pthread_self(), SIG_TICK );
+	}
+}
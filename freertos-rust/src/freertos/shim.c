@@ -10,6 +10,51 @@ STM32 example:
 #include "timers.h"
 #include "queue.h"
 #include "semphr.h"
+#include "stream_buffer.h"
+#include "event_groups.h"
+
+#ifdef FREERTOS_RS_FAULT_INJECT
+/* Fault injection, see fault_inject.rs. A shim with an interception point consults its
+   entry in the hook table before calling the kernel, and fails without calling it when
+   the hook returns non-zero. Compiled out unless the fault_inject feature is enabled. */
+enum
+{
+	FREERTOS_RS_FAULT_QUEUE_SEND,
+	FREERTOS_RS_FAULT_QUEUE_RECEIVE,
+	FREERTOS_RS_FAULT_SEMAPHORE_TAKE,
+	FREERTOS_RS_FAULT_TASK_SPAWN,
+	FREERTOS_RS_FAULT_MALLOC,
+	FREERTOS_RS_FAULT_TIMER_COMMAND,
+	FREERTOS_RS_FAULT_TARGETS
+};
+
+typedef uint8_t (*freertos_rs_fault_hook)(uint8_t target, const void *object, size_t arg);
+
+static freertos_rs_fault_hook freertos_rs_fault_hooks[FREERTOS_RS_FAULT_TARGETS];
+
+void freertos_rs_set_fault_hook(uint8_t target, freertos_rs_fault_hook hook)
+{
+	if (target < FREERTOS_RS_FAULT_TARGETS)
+	{
+		freertos_rs_fault_hooks[target] = hook;
+	}
+}
+
+#define FREERTOS_RS_FAULT(target, object, arg, failure)                                   \
+	do                                                                                    \
+	{                                                                                     \
+		freertos_rs_fault_hook hook = freertos_rs_fault_hooks[target];                    \
+		if (hook && hook(target, (object), (size_t)(arg)))                                \
+		{                                                                                 \
+			return failure;                                                               \
+		}                                                                                 \
+	} while (0)
+#else
+#define FREERTOS_RS_FAULT(target, object, arg, failure) \
+	do                                                  \
+	{                                                   \
+	} while (0)
+#endif
 
 // Just for testing
 void freertos_rs_invoke_configASSERT()
@@ -17,13 +62,83 @@ void freertos_rs_invoke_configASSERT()
 	configASSERT(0);
 }
 
+/* The application hooks, dispatched to FREERTOS_HOOKS in hooks.rs. Each is defined when
+   FreeRTOSConfig.h enables it, so the application must not define it as well. */
+#if (configUSE_IDLE_HOOK == 1)
+void freertos_rs_idle_hook(void);
+
+void vApplicationIdleHook(void)
+{
+	freertos_rs_idle_hook();
+}
+#endif
+
+#if (configUSE_TICK_HOOK == 1)
+void freertos_rs_tick_hook(void);
+
+void vApplicationTickHook(void)
+{
+	freertos_rs_tick_hook();
+}
+#endif
+
+#if (configCHECK_FOR_STACK_OVERFLOW > 0)
+void freertos_rs_stack_overflow_hook(const char *pcTaskName);
+
+void vApplicationStackOverflowHook(TaskHandle_t xTask, char *pcTaskName)
+{
+	(void)xTask;
+	freertos_rs_stack_overflow_hook(pcTaskName);
+}
+#endif
+
+#if (configUSE_DAEMON_TASK_STARTUP_HOOK == 1)
+void freertos_rs_daemon_startup_hook(void);
+
+void vApplicationDaemonTaskStartupHook(void)
+{
+	freertos_rs_daemon_startup_hook();
+}
+#endif
+
 void freertos_rs_vTaskStartScheduler()
 {
 	vTaskStartScheduler();
 }
 
+#ifdef FREERTOS_RS_END_SCHEDULER
+/* Ending the scheduler on the POSIX port cancels the threads of all tasks, and a thread
+   cancelled in Rust code aborts the process. So tasks started from Rust keep their thread
+   with freertos_rs_task_keep_thread, and the task ending the scheduler waits here until
+   the process exits. The Windows port exits the process in vTaskEndScheduler. */
+#ifdef __unix__
+#include <pthread.h>
+#include <unistd.h>
+#endif
+
+void freertos_rs_task_keep_thread()
+{
+#ifdef __unix__
+	pthread_setcancelstate(PTHREAD_CANCEL_DISABLE, NULL);
+#endif
+}
+
+void freertos_rs_vTaskEndScheduler()
+{
+	vTaskEndScheduler();
+	for (;;)
+	{
+#ifdef __unix__
+		pause();
+#endif
+	}
+}
+#endif
+
 void *freertos_rs_pvPortMalloc(size_t xWantedSize)
 {
+	FREERTOS_RS_FAULT(FREERTOS_RS_FAULT_MALLOC, NULL, xWantedSize, NULL);
+
 	return pvPortMalloc(xWantedSize);
 }
 
@@ -32,6 +147,59 @@ void freertos_rs_vPortFree(void *pv)
 	vPortFree(pv);
 }
 
+#if defined(__GNUC__)
+/* Not every heap_?.c implements these, a missing one reads as 0. */
+size_t xPortGetFreeHeapSize(void) __attribute__((weak));
+size_t xPortGetMinimumEverFreeHeapSize(void) __attribute__((weak));
+void vPortGetHeapStats(HeapStats_t *pxHeapStats) __attribute__((weak));
+#endif
+
+size_t freertos_rs_xPortGetFreeHeapSize()
+{
+#if defined(__GNUC__)
+	if (!xPortGetFreeHeapSize)
+	{
+		return 0;
+	}
+#endif
+	return xPortGetFreeHeapSize();
+}
+
+size_t freertos_rs_xPortGetMinimumEverFreeHeapSize()
+{
+#if defined(__GNUC__)
+	if (!xPortGetMinimumEverFreeHeapSize)
+	{
+		return 0;
+	}
+#endif
+	return xPortGetMinimumEverFreeHeapSize();
+}
+
+UBaseType_t freertos_rs_get_heap_stats(HeapStats_t *stats)
+{
+#if defined(__GNUC__)
+	if (!vPortGetHeapStats)
+	{
+		return 1;
+	}
+#endif
+	vPortGetHeapStats(stats);
+	return 0;
+}
+
+#ifdef FREERTOS_RS_HEAP_5
+void freertos_rs_define_heap_regions(const HeapRegion_t *regions)
+{
+	vPortDefineHeapRegions(regions);
+}
+#endif
+
+UBaseType_t freertos_rs_get_portBYTE_ALIGNMENT()
+{
+	return portBYTE_ALIGNMENT;
+}
+
 uint8_t freertos_rs_sizeof(uint8_t _type)
 {
 	switch (_type)
@@ -87,6 +255,9 @@ uint8_t freertos_rs_sizeof(uint8_t _type)
 	case 33:
 		return sizeof(unsigned short);
 		break;
+	case 34:
+		return sizeof(configSTACK_DEPTH_TYPE);
+		break;
 
 		break;
 	default:
@@ -113,6 +284,37 @@ TickType_t freertos_rs_xTaskGetTickCount()
 	return xTaskGetTickCount();
 }
 
+TickType_t freertos_rs_xTaskGetTickCountFromISR()
+{
+	return xTaskGetTickCountFromISR();
+}
+
+UBaseType_t freertos_rs_is_inside_interrupt()
+{
+#ifdef FREERTOS_RS_INSIDE_INTERRUPT
+	return xPortIsInsideInterrupt() == pdTRUE;
+#else
+	return 0;
+#endif
+}
+
+void freertos_rs_vTaskSuspendAll()
+{
+	vTaskSuspendAll();
+}
+
+BaseType_t freertos_rs_xTaskResumeAll()
+{
+	return xTaskResumeAll();
+}
+
+#if ((INCLUDE_xTaskGetSchedulerState == 1) || (configUSE_TIMERS == 1))
+BaseType_t freertos_rs_xTaskGetSchedulerState()
+{
+	return xTaskGetSchedulerState();
+}
+#endif
+
 UBaseType_t freertos_rs_get_system_state(TaskStatus_t *const pxTaskStatusArray, const UBaseType_t uxArraySize, uint32_t *const pulTotalRunTime)
 {
 	return uxTaskGetSystemState(pxTaskStatusArray, uxArraySize, pulTotalRunTime);
@@ -128,6 +330,20 @@ UBaseType_t freertos_rs_get_number_of_tasks()
 	return uxTaskGetNumberOfTasks();
 }
 
+UBaseType_t freertos_rs_get_max_priorities()
+{
+	return configMAX_PRIORITIES;
+}
+
+UBaseType_t freertos_rs_get_timer_task_priority()
+{
+#if (configUSE_TIMERS == 1)
+	return configTIMER_TASK_PRIORITY;
+#else
+	return 0;
+#endif
+}
+
 #if (configUSE_RECURSIVE_MUTEXES == 1)
 QueueHandle_t freertos_rs_create_recursive_semaphore()
 {
@@ -136,6 +352,8 @@ QueueHandle_t freertos_rs_create_recursive_semaphore()
 
 UBaseType_t freertos_rs_take_recursive_semaphore(QueueHandle_t semaphore, UBaseType_t max)
 {
+	FREERTOS_RS_FAULT(FREERTOS_RS_FAULT_SEMAPHORE_TAKE, semaphore, 0, 1);
+
 	if (xSemaphoreTakeRecursive(semaphore, max) == pdTRUE)
 	{
 		return 0;
@@ -156,6 +374,13 @@ UBaseType_t freertos_rs_give_recursive_semaphore(QueueHandle_t semaphore)
 }
 #endif
 
+#if (INCLUDE_xSemaphoreGetMutexHolder == 1)
+TaskHandle_t freertos_rs_get_mutex_holder(QueueHandle_t mutex)
+{
+	return xSemaphoreGetMutexHolder(mutex);
+}
+#endif
+
 QueueHandle_t freertos_rs_create_semaphore()
 {
 	return xSemaphoreCreateMutex();
@@ -171,11 +396,84 @@ QueueHandle_t freertos_rs_create_counting_semaphore(UBaseType_t max, UBaseType_t
 	return xSemaphoreCreateCounting(max, initial);
 }
 
+/* The tasks blocked in the queue calls of the shim, for freertos_rs_queue_blocked_tasks.
+   Queue_t is private to queue.c, so the count is kept in the queue number of the trace
+   facility. A task counts from before its call blocks until it returns, so a queue isn't
+   deleted under a task about to block on it either. */
+#if (configUSE_TRACE_FACILITY == 1)
+static QueueHandle_t freertos_rs_queue_created(QueueHandle_t queue)
+{
+	if (queue != NULL)
+	{
+		vQueueSetQueueNumber(queue, 0);
+	}
+	return queue;
+}
+
+static void freertos_rs_queue_waiting(QueueHandle_t queue, TickType_t max_wait, BaseType_t waiting)
+{
+	if (max_wait == 0)
+	{
+		return;
+	}
+	taskENTER_CRITICAL();
+	UBaseType_t waiters = uxQueueGetQueueNumber(queue);
+	vQueueSetQueueNumber(queue, waiting == pdTRUE ? waiters + 1 : waiters - 1);
+	taskEXIT_CRITICAL();
+}
+
+UBaseType_t freertos_rs_queue_blocked_tasks(QueueHandle_t queue)
+{
+	return uxQueueGetQueueNumber(queue);
+}
+#else
+#define freertos_rs_queue_created(queue) (queue)
+#define freertos_rs_queue_waiting(queue, max_wait, waiting)
+
+UBaseType_t freertos_rs_queue_blocked_tasks(QueueHandle_t queue)
+{
+	(void)queue;
+	return 0;
+}
+#endif
+
+#if (configSUPPORT_STATIC_ALLOCATION == 1)
+uint32_t freertos_rs_static_queue_size()
+{
+	return sizeof(StaticQueue_t);
+}
+
+QueueHandle_t freertos_rs_create_semaphore_static(StaticSemaphore_t *control)
+{
+	return xSemaphoreCreateMutexStatic(control);
+}
+
+QueueHandle_t freertos_rs_create_binary_semaphore_static(StaticSemaphore_t *control)
+{
+	return xSemaphoreCreateBinaryStatic(control);
+}
+
+QueueHandle_t freertos_rs_queue_create_static(UBaseType_t queue_length, UBaseType_t item_size, uint8_t *storage, StaticQueue_t *control)
+{
+	return freertos_rs_queue_created(xQueueCreateStatic(queue_length, item_size, storage, control));
+}
+#endif
+
 UBaseType_t freertos_rs_semaphore_get_count(SemaphoreHandle_t xSemaphore)
 {
 	return uxSemaphoreGetCount(xSemaphore);
 }
 
+UBaseType_t freertos_rs_semaphore_get_count_isr(SemaphoreHandle_t xSemaphore)
+{
+	// uxSemaphoreGetCountFromISR is new in FreeRTOS 10.5, and is the same call.
+#ifdef uxSemaphoreGetCountFromISR
+	return uxSemaphoreGetCountFromISR(xSemaphore);
+#else
+	return uxQueueMessagesWaitingFromISR(xSemaphore);
+#endif
+}
+
 void freertos_rs_delete_semaphore(QueueHandle_t semaphore)
 {
 	vSemaphoreDelete(semaphore);
@@ -183,6 +481,8 @@ void freertos_rs_delete_semaphore(QueueHandle_t semaphore)
 
 UBaseType_t freertos_rs_take_semaphore(QueueHandle_t semaphore, UBaseType_t max)
 {
+	FREERTOS_RS_FAULT(FREERTOS_RS_FAULT_SEMAPHORE_TAKE, semaphore, 0, 1);
+
 	if (xSemaphoreTake(semaphore, max) == pdTRUE)
 	{
 		return 0;
@@ -221,8 +521,19 @@ UBaseType_t freertos_rs_give_semaphore_isr(QueueHandle_t semaphore, BaseType_t *
 	return 1;
 }
 
-UBaseType_t freertos_rs_spawn_task(TaskFunction_t entry_point, void *pvParameters, const char *const name, uint8_t name_len, uint16_t stack_size, UBaseType_t priority, TaskHandle_t *task_handle)
+#ifdef FREERTOS_RS_MPU
+/* The MPU ports create tasks unprivileged unless their priority has portPRIVILEGE_BIT.
+   Tasks spawned from Rust closures read them from the heap, so they keep the privilege;
+   restricted tasks are created with freertos_rs_spawn_task_restricted. */
+#define FREERTOS_RS_TASK_PRIORITY(priority) ((priority) | portPRIVILEGE_BIT)
+#else
+#define FREERTOS_RS_TASK_PRIORITY(priority) (priority)
+#endif
+
+UBaseType_t freertos_rs_spawn_task(TaskFunction_t entry_point, void *pvParameters, const char *const name, uint8_t name_len, configSTACK_DEPTH_TYPE stack_size, UBaseType_t priority, TaskHandle_t *task_handle)
 {
+	FREERTOS_RS_FAULT(FREERTOS_RS_FAULT_TASK_SPAWN, name, name_len, 1);
+
 	char c_name[configMAX_TASK_NAME_LEN] = {0};
 	for (int i = 0; i < name_len; i++)
 	{
@@ -234,7 +545,7 @@ UBaseType_t freertos_rs_spawn_task(TaskFunction_t entry_point, void *pvParameter
 		}
 	}
 
-	BaseType_t ret = xTaskCreate(entry_point, c_name, stack_size, pvParameters, priority, task_handle);
+	BaseType_t ret = xTaskCreate(entry_point, c_name, stack_size, pvParameters, FREERTOS_RS_TASK_PRIORITY(priority), task_handle);
 
 	if (ret != pdPASS)
 	{
@@ -246,6 +557,125 @@ UBaseType_t freertos_rs_spawn_task(TaskFunction_t entry_point, void *pvParameter
 	return 0;
 }
 
+uint32_t freertos_rs_stack_type_size()
+{
+	return sizeof(StackType_t);
+}
+
+#if (configSUPPORT_STATIC_ALLOCATION == 1)
+uint32_t freertos_rs_static_task_size()
+{
+	return sizeof(StaticTask_t);
+}
+
+TaskHandle_t freertos_rs_spawn_task_static(TaskFunction_t entry_point, void *pvParameters, const char *const name, uint8_t name_len, configSTACK_DEPTH_TYPE stack_size, UBaseType_t priority, StackType_t *stack, StaticTask_t *tcb)
+{
+	char c_name[configMAX_TASK_NAME_LEN] = {0};
+	for (int i = 0; i < name_len; i++)
+	{
+		c_name[i] = name[i];
+
+		if (i == configMAX_TASK_NAME_LEN - 1)
+		{
+			break;
+		}
+	}
+
+	return xTaskCreateStatic(entry_point, c_name, stack_size, pvParameters, FREERTOS_RS_TASK_PRIORITY(priority), stack, tcb);
+}
+#endif
+
+#ifdef FREERTOS_RS_MPU
+/* Mirrors FreeRtosRestrictedTaskFfi in base.rs, and MemoryRegion mirrors MemoryRegion_t.
+   Rust doesn't know portNUM_CONFIGURABLE_REGIONS, so it passes a slice of regions, copied
+   here into the fixed array of the kernel, with the unused ones zeroed. */
+typedef struct
+{
+	TaskFunction_t entry_point;
+	void *parameters;
+	const char *name;
+	uint8_t name_len;
+	configSTACK_DEPTH_TYPE stack_depth;
+	UBaseType_t priority;
+	uint8_t privileged;
+	StackType_t *stack;
+	const MemoryRegion_t *regions;
+	UBaseType_t region_count;
+} freertos_rs_restricted_task;
+
+UBaseType_t freertos_rs_get_configurable_regions()
+{
+	return portNUM_CONFIGURABLE_REGIONS;
+}
+
+static void freertos_rs_copy_regions(MemoryRegion_t *to, const MemoryRegion_t *from, UBaseType_t count)
+{
+	for (UBaseType_t i = 0; i < portNUM_CONFIGURABLE_REGIONS; i++)
+	{
+		if (i < count)
+		{
+			to[i] = from[i];
+		}
+		else
+		{
+			to[i].pvBaseAddress = NULL;
+			to[i].ulLengthInBytes = 0;
+			to[i].ulParameters = 0;
+		}
+	}
+}
+
+UBaseType_t freertos_rs_spawn_task_restricted(const freertos_rs_restricted_task *task, TaskHandle_t *task_handle)
+{
+	if (task->region_count > portNUM_CONFIGURABLE_REGIONS)
+	{
+		return 2;
+	}
+
+	char c_name[configMAX_TASK_NAME_LEN] = {0};
+	for (int i = 0; i < task->name_len; i++)
+	{
+		c_name[i] = task->name[i];
+
+		if (i == configMAX_TASK_NAME_LEN - 1)
+		{
+			break;
+		}
+	}
+
+	TaskParameters_t parameters = {
+		.pvTaskCode = task->entry_point,
+		.pcName = c_name,
+		.usStackDepth = task->stack_depth,
+		.pvParameters = task->parameters,
+		.uxPriority = task->privileged ? (task->priority | portPRIVILEGE_BIT) : task->priority,
+		.puxStackBuffer = task->stack,
+	};
+	freertos_rs_copy_regions(parameters.xRegions, task->regions, task->region_count);
+
+	if (xTaskCreateRestricted(&parameters, task_handle) != pdPASS)
+	{
+		return 1;
+	}
+
+	return 0;
+}
+
+UBaseType_t freertos_rs_allocate_mpu_regions(TaskHandle_t task, const MemoryRegion_t *regions, UBaseType_t region_count)
+{
+	if (region_count > portNUM_CONFIGURABLE_REGIONS)
+	{
+		return 1;
+	}
+
+	MemoryRegion_t all_regions[portNUM_CONFIGURABLE_REGIONS];
+	freertos_rs_copy_regions(all_regions, regions, region_count);
+	vTaskAllocateMPURegions(task, all_regions);
+
+	return 0;
+}
+#endif
+
 #if (INCLUDE_vTaskDelete == 1)
 void freertos_rs_delete_task(TaskHandle_t task)
 {
@@ -264,7 +694,7 @@ UBaseType_t freertos_rs_get_stack_high_water_mark(TaskHandle_t task)
 
 QueueHandle_t freertos_rs_queue_create(UBaseType_t queue_length, UBaseType_t item_size)
 {
-	return xQueueCreate(queue_length, item_size);
+	return freertos_rs_queue_created(xQueueCreate(queue_length, item_size));
 }
 
 void freertos_rs_queue_delete(QueueHandle_t queue)
@@ -272,9 +702,59 @@ void freertos_rs_queue_delete(QueueHandle_t queue)
 	vQueueDelete(queue);
 }
 
+void freertos_rs_queue_add_to_registry(QueueHandle_t queue, const char *name)
+{
+#if (configQUEUE_REGISTRY_SIZE > 0)
+	vQueueAddToRegistry(queue, name);
+#endif
+}
+
+void freertos_rs_queue_unregister(QueueHandle_t queue)
+{
+#if (configQUEUE_REGISTRY_SIZE > 0)
+	vQueueUnregisterQueue(queue);
+#endif
+}
+
+const char *freertos_rs_queue_get_name(QueueHandle_t queue)
+{
+#if (configQUEUE_REGISTRY_SIZE > 0)
+	return pcQueueGetName(queue);
+#else
+	return NULL;
+#endif
+}
+
+UBaseType_t freertos_rs_get_queue_registry_size()
+{
+	return configQUEUE_REGISTRY_SIZE;
+}
+
 UBaseType_t freertos_rs_queue_send(QueueHandle_t queue, void *item, TickType_t max_wait)
 {
-	if (xQueueSend(queue, item, max_wait) != pdTRUE)
+	FREERTOS_RS_FAULT(FREERTOS_RS_FAULT_QUEUE_SEND, queue, 0, 1);
+
+	freertos_rs_queue_waiting(queue, max_wait, pdTRUE);
+	BaseType_t result = xQueueSend(queue, item, max_wait);
+	freertos_rs_queue_waiting(queue, max_wait, pdFALSE);
+
+	if (result != pdTRUE)
+	{
+		return 1;
+	}
+
+	return 0;
+}
+
+UBaseType_t freertos_rs_queue_send_to_front(QueueHandle_t queue, void *item, TickType_t max_wait)
+{
+	FREERTOS_RS_FAULT(FREERTOS_RS_FAULT_QUEUE_SEND, queue, 0, 1);
+
+	freertos_rs_queue_waiting(queue, max_wait, pdTRUE);
+	BaseType_t result = xQueueSendToFront(queue, item, max_wait);
+	freertos_rs_queue_waiting(queue, max_wait, pdFALSE);
+
+	if (result != pdTRUE)
 	{
 		return 1;
 	}
@@ -291,18 +771,214 @@ UBaseType_t freertos_rs_queue_send_isr(QueueHandle_t queue, void *item, BaseType
 	return 1;
 }
 
+UBaseType_t freertos_rs_queue_send_to_front_isr(QueueHandle_t queue, void *item, BaseType_t *xHigherPriorityTaskWoken)
+{
+	if (xQueueSendToFrontFromISR(queue, item, xHigherPriorityTaskWoken) == pdTRUE)
+	{
+		return 0;
+	}
+	return 1;
+}
+
+UBaseType_t freertos_rs_queue_overwrite(QueueHandle_t queue, void *item)
+{
+	xQueueOverwrite(queue, item);
+	return 0;
+}
+
+UBaseType_t freertos_rs_queue_overwrite_isr(QueueHandle_t queue, void *item, BaseType_t *xHigherPriorityTaskWoken)
+{
+	xQueueOverwriteFromISR(queue, item, xHigherPriorityTaskWoken);
+	return 0;
+}
+
 UBaseType_t freertos_rs_queue_receive_isr(QueueHandle_t queue, void *item, BaseType_t *pxHigherPriorityTaskWoken)
 {
-	if (xQueueReceiveFromISR(queue, item, xHigherPriorityTaskWoken) == pdTRUE)
+	if (xQueueReceiveFromISR(queue, item, pxHigherPriorityTaskWoken) == pdTRUE)
 	{
 		return 0;
 	}
 	return 1;
 }
 
+UBaseType_t freertos_rs_queue_messages_waiting(QueueHandle_t queue)
+{
+	return uxQueueMessagesWaiting(queue);
+}
+
+UBaseType_t freertos_rs_queue_messages_waiting_isr(QueueHandle_t queue)
+{
+	return uxQueueMessagesWaitingFromISR(queue);
+}
+
+UBaseType_t freertos_rs_queue_spaces_available(QueueHandle_t queue)
+{
+	return uxQueueSpacesAvailable(queue);
+}
+
+#if (configUSE_QUEUE_SETS == 1)
+QueueSetHandle_t freertos_rs_queue_set_create(UBaseType_t length)
+{
+	return xQueueCreateSet(length);
+}
+
+UBaseType_t freertos_rs_queue_set_add(QueueSetMemberHandle_t member, QueueSetHandle_t set)
+{
+	if (xQueueAddToSet(member, set) != pdPASS)
+	{
+		return 1;
+	}
+	return 0;
+}
+
+UBaseType_t freertos_rs_queue_set_remove(QueueSetMemberHandle_t member, QueueSetHandle_t set)
+{
+	if (xQueueRemoveFromSet(member, set) != pdPASS)
+	{
+		return 1;
+	}
+	return 0;
+}
+
+QueueSetMemberHandle_t freertos_rs_queue_set_select(QueueSetHandle_t set, TickType_t max_wait)
+{
+	return xQueueSelectFromSet(set, max_wait);
+}
+#else
+/* Without queue sets no set can be created, so queues and semaphores are never members. */
+QueueSetHandle_t freertos_rs_queue_set_create(UBaseType_t length)
+{
+	(void)length;
+	return NULL;
+}
+
+UBaseType_t freertos_rs_queue_set_add(QueueSetMemberHandle_t member, QueueSetHandle_t set)
+{
+	(void)member;
+	(void)set;
+	return 1;
+}
+
+UBaseType_t freertos_rs_queue_set_remove(QueueSetMemberHandle_t member, QueueSetHandle_t set)
+{
+	(void)member;
+	(void)set;
+	return 0;
+}
+
+QueueSetMemberHandle_t freertos_rs_queue_set_select(QueueSetHandle_t set, TickType_t max_wait)
+{
+	(void)set;
+	(void)max_wait;
+	return NULL;
+}
+#endif
+
+StreamBufferHandle_t freertos_rs_stream_buffer_create(size_t size, size_t trigger_level)
+{
+	return xStreamBufferCreate(size, trigger_level);
+}
+
+void freertos_rs_stream_buffer_delete(StreamBufferHandle_t stream_buffer)
+{
+	vStreamBufferDelete(stream_buffer);
+}
+
+size_t freertos_rs_stream_buffer_send(StreamBufferHandle_t stream_buffer, const void *data, size_t len, TickType_t max_wait)
+{
+	return xStreamBufferSend(stream_buffer, data, len, max_wait);
+}
+
+size_t freertos_rs_stream_buffer_send_isr(StreamBufferHandle_t stream_buffer, const void *data, size_t len, BaseType_t *xHigherPriorityTaskWoken)
+{
+	return xStreamBufferSendFromISR(stream_buffer, data, len, xHigherPriorityTaskWoken);
+}
+
+size_t freertos_rs_stream_buffer_receive(StreamBufferHandle_t stream_buffer, void *buf, size_t len, TickType_t max_wait)
+{
+	return xStreamBufferReceive(stream_buffer, buf, len, max_wait);
+}
+
+size_t freertos_rs_stream_buffer_receive_isr(StreamBufferHandle_t stream_buffer, void *buf, size_t len, BaseType_t *xHigherPriorityTaskWoken)
+{
+	return xStreamBufferReceiveFromISR(stream_buffer, buf, len, xHigherPriorityTaskWoken);
+}
+
+size_t freertos_rs_stream_buffer_bytes_available(StreamBufferHandle_t stream_buffer)
+{
+	return xStreamBufferBytesAvailable(stream_buffer);
+}
+
+size_t freertos_rs_stream_buffer_spaces_available(StreamBufferHandle_t stream_buffer)
+{
+	return xStreamBufferSpacesAvailable(stream_buffer);
+}
+
+EventGroupHandle_t freertos_rs_event_group_create()
+{
+	return xEventGroupCreate();
+}
+
+void freertos_rs_event_group_delete(EventGroupHandle_t event_group)
+{
+	vEventGroupDelete(event_group);
+}
+
+EventBits_t freertos_rs_event_group_set_bits(EventGroupHandle_t event_group, EventBits_t bits)
+{
+	return xEventGroupSetBits(event_group, bits);
+}
+
+EventBits_t freertos_rs_event_group_clear_bits(EventGroupHandle_t event_group, EventBits_t bits)
+{
+	return xEventGroupClearBits(event_group, bits);
+}
+
+EventBits_t freertos_rs_event_group_get_bits(EventGroupHandle_t event_group)
+{
+	return xEventGroupGetBits(event_group);
+}
+
+EventBits_t freertos_rs_event_group_get_bits_isr(EventGroupHandle_t event_group)
+{
+	return xEventGroupGetBitsFromISR(event_group);
+}
+
+EventBits_t freertos_rs_event_group_wait_bits(EventGroupHandle_t event_group, EventBits_t bits, uint8_t clear_on_exit, uint8_t wait_for_all, TickType_t max_wait)
+{
+	return xEventGroupWaitBits(event_group, bits, clear_on_exit ? pdTRUE : pdFALSE, wait_for_all ? pdTRUE : pdFALSE, max_wait);
+}
+
+EventBits_t freertos_rs_event_group_sync(EventGroupHandle_t event_group, EventBits_t set_bits, EventBits_t wait_bits, TickType_t max_wait)
+{
+	return xEventGroupSync(event_group, set_bits, wait_bits, max_wait);
+}
+
 UBaseType_t freertos_rs_queue_receive(QueueHandle_t queue, void *item, TickType_t max_wait)
 {
-	if (xQueueReceive(queue, item, max_wait) != pdTRUE)
+	FREERTOS_RS_FAULT(FREERTOS_RS_FAULT_QUEUE_RECEIVE, queue, 0, 1);
+
+	freertos_rs_queue_waiting(queue, max_wait, pdTRUE);
+	BaseType_t result = xQueueReceive(queue, item, max_wait);
+	freertos_rs_queue_waiting(queue, max_wait, pdFALSE);
+
+	if (result != pdTRUE)
+	{
+		return 1;
+	}
+
+	return 0;
+}
+
+UBaseType_t freertos_rs_queue_peek(QueueHandle_t queue, void *item, TickType_t max_wait)
+{
+	FREERTOS_RS_FAULT(FREERTOS_RS_FAULT_QUEUE_RECEIVE, queue, 0, 1);
+
+	freertos_rs_queue_waiting(queue, max_wait, pdTRUE);
+	BaseType_t result = xQueuePeek(queue, item, max_wait);
+	freertos_rs_queue_waiting(queue, max_wait, pdFALSE);
+
+	if (result != pdTRUE)
 	{
 		return 1;
 	}
@@ -320,11 +996,27 @@ TickType_t freertos_rs_max_wait()
 	return portMAX_DELAY;
 }
 
+UBaseType_t freertos_rs_max_task_name_len()
+{
+	return configMAX_TASK_NAME_LEN;
+}
+
 char *freertos_rs_task_get_name(TaskHandle_t task)
 {
 	return pcTaskGetName(task);
 }
 
+#if (configUSE_TRACE_FACILITY == 1)
+/* The number uxTaskGetSystemState reports. uxTaskGetTaskNumber returns the one set with
+   vTaskSetTaskNumber instead, 0 unless trace code sets it. */
+UBaseType_t freertos_rs_task_get_task_number(TaskHandle_t task)
+{
+	TaskStatus_t status;
+	vTaskGetInfo(task, &status, pdFALSE, eInvalid);
+	return status.xTaskNumber;
+}
+#endif
+
 void freertos_rs_task_suspend(TaskHandle_t xTaskToSuspend)
 {
 	vTaskSuspend(xTaskToSuspend);
@@ -335,6 +1027,41 @@ void freertos_rs_task_resume(TaskHandle_t xTaskToResume)
 	vTaskResume(xTaskToResume);
 }
 
+#if (INCLUDE_xTaskAbortDelay == 1)
+BaseType_t freertos_rs_task_abort_delay(TaskHandle_t xTask)
+{
+	if (xTaskAbortDelay(xTask) != pdPASS)
+	{
+		return 1;
+	}
+	return 0;
+}
+#endif
+
+#if (INCLUDE_uxTaskPriorityGet == 1)
+UBaseType_t freertos_rs_task_priority_get(TaskHandle_t task)
+{
+	return uxTaskPriorityGet(task);
+}
+
+UBaseType_t freertos_rs_task_priority_get_isr(TaskHandle_t task)
+{
+	return uxTaskPriorityGetFromISR(task);
+}
+#endif
+
+#if (INCLUDE_vTaskPrioritySet == 1)
+UBaseType_t freertos_rs_task_priority_set(TaskHandle_t task, UBaseType_t priority)
+{
+	if (priority >= configMAX_PRIORITIES)
+	{
+		priority = configMAX_PRIORITIES - 1;
+	}
+	vTaskPrioritySet(task, priority);
+	return priority;
+}
+#endif
+
 uint32_t freertos_rs_task_notify_take(uint8_t clear_count, TickType_t wait)
 {
 	return ulTaskNotifyTake(clear_count == 1 ? pdTRUE : pdFALSE, wait);
@@ -391,6 +1118,55 @@ BaseType_t freertos_rs_task_notify_isr(void *task, uint32_t value, uint8_t actio
 	return 0;
 }
 
+void freertos_rs_task_notify_give_isr(void *task, BaseType_t *xHigherPriorityTaskWoken)
+{
+	vTaskNotifyGiveFromISR(task, xHigherPriorityTaskWoken);
+}
+
+#ifdef FREERTOS_RS_NOTIFY_INDEXED
+UBaseType_t freertos_rs_get_notification_array_entries()
+{
+	return configTASK_NOTIFICATION_ARRAY_ENTRIES;
+}
+
+uint32_t freertos_rs_task_notify_take_indexed(UBaseType_t index, uint8_t clear_count, TickType_t wait)
+{
+	return ulTaskNotifyTakeIndexed(index, clear_count == 1 ? pdTRUE : pdFALSE, wait);
+}
+
+BaseType_t freertos_rs_task_notify_wait_indexed(UBaseType_t index, uint32_t ulBitsToClearOnEntry, uint32_t ulBitsToClearOnExit, uint32_t *pulNotificationValue, TickType_t xTicksToWait)
+{
+	if (xTaskNotifyWaitIndexed(index, ulBitsToClearOnEntry, ulBitsToClearOnExit, pulNotificationValue, xTicksToWait) == pdTRUE)
+	{
+		return 0;
+	}
+
+	return 1;
+}
+
+BaseType_t freertos_rs_task_notify_indexed(void *task, UBaseType_t index, uint32_t value, uint8_t action)
+{
+	eNotifyAction eAction = freertos_rs_task_notify_action(action);
+
+	if (xTaskNotifyIndexed(task, index, value, eAction) != pdPASS)
+	{
+		return 1;
+	}
+	return 0;
+}
+
+BaseType_t freertos_rs_task_notify_indexed_isr(void *task, UBaseType_t index, uint32_t value, uint8_t action, BaseType_t *xHigherPriorityTaskWoken)
+{
+	eNotifyAction eAction = freertos_rs_task_notify_action(action);
+
+	if (xTaskNotifyIndexedFromISR(task, index, value, eAction, xHigherPriorityTaskWoken) != pdPASS)
+	{
+		return 1;
+	}
+	return 0;
+}
+#endif
+
 #if ((INCLUDE_xTaskGetCurrentTaskHandle == 1) || (configUSE_MUTEXES == 1))
 TaskHandle_t freertos_rs_get_current_task()
 {
@@ -400,32 +1176,25 @@ TaskHandle_t freertos_rs_get_current_task()
 
 #if (configUSE_TIMERS == 1)
 
-TimerHandle_t freertos_rs_timer_create(const char *const name, uint8_t name_len, const TickType_t period,
+TimerHandle_t freertos_rs_timer_create(const char *const name, const TickType_t period,
 									   uint8_t auto_reload, void *const timer_id, TimerCallbackFunction_t callback)
 {
-	char c_name[configMAX_TASK_NAME_LEN] = {0};
-	for (int i = 0; i < name_len; i++)
-	{
-		c_name[i] = name[i];
-
-		if (i == configMAX_TASK_NAME_LEN - 1)
-		{
-			break;
-		}
-	}
-
+	// The kernel keeps a pointer to the name instead of copying it, so the caller owns a
+	// nul terminated buffer that outlives the timer, and checked it fits like a task name.
 	UBaseType_t timer_auto_reload = pdFALSE;
 	if (auto_reload == 1)
 	{
 		timer_auto_reload = pdTRUE;
 	}
 
-	TimerHandle_t handle = xTimerCreate(c_name, period, timer_auto_reload, timer_id, callback);
+	TimerHandle_t handle = xTimerCreate(name, period, timer_auto_reload, timer_id, callback);
 	return handle;
 }
 
 BaseType_t freertos_rs_timer_reset(TimerHandle timer, TickType_t block_time)
 {
+	FREERTOS_RS_FAULT(FREERTOS_RS_FAULT_TIMER_COMMAND, timer, 0, 1);
+
 	if (xTimerReset(timer, block_time) != pdPASS)
 	{
 		return 1;
@@ -444,6 +1213,8 @@ BaseType_t freertos_rs_timer_reset_isr(TimerHandle_t xTimer, BaseType_t *pxHighe
 
 BaseType_t freertos_rs_timer_start(TimerHandle_t timer, TickType_t block_time)
 {
+	FREERTOS_RS_FAULT(FREERTOS_RS_FAULT_TIMER_COMMAND, timer, 0, 1);
+
 	if (xTimerStart(timer, block_time) != pdPASS)
 	{
 		return 1;
@@ -462,6 +1233,8 @@ BaseType_t freertos_rs_timer_start_isr(TimerHandle_t xTimer, BaseType_t *pxHighe
 
 BaseType_t freertos_rs_timer_stop(TimerHandle_t timer, TickType_t block_time)
 {
+	FREERTOS_RS_FAULT(FREERTOS_RS_FAULT_TIMER_COMMAND, timer, 0, 1);
+
 	if (xTimerStop(timer, block_time) != pdPASS)
 	{
 		return 1;
@@ -480,6 +1253,8 @@ BaseType_t freertos_rs_timer_stop_isr(TimerHandle_t xTimer, BaseType_t *pxHigher
 
 BaseType_t freertos_rs_timer_delete(TimerHandle_t timer, TickType_t block_time)
 {
+	FREERTOS_RS_FAULT(FREERTOS_RS_FAULT_TIMER_COMMAND, timer, 0, 1);
+
 	if (xTimerDelete(timer, block_time) != pdPASS)
 	{
 		return 1;
@@ -489,6 +1264,8 @@ BaseType_t freertos_rs_timer_delete(TimerHandle_t timer, TickType_t block_time)
 
 BaseType_t freertos_rs_timer_change_period(TimerHandle_t timer, TickType_t block_time, TickType_t new_period)
 {
+	FREERTOS_RS_FAULT(FREERTOS_RS_FAULT_TIMER_COMMAND, timer, 0, 1);
+
 	if (xTimerChangePeriod(timer, new_period, block_time) != pdPASS)
 	{
 		return 1;
@@ -510,6 +1287,64 @@ void *freertos_rs_timer_get_id(TimerHandle_t timer)
 	return pvTimerGetTimerID(timer);
 }
 
+TickType_t freertos_rs_timer_get_expiry_time(TimerHandle_t timer)
+{
+	return xTimerGetExpiryTime(timer);
+}
+
+uint8_t freertos_rs_timer_is_active(TimerHandle_t timer)
+{
+	return xTimerIsTimerActive(timer) != pdFALSE;
+}
+
+const char *freertos_rs_timer_get_name(TimerHandle_t timer)
+{
+	return pcTimerGetName(timer);
+}
+
+TickType_t freertos_rs_timer_get_period(TimerHandle_t timer)
+{
+	return xTimerGetPeriod(timer);
+}
+
+TaskHandle_t freertos_rs_timer_get_daemon_task_handle()
+{
+	return xTimerGetTimerDaemonTaskHandle();
+}
+
+BaseType_t freertos_rs_pend_function_call(PendedFunction_t function, void *parameter1, uint32_t parameter2, TickType_t block_time)
+{
+#if (INCLUDE_xTimerPendFunctionCall == 1)
+	if (xTimerPendFunctionCall(function, parameter1, parameter2, block_time) != pdPASS)
+	{
+		return 1;
+	}
+	return 0;
+#else
+	return 1;
+#endif
+}
+
+#if (INCLUDE_xTimerPendFunctionCall == 1)
+UBaseType_t freertos_rs_event_group_set_bits_isr(EventGroupHandle_t event_group, EventBits_t bits, BaseType_t *xHigherPriorityTaskWoken)
+{
+	if (xEventGroupSetBitsFromISR(event_group, bits, xHigherPriorityTaskWoken) != pdPASS)
+	{
+		return 1;
+	}
+	return 0;
+}
+
+BaseType_t freertos_rs_pend_function_call_isr(PendedFunction_t function, void *parameter1, uint32_t parameter2, BaseType_t *pxHigherPriorityTaskWoken)
+{
+	if (xTimerPendFunctionCallFromISR(function, parameter1, parameter2, pxHigherPriorityTaskWoken) != pdPASS)
+	{
+		return 1;
+	}
+	return 0;
+}
+#endif
+
 #endif
 
 void freertos_rs_enter_critical()
@@ -520,4 +1355,14 @@ void freertos_rs_enter_critical()
 void freertos_rs_exit_critical()
 {
 	taskEXIT_CRITICAL();
+}
+
+UBaseType_t freertos_rs_enter_critical_from_isr()
+{
+	return taskENTER_CRITICAL_FROM_ISR();
+}
+
+void freertos_rs_exit_critical_from_isr(UBaseType_t saved_interrupt_status)
+{
+	taskEXIT_CRITICAL_FROM_ISR(saved_interrupt_status);
 }
\ No newline at end of file
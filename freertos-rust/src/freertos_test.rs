@@ -0,0 +1,141 @@
+//! A harness for `#[test]` functions running the kernel on the Linux and Windows
+//! simulator ports.
+//!
+//! `run_freertos_test` starts the scheduler, runs the test closure in a task, ends the
+//! scheduler once the closure returned, and panics on the calling thread if it panicked,
+//! so the host test framework reports the test as failed. Tests exercise queues, mutexes
+//! and notifications end to end from the closure, creating the tasks they need.
+//!
+//! A watchdog task above the test task bounds the run: when the test didn't finish by its
+//! timeout, it calls the assert hook and aborts the process, so a deadlocked test fails
+//! CI rather than hanging it. A kernel assert aborts the process as well.
+//!
+//! Panics print their message and location without a backtrace, as capturing one needs
+//! more heap than the simulator has.
+//!
+//! The scheduler runs once per process, so each test goes in its own file under `tests/`,
+//! which cargo builds into its own binary.
+//!
+//! ```rust
+//! # use freertos_rs::*;
+//! use freertos_rs::freertos_test::run_freertos_test;
+//!
+//! // The body of a #[test] function.
+//! run_freertos_test(|os| {
+//!     let queue = os.new_queue(1).unwrap();
+//!     queue.send(7u32, Duration::zero()).unwrap();
+//!     assert_eq!(queue.receive(Duration::zero()), Ok(7));
+//! });
+//! ```
+
+use crate::hooks::FREERTOS_HOOKS;
+use crate::operating_system::*;
+use crate::prelude::v1::*;
+use crate::task::*;
+use crate::units::*;
+// A static, so it has to be const-initialised.
+use core::sync::atomic::{AtomicBool, Ordering};
+use std::eprintln;
+use std::panic::{self, AssertUnwindSafe};
+use std::process;
+
+/// Set by the first run, as the kernel can't be started again.
+static STARTED: AtomicBool = AtomicBool::new(false);
+
+/// How a test runs: the stack of its task and how long it may take.
+#[derive(Debug, Copy, Clone)]
+pub struct FreeRtosTest {
+    stack_depth: usize,
+    timeout: Duration,
+}
+
+impl Default for FreeRtosTest {
+    fn default() -> Self {
+        FreeRtosTest::new()
+    }
+}
+
+impl FreeRtosTest {
+    /// A test task with 1024 words of stack, and a timeout of 5 seconds.
+    pub fn new() -> Self {
+        FreeRtosTest {
+            stack_depth: 1024,
+            timeout: Duration::ms(5000),
+        }
+    }
+
+    /// Set the stack depth of the test task, in words.
+    pub fn stack_depth(mut self, stack_depth: usize) -> Self {
+        self.stack_depth = stack_depth;
+        self
+    }
+
+    /// Set how long the test may run before the watchdog aborts the process.
+    pub fn timeout(mut self, timeout: Duration) -> Self {
+        self.timeout = timeout;
+        self
+    }
+
+    /// Run `test` on the kernel, see the module documentation. Panics with the message of
+    /// `test` when it panicked, and when the scheduler ran before in this process.
+    #[track_caller]
+    pub fn run<F>(self, test: F)
+    where
+        F: FnOnce(FreeRTOS),
+        F: Send + 'static,
+    {
+        panic::set_hook(Box::new(|info| eprintln!("{}", info)));
+        if STARTED.swap(true, Ordering::SeqCst) {
+            panic!("The FreeRTOS scheduler runs once per process, put each test in its own file");
+        }
+
+        let timeout = self.timeout;
+        let os = FreeRTOS {};
+        let max = os.get_max_priorities().min(u8::MAX as usize) as u8;
+        let watchdog = TaskPriority(max.saturating_sub(1));
+        let priority = TaskPriority(max.saturating_sub(2));
+
+        let outcome = FreeRTOS::run_scheduler_until(self.stack_depth, priority, move |os| {
+            let watchdog = os.new_task("watchdog", 256, watchdog, move |_, os| {
+                os.delay(timeout);
+                eprintln!("FreeRTOS test timed out after {} ms", timeout.to_ms());
+                unsafe { (*ptr::addr_of!(FREERTOS_HOOKS)).do_on_assert() };
+                process::abort()
+            });
+            if let Err(error) = watchdog {
+                return Some(format!("Couldn't create the test watchdog: {}", error));
+            }
+
+            panic::catch_unwind(AssertUnwindSafe(|| test(os)))
+                .err()
+                .map(|payload| panic_message(&*payload))
+        });
+
+        match outcome {
+            Ok(None) => {}
+            Ok(Some(message)) => panic!("{}", message),
+            Err(error) => panic!("FreeRTOS test didn't run: {}", error),
+        }
+    }
+}
+
+/// Run `test` on the kernel with the default stack and timeout, see the module
+/// documentation.
+#[track_caller]
+pub fn run_freertos_test<F>(test: F)
+where
+    F: FnOnce(FreeRTOS),
+    F: Send + 'static,
+{
+    FreeRtosTest::new().run(test)
+}
+
+fn panic_message(payload: &(dyn core::any::Any + Send)) -> String {
+    if let Some(message) = payload.downcast_ref::<&str>() {
+        String::from(*message)
+    } else if let Some(message) = payload.downcast_ref::<String>() {
+        message.clone()
+    } else {
+        String::from("FreeRTOS test panicked")
+    }
+}
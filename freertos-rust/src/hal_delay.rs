@@ -0,0 +1,74 @@
+use crate::base::*;
+use crate::operating_system::*;
+use crate::units::*;
+
+/// Implements the `embedded-hal` delay traits with `vTaskDelay`, so drivers that wait
+/// let other tasks run.
+///
+/// Delays can't be shorter than a tick. They are rounded up to whole ticks, plus one
+/// more for the tick already under way, so a driver always waits at least as long as
+/// it asked for: with 1 ms ticks, `delay_us(10)` and `delay_ms(1)` block for one to two
+/// ticks.
+#[derive(Copy, Clone)]
+pub struct FreeRtosDelay {
+    os: FreeRTOS,
+}
+
+impl FreeRtosDelay {
+    pub fn new(os: FreeRTOS) -> FreeRtosDelay {
+        FreeRtosDelay { os }
+    }
+
+    /// The ticks that `delay_ns(ns)` blocks for.
+    pub fn ticks_for_ns(ns: u64) -> FreeRtosTickType {
+        sleep_ticks(ns as u128)
+    }
+
+    fn delay_ns_u64(&self, ns: u64) {
+        let ticks = Self::ticks_for_ns(ns);
+        if ticks > 0 {
+            self.os.delay(Duration::ticks(ticks));
+        }
+    }
+}
+
+#[cfg(feature = "embedded-hal")]
+impl embedded_hal::delay::DelayNs for FreeRtosDelay {
+    fn delay_ns(&mut self, ns: u32) {
+        self.delay_ns_u64(ns as u64);
+    }
+
+    fn delay_us(&mut self, us: u32) {
+        self.delay_ns_u64(us as u64 * 1_000);
+    }
+
+    fn delay_ms(&mut self, ms: u32) {
+        self.delay_ns_u64(ms as u64 * 1_000_000);
+    }
+}
+
+#[cfg(feature = "embedded-hal-02")]
+mod hal_02 {
+    use super::FreeRtosDelay;
+    use embedded_hal_02::blocking::delay::{DelayMs, DelayUs};
+
+    macro_rules! impl_delay_02 {
+        ($($t:ty),*) => {
+            $(
+                impl DelayMs<$t> for FreeRtosDelay {
+                    fn delay_ms(&mut self, ms: $t) {
+                        self.delay_ns_u64(ms as u64 * 1_000_000);
+                    }
+                }
+
+                impl DelayUs<$t> for FreeRtosDelay {
+                    fn delay_us(&mut self, us: $t) {
+                        self.delay_ns_u64(us as u64 * 1_000);
+                    }
+                }
+            )*
+        };
+    }
+
+    impl_delay_02!(u8, u16, u32);
+}
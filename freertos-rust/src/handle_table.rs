@@ -0,0 +1,477 @@
+use crate::base::*;
+use crate::capacities::*;
+use crate::critical::*;
+use crate::isr::*;
+use crate::operating_system::*;
+use crate::prelude::v1::*;
+use crate::queue::*;
+use crate::semaphore::*;
+use crate::shim::*;
+use crate::units::*;
+
+impl !ISRSafe for HandleTable {}
+
+/// A small integer naming an object in a `HandleTable`: a slot index and the
+/// generation of the slot when the object was registered.
+#[repr(transparent)]
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Hash)]
+pub struct ObjectId(u32);
+
+impl ObjectId {
+    pub fn from_raw(raw: u32) -> ObjectId {
+        ObjectId(raw)
+    }
+
+    pub fn as_raw(&self) -> u32 {
+        self.0
+    }
+
+    pub fn index(&self) -> u16 {
+        self.0 as u16
+    }
+
+    pub fn generation(&self) -> u16 {
+        (self.0 >> 16) as u16
+    }
+
+    fn new(index: u16, generation: u16) -> ObjectId {
+        ObjectId(((generation as u32) << 16) | index as u32)
+    }
+}
+
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum HandleError {
+    /// The id never named an object in this table.
+    Invalid,
+    /// The slot was reused for another object since the id was handed out.
+    Stale,
+    /// The object was revoked.
+    Revoked,
+    /// The object isn't of the requested kind.
+    WrongType,
+    /// The queue's items have a different size than requested.
+    ItemSize,
+    /// Every slot of the table is in use.
+    Full,
+}
+
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum ObjectKind {
+    Queue,
+    Semaphore,
+}
+
+/// What a handle table needs to know about a registered object.
+pub struct Registration {
+    kind: ObjectKind,
+    raw: *const CVoid,
+    item_size: usize,
+}
+
+/// Kernel objects that can be registered in a `HandleTable`.
+pub trait Registered {
+    fn registration(&self) -> Registration;
+}
+
+impl<T: Sized + Copy> Registered for Queue<T> {
+    fn registration(&self) -> Registration {
+        Registration {
+            kind: ObjectKind::Queue,
+            raw: self.raw_handle(),
+            item_size: mem::size_of::<T>(),
+        }
+    }
+}
+
+impl Registered for BinarySemaphore {
+    fn registration(&self) -> Registration {
+        Registration {
+            kind: ObjectKind::Semaphore,
+            raw: Semaphore::raw_handle(self),
+            item_size: 0,
+        }
+    }
+}
+
+impl Registered for CountingSemaphore {
+    fn registration(&self) -> Registration {
+        Registration {
+            kind: ObjectKind::Semaphore,
+            raw: Semaphore::raw_handle(self),
+            item_size: 0,
+        }
+    }
+}
+
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+enum SlotState {
+    Free,
+    Live,
+    Revoked,
+}
+
+struct Slot {
+    state: SlotState,
+    generation: u16,
+    kind: ObjectKind,
+    raw: *const CVoid,
+    item_size: usize,
+    /// Resolved references that haven't been dropped yet.
+    users: u32,
+}
+
+unsafe impl Send for Slot {}
+unsafe impl Sync for Slot {}
+
+/// Names kernel objects with small, checked integer ids instead of pointers, e.g. to
+/// pass them through a plugin ABI.
+///
+/// Every use of an id resolves it again, checking that the object is still registered
+/// and is of the expected kind. The table never owns or deletes the objects.
+pub struct HandleTable {
+    os: FreeRTOS,
+    slots: ExclusiveData<Vec<Slot>>,
+}
+
+impl HandleTable {
+    /// Create a table with room for `capacity` objects, at most 65536.
+    pub fn new(os: FreeRTOS, capacity: usize) -> HandleTable {
+        let capacity = capacity.min(u16::MAX as usize + 1);
+        let mut slots = Vec::with_capacity(capacity);
+        for _ in 0..capacity {
+            slots.push(Slot {
+                state: SlotState::Free,
+                generation: 0,
+                kind: ObjectKind::Queue,
+                raw: ptr::null(),
+                item_size: 0,
+                users: 0,
+            });
+        }
+
+        HandleTable {
+            os,
+            slots: ExclusiveData::new(slots),
+        }
+    }
+
+    /// Register an object, returning its id.
+    ///
+    /// # Safety
+    ///
+    /// The object must be revoked before it is dropped.
+    pub unsafe fn register(&self, obj: &impl Registered) -> Result<ObjectId, HandleError> {
+        let registration = obj.registration();
+        let mut slots = self.slots.lock(&self.os).map_err(|_| HandleError::Full)?;
+
+        let (index, slot) = slots
+            .iter_mut()
+            .enumerate()
+            .find(|(_, s)| s.state != SlotState::Live && s.users == 0)
+            .ok_or_else(|| {
+                registry_full(RegistryKind::HandleTable);
+                HandleError::Full
+            })?;
+
+        // Generation 0 is never handed out, so a zeroed id is always invalid.
+        slot.generation = match slot.generation.wrapping_add(1) {
+            0 => 1,
+            generation => generation,
+        };
+        slot.state = SlotState::Live;
+        slot.kind = registration.kind;
+        slot.raw = registration.raw;
+        slot.item_size = registration.item_size;
+
+        Ok(ObjectId::new(index as u16, slot.generation))
+    }
+
+    /// Invalidate an id. Resolving it fails with `Revoked` from now on, until the slot
+    /// is reused, and with `Stale` after that.
+    ///
+    /// Waits for references resolved before the revocation to be dropped, so the object
+    /// can be dropped once this returns.
+    pub fn revoke(&self, id: ObjectId) -> Result<(), HandleError> {
+        {
+            let mut slots = self
+                .slots
+                .lock(&self.os)
+                .map_err(|_| HandleError::Invalid)?;
+            let slot = Self::find(&mut slots, id)?;
+            slot.state = SlotState::Revoked;
+        }
+
+        loop {
+            let users = match self.slots.lock(&self.os) {
+                Ok(slots) => slots[id.index() as usize].users,
+                Err(_) => 0,
+            };
+            if users == 0 {
+                return Ok(());
+            }
+            self.os.delay(Duration::ticks(1));
+        }
+    }
+
+    /// Resolve an id naming a queue of `T`.
+    pub fn resolve_queue<T: Sized + Copy>(
+        &self,
+        id: ObjectId,
+    ) -> Result<QueueRef<'_, T>, HandleError> {
+        let raw = self.acquire(id, ObjectKind::Queue, mem::size_of::<T>())?;
+
+        Ok(QueueRef {
+            table: self,
+            id,
+            queue: raw,
+            item_type: PhantomData,
+        })
+    }
+
+    /// Resolve an id naming a binary or counting semaphore.
+    pub fn resolve_semaphore(&self, id: ObjectId) -> Result<SemaphoreRef<'_>, HandleError> {
+        let raw = self.acquire(id, ObjectKind::Semaphore, 0)?;
+
+        Ok(SemaphoreRef {
+            table: self,
+            id,
+            semaphore: raw,
+        })
+    }
+
+    fn find(slots: &mut [Slot], id: ObjectId) -> Result<&mut Slot, HandleError> {
+        let slot = slots
+            .get_mut(id.index() as usize)
+            .ok_or(HandleError::Invalid)?;
+
+        if id.generation() == 0 || slot.state == SlotState::Free {
+            Err(HandleError::Invalid)
+        } else if slot.generation != id.generation() {
+            Err(HandleError::Stale)
+        } else if slot.state == SlotState::Revoked {
+            Err(HandleError::Revoked)
+        } else {
+            Ok(slot)
+        }
+    }
+
+    fn acquire(
+        &self,
+        id: ObjectId,
+        kind: ObjectKind,
+        item_size: usize,
+    ) -> Result<*const CVoid, HandleError> {
+        let mut slots = self
+            .slots
+            .lock(&self.os)
+            .map_err(|_| HandleError::Invalid)?;
+        let slot = Self::find(&mut slots, id)?;
+
+        if slot.kind != kind {
+            return Err(HandleError::WrongType);
+        }
+        if slot.item_size != item_size {
+            return Err(HandleError::ItemSize);
+        }
+
+        slot.users += 1;
+        Ok(slot.raw)
+    }
+
+    fn release(&self, id: ObjectId) {
+        if let Ok(mut slots) = self.slots.lock(&self.os) {
+            let slot = &mut slots[id.index() as usize];
+            slot.users = slot.users.saturating_sub(1);
+        }
+    }
+}
+
+/// A resolved queue id. The queue can't be revoked away while this exists.
+pub struct QueueRef<'a, T: Sized + Copy> {
+    table: &'a HandleTable,
+    id: ObjectId,
+    queue: FreeRtosQueueHandle,
+    item_type: PhantomData<T>,
+}
+
+impl<'a, T: Sized + Copy> QueueRef<'a, T> {
+    #[inline]
+    pub fn send<D: DurationTicks>(&self, item: T, max_wait: D) -> Result<(), FreeRtosError> {
+        unsafe {
+            queue_send(
+                "QueueRef::send",
+                self.queue,
+                &item as *const _ as FreeRtosVoidPtr,
+                max_wait.to_ticks(),
+            )
+        }
+    }
+
+    #[inline]
+    pub fn receive<D: DurationTicks>(&self, max_wait: D) -> Result<T, FreeRtosError> {
+        unsafe {
+            let mut buff = mem::zeroed::<T>();
+            queue_receive(
+                "QueueRef::receive",
+                self.queue,
+                &mut buff as *mut _ as FreeRtosMutVoidPtr,
+                max_wait.to_ticks(),
+                None,
+            )?;
+            Ok(buff)
+        }
+    }
+}
+
+impl<'a, T: Sized + Copy> Drop for QueueRef<'a, T> {
+    fn drop(&mut self) {
+        self.table.release(self.id);
+    }
+}
+
+/// A resolved semaphore id. The semaphore can't be revoked away while this exists.
+pub struct SemaphoreRef<'a> {
+    table: &'a HandleTable,
+    id: ObjectId,
+    semaphore: FreeRtosSemaphoreHandle,
+}
+
+impl<'a> SemaphoreRef<'a> {
+    #[inline]
+    pub fn take<D: DurationTicks>(&self, max_wait: D) -> Result<(), FreeRtosError> {
+        semaphore_take("SemaphoreRef::take", self.semaphore, max_wait.to_ticks())
+    }
+
+    pub fn give(&self) {
+        unsafe {
+            freertos_rs_give_semaphore(self.semaphore);
+        }
+    }
+}
+
+impl<'a> Drop for SemaphoreRef<'a> {
+    fn drop(&mut self) {
+        self.table.release(self.id);
+    }
+}
+
+#[cfg(feature = "c_api")]
+static mut C_API_TABLE: Option<&'static HandleTable> = None;
+
+/// Set the table the `frrs_*` C functions resolve ids in.
+///
+/// # Safety
+///
+/// Must be called before any plugin uses the C functions, and not while one does.
+#[cfg(feature = "c_api")]
+pub unsafe fn set_c_api_table(table: &'static HandleTable) {
+    C_API_TABLE = Some(table);
+}
+
+/// Return codes of the `frrs_*` C functions.
+#[cfg(feature = "c_api")]
+pub mod c_api_status {
+    pub const OK: i32 = 0;
+    pub const TIMEOUT: i32 = -1;
+    pub const INVALID: i32 = -2;
+    pub const STALE: i32 = -3;
+    pub const REVOKED: i32 = -4;
+    pub const WRONG_TYPE: i32 = -5;
+    pub const ITEM_SIZE: i32 = -6;
+    pub const NO_TABLE: i32 = -7;
+}
+
+#[cfg(feature = "c_api")]
+impl HandleError {
+    fn status(&self) -> i32 {
+        use self::c_api_status::*;
+        match self {
+            HandleError::Invalid | HandleError::Full => INVALID,
+            HandleError::Stale => STALE,
+            HandleError::Revoked => REVOKED,
+            HandleError::WrongType => WRONG_TYPE,
+            HandleError::ItemSize => ITEM_SIZE,
+        }
+    }
+}
+
+#[cfg(feature = "c_api")]
+fn c_api_wait(timeout_ms: u32) -> FreeRtosTickType {
+    if timeout_ms == u32::MAX {
+        Duration::infinite().to_ticks()
+    } else {
+        Duration::ms(timeout_ms).to_ticks()
+    }
+}
+
+/// Run `f` on a resolved object, keeping it from being revoked until `f` returns.
+#[cfg(feature = "c_api")]
+fn c_api_with(
+    id: u32,
+    kind: ObjectKind,
+    item_size: usize,
+    f: impl FnOnce(*const CVoid) -> bool,
+) -> i32 {
+    let table = match unsafe { C_API_TABLE } {
+        Some(table) => table,
+        None => return c_api_status::NO_TABLE,
+    };
+
+    let id = ObjectId::from_raw(id);
+    match table.acquire(id, kind, item_size) {
+        Ok(raw) => {
+            let done = f(raw);
+            table.release(id);
+            if done {
+                c_api_status::OK
+            } else {
+                c_api_status::TIMEOUT
+            }
+        }
+        Err(e) => e.status(),
+    }
+}
+
+/// Send the `len` bytes at `item` to the queue `id`. `len` must be the queue's item size.
+#[cfg(feature = "c_api")]
+#[no_mangle]
+pub unsafe extern "C" fn frrs_queue_send(
+    id: u32,
+    item: *const u8,
+    len: usize,
+    timeout_ms: u32,
+) -> i32 {
+    c_api_with(id, ObjectKind::Queue, len, |queue| {
+        freertos_rs_queue_send(queue, item as FreeRtosVoidPtr, c_api_wait(timeout_ms)) == 0
+    })
+}
+
+/// Receive an item from the queue `id` into the `len` bytes at `item`.
+#[cfg(feature = "c_api")]
+#[no_mangle]
+pub unsafe extern "C" fn frrs_queue_receive(
+    id: u32,
+    item: *mut u8,
+    len: usize,
+    timeout_ms: u32,
+) -> i32 {
+    c_api_with(id, ObjectKind::Queue, len, |queue| {
+        freertos_rs_queue_receive(queue, item as FreeRtosMutVoidPtr, c_api_wait(timeout_ms)) == 0
+    })
+}
+
+#[cfg(feature = "c_api")]
+#[no_mangle]
+pub unsafe extern "C" fn frrs_semaphore_take(id: u32, timeout_ms: u32) -> i32 {
+    c_api_with(id, ObjectKind::Semaphore, 0, |semaphore| {
+        freertos_rs_take_semaphore(semaphore, c_api_wait(timeout_ms)) == 0
+    })
+}
+
+#[cfg(feature = "c_api")]
+#[no_mangle]
+pub unsafe extern "C" fn frrs_semaphore_give(id: u32) -> i32 {
+    c_api_with(id, ObjectKind::Semaphore, 0, |semaphore| {
+        freertos_rs_give_semaphore(semaphore) == 0
+    })
+}
@@ -0,0 +1,220 @@
+use crate::base::*;
+use crate::critical::*;
+use crate::hooks::*;
+use crate::infra::*;
+use crate::operating_system::*;
+use crate::prelude::v1::*;
+use crate::shim::*;
+use crate::task::*;
+use crate::units::*;
+
+/// How many live allocations the shadow table can track. Allocations made while it is
+/// full aren't checked, see `untracked_allocations`.
+pub const HEAP_SHADOW_CAPACITY: usize = 512;
+
+/// Bytes added after every allocation for the canary.
+pub const HEAP_CANARY_SIZE: usize = mem::size_of::<u32>();
+
+const CANARY: u32 = 0xC0DE_FACE;
+
+/// A block whose canary was overwritten.
+#[derive(Debug, Copy, Clone)]
+pub struct HeapCorruption {
+    pub block: *const u8,
+    /// The size requested by the allocation, not counting the canary.
+    pub size: usize,
+    /// The task that allocated the block, or null if it was allocated before the
+    /// scheduler started. The task may have been deleted since.
+    pub owner: FreeRtosTaskHandle,
+    /// The tracked block just below this one, as its start and size.
+    pub previous: Option<(*const u8, usize)>,
+    /// The tracked block just above this one, which is what an overrun would hit next.
+    pub next: Option<(*const u8, usize)>,
+}
+
+#[derive(Copy, Clone)]
+enum Slot {
+    Empty,
+    Removed,
+    Block {
+        start: *mut u8,
+        size: usize,
+        owner: FreeRtosTaskHandle,
+    },
+}
+
+struct ShadowTable {
+    slots: [Slot; HEAP_SHADOW_CAPACITY],
+    cursor: usize,
+    untracked: u32,
+}
+
+// The shadow table can't allocate, so it is a fixed open-addressed hash table of the
+// live blocks, keyed by address.
+static mut SHADOW: ShadowTable = ShadowTable {
+    slots: [Slot::Empty; HEAP_SHADOW_CAPACITY],
+    cursor: 0,
+    untracked: 0,
+};
+
+fn with_shadow<R>(f: impl FnOnce(&mut ShadowTable) -> R) -> R {
+    let _lock = CriticalRegion::enter();
+    unsafe { f(&mut *ptr::addr_of_mut!(SHADOW)) }
+}
+
+fn home(start: *mut u8) -> usize {
+    // Heap blocks are at least 8-byte aligned, so the low bits carry no information.
+    (start as usize >> 3) % HEAP_SHADOW_CAPACITY
+}
+
+fn canary_for(start: *mut u8) -> u32 {
+    CANARY ^ (start as usize as u32)
+}
+
+unsafe fn canary_intact(start: *mut u8, size: usize) -> bool {
+    ptr::read_unaligned(start.add(size) as *const u32) == canary_for(start)
+}
+
+/// Write the canary after a new block and start tracking it. Called by the allocator.
+pub(crate) unsafe fn track(start: *mut u8, size: usize) {
+    ptr::write_unaligned(start.add(size) as *mut u32, canary_for(start));
+    let owner = freertos_rs_get_current_task();
+
+    with_shadow(|shadow| {
+        let first = home(start);
+        for probe in 0..HEAP_SHADOW_CAPACITY {
+            let index = (first + probe) % HEAP_SHADOW_CAPACITY;
+            match shadow.slots[index] {
+                Slot::Empty | Slot::Removed => {
+                    shadow.slots[index] = Slot::Block { start, size, owner };
+                    return;
+                }
+                Slot::Block { .. } => {}
+            }
+        }
+        shadow.untracked = shadow.untracked.saturating_add(1);
+    });
+}
+
+/// Check the canary of a block that is being freed and stop tracking it. Called by
+/// the allocator.
+pub(crate) unsafe fn untrack(start: *mut u8) {
+    let found = with_shadow(|shadow| {
+        let first = home(start);
+        for probe in 0..HEAP_SHADOW_CAPACITY {
+            let index = (first + probe) % HEAP_SHADOW_CAPACITY;
+            match shadow.slots[index] {
+                Slot::Empty => return None,
+                Slot::Block { start: s, size, .. } if s == start => {
+                    let corruption = if canary_intact(start, size) {
+                        None
+                    } else {
+                        Some(describe(shadow, index))
+                    };
+                    shadow.slots[index] = Slot::Removed;
+                    return corruption;
+                }
+                _ => {}
+            }
+        }
+        None
+    });
+
+    if let Some(corruption) = found {
+        report(&corruption);
+    }
+}
+
+fn describe(shadow: &ShadowTable, index: usize) -> HeapCorruption {
+    let (start, size, owner) = match shadow.slots[index] {
+        Slot::Block { start, size, owner } => (start, size, owner),
+        _ => unreachable!(),
+    };
+
+    let mut previous: Option<(*const u8, usize)> = None;
+    let mut next: Option<(*const u8, usize)> = None;
+    for slot in shadow.slots.iter() {
+        if let Slot::Block { start: s, size, .. } = *slot {
+            let s = s as *const u8;
+            if s < start as *const u8 && previous.map_or(true, |(p, _)| s > p) {
+                previous = Some((s, size));
+            }
+            if s > start as *const u8 && next.map_or(true, |(n, _)| s < n) {
+                next = Some((s, size));
+            }
+        }
+    }
+
+    HeapCorruption {
+        block: start,
+        size,
+        owner,
+        previous,
+        next,
+    }
+}
+
+fn report(corruption: &HeapCorruption) {
+    unsafe {
+        (*ptr::addr_of!(FREERTOS_HOOKS)).do_on_heap_corruption(corruption);
+    }
+}
+
+/// Check the canaries of the next `blocks` tracked blocks, continuing where the last
+/// call stopped, so a full pass can be spread over many short calls.
+pub fn verify_heap_step(blocks: usize) -> Result<(), HeapCorruption> {
+    let found = with_shadow(|shadow| {
+        let mut checked = 0;
+        for _ in 0..HEAP_SHADOW_CAPACITY {
+            if checked >= blocks {
+                break;
+            }
+
+            let index = shadow.cursor;
+            shadow.cursor = (shadow.cursor + 1) % HEAP_SHADOW_CAPACITY;
+
+            if let Slot::Block { start, size, .. } = shadow.slots[index] {
+                checked += 1;
+                if unsafe { !canary_intact(start, size) } {
+                    return Some(describe(shadow, index));
+                }
+            }
+        }
+        None
+    });
+
+    match found {
+        Some(corruption) => {
+            report(&corruption);
+            Err(corruption)
+        }
+        None => Ok(()),
+    }
+}
+
+/// Check every tracked block now, for example from the assert hook.
+pub fn verify_heap_now() -> Result<(), HeapCorruption> {
+    verify_heap_step(HEAP_SHADOW_CAPACITY)
+}
+
+/// How many allocations weren't tracked because the shadow table was full.
+pub fn untracked_allocations() -> u32 {
+    with_shadow(|shadow| shadow.untracked)
+}
+
+/// Start a task that checks `blocks_per_pass` blocks every `period`.
+pub fn start_heap_verifier<D: DurationTicks>(
+    os: &FreeRTOS,
+    blocks_per_pass: usize,
+    period: D,
+    priority: TaskPriority,
+) -> Result<InfraTask, FreeRtosError> {
+    let period = Duration::ticks(period.to_ticks());
+
+    InfraTask::spawn(os.clone(), "heap_verify", 256, priority, move |ctx, _os| {
+        while !ctx.should_stop() {
+            let _ = verify_heap_step(blocks_per_pass);
+            ctx.sleep(period);
+        }
+    })
+}
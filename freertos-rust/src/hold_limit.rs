@@ -0,0 +1,124 @@
+use crate::base::*;
+use crate::operating_system::*;
+use crate::prelude::v1::*;
+use crate::shim::*;
+use crate::sync::atomic::{AtomicU32, AtomicUsize, Ordering};
+use crate::task::*;
+use crate::ticks::*;
+use crate::timers::*;
+use crate::units::*;
+
+/// What a mutex with a maximum hold time does to a holder that exceeds it, see
+/// `Mutex::with_max_hold`.
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum HoldPolicy {
+    /// Only call the violation callback.
+    NotifyOnly,
+    /// Wake the holder with `TaskRemoteHandle::abort_delay` from the delay or kernel
+    /// wait it is stuck in, then call the violation callback.
+    AbortDelayAndNotify,
+}
+
+/// Reported when a task held a mutex longer than its maximum hold time.
+#[derive(Debug)]
+pub struct HoldViolation {
+    /// The task holding the mutex.
+    pub holder: TaskRemoteHandle,
+    pub max_hold: Duration,
+    pub policy: HoldPolicy,
+    /// Whether the holder was blocked and woken up. Always false with `NotifyOnly`.
+    pub woken: bool,
+}
+
+/// The part of a hold limit shared with its timer.
+struct HoldState {
+    max_hold: FreeRtosTickType,
+    policy: HoldPolicy,
+    /// The task holding the mutex, zero while it is free.
+    holder: AtomicUsize,
+    acquired_at: AtomicU32,
+    on_violation: Box<dyn Fn(&HoldViolation) + Send + Sync>,
+}
+
+impl HoldState {
+    /// Called by the timer daemon when a hold may have run out.
+    fn check(&self) {
+        let holder = self.holder.load(Ordering::Relaxed);
+        if holder == 0 {
+            return;
+        }
+        // A stop that didn't make it into the timer queue leaves the timer running into
+        // a later hold, which isn't over its limit yet.
+        let now = unsafe { freertos_rs_xTaskGetTickCount() };
+        if tick_elapsed(now, self.acquired_at.load(Ordering::Relaxed)) < self.max_hold {
+            return;
+        }
+
+        let holder = unsafe { TaskRemoteHandle::from_raw(holder as FreeRtosTaskHandle) };
+        let woken = match self.policy {
+            HoldPolicy::NotifyOnly => false,
+            HoldPolicy::AbortDelayAndNotify => holder.abort_delay().is_ok(),
+        };
+        (self.on_violation)(&HoldViolation {
+            holder,
+            max_hold: Duration::ticks(self.max_hold),
+            policy: self.policy,
+            woken,
+        });
+    }
+}
+
+/// A one-shot kernel timer started when the mutex is locked and stopped when it is
+/// unlocked, so a hold costs two timer commands and the check only runs for holds that
+/// last too long.
+pub(crate) struct HoldLimit {
+    state: Arc<HoldState>,
+    timer: Timer,
+}
+
+impl HoldLimit {
+    pub(crate) fn new<F>(
+        max_hold: FreeRtosTickType,
+        policy: HoldPolicy,
+        on_violation: F,
+    ) -> Result<HoldLimit, FreeRtosError>
+    where
+        F: Fn(&HoldViolation) + Send + Sync + 'static,
+    {
+        let state = Arc::new(HoldState {
+            max_hold: max_hold.max(1),
+            policy,
+            holder: AtomicUsize::new(0),
+            acquired_at: AtomicU32::new(0),
+            on_violation: Box::new(on_violation),
+        });
+
+        let timer = {
+            let state = state.clone();
+            TimerBuilder::new(FreeRTOS {}, Duration::ticks(max_hold.max(1)))
+                .set_name("max hold")
+                .one_shot()
+                .create(move |_| state.check())?
+        };
+
+        Ok(HoldLimit { state, timer })
+    }
+
+    /// Called by the task that just locked the mutex.
+    pub(crate) fn acquired(&self) {
+        let now = unsafe { freertos_rs_xTaskGetTickCount() };
+        self.state.acquired_at.store(now, Ordering::Relaxed);
+        self.state.holder.store(
+            unsafe { freertos_rs_get_current_task() } as usize,
+            Ordering::Relaxed,
+        );
+        // Without room in the timer queue, this hold isn't checked.
+        let _ = self.timer.reset(Duration::zero());
+    }
+
+    /// Called by the holder right before it unlocks the mutex.
+    pub(crate) fn released(&self) {
+        self.state.holder.store(0, Ordering::Relaxed);
+        let _ = self.timer.stop(Duration::zero());
+    }
+}
@@ -1,11 +1,86 @@
+use crate::allocator::low_water_idle_hook;
 use crate::base::*;
+use crate::critical::*;
+use crate::isr::*;
+use crate::operating_system::*;
+use crate::prelude::v1::Box;
+#[cfg(feature = "fmt")]
 use crate::prelude::v1::String;
+use crate::quiescent::quiescent_idle_hook;
 use crate::utils::*;
+use core::mem;
+use core::ptr;
+
+#[cfg(feature = "footprint_diag")]
+use crate::footprint::*;
+#[cfg(feature = "heap_integrity")]
+use crate::heap_integrity::*;
+#[cfg(feature = "rt_checks")]
+use crate::no_block::*;
+#[cfg(feature = "owner_checks")]
+use crate::owner::*;
+#[cfg(feature = "test_support")]
+use crate::test_support::*;
 
 type Callback = fn();
 
+/// Called with the requested size when the heap can't satisfy an allocation. Must not
+/// allocate.
+type AllocFailureCallback = fn(usize);
+
+/// Called from the tick interrupt.
+type TickCallback = Box<dyn Fn(&mut InterruptContext)>;
+
+/// Called with the name of the task whose stack overflowed.
+type StackOverflowCallback = fn(&str);
+
+/// Called once by the timer daemon task when it starts.
+type DaemonStartupCallback = Box<dyn FnOnce(FreeRTOS) + Send>;
+
+/// Called with the error when the scheduler couldn't be started.
+type StartupFailureCallback = fn(FreeRtosError);
+
+/// Called with the task or timer name when a closure is larger than the threshold.
+#[cfg(feature = "footprint_diag")]
+type ClosureFootprintCallback = fn(&str, ClosureFootprint);
+
+/// Called when a task blocks inside a `NoBlockSection`.
+#[cfg(feature = "rt_checks")]
+type BlockViolationCallback = fn(&BlockViolation);
+
+/// Called when a heap block's canary was found overwritten. Must not allocate.
+#[cfg(feature = "heap_integrity")]
+type HeapCorruptionCallback = fn(&HeapCorruption);
+
+/// Called with the requested size when something allocates before `allocator::init`.
+/// Must not allocate.
+#[cfg(feature = "alloc_checks")]
+type PrematureAllocCallback = fn(usize);
+
+/// Called when a task-bound helper is used from another task.
+#[cfg(feature = "owner_checks")]
+type OwnerViolationCallback = fn(&OwnerViolation);
+
 pub struct FreeRtosHooks {
     on_assert: Callback,
+    on_alloc_failure: Option<AllocFailureCallback>,
+    on_idle: Option<Callback>,
+    on_tick: Option<TickCallback>,
+    on_stack_overflow: Option<StackOverflowCallback>,
+    on_daemon_startup: Option<DaemonStartupCallback>,
+    on_startup_failure: Option<StartupFailureCallback>,
+    #[cfg(feature = "footprint_diag")]
+    on_large_closure: ClosureFootprintCallback,
+    #[cfg(feature = "footprint_diag")]
+    closure_size_threshold: usize,
+    #[cfg(feature = "rt_checks")]
+    on_block_violation: Option<BlockViolationCallback>,
+    #[cfg(feature = "heap_integrity")]
+    on_heap_corruption: Option<HeapCorruptionCallback>,
+    #[cfg(feature = "owner_checks")]
+    on_owner_violation: Option<OwnerViolationCallback>,
+    #[cfg(feature = "alloc_checks")]
+    on_premature_alloc: Option<PrematureAllocCallback>,
 }
 
 impl FreeRtosHooks {
@@ -13,22 +88,214 @@ impl FreeRtosHooks {
         self.on_assert = c;
     }
 
-    fn do_on_assert(&self) {
+    pub(crate) fn do_on_assert(&self) {
         (self.on_assert)();
     }
+
+    /// Set the callback for allocations that failed because the heap is exhausted. It
+    /// runs before `FreeRtosAllocator` returns null.
+    pub fn set_on_alloc_failure(&mut self, c: AllocFailureCallback) {
+        self.on_alloc_failure = Some(c);
+    }
+
+    pub(crate) fn do_on_alloc_failure(&self, size: usize) {
+        if let Some(c) = self.on_alloc_failure {
+            c(size);
+        }
+    }
+
+    /// Set the callback for the idle hook, run by the idle task on every pass of its loop
+    /// with `configUSE_IDLE_HOOK` set to 1. It must not block.
+    pub fn set_on_idle(&mut self, c: Callback) {
+        self.on_idle = Some(c);
+    }
+
+    fn do_on_idle(&self) {
+        if let Some(c) = self.on_idle {
+            c();
+        }
+    }
+
+    /// Set the callback for the tick hook, run from the tick interrupt with
+    /// `configUSE_TICK_HOOK` set to 1. Tasks woken through the context are switched to
+    /// when the tick interrupt returns.
+    pub fn set_on_tick<F>(&mut self, c: F)
+    where
+        F: Fn(&mut InterruptContext) + ISRSafe + 'static,
+    {
+        let c: TickCallback = Box::new(c);
+        let previous = {
+            let _region = CriticalRegion::enter();
+            self.on_tick.replace(c)
+        };
+        drop(previous);
+    }
+
+    fn do_on_tick(&self) {
+        if let Some(c) = &self.on_tick {
+            let mut context = InterruptContext::new(unsafe { KernelIsr::claim() });
+            c(&mut context);
+            // The tick interrupt yields by itself when the callback woke a task, a yield
+            // from the context would come too early.
+            mem::forget(context);
+        }
+    }
+
+    /// Set the callback for stack overflows found by the kernel, with
+    /// `configCHECK_FOR_STACK_OVERFLOW` set to 1 or 2. It gets the name of the task and
+    /// should not return, e.g. log the name and reset. If it returns, or without one,
+    /// the stack overflow panics.
+    pub fn set_on_stack_overflow(&mut self, c: StackOverflowCallback) {
+        self.on_stack_overflow = Some(c);
+    }
+
+    fn do_on_stack_overflow(&self, task_name: &str) {
+        if let Some(c) = self.on_stack_overflow {
+            c(task_name);
+        }
+    }
+
+    /// Set the callback for the daemon task startup hook, with
+    /// `configUSE_DAEMON_TASK_STARTUP_HOOK` set to 1. The timer daemon task runs it once
+    /// when the scheduler starts, before any timer command, so it can create the queues
+    /// and other objects tasks share. Tasks with a priority above `configTIMER_TASK_PRIORITY`
+    /// run before it.
+    ///
+    /// Has to be set before `FreeRTOS::start_scheduler`, a later one is never run.
+    pub fn set_on_daemon_startup<F>(&mut self, c: F)
+    where
+        F: FnOnce(FreeRTOS) + Send + 'static,
+    {
+        self.on_daemon_startup = Some(Box::new(c));
+    }
+
+    fn do_on_daemon_startup(&mut self) {
+        // Taken, so the closure is freed once it has run.
+        if let Some(c) = self.on_daemon_startup.take() {
+            c(unsafe { FreeRTOS::assume_init() });
+        }
+    }
+
+    /// Set the callback for `FreeRTOS::try_start_scheduler` failing, for targets without a
+    /// console. It gets the error of the setup closure, or `OutOfMemory` when the kernel
+    /// couldn't create the idle or timer task. Without one, the assert hook is called.
+    pub fn set_on_startup_failure(&mut self, c: StartupFailureCallback) {
+        self.on_startup_failure = Some(c);
+    }
+
+    pub(crate) fn do_on_startup_failure(&self, error: FreeRtosError) {
+        match self.on_startup_failure {
+            Some(c) => c(error),
+            None => self.do_on_assert(),
+        }
+    }
+
+    /// Set the callback for task and timer closures larger than the closure size threshold.
+    #[cfg(feature = "footprint_diag")]
+    pub fn set_on_large_closure(&mut self, c: ClosureFootprintCallback) {
+        self.on_large_closure = c;
+    }
+
+    /// Set the closure size, in bytes, above which `on_large_closure` is called.
+    /// Defaults to 256.
+    #[cfg(feature = "footprint_diag")]
+    pub fn set_closure_size_threshold(&mut self, bytes: usize) {
+        self.closure_size_threshold = bytes;
+    }
+
+    #[cfg(feature = "footprint_diag")]
+    pub(crate) fn do_on_closure_footprint(&self, name: &str, footprint: ClosureFootprint) {
+        if footprint.size > self.closure_size_threshold {
+            (self.on_large_closure)(name, footprint);
+        }
+    }
+
+    /// Set the callback for blocking calls inside a `NoBlockSection`. Without one, the
+    /// assert hook is called.
+    #[cfg(feature = "rt_checks")]
+    pub fn set_on_block_violation(&mut self, c: BlockViolationCallback) {
+        self.on_block_violation = Some(c);
+    }
+
+    #[cfg(feature = "rt_checks")]
+    pub(crate) fn do_on_block_violation(&self, violation: &BlockViolation) {
+        match self.on_block_violation {
+            Some(c) => c(violation),
+            None => self.do_on_assert(),
+        }
+    }
+
+    /// Set the callback for corrupted heap blocks. Without one, the assert hook is called.
+    #[cfg(feature = "heap_integrity")]
+    pub fn set_on_heap_corruption(&mut self, c: HeapCorruptionCallback) {
+        self.on_heap_corruption = Some(c);
+    }
+
+    #[cfg(feature = "heap_integrity")]
+    pub(crate) fn do_on_heap_corruption(&self, corruption: &HeapCorruption) {
+        match self.on_heap_corruption {
+            Some(c) => c(corruption),
+            None => self.do_on_assert(),
+        }
+    }
+
+    /// Set the callback for task-bound helpers used from another task. Without one, the
+    /// assert hook is called.
+    #[cfg(feature = "owner_checks")]
+    pub fn set_on_owner_violation(&mut self, c: OwnerViolationCallback) {
+        self.on_owner_violation = Some(c);
+    }
+
+    #[cfg(feature = "owner_checks")]
+    pub(crate) fn do_on_owner_violation(&self, violation: &OwnerViolation) {
+        match self.on_owner_violation {
+            Some(c) => c(violation),
+            None => self.do_on_assert(),
+        }
+    }
+
+    /// Set the callback for allocations before `allocator::init`. Without one, the
+    /// assert hook is called. The allocation panics when it returns.
+    #[cfg(feature = "alloc_checks")]
+    pub fn set_on_premature_alloc(&mut self, c: PrematureAllocCallback) {
+        self.on_premature_alloc = Some(c);
+    }
+
+    #[cfg(feature = "alloc_checks")]
+    pub(crate) fn do_on_premature_alloc(&self, size: usize) {
+        match self.on_premature_alloc {
+            Some(c) => c(size),
+            None => self.do_on_assert(),
+        }
+    }
 }
 
 // TODO: It's unsafe to use, we should build some safe wrapper around
-pub static mut FREERTOS_HOOKS: FreeRtosHooks = FreeRtosHooks { on_assert: || {} };
+pub static mut FREERTOS_HOOKS: FreeRtosHooks = FreeRtosHooks {
+    on_assert: || {},
+    on_alloc_failure: None,
+    on_idle: None,
+    on_tick: None,
+    on_stack_overflow: None,
+    on_daemon_startup: None,
+    on_startup_failure: None,
+    #[cfg(feature = "footprint_diag")]
+    on_large_closure: |_, _| {},
+    #[cfg(feature = "footprint_diag")]
+    closure_size_threshold: 256,
+    #[cfg(feature = "rt_checks")]
+    on_block_violation: None,
+    #[cfg(feature = "heap_integrity")]
+    on_heap_corruption: None,
+    #[cfg(feature = "owner_checks")]
+    on_owner_violation: None,
+    #[cfg(feature = "alloc_checks")]
+    on_premature_alloc: None,
+};
 
 #[allow(unused_doc_comments)]
 #[no_mangle]
 pub extern "C" fn vAssertCalled(file_name_ptr: FreeRtosCharPtr, line: FreeRtosUBaseType) {
-    let file_name: String;
-    unsafe {
-        file_name = str_from_c_string(file_name_ptr).unwrap();
-    }
-
     unsafe {
         FREERTOS_HOOKS.do_on_assert();
     }
@@ -36,6 +303,73 @@ pub extern "C" fn vAssertCalled(file_name_ptr: FreeRtosCharPtr, line: FreeRtosUB
     // we can't print without std yet.
     // TODO: make the macro work for debug UART? Or use Panic here?
     // println!("ASSERT: {} {}", line, file_name);
-    panic!("FreeRTOS ASSERT: {}:{}", file_name, line);
+    #[cfg(feature = "fmt")]
+    {
+        let file_name: String;
+        unsafe {
+            file_name = str_from_c_string(file_name_ptr).unwrap();
+        }
+
+        panic!("FreeRTOS ASSERT: {}:{}", file_name, line);
+    }
+
+    // Keep the formatting machinery out of the binary.
+    #[cfg(not(feature = "fmt"))]
+    {
+        let _ = (file_name_ptr, line);
+        panic!("FreeRTOS ASSERT");
+    }
     //loop {}
 }
+
+/// Called by `vApplicationIdleHook` in shim.c.
+#[no_mangle]
+pub extern "C" fn freertos_rs_idle_hook() {
+    quiescent_idle_hook();
+    low_water_idle_hook();
+    unsafe {
+        (*ptr::addr_of!(FREERTOS_HOOKS)).do_on_idle();
+    }
+    #[cfg(feature = "test_support")]
+    virtual_time_idle_hook();
+}
+
+/// Called by `vApplicationTickHook` in shim.c.
+#[no_mangle]
+pub extern "C" fn freertos_rs_tick_hook() {
+    unsafe {
+        (*ptr::addr_of!(FREERTOS_HOOKS)).do_on_tick();
+    }
+    #[cfg(feature = "test_support")]
+    virtual_time_tick_hook();
+}
+
+/// Called by `vApplicationStackOverflowHook` in shim.c.
+///
+/// # Safety
+///
+/// `task_name_ptr` must point to the NUL terminated name of the task, as the kernel
+/// passes it.
+#[no_mangle]
+pub unsafe extern "C" fn freertos_rs_stack_overflow_hook(task_name_ptr: FreeRtosCharPtr) {
+    // The stack is gone, so nothing is allocated here.
+    let task_name = bytes_from_c_string(task_name_ptr);
+    let task_name = core::str::from_utf8(task_name).unwrap_or("?");
+    (*ptr::addr_of!(FREERTOS_HOOKS)).do_on_stack_overflow(task_name);
+
+    #[cfg(feature = "fmt")]
+    panic!("FreeRTOS stack overflow in task {}", task_name);
+
+    #[cfg(not(feature = "fmt"))]
+    panic!("FreeRTOS stack overflow");
+}
+
+/// Called by `vApplicationDaemonTaskStartupHook` in shim.c.
+#[no_mangle]
+pub extern "C" fn freertos_rs_daemon_startup_hook() {
+    #[cfg(feature = "test_support")]
+    virtual_time_start_hook();
+    unsafe {
+        (*ptr::addr_of_mut!(FREERTOS_HOOKS)).do_on_daemon_startup();
+    }
+}
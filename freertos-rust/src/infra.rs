@@ -0,0 +1,224 @@
+use crate::base::*;
+use crate::capacities::*;
+use crate::isr::*;
+use crate::operating_system::*;
+use crate::prelude::v1::*;
+use crate::shim::*;
+use crate::sync::atomic::{AtomicBool, Ordering};
+use crate::task::*;
+use crate::ticks::*;
+use crate::units::*;
+use core::cell::Cell;
+
+impl !ISRSafe for InfraTask {}
+
+/// The notification bit reserved for asking an infrastructure task to stop.
+pub const INFRA_STOP_BIT: u32 = 1 << 31;
+
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum InfraStopOutcome {
+    /// The task returned from its function.
+    Stopped,
+    /// The task didn't return in time and was deleted. Whatever it owned is leaked.
+    ForceDeleted,
+}
+
+#[derive(Debug, Copy, Clone)]
+pub struct InfraStopReport {
+    pub name: TaskName,
+    pub outcome: InfraStopOutcome,
+}
+
+struct InfraState {
+    finished: AtomicBool,
+}
+
+pub(crate) struct InfraEntry {
+    task: FreeRtosTaskHandle,
+    name: TaskName,
+    state: Arc<InfraState>,
+}
+
+/// What an infrastructure task's function gets to wait with while watching for a stop request.
+pub struct InfraContext<'a> {
+    this: &'a TaskSelfHandle,
+    stopping: Cell<bool>,
+}
+
+impl<'a> InfraContext<'a> {
+    pub fn task(&self) -> &TaskSelfHandle {
+        self.this
+    }
+
+    /// Has the task been asked to stop? The function should return soon after this is true.
+    pub fn should_stop(&self) -> bool {
+        self.stopping.get()
+    }
+
+    /// Wait for a notification like `TaskSelfHandle::wait_for_notification`, but with
+    /// `INFRA_STOP_BIT` left out of the returned value. A stop request ends the wait.
+    pub fn wait_for_notification<D: DurationTicks>(
+        &self,
+        clear_bits_enter: u32,
+        clear_bits_exit: u32,
+        wait_for: D,
+    ) -> Result<u32, FreeRtosError> {
+        let value = self.this.wait_for_notification(
+            clear_bits_enter & !INFRA_STOP_BIT,
+            clear_bits_exit,
+            wait_for,
+        )?;
+
+        if value & INFRA_STOP_BIT != 0 {
+            self.stopping.set(true);
+        }
+        Ok(value & !INFRA_STOP_BIT)
+    }
+
+    /// Sleep for `delay`, or until the task is asked to stop. Other notification bits are
+    /// left pending.
+    pub fn sleep<D: DurationTicks>(&self, delay: D) {
+        let _ = self.wait_for_notification(0, INFRA_STOP_BIT, delay);
+    }
+}
+
+/// A task run by the crate itself, like the census or persistence tasks.
+///
+/// Infrastructure tasks return from their function when asked to stop and are then
+/// deleted. They are registered when spawned, so `shutdown_infrastructure` can stop
+/// every one of them.
+pub struct InfraTask {
+    task: TaskRemoteHandle,
+    state: Arc<InfraState>,
+}
+
+impl TaskHandle for InfraTask {
+    fn raw_handle(&self) -> FreeRtosTaskHandle {
+        self.task.raw_handle()
+    }
+}
+
+impl InfraTask {
+    pub fn spawn<F>(
+        os: FreeRTOS,
+        name: &str,
+        stack_size: usize,
+        priority: TaskPriority,
+        func: F,
+    ) -> Result<InfraTask, FreeRtosError>
+    where
+        F: FnOnce(&InfraContext, FreeRTOS),
+        F: Send + 'static,
+    {
+        if with_infra_registry(|tasks| tasks.is_full()) {
+            return Err(registry_full(RegistryKind::Infrastructure));
+        }
+
+        let state = Arc::new(InfraState {
+            finished: AtomicBool::new(false),
+        });
+
+        let task = {
+            let state = state.clone();
+
+            os.new_task(name, stack_size, priority, move |this, os| {
+                func(
+                    &InfraContext {
+                        this,
+                        stopping: Cell::new(false),
+                    },
+                    os,
+                );
+
+                state.finished.store(true, Ordering::Release);
+                drop(state);
+                unsafe { this.delete() }
+            })?
+        };
+
+        let entry = InfraEntry {
+            task: task.raw_handle(),
+            name: TaskName::from_bytes(name.as_bytes()),
+            state: state.clone(),
+        };
+        if let Err(entry) = with_infra_registry(|tasks| tasks.push(entry)) {
+            // Another task took the last slot after the check above.
+            stop_entry(&os, &entry, 0);
+            return Err(registry_full(RegistryKind::Infrastructure));
+        }
+
+        Ok(InfraTask { task, state })
+    }
+
+    pub fn handle(&self) -> &TaskRemoteHandle {
+        &self.task
+    }
+
+    pub fn notify(&self, notification: TaskNotification) {
+        if !self.state.finished.load(Ordering::Acquire) {
+            self.task.notify(notification);
+        }
+    }
+
+    /// Ask the task to stop and wait up to `timeout` for it to return, deleting it
+    /// after that.
+    pub fn stop<D: DurationTicks>(&self, os: &FreeRTOS, timeout: D) -> InfraStopOutcome {
+        let raw = self.raw_handle();
+        let entry = with_infra_registry(|tasks| {
+            let index = tasks.iter().position(|e| e.task == raw)?;
+            Some(tasks.remove(index))
+        });
+
+        match entry {
+            Some(entry) => stop_entry(os, &entry, timeout.to_ticks()),
+            None => InfraStopOutcome::Stopped,
+        }
+    }
+}
+
+fn stop_entry(os: &FreeRTOS, entry: &InfraEntry, timeout: FreeRtosTickType) -> InfraStopOutcome {
+    if entry.state.finished.load(Ordering::Acquire) {
+        return InfraStopOutcome::Stopped;
+    }
+
+    unsafe {
+        TaskRemoteHandle::from_raw(entry.task).notify(TaskNotification::SetBits(INFRA_STOP_BIT));
+    }
+
+    let start = os.get_tick_count();
+    loop {
+        if entry.state.finished.load(Ordering::Acquire) {
+            return InfraStopOutcome::Stopped;
+        }
+        if tick_elapsed(os.get_tick_count(), start) >= timeout {
+            unsafe {
+                freertos_rs_delete_task(entry.task);
+            }
+            return InfraStopOutcome::ForceDeleted;
+        }
+        os.delay(Duration::ticks(1));
+    }
+}
+
+/// Stop every running infrastructure task, most recently spawned first, giving each
+/// up to `timeout` to return before it is deleted.
+pub fn shutdown_infrastructure<D: DurationTicks>(
+    os: &FreeRTOS,
+    timeout: D,
+) -> Vec<InfraStopReport> {
+    let mut reports = Vec::new();
+
+    while let Some(entry) = with_infra_registry(|tasks| tasks.pop()) {
+        reports.push(InfraStopReport {
+            name: entry.name,
+            outcome: stop_entry(os, &entry, timeout.to_ticks()),
+        });
+    }
+
+    reports
+}
+
+/// How many infrastructure tasks are registered and not stopped yet.
+pub fn infrastructure_task_count() -> usize {
+    with_infra_registry(|tasks| tasks.len())
+}
@@ -0,0 +1,462 @@
+use crate::base::*;
+use crate::isr::*;
+use crate::mutex::*;
+use crate::operating_system::*;
+use crate::prelude::v1::*;
+use crate::priority_band::*;
+use crate::semaphore::*;
+use crate::task::*;
+use crate::ticks::*;
+use crate::units::*;
+
+impl !ISRSafe for InitGraph {}
+
+/// Why an init node didn't succeed.
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum InitError {
+    /// The node's runner failed with an application specific code.
+    Failed(u32),
+    /// The node was still running when its timeout expired. Its worker task is left to
+    /// finish on its own.
+    Timeout,
+    /// A dependency didn't succeed, or the graph stopped after another node failed.
+    Skipped,
+}
+
+/// A problem with the declaration of an `InitGraph`.
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum InitGraphError {
+    /// More nodes than the capacity given to `InitGraph::new`.
+    Full,
+    DuplicateNode(&'static str),
+    /// The node would close a dependency cycle.
+    Cycle(&'static str),
+    /// A dependency that was never declared as a node.
+    UnknownDependency(&'static str),
+    /// The workers or the state they share couldn't be created.
+    Spawn(FreeRtosError),
+}
+
+/// What the graph does once a node failed.
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum InitPolicy {
+    /// Start no more nodes. Nodes already running are waited for.
+    FailFast,
+    /// Skip only the nodes that depend on the failed one.
+    ContinueWherePossible,
+}
+
+/// What a runner gets while it initialises its node.
+pub struct InitCtx {
+    name: &'static str,
+    os: FreeRTOS,
+}
+
+impl InitCtx {
+    pub fn name(&self) -> &'static str {
+        self.name
+    }
+
+    pub fn os(&self) -> FreeRTOS {
+        self.os
+    }
+}
+
+type InitRunner = Box<dyn FnOnce(&InitCtx) -> Result<(), InitError> + Send>;
+
+struct InitNode {
+    name: &'static str,
+    deps: Vec<&'static str>,
+    runner: InitRunner,
+}
+
+/// A startup dependency graph, declared once and run by a few worker tasks in
+/// dependency order, with independent nodes running in parallel:
+///
+///     let mut graph = InitGraph::new(8);
+///     graph
+///         .node("clocks", &[], |_| init_clocks())?
+///         .node("spi", &["clocks"], |_| init_spi())?
+///         .node("uart", &["clocks"], |_| init_uart())?
+///         .node("flash", &["spi"], |_| init_flash())?;
+///     let report = graph.execute(&os, 2, Duration::ms(500))?;
+///
+/// Dependencies may be declared after the nodes that use them.
+pub struct InitGraph {
+    nodes: Vec<InitNode>,
+    capacity: usize,
+    policy: InitPolicy,
+    worker_stack_size: usize,
+    worker_priority: Option<TaskPriority>,
+}
+
+impl InitGraph {
+    pub fn new(capacity: usize) -> InitGraph {
+        InitGraph {
+            nodes: Vec::with_capacity(capacity),
+            capacity,
+            policy: InitPolicy::ContinueWherePossible,
+            worker_stack_size: 1024,
+            worker_priority: None,
+        }
+    }
+
+    /// Declare a node that runs once all of `deps` succeeded.
+    pub fn node<F>(
+        &mut self,
+        name: &'static str,
+        deps: &[&'static str],
+        runner: F,
+    ) -> Result<&mut Self, InitGraphError>
+    where
+        F: FnOnce(&InitCtx) -> Result<(), InitError>,
+        F: Send + 'static,
+    {
+        if self.nodes.len() >= self.capacity {
+            return Err(InitGraphError::Full);
+        }
+        if self.nodes.iter().any(|n| n.name == name) {
+            return Err(InitGraphError::DuplicateNode(name));
+        }
+        if deps.iter().any(|d| *d == name || self.depends_on(d, name)) {
+            return Err(InitGraphError::Cycle(name));
+        }
+
+        self.nodes.push(InitNode {
+            name,
+            deps: deps.to_vec(),
+            runner: Box::new(runner),
+        });
+        Ok(self)
+    }
+
+    /// Does `from` depend on `target`, directly or through other declared nodes?
+    fn depends_on(&self, from: &str, target: &str) -> bool {
+        let mut stack = vec![from];
+        let mut visited = Vec::new();
+
+        while let Some(name) = stack.pop() {
+            if name == target {
+                return true;
+            }
+            if visited.contains(&name) {
+                continue;
+            }
+            visited.push(name);
+
+            if let Some(node) = self.nodes.iter().find(|n| n.name == name) {
+                stack.extend(node.deps.iter().copied());
+            }
+        }
+
+        false
+    }
+
+    /// Set what happens after a node fails. Defaults to `ContinueWherePossible`.
+    pub fn policy(&mut self, policy: InitPolicy) -> &mut Self {
+        self.policy = policy;
+        self
+    }
+
+    /// Stack size of the worker tasks, in words. Defaults to 1024.
+    pub fn worker_stack_size(&mut self, stack_size: usize) -> &mut Self {
+        self.worker_stack_size = stack_size;
+        self
+    }
+
+    /// Priority of the worker tasks. Defaults to `PriorityBand::Normal`.
+    pub fn worker_priority(&mut self, priority: impl Into<TaskPriority>) -> &mut Self {
+        self.worker_priority = Some(priority.into());
+        self
+    }
+
+    /// Run the graph on up to `parallelism` worker tasks and wait for every node to
+    /// finish, fail, time out or be skipped. Call this from a task.
+    pub fn execute<D: DurationTicks>(
+        self,
+        os: &FreeRTOS,
+        parallelism: usize,
+        per_node_timeout: D,
+    ) -> Result<InitReport, InitGraphError> {
+        let mut deps = Vec::with_capacity(self.nodes.len());
+        for node in self.nodes.iter() {
+            let mut indices = Vec::with_capacity(node.deps.len());
+            for dep in node.deps.iter() {
+                match self.nodes.iter().position(|n| n.name == *dep) {
+                    Some(index) => indices.push(index),
+                    None => return Err(InitGraphError::UnknownDependency(dep)),
+                }
+            }
+            deps.push(indices);
+        }
+
+        let mut reports = Vec::with_capacity(self.nodes.len());
+        let mut runners = Vec::with_capacity(self.nodes.len());
+        for node in self.nodes.into_iter() {
+            reports.push(InitNodeReport {
+                name: node.name,
+                deps: node.deps,
+                duration: Duration::zero(),
+                status: InitStatus::Pending,
+            });
+            runners.push(Some(SendRunner(node.runner)));
+        }
+
+        let parallelism = parallelism.max(1).min(reports.len().max(1));
+        let state = Arc::new(GraphState {
+            nodes: Mutex::new(
+                *os,
+                GraphNodes {
+                    reports,
+                    deps,
+                    runners,
+                    started: Vec::new(),
+                    policy: self.policy,
+                    stopped: false,
+                },
+            )
+            .map_err(InitGraphError::Spawn)?,
+            progress: CountingSemaphore::new(*os, parallelism as u32 + 1, 0)
+                .map_err(InitGraphError::Spawn)?,
+            wakeups: parallelism as u32 + 1,
+        });
+
+        let priority = self
+            .worker_priority
+            .unwrap_or_else(|| TaskPriority::from_band(PriorityBand::Normal));
+        let mut workers = 0;
+        for _ in 0..parallelism {
+            let state = state.clone();
+            let spawned = os.new_task("init", self.worker_stack_size, priority, move |this, os| {
+                state.work(os);
+                drop(state);
+                unsafe { this.delete() }
+            });
+            if spawned.is_ok() {
+                workers += 1;
+            }
+        }
+        if workers == 0 {
+            return Err(InitGraphError::Spawn(FreeRtosError::OutOfMemory));
+        }
+
+        let timeout = per_node_timeout.to_ticks();
+        loop {
+            let _ = state.progress.take(Duration::ms(10));
+
+            let mut nodes = state.lock();
+            let now = os.get_tick_count();
+            for index in 0..nodes.reports.len() {
+                if let InitStatus::Running(start) = nodes.reports[index].status {
+                    if tick_elapsed(now, start) >= timeout {
+                        nodes.finish(index, now, Err(InitError::Timeout));
+                    }
+                }
+            }
+            if nodes.all_resolved() {
+                return Ok(InitReport {
+                    nodes: mem::take(&mut nodes.reports),
+                });
+            }
+        }
+    }
+}
+
+/// Runners are only ever taken out under the graph's mutex.
+struct SendRunner(InitRunner);
+
+unsafe impl Sync for SendRunner {}
+
+struct GraphNodes {
+    reports: Vec<InitNodeReport>,
+    deps: Vec<Vec<usize>>,
+    runners: Vec<Option<SendRunner>>,
+    started: Vec<usize>,
+    policy: InitPolicy,
+    stopped: bool,
+}
+
+impl GraphNodes {
+    /// Take the first pending node whose dependencies all succeeded.
+    fn next_ready(&mut self, now: FreeRtosTickType) -> Option<(usize, SendRunner)> {
+        if self.stopped {
+            return None;
+        }
+
+        for index in 0..self.reports.len() {
+            if self.reports[index].status != InitStatus::Pending {
+                continue;
+            }
+
+            let ready = self.deps[index]
+                .iter()
+                .all(|d| self.reports[*d].status == InitStatus::Done(Ok(())));
+            if ready {
+                self.reports[index].status = InitStatus::Running(now);
+                self.started.push(index);
+                return self.runners[index].take().map(|r| (index, r));
+            }
+        }
+
+        None
+    }
+
+    fn finish(&mut self, index: usize, now: FreeRtosTickType, result: Result<(), InitError>) {
+        let start = match self.reports[index].status {
+            InitStatus::Running(start) => start,
+            _ => return,
+        };
+        self.reports[index].duration = Duration::ticks(tick_elapsed(now, start));
+        self.reports[index].status = InitStatus::Done(result);
+
+        if result.is_err() && self.policy == InitPolicy::FailFast {
+            self.stopped = true;
+        }
+        // Mark everything that can't run any more.
+        while self.next_skippable() {}
+    }
+
+    fn next_skippable(&mut self) -> bool {
+        for index in 0..self.reports.len() {
+            if self.reports[index].status != InitStatus::Pending {
+                continue;
+            }
+            let blocked = self.stopped
+                || self.deps[index]
+                    .iter()
+                    .any(|d| matches!(self.reports[*d].status, InitStatus::Done(Err(_))));
+            if blocked {
+                self.reports[index].status = InitStatus::Done(Err(InitError::Skipped));
+                return true;
+            }
+        }
+        false
+    }
+
+    fn all_resolved(&self) -> bool {
+        self.reports
+            .iter()
+            .all(|r| matches!(r.status, InitStatus::Done(_)))
+    }
+}
+
+struct GraphState {
+    nodes: Mutex<GraphNodes>,
+    progress: CountingSemaphore,
+    /// Every worker plus the caller of `execute`.
+    wakeups: u32,
+}
+
+impl GraphState {
+    fn lock(&self) -> MutexGuard<'_, GraphNodes, MutexNormal> {
+        loop {
+            if let Ok(guard) = self.nodes.lock(Duration::infinite()) {
+                return guard;
+            }
+        }
+    }
+
+    fn work(&self, os: FreeRTOS) {
+        loop {
+            let next = {
+                let mut nodes = self.lock();
+                if nodes.all_resolved() || nodes.stopped {
+                    return;
+                }
+                nodes.next_ready(os.get_tick_count())
+            };
+
+            match next {
+                Some((index, runner)) => {
+                    let name = self.lock().reports[index].name;
+                    let result = (runner.0)(&InitCtx { name, os });
+                    self.lock().finish(index, os.get_tick_count(), result);
+
+                    // Wake the other workers and the caller of `execute`.
+                    for _ in 0..self.wakeups {
+                        if !self.progress.try_give() {
+                            break;
+                        }
+                    }
+                }
+                None => {
+                    let _ = self.progress.take(Duration::ms(10));
+                }
+            }
+        }
+    }
+}
+
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+enum InitStatus {
+    Pending,
+    /// Running since the given tick.
+    Running(FreeRtosTickType),
+    Done(Result<(), InitError>),
+}
+
+/// How one node of an `InitGraph` went.
+#[derive(Debug, Clone)]
+pub struct InitNodeReport {
+    pub name: &'static str,
+    pub deps: Vec<&'static str>,
+    /// How long the runner took. Zero for skipped nodes.
+    pub duration: Duration,
+    status: InitStatus,
+}
+
+impl InitNodeReport {
+    pub fn result(&self) -> Result<(), InitError> {
+        match self.status {
+            InitStatus::Done(result) => result,
+            _ => Err(InitError::Skipped),
+        }
+    }
+}
+
+/// The outcome of `InitGraph::execute`, one entry per node in declaration order.
+#[derive(Debug, Clone)]
+pub struct InitReport {
+    pub nodes: Vec<InitNodeReport>,
+}
+
+impl InitReport {
+    pub fn is_success(&self) -> bool {
+        self.nodes.iter().all(|n| n.result().is_ok())
+    }
+
+    pub fn node(&self, name: &str) -> Option<&InitNodeReport> {
+        self.nodes.iter().find(|n| n.name == name)
+    }
+}
+
+/// Writes the report as a table, one line per node.
+#[cfg(feature = "fmt")]
+impl fmt::Display for InitReport {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        writeln!(f, "{:<16} {:<24} {:>8} status", "node", "deps", "time")?;
+        for node in self.nodes.iter() {
+            write!(f, "{:<16} ", node.name)?;
+
+            let mut width = 0;
+            for (i, dep) in node.deps.iter().enumerate() {
+                if i > 0 {
+                    write!(f, ",")?;
+                    width += 1;
+                }
+                write!(f, "{}", dep)?;
+                width += dep.len();
+            }
+            write!(f, "{:width$} ", "", width = 24usize.saturating_sub(width))?;
+
+            write!(f, "{:>8} ", node.duration)?;
+            match node.result() {
+                Ok(()) => writeln!(f, "ok")?,
+                Err(InitError::Failed(code)) => writeln!(f, "failed ({})", code)?,
+                Err(InitError::Timeout) => writeln!(f, "timeout")?,
+                Err(InitError::Skipped) => writeln!(f, "skipped")?,
+            }
+        }
+        Ok(())
+    }
+}
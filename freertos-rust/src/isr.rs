@@ -1,10 +1,202 @@
+//! Interrupts come in two classes on ports with `configMAX_SYSCALL_INTERRUPT_PRIORITY`,
+//! like Cortex-M:
+//!
+//! - `KernelIsr`: interrupts at or below the syscall priority. The kernel masks them in its
+//!   critical sections, so they may call the `FromISR` API.
+//! - `ZeroLatencyIsr`: interrupts above it. The kernel never masks them, so any kernel call
+//!   from one can corrupt its state. They may only touch lock-free data.
+//!
+//! An `InterruptContext` carries the class of the interrupt it was created in. Handles
+//! backed by `FromISR` calls take an `InterruptContext<KernelIsr>`, the default, so they
+//! don't compile in a zero-latency interrupt. Types that are `AnyIsrSafe` don't call the
+//! kernel and take a context of either class.
+//!
+//! | Type                                         | `KernelIsr` | `ZeroLatencyIsr` |
+//! |----------------------------------------------|-------------|------------------|
+//! | `ISRSafe` types, `KernelIsrSafe`             | yes         | no               |
+//! | `*ISRHandle`s, `ISR*Semaphore`s              | yes         | no               |
+//! | `InterruptContext::critical_section`         | yes         | no               |
+//! | `defer_to_daemon_isr`                        | yes         | no               |
+//! | `AnyIsrSafe` types, like the `core` atomics  | yes         | yes              |
+//!
+//! On ports without the mask every interrupt is a `KernelIsr`.
+//!
+//! A zero-latency interrupt can't give a semaphore:
+//!
+//! ```compile_fail,E0308
+//! # use freertos_rust::*;
+//! fn zero_latency_irq(semaphore: &ISRBinarySemaphore) {
+//!     let mut context = InterruptContext::new(ZeroLatencyIsr::new());
+//!     semaphore.give(&mut context);
+//! }
+//! ```
+//!
+//! nor defer work to the timer daemon:
+//!
+//! ```compile_fail,E0308
+//! # use freertos_rust::*;
+//! fn zero_latency_irq(call: DeferredCall) {
+//!     let mut context = InterruptContext::new(ZeroLatencyIsr::new());
+//!     let _ = defer_to_daemon_isr(&mut context, call);
+//! }
+//! ```
+//!
+//! nor enter a critical section or read the tick count:
+//!
+//! ```compile_fail,E0599
+//! # use freertos_rust::*;
+//! fn zero_latency_irq() {
+//!     let context = InterruptContext::new(ZeroLatencyIsr::new());
+//!     context.critical_section(|| ());
+//! }
+//! ```
+//!
+//! ```compile_fail,E0599
+//! # use freertos_rust::*;
+//! fn zero_latency_irq() -> FreeRtosTickType {
+//!     let context = InterruptContext::new(ZeroLatencyIsr::new());
+//!     context.tick_count()
+//! }
+//! ```
+
 use crate::base::*;
+use crate::critical::*;
 use crate::shim::*;
 use alloc::prelude::v1::Box;
 use core::marker::PhantomData;
+use core::mem;
+use core::ops::Deref;
+use core::sync::atomic::*;
 
 pub auto trait ISRSafe {}
 
+/// Types that can be used from interrupts at or below `configMAX_SYSCALL_INTERRUPT_PRIORITY`,
+/// which is everything `ISRSafe`.
+///
+/// # Safety
+///
+/// The type's interrupt methods may call the `FromISR` API and raise the interrupt mask
+/// to `configMAX_SYSCALL_INTERRUPT_PRIORITY`, and nothing else of the kernel. That is only
+/// sound in interrupts the kernel masks in its critical sections, so implement it for a
+/// type that isn't `ISRSafe` only if its methods stay within this.
+pub unsafe trait KernelIsrSafe {}
+
+unsafe impl<T: ?Sized + ISRSafe> KernelIsrSafe for T {}
+
+/// Types that can also be used from zero-latency interrupts, because they never call the
+/// kernel. Their interrupt methods take an `InterruptContext<C>` of any class.
+///
+/// # Safety
+///
+/// Zero-latency interrupts run above `configMAX_SYSCALL_INTERRUPT_PRIORITY`, so the
+/// kernel's critical sections don't mask them. Nothing reachable from the type's methods
+/// may call the kernel, the `FromISR` API included, or raise the interrupt mask, or it
+/// may run in the middle of a critical section and corrupt the kernel's state.
+pub unsafe trait AnyIsrSafe: KernelIsrSafe {}
+
+unsafe impl AnyIsrSafe for AtomicBool {}
+unsafe impl AnyIsrSafe for AtomicU8 {}
+unsafe impl AnyIsrSafe for AtomicU16 {}
+unsafe impl AnyIsrSafe for AtomicU32 {}
+unsafe impl AnyIsrSafe for AtomicUsize {}
+unsafe impl AnyIsrSafe for AtomicI8 {}
+unsafe impl AnyIsrSafe for AtomicI16 {}
+unsafe impl AnyIsrSafe for AtomicI32 {}
+unsafe impl AnyIsrSafe for AtomicIsize {}
+
+mod private {
+    pub trait Sealed {}
+}
+
+/// The class of an interrupt, by its priority. See the table at the top of this module.
+pub trait IsrClass: private::Sealed + Sized + 'static {
+    /// Whether interrupts of this class may call the `FromISR` API.
+    const KERNEL_CALLS: bool;
+
+    /// # Safety
+    ///
+    /// Must only be called in an interrupt of this class. A `KernelIsr` claimed above
+    /// `configMAX_SYSCALL_INTERRUPT_PRIORITY` lets the interrupt call the kernel while
+    /// it is in a critical section.
+    unsafe fn claim() -> Self;
+}
+
+/// Marks an interrupt at or below `configMAX_SYSCALL_INTERRUPT_PRIORITY`.
+pub struct KernelIsr {
+    _private: (),
+}
+
+/// Marks a zero-latency interrupt, above `configMAX_SYSCALL_INTERRUPT_PRIORITY`.
+///
+/// The callback of a controller of this class can't give a semaphore:
+///
+/// ```compile_fail,E0308
+/// # use freertos_rust::*;
+/// struct Encoder;
+/// impl InterruptController for Encoder {
+///     type Class = ZeroLatencyIsr;
+///     # fn enabled_flag() -> &'static core::sync::atomic::AtomicBool {
+///     #     static ENABLED: core::sync::atomic::AtomicBool =
+///     #         core::sync::atomic::AtomicBool::new(false);
+///     #     &ENABLED
+///     # }
+///     # unsafe fn enable(_: Box<dyn Fn(&mut InterruptContext<ZeroLatencyIsr>)>) {}
+///     # unsafe fn disable() {}
+/// }
+///
+/// # fn scope(os: FreeRTOS) {
+/// let semaphore = os.new_binary_semaphore().unwrap();
+/// InterruptScope::<Encoder, _>::scope(
+///     BorrowedISRHandle::new(&semaphore),
+///     |context, semaphore: &BorrowedISRHandle<ISRBinarySemaphore>| {
+///         semaphore.give(context);
+///     },
+///     |_scope| (),
+/// )
+/// .unwrap();
+/// # }
+/// ```
+///
+/// nor send to a queue:
+///
+/// ```compile_fail,E0308
+/// # use freertos_rust::*;
+/// fn zero_latency_irq(queue: &QueueISRHandle<u32>) {
+///     let mut context = InterruptContext::new(ZeroLatencyIsr::new());
+///     let _ = queue.send(&mut context, 7);
+/// }
+/// ```
+#[derive(Default)]
+pub struct ZeroLatencyIsr {
+    _private: (),
+}
+
+impl private::Sealed for KernelIsr {}
+impl private::Sealed for ZeroLatencyIsr {}
+
+impl IsrClass for KernelIsr {
+    const KERNEL_CALLS: bool = true;
+
+    unsafe fn claim() -> Self {
+        KernelIsr { _private: () }
+    }
+}
+
+impl IsrClass for ZeroLatencyIsr {
+    const KERNEL_CALLS: bool = false;
+
+    unsafe fn claim() -> Self {
+        ZeroLatencyIsr { _private: () }
+    }
+}
+
+impl ZeroLatencyIsr {
+    /// Claiming the zero-latency class is always sound, it only allows less.
+    pub fn new() -> Self {
+        ZeroLatencyIsr { _private: () }
+    }
+}
+
 /// A struct that implements this can have an ISR safe handle created.
 pub trait ISRSafeHandle<SafeForm: ISRSafe> {
     /// Create an ISR safe handle to this object. Calling functions on it from within a task will not cause
@@ -22,30 +214,96 @@ pub trait ISRSafeHandle<SafeForm: ISRSafe> {
     }
 }
 
+/// An ISR safe handle that borrows the object it was created from, so the compiler
+/// checks that the object outlives it. Made without `unsafe`, unlike
+/// `new_isr_safe_handle`, for the resources of an `InterruptScope`.
+pub struct BorrowedISRHandle<'a, S: ISRSafe> {
+    handle: S,
+    _object: PhantomData<&'a ()>,
+}
+
+impl<'a, S: ISRSafe> BorrowedISRHandle<'a, S> {
+    pub fn new<T: ISRSafeHandle<S> + ?Sized>(object: &'a T) -> BorrowedISRHandle<'a, S> {
+        BorrowedISRHandle {
+            handle: unsafe { object.new_isr_safe_handle() },
+            _object: PhantomData,
+        }
+    }
+}
+
+impl<'a, S: ISRSafe> Deref for BorrowedISRHandle<'a, S> {
+    type Target = S;
+
+    fn deref(&self) -> &S {
+        &self.handle
+    }
+}
+
 /// Keep track of whether we need to yield the execution to a different
 /// task at the end of the interrupt.
 ///
-/// Should be dropped as the last thing inside an interrupt.
-pub struct InterruptContext {
+/// Should be dropped as the last thing inside an interrupt, which yields if a task was
+/// woken. `into_woken` leaves the yield to the caller instead.
+pub struct InterruptContext<C: IsrClass = KernelIsr> {
     x_higher_priority_task_woken: FreeRtosBaseType,
+    _class: C,
 }
 
-impl InterruptContext {
-    /// Instantiate a new context.
-    pub fn new() -> InterruptContext {
+impl<C: IsrClass> InterruptContext<C> {
+    /// Instantiate a new context, for an interrupt of the class `class` was claimed for.
+    pub fn new(class: C) -> InterruptContext<C> {
         InterruptContext {
             x_higher_priority_task_woken: 0,
+            _class: class,
         }
     }
 
-    pub unsafe fn get_task_field_mut(&self) -> FreeRtosBaseTypeMutPtr {
-        self.x_higher_priority_task_woken as *mut _
+    /// Whether a `FromISR` call made with this context woke a task of a higher priority
+    /// than the interrupted one. Always false for a `ZeroLatencyIsr`.
+    pub fn higher_priority_task_woken(&self) -> bool {
+        C::KERNEL_CALLS && self.x_higher_priority_task_woken != 0
+    }
+
+    /// End the context without yielding, and return whether it should have. For ISRs
+    /// shared with C code, which pass the result to their own `portYIELD_FROM_ISR`.
+    pub fn into_woken(self) -> bool {
+        let woken = self.higher_priority_task_woken();
+        mem::forget(self);
+        woken
+    }
+
+    /// End the context now, yielding if a task was woken, instead of when it is dropped.
+    pub fn yield_now(self) {
+        if self.into_woken() {
+            unsafe {
+                freertos_rs_isr_yield();
+            }
+        }
     }
 }
 
-impl Drop for InterruptContext {
+impl InterruptContext<KernelIsr> {
+    /// Run `f` in a critical section, with the interrupt mask it had restored afterwards.
+    pub fn critical_section<R, F: FnOnce() -> R>(&self, f: F) -> R {
+        let _region = CriticalRegionIsr::enter(self);
+        f()
+    }
+
+    /// The tick count, read with `xTaskGetTickCountFromISR`, to timestamp events with.
+    pub fn tick_count(&self) -> FreeRtosTickType {
+        unsafe { freertos_rs_xTaskGetTickCountFromISR() }
+    }
+
+    /// The `pxHigherPriorityTaskWoken` argument of `FromISR` calls, which the kernel sets
+    /// when the call woke a task that should run when the interrupt returns.
+    pub unsafe fn get_task_field_mut(&mut self) -> FreeRtosBaseTypeMutPtr {
+        &mut self.x_higher_priority_task_woken as *mut _
+    }
+}
+
+impl<C: IsrClass> Drop for InterruptContext<C> {
     fn drop(&mut self) {
-        if self.x_higher_priority_task_woken == 1 {
+        if self.higher_priority_task_woken() {
             unsafe {
                 freertos_rs_isr_yield();
             }
@@ -53,41 +311,172 @@ impl Drop for InterruptContext {
     }
 }
 
-pub struct InterruptScope<C: InterruptController> {
-    _marker: PhantomData<C>,
+/// What an `InterruptScope` owns and lends to its callback: `BorrowedISRHandle`s,
+/// references and tuples of resources, or a struct of them that implements this trait.
+pub trait ISRResources: ISRSafe + Sync {}
+
+impl ISRResources for () {}
+impl<'a, S: ISRSafe + Sync> ISRResources for BorrowedISRHandle<'a, S> {}
+impl<T: ISRResources + ?Sized> ISRResources for &T {}
+impl<A: ISRResources> ISRResources for (A,) {}
+impl<A: ISRResources, B: ISRResources> ISRResources for (A, B) {}
+impl<A: ISRResources, B: ISRResources, C: ISRResources> ISRResources for (A, B, C) {}
+impl<A: ISRResources, B: ISRResources, C: ISRResources, D: ISRResources> ISRResources
+    for (A, B, C, D)
+{
 }
 
-impl<C: InterruptController> InterruptScope<C> {
-    pub fn open<F>(callback: F) -> InterruptScope<C>
+/// An interrupt that is enabled while the scope lives, with the resources its callback
+/// uses.
+///
+/// The scope owns the resources and passes them to every call of the callback. A scope
+/// made with `open` holds `'static` resources, so leaking it leaves nothing dangling.
+/// Resources that borrow, like the semaphore of a `BorrowedISRHandle`, need `scope`,
+/// which disables the interrupt before it returns. The borrowed objects can't be dropped
+/// before that, which the compiler checks:
+///
+/// ```compile_fail,E0505
+/// # use freertos_rust::*;
+/// # struct Timer;
+/// # impl InterruptController for Timer {
+/// #     type Class = KernelIsr;
+/// #     fn enabled_flag() -> &'static core::sync::atomic::AtomicBool {
+/// #         static ENABLED: core::sync::atomic::AtomicBool =
+/// #             core::sync::atomic::AtomicBool::new(false);
+/// #         &ENABLED
+/// #     }
+/// #     unsafe fn enable(_: Box<dyn Fn(&mut InterruptContext<KernelIsr>)>) {}
+/// #     unsafe fn disable() {}
+/// # }
+/// # fn scope(os: FreeRTOS) {
+/// let semaphore = os.new_binary_semaphore().unwrap();
+/// InterruptScope::<Timer, _>::scope(
+///     BorrowedISRHandle::new(&semaphore),
+///     |context, semaphore: &BorrowedISRHandle<ISRBinarySemaphore>| {
+///         semaphore.give(context);
+///     },
+///     |_scope| {
+///         drop(semaphore); // The interrupt would give a deleted semaphore.
+///     },
+/// )
+/// .unwrap();
+/// # }
+/// ```
+///
+/// One scope of a controller is open at a time, which the crate keeps track of with the
+/// controller's `enabled_flag`.
+pub struct InterruptScope<C: InterruptController, R: ISRResources = ()> {
+    resources: *mut R,
+    _marker: PhantomData<(C, R)>,
+}
+
+type Callback<'a, C> = Box<dyn Fn(&mut InterruptContext<C>) + 'a>;
+
+impl<C: InterruptController, R: ISRResources + 'static> InterruptScope<C, R> {
+    /// Enable the interrupt of `C`, calling `callback` with `resources` until the scope is
+    /// dropped. Fails with `InterruptAlreadyEnabled` while another scope of `C` is open.
+    pub fn open<F>(resources: R, callback: F) -> Result<InterruptScope<C, R>, FreeRtosError>
     where
-        F: Fn(&InterruptContext) + ISRSafe + 'static,
+        F: Fn(&mut InterruptContext<C::Class>, &R) + ISRSafe + 'static,
     {
+        // The resources live as long as the callback, even if the scope is leaked.
+        unsafe { InterruptScope::open_unchecked(resources, callback) }
+    }
+}
+
+impl<C: InterruptController, R: ISRResources> InterruptScope<C, R> {
+    /// Enable the interrupt of `C`, calling `callback` with `resources` while `f` runs,
+    /// and disable it before returning what `f` returned. The resources may borrow from
+    /// the caller, as the interrupt can't outlive this call. Fails with
+    /// `InterruptAlreadyEnabled` while another scope of `C` is open.
+    pub fn scope<F, T>(
+        resources: R,
+        callback: F,
+        f: impl FnOnce(&InterruptScope<C, R>) -> T,
+    ) -> Result<T, FreeRtosError>
+    where
+        F: Fn(&mut InterruptContext<C::Class>, &R) + ISRSafe + 'static,
+    {
+        // `f` only gets a reference, so the scope is dropped here, or while unwinding.
+        let scope = unsafe { InterruptScope::open_unchecked(resources, callback)? };
+        Ok(f(&scope))
+    }
+
+    /// Safety:
+    /// The scope must be dropped before anything the resources borrow.
+    unsafe fn open_unchecked<F>(
+        resources: R,
+        callback: F,
+    ) -> Result<InterruptScope<C, R>, FreeRtosError>
+    where
+        F: Fn(&mut InterruptContext<C::Class>, &R) + ISRSafe + 'static,
+    {
+        if C::enabled_flag()
+            .compare_exchange(false, true, Ordering::AcqRel, Ordering::Acquire)
+            .is_err()
+        {
+            return Err(FreeRtosError::InterruptAlreadyEnabled);
+        }
+
+        let resources = Box::into_raw(Box::new(resources));
         let scope = InterruptScope {
+            resources,
             _marker: PhantomData,
         };
 
-        // It is now safe to enable the ISR.
-        unsafe {
-            C::enable(Box::new(callback));
-        }
+        let shared = resources as *const R;
+        let callback: Callback<'_, C::Class> = Box::new(move |context| callback(context, &*shared));
+
+        // It is now safe to enable the ISR. The callback only lives as long as the
+        // resources, which the scope drops after it disabled the ISR.
+        C::enable(mem::transmute::<
+            Callback<'_, C::Class>,
+            Callback<'static, C::Class>,
+        >(callback));
+
+        Ok(scope)
+    }
 
-        scope
+    /// Whether a scope of `C` is open, `InterruptScope::<C>::is_open()`.
+    pub fn is_open() -> bool {
+        C::enabled_flag().load(Ordering::Acquire)
+    }
+
+    /// The resources the callback is called with, to use them from tasks as well.
+    pub fn resources(&self) -> &R {
+        unsafe { &*self.resources }
     }
 }
 
-impl<C: InterruptController> Drop for InterruptScope<C> {
+impl<C: InterruptController, R: ISRResources> Drop for InterruptScope<C, R> {
     fn drop(&mut self) {
         // We must disable the ISR or risk invalid memory access.
         unsafe {
             C::disable();
+            drop(Box::from_raw(self.resources));
         }
+        C::enabled_flag().store(false, Ordering::Release);
     }
 }
 
 pub trait InterruptController: Sized + ISRSafe {
-    /// Enable the ISR.
-    /// This function must panic if the ISR happens to already be enabled.
-    unsafe fn enable(callback: Box<dyn Fn(&InterruptContext)>);
+    /// The class of the interrupt, by the priority it is configured with. Controllers for a
+    /// zero-latency interrupt must declare `ZeroLatencyIsr`, so callbacks can't call the kernel.
+    type Class: IsrClass;
+
+    /// Whether the ISR is enabled, set and cleared by `InterruptScope`. Each controller
+    /// returns a static of its own:
+    ///
+    ///     # use core::sync::atomic::AtomicBool;
+    ///     fn enabled_flag() -> &'static AtomicBool {
+    ///         static ENABLED: AtomicBool = AtomicBool::new(false);
+    ///         &ENABLED
+    ///     }
+    fn enabled_flag() -> &'static AtomicBool;
+
+    /// Enable the ISR. The callback is to be called with a context created from
+    /// `Self::Class::claim()`. `InterruptScope` only calls it while the ISR is disabled.
+    unsafe fn enable(callback: Box<dyn Fn(&mut InterruptContext<Self::Class>)>);
 
     /// Disables the interrupt. It won't be called anymore.
     /// The interrupt controller that was passed to the enable function will immediately become invalid after
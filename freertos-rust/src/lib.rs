@@ -63,24 +63,92 @@
 
 #[macro_use]
 extern crate alloc;
+#[cfg(feature = "hosted_tests")]
+extern crate std;
 
 mod hooks;
 mod prelude;
 mod shim;
 
-mod allocator;
+pub mod allocator;
 mod base;
+#[cfg(feature = "c_hooks")]
+pub mod c_hooks;
+mod capacities;
+mod ceiling_mutex;
+mod census;
+mod channel;
+#[cfg(feature = "cmsis-compat")]
+pub mod cmsis;
+mod condvar;
+mod config_distributor;
 mod critical;
+mod defer;
 mod delays;
+mod emergency;
+mod event_counter;
+mod event_group;
+#[cfg(feature = "fault_inject")]
+pub mod fault_inject;
+#[cfg(feature = "footprint_diag")]
+mod footprint;
+mod fr_arc;
+mod framing;
+#[cfg(feature = "hosted_tests")]
+pub mod freertos_test;
+#[cfg(any(feature = "embedded-hal", feature = "embedded-hal-02"))]
+mod hal_delay;
+mod handle_table;
+#[cfg(feature = "heap_integrity")]
+mod heap_integrity;
+mod hold_limit;
+mod infra;
+mod init_graph;
 mod isr;
+mod log_sink;
 mod mutex;
+mod no_block;
+mod notification;
+mod once_cell;
 mod operating_system;
+mod owner;
+mod persistence;
+mod priority_band;
+mod priority_plan;
+mod progress;
+mod pump;
 mod queue;
+mod queue_registry;
+mod queue_set;
+mod quiescent;
+#[cfg(feature = "fmt")]
+mod render;
+mod rendezvous;
+mod replenishing_semaphore;
+#[cfg(feature = "mpu")]
+mod restricted_task;
 mod semaphore;
+mod service_budget;
+#[cfg(feature = "static_allocation")]
+mod static_task;
+mod stats;
+mod status_cell;
+mod stream_buffer;
+mod sync;
 mod task;
+#[cfg(feature = "test_support")]
+pub mod test_support;
+mod ticks;
+mod time_window;
+mod timer_service;
 mod timers;
+mod trace_sink;
+mod transaction;
+mod tunable;
 mod units;
 mod utils;
+mod watchdog;
+mod wip;
 
 // TODO get that working again once we get the core utils where we want them.
 // pub mod patterns;
@@ -90,18 +158,75 @@ pub use crate::base::*;
 pub use crate::shim::*;
 // ----------
 
-pub use crate::allocator::*;
+pub use crate::allocator::{FreeRtosAllocator, HeapRegion, HeapStats};
 pub use crate::base::FreeRtosError;
+pub use crate::capacities::*;
+pub use crate::ceiling_mutex::*;
+pub use crate::census::*;
+pub use crate::channel::*;
+pub use crate::condvar::*;
+pub use crate::config_distributor::*;
 pub use crate::critical::*;
+pub use crate::defer::{defer_to_daemon_isr, DeferredCall};
 pub use crate::delays::*;
+pub use crate::emergency::*;
+pub use crate::event_counter::*;
+pub use crate::event_group::*;
+#[cfg(feature = "footprint_diag")]
+pub use crate::footprint::*;
+pub use crate::fr_arc::*;
+pub use crate::framing::*;
+#[cfg(any(feature = "embedded-hal", feature = "embedded-hal-02"))]
+pub use crate::hal_delay::*;
+pub use crate::handle_table::*;
+#[cfg(feature = "heap_integrity")]
+pub use crate::heap_integrity::*;
+pub use crate::hold_limit::*;
 pub use crate::hooks::*;
+pub use crate::infra::*;
+pub use crate::init_graph::*;
 pub use crate::isr::*;
+pub use crate::log_sink::*;
 pub use crate::mutex::*;
-pub use crate::operating_system::FreeRTOS;
+#[cfg(feature = "rt_checks")]
+pub use crate::no_block::{without_blocking, BlockViolation, NoBlockSection};
+pub use crate::notification::*;
+pub use crate::once_cell::*;
+pub use crate::operating_system::{FreeRTOS, SchedulerState};
+#[cfg(feature = "owner_checks")]
+pub use crate::owner::OwnerViolation;
+pub use crate::persistence::*;
+pub use crate::priority_band::*;
+pub use crate::priority_plan::*;
+pub use crate::progress::*;
+pub use crate::pump::*;
 pub use crate::queue::*;
+pub use crate::queue_set::{QueueSet, QueueSetMember, QueueSetMemberHandle};
+pub use crate::quiescent::*;
+#[cfg(feature = "fmt")]
+pub use crate::render::{RenderCursor, RenderOptions};
+pub use crate::rendezvous::*;
+pub use crate::replenishing_semaphore::*;
+#[cfg(feature = "mpu")]
+pub use crate::restricted_task::*;
 pub use crate::semaphore::*;
+pub use crate::service_budget::ServiceBudgetViolation;
+#[cfg(feature = "static_allocation")]
+pub use crate::static_task::*;
+pub use crate::stats::*;
+pub use crate::status_cell::*;
+pub use crate::stream_buffer::*;
+pub use crate::sync::{consume_payload, publish_with_payload};
 pub use crate::task::*;
+pub use crate::ticks::*;
+pub use crate::time_window::*;
+pub use crate::timer_service::*;
 pub use crate::timers::*;
+pub use crate::trace_sink::*;
+pub use crate::transaction::*;
+pub use crate::tunable::*;
 pub use crate::units::*;
 
 pub use crate::utils::shim_sanity_check;
+pub use crate::watchdog::*;
+pub use crate::wip::*;
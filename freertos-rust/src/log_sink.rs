@@ -0,0 +1,108 @@
+use crate::base::*;
+use crate::prelude::v1::*;
+use crate::shim::*;
+use crate::ticks::*;
+// Kept in statics, so the atomics have to be const-initialised.
+use core::sync::atomic::{AtomicU32, Ordering};
+
+/// The severity of a log line.
+#[derive(Debug, Copy, Clone, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub enum LogLevel {
+    Error,
+    Warn,
+    Info,
+    Debug,
+}
+
+/// Where log lines go, see `set_log_sink`.
+///
+/// `write` is called from tasks and from ISRs, so it must not block. A sink that hands
+/// the line to a task typically copies it into a queue with `send_from_isr`.
+pub trait LogSink: Sync {
+    fn write(&self, level: LogLevel, module: &str, message: &[u8]);
+}
+
+/// Let at most `lines` lines through every `window` ticks. Lines over that are dropped
+/// and counted, see `log_lines_dropped`.
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub struct LogRateLimit {
+    pub lines: u32,
+    pub window: FreeRtosTickType,
+}
+
+impl LogRateLimit {
+    pub const UNLIMITED: LogRateLimit = LogRateLimit {
+        lines: u32::MAX,
+        window: 1,
+    };
+}
+
+/// Why `log_write` didn't pass a line to the sink.
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum LogDropped {
+    NoSink,
+    RateLimited,
+}
+
+static mut LOG_SINK: Option<(&'static dyn LogSink, LogRateLimit)> = None;
+
+static WINDOW_START: AtomicU32 = AtomicU32::new(0);
+static WINDOW_LINES: AtomicU32 = AtomicU32::new(0);
+static DROPPED: AtomicU32 = AtomicU32::new(0);
+
+/// Send log lines to `sink`, at most as many as `limit` lets through.
+///
+/// # Safety
+///
+/// Must be called before anything logs, and not while anything does.
+pub unsafe fn set_log_sink(sink: &'static dyn LogSink, limit: LogRateLimit) {
+    LOG_SINK = Some((sink, limit));
+    WINDOW_START.store(freertos_rs_xTaskGetTickCountFromISR(), Ordering::Relaxed);
+    WINDOW_LINES.store(0, Ordering::Relaxed);
+}
+
+/// Pass a line to the log sink, unless the rate limit is reached. Can be called from
+/// ISRs.
+pub fn log_write(level: LogLevel, module: &str, message: &[u8]) -> Result<(), LogDropped> {
+    let (sink, limit) = match unsafe { *ptr::addr_of!(LOG_SINK) } {
+        Some(log) => log,
+        None => return Err(LogDropped::NoSink),
+    };
+
+    if !rate_limit_allows(limit) {
+        DROPPED.fetch_add(1, Ordering::Relaxed);
+        return Err(LogDropped::RateLimited);
+    }
+
+    sink.write(level, module, message);
+    Ok(())
+}
+
+/// How many lines the rate limit dropped so far.
+pub fn log_lines_dropped() -> u32 {
+    DROPPED.load(Ordering::Relaxed)
+}
+
+fn rate_limit_allows(limit: LogRateLimit) -> bool {
+    let now = unsafe { freertos_rs_xTaskGetTickCountFromISR() };
+    let start = WINDOW_START.load(Ordering::Relaxed);
+    // Whoever moves the window on resets the count. Lines racing with that may count
+    // towards either window.
+    if tick_elapsed(now, start) >= limit.window
+        && WINDOW_START
+            .compare_exchange(start, now, Ordering::Relaxed, Ordering::Relaxed)
+            .is_ok()
+    {
+        WINDOW_LINES.store(0, Ordering::Relaxed);
+    }
+
+    WINDOW_LINES
+        .fetch_update(Ordering::Relaxed, Ordering::Relaxed, |lines| {
+            if lines < limit.lines {
+                Some(lines + 1)
+            } else {
+                None
+            }
+        })
+        .is_ok()
+}
@@ -1,8 +1,15 @@
 use crate::base::*;
+use crate::hold_limit::*;
 use crate::isr::*;
+use crate::no_block::check_blocking;
 use crate::operating_system::*;
 use crate::prelude::v1::*;
+#[cfg(feature = "static_allocation")]
+use crate::queue::static_control_block_fits;
+#[cfg(feature = "static_allocation")]
+use crate::semaphore::StaticSemaphoreStorage;
 use crate::shim::*;
+use crate::task::*;
 use crate::units::*;
 
 pub type Mutex<T> = MutexImpl<T, MutexNormal>;
@@ -15,8 +22,14 @@ impl<T: Sync + Send, M> !ISRSafe for MutexImpl<T, M> {}
 
 /// Mutual exclusion access to a contained value. Can be recursive -
 /// the current owner of a lock can re-lock it.
+///
+/// The kernel applies priority inheritance: while a higher priority task waits for the
+/// lock, the holder runs at that task's priority, and drops back when it unlocks. Until a
+/// task waits, the holder can be preempted by any task above it. `CeilingMutex` raises
+/// the holder for the whole critical section instead.
 pub struct MutexImpl<T: ?Sized, M> {
     mutex: M,
+    hold: Option<Box<HoldLimit>>,
     data: UnsafeCell<T>,
 }
 
@@ -34,13 +47,21 @@ where
     M: MutexInnerImpl,
 {
     /// Try to obtain a lock and mutable access to our inner value
+    #[inline]
     pub fn lock<D: DurationTicks>(&self, max_wait: D) -> Result<MutexGuard<T, M>, FreeRtosError> {
-        self.mutex.take(max_wait)?;
-
-        Ok(MutexGuard {
+        UnlockedGuard {
             __mutex: &self.mutex,
+            __hold: self.hold.as_deref(),
             __data: &self.data,
-        })
+        }
+        .lock(max_wait.to_ticks())
+    }
+
+    /// Try to obtain a lock without waiting. Returns `None` if the mutex is held.
+    ///
+    /// A recursive mutex that is already held by the calling task is locked again.
+    pub fn try_lock(&self) -> Option<MutexGuard<'_, T, M>> {
+        self.lock(Duration::zero()).ok()
     }
 
     /// Consume the mutex and return its inner value
@@ -48,20 +69,30 @@ where
         // Manually deconstruct the structure, because it implements Drop
         // and we cannot move the data value out of it.
         unsafe {
-            let (mutex, data) = {
+            let (mutex, hold, data) = {
                 let Self {
                     ref mutex,
+                    ref hold,
                     ref data,
                 } = self;
-                (ptr::read(mutex), ptr::read(data))
+                (ptr::read(mutex), ptr::read(hold), ptr::read(data))
             };
             mem::forget(self);
 
+            drop(hold);
             drop(mutex);
 
             data.into_inner()
         }
     }
+
+    pub(crate) fn inner(&self) -> &M {
+        &self.mutex
+    }
+
+    pub(crate) fn data_ptr(&self) -> *mut T {
+        self.data.get()
+    }
 }
 
 impl<T: ISRSafe + Clone> ISRSafeHandle<ISRMutexImpl> for MutexImpl<T, MutexNormal> {
@@ -94,6 +125,69 @@ impl<T> MutexImpl<T, MutexNormal> {
     pub fn new(os: FreeRTOS, t: T) -> Result<Self, FreeRtosError> {
         Ok(MutexImpl {
             mutex: MutexNormal::create(os)?,
+            hold: None,
+            data: UnsafeCell::new(t),
+        })
+    }
+
+    /// Call `on_hold_violation` when a task holds the mutex longer than `max_hold`, and
+    /// with `HoldPolicy::AbortDelayAndNotify` also wake the holder from the delay or kernel
+    /// wait it is in, so its own timeout and error paths can run and unlock the mutex.
+    ///
+    /// Each lock starts a one-shot timer of the kernel's timer daemon and each unlock
+    /// stops it, so the check costs two timer commands per hold and the callback runs in
+    /// the daemon task. Holds are not checked while the timer command queue is full.
+    ///
+    /// The mutex is deliberately never released on behalf of the holder: the kernel only
+    /// lets the holder give a mutex back, the data may be halfway through an update that
+    /// the next task would then see, and the holder's guard would still unlock it later,
+    /// while another task holds it.
+    pub fn with_max_hold<D: DurationTicks, F>(
+        mut self,
+        max_hold: D,
+        policy: HoldPolicy,
+        on_hold_violation: F,
+    ) -> Result<Self, FreeRtosError>
+    where
+        F: Fn(&HoldViolation) + Send + Sync + 'static,
+    {
+        self.hold = Some(Box::new(HoldLimit::new(
+            max_hold.to_ticks(),
+            policy,
+            on_hold_violation,
+        )?));
+        Ok(self)
+    }
+
+    /// The task holding the mutex, or `None` when it isn't locked. Only a snapshot, for
+    /// diagnostics: the holder may have unlocked it by the time this returns. Needs
+    /// `INCLUDE_xSemaphoreGetMutexHolder`.
+    pub fn holder(&self) -> Option<TaskRemoteHandle> {
+        mutex_holder(self.mutex.0)
+    }
+}
+
+#[cfg(feature = "static_allocation")]
+impl<T> MutexImpl<T, MutexNormal> {
+    /// Create a new mutex with the given inner value, in caller-provided storage instead
+    /// of the FreeRTOS heap.
+    pub fn new_static(
+        _os: FreeRTOS,
+        storage: &'static mut StaticSemaphoreStorage,
+        t: T,
+    ) -> Result<Self, FreeRtosError> {
+        if !static_control_block_fits() {
+            return Err(FreeRtosError::OutOfMemory);
+        }
+
+        let m = unsafe { freertos_rs_create_semaphore_static(storage.as_mut_ptr()) };
+        if m == 0 as *const _ {
+            return Err(FreeRtosError::OutOfMemory);
+        }
+
+        Ok(MutexImpl {
+            mutex: MutexNormal(m),
+            hold: None,
             data: UnsafeCell::new(t),
         })
     }
@@ -104,9 +198,23 @@ impl<T> MutexImpl<T, MutexRecursive> {
     pub fn new(os: FreeRTOS, t: T) -> Result<Self, FreeRtosError> {
         Ok(MutexImpl {
             mutex: MutexRecursive::create(os)?,
+            hold: None,
             data: UnsafeCell::new(t),
         })
     }
+
+    /// The task holding the mutex, see `Mutex::holder`.
+    pub fn holder(&self) -> Option<TaskRemoteHandle> {
+        mutex_holder(self.mutex.0)
+    }
+}
+
+fn mutex_holder(mutex: FreeRtosSemaphoreHandle) -> Option<TaskRemoteHandle> {
+    let task = unsafe { freertos_rs_get_mutex_holder(mutex) };
+    if task.is_null() {
+        return None;
+    }
+    Some(unsafe { TaskRemoteHandle::from_raw(task) })
 }
 
 /// Holds the mutex until we are dropped
@@ -115,6 +223,7 @@ where
     M: MutexInnerImpl,
 {
     __mutex: &'a M,
+    __hold: Option<&'a HoldLimit>,
     __data: &'a UnsafeCell<T>,
 }
 
@@ -143,6 +252,143 @@ where
     M: MutexInnerImpl,
 {
     fn drop(&mut self) {
+        if let Some(hold) = self.__hold {
+            hold.released();
+        }
+        self.__mutex.give();
+    }
+}
+
+impl<'a, T: ?Sized, M> MutexGuard<'a, T, M>
+where
+    M: MutexInnerImpl,
+{
+    /// Narrow the guard down to a part of the locked value, such as one field. The
+    /// mutex stays locked until the returned guard is dropped.
+    pub fn map<U: ?Sized, F>(self, f: F) -> MappedMutexGuard<'a, U, M>
+    where
+        F: FnOnce(&mut T) -> &mut U,
+    {
+        // The guard still unlocks the mutex if `f` panics.
+        let data = f(unsafe { &mut *self.__data.get() }) as *mut U;
+        let (mutex, hold) = (self.__mutex, self.__hold);
+        mem::forget(self);
+
+        MappedMutexGuard {
+            __mutex: mutex,
+            __hold: hold,
+            __data: data,
+            _marker: PhantomData,
+        }
+    }
+}
+
+impl<'a, T: ?Sized, M> MutexGuard<'a, T, M>
+where
+    M: MutexInnerImpl,
+{
+    /// Unlock the mutex, keeping what is needed to lock it again, see `Condvar::wait`.
+    pub(crate) fn unlock(self) -> UnlockedGuard<'a, T, M> {
+        let unlocked = UnlockedGuard {
+            __mutex: self.__mutex,
+            __hold: self.__hold,
+            __data: self.__data,
+        };
+        drop(self);
+        unlocked
+    }
+}
+
+/// A mutex that isn't locked, to be locked into a `MutexGuard`.
+pub(crate) struct UnlockedGuard<'a, T: ?Sized + 'a, M: 'a> {
+    __mutex: &'a M,
+    __hold: Option<&'a HoldLimit>,
+    __data: &'a UnsafeCell<T>,
+}
+
+impl<'a, T: ?Sized, M> UnlockedGuard<'a, T, M>
+where
+    M: MutexInnerImpl,
+{
+    #[inline]
+    pub(crate) fn lock(
+        self,
+        max_wait: FreeRtosTickType,
+    ) -> Result<MutexGuard<'a, T, M>, FreeRtosError> {
+        self.__mutex.take(Duration::ticks(max_wait))?;
+        if let Some(hold) = self.__hold {
+            hold.acquired();
+        }
+
+        Ok(MutexGuard {
+            __mutex: self.__mutex,
+            __hold: self.__hold,
+            __data: self.__data,
+        })
+    }
+}
+
+/// A `MutexGuard` narrowed down by `MutexGuard::map`. Holds the mutex until we are dropped.
+pub struct MappedMutexGuard<'a, T: ?Sized + 'a, M: 'a>
+where
+    M: MutexInnerImpl,
+{
+    __mutex: &'a M,
+    __hold: Option<&'a HoldLimit>,
+    __data: *mut T,
+    _marker: PhantomData<&'a mut T>,
+}
+
+impl<'a, T: ?Sized, M> MappedMutexGuard<'a, T, M>
+where
+    M: MutexInnerImpl,
+{
+    /// Narrow the guard down further, like `MutexGuard::map`.
+    pub fn map<U: ?Sized, F>(self, f: F) -> MappedMutexGuard<'a, U, M>
+    where
+        F: FnOnce(&mut T) -> &mut U,
+    {
+        let data = f(unsafe { &mut *self.__data }) as *mut U;
+        let (mutex, hold) = (self.__mutex, self.__hold);
+        mem::forget(self);
+
+        MappedMutexGuard {
+            __mutex: mutex,
+            __hold: hold,
+            __data: data,
+            _marker: PhantomData,
+        }
+    }
+}
+
+impl<'mutex, T: ?Sized, M> Deref for MappedMutexGuard<'mutex, T, M>
+where
+    M: MutexInnerImpl,
+{
+    type Target = T;
+
+    fn deref(&self) -> &T {
+        unsafe { &*self.__data }
+    }
+}
+
+impl<'mutex, T: ?Sized, M> DerefMut for MappedMutexGuard<'mutex, T, M>
+where
+    M: MutexInnerImpl,
+{
+    fn deref_mut(&mut self) -> &mut T {
+        unsafe { &mut *self.__data }
+    }
+}
+
+impl<'a, T: ?Sized, M> Drop for MappedMutexGuard<'a, T, M>
+where
+    M: MutexInnerImpl,
+{
+    fn drop(&mut self) {
+        if let Some(hold) = self.__hold {
+            hold.released();
+        }
         self.__mutex.give();
     }
 }
@@ -160,6 +406,11 @@ where
 pub struct MutexNormal(FreeRtosSemaphoreHandle);
 
 impl MutexNormal {
+    #[cfg(feature = "cmsis-compat")]
+    pub(crate) fn raw_handle(&self) -> FreeRtosSemaphoreHandle {
+        self.0
+    }
+
     pub fn take_isr(&self, context: &mut InterruptContext) -> bool {
         unsafe { freertos_rs_take_semaphore_isr(self.0, context.get_task_field_mut()) == 0 }
     }
@@ -178,14 +429,9 @@ impl MutexInnerImpl for MutexNormal {
         Ok(MutexNormal(m))
     }
 
+    #[inline]
     fn take<D: DurationTicks>(&self, max_wait: D) -> Result<(), FreeRtosError> {
-        let res = unsafe { freertos_rs_take_semaphore(self.0, max_wait.to_ticks()) };
-
-        if res != 0 {
-            return Err(FreeRtosError::MutexTimeout);
-        }
-
-        Ok(())
+        mutex_take(self.0, max_wait.to_ticks())
     }
 
     fn give(&self) {
@@ -195,6 +441,18 @@ impl MutexInnerImpl for MutexNormal {
     }
 }
 
+fn mutex_take(
+    mutex: FreeRtosSemaphoreHandle,
+    max_wait: FreeRtosTickType,
+) -> Result<(), FreeRtosError> {
+    check_blocking("Mutex::lock", mutex, max_wait);
+
+    if unsafe { freertos_rs_take_semaphore(mutex, max_wait) } != 0 {
+        return Err(mutex_lock_failed(mutex));
+    }
+    Ok(())
+}
+
 impl Drop for MutexNormal {
     fn drop(&mut self) {
         unsafe { freertos_rs_delete_semaphore(self.0) }
@@ -210,6 +468,13 @@ impl fmt::Debug for MutexNormal {
 #[derive(Clone)]
 pub struct MutexRecursive(FreeRtosSemaphoreHandle);
 
+impl MutexRecursive {
+    #[cfg(feature = "cmsis-compat")]
+    pub(crate) fn raw_handle(&self) -> FreeRtosSemaphoreHandle {
+        self.0
+    }
+}
+
 impl MutexInnerImpl for MutexRecursive {
     fn create(_os: FreeRTOS) -> Result<Self, FreeRtosError> {
         let m = unsafe { freertos_rs_create_recursive_semaphore() };
@@ -219,14 +484,9 @@ impl MutexInnerImpl for MutexRecursive {
         Ok(MutexRecursive(m))
     }
 
+    #[inline]
     fn take<D: DurationTicks>(&self, max_wait: D) -> Result<(), FreeRtosError> {
-        let res = unsafe { freertos_rs_take_recursive_semaphore(self.0, max_wait.to_ticks()) };
-
-        if res != 0 {
-            return Err(FreeRtosError::MutexTimeout);
-        }
-
-        Ok(())
+        recursive_mutex_take(self.0, max_wait.to_ticks())
     }
 
     fn give(&self) {
@@ -236,6 +496,34 @@ impl MutexInnerImpl for MutexRecursive {
     }
 }
 
+fn recursive_mutex_take(
+    mutex: FreeRtosSemaphoreHandle,
+    max_wait: FreeRtosTickType,
+) -> Result<(), FreeRtosError> {
+    check_blocking("RecursiveMutex::lock", mutex, max_wait);
+
+    if unsafe { freertos_rs_take_recursive_semaphore(mutex, max_wait) } != 0 {
+        return Err(mutex_lock_failed(mutex));
+    }
+    Ok(())
+}
+
+/// With `debug_sync`, names the task that held the mutex when the lock timed out, if it
+/// still holds it. With `emergency_abort`, `Emergency` in a task an emergency aborted.
+#[cold]
+#[inline(never)]
+fn mutex_lock_failed(_mutex: FreeRtosSemaphoreHandle) -> FreeRtosError {
+    #[cfg(feature = "emergency_abort")]
+    if crate::emergency::emergency_aborted() {
+        return FreeRtosError::Emergency;
+    }
+    #[cfg(feature = "debug_sync")]
+    if let Some(holder) = mutex_holder(_mutex) {
+        return FreeRtosError::MutexTimeoutHeldBy(TaskName::from_bytes(holder.get_name_bytes()));
+    }
+    FreeRtosError::MutexTimeout
+}
+
 impl Drop for MutexRecursive {
     fn drop(&mut self) {
         unsafe { freertos_rs_delete_semaphore(self.0) }
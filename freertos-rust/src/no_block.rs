@@ -0,0 +1,138 @@
+#[cfg(feature = "rt_checks")]
+pub use self::checks::*;
+
+#[cfg(not(feature = "rt_checks"))]
+use crate::base::*;
+
+/// Without `rt_checks` there's nothing to check, and this compiles to nothing.
+#[cfg(not(feature = "rt_checks"))]
+#[inline(always)]
+pub(crate) fn check_blocking(_api: &'static str, _object: *const CVoid, _wait: FreeRtosTickType) {}
+
+/// Without `rt_checks` there are no sections.
+#[cfg(not(feature = "rt_checks"))]
+#[inline(always)]
+pub(crate) fn in_no_block_section() -> bool {
+    false
+}
+
+#[cfg(feature = "rt_checks")]
+mod checks {
+    use crate::base::*;
+    use crate::critical::*;
+    use crate::hooks::*;
+    use crate::prelude::v1::*;
+    use crate::shim::*;
+    use crate::task::*;
+    // Read by every blocking call, so it has to be a const-initialised static.
+    use core::sync::atomic::{AtomicU32, Ordering};
+
+    /// A blocking kernel call made by a task inside a `NoBlockSection`.
+    #[derive(Debug, Copy, Clone)]
+    pub struct BlockViolation {
+        /// The wrapper that was called, like `"Mutex::lock"`.
+        pub api: &'static str,
+        /// The queue, semaphore or other kernel object waited on, or null for delays and
+        /// notifications.
+        pub object: *const CVoid,
+        pub task: FreeRtosTaskHandle,
+        pub task_name: TaskName,
+        pub wait: FreeRtosTickType,
+    }
+
+    static ACTIVE_SECTIONS: AtomicU32 = AtomicU32::new(0);
+
+    static mut SECTION_DEPTHS: Vec<(FreeRtosTaskHandle, u32)> = Vec::new();
+
+    /// Marks a stretch of a task in which no blocking kernel call may be made.
+    ///
+    /// While any section is entered, every blocking wrapper in the crate called with a
+    /// nonzero wait checks whether its task is inside a section and, if it is, reports a
+    /// `BlockViolation` through `FREERTOS_HOOKS`. Calls with a zero wait, like `try_lock`,
+    /// are always allowed. Sections nest and end when the guard is dropped, also while
+    /// unwinding.
+    pub struct NoBlockSection {
+        task: FreeRtosTaskHandle,
+    }
+
+    impl !Send for NoBlockSection {}
+
+    impl NoBlockSection {
+        pub fn enter(this: &TaskSelfHandle) -> NoBlockSection {
+            let task = this.raw_handle();
+
+            let _lock = CriticalRegion::enter();
+            let depths = unsafe { &mut *ptr::addr_of_mut!(SECTION_DEPTHS) };
+            match depths.iter_mut().find(|(t, _)| *t == task) {
+                Some((_, depth)) => *depth += 1,
+                None => depths.push((task, 1)),
+            }
+            ACTIVE_SECTIONS.fetch_add(1, Ordering::Relaxed);
+
+            NoBlockSection { task }
+        }
+    }
+
+    impl Drop for NoBlockSection {
+        fn drop(&mut self) {
+            let _lock = CriticalRegion::enter();
+            let depths = unsafe { &mut *ptr::addr_of_mut!(SECTION_DEPTHS) };
+            if let Some(index) = depths.iter().position(|(t, _)| *t == self.task) {
+                depths[index].1 -= 1;
+                if depths[index].1 == 0 {
+                    depths.swap_remove(index);
+                }
+            }
+            ACTIVE_SECTIONS.fetch_sub(1, Ordering::Relaxed);
+        }
+    }
+
+    /// Run `f` inside a `NoBlockSection`.
+    pub fn without_blocking<R, F: FnOnce() -> R>(this: &TaskSelfHandle, f: F) -> R {
+        let _section = NoBlockSection::enter(this);
+        f()
+    }
+
+    /// Report a blocking call with a nonzero wait made inside a `NoBlockSection`.
+    #[inline]
+    pub(crate) fn check_blocking(api: &'static str, object: *const CVoid, wait: FreeRtosTickType) {
+        if wait != 0 && ACTIVE_SECTIONS.load(Ordering::Relaxed) != 0 {
+            report_if_in_section(api, object, wait);
+        }
+    }
+
+    /// Whether the current task is inside a `NoBlockSection`.
+    pub(crate) fn in_no_block_section() -> bool {
+        if ACTIVE_SECTIONS.load(Ordering::Relaxed) == 0 {
+            return false;
+        }
+
+        let task = unsafe { freertos_rs_get_current_task() };
+        let _lock = CriticalRegion::enter();
+        unsafe {
+            (*ptr::addr_of!(SECTION_DEPTHS))
+                .iter()
+                .any(|(t, _)| *t == task)
+        }
+    }
+
+    #[cold]
+    fn report_if_in_section(api: &'static str, object: *const CVoid, wait: FreeRtosTickType) {
+        if in_no_block_section() {
+            let task = unsafe { freertos_rs_get_current_task() };
+            let violation = BlockViolation {
+                api,
+                object,
+                task,
+                task_name: TaskName::from_bytes(
+                    unsafe { TaskRemoteHandle::from_raw(task) }.get_name_bytes(),
+                ),
+                wait,
+            };
+
+            unsafe {
+                (*ptr::addr_of!(FREERTOS_HOOKS)).do_on_block_violation(&violation);
+            }
+        }
+    }
+}
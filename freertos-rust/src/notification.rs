@@ -0,0 +1,278 @@
+//! Typed ends for the notifications of a task, the cheapest way to signal a task.
+//!
+//! `NotificationSemaphore::new` and `NotificationMailbox::new` split one notification of
+//! the calling task into a consumer end, which stays in the task, and a producer end,
+//! which can be cloned and sent to other tasks, or turned into an ISR handle. A
+//! notification must only back one of these, and nothing else may notify the task on it:
+//! `TaskRemoteHandle::notify`, `InfraTask` and `StateMachinePump` use the same one as
+//! the ends made by `new`. With the `notify_indexed` feature, on FreeRTOS 10.4 or
+//! later, `new_indexed` picks another one of the `configTASK_NOTIFICATION_ARRAY_ENTRIES`.
+//!
+//! What a task writes before it gives or sends is visible to the task once its `take` or
+//! `receive` returned, also on weakly ordered cores like the Cortex-M7. The kernel only
+//! orders the notification value, so the ends fence around it, and a value sent to a
+//! mailbox can be the index of a buffer that isn't atomic.
+
+use crate::base::*;
+use crate::isr::*;
+use crate::no_block::check_blocking;
+use crate::prelude::v1::*;
+use crate::shim::*;
+use crate::sync::atomic::{fence, Ordering};
+use crate::task::*;
+use crate::units::*;
+
+impl !ISRSafe for NotificationSemaphore {}
+impl !ISRSafe for NotificationGiver {}
+impl !ISRSafe for NotificationMailbox {}
+impl !ISRSafe for NotificationMailboxSender {}
+
+unsafe impl Send for NotificationGiver {}
+unsafe impl Sync for NotificationGiver {}
+unsafe impl Send for ISRNotificationGiver {}
+unsafe impl Sync for ISRNotificationGiver {}
+unsafe impl Send for NotificationMailboxSender {}
+unsafe impl Sync for NotificationMailboxSender {}
+unsafe impl Send for ISRNotificationMailboxSender {}
+unsafe impl Sync for ISRNotificationMailboxSender {}
+
+/// A notification of a task, by its index.
+#[derive(Debug, Copy, Clone)]
+struct Slot {
+    task: FreeRtosTaskHandle,
+    #[cfg(feature = "notify_indexed")]
+    index: FreeRtosUBaseType,
+}
+
+impl Slot {
+    fn new(this: &TaskSelfHandle) -> Slot {
+        Slot {
+            task: this.raw_handle(),
+            #[cfg(feature = "notify_indexed")]
+            index: 0,
+        }
+    }
+
+    #[cfg(feature = "notify_indexed")]
+    fn indexed(this: &TaskSelfHandle, index: FreeRtosUBaseType) -> Slot {
+        let entries = unsafe { freertos_rs_get_notification_array_entries() };
+        assert!(
+            index < entries,
+            "notification index {} out of configTASK_NOTIFICATION_ARRAY_ENTRIES {}",
+            index,
+            entries
+        );
+        Slot {
+            task: this.raw_handle(),
+            index,
+        }
+    }
+
+    fn notify(&self, notification: TaskNotification) {
+        let (value, action) = notification.to_freertos();
+        fence(Ordering::Release);
+        unsafe {
+            #[cfg(feature = "notify_indexed")]
+            freertos_rs_task_notify_indexed(self.task, self.index, value, action);
+            #[cfg(not(feature = "notify_indexed"))]
+            freertos_rs_task_notify(self.task, value, action);
+        }
+    }
+
+    fn notify_isr(&self, context: &mut InterruptContext, notification: TaskNotification) {
+        let (value, action) = notification.to_freertos();
+        fence(Ordering::Release);
+        unsafe {
+            let woken = context.get_task_field_mut();
+            #[cfg(feature = "notify_indexed")]
+            freertos_rs_task_notify_indexed_isr(self.task, self.index, value, action, woken);
+            #[cfg(not(feature = "notify_indexed"))]
+            freertos_rs_task_notify_isr(self.task, value, action, woken);
+        }
+    }
+
+    /// Must be called from the task of the slot.
+    fn take(&self, api: &'static str, wait: FreeRtosTickType) -> u32 {
+        check_blocking(api, ptr::null(), wait);
+        let taken = unsafe {
+            #[cfg(feature = "notify_indexed")]
+            let taken = freertos_rs_task_notify_take_indexed(self.index, 0, wait);
+            #[cfg(not(feature = "notify_indexed"))]
+            let taken = freertos_rs_task_notify_take(0, wait);
+            taken
+        };
+        fence(Ordering::Acquire);
+        taken
+    }
+
+    /// Must be called from the task of the slot.
+    fn wait(&self, api: &'static str, wait: FreeRtosTickType) -> Result<u32, FreeRtosError> {
+        check_blocking(api, ptr::null(), wait);
+        let mut value = 0;
+        let r = unsafe {
+            #[cfg(feature = "notify_indexed")]
+            let r = freertos_rs_task_notify_wait_indexed(self.index, 0, u32::MAX, &mut value, wait);
+            #[cfg(not(feature = "notify_indexed"))]
+            let r = freertos_rs_task_notify_wait(0, u32::MAX, &mut value, wait);
+            r
+        };
+        if r != 0 {
+            return Err(timed_out());
+        }
+        fence(Ordering::Acquire);
+        Ok(value)
+    }
+}
+
+/// The taking end of a semaphore kept in a notification of the task that created it.
+///
+/// Gives add up in the notification value and each `take` takes one, like a
+/// `CountingSemaphore` without a maximum. Stays in its task: only the task a notification
+/// belongs to can wait for it.
+pub struct NotificationSemaphore {
+    slot: Slot,
+    _not_send: PhantomData<*const ()>,
+}
+
+impl NotificationSemaphore {
+    /// A semaphore in the notification of `this` task, and the end that gives it.
+    pub fn new(this: &TaskSelfHandle) -> (NotificationGiver, NotificationSemaphore) {
+        Self::with_slot(Slot::new(this))
+    }
+
+    /// `new` on notification `index` of `this` task. Panics when the index isn't below
+    /// `configTASK_NOTIFICATION_ARRAY_ENTRIES`.
+    #[cfg(feature = "notify_indexed")]
+    pub fn new_indexed(
+        this: &TaskSelfHandle,
+        index: FreeRtosUBaseType,
+    ) -> (NotificationGiver, NotificationSemaphore) {
+        Self::with_slot(Slot::indexed(this, index))
+    }
+
+    fn with_slot(slot: Slot) -> (NotificationGiver, NotificationSemaphore) {
+        (
+            NotificationGiver { slot },
+            NotificationSemaphore {
+                slot,
+                _not_send: PhantomData,
+            },
+        )
+    }
+
+    /// Take one give, waiting up to `max_wait` for it. Fails with `Timeout`.
+    pub fn take<D: DurationTicks>(&self, max_wait: D) -> Result<(), FreeRtosError> {
+        match self
+            .slot
+            .take("NotificationSemaphore::take", max_wait.to_ticks())
+        {
+            0 => Err(timed_out()),
+            _ => Ok(()),
+        }
+    }
+}
+
+/// The giving end of a `NotificationSemaphore`.
+#[derive(Clone)]
+pub struct NotificationGiver {
+    slot: Slot,
+}
+
+impl NotificationGiver {
+    pub fn give(&self) {
+        self.slot.notify(TaskNotification::Increment);
+    }
+}
+
+/// An ISR safe handle to the giving end of a `NotificationSemaphore`.
+pub struct ISRNotificationGiver {
+    slot: Slot,
+}
+
+impl ISRNotificationGiver {
+    pub fn give(&self, context: &mut InterruptContext) {
+        self.slot.notify_isr(context, TaskNotification::Increment);
+    }
+}
+
+impl ISRSafeHandle<ISRNotificationGiver> for NotificationGiver {
+    unsafe fn new_isr_safe_handle(&self) -> ISRNotificationGiver {
+        ISRNotificationGiver { slot: self.slot }
+    }
+}
+
+/// The receiving end of a mailbox of one `u32` kept in a notification of the task that
+/// created it.
+///
+/// A send replaces the value waiting in the mailbox, if there is one, so the task only
+/// gets the latest. Stays in its task, like a `NotificationSemaphore`.
+pub struct NotificationMailbox {
+    slot: Slot,
+    _not_send: PhantomData<*const ()>,
+}
+
+impl NotificationMailbox {
+    /// A mailbox in the notification of `this` task, and the end that sends to it.
+    pub fn new(this: &TaskSelfHandle) -> (NotificationMailboxSender, NotificationMailbox) {
+        Self::with_slot(Slot::new(this))
+    }
+
+    /// `new` on notification `index` of `this` task. Panics when the index isn't below
+    /// `configTASK_NOTIFICATION_ARRAY_ENTRIES`.
+    #[cfg(feature = "notify_indexed")]
+    pub fn new_indexed(
+        this: &TaskSelfHandle,
+        index: FreeRtosUBaseType,
+    ) -> (NotificationMailboxSender, NotificationMailbox) {
+        Self::with_slot(Slot::indexed(this, index))
+    }
+
+    fn with_slot(slot: Slot) -> (NotificationMailboxSender, NotificationMailbox) {
+        (
+            NotificationMailboxSender { slot },
+            NotificationMailbox {
+                slot,
+                _not_send: PhantomData,
+            },
+        )
+    }
+
+    /// The value waiting in the mailbox, or the next one sent within `max_wait`, which
+    /// empties the mailbox. Fails with `Timeout`.
+    pub fn receive<D: DurationTicks>(&self, max_wait: D) -> Result<u32, FreeRtosError> {
+        self.slot
+            .wait("NotificationMailbox::receive", max_wait.to_ticks())
+    }
+}
+
+/// The sending end of a `NotificationMailbox`.
+#[derive(Clone)]
+pub struct NotificationMailboxSender {
+    slot: Slot,
+}
+
+impl NotificationMailboxSender {
+    /// Put `value` in the mailbox, replacing the one waiting there.
+    pub fn send(&self, value: u32) {
+        self.slot.notify(TaskNotification::OverwriteValue(value));
+    }
+}
+
+/// An ISR safe handle to the sending end of a `NotificationMailbox`.
+pub struct ISRNotificationMailboxSender {
+    slot: Slot,
+}
+
+impl ISRNotificationMailboxSender {
+    /// Put `value` in the mailbox, replacing the one waiting there.
+    pub fn send(&self, context: &mut InterruptContext, value: u32) {
+        self.slot
+            .notify_isr(context, TaskNotification::OverwriteValue(value));
+    }
+}
+
+impl ISRSafeHandle<ISRNotificationMailboxSender> for NotificationMailboxSender {
+    unsafe fn new_isr_safe_handle(&self) -> ISRNotificationMailboxSender {
+        ISRNotificationMailboxSender { slot: self.slot }
+    }
+}
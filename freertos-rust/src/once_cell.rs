@@ -0,0 +1,273 @@
+use crate::base::*;
+use crate::critical::*;
+use crate::isr::*;
+use crate::no_block::check_blocking;
+use crate::operating_system::*;
+use crate::prelude::v1::*;
+use crate::shim::*;
+use core::mem::MaybeUninit;
+// Cells are made by a `const fn` to be usable as statics, so their atomics have to be
+// const-initialised.
+use core::sync::atomic::{AtomicBool, AtomicPtr, AtomicU8, Ordering};
+
+unsafe impl<T: Send + Sync> Sync for FreeRtosOnceCell<T> {}
+
+impl<T> !ISRSafe for FreeRtosOnceCell<T> {}
+
+/// A value set once, by whichever task gets to it first, like `core::cell::OnceCell`
+/// but shared between tasks.
+///
+/// Tasks that find the cell empty take turns through a kernel mutex, so the others block
+/// while one runs the initializer instead of spinning. The mutex is created by the first
+/// of them, which makes a `const` empty cell free until then and usable as a `static`.
+///
+/// ```rust
+/// # use freertos_rs::*;
+/// static CONFIG: FreeRtosOnceCell<Config> = FreeRtosOnceCell::new();
+///
+/// let config = CONFIG.get_or_init(os, || Config::load());
+/// ```
+pub struct FreeRtosOnceCell<T> {
+    ready: AtomicBool,
+    /// The mutex initializers take turns on.
+    lock: AtomicPtr<CVoid>,
+    value: UnsafeCell<MaybeUninit<T>>,
+}
+
+impl<T> FreeRtosOnceCell<T> {
+    pub const fn new() -> FreeRtosOnceCell<T> {
+        FreeRtosOnceCell {
+            ready: AtomicBool::new(false),
+            lock: AtomicPtr::new(ptr::null_mut()),
+            value: UnsafeCell::new(MaybeUninit::uninit()),
+        }
+    }
+
+    /// The value, if the cell was set.
+    pub fn get(&self) -> Option<&T> {
+        if self.ready.load(Ordering::Acquire) {
+            Some(unsafe { (*self.value.get()).assume_init_ref() })
+        } else {
+            None
+        }
+    }
+
+    /// The value, set to what `f` returns first if the cell is empty. When several tasks
+    /// get here at once, one runs `f` and the others wait for it.
+    ///
+    /// `f` must not initialize the same cell, which waits for itself forever.
+    pub fn get_or_init<F: FnOnce() -> T>(&self, _os: FreeRTOS, f: F) -> &T {
+        if let Some(value) = self.get() {
+            return value;
+        }
+        self.initialize(f);
+        self.get().unwrap()
+    }
+
+    /// Set the cell to `value`, or give it back if the cell was already set.
+    pub fn set(&self, value: T) -> Result<(), T> {
+        let mut value = Some(value);
+        if self.get().is_none() && self.initialize(|| value.take().unwrap()) {
+            Ok(())
+        } else {
+            Err(value.take().unwrap())
+        }
+    }
+
+    /// Run `f` and keep what it returns, unless the cell was set while waiting for the
+    /// turn to. Returns whether `f` ran.
+    fn initialize<F: FnOnce() -> T>(&self, f: F) -> bool {
+        let _turn = Turn::take(self.lock());
+        if self.ready.load(Ordering::Acquire) {
+            return false;
+        }
+
+        unsafe { (*self.value.get()).as_mut_ptr().write(f()) };
+        self.ready.store(true, Ordering::Release);
+        true
+    }
+
+    /// The mutex, created by the first caller. Two tasks may both create one, the one
+    /// that comes second in the critical section deletes its own.
+    fn lock(&self) -> FreeRtosSemaphoreHandle {
+        let lock = self.lock.load(Ordering::Acquire);
+        if !lock.is_null() {
+            return lock;
+        }
+
+        let created = unsafe { freertos_rs_create_semaphore() };
+        if created.is_null() {
+            panic!("FreeRtosOnceCell: no memory for its mutex");
+        }
+        let lock = {
+            let _region = CriticalRegion::enter();
+            let lock = self.lock.load(Ordering::Acquire);
+            if lock.is_null() {
+                self.lock.store(created as *mut CVoid, Ordering::Release);
+                created
+            } else {
+                lock
+            }
+        };
+        if lock != created {
+            unsafe { freertos_rs_delete_semaphore(created) };
+        }
+        lock
+    }
+}
+
+impl<T> Default for FreeRtosOnceCell<T> {
+    fn default() -> Self {
+        FreeRtosOnceCell::new()
+    }
+}
+
+impl<T> Drop for FreeRtosOnceCell<T> {
+    fn drop(&mut self) {
+        if *self.ready.get_mut() {
+            unsafe { ptr::drop_in_place((*self.value.get()).as_mut_ptr()) };
+        }
+        let lock = *self.lock.get_mut();
+        if !lock.is_null() {
+            unsafe { freertos_rs_delete_semaphore(lock) };
+        }
+    }
+}
+
+/// Holds the mutex of a `FreeRtosOnceCell`, also if the initializer panics.
+struct Turn(FreeRtosSemaphoreHandle);
+
+impl Turn {
+    fn take(lock: FreeRtosSemaphoreHandle) -> Turn {
+        let wait = unsafe { freertos_rs_max_wait() };
+        check_blocking("FreeRtosOnceCell::get_or_init", lock, wait);
+        unsafe { freertos_rs_take_semaphore(lock, wait) };
+        Turn(lock)
+    }
+}
+
+impl Drop for Turn {
+    fn drop(&mut self) {
+        unsafe { freertos_rs_give_semaphore(self.0) };
+    }
+}
+
+unsafe impl<T: Send + Sync> Sync for StaticFreeRtosCell<T> {}
+
+const CELL_EMPTY: u8 = 0;
+const CELL_SETTING: u8 = 1;
+const CELL_READY: u8 = 2;
+
+/// A `static` slot for a kernel object, set once in the setup closure of
+/// `FreeRTOS::start_scheduler` and then used by any task, without passing `Arc`s into
+/// every task closure.
+///
+/// Unlike `FreeRtosOnceCell` it never blocks or allocates, so it can be set before the
+/// scheduler runs and read from interrupts. Reading it before it was set panics.
+///
+/// ```rust
+/// # use freertos_rs::*;
+/// static EVENTS: StaticFreeRtosCell<Queue<Event>> = StaticFreeRtosCell::new();
+///
+/// FreeRTOS::start_scheduler(|os| {
+///     EVENTS.init(os, |os| os.new_queue(8)).unwrap();
+///     os.new_task("consumer", 256, TaskPriority(2), |_, _| loop {
+///         handle(EVENTS.receive(Duration::infinite()).unwrap());
+///     })
+///     .unwrap();
+/// });
+/// ```
+pub struct StaticFreeRtosCell<T> {
+    state: AtomicU8,
+    value: UnsafeCell<MaybeUninit<T>>,
+}
+
+impl<T> StaticFreeRtosCell<T> {
+    pub const fn new() -> StaticFreeRtosCell<T> {
+        StaticFreeRtosCell {
+            state: AtomicU8::new(CELL_EMPTY),
+            value: UnsafeCell::new(MaybeUninit::uninit()),
+        }
+    }
+
+    /// Set the cell to what `f` creates and return it. Fails with the error of `f`, which
+    /// leaves the cell empty. Panics if the cell was already set.
+    pub fn init<F>(&self, os: FreeRTOS, f: F) -> Result<&T, FreeRtosError>
+    where
+        F: FnOnce(FreeRTOS) -> Result<T, FreeRtosError>,
+    {
+        if self.set(f(os)?).is_err() {
+            panic!("StaticFreeRtosCell initialized twice");
+        }
+        Ok(self.get())
+    }
+
+    /// Set the cell to `value`, or give it back if the cell was already set.
+    pub fn set(&self, value: T) -> Result<(), T> {
+        if self
+            .state
+            .compare_exchange(
+                CELL_EMPTY,
+                CELL_SETTING,
+                Ordering::Acquire,
+                Ordering::Relaxed,
+            )
+            .is_err()
+        {
+            return Err(value);
+        }
+        unsafe { (*self.value.get()).as_mut_ptr().write(value) };
+        self.state.store(CELL_READY, Ordering::Release);
+        Ok(())
+    }
+
+    /// The value, if the cell was set.
+    pub fn try_get(&self) -> Option<&T> {
+        if self.state.load(Ordering::Acquire) == CELL_READY {
+            Some(unsafe { (*self.value.get()).assume_init_ref() })
+        } else {
+            None
+        }
+    }
+
+    /// The value. Panics if the cell wasn't set yet.
+    pub fn get(&self) -> &T {
+        match self.try_get() {
+            Some(value) => value,
+            None => panic!("StaticFreeRtosCell used before it was initialized"),
+        }
+    }
+
+    /// An ISR safe handle to the value, which lives as long as the `static`, see
+    /// `ISRSafeHandle::new_isr_safe_handle_form_static`. Panics if the cell wasn't set
+    /// yet.
+    pub fn isr_handle<S: ISRSafe>(&'static self) -> S
+    where
+        T: ISRSafeHandle<S>,
+    {
+        self.get().new_isr_safe_handle_form_static()
+    }
+}
+
+impl<T> Deref for StaticFreeRtosCell<T> {
+    type Target = T;
+
+    /// The value, see `get`.
+    fn deref(&self) -> &T {
+        self.get()
+    }
+}
+
+impl<T> Default for StaticFreeRtosCell<T> {
+    fn default() -> Self {
+        StaticFreeRtosCell::new()
+    }
+}
+
+impl<T> Drop for StaticFreeRtosCell<T> {
+    fn drop(&mut self) {
+        if *self.state.get_mut() == CELL_READY {
+            unsafe { ptr::drop_in_place((*self.value.get()).as_mut_ptr()) };
+        }
+    }
+}
@@ -1,15 +1,52 @@
+use crate::allocator::{FreeRtosAllocator, HeapStats};
 use crate::base::*;
+use crate::ceiling_mutex::*;
+use crate::census::*;
+use crate::channel::*;
+use crate::condvar::*;
+use crate::config_distributor::*;
+use crate::critical::*;
+use crate::defer::*;
 use crate::delays::*;
+use crate::emergency::*;
+use crate::event_group::*;
+use crate::hooks::FREERTOS_HOOKS;
 use crate::isr::*;
 use crate::mutex::*;
+use crate::no_block::check_blocking;
 use crate::prelude::v1::*;
+use crate::progress::*;
+use crate::pump::*;
 use crate::queue::*;
+use crate::queue_set::*;
+use crate::rendezvous::*;
+use crate::replenishing_semaphore::*;
 use crate::semaphore::*;
 use crate::shim::*;
+use crate::stream_buffer::*;
 use crate::task::*;
+use crate::ticks::*;
+use crate::time_window::*;
 use crate::timers::*;
 use crate::units::*;
 use crate::utils::*;
+// A static, so it has to be const-initialised.
+#[cfg(feature = "end_scheduler")]
+use core::sync::atomic::{AtomicBool, Ordering};
+
+/// What `FreeRTOS::scheduler_state` returns.
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum SchedulerState {
+    /// Suspended with `suspend_all_tasks`. Blocking isn't allowed.
+    Suspended,
+    NotStarted,
+    Running,
+}
+
+/// Set by `end_scheduler`, so `try_start_scheduler` tells an ended scheduler from one
+/// that didn't start.
+#[cfg(feature = "end_scheduler")]
+static SCHEDULER_ENDED: AtomicBool = AtomicBool::new(false);
 
 /// A handle to the operating system to prevent calling non-ISR safe functions from ISRs.
 #[derive(Clone, Copy)]
@@ -18,24 +55,135 @@ pub struct FreeRTOS {}
 impl !ISRSafe for FreeRTOS {}
 
 impl FreeRTOS {
+    /// Whether this runs in an interrupt, for drivers to assert where they are called
+    /// from. Needs the `port_inside_interrupt` feature and a port with
+    /// `xPortIsInsideInterrupt`, like the Cortex-M ones, and is always false without.
+    pub fn is_inside_interrupt() -> bool {
+        unsafe { freertos_rs_is_inside_interrupt() != 0 }
+    }
+
+    /// Run `setup_function`, then start the scheduler. Panics if the kernel couldn't start
+    /// it, see `try_start_scheduler`.
+    #[cfg(not(feature = "end_scheduler"))]
     pub fn start_scheduler<F: FnOnce(FreeRTOS)>(setup_function: F) -> ! {
-        setup_function(FreeRTOS {});
+        FreeRTOS::try_start_scheduler(|os| {
+            setup_function(os);
+            Ok(())
+        });
+        panic!("FreeRTOS scheduler failed to start");
+    }
 
-        unsafe {
-            freertos_rs_vTaskStartScheduler();
+    /// Run `setup_function`, then start the scheduler, and return once a task ended it
+    /// with `end_scheduler`. Panics if the kernel couldn't start it, see
+    /// `try_start_scheduler`.
+    #[cfg(feature = "end_scheduler")]
+    pub fn start_scheduler<F: FnOnce(FreeRTOS)>(setup_function: F) {
+        let error = FreeRTOS::try_start_scheduler(|os| {
+            setup_function(os);
+            Ok(())
+        });
+        if error != FreeRtosError::ProcessorHasShutDown {
+            panic!("FreeRTOS scheduler failed to start");
         }
     }
 
+    /// Run `setup_function`, then start the scheduler if it succeeded. Only returns when
+    /// starting failed, with the error of `setup_function`, or `OutOfMemory` when the
+    /// kernel couldn't create the idle or timer task, after calling the startup failure
+    /// hook, see `FreeRtosHooks::set_on_startup_failure`.
+    ///
+    /// What `setup_function` created before it failed is kept, and its tasks run if the
+    /// scheduler is started later. With `configASSERT` defined, the kernel asserts before
+    /// returning `OutOfMemory`.
+    ///
+    /// With the `end_scheduler` feature, it also returns `ProcessorHasShutDown` once a
+    /// task ended the scheduler, without calling the hook.
+    pub fn try_start_scheduler<F>(setup_function: F) -> FreeRtosError
+    where
+        F: FnOnce(FreeRTOS) -> Result<(), FreeRtosError>,
+    {
+        let error = match setup_function(FreeRTOS {}) {
+            Ok(()) => {
+                unsafe { freertos_rs_vTaskStartScheduler() };
+                #[cfg(feature = "end_scheduler")]
+                if SCHEDULER_ENDED.load(Ordering::SeqCst) {
+                    return FreeRtosError::ProcessorHasShutDown;
+                }
+                FreeRtosError::OutOfMemory
+            }
+            Err(error) => error,
+        };
+        unsafe { (*ptr::addr_of!(FREERTOS_HOOKS)).do_on_startup_failure(error) };
+        error
+    }
+
+    /// Stop the scheduler from a task, so `start_scheduler` returns to `main`. Only for
+    /// the hosted ports, for tests running the kernel in a process:
+    ///
+    /// * on Linux, the threads of the other tasks are stopped, which takes the port about a
+    ///   second per task, and the calling task never returns,
+    /// * on Windows, the port exits the process instead.
+    ///
+    /// Nothing that ran in the tasks is dropped, and the kernel can't be started again,
+    /// so a process runs the scheduler once.
+    #[cfg(feature = "end_scheduler")]
+    pub fn end_scheduler(&self) -> ! {
+        SCHEDULER_ENDED.store(true, Ordering::SeqCst);
+        unsafe { freertos_rs_vTaskEndScheduler() }
+    }
+
+    /// Start the scheduler, run `test` in a new task, and end the scheduler once `test`
+    /// returned, returning its result to the caller. `test` can create the other tasks and
+    /// objects it needs. Errors when the task can't be created or the scheduler doesn't
+    /// start. See `end_scheduler`, like it only for the hosted ports.
+    ///
+    /// A test in its own file under `tests/`, as the scheduler runs once per process:
+    ///
+    ///     let received = FreeRTOS::run_scheduler_until(512, TaskPriority(2), |os| {
+    ///         let queue = os.new_queue(1).unwrap();
+    ///         queue.send(7u32, Duration::zero()).unwrap();
+    ///         queue.receive(Duration::zero())
+    ///     });
+    ///     assert_eq!(received, Ok(Ok(7)));
+    #[cfg(feature = "end_scheduler")]
+    pub fn run_scheduler_until<F, R>(
+        stack_depth: usize,
+        priority: TaskPriority,
+        test: F,
+    ) -> Result<R, FreeRtosError>
+    where
+        F: FnOnce(FreeRTOS) -> R,
+        F: Send + 'static,
+        R: Send + Sync + 'static,
+    {
+        let os = FreeRTOS {};
+        let result = Arc::new(Mutex::new(os, None)?);
+        let slot = result.clone();
+        let error = FreeRTOS::try_start_scheduler(move |os| {
+            os.new_task("test", stack_depth, priority, move |_, os| {
+                let value = test(os);
+                *slot.lock(Duration::infinite()).unwrap() = Some(value);
+                os.end_scheduler()
+            })?;
+            Ok(())
+        });
+        if error != FreeRtosError::ProcessorHasShutDown {
+            return Err(error);
+        }
+        let value = result.lock(Duration::zero())?.take();
+        Ok(value.unwrap())
+    }
+
     pub unsafe fn assume_init() -> FreeRTOS {
         FreeRTOS {}
     }
 
-    /// Prepare a builder object for the new task.
+    /// Spawn a new task.
     pub fn new_task<F>(
         &self,
         name: &str,
-        stack_depth: u16,
-        priority: TaskPriority,
+        stack_depth: usize,
+        priority: impl Into<TaskPriority>,
         func: F,
     ) -> Result<TaskRemoteHandle, FreeRtosError>
     where
@@ -45,6 +193,11 @@ impl FreeRTOS {
         TaskRemoteHandle::new(self.clone(), name, stack_depth, priority, func)
     }
 
+    /// Prepare a builder object for a new task.
+    pub fn new_task_builder(&self) -> TaskBuilder {
+        TaskBuilder::new(self.clone())
+    }
+
     /// Create a new delay helper, marking the current time as the start of the
     /// next measurement.
     pub fn new_delay(&self) -> TaskDelay {
@@ -65,6 +218,53 @@ impl FreeRTOS {
         Queue::new(self.clone(), max_size)
     }
 
+    /// Create a queue registered under `name` for kernel-aware debuggers, see
+    /// `Queue::new_named`.
+    pub fn new_queue_named<T: Copy>(
+        &self,
+        max_size: usize,
+        name: &str,
+    ) -> Result<Queue<T>, FreeRtosError> {
+        Queue::new_named(self.clone(), max_size, name)
+    }
+
+    /// Create a queue of one item, for `Queue::overwrite`.
+    pub fn new_mailbox<T: Copy>(&self) -> Result<Queue<T>, FreeRtosError> {
+        Queue::new(self.clone(), 1)
+    }
+
+    /// Create a queue set holding up to `capacity` events, see `QueueSet`.
+    pub fn new_queue_set(&self, capacity: usize) -> Result<QueueSet, FreeRtosError> {
+        QueueSet::new(self.clone(), capacity)
+    }
+
+    pub fn new_channel<T: Send>(&self, max_size: usize) -> Result<Channel<T>, FreeRtosError> {
+        Channel::new(self.clone(), max_size)
+    }
+
+    pub fn new_condvar(&self) -> Result<Condvar, FreeRtosError> {
+        Condvar::new(self.clone())
+    }
+
+    pub fn new_rendezvous<T: Copy>(&self) -> Result<Rendezvous<T>, FreeRtosError> {
+        Rendezvous::new(self.clone())
+    }
+
+    /// Create a new stream buffer holding `size` bytes, waking a blocked reader once
+    /// `trigger_level` bytes are available.
+    pub fn new_stream_buffer(
+        &self,
+        size: usize,
+        trigger_level: usize,
+    ) -> Result<StreamBuffer, FreeRtosError> {
+        StreamBuffer::new(self.clone(), size, trigger_level)
+    }
+
+    /// Create a new event group
+    pub fn new_event_group(&self) -> Result<EventGroup, FreeRtosError> {
+        EventGroup::new(self.clone())
+    }
+
     /// Create a new binary semaphore
     pub fn new_binary_semaphore(&self) -> Result<BinarySemaphore, FreeRtosError> {
         BinarySemaphore::new(self.clone())
@@ -79,6 +279,62 @@ impl FreeRTOS {
         CountingSemaphore::new(self.clone(), max, initial)
     }
 
+    /// Create a new counting semaphore that gets `replenish_amount` permits back every
+    /// `replenish_period`, up to `capacity`.
+    pub fn new_replenishing_semaphore<D: DurationTicks>(
+        &self,
+        capacity: u32,
+        replenish_amount: u32,
+        replenish_period: D,
+    ) -> Result<ReplenishingSemaphore, FreeRtosError> {
+        ReplenishingSemaphore::new(self.clone(), capacity, replenish_amount, replenish_period)
+    }
+
+    /// Start a task census, publishing task changes found every `update_period`.
+    pub fn new_task_census<D: DurationTicks>(
+        &self,
+        update_period: D,
+        stack_size: usize,
+        priority: TaskPriority,
+    ) -> Result<TaskCensus, FreeRtosError> {
+        TaskCensus::start(self.clone(), update_period, stack_size, priority)
+    }
+
+    /// Create a state machine pump for up to `capacity` machines. Register the machines,
+    /// then start it.
+    pub fn new_state_machine_pump<E: Copy + Send + 'static>(
+        &self,
+        capacity: usize,
+        inbox_depth: usize,
+        tagged_depth: usize,
+    ) -> StateMachinePump<E> {
+        StateMachinePump::new(self.clone(), capacity, inbox_depth, tagged_depth)
+    }
+
+    /// Create a new configuration distributor holding `initial` as version 0.
+    pub fn new_config_distributor<T: Copy + Send>(&self, initial: T) -> ConfigDistributor<T> {
+        ConfigDistributor::new(self.clone(), initial)
+    }
+
+    /// Create a new progress tracker for a long operation.
+    pub fn new_progress_tracker(&self) -> ProgressTracker {
+        ProgressTracker::new(self.clone())
+    }
+
+    /// Create a new group of tasks restricted to the windows of `window`.
+    pub fn new_window_group(
+        &self,
+        window: TimeWindow,
+        policy: WindowPolicy,
+    ) -> Result<WindowGroup, FreeRtosError> {
+        WindowGroup::new(self.clone(), window, policy)
+    }
+
+    /// Create a new emergency broadcast for up to `capacity` tasks.
+    pub fn new_emergency_broadcast(&self, capacity: usize) -> EmergencyBroadcast {
+        EmergencyBroadcast::new(self.clone(), capacity)
+    }
+
     /// Create a new mutex with the given inner value
     pub fn new_mutex<T>(&self, t: T) -> Result<Mutex<T>, FreeRtosError> {
         Mutex::new(self.clone(), t)
@@ -89,6 +345,25 @@ impl FreeRTOS {
         RecursiveMutex::new(self.clone(), t)
     }
 
+    /// Create a new mutex with the given inner value, raising holders to `ceiling`
+    pub fn new_ceiling_mutex<T>(
+        &self,
+        t: T,
+        ceiling: TaskPriority,
+    ) -> Result<CeilingMutex<T>, FreeRtosError> {
+        CeilingMutex::new(self.clone(), t, ceiling)
+    }
+
+    /// Create a new recursive mutex with the given inner value, raising holders to
+    /// `ceiling`
+    pub fn new_recursive_ceiling_mutex<T>(
+        &self,
+        t: T,
+        ceiling: TaskPriority,
+    ) -> Result<RecursiveCeilingMutex<T>, FreeRtosError> {
+        RecursiveCeilingMutex::new(self.clone(), t, ceiling)
+    }
+
     // Should only be used for testing purpose!
     pub fn invoke_assert() {
         unsafe {
@@ -97,9 +372,70 @@ impl FreeRTOS {
     }
 
     /// Delay the execution of the current task.
+    #[inline]
     pub fn delay<D: DurationTicks>(&self, delay: D) {
-        unsafe {
-            freertos_rs_vTaskDelay(delay.to_ticks());
+        delay_ticks(delay.to_ticks())
+    }
+
+    /// Delay the current task by at least `d`, like `std::thread::sleep`.
+    ///
+    /// `d` is rounded up to whole ticks, plus one for the tick already under way.
+    /// Durations too long for the tick type saturate at `freertos_rs_max_wait()` ticks;
+    /// unlike blocking calls, `vTaskDelay` doesn't treat that as forever, so the task
+    /// wakes up again after that many ticks.
+    pub fn sleep(&self, d: core::time::Duration) {
+        let ticks = sleep_ticks(d.as_nanos());
+        if ticks > 0 {
+            self.delay(Duration::ticks(ticks));
+        }
+    }
+
+    /// Have the timer daemon task run `f` once, waiting up to `max_wait` for room in
+    /// the timer command queue. Needs `INCLUDE_xTimerPendFunctionCall`.
+    pub fn defer_to_daemon<F, D>(&self, f: F, max_wait: D) -> Result<(), FreeRtosError>
+    where
+        F: FnOnce() + Send + 'static,
+        D: DurationTicks,
+    {
+        let max_wait = max_wait.to_ticks();
+        check_blocking("FreeRTOS::defer_to_daemon", ptr::null(), max_wait);
+
+        defer_to_daemon_raw(DeferredCall::new(f), max_wait)
+    }
+
+    /// Run `f` in a critical section. Critical sections nest, and this one is left when
+    /// `f` returns or unwinds.
+    ///
+    /// `f` has to be `ISRSafe` for the same reason as with `suspend_all_tasks`.
+    pub fn critical_section<R, F>(&self, f: F) -> R
+    where
+        F: FnOnce() -> R + ISRSafe,
+    {
+        let _region = CriticalRegion::enter();
+        f()
+    }
+
+    /// Run `f` with the scheduler suspended, so no other task runs in between while
+    /// interrupts stay enabled. The scheduler is resumed when `f` returns.
+    ///
+    /// Blocking with the scheduler suspended is an error, so `f` has to be `ISRSafe`: it
+    /// can't capture the `FreeRTOS` handle or the queues, mutexes and other objects that
+    /// have blocking methods.
+    pub fn suspend_all_tasks<R, F>(&self, f: F) -> R
+    where
+        F: FnOnce() -> R + ISRSafe,
+    {
+        let _suspension = unsafe { SchedulerSuspension::enter() };
+        f()
+    }
+
+    /// Whether the scheduler was started and is running. Needs
+    /// `INCLUDE_xTaskGetSchedulerState` or `configUSE_TIMERS`.
+    pub fn scheduler_state(&self) -> SchedulerState {
+        match unsafe { freertos_rs_xTaskGetSchedulerState() } {
+            0 => SchedulerState::Suspended,
+            1 => SchedulerState::NotStarted,
+            _ => SchedulerState::Running,
         }
     }
 
@@ -111,14 +447,42 @@ impl FreeRTOS {
         Duration::ticks(self.get_tick_count())
     }
 
+    /// The current instant, to measure the time since with `TickInstant::elapsed`.
+    pub fn now(&self) -> TickInstant {
+        TickInstant::from_ticks(self.get_tick_count())
+    }
+
+    /// A handle to the task this is called from, equal to the one it was spawned with.
+    pub fn current_task(&self) -> TaskRemoteHandle {
+        unsafe { TaskRemoteHandle::from_raw(freertos_rs_get_current_task()) }
+    }
+
     pub fn get_number_of_tasks(&self) -> usize {
         unsafe { freertos_rs_get_number_of_tasks() as usize }
     }
 
+    /// The number of task priorities, `configMAX_PRIORITIES`.
+    pub fn get_max_priorities(&self) -> usize {
+        unsafe { freertos_rs_get_max_priorities() as usize }
+    }
+
+    /// The priority of the timer daemon task, `configTIMER_TASK_PRIORITY`, or 0 without
+    /// `configUSE_TIMERS`.
+    pub fn get_timer_task_priority(&self) -> TaskPriority {
+        unsafe { TaskPriority(freertos_rs_get_timer_task_priority() as u8) }
+    }
+
+    /// The statistics of heap_4 or heap_5, `None` with the other heaps. See
+    /// `FreeRtosAllocator::heap_stats`.
+    pub fn heap_stats(&self) -> Option<HeapStats> {
+        FreeRtosAllocator::heap_stats()
+    }
+
     pub fn get_all_tasks(&self, tasks_len: Option<usize>) -> FreeRtosSchedulerState {
         let tasks_len = tasks_len.unwrap_or(self.get_number_of_tasks());
         let mut tasks = Vec::with_capacity(tasks_len as usize);
         let mut total_run_time = 0;
+        let tick_count;
 
         unsafe {
             let filled = freertos_rs_get_system_state(
@@ -126,6 +490,7 @@ impl FreeRTOS {
                 tasks_len as FreeRtosUBaseType,
                 &mut total_run_time,
             );
+            tick_count = freertos_rs_xTaskGetTickCount();
             tasks.set_len(filled as usize);
         }
 
@@ -141,12 +506,26 @@ impl FreeRTOS {
                 base_priority: TaskPriority(t.base_priority as u8),
                 run_time_counter: t.run_time_counter,
                 stack_high_water_mark: t.stack_high_water_mark,
+                #[cfg(feature = "footprint_diag")]
+                closure: crate::footprint::task_closure_footprint(&unsafe {
+                    TaskRemoteHandle::from_raw(t.handle)
+                }),
             })
             .collect();
 
         FreeRtosSchedulerState {
             tasks: tasks,
             total_run_time: total_run_time,
+            tick_count,
         }
     }
 }
+
+/// The body of `FreeRTOS::delay`, shared by all duration types.
+fn delay_ticks(delay: FreeRtosTickType) {
+    check_blocking("FreeRTOS::delay", ptr::null(), delay);
+
+    unsafe {
+        freertos_rs_vTaskDelay(delay);
+    }
+}
@@ -0,0 +1,95 @@
+use crate::base::*;
+#[cfg(feature = "owner_checks")]
+use crate::hooks::*;
+use crate::prelude::v1::*;
+#[cfg(feature = "owner_checks")]
+use crate::shim::*;
+use crate::task::*;
+use core::cell::Cell;
+
+/// A helper used from a task other than the one it is bound to.
+#[cfg(feature = "owner_checks")]
+#[derive(Debug, Copy, Clone)]
+pub struct OwnerViolation {
+    /// The method that was called, like `"TaskDelay::delay_until"`.
+    pub api: &'static str,
+    pub owner: FreeRtosTaskHandle,
+    pub owner_name: TaskName,
+    pub caller: FreeRtosTaskHandle,
+    pub caller_name: TaskName,
+}
+
+/// Binds a helper whose bookkeeping only makes sense in one task to the first task that
+/// uses it.
+///
+/// With the `owner_checks` feature, use from another task is reported through
+/// `FREERTOS_HOOKS` until the helper is rebound. Without it this is zero sized and the
+/// checks compile to nothing; misuse is still memory safe, but timing goes wrong.
+/// Either way the helper becomes `!Sync`.
+#[derive(Debug, Default)]
+pub(crate) struct TaskOwner {
+    #[cfg(feature = "owner_checks")]
+    task: Cell<usize>,
+    #[cfg(not(feature = "owner_checks"))]
+    _not_sync: PhantomData<Cell<()>>,
+}
+
+impl TaskOwner {
+    pub(crate) fn new() -> TaskOwner {
+        TaskOwner::default()
+    }
+
+    /// Bound to `task` from the start, like a consumer registered for a task.
+    pub(crate) fn bound_to(task: FreeRtosTaskHandle) -> TaskOwner {
+        let owner = TaskOwner::new();
+        #[cfg(feature = "owner_checks")]
+        owner.task.set(task as usize);
+        #[cfg(not(feature = "owner_checks"))]
+        let _ = task;
+        owner
+    }
+
+    /// Bind to `this` task, as for a deliberate handoff.
+    #[inline(always)]
+    pub(crate) fn rebind(&mut self, this: &TaskSelfHandle) {
+        #[cfg(feature = "owner_checks")]
+        self.task.set(this.raw_handle() as usize);
+        #[cfg(not(feature = "owner_checks"))]
+        let _ = this;
+    }
+
+    /// Bind to the calling task on first use, and report use from any other task.
+    #[inline(always)]
+    pub(crate) fn check(&self, api: &'static str) {
+        #[cfg(feature = "owner_checks")]
+        {
+            let caller = unsafe { freertos_rs_get_current_task() };
+            let owner = self.task.get();
+            if owner == 0 {
+                self.task.set(caller as usize);
+            } else if owner != caller as usize {
+                report(api, owner as FreeRtosTaskHandle, caller);
+            }
+        }
+        #[cfg(not(feature = "owner_checks"))]
+        let _ = api;
+    }
+}
+
+#[cfg(feature = "owner_checks")]
+#[cold]
+fn report(api: &'static str, owner: FreeRtosTaskHandle, caller: FreeRtosTaskHandle) {
+    let name =
+        |task| TaskName::from_bytes(unsafe { TaskRemoteHandle::from_raw(task) }.get_name_bytes());
+    let violation = OwnerViolation {
+        api,
+        owner,
+        owner_name: name(owner),
+        caller,
+        caller_name: name(caller),
+    };
+
+    unsafe {
+        (*ptr::addr_of!(FREERTOS_HOOKS)).do_on_owner_violation(&violation);
+    }
+}
@@ -0,0 +1,328 @@
+use crate::base::*;
+use crate::critical::*;
+use crate::infra::*;
+use crate::isr::*;
+use crate::mutex::*;
+use crate::operating_system::*;
+use crate::prelude::v1::*;
+use crate::semaphore::*;
+use crate::shim::*;
+use crate::sync::atomic::{AtomicBool, AtomicU32, Ordering};
+use crate::task::*;
+use crate::ticks::*;
+use crate::units::*;
+
+impl<B: PersistBackend> !ISRSafe for Persistence<B> {}
+impl<T: Copy> !ISRSafe for PersistedCell<T> {}
+
+/// Storage for persisted cells, implemented by the application over its flash or EEPROM driver.
+///
+/// Every cell is stored under two keys, written alternately, so the backend only has to
+/// store whole values per key. A write torn by a power loss is detected on load and the
+/// other, older copy is used.
+pub trait PersistBackend: Send + Sync + 'static {
+    type Error;
+
+    /// Store `bytes` under `key`, replacing what was there.
+    fn save(&mut self, key: &str, bytes: &[u8]) -> Result<(), Self::Error>;
+
+    /// Read the value stored under `key` into `buf`, returning how many bytes were read.
+    fn load(&mut self, key: &str, buf: &mut [u8]) -> Result<usize, Self::Error>;
+}
+
+const SEQUENCE_LEN: usize = 4;
+const CHECKSUM_LEN: usize = 4;
+
+fn checksum(bytes: &[u8]) -> u32 {
+    // FNV-1a
+    bytes.iter().fold(0x811c_9dc5, |hash: u32, byte| {
+        (hash ^ *byte as u32).wrapping_mul(0x0100_0193)
+    })
+}
+
+trait PersistSlot: Send + Sync {
+    /// Write the value to the backend if it is dirty and its minimum interval has passed,
+    /// or unconditionally when `force` is set. Returns false if the backend failed.
+    fn persist(
+        &self,
+        os: &FreeRTOS,
+        save: &mut dyn FnMut(&str, &[u8]) -> bool,
+        now: FreeRtosTickType,
+        force: bool,
+    ) -> bool;
+}
+
+struct CellState<T: Copy> {
+    keys: [String; 2],
+    value: ExclusiveData<T>,
+    dirty: AtomicBool,
+    sequence: AtomicU32,
+    last_persist: AtomicU32,
+    min_interval: FreeRtosTickType,
+}
+
+impl<T: Copy> CellState<T> {
+    const RECORD_LEN: usize = SEQUENCE_LEN + mem::size_of::<T>() + CHECKSUM_LEN;
+
+    fn encode(sequence: u32, value: &T) -> Vec<u8> {
+        let mut record = Vec::with_capacity(Self::RECORD_LEN);
+        record.extend_from_slice(&sequence.to_le_bytes());
+        record.extend_from_slice(unsafe {
+            core::slice::from_raw_parts(value as *const T as *const u8, mem::size_of::<T>())
+        });
+        let sum = checksum(&record);
+        record.extend_from_slice(&sum.to_le_bytes());
+        record
+    }
+
+    fn decode(record: &[u8]) -> Option<(u32, T)> {
+        if record.len() != Self::RECORD_LEN {
+            return None;
+        }
+
+        let (body, sum) = record.split_at(Self::RECORD_LEN - CHECKSUM_LEN);
+        let mut sum_bytes = [0; CHECKSUM_LEN];
+        sum_bytes.copy_from_slice(sum);
+        if checksum(body) != u32::from_le_bytes(sum_bytes) {
+            return None;
+        }
+
+        let mut sequence = [0; SEQUENCE_LEN];
+        sequence.copy_from_slice(&body[..SEQUENCE_LEN]);
+        let value = unsafe { ptr::read_unaligned(body[SEQUENCE_LEN..].as_ptr() as *const T) };
+
+        Some((u32::from_le_bytes(sequence), value))
+    }
+}
+
+impl<T: Copy + Send + Sync> PersistSlot for CellState<T> {
+    fn persist(
+        &self,
+        os: &FreeRTOS,
+        save: &mut dyn FnMut(&str, &[u8]) -> bool,
+        now: FreeRtosTickType,
+        force: bool,
+    ) -> bool {
+        if !self.dirty.load(Ordering::Acquire) {
+            return true;
+        }
+
+        let since_last = tick_elapsed(now, self.last_persist.load(Ordering::Relaxed));
+        if !force && since_last < self.min_interval {
+            return true;
+        }
+
+        // Clear before copying, so a set() racing with the write marks the cell dirty again.
+        self.dirty.store(false, Ordering::Release);
+        let value = match self.value.lock(os) {
+            Ok(value) => *value,
+            Err(_) => return true,
+        };
+
+        let sequence = self.sequence.load(Ordering::Relaxed).wrapping_add(1);
+        let key = &self.keys[(sequence & 1) as usize];
+
+        if save(key, &Self::encode(sequence, &value)) {
+            self.sequence.store(sequence, Ordering::Relaxed);
+            self.last_persist.store(now, Ordering::Relaxed);
+            true
+        } else {
+            self.dirty.store(true, Ordering::Release);
+            false
+        }
+    }
+}
+
+/// A value that is written to the persistence backend in the background after it changes.
+pub struct PersistedCell<T: Copy> {
+    state: Arc<CellState<T>>,
+}
+
+impl<T: Copy> PersistedCell<T> {
+    pub fn get(&self, os: &FreeRTOS) -> Result<T, FreeRtosError> {
+        Ok(*self.state.value.lock(os)?)
+    }
+
+    /// Change the value and mark the cell for persisting. Several sets between two
+    /// persistence passes result in a single write.
+    pub fn set(&self, os: &FreeRTOS, value: T) -> Result<(), FreeRtosError> {
+        *self.state.value.lock(os)? = value;
+        self.state.dirty.store(true, Ordering::Release);
+        Ok(())
+    }
+
+    /// Has the value changed since it was last written to the backend?
+    pub fn is_dirty(&self) -> bool {
+        self.state.dirty.load(Ordering::Acquire)
+    }
+}
+
+struct PersistenceInner<B: PersistBackend> {
+    backend: Mutex<B>,
+    cells: ExclusiveData<Vec<Arc<dyn PersistSlot>>>,
+    flushed: BinarySemaphore,
+    write_errors: AtomicU32,
+}
+
+impl<B: PersistBackend> PersistenceInner<B> {
+    fn persist_all(&self, os: &FreeRTOS, force: bool) {
+        let cells = match self.cells.lock(os) {
+            Ok(cells) => cells.clone(),
+            Err(_) => return,
+        };
+
+        let mut backend = match self.backend.lock(Duration::infinite()) {
+            Ok(backend) => backend,
+            Err(_) => return,
+        };
+
+        let now = os.get_tick_count();
+        let mut save = |key: &str, bytes: &[u8]| backend.save(key, bytes).is_ok();
+        for cell in cells.iter() {
+            if !cell.persist(os, &mut save, now, force) {
+                self.write_errors.fetch_add(1, Ordering::Relaxed);
+            }
+        }
+    }
+}
+
+/// Runs a low priority task that writes changed `PersistedCell`s to a `PersistBackend`.
+///
+/// Dirty cells are written every `cadence`, but no more often than each cell's minimum
+/// interval, to protect flash endurance. `flush_now` writes every dirty cell immediately,
+/// regardless of the minimum interval.
+pub struct Persistence<B: PersistBackend> {
+    inner: Arc<PersistenceInner<B>>,
+    task: InfraTask,
+}
+
+impl<B: PersistBackend> Persistence<B> {
+    /// Create the persistence task. Can be called before the scheduler is started.
+    pub fn new<D: DurationTicks>(
+        os: FreeRTOS,
+        backend: B,
+        cadence: D,
+        stack_size: usize,
+        priority: TaskPriority,
+    ) -> Result<Persistence<B>, FreeRtosError> {
+        let inner = Arc::new(PersistenceInner {
+            backend: Mutex::new(os, backend)?,
+            cells: ExclusiveData::new(Vec::new()),
+            flushed: BinarySemaphore::new(os)?,
+            write_errors: AtomicU32::new(0),
+        });
+
+        let task = {
+            let inner = inner.clone();
+            let cadence = Duration::ticks(cadence.to_ticks());
+
+            // A stop request ends the wait like a flush does, so dirty cells are
+            // written before the task stops.
+            InfraTask::spawn(os, "persist", stack_size, priority, move |ctx, os| {
+                while !ctx.should_stop() {
+                    let forced = ctx.wait_for_notification(0, u32::MAX, cadence).is_ok();
+                    inner.persist_all(&os, forced);
+                    if forced {
+                        inner.flushed.give();
+                    }
+                }
+            })?
+        };
+
+        Ok(Persistence { inner, task })
+    }
+
+    /// Register a cell stored under `name`, loading its last persisted value from the
+    /// backend, or using `default` if there is no intact copy.
+    ///
+    /// Call this before the scheduler is started to have values in place before any
+    /// task runs. `T` should be plain data, as its bytes are stored as they are.
+    pub fn register<T, D>(
+        &self,
+        os: &FreeRTOS,
+        name: &str,
+        default: T,
+        min_interval: D,
+    ) -> Result<PersistedCell<T>, FreeRtosError>
+    where
+        T: Copy + Send + Sync + 'static,
+        D: DurationTicks,
+    {
+        let keys = [format!("{}.0", name), format!("{}.1", name)];
+
+        let mut newest: Option<(u32, T)> = None;
+        {
+            let mut backend = self.inner.backend.lock(Duration::infinite())?;
+            let mut buf = vec![0; CellState::<T>::RECORD_LEN];
+            for key in keys.iter() {
+                let copy = match backend.load(key, &mut buf) {
+                    Ok(len) => CellState::<T>::decode(&buf[..len.min(buf.len())]),
+                    Err(_) => None,
+                };
+
+                newest = match (newest, copy) {
+                    (Some(a), Some(b)) if (b.0.wrapping_sub(a.0) as i32) > 0 => Some(b),
+                    (None, b) => b,
+                    (a, _) => a,
+                };
+            }
+        }
+
+        let (sequence, value) = newest.unwrap_or((0, default));
+        let state = Arc::new(CellState {
+            keys,
+            value: ExclusiveData::new(value),
+            dirty: AtomicBool::new(false),
+            sequence: AtomicU32::new(sequence),
+            last_persist: AtomicU32::new(os.get_tick_count().wrapping_sub(min_interval.to_ticks())),
+            min_interval: min_interval.to_ticks(),
+        });
+
+        self.inner.cells.lock(os)?.push(state.clone());
+
+        Ok(PersistedCell { state })
+    }
+
+    /// Write every dirty cell now and wait up to `timeout` for the writes to finish.
+    ///
+    /// Values set after this returns are written in a later pass.
+    pub fn flush_now<D: DurationTicks>(&self, timeout: D) -> Result<(), FreeRtosError> {
+        // Drop a completion left over from a flush requested by an interrupt.
+        let _ = self.inner.flushed.take(Duration::zero());
+
+        self.task.notify(TaskNotification::SetBits(1));
+        self.inner.flushed.take(timeout)
+    }
+
+    /// The task writing the cells.
+    pub fn task(&self) -> &InfraTask {
+        &self.task
+    }
+
+    /// How many cell writes the backend has rejected. Rejected cells stay dirty and are retried.
+    pub fn write_errors(&self) -> u32 {
+        self.inner.write_errors.load(Ordering::Relaxed)
+    }
+}
+
+/// An ISR safe handle that can ask the persistence task to flush, e.g. on brown-out.
+pub struct PersistenceISRHandle {
+    task: FreeRtosTaskHandle,
+}
+
+impl<B: PersistBackend> ISRSafeHandle<PersistenceISRHandle> for Persistence<B> {
+    unsafe fn new_isr_safe_handle(&self) -> PersistenceISRHandle {
+        PersistenceISRHandle {
+            task: self.task.raw_handle(),
+        }
+    }
+}
+
+impl PersistenceISRHandle {
+    /// Wake the persistence task to write every dirty cell.
+    pub fn request_flush(&self, context: &mut InterruptContext) {
+        unsafe {
+            freertos_rs_task_notify_isr(self.task, 1, 1, context.get_task_field_mut());
+        }
+    }
+}
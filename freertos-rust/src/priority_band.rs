@@ -0,0 +1,184 @@
+use crate::operating_system::*;
+use crate::prelude::v1::*;
+use crate::shim::*;
+use crate::task::*;
+
+/// Named task priority levels, mapped to numbers by the installed `PriorityMap`.
+#[derive(Debug, Copy, Clone, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub enum PriorityBand {
+    /// The idle task's priority, 0.
+    Idle,
+    Low,
+    Normal,
+    High,
+    Critical,
+    /// Just below the timer daemon, so timer callbacks and deferred calls still
+    /// preempt these tasks.
+    TimerDaemonAdjacent,
+}
+
+impl PriorityBand {
+    pub const ALL: [PriorityBand; 6] = [
+        PriorityBand::Idle,
+        PriorityBand::Low,
+        PriorityBand::Normal,
+        PriorityBand::High,
+        PriorityBand::Critical,
+        PriorityBand::TimerDaemonAdjacent,
+    ];
+
+    pub fn name(&self) -> &'static str {
+        match self {
+            PriorityBand::Idle => "Idle",
+            PriorityBand::Low => "Low",
+            PriorityBand::Normal => "Normal",
+            PriorityBand::High => "High",
+            PriorityBand::Critical => "Critical",
+            PriorityBand::TimerDaemonAdjacent => "TimerAdj",
+        }
+    }
+}
+
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum PriorityMapError {
+    /// The band's priority isn't below `configMAX_PRIORITIES`.
+    ExceedsMaxPriorities {
+        band: PriorityBand,
+        priority: TaskPriority,
+        max_priorities: u8,
+    },
+    /// The band's priority is lower than the band below it.
+    NotAscending { band: PriorityBand },
+    /// The idle band must map to the idle task's priority, 0.
+    IdleNotZero,
+    /// Only the idle band may use the idle task's priority.
+    NotAboveIdle { band: PriorityBand },
+}
+
+/// The priority of each `PriorityBand`.
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub struct PriorityMap {
+    levels: [u8; 6],
+}
+
+static mut PRIORITY_MAP: Option<PriorityMap> = None;
+
+impl PriorityMap {
+    /// Spread the bands over the priorities below the timer daemon, or over every
+    /// priority without timers.
+    pub fn from_config(os: &FreeRTOS) -> PriorityMap {
+        let max = os.get_max_priorities().max(1).min(u8::MAX as usize) as u8;
+        let daemon = os.get_timer_task_priority().0;
+
+        let adjacent = if daemon >= 2 {
+            daemon - 1
+        } else if daemon == 1 {
+            1
+        } else {
+            max.saturating_sub(1).max(1)
+        };
+        let critical = adjacent.saturating_sub(1).max(1);
+        let span = critical - 1;
+
+        PriorityMap {
+            levels: [0, 1, 1 + span / 3, 1 + span * 2 / 3, critical, adjacent],
+        }
+    }
+
+    /// Set a band's priority.
+    pub fn with(mut self, band: PriorityBand, priority: TaskPriority) -> PriorityMap {
+        self.levels[band as usize] = priority.0;
+        self
+    }
+
+    pub fn priority(&self, band: PriorityBand) -> TaskPriority {
+        TaskPriority(self.levels[band as usize])
+    }
+
+    /// The band mapped to exactly `priority`. If several bands share the priority,
+    /// the highest is returned.
+    pub fn band(&self, priority: TaskPriority) -> Option<PriorityBand> {
+        PriorityBand::ALL
+            .iter()
+            .rev()
+            .find(|b| self.levels[**b as usize] == priority.0)
+            .copied()
+    }
+
+    /// Check that every band is below `configMAX_PRIORITIES` and that the bands are in
+    /// ascending order.
+    pub fn validate(&self, os: &FreeRTOS) -> Result<(), PriorityMapError> {
+        let max_priorities = os.get_max_priorities().min(u8::MAX as usize) as u8;
+
+        if self.levels[PriorityBand::Idle as usize] != 0 {
+            return Err(PriorityMapError::IdleNotZero);
+        }
+
+        for (index, band) in PriorityBand::ALL.iter().enumerate() {
+            let priority = self.levels[index];
+            if priority >= max_priorities {
+                return Err(PriorityMapError::ExceedsMaxPriorities {
+                    band: *band,
+                    priority: TaskPriority(priority),
+                    max_priorities,
+                });
+            }
+            if index > 0 && priority < self.levels[index - 1] {
+                return Err(PriorityMapError::NotAscending { band: *band });
+            }
+            if index > 0 && priority == 0 {
+                return Err(PriorityMapError::NotAboveIdle { band: *band });
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Validate the map and use it for every band conversion from now on.
+    /// Call this once, before the scheduler is started.
+    pub fn install(self, os: &FreeRTOS) -> Result<(), PriorityMapError> {
+        self.validate(os)?;
+        unsafe {
+            PRIORITY_MAP = Some(self);
+        }
+        Ok(())
+    }
+
+    /// The installed map, or the map derived from the configuration if none was installed.
+    pub fn current() -> PriorityMap {
+        match unsafe { PRIORITY_MAP } {
+            Some(map) => map,
+            None => PriorityMap::from_config(&unsafe { FreeRTOS::assume_init() }),
+        }
+    }
+}
+
+impl TaskPriority {
+    pub fn from_band(band: PriorityBand) -> TaskPriority {
+        PriorityMap::current().priority(band)
+    }
+
+    /// The band mapped to exactly this priority.
+    pub fn band(&self) -> Option<PriorityBand> {
+        PriorityMap::current().band(*self)
+    }
+
+    /// Raise the priority by `n`, at most to `configMAX_PRIORITIES - 1`.
+    pub fn saturating_add(self, n: u8) -> TaskPriority {
+        let highest = unsafe { freertos_rs_get_max_priorities() }
+            .saturating_sub(1)
+            .min(u8::MAX as _) as u8;
+        TaskPriority(self.0.saturating_add(n).min(highest))
+    }
+
+    /// Lower the priority by `n`, at least to 1, above the idle task.
+    pub fn saturating_sub(self, n: u8) -> TaskPriority {
+        TaskPriority(self.0.saturating_sub(n).max(1))
+    }
+}
+
+impl From<PriorityBand> for TaskPriority {
+    fn from(band: PriorityBand) -> TaskPriority {
+        TaskPriority::from_band(band)
+    }
+}
@@ -0,0 +1,298 @@
+use crate::base::*;
+use crate::operating_system::*;
+use crate::prelude::v1::*;
+use crate::task::*;
+use crate::units::*;
+
+/// The timing requirements a task declares for priority planning, in ticks.
+#[derive(Debug, Copy, Clone)]
+pub struct TaskTiming {
+    pub name: &'static str,
+    /// The task's period, or the minimum time between two of its releases.
+    pub period: FreeRtosTickType,
+    /// How long after its release the task must have finished.
+    pub deadline: FreeRtosTickType,
+    /// An estimate of the task's worst case execution time.
+    pub wcet: FreeRtosTickType,
+}
+
+impl TaskTiming {
+    pub fn new<D: DurationTicks>(
+        name: &'static str,
+        period: D,
+        deadline: D,
+        wcet: D,
+    ) -> TaskTiming {
+        TaskTiming {
+            name,
+            period: period.to_ticks(),
+            deadline: deadline.to_ticks(),
+            wcet: wcet.to_ticks(),
+        }
+    }
+
+    /// A task whose deadline is the end of its period.
+    pub fn implicit<D: DurationTicks>(name: &'static str, period: D, wcet: D) -> TaskTiming {
+        let period = period.to_ticks();
+        TaskTiming {
+            name,
+            period,
+            deadline: period,
+            wcet: wcet.to_ticks(),
+        }
+    }
+}
+
+/// The planned priority of one declared task.
+#[derive(Debug, Copy, Clone)]
+pub struct PlannedTask {
+    pub priority: TaskPriority,
+    /// The worst case response time, or `None` if it exceeds the deadline.
+    pub response_time: Option<FreeRtosTickType>,
+    /// Position in deadline order, 0 for the shortest deadline.
+    rank: usize,
+    /// Shares a priority with the next task in deadline order.
+    grouped_with_next: bool,
+}
+
+impl Default for PlannedTask {
+    fn default() -> Self {
+        PlannedTask {
+            priority: TaskPriority(0),
+            response_time: None,
+            rank: 0,
+            grouped_with_next: false,
+        }
+    }
+}
+
+impl PlannedTask {
+    pub fn is_feasible(&self) -> bool {
+        self.response_time.is_some()
+    }
+}
+
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum PlanError {
+    /// The output array is shorter than the declared tasks.
+    OutputTooSmall,
+    /// The priority band has no levels.
+    NoPriorities,
+    /// The task at this index has a zero period, deadline or execution time, or a
+    /// deadline longer than its period.
+    InvalidTiming { index: usize },
+}
+
+/// Priorities assigned to declared tasks, in the order they were declared.
+pub struct AssignedPriorities<'a> {
+    declared: &'a [TaskTiming],
+    tasks: &'a [PlannedTask],
+}
+
+impl<'a> AssignedPriorities<'a> {
+    /// Does every task meet its deadline?
+    pub fn is_feasible(&self) -> bool {
+        self.tasks.iter().all(|t| t.is_feasible())
+    }
+
+    pub fn tasks(&self) -> &'a [PlannedTask] {
+        self.tasks
+    }
+
+    /// The priority planned for the task declared with `name`, to pass when spawning it.
+    pub fn priority_of(&self, name: &str) -> Option<TaskPriority> {
+        self.declared
+            .iter()
+            .position(|t| t.name == name)
+            .map(|index| self.tasks[index].priority)
+    }
+}
+
+/// Writes the plan as a table, one line per task in priority order.
+#[cfg(feature = "fmt")]
+impl<'a> fmt::Display for AssignedPriorities<'a> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        writeln!(
+            f,
+            "{:<16} {:>4} {:>8} {:>8} {:>8} {:>8}",
+            "task", "prio", "period", "deadline", "wcet", "response"
+        )?;
+        for rank in 0..self.tasks.len() {
+            let index = match self.tasks.iter().position(|t| t.rank == rank) {
+                Some(index) => index,
+                None => continue,
+            };
+            let (timing, planned) = (&self.declared[index], &self.tasks[index]);
+            write!(
+                f,
+                "{:<16} {:>4} {:>8} {:>8} {:>8} ",
+                timing.name, planned.priority.0, timing.period, timing.deadline, timing.wcet
+            )?;
+            match planned.response_time {
+                Some(response) => writeln!(f, "{:>8}", response)?,
+                None => writeln!(f, "{:>8}", "MISSED")?,
+            }
+        }
+        write!(
+            f,
+            "{}",
+            if self.is_feasible() {
+                "schedulable"
+            } else {
+                "not schedulable"
+            }
+        )
+    }
+}
+
+/// Assigns task priorities by deadline, shortest deadline first, and checks the result
+/// with a response time analysis.
+///
+/// When there are more tasks than priority levels, tasks with the most similar deadlines
+/// share a level. Tasks sharing a level are time sliced by the kernel, so the analysis
+/// counts each of them as interference for the others.
+#[derive(Debug, Copy, Clone)]
+pub struct PriorityPlan {
+    lowest: u8,
+    highest: u8,
+}
+
+impl PriorityPlan {
+    /// Plan with every priority above the idle task, up to `configMAX_PRIORITIES - 1`.
+    pub fn new(os: FreeRTOS) -> PriorityPlan {
+        let highest = os
+            .get_max_priorities()
+            .saturating_sub(1)
+            .min(u8::MAX as usize) as u8;
+        PriorityPlan { lowest: 1, highest }
+    }
+
+    /// Plan within the priorities `lowest..=highest`, e.g. to leave room for other tasks.
+    pub fn with_band(lowest: TaskPriority, highest: TaskPriority) -> PriorityPlan {
+        PriorityPlan {
+            lowest: lowest.0,
+            highest: highest.0,
+        }
+    }
+
+    /// Plan priorities for the `declared` tasks, writing the result for each to the same
+    /// index of `out`.
+    pub fn compute<'a>(
+        &self,
+        declared: &'a [TaskTiming],
+        out: &'a mut [PlannedTask],
+    ) -> Result<AssignedPriorities<'a>, PlanError> {
+        if out.len() < declared.len() {
+            return Err(PlanError::OutputTooSmall);
+        }
+        if self.highest < self.lowest {
+            return Err(PlanError::NoPriorities);
+        }
+        for (index, t) in declared.iter().enumerate() {
+            if t.period == 0 || t.deadline == 0 || t.wcet == 0 || t.deadline > t.period {
+                return Err(PlanError::InvalidTiming { index });
+            }
+        }
+
+        let count = declared.len();
+        let out = &mut out[..count];
+
+        // Deadline order, ties broken by declaration order.
+        for i in 0..count {
+            let rank = declared
+                .iter()
+                .enumerate()
+                .filter(|(j, t)| {
+                    t.deadline < declared[i].deadline
+                        || (t.deadline == declared[i].deadline && *j < i)
+                })
+                .count();
+            out[i] = PlannedTask {
+                rank,
+                ..PlannedTask::default()
+            };
+        }
+
+        let by_rank = |out: &[PlannedTask], rank: usize| -> usize {
+            out.iter().position(|t| t.rank == rank).unwrap_or(0)
+        };
+
+        // Merge the neighbours whose deadline ratio is closest to one until the tasks fit.
+        let levels = (self.highest - self.lowest) as usize + 1;
+        for _ in levels..count {
+            let mut best: Option<(usize, FreeRtosTickType, FreeRtosTickType)> = None;
+            for rank in 0..count - 1 {
+                let index = by_rank(out, rank);
+                if out[index].grouped_with_next {
+                    continue;
+                }
+                let shorter = declared[index].deadline;
+                let longer = declared[by_rank(out, rank + 1)].deadline;
+                let closer = match best {
+                    // shorter / longer > best_shorter / best_longer
+                    Some((_, s, l)) => (shorter as u64) * (l as u64) > (s as u64) * (longer as u64),
+                    None => true,
+                };
+                if closer {
+                    best = Some((index, shorter, longer));
+                }
+            }
+            if let Some((index, _, _)) = best {
+                out[index].grouped_with_next = true;
+            }
+        }
+
+        let mut priority = self.highest;
+        for rank in 0..count {
+            let index = by_rank(out, rank);
+            out[index].priority = TaskPriority(priority);
+            if !out[index].grouped_with_next {
+                priority = priority.saturating_sub(1);
+            }
+        }
+
+        for i in 0..count {
+            out[i].response_time = response_time(declared, out, i);
+        }
+
+        Ok(AssignedPriorities {
+            declared,
+            tasks: out,
+        })
+    }
+}
+
+/// The fixed point of `R = C + sum(ceil(R / T_j) * C_j)` over the tasks that can preempt
+/// or share a level with task `i`, or `None` once it passes the deadline.
+fn response_time(
+    declared: &[TaskTiming],
+    planned: &[PlannedTask],
+    i: usize,
+) -> Option<FreeRtosTickType> {
+    let own = &declared[i];
+    let deadline = own.deadline as u64;
+    let mut response = own.wcet as u64;
+
+    loop {
+        if response > deadline {
+            return None;
+        }
+
+        let interference: u64 = declared
+            .iter()
+            .zip(planned.iter())
+            .enumerate()
+            .filter(|(j, (_, p))| *j != i && p.priority.0 >= planned[i].priority.0)
+            .map(|(_, (t, _))| {
+                let releases = response.div_ceil(t.period as u64);
+                releases * t.wcet as u64
+            })
+            .sum();
+
+        let next = own.wcet as u64 + interference;
+        if next == response {
+            return Some(response as FreeRtosTickType);
+        }
+        response = next;
+    }
+}
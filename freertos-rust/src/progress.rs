@@ -0,0 +1,341 @@
+use crate::base::*;
+use crate::critical::*;
+use crate::isr::*;
+use crate::operating_system::*;
+use crate::prelude::v1::*;
+use crate::semaphore::*;
+use crate::shim::*;
+use crate::stats::*;
+use crate::sync::atomic::{AtomicBool, AtomicU32, Ordering};
+use crate::ticks::*;
+use crate::units::*;
+
+impl !ISRSafe for ProgressTracker {}
+impl !ISRSafe for ProgressReporter {}
+impl !ISRSafe for ProgressWatcher {}
+
+/// Smoothing of the rate, each snapshot moves it a quarter of the way to the latest.
+const RATE_ALPHA: (u32, u32) = (1, 4);
+
+/// Where a long operation is, as seen by `ProgressWatcher::snapshot`.
+#[derive(Debug, Copy, Clone)]
+pub struct Progress {
+    pub phase: &'static str,
+    pub done: u32,
+    /// 0 until the reporter calls `set_total`.
+    pub total: u32,
+    /// Units per second, averaged over the snapshots taken so far.
+    pub rate: u32,
+    /// Time left at the current rate. `None` while the total or the rate is unknown.
+    pub eta: Option<Duration>,
+    pub cancelled: bool,
+}
+
+impl Progress {
+    /// Whole percent done, 0 while the total is unknown.
+    pub fn percent(&self) -> u32 {
+        percent(self.done, self.total)
+    }
+}
+
+fn percent(done: u32, total: u32) -> u32 {
+    if total == 0 {
+        0
+    } else {
+        (done as u64 * 100 / total as u64).min(100) as u32
+    }
+}
+
+/// A fixed-width line for consoles, e.g.
+/// `write      [#########...........]  45%       450/1000     120/s      4.5s`.
+/// The phase is cut to 10 characters.
+#[cfg(feature = "fmt")]
+impl fmt::Display for Progress {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        const BAR: u32 = 20;
+
+        let phase = match self.phase.char_indices().nth(10) {
+            Some((end, _)) => &self.phase[..end],
+            None => self.phase,
+        };
+        write!(f, "{:<10} [", phase)?;
+        let filled = self.percent() * BAR / 100;
+        for i in 0..BAR {
+            f.write_str(if i < filled { "#" } else { "." })?;
+        }
+        write!(
+            f,
+            "] {:>3}% {:>10}/{:<10} {:>6}/s ",
+            self.percent(),
+            self.done,
+            self.total,
+            self.rate
+        )?;
+
+        match self.eta {
+            _ if self.cancelled => write!(f, "{:>8}", "cancel"),
+            Some(eta) => write!(f, "{}", eta.display_compact()),
+            None => write!(f, "{:>8}", "--"),
+        }
+    }
+}
+
+struct RateState {
+    tick: FreeRtosTickType,
+    done: u32,
+    ewma: Ewma,
+}
+
+struct ProgressState {
+    done: AtomicU32,
+    total: AtomicU32,
+    cancelled: AtomicBool,
+    reporting: AtomicBool,
+    /// Bumped on every change watchers are woken up for.
+    version: AtomicU32,
+    /// Only accessed in critical sections.
+    phase: UnsafeCell<&'static str>,
+    rate: UnsafeCell<RateState>,
+    watchers: ExclusiveData<Vec<Arc<BinarySemaphore>>>,
+}
+
+unsafe impl Send for ProgressState {}
+unsafe impl Sync for ProgressState {}
+
+impl ProgressState {
+    fn changed(&self) {
+        self.version.fetch_add(1, Ordering::Release);
+
+        if let Ok(watchers) = self.watchers.lock(&FreeRTOS {}) {
+            for watcher in watchers.iter() {
+                watcher.give();
+            }
+        }
+    }
+
+    fn restart_rate(&self) {
+        let _lock = CriticalRegion::enter();
+        let rate = unsafe { &mut *self.rate.get() };
+        rate.tick = unsafe { freertos_rs_xTaskGetTickCount() };
+        rate.done = self.done.load(Ordering::Relaxed);
+        rate.ewma.reset();
+    }
+
+    fn snapshot(&self) -> Progress {
+        let _lock = CriticalRegion::enter();
+        let now = unsafe { freertos_rs_xTaskGetTickCount() };
+        let done = self.done.load(Ordering::Relaxed);
+        let total = self.total.load(Ordering::Relaxed);
+        let rate = unsafe { &mut *self.rate.get() };
+
+        let elapsed_ms = Duration::ticks(tick_elapsed(now, rate.tick)).to_ms();
+        if elapsed_ms > 0 {
+            let units = done.wrapping_sub(rate.done) as u64;
+            rate.ewma.record(units * 1000 / elapsed_ms as u64);
+            rate.tick = now;
+            rate.done = done;
+        }
+
+        // Units per 1000 seconds, so slow operations still get an ETA.
+        let rate_milli = (rate.ewma.value_fixed() * 1000) >> STATS_FRACTION_BITS;
+        let eta = if total == 0 || rate_milli == 0 {
+            None
+        } else {
+            let left = total.saturating_sub(done) as u64;
+            let ms = (left * 1_000_000 / rate_milli).min(u32::MAX as u64) as u32;
+            Some(Duration::ms(ms))
+        };
+
+        Progress {
+            phase: unsafe { *self.phase.get() },
+            done,
+            total,
+            rate: rate.ewma.value().min(u32::MAX as u64) as u32,
+            eta,
+            cancelled: self.cancelled.load(Ordering::Relaxed),
+        }
+    }
+}
+
+/// Progress of a long operation, like a firmware update or a calibration, shared between
+/// the task doing it and the tasks showing it.
+///
+/// The worker reports through the one `ProgressReporter`, which is cheap enough to call
+/// for every block written: `advance` is an atomic add, and only wakes up the watchers
+/// when the whole percentage changes. Any number of `ProgressWatcher`s read snapshots,
+/// wait for changes and can cancel the operation. The rate and ETA are computed when a
+/// snapshot is taken, as an `Ewma` of the rate seen since the snapshot before.
+///
+/// ```rust
+/// # use freertos_rs::*;
+/// let tracker = os.new_progress_tracker();
+/// let mut watcher = tracker.watcher()?;
+///
+/// let reporter = tracker.reporter().unwrap();
+/// os.new_task("update", 512, TaskPriority(1), move |_, _| {
+///     reporter.set_phase("write");
+///     reporter.set_total(image_len);
+///     for block in image.chunks(256) {
+///         write_block(block);
+///         if reporter.advance(block.len() as u32) {
+///             break;
+///         }
+///     }
+///     # loop {}
+/// })?;
+///
+/// while let Ok(progress) = watcher.wait_change(Duration::ms(1000)) {
+///     println!("{}", progress);
+/// }
+/// ```
+#[derive(Clone)]
+pub struct ProgressTracker {
+    state: Arc<ProgressState>,
+}
+
+impl ProgressTracker {
+    pub fn new(_os: FreeRTOS) -> ProgressTracker {
+        ProgressTracker {
+            state: Arc::new(ProgressState {
+                done: AtomicU32::new(0),
+                total: AtomicU32::new(0),
+                cancelled: AtomicBool::new(false),
+                reporting: AtomicBool::new(false),
+                version: AtomicU32::new(0),
+                phase: UnsafeCell::new(""),
+                rate: UnsafeCell::new(RateState {
+                    tick: 0,
+                    done: 0,
+                    ewma: Ewma::new(RATE_ALPHA.0, RATE_ALPHA.1),
+                }),
+                watchers: ExclusiveData::new(Vec::new()),
+            }),
+        }
+    }
+
+    /// The reporter for the worker. `None` while another one exists.
+    pub fn reporter(&self) -> Option<ProgressReporter> {
+        if self.state.reporting.swap(true, Ordering::Acquire) {
+            return None;
+        }
+        self.state.restart_rate();
+
+        Some(ProgressReporter {
+            state: self.state.clone(),
+        })
+    }
+
+    pub fn watcher(&self) -> Result<ProgressWatcher, FreeRtosError> {
+        let wake = Arc::new(BinarySemaphore::new(FreeRTOS {})?);
+        self.state.watchers.lock(&FreeRTOS {})?.push(wake.clone());
+
+        Ok(ProgressWatcher {
+            state: self.state.clone(),
+            wake,
+            seen: self.state.version.load(Ordering::Acquire),
+        })
+    }
+
+    pub fn snapshot(&self) -> Progress {
+        self.state.snapshot()
+    }
+
+    pub fn cancel(&self) {
+        self.state.cancelled.store(true, Ordering::Relaxed);
+        self.state.changed();
+    }
+}
+
+/// The worker's side of a `ProgressTracker`, see `ProgressTracker::reporter`.
+pub struct ProgressReporter {
+    state: Arc<ProgressState>,
+}
+
+impl ProgressReporter {
+    pub fn set_total(&self, units: u32) {
+        self.state.total.store(units, Ordering::Relaxed);
+        self.state.changed();
+    }
+
+    /// Start a new phase, counting from 0 again. The rate starts over as well, as the
+    /// units of a phase may take a different time than the ones before.
+    pub fn set_phase(&self, phase: &'static str) {
+        {
+            let _lock = CriticalRegion::enter();
+            unsafe { *self.state.phase.get() = phase };
+            self.state.done.store(0, Ordering::Relaxed);
+        }
+        self.state.restart_rate();
+        self.state.changed();
+    }
+
+    /// Count `units` more as done. Returns `check_cancelled()`, so a loop can stop as soon
+    /// as the operation is cancelled.
+    pub fn advance(&self, units: u32) -> bool {
+        let total = self.state.total.load(Ordering::Relaxed);
+        let before = self.state.done.fetch_add(units, Ordering::Relaxed);
+        if percent(before, total) != percent(before.wrapping_add(units), total) {
+            self.state.changed();
+        }
+
+        self.check_cancelled()
+    }
+
+    /// Has a watcher cancelled the operation?
+    pub fn check_cancelled(&self) -> bool {
+        self.state.cancelled.load(Ordering::Relaxed)
+    }
+}
+
+impl Drop for ProgressReporter {
+    fn drop(&mut self) {
+        self.state.reporting.store(false, Ordering::Release);
+    }
+}
+
+/// An observer of a `ProgressTracker`, see `ProgressTracker::watcher`.
+pub struct ProgressWatcher {
+    state: Arc<ProgressState>,
+    wake: Arc<BinarySemaphore>,
+    seen: u32,
+}
+
+impl ProgressWatcher {
+    /// The progress right now. Doesn't block or allocate, it only takes a short critical
+    /// section.
+    pub fn snapshot(&self) -> Progress {
+        self.state.snapshot()
+    }
+
+    /// Wait for the phase, the total or the whole percentage to change, or for the
+    /// operation to be cancelled, since the last call. Fails with `Timeout`.
+    pub fn wait_change<D: DurationTicks>(&mut self, timeout: D) -> Result<Progress, FreeRtosError> {
+        let start = unsafe { freertos_rs_xTaskGetTickCount() };
+        let timeout = timeout.to_ticks();
+
+        loop {
+            let version = self.state.version.load(Ordering::Acquire);
+            if version != self.seen {
+                self.seen = version;
+                return Ok(self.snapshot());
+            }
+
+            // The semaphore may still hold a wake up for a change that was already seen.
+            let wait = remaining_wait(start, timeout).ok_or(FreeRtosError::Timeout)?;
+            self.wake.take(wait)?;
+        }
+    }
+
+    pub fn cancel(&self) {
+        self.state.cancelled.store(true, Ordering::Relaxed);
+        self.state.changed();
+    }
+}
+
+impl Drop for ProgressWatcher {
+    fn drop(&mut self) {
+        if let Ok(mut watchers) = self.state.watchers.lock(&FreeRTOS {}) {
+            watchers.retain(|w| !Arc::ptr_eq(w, &self.wake));
+        }
+    }
+}
@@ -0,0 +1,396 @@
+use crate::base::*;
+use crate::critical::*;
+use crate::infra::*;
+use crate::isr::*;
+use crate::operating_system::*;
+use crate::prelude::v1::*;
+use crate::queue::*;
+use crate::shim::*;
+use crate::stats::*;
+use crate::sync::atomic::{AtomicBool, AtomicU32, Ordering};
+use crate::task::*;
+use crate::ticks::*;
+use crate::units::*;
+
+impl<E: Copy + Send + 'static> !ISRSafe for StateMachinePump<E> {}
+impl<E: Copy + Send + 'static> !ISRSafe for PumpHandle<E> {}
+
+/// Index of a machine registered with a `StateMachinePump`.
+pub type MachineId = usize;
+
+/// What a machine reports after being polled.
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum PumpResult {
+    /// Nothing to do until the next event or timer.
+    Idle,
+    /// More work is pending, poll again.
+    Working,
+    /// The machine has finished and won't be polled again.
+    Done,
+}
+
+/// A lightweight state machine driven by a `StateMachinePump`.
+pub trait Pump<E: Copy>: Send {
+    fn poll(&mut self, cx: &mut PumpCtx<E>) -> PumpResult;
+}
+
+/// Per-machine statistics, with times in ticks.
+#[derive(Debug, Copy, Clone, Default)]
+pub struct PumpStats {
+    pub polls: u32,
+    pub total_poll_ticks: u32,
+    pub max_poll_ticks: u32,
+    /// Count, mean and extremes of the time taken by a poll.
+    pub poll_ticks: RunningStats,
+    /// A single poll took longer than the pump's poll time budget.
+    pub over_budget: bool,
+}
+
+/// What a machine can see and do while it is polled.
+pub struct PumpCtx<'a, E: Copy> {
+    id: MachineId,
+    now: FreeRtosTickType,
+    inbox: &'a Queue<E>,
+    timer: &'a mut Option<FreeRtosTickType>,
+    timer_fired: bool,
+}
+
+impl<'a, E: Copy> PumpCtx<'a, E> {
+    pub fn id(&self) -> MachineId {
+        self.id
+    }
+
+    /// The tick count when this poll started.
+    pub fn now(&self) -> FreeRtosTickType {
+        self.now
+    }
+
+    /// Take the next event delivered to this machine.
+    pub fn next_event(&mut self) -> Option<E> {
+        self.inbox.receive(Duration::zero()).ok()
+    }
+
+    /// Poll this machine again after `delay`, replacing any timer already set.
+    pub fn set_timer<D: DurationTicks>(&mut self, delay: D) {
+        *self.timer = Some(tick_add(self.now, delay.to_ticks()));
+    }
+
+    pub fn cancel_timer(&mut self) {
+        *self.timer = None;
+    }
+
+    /// Is this poll caused by the machine's timer expiring?
+    pub fn timer_fired(&self) -> bool {
+        self.timer_fired
+    }
+}
+
+struct MachineShared<E: Copy> {
+    inbox: Queue<E>,
+    pending: AtomicBool,
+    poll_ticks: ExclusiveData<RunningStats>,
+    over_budget: AtomicBool,
+}
+
+struct PumpShared<E: Copy> {
+    machines: Vec<MachineShared<E>>,
+    tagged: Queue<(MachineId, E)>,
+    dropped: AtomicU32,
+}
+
+impl<E: Copy> PumpShared<E> {
+    fn deliver(&self, id: MachineId, event: E) -> Result<(), FreeRtosError> {
+        let machine = self.machines.get(id).ok_or(FreeRtosError::TaskNotFound)?;
+        machine.inbox.send(event, Duration::zero())?;
+        machine.pending.store(true, Ordering::Release);
+        Ok(())
+    }
+}
+
+struct MachineSlot<E: Copy> {
+    machine: Box<dyn Pump<E>>,
+    timer: Option<FreeRtosTickType>,
+    working: bool,
+    done: bool,
+}
+
+/// Runs many small event driven state machines inside a single task.
+///
+/// Machines with pending events, expired timers or more work are polled round robin,
+/// each at most `poll_budget` times per cycle so a busy machine can't starve the others.
+/// When every machine is idle the task sleeps until an event is posted or the nearest
+/// timer expires.
+pub struct StateMachinePump<E: Copy + Send + 'static> {
+    os: FreeRTOS,
+    capacity: usize,
+    inbox_depth: usize,
+    machines: Vec<Box<dyn Pump<E>>>,
+    shared: Vec<MachineShared<E>>,
+    tagged_depth: usize,
+}
+
+impl<E: Copy + Send + 'static> StateMachinePump<E> {
+    /// Create a pump for up to `capacity` machines, each with an event queue of `inbox_depth`.
+    /// Events posted to the shared queue are routed from a queue of `tagged_depth`.
+    pub fn new(os: FreeRTOS, capacity: usize, inbox_depth: usize, tagged_depth: usize) -> Self {
+        StateMachinePump {
+            os,
+            capacity,
+            inbox_depth,
+            machines: Vec::with_capacity(capacity),
+            shared: Vec::with_capacity(capacity),
+            tagged_depth,
+        }
+    }
+
+    pub fn register<P: Pump<E> + 'static>(
+        &mut self,
+        machine: P,
+    ) -> Result<MachineId, FreeRtosError> {
+        if self.machines.len() >= self.capacity {
+            return Err(FreeRtosError::OutOfMemory);
+        }
+
+        self.shared.push(MachineShared {
+            inbox: Queue::new(self.os, self.inbox_depth)?,
+            // Every machine gets a first poll.
+            pending: AtomicBool::new(true),
+            poll_ticks: ExclusiveData::new(RunningStats::new()),
+            over_budget: AtomicBool::new(false),
+        });
+        self.machines.push(Box::new(machine));
+
+        Ok(self.machines.len() - 1)
+    }
+
+    /// Start the pump task.
+    ///
+    /// A single poll taking longer than `max_poll_time` flags the machine as over budget.
+    pub fn start<D: DurationTicks>(
+        self,
+        stack_size: usize,
+        priority: TaskPriority,
+        poll_budget: u32,
+        max_poll_time: D,
+    ) -> Result<PumpHandle<E>, FreeRtosError> {
+        let shared = Arc::new(PumpShared {
+            machines: self.shared,
+            tagged: Queue::new(self.os, self.tagged_depth)?,
+            dropped: AtomicU32::new(0),
+        });
+
+        let mut slots: Vec<MachineSlot<E>> = self
+            .machines
+            .into_iter()
+            .map(|machine| MachineSlot {
+                machine,
+                timer: None,
+                working: false,
+                done: false,
+            })
+            .collect();
+
+        let max_poll_ticks = max_poll_time.to_ticks();
+        let task = {
+            let shared = shared.clone();
+
+            InfraTask::spawn(self.os, "pump", stack_size, priority, move |ctx, os| {
+                let mut start = 0;
+                while !ctx.should_stop() {
+                    let busy = run_cycle(
+                        &os,
+                        &shared,
+                        &mut slots,
+                        start,
+                        poll_budget.max(1),
+                        max_poll_ticks,
+                    );
+                    start = if slots.is_empty() {
+                        0
+                    } else {
+                        (start + 1) % slots.len()
+                    };
+
+                    if !busy {
+                        let now = os.get_tick_count();
+                        let wait = slots
+                            .iter()
+                            .filter(|s| !s.done)
+                            .filter_map(|s| s.timer)
+                            .map(|t| tick_remaining(now, t))
+                            .min()
+                            .map(Duration::ticks)
+                            .unwrap_or(Duration::infinite());
+                        let _ = ctx.wait_for_notification(0, u32::MAX, wait);
+                    }
+                }
+            })?
+        };
+
+        Ok(PumpHandle { shared, task })
+    }
+}
+
+/// Poll every machine that has something to do once. Returns true if any machine
+/// still has work left.
+fn run_cycle<E: Copy>(
+    os: &FreeRTOS,
+    shared: &PumpShared<E>,
+    slots: &mut [MachineSlot<E>],
+    start: usize,
+    poll_budget: u32,
+    max_poll_ticks: FreeRtosTickType,
+) -> bool {
+    while let Ok((id, event)) = shared.tagged.receive(Duration::zero()) {
+        if shared.deliver(id, event).is_err() {
+            shared.dropped.fetch_add(1, Ordering::Relaxed);
+        }
+    }
+
+    let mut busy = false;
+    let count = slots.len();
+    for k in 0..count {
+        let id = (start + k) % count;
+        let slot = &mut slots[id];
+        let machine = &shared.machines[id];
+        if slot.done {
+            continue;
+        }
+
+        let now = unsafe { freertos_rs_xTaskGetTickCount() };
+        let timer_fired = match slot.timer {
+            Some(deadline) => tick_deadline_reached(now, deadline),
+            None => false,
+        };
+        if timer_fired {
+            slot.timer = None;
+        }
+
+        let pending = machine.pending.swap(false, Ordering::Acquire);
+        if !(pending || timer_fired || slot.working) {
+            continue;
+        }
+
+        for _ in 0..poll_budget {
+            let before = unsafe { freertos_rs_xTaskGetTickCount() };
+            let result = slot.machine.poll(&mut PumpCtx {
+                id,
+                now: before,
+                inbox: &machine.inbox,
+                timer: &mut slot.timer,
+                timer_fired,
+            });
+            let took = tick_elapsed(unsafe { freertos_rs_xTaskGetTickCount() }, before);
+
+            if let Ok(mut poll_ticks) = machine.poll_ticks.lock(os) {
+                poll_ticks.record(took);
+            }
+            if took > max_poll_ticks {
+                machine.over_budget.store(true, Ordering::Relaxed);
+            }
+
+            match result {
+                PumpResult::Idle => {
+                    slot.working = false;
+                    break;
+                }
+                PumpResult::Working => slot.working = true,
+                PumpResult::Done => {
+                    slot.done = true;
+                    slot.working = false;
+                    break;
+                }
+            }
+        }
+
+        // Events left in the inbox after going idle still need a poll.
+        if !slot.done && (slot.working || !machine.inbox.is_empty()) {
+            machine.pending.store(true, Ordering::Release);
+            busy = true;
+        }
+    }
+
+    busy
+}
+
+/// Posts events to the machines of a running pump.
+pub struct PumpHandle<E: Copy + Send + 'static> {
+    shared: Arc<PumpShared<E>>,
+    task: InfraTask,
+}
+
+impl<E: Copy + Send + 'static> PumpHandle<E> {
+    /// Deliver an event straight to a machine's own queue.
+    pub fn post(&self, id: MachineId, event: E) -> Result<(), FreeRtosError> {
+        self.shared.deliver(id, event)?;
+        self.task.notify(TaskNotification::SetBits(1));
+        Ok(())
+    }
+
+    /// Deliver an event through the shared queue, waiting up to `max_wait` for space.
+    pub fn post_tagged<D: DurationTicks>(
+        &self,
+        id: MachineId,
+        event: E,
+        max_wait: D,
+    ) -> Result<(), FreeRtosError> {
+        self.shared.tagged.send((id, event), max_wait)?;
+        self.task.notify(TaskNotification::SetBits(1));
+        Ok(())
+    }
+
+    pub fn stats(&self, id: MachineId) -> Option<PumpStats> {
+        let os = unsafe { FreeRTOS::assume_init() };
+        self.shared.machines.get(id).map(|m| {
+            let poll_ticks = m.poll_ticks.lock(&os).map(|s| *s).unwrap_or_default();
+            PumpStats {
+                polls: poll_ticks.count(),
+                total_poll_ticks: poll_ticks.sum().min(u32::MAX as u64) as u32,
+                max_poll_ticks: poll_ticks.max().unwrap_or(0) as u32,
+                poll_ticks,
+                over_budget: m.over_budget.load(Ordering::Relaxed),
+            }
+        })
+    }
+
+    /// The task running the machines.
+    pub fn task(&self) -> &InfraTask {
+        &self.task
+    }
+
+    /// Events from the shared queue that couldn't be delivered, because the machine
+    /// doesn't exist or its queue was full.
+    pub fn dropped(&self) -> u32 {
+        self.shared.dropped.load(Ordering::Relaxed)
+    }
+}
+
+/// Posts events to a pump from interrupts, through the shared queue.
+pub struct PumpISRHandle<E: Copy> {
+    tagged: QueueISRHandle<(MachineId, E)>,
+    task: FreeRtosTaskHandle,
+}
+
+impl<E: Copy + Send + ISRSafe + 'static> ISRSafeHandle<PumpISRHandle<E>> for PumpHandle<E> {
+    unsafe fn new_isr_safe_handle(&self) -> PumpISRHandle<E> {
+        PumpISRHandle {
+            tagged: self.shared.tagged.new_isr_safe_handle(),
+            task: self.task.raw_handle(),
+        }
+    }
+}
+
+impl<E: Copy> PumpISRHandle<E> {
+    pub fn post(
+        &self,
+        context: &mut InterruptContext,
+        id: MachineId,
+        event: E,
+    ) -> Result<(), FreeRtosError> {
+        self.tagged.send(context, (id, event))?;
+        unsafe {
+            freertos_rs_task_notify_isr(self.task, 1, 1, context.get_task_field_mut());
+        }
+        Ok(())
+    }
+}
@@ -1,8 +1,16 @@
 use crate::base::*;
+use crate::hooks::*;
 use crate::isr::*;
+use crate::no_block::check_blocking;
 use crate::operating_system::*;
 use crate::prelude::v1::*;
+use crate::queue_registry::*;
+use crate::queue_set::SetMembership;
+use crate::service_budget::*;
 use crate::shim::*;
+use crate::stats::*;
+use crate::task::check_name_nul;
+use crate::ticks::tick_elapsed;
 use crate::units::*;
 
 unsafe impl<T: Sized + Copy> Send for Queue<T> {}
@@ -12,10 +20,19 @@ impl<T: Sized + Copy> !ISRSafe for Queue<T> {}
 
 /// A queue with a finite size. The items are owned by the queue and are
 /// copied.
+///
+/// Dropping the queue deletes it, which the kernel doesn't allow while tasks are blocked
+/// on it, e.g. C code or tasks holding its handle. A queue dropped then is left to them
+/// instead, leaked, and the assert hook is called. `delete` waits for them to leave.
+/// Both only see the tasks `blocked_tasks` counts.
 #[derive(Debug)]
 pub struct Queue<T: Sized + Copy> {
     queue: FreeRtosQueueHandle,
     item_type: PhantomData<T>,
+    max_size: usize,
+    budget: Option<ServiceBudget>,
+    registry: Option<RegistryEntry>,
+    pub(crate) set: SetMembership,
 }
 
 impl<T: Sized + Copy> Queue<T> {
@@ -30,46 +47,378 @@ impl<T: Sized + Copy> Queue<T> {
             Ok(Queue {
                 queue: handle,
                 item_type: PhantomData,
+                max_size,
+                budget: None,
+                registry: None,
+                set: SetMembership::new(),
             })
         }
     }
 
+    /// Create a queue of `N` items in caller-provided storage instead of the FreeRTOS heap.
+    /// Fails with `OutOfMemory` if the kernel's `StaticQueue_t` doesn't fit the storage.
+    #[cfg(feature = "static_allocation")]
+    pub fn new_static<const N: usize>(
+        _os: FreeRTOS,
+        storage: &'static mut StaticQueueStorage<T, N>,
+    ) -> Result<Queue<T>, FreeRtosError> {
+        if !static_control_block_fits() {
+            return Err(FreeRtosError::OutOfMemory);
+        }
+
+        let item_size = mem::size_of::<T>();
+        let items = if item_size == 0 {
+            ptr::null_mut()
+        } else {
+            storage.items.as_mut_ptr() as FreeRtosMutVoidPtr
+        };
+
+        let handle = unsafe {
+            freertos_rs_queue_create_static(
+                N as FreeRtosUBaseType,
+                item_size as FreeRtosUBaseType,
+                items,
+                storage.control.as_mut_ptr() as FreeRtosMutVoidPtr,
+            )
+        };
+
+        if handle == 0 as *const _ {
+            Err(FreeRtosError::OutOfMemory)
+        } else {
+            Ok(Queue {
+                queue: handle,
+                item_type: PhantomData,
+                max_size: N,
+                budget: None,
+                registry: None,
+                set: SetMembership::new(),
+            })
+        }
+    }
+
+    /// Create a queue and add it to the kernel queue registry under `name`, for
+    /// kernel-aware debuggers. It is removed from the registry when dropped.
+    ///
+    /// Without a registry, `configQUEUE_REGISTRY_SIZE` of 0, this is `new`. The registry
+    /// keeps the whole name, but fails with `InvalidName` if it has a nul byte.
+    pub fn new_named(os: FreeRTOS, max_size: usize, name: &str) -> Result<Queue<T>, FreeRtosError> {
+        check_name_nul(name)?;
+        let mut queue = Queue::new(os, max_size)?;
+        queue.registry = RegistryEntry::new(queue.queue, name);
+        Ok(queue)
+    }
+
+    /// The name this queue has in the kernel queue registry. `None` if it was created
+    /// without one, or the registry was full or disabled.
+    pub fn registered_name(&self) -> Option<String> {
+        registered_name(self.queue)
+    }
+
     /// Send an item to the end of the queue. Wait for the queue to have empty space for it.
+    #[inline]
     pub fn send<D: DurationTicks>(&self, item: T, max_wait: D) -> Result<(), FreeRtosError> {
         unsafe {
-            if freertos_rs_queue_send(
+            queue_send(
+                "Queue::send",
                 self.queue,
                 &item as *const _ as FreeRtosVoidPtr,
                 max_wait.to_ticks(),
-            ) != 0
-            {
-                Err(FreeRtosError::QueueSendTimeout)
-            } else {
-                Ok(())
-            }
+            )
+        }
+    }
+
+    /// Send an item to the front of the queue, ahead of items already waiting.
+    /// Wait for the queue to have empty space for it.
+    #[inline]
+    pub fn send_to_front<D: DurationTicks>(
+        &self,
+        item: T,
+        max_wait: D,
+    ) -> Result<(), FreeRtosError> {
+        unsafe {
+            queue_send_to_front(
+                "Queue::send_to_front",
+                self.queue,
+                &item as *const _ as FreeRtosVoidPtr,
+                max_wait.to_ticks(),
+            )
         }
     }
 
     /// Wait for an item to be available on the queue.
+    #[inline]
     pub fn receive<D: DurationTicks>(&self, max_wait: D) -> Result<T, FreeRtosError> {
+        self.set.check_read(max_wait.to_ticks())?;
         unsafe {
             let mut buff = mem::zeroed::<T>();
-            let r = freertos_rs_queue_receive(
+            queue_receive(
+                "Queue::receive",
                 self.queue,
                 &mut buff as *mut _ as FreeRtosMutVoidPtr,
                 max_wait.to_ticks(),
-            );
-            if r == 0 {
-                return Ok(buff);
-            } else {
-                return Err(FreeRtosError::QueueReceiveTimeout);
+                self.budget.as_ref(),
+            )?;
+            Ok(buff)
+        }
+    }
+
+    /// Wait for an item to be available and return a copy of it, leaving it in the queue.
+    #[inline]
+    pub fn peek<D: DurationTicks>(&self, max_wait: D) -> Result<T, FreeRtosError> {
+        self.set.check_read(max_wait.to_ticks())?;
+        unsafe {
+            let mut buff = mem::zeroed::<T>();
+            queue_peek(
+                "Queue::peek",
+                self.queue,
+                &mut buff as *mut _ as FreeRtosMutVoidPtr,
+                max_wait.to_ticks(),
+            )?;
+            Ok(buff)
+        }
+    }
+
+    /// Replace the item of a mailbox, a queue of one item, or send it if the mailbox is
+    /// empty. Never waits. Fails with `InvalidQueueSize` for a queue of any other size.
+    pub fn overwrite(&self, item: T) -> Result<(), FreeRtosError> {
+        if self.max_size != 1 {
+            return Err(FreeRtosError::InvalidQueueSize);
+        }
+
+        unsafe {
+            freertos_rs_queue_overwrite(self.queue, &item as *const _ as FreeRtosVoidPtr);
+        }
+        Ok(())
+    }
+
+    /// The number of items waiting in the queue.
+    pub fn len(&self) -> usize {
+        unsafe { freertos_rs_queue_messages_waiting(self.queue) as usize }
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    /// The number of tasks blocked sending to or receiving from the queue through the shim,
+    /// which counts them in the queue number of the trace facility. Always 0 without
+    /// `configUSE_TRACE_FACILITY`, and tasks calling the kernel directly aren't counted.
+    pub fn blocked_tasks(&self) -> usize {
+        unsafe { freertos_rs_queue_blocked_tasks(self.queue) as usize }
+    }
+
+    /// Delete the queue once no task is blocked on it, waiting up to `max_wait` for that.
+    ///
+    /// The items left in the queue are thrown away while waiting, so tasks blocked
+    /// sending get through. Tasks blocked receiving have to time out. Fails with
+    /// `Timeout` if tasks are still blocked after `max_wait`, and leaks the queue then,
+    /// so they stay safe.
+    pub fn delete<D: DurationTicks>(self, max_wait: D) -> Result<(), FreeRtosError> {
+        let max_wait = max_wait.to_ticks();
+        check_blocking("Queue::delete", self.queue, max_wait);
+
+        let forever = unsafe { freertos_rs_max_wait() };
+        let start = unsafe { freertos_rs_xTaskGetTickCount() };
+        let mut item = mem::MaybeUninit::<T>::uninit();
+        let item = item.as_mut_ptr() as FreeRtosMutVoidPtr;
+        loop {
+            while unsafe { freertos_rs_queue_receive(self.queue, item, 0) } == 0 {}
+            if self.blocked_tasks() == 0 {
+                return Ok(());
+            }
+            let now = unsafe { freertos_rs_xTaskGetTickCount() };
+            if max_wait != forever && tick_elapsed(now, start) >= max_wait {
+                mem::forget(self);
+                return Err(FreeRtosError::Timeout);
             }
+            unsafe { freertos_rs_vTaskDelay(1) };
         }
     }
+
+    /// The number of items that can be sent before the queue is full.
+    pub fn spaces_available(&self) -> usize {
+        unsafe { freertos_rs_queue_spaces_available(self.queue) as usize }
+    }
+
+    pub fn is_full(&self) -> bool {
+        self.spaces_available() == 0
+    }
+
+    /// The number of items the queue was created to hold.
+    pub fn max_size(&self) -> usize {
+        self.max_size
+    }
+
+    pub(crate) fn raw_handle(&self) -> FreeRtosQueueHandle {
+        self.queue
+    }
+
+    /// Measure how long items sent from interrupts wait before a task receives them, and
+    /// call `on_violation` from the receiving task when that exceeds `budget`.
+    ///
+    /// Reports are rate limited; every violation is still counted. ISR handles created
+    /// before the budget was first set don't stamp their sends.
+    pub fn set_service_budget<D: DurationTicks, F>(&mut self, budget: D, on_violation: F)
+    where
+        F: Fn(&ServiceBudgetViolation) + Send + Sync + 'static,
+    {
+        match &mut self.budget {
+            Some(b) => b.set(budget.to_ticks(), on_violation),
+            None => self.budget = Some(ServiceBudget::new(budget.to_ticks(), on_violation)),
+        }
+    }
+
+    /// Stop measuring service latency.
+    pub fn clear_service_budget(&mut self) {
+        if let Some(budget) = &self.budget {
+            budget.disable();
+        }
+    }
+
+    /// How many times the service budget was exceeded.
+    pub fn service_budget_violations(&self) -> u32 {
+        self.budget.as_ref().map(|b| b.violations()).unwrap_or(0)
+    }
+
+    /// The longest observed service latency while a budget was set.
+    pub fn max_service_latency(&self) -> Duration {
+        Duration::ticks(self.service_latency().max().unwrap_or(0) as FreeRtosTickType)
+    }
+
+    /// Count, mean and extremes of the observed service latencies in ticks, while a
+    /// budget was set.
+    pub fn service_latency(&self) -> RunningStats {
+        self.budget
+            .as_ref()
+            .map(|b| b.observed())
+            .unwrap_or_default()
+    }
+}
+
+// The bodies of the blocking calls aren't generic, so all `Queue<T>`s and `QueueRef<T>`s
+// share one copy, and call sites only inline the conversion to ticks.
+
+pub(crate) unsafe fn queue_send(
+    api: &'static str,
+    queue: FreeRtosQueueHandle,
+    item: FreeRtosVoidPtr,
+    max_wait: FreeRtosTickType,
+) -> Result<(), FreeRtosError> {
+    check_blocking(api, queue, max_wait);
+
+    if freertos_rs_queue_send(queue, item, max_wait) != 0 {
+        return Err(queue_send_failed());
+    }
+    Ok(())
+}
+
+pub(crate) unsafe fn queue_send_to_front(
+    api: &'static str,
+    queue: FreeRtosQueueHandle,
+    item: FreeRtosVoidPtr,
+    max_wait: FreeRtosTickType,
+) -> Result<(), FreeRtosError> {
+    check_blocking(api, queue, max_wait);
+
+    if freertos_rs_queue_send_to_front(queue, item, max_wait) != 0 {
+        return Err(queue_send_failed());
+    }
+    Ok(())
+}
+
+pub(crate) unsafe fn queue_receive(
+    api: &'static str,
+    queue: FreeRtosQueueHandle,
+    buff: FreeRtosMutVoidPtr,
+    max_wait: FreeRtosTickType,
+    budget: Option<&ServiceBudget>,
+) -> Result<(), FreeRtosError> {
+    check_blocking(api, queue, max_wait);
+
+    if freertos_rs_queue_receive(queue, buff, max_wait) != 0 {
+        return Err(queue_receive_failed());
+    }
+    if let Some(budget) = budget {
+        budget.serviced();
+    }
+    Ok(())
+}
+
+pub(crate) unsafe fn queue_peek(
+    api: &'static str,
+    queue: FreeRtosQueueHandle,
+    buff: FreeRtosMutVoidPtr,
+    max_wait: FreeRtosTickType,
+) -> Result<(), FreeRtosError> {
+    check_blocking(api, queue, max_wait);
+
+    if freertos_rs_queue_peek(queue, buff, max_wait) != 0 {
+        return Err(queue_receive_failed());
+    }
+    Ok(())
+}
+
+#[cold]
+#[inline(never)]
+fn queue_send_failed() -> FreeRtosError {
+    #[cfg(feature = "emergency_abort")]
+    if crate::emergency::emergency_aborted() {
+        return FreeRtosError::Emergency;
+    }
+    FreeRtosError::QueueSendTimeout
 }
 
+#[cold]
+#[inline(never)]
+fn queue_receive_failed() -> FreeRtosError {
+    #[cfg(feature = "emergency_abort")]
+    if crate::emergency::emergency_aborted() {
+        return FreeRtosError::Emergency;
+    }
+    FreeRtosError::QueueReceiveTimeout
+}
+
+/// Words reserved for the kernel's `StaticQueue_t`, which semaphores and mutexes use as
+/// their `StaticSemaphore_t` too.
+#[cfg(feature = "static_allocation")]
+pub const STATIC_QUEUE_CONTROL_WORDS: usize = 32;
+
+#[cfg(feature = "static_allocation")]
+pub(crate) fn static_control_block_fits() -> bool {
+    let size = unsafe { freertos_rs_static_queue_size() } as usize;
+    size <= STATIC_QUEUE_CONTROL_WORDS * mem::size_of::<usize>()
+}
+
+/// Memory for a statically allocated queue of up to `N` items, for `Queue::new_static`.
+#[cfg(feature = "static_allocation")]
+pub struct StaticQueueStorage<T: Sized + Copy, const N: usize> {
+    items: mem::MaybeUninit<[T; N]>,
+    control: [usize; STATIC_QUEUE_CONTROL_WORDS],
+}
+
+#[cfg(feature = "static_allocation")]
+impl<T: Sized + Copy, const N: usize> StaticQueueStorage<T, N> {
+    pub const fn new() -> StaticQueueStorage<T, N> {
+        StaticQueueStorage {
+            items: mem::MaybeUninit::uninit(),
+            control: [0; STATIC_QUEUE_CONTROL_WORDS],
+        }
+    }
+}
+
+/// Deleting a statically allocated queue only unregisters it; its storage stays in use
+/// and can't be handed out again.
 impl<T: Sized + Copy> Drop for Queue<T> {
     fn drop(&mut self) {
+        self.registry = None;
+        if self.blocked_tasks() != 0 {
+            // The tasks would be left in lists in freed memory.
+            unsafe { (*ptr::addr_of!(FREERTOS_HOOKS)).do_on_assert() };
+            return;
+        }
+        if !self.set.leave(self.queue) {
+            return;
+        }
         unsafe {
             freertos_rs_queue_delete(self.queue);
         }
@@ -79,6 +428,8 @@ impl<T: Sized + Copy> Drop for Queue<T> {
 pub struct QueueISRHandle<T: Sized + Copy> {
     queue: FreeRtosQueueHandle,
     item_type: PhantomData<T>,
+    max_size: usize,
+    budget: *const ServiceBudgetState,
 }
 
 impl<T: Sized + Copy + ISRSafe> ISRSafeHandle<QueueISRHandle<T>> for Queue<T> {
@@ -86,6 +437,11 @@ impl<T: Sized + Copy + ISRSafe> ISRSafeHandle<QueueISRHandle<T>> for Queue<T> {
         QueueISRHandle {
             queue: self.queue,
             item_type: self.item_type,
+            max_size: self.max_size,
+            budget: match &self.budget {
+                Some(budget) => budget.state(),
+                None => ptr::null(),
+            },
         }
     }
 }
@@ -102,11 +458,76 @@ impl<T: Sized + Copy> QueueISRHandle<T> {
             {
                 Err(FreeRtosError::QueueFull)
             } else {
+                if !self.budget.is_null() {
+                    (*self.budget).stamp_isr();
+                }
                 Ok(())
             }
         }
     }
 
+    /// Send an item to the front of the queue, from an interrupt.
+    pub fn send_to_front(
+        &self,
+        context: &mut InterruptContext,
+        item: T,
+    ) -> Result<(), FreeRtosError> {
+        unsafe {
+            if freertos_rs_queue_send_to_front_isr(
+                self.queue,
+                &item as *const _ as FreeRtosVoidPtr,
+                context.get_task_field_mut(),
+            ) != 0
+            {
+                Err(FreeRtosError::QueueFull)
+            } else {
+                if !self.budget.is_null() {
+                    (*self.budget).stamp_isr();
+                }
+                Ok(())
+            }
+        }
+    }
+
+    /// Replace the item of a mailbox, a queue of one item, or send it if the mailbox is
+    /// empty, from an interrupt. Fails with `InvalidQueueSize` for a queue of any other
+    /// size.
+    pub fn overwrite(&self, context: &mut InterruptContext, item: T) -> Result<(), FreeRtosError> {
+        if self.max_size != 1 {
+            return Err(FreeRtosError::InvalidQueueSize);
+        }
+
+        unsafe {
+            freertos_rs_queue_overwrite_isr(
+                self.queue,
+                &item as *const _ as FreeRtosVoidPtr,
+                context.get_task_field_mut(),
+            );
+            if !self.budget.is_null() {
+                (*self.budget).stamp_isr();
+            }
+        }
+        Ok(())
+    }
+
+    /// The number of items waiting in the queue, from an interrupt.
+    pub fn len(&self, _context: &mut InterruptContext) -> usize {
+        unsafe { freertos_rs_queue_messages_waiting_isr(self.queue) as usize }
+    }
+
+    pub fn is_empty(&self, context: &mut InterruptContext) -> bool {
+        self.len(context) == 0
+    }
+
+    /// The number of items that can be sent before the queue is full, from an interrupt.
+    pub fn spaces_available(&self, context: &mut InterruptContext) -> usize {
+        self.max_size - self.len(context)
+    }
+
+    pub fn is_full(&self, context: &mut InterruptContext) -> bool {
+        self.spaces_available(context) == 0
+    }
+
     // Receive an item from the front of the queue, from an interrupt.
     pub fn receive<D: DurationTicks>(&self, context: &mut InterruptContext) -> Option<T> {
         unsafe {
@@ -0,0 +1,52 @@
+use crate::base::*;
+use crate::prelude::v1::*;
+use crate::shim::*;
+use crate::utils::*;
+
+/// A queue, semaphore or mutex added to the kernel queue registry, which kernel-aware
+/// debuggers read to show it by name. The registry keeps a pointer to the name, so it is
+/// owned here until the handle is unregistered on drop.
+///
+/// Drop it before deleting the handle.
+#[derive(Debug)]
+pub(crate) struct RegistryEntry {
+    handle: FreeRtosQueueHandle,
+    /// NUL terminated.
+    _name: Box<[u8]>,
+}
+
+impl RegistryEntry {
+    /// Register `handle` under `name`. Returns `None`, without copying the name, when
+    /// `configQUEUE_REGISTRY_SIZE` is 0. A full registry drops the name silently, see
+    /// `registered_name`.
+    pub(crate) fn new(handle: FreeRtosQueueHandle, name: &str) -> Option<RegistryEntry> {
+        if unsafe { freertos_rs_get_queue_registry_size() } == 0 {
+            return None;
+        }
+
+        let mut c_name = Vec::with_capacity(name.len() + 1);
+        c_name.extend_from_slice(name.as_bytes());
+        c_name.push(0);
+        let name = c_name.into_boxed_slice();
+        unsafe { freertos_rs_queue_add_to_registry(handle, name.as_ptr()) };
+        Some(RegistryEntry {
+            handle,
+            _name: name,
+        })
+    }
+}
+
+impl Drop for RegistryEntry {
+    fn drop(&mut self) {
+        unsafe { freertos_rs_queue_unregister(self.handle) }
+    }
+}
+
+/// The name `handle` is registered under, as reported by the kernel.
+pub(crate) fn registered_name(handle: FreeRtosQueueHandle) -> Option<String> {
+    let name = unsafe { freertos_rs_queue_get_name(handle) };
+    if name.is_null() {
+        return None;
+    }
+    unsafe { str_from_c_string(name) }.ok()
+}
@@ -0,0 +1,237 @@
+use crate::base::*;
+use crate::hooks::*;
+use crate::isr::*;
+use crate::no_block::check_blocking;
+use crate::operating_system::*;
+use crate::prelude::v1::*;
+use crate::queue::Queue;
+use crate::semaphore::{BinarySemaphore, CountingSemaphore, Semaphore};
+use crate::shim::*;
+use crate::sync::atomic::{AtomicPtr, AtomicUsize, Ordering};
+use crate::units::*;
+
+unsafe impl Send for SetInner {}
+unsafe impl Sync for SetInner {}
+
+impl !ISRSafe for QueueSet {}
+
+/// A set of queues and semaphores a task waits on at once, with `configUSE_QUEUE_SETS`.
+///
+/// `select` blocks until one of the members has an item, or a semaphore is given, and
+/// returns which one. The task then reads that member with a zero timeout:
+///
+///     let set = os.new_queue_set(2)?;
+///     set.add(&commands)?;
+///     set.add(&stop)?;
+///     loop {
+///         let ready = set.select(Duration::infinite())?;
+///         if ready.is(&commands) {
+///             handle(commands.receive(Duration::zero())?);
+///         } else if ready.is(&stop) {
+///             stop.take(Duration::zero())?;
+///             break;
+///         }
+///     }
+///
+/// The kernel requires members to be read only once `select` returned them, one read
+/// per return. Blocking reads of a member fail with `InQueueSet`, as the task would
+/// race the set for the item. A zero timeout read without `select` leaves the set with
+/// an event of the member it returns later, and the read after that finds nothing.
+///
+/// The capacity is the number of events the set holds, the sum of the lengths of its
+/// members: a queue counts its size, a binary semaphore one and a counting semaphore its
+/// maximum count. A member leaves the set when it is dropped, which the kernel only
+/// allows when it is empty. A member dropped holding items stays in the set, leaked, and
+/// the assert hook is called. The kernel set is deleted once the `QueueSet` and all its
+/// members are gone.
+#[derive(Debug)]
+pub struct QueueSet {
+    inner: Arc<SetInner>,
+}
+
+/// The kernel set, shared by the `QueueSet` and its members.
+#[derive(Debug)]
+struct SetInner {
+    set: FreeRtosQueueHandle,
+    capacity: usize,
+    used: AtomicUsize,
+}
+
+impl Drop for SetInner {
+    fn drop(&mut self) {
+        unsafe { freertos_rs_queue_delete(self.set) };
+    }
+}
+
+impl QueueSet {
+    /// Create a set holding up to `capacity` events. Fails with `InvalidQueueSize` for a
+    /// capacity of zero, and with `OutOfMemory` without `configUSE_QUEUE_SETS`.
+    pub fn new(_os: FreeRTOS, capacity: usize) -> Result<QueueSet, FreeRtosError> {
+        if capacity == 0 {
+            return Err(FreeRtosError::InvalidQueueSize);
+        }
+        let set = unsafe { freertos_rs_queue_set_create(capacity as FreeRtosUBaseType) };
+        if set.is_null() {
+            return Err(FreeRtosError::OutOfMemory);
+        }
+        Ok(QueueSet {
+            inner: Arc::new(SetInner {
+                set,
+                capacity,
+                used: AtomicUsize::new(0),
+            }),
+        })
+    }
+
+    /// Add `member` to the set. Fails with `QueueSetMemberNotEmpty` if it holds items or
+    /// is an available semaphore, with `InQueueSet` if it is in a set already, and with
+    /// `InvalidQueueSize` if the set doesn't have the capacity for its length left.
+    pub fn add<M: QueueSetMember>(&self, member: &M) -> Result<(), FreeRtosError> {
+        let handle = member.member_handle();
+        let inner = Arc::into_raw(self.inner.clone()) as *mut SetInner;
+        let membership = &member.membership().set;
+        let joined =
+            membership.compare_exchange(ptr::null_mut(), inner, Ordering::SeqCst, Ordering::SeqCst);
+        if joined.is_err() {
+            unsafe { drop(Arc::from_raw(inner)) };
+            return Err(FreeRtosError::InQueueSet);
+        }
+
+        let length = unsafe {
+            freertos_rs_queue_messages_waiting(handle) + freertos_rs_queue_spaces_available(handle)
+        } as usize;
+        let used = self.inner.used.fetch_add(length, Ordering::SeqCst);
+        let error = if used + length > self.inner.capacity {
+            FreeRtosError::InvalidQueueSize
+        } else if unsafe { freertos_rs_queue_set_add(handle, self.inner.set) } != 0 {
+            FreeRtosError::QueueSetMemberNotEmpty
+        } else {
+            return Ok(());
+        };
+        self.inner.used.fetch_sub(length, Ordering::SeqCst);
+        membership.store(ptr::null_mut(), Ordering::SeqCst);
+        unsafe { drop(Arc::from_raw(inner)) };
+        Err(error)
+    }
+
+    /// Wait up to `max_wait` for a member to have an item or to be given, and return it.
+    /// Fails with `Timeout`.
+    pub fn select<D: DurationTicks>(
+        &self,
+        max_wait: D,
+    ) -> Result<QueueSetMemberHandle, FreeRtosError> {
+        let max_wait = max_wait.to_ticks();
+        check_blocking("QueueSet::select", self.inner.set, max_wait);
+
+        let member = unsafe { freertos_rs_queue_set_select(self.inner.set, max_wait) };
+        if member.is_null() {
+            return Err(timed_out());
+        }
+        Ok(QueueSetMemberHandle(member))
+    }
+}
+
+/// The member `QueueSet::select` returned.
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub struct QueueSetMemberHandle(FreeRtosQueueHandle);
+
+unsafe impl Send for QueueSetMemberHandle {}
+unsafe impl Sync for QueueSetMemberHandle {}
+
+impl QueueSetMemberHandle {
+    /// Whether this is `member`.
+    pub fn is<M: QueueSetMember>(&self, member: &M) -> bool {
+        self.0 == member.member_handle()
+    }
+}
+
+mod private {
+    pub trait Sealed {
+        fn member_handle(&self) -> super::FreeRtosQueueHandle;
+        fn membership(&self) -> &super::SetMembership;
+    }
+}
+
+/// What can be added to a `QueueSet`: `Queue`, `BinarySemaphore` and
+/// `CountingSemaphore`.
+pub trait QueueSetMember: private::Sealed {}
+
+/// The set a queue or semaphore is a member of, if any.
+#[derive(Debug)]
+pub struct SetMembership {
+    set: AtomicPtr<SetInner>,
+}
+
+impl SetMembership {
+    pub(crate) fn new() -> SetMembership {
+        SetMembership {
+            set: AtomicPtr::new(ptr::null_mut()),
+        }
+    }
+
+    /// Fails with `InQueueSet` for a blocking read of a member.
+    #[inline]
+    pub(crate) fn check_read(&self, max_wait: FreeRtosTickType) -> Result<(), FreeRtosError> {
+        if max_wait != 0 && !self.set.load(Ordering::Relaxed).is_null() {
+            return Err(FreeRtosError::InQueueSet);
+        }
+        Ok(())
+    }
+
+    /// Take `member` out of its set before it is deleted. Returns false if the kernel
+    /// didn't let it leave, when it had items, and it has to be leaked.
+    pub(crate) fn leave(&self, member: FreeRtosQueueHandle) -> bool {
+        let inner = self.set.swap(ptr::null_mut(), Ordering::SeqCst);
+        if inner.is_null() {
+            return true;
+        }
+        let inner = unsafe { Arc::from_raw(inner) };
+        let length = unsafe {
+            freertos_rs_queue_messages_waiting(member) + freertos_rs_queue_spaces_available(member)
+        } as usize;
+        if unsafe { freertos_rs_queue_set_remove(member, inner.set) } != 0 {
+            // The set would return the handle of the deleted member.
+            mem::forget(inner);
+            unsafe { (*ptr::addr_of!(FREERTOS_HOOKS)).do_on_assert() };
+            return false;
+        }
+        inner.used.fetch_sub(length, Ordering::SeqCst);
+        true
+    }
+}
+
+impl<T: Sized + Copy> private::Sealed for Queue<T> {
+    fn member_handle(&self) -> FreeRtosQueueHandle {
+        self.raw_handle()
+    }
+
+    fn membership(&self) -> &SetMembership {
+        &self.set
+    }
+}
+
+impl<T: Sized + Copy> QueueSetMember for Queue<T> {}
+
+impl private::Sealed for BinarySemaphore {
+    fn member_handle(&self) -> FreeRtosQueueHandle {
+        self.raw_handle()
+    }
+
+    fn membership(&self) -> &SetMembership {
+        &self.set
+    }
+}
+
+impl QueueSetMember for BinarySemaphore {}
+
+impl private::Sealed for CountingSemaphore {
+    fn member_handle(&self) -> FreeRtosQueueHandle {
+        self.raw_handle()
+    }
+
+    fn membership(&self) -> &SetMembership {
+        &self.set
+    }
+}
+
+impl QueueSetMember for CountingSemaphore {}
@@ -0,0 +1,244 @@
+use crate::base::*;
+use crate::critical::*;
+use crate::isr::*;
+use crate::mutex::*;
+use crate::operating_system::*;
+use crate::prelude::v1::*;
+use crate::shim::*;
+use crate::sync::atomic::{AtomicBool, AtomicU32, AtomicUsize, Ordering};
+use crate::task::*;
+use crate::ticks::*;
+use crate::units::*;
+
+impl !ISRSafe for QuiescentWorker {}
+
+/// What a unit of quiescent work left to do.
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum WorkOutcome {
+    /// Run another unit in a later idle period.
+    MoreWork,
+    /// Finished, the work is removed.
+    Done,
+}
+
+type QuiescentWork = Box<dyn FnMut() -> WorkOutcome + Send>;
+
+/// The `QuiescentShared` of the live worker. Only touched in critical sections, so the
+/// idle hook never sees it freed halfway. A static, so its atomic is const-initialised.
+static LISTENER: core::sync::atomic::AtomicUsize = core::sync::atomic::AtomicUsize::new(0);
+
+struct QuiescentShared {
+    work: Mutex<Vec<QuiescentWork>>,
+    pending: AtomicUsize,
+    task: AtomicUsize,
+    threshold: FreeRtosTickType,
+    /// When the idle hook last ran.
+    last_idle: AtomicU32,
+    /// When the current idle streak started.
+    streak_start: AtomicU32,
+    /// The worker was notified and hasn't finished its unit yet.
+    woken: AtomicBool,
+    woken_at: AtomicU32,
+    unit_end: AtomicU32,
+    /// The last unit took at most a tick, so the gap it left doesn't end the streak.
+    excused: AtomicBool,
+    shutdown: AtomicBool,
+}
+
+unsafe impl Send for QuiescentShared {}
+unsafe impl Sync for QuiescentShared {}
+
+impl QuiescentShared {
+    /// Called by the idle hook in a critical section.
+    fn on_idle(&self, now: FreeRtosTickType) {
+        let last = self.last_idle.swap(now, Ordering::Relaxed);
+        let excused = self.excused.swap(false, Ordering::Relaxed);
+        if tick_elapsed(now, last) > 1 {
+            // Some task ran for a tick or more since the idle task last got here. A
+            // short unit of our own that the idle task resumed right after is fine.
+            let own_unit = excused && tick_elapsed(now, self.unit_end.load(Ordering::Relaxed)) <= 1;
+            if !own_unit {
+                self.streak_start.store(now, Ordering::Relaxed);
+            }
+        }
+
+        if self.woken.load(Ordering::Relaxed) || self.pending.load(Ordering::Relaxed) == 0 {
+            return;
+        }
+
+        if tick_elapsed(now, self.streak_start.load(Ordering::Relaxed)) >= self.threshold {
+            self.woken.store(true, Ordering::Relaxed);
+            self.woken_at.store(now, Ordering::Relaxed);
+            unsafe {
+                TaskRemoteHandle::from_raw(self.task.load(Ordering::Relaxed) as FreeRtosTaskHandle)
+            }
+            .notify(TaskNotification::Increment);
+        }
+    }
+
+    fn run_one(&self) {
+        let mut work = match self.work.lock(Duration::infinite()) {
+            Ok(mut list) if !list.is_empty() => list.remove(0),
+            _ => return,
+        };
+
+        match work() {
+            WorkOutcome::MoreWork => {
+                // Back of the list, so registered work takes turns.
+                if let Ok(mut list) = self.work.lock(Duration::infinite()) {
+                    list.push(work);
+                }
+            }
+            WorkOutcome::Done => {
+                self.pending.fetch_sub(1, Ordering::Relaxed);
+            }
+        }
+    }
+}
+
+/// Runs maintenance work, like flushing persistence or draining deferred frees, only
+/// once the system has been idle for a while.
+///
+/// A crate-spawned task at priority 1, just above the idle task, sleeps until the idle
+/// hook sees an idle streak of at least the threshold. It then runs one unit of
+/// registered work and goes back to sleep, so the idle hook has to see the system idle
+/// again before the next unit. Work takes turns in the order it was registered.
+///
+/// Needs `configUSE_IDLE_HOOK` set to 1, the idle hook in shim.c feeds it.
+///
+/// Activity is detected by gaps between idle hook calls: while the system is idle the
+/// idle task calls the hook many times per tick, so a gap of more than one tick means
+/// another task ran. This is cheaper than a trace hook on every context switch, but
+/// bursts shorter than a tick go unseen. The worker itself counts as activity, except
+/// for a unit that finished within a tick of being woken with the idle task resuming
+/// right after; that keeps short units flowing back to back, while a unit that ran long
+/// or was preempted by real work restarts the streak.
+///
+/// With tickless idle, the worker counts as activity like any task and keeps the tick
+/// running while it works. A tickless sleep also shows up as a gap and restarts the
+/// streak, so work only runs in the time the idle task spends awake. Use a threshold of
+/// 0 there to run work as soon as the idle task does.
+///
+/// Only one worker can exist at a time.
+pub struct QuiescentWorker {
+    shared: Arc<QuiescentShared>,
+    task: TaskRemoteHandle,
+}
+
+impl QuiescentWorker {
+    /// Spawn the worker task. Fails with `StorageInUse` if another worker exists.
+    pub fn new<D: DurationTicks>(
+        os: FreeRTOS,
+        stack_size: usize,
+        idle_threshold: D,
+    ) -> Result<QuiescentWorker, FreeRtosError> {
+        if LISTENER.load(Ordering::Relaxed) != 0 {
+            return Err(FreeRtosError::StorageInUse);
+        }
+
+        let now = os.get_tick_count();
+        let shared = Arc::new(QuiescentShared {
+            work: Mutex::new(os, Vec::new())?,
+            pending: AtomicUsize::new(0),
+            task: AtomicUsize::new(0),
+            threshold: idle_threshold.to_ticks(),
+            last_idle: AtomicU32::new(now),
+            streak_start: AtomicU32::new(now),
+            woken: AtomicBool::new(false),
+            woken_at: AtomicU32::new(now),
+            unit_end: AtomicU32::new(now),
+            excused: AtomicBool::new(false),
+            shutdown: AtomicBool::new(false),
+        });
+
+        let task = {
+            let shared = shared.clone();
+            os.new_task("quiescent", stack_size, TaskPriority(1), move |this, os| {
+                run_worker(this, &shared, &os);
+                drop(shared);
+                unsafe { this.delete() }
+            })?
+        };
+        shared
+            .task
+            .store(task.raw_handle() as usize, Ordering::Relaxed);
+
+        {
+            let _region = CriticalRegion::enter();
+            if LISTENER.load(Ordering::Relaxed) == 0 {
+                LISTENER.store(Arc::as_ptr(&shared) as usize, Ordering::Release);
+            } else {
+                // Another worker was created meanwhile.
+                shared.shutdown.store(true, Ordering::Relaxed);
+                task.notify(TaskNotification::Increment);
+                return Err(FreeRtosError::StorageInUse);
+            }
+        }
+
+        Ok(QuiescentWorker { shared, task })
+    }
+
+    /// Add work to be run one unit at a time while the system is idle, until it returns
+    /// `WorkOutcome::Done`.
+    pub fn register<F>(&self, work: F) -> Result<(), FreeRtosError>
+    where
+        F: FnMut() -> WorkOutcome + Send + 'static,
+    {
+        let mut list = self.shared.work.lock(Duration::infinite())?;
+        list.push(Box::new(work));
+        self.shared.pending.fetch_add(1, Ordering::Relaxed);
+        Ok(())
+    }
+
+    /// How much registered work hasn't returned `Done` yet.
+    pub fn pending(&self) -> usize {
+        self.shared.pending.load(Ordering::Relaxed)
+    }
+
+    /// The task running the work.
+    pub fn task(&self) -> &TaskRemoteHandle {
+        &self.task
+    }
+}
+
+impl Drop for QuiescentWorker {
+    /// Detach from the idle hook and stop the worker task after its current unit.
+    /// Unfinished work is dropped with it.
+    fn drop(&mut self) {
+        {
+            let _region = CriticalRegion::enter();
+            LISTENER.store(0, Ordering::Release);
+        }
+
+        self.shared.shutdown.store(true, Ordering::Relaxed);
+        self.task.notify(TaskNotification::Increment);
+    }
+}
+
+fn run_worker(this: &TaskSelfHandle, shared: &QuiescentShared, os: &FreeRTOS) {
+    loop {
+        this.take_notification(true, Duration::infinite());
+        if shared.shutdown.load(Ordering::Relaxed) {
+            return;
+        }
+
+        shared.run_one();
+
+        let end = os.get_tick_count();
+        let woken_at = shared.woken_at.load(Ordering::Relaxed);
+        shared.unit_end.store(end, Ordering::Relaxed);
+        shared
+            .excused
+            .store(tick_elapsed(end, woken_at) <= 1, Ordering::Relaxed);
+        shared.woken.store(false, Ordering::Relaxed);
+    }
+}
+
+/// The idle hook listener of `QuiescentWorker`.
+pub(crate) fn quiescent_idle_hook() {
+    let _region = CriticalRegion::enter();
+    let shared = LISTENER.load(Ordering::Acquire) as *const QuiescentShared;
+    if !shared.is_null() {
+        unsafe { (*shared).on_idle(freertos_rs_xTaskGetTickCount()) };
+    }
+}
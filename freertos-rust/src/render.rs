@@ -0,0 +1,139 @@
+//! Incremental rendering of text tables into caller-sized chunks, for slow links like a
+//! debug UART.
+//!
+//! A table is a list of lines, each formatted on its own without allocating. A
+//! `RenderCursor` remembers the line and the byte within it that the next chunk starts
+//! at, so a table can be fed to a byte queue or stream buffer a few bytes at a time from
+//! a low-priority task or a fault handler. A line cut by the end of a chunk is formatted
+//! again for the next one, which only costs time for lines longer than the chunks.
+//!
+//! The cursor holds no reference to the table, so it works over a snapshot, like a
+//! `FreeRtosSchedulerState`, taken once and rendered over time. Rendering a different
+//! table with the same cursor picks up at the same line and byte.
+
+use crate::prelude::v1::*;
+
+/// How a table is rendered. `RenderOptions::default()` matches `{}`, `alternate` matches
+/// `{:#}`.
+#[derive(Debug, Default, Copy, Clone, PartialEq, Eq)]
+pub struct RenderOptions {
+    /// Show priorities that match a `PriorityBand` by name.
+    pub alternate: bool,
+}
+
+/// Where the next chunk of a table starts. Start with `RenderCursor::new()`.
+#[derive(Debug, Default, Copy, Clone, PartialEq, Eq)]
+pub struct RenderCursor {
+    line: usize,
+    offset: usize,
+}
+
+impl RenderCursor {
+    pub const fn new() -> RenderCursor {
+        RenderCursor { line: 0, offset: 0 }
+    }
+}
+
+/// A table that can be rendered line by line.
+pub(crate) trait Lines {
+    fn line_count(&self) -> usize;
+
+    /// Write line `index` to `w`. Must not allocate, and must write the same bytes every
+    /// time.
+    fn write_line(&self, index: usize, opts: RenderOptions, w: &mut dyn fmt::Write) -> fmt::Result;
+
+    /// Write every line, for `Display`.
+    fn write_all(&self, opts: RenderOptions, w: &mut dyn fmt::Write) -> fmt::Result {
+        for index in 0..self.line_count() {
+            self.write_line(index, opts, w)?;
+        }
+        Ok(())
+    }
+}
+
+/// Copies the bytes written to it from `skip` on into `out`, as far as they fit, and
+/// counts all of them.
+struct Window<'a> {
+    skip: usize,
+    out: &'a mut [u8],
+    written: usize,
+    total: usize,
+}
+
+impl<'a> fmt::Write for Window<'a> {
+    fn write_str(&mut self, s: &str) -> fmt::Result {
+        let bytes = s.as_bytes();
+        let start = self.total;
+        self.total += bytes.len();
+
+        let from = self.skip.max(start) - start;
+        if from < bytes.len() {
+            let count = (bytes.len() - from).min(self.out.len() - self.written);
+            self.out[self.written..self.written + count]
+                .copy_from_slice(&bytes[from..from + count]);
+            self.written += count;
+        }
+        Ok(())
+    }
+}
+
+/// Fill `buf` with the next bytes of `lines`. Returns the number of bytes written, or
+/// `None` once everything was rendered.
+pub(crate) fn render_next<L: Lines + ?Sized>(
+    lines: &L,
+    opts: RenderOptions,
+    cursor: &mut RenderCursor,
+    buf: &mut [u8],
+) -> Option<usize> {
+    let mut filled = 0;
+    while cursor.line < lines.line_count() && filled < buf.len() {
+        let mut window = Window {
+            skip: cursor.offset,
+            out: &mut buf[filled..],
+            written: 0,
+            total: 0,
+        };
+        let _ = lines.write_line(cursor.line, opts, &mut window);
+        filled += window.written;
+
+        if cursor.offset + window.written >= window.total {
+            cursor.line += 1;
+            cursor.offset = 0;
+        } else {
+            cursor.offset += window.written;
+        }
+    }
+
+    if filled == 0 && cursor.line >= lines.line_count() {
+        None
+    } else {
+        Some(filled)
+    }
+}
+
+/// The number of bytes `render_next` produces for `lines` in total.
+pub(crate) fn rendered_size<L: Lines + ?Sized>(lines: &L, opts: RenderOptions) -> usize {
+    let mut window = Window {
+        skip: usize::MAX,
+        out: &mut [],
+        written: 0,
+        total: 0,
+    };
+    let _ = lines.write_all(opts, &mut window);
+    window.total
+}
+
+/// A table cell padded like a `String`, without being formatted into one.
+pub(crate) enum Cell<'a> {
+    Text(&'a str),
+    Number(u64),
+}
+
+impl<'a> fmt::Display for Cell<'a> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Cell::Text(text) => f.pad(text),
+            Cell::Number(number) => fmt::Display::fmt(number, f),
+        }
+    }
+}
@@ -0,0 +1,73 @@
+use crate::base::*;
+use crate::isr::*;
+use crate::mutex::*;
+use crate::operating_system::*;
+use crate::queue::*;
+use crate::semaphore::*;
+use crate::shim::*;
+use crate::units::*;
+
+impl<T: Sized + Copy> !ISRSafe for Rendezvous<T> {}
+
+/// A zero-capacity channel: `send` only returns once a receiver has taken the value.
+///
+/// Built from a one-deep `Queue` carrying the value and a `BinarySemaphore` the receiver
+/// gives to acknowledge it. Senders take turns through a `Mutex`, so each acknowledgment
+/// belongs to the one value in flight. When no receiver takes the value in time, the
+/// sender takes it back out of the queue, so a later `receive` never sees a value whose
+/// `send` failed.
+pub struct Rendezvous<T: Sized + Copy> {
+    slot: Queue<T>,
+    ack: BinarySemaphore,
+    sender: Mutex<()>,
+}
+
+impl<T: Sized + Copy> Rendezvous<T> {
+    pub fn new(os: FreeRTOS) -> Result<Rendezvous<T>, FreeRtosError> {
+        // A new binary semaphore is already taken, so it starts without acknowledgment.
+        Ok(Rendezvous {
+            slot: Queue::new(os, 1)?,
+            ack: BinarySemaphore::new(os)?,
+            sender: Mutex::new(os, ())?,
+        })
+    }
+
+    /// Hand `item` to a receiver, waiting up to `max_wait` for one to take it. Fails with
+    /// `RendezvousSendTimeout` if none did, in which case no receiver gets the value.
+    pub fn send<D: DurationTicks>(&self, item: T, max_wait: D) -> Result<(), FreeRtosError> {
+        let start = unsafe { freertos_rs_xTaskGetTickCount() };
+        let timeout = max_wait.to_ticks();
+
+        let _turn = self
+            .sender
+            .lock(Duration::ticks(timeout))
+            .map_err(|_| FreeRtosError::RendezvousSendTimeout)?;
+        // The slot is empty between turns, so this doesn't block.
+        self.slot
+            .send(item, Duration::zero())
+            .map_err(|_| FreeRtosError::RendezvousSendTimeout)?;
+
+        let left = remaining_wait(start, timeout).unwrap_or_else(Duration::zero);
+        if self.ack.take(left).is_ok() {
+            return Ok(());
+        }
+
+        if self.slot.receive(Duration::zero()).is_ok() {
+            return Err(FreeRtosError::RendezvousSendTimeout);
+        }
+        // A receiver took the value after all, and gives the acknowledgment right after.
+        let _ = self.ack.take(Duration::infinite());
+        Ok(())
+    }
+
+    /// Wait up to `max_wait` for a sender, failing with `RendezvousReceiveTimeout` if
+    /// none came.
+    pub fn receive<D: DurationTicks>(&self, max_wait: D) -> Result<T, FreeRtosError> {
+        let item = self
+            .slot
+            .receive(max_wait)
+            .map_err(|_| FreeRtosError::RendezvousReceiveTimeout)?;
+        self.ack.give();
+        Ok(item)
+    }
+}
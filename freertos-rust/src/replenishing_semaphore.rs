@@ -0,0 +1,130 @@
+use crate::base::*;
+use crate::critical::*;
+use crate::isr::*;
+use crate::operating_system::*;
+use crate::prelude::v1::*;
+use crate::semaphore::*;
+use crate::sync::atomic::{AtomicU32, Ordering};
+use crate::ticks::*;
+use crate::timers::*;
+use crate::units::*;
+
+impl !ISRSafe for ReplenishingSemaphore {}
+
+/// A counting semaphore that models a budget of credits, refilled by a software timer.
+///
+/// Every replenish period `replenish_amount` permits are given back, but never above
+/// `capacity`, so unused credit does not accumulate past the configured burst size.
+pub struct ReplenishingSemaphore {
+    semaphore: Arc<CountingSemaphore>,
+    pending: Arc<ExclusiveData<Option<ReplenishRate>>>,
+    capacity: u32,
+    timer: Timer,
+    os: FreeRTOS,
+}
+
+/// A rate set by `set_replenish`, which the timer switches to at the end of its period.
+#[derive(Copy, Clone)]
+struct ReplenishRate {
+    amount: u32,
+    period: FreeRtosTickType,
+}
+
+impl ReplenishingSemaphore {
+    /// Create a new semaphore, initially full, and start its replenish timer.
+    pub fn new<D: DurationTicks>(
+        os: FreeRTOS,
+        capacity: u32,
+        replenish_amount: u32,
+        replenish_period: D,
+    ) -> Result<ReplenishingSemaphore, FreeRtosError> {
+        let semaphore = Arc::new(CountingSemaphore::new(os, capacity, capacity)?);
+        let pending = Arc::new(ExclusiveData::new(None::<ReplenishRate>));
+
+        let timer = {
+            let semaphore = semaphore.clone();
+            let pending = pending.clone();
+            // Only the timer's callback uses the amount, the rate of the running period.
+            let amount = AtomicU32::new(replenish_amount);
+
+            TimerBuilder::new(os, Duration::ticks(replenish_period.to_ticks()))
+                .set_name("replenish")
+                .set_auto_reload(true)
+                .create(move |timer| {
+                    for _ in 0..amount.load(Ordering::Relaxed) {
+                        if !semaphore.try_give() {
+                            break;
+                        }
+                    }
+
+                    // The period that just ended was credited at the old rate, the one
+                    // starting now runs at the new one.
+                    let rate = match pending.lock(&os) {
+                        Ok(mut pending) => pending.take(),
+                        Err(_) => None,
+                    };
+                    if let Some(rate) = rate {
+                        if timer.change_period(Duration::ticks(rate.period)).is_ok() {
+                            amount.store(rate.amount, Ordering::Relaxed);
+                        } else if let Ok(mut pending) = pending.lock(&os) {
+                            // The command queue is full, try again at the next boundary,
+                            // unless the rate was set again meanwhile.
+                            pending.get_or_insert(rate);
+                        }
+                    }
+                })?
+        };
+
+        timer.start(Duration::infinite())?;
+
+        Ok(ReplenishingSemaphore {
+            semaphore,
+            pending,
+            capacity,
+            timer,
+            os,
+        })
+    }
+
+    /// Take one credit, waiting up to `timeout` for the next replenish.
+    pub fn acquire<D: DurationTicks>(&self, timeout: D) -> Result<(), FreeRtosError> {
+        self.semaphore.take(timeout)
+    }
+
+    /// Take one credit if one is available right now.
+    pub fn try_acquire(&self) -> bool {
+        self.semaphore.take(Duration::zero()).is_ok()
+    }
+
+    /// The number of credits currently available.
+    pub fn available(&self) -> u32 {
+        self.semaphore.get_count()
+    }
+
+    /// The maximum number of credits that can be held at once.
+    pub fn capacity(&self) -> u32 {
+        self.capacity
+    }
+
+    /// Time left until the next replenish.
+    pub fn next_replenish_in(&self, os: &FreeRTOS) -> Duration {
+        let expiry = self.timer.get_expiry_time().to_ticks();
+        Duration::ticks(tick_remaining(os.get_tick_count(), expiry))
+    }
+
+    /// Change the replenish amount and period.
+    ///
+    /// The new rate takes effect at the next period boundary: the replenish ending the
+    /// current period still gives the old amount, then the timer runs the new period and
+    /// gives the new amount at its end. Setting the rate again before that boundary
+    /// replaces the pending one.
+    pub fn set_replenish<D: DurationTicks>(
+        &self,
+        amount: u32,
+        period: D,
+    ) -> Result<(), FreeRtosError> {
+        let period = period.to_ticks();
+        *self.pending.lock(&self.os)? = Some(ReplenishRate { amount, period });
+        Ok(())
+    }
+}
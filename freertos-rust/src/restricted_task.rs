@@ -0,0 +1,157 @@
+use crate::base::*;
+use crate::operating_system::*;
+use crate::prelude::v1::*;
+use crate::shim::*;
+use crate::task::*;
+
+/// The code of a restricted task, implemented by the context it runs with.
+///
+/// An unprivileged task only reads memory granted to it: its stack, its regions and the
+/// flash. So the task gets no boxed closure from the heap, but `run` on a `&'static`
+/// context, which must be readable by the task as well. A `static` without interior
+/// mutability is placed in flash; others need a region.
+pub trait RestrictedTaskEntry: Sync + 'static {
+    fn run(&'static self, this: &TaskSelfHandle, os: FreeRTOS) -> !;
+}
+
+/// The number of regions a restricted task can be granted besides its stack,
+/// `portNUM_CONFIGURABLE_REGIONS` of the port.
+pub fn max_mpu_regions() -> usize {
+    unsafe { freertos_rs_get_configurable_regions() as usize }
+}
+
+/// Creates a task with `xTaskCreateRestricted`, on a port with an MPU.
+///
+/// The task runs on the given stack, which the port grants it as a region of its own.
+/// The ARMv7-M ports need the stack's size in bytes to be a power of two, and the stack
+/// aligned to it. Up to `max_mpu_regions` more regions grant it other memory, like
+/// buffers or peripherals.
+///
+/// Unprivileged by default, the task can't touch kernel or crate state, and calls into
+/// the kernel through its system call wrappers. Tasks started any other way are
+/// privileged with the `mpu` feature, as they run closures from the heap.
+///
+///     static WORKER: Worker = Worker { .. };
+///
+///     RestrictedTaskBuilder::new(os, stack)
+///         .name("worker")
+///         .priority(TaskPriority(2))
+///         .region(MemoryRegion { base, length: 256, parameters: READ_WRITE })
+///         .start(&WORKER)?;
+pub struct RestrictedTaskBuilder {
+    name: String,
+    stack: &'static mut [usize],
+    priority: TaskPriority,
+    privileged: bool,
+    regions: Vec<MemoryRegion>,
+}
+
+impl RestrictedTaskBuilder {
+    /// A builder for an unprivileged task on `stack`, with the default name and priority
+    /// of `TaskBuilder` and no regions.
+    pub fn new(_os: FreeRTOS, stack: &'static mut [usize]) -> RestrictedTaskBuilder {
+        RestrictedTaskBuilder {
+            name: TaskBuilder::DEFAULT_NAME.into(),
+            stack,
+            priority: TaskBuilder::DEFAULT_PRIORITY,
+            privileged: false,
+            regions: Vec::new(),
+        }
+    }
+
+    /// Set the name of the task. Starting the task fails with `NameTooLong` if it is
+    /// longer than `max_name_len`, or with `InvalidName` if it has a nul byte.
+    pub fn name(mut self, name: &str) -> Self {
+        self.name = name.into();
+        self
+    }
+
+    /// Set the priority of the task, as a `TaskPriority` or a `PriorityBand`.
+    pub fn priority<P: Into<TaskPriority>>(mut self, priority: P) -> Self {
+        self.priority = priority.into();
+        self
+    }
+
+    /// Run the task in privileged mode, where the MPU only keeps it to its stack.
+    pub fn privileged(mut self, privileged: bool) -> Self {
+        self.privileged = privileged;
+        self
+    }
+
+    /// Grant the task `region`. Starting the task fails with `TooManyRegions` if it is
+    /// given more than `max_mpu_regions`.
+    pub fn region(mut self, region: MemoryRegion) -> Self {
+        self.regions.push(region);
+        self
+    }
+
+    /// Create the task, running `entry`. Fails with `StackTooLarge` if the stack doesn't
+    /// fit in `FreeRtosStackDepthType`, and with `OutOfMemory` if the kernel couldn't
+    /// allocate the task's control block.
+    pub fn start<C: RestrictedTaskEntry>(
+        self,
+        entry: &'static C,
+    ) -> Result<TaskRemoteHandle, FreeRtosError> {
+        check_name(&self.name)?;
+        if self.regions.len() > max_mpu_regions() {
+            return Err(FreeRtosError::TooManyRegions);
+        }
+        let stack_type_size = unsafe { freertos_rs_stack_type_size() } as usize;
+        if stack_type_size > mem::size_of::<usize>() {
+            return Err(FreeRtosError::OutOfMemory);
+        }
+        let stack_depth = mem::size_of_val(self.stack) / stack_type_size;
+        if stack_depth > FreeRtosStackDepthType::MAX as usize {
+            return Err(FreeRtosError::StackTooLarge);
+        }
+
+        extern "C" fn restricted_thread_start<C: RestrictedTaskEntry>(
+            entry: *mut CVoid,
+        ) -> *mut CVoid {
+            unsafe {
+                let entry = &*(entry as *const C);
+                entry.run(&TaskSelfHandle::current(), FreeRTOS::assume_init());
+            }
+        }
+
+        let name = self.name.as_bytes();
+        let task = FreeRtosRestrictedTaskFfi {
+            entry_point: restricted_thread_start::<C>,
+            parameters: entry as *const C as FreeRtosMutVoidPtr,
+            name: name.as_ptr(),
+            name_len: name.len().min(u8::MAX as usize) as u8,
+            stack_depth: stack_depth as FreeRtosStackDepthType,
+            priority: self.priority.to_freertos(),
+            privileged: self.privileged as u8,
+            stack: self.stack.as_mut_ptr() as FreeRtosMutVoidPtr,
+            regions: self.regions.as_ptr(),
+            region_count: self.regions.len() as FreeRtosUBaseType,
+        };
+
+        let mut task_handle = ptr::null();
+        match unsafe { freertos_rs_spawn_task_restricted(&task, &mut task_handle) } {
+            0 => Ok(unsafe { TaskRemoteHandle::from_raw(task_handle) }),
+            2 => Err(FreeRtosError::TooManyRegions),
+            _ => Err(FreeRtosError::OutOfMemory),
+        }
+    }
+}
+
+impl TaskRemoteHandle {
+    /// Replace the regions granted to a restricted task with `regions`, with
+    /// `vTaskAllocateMPURegions`. Its stack stays granted. Fails with `TooManyRegions` if
+    /// there are more than `max_mpu_regions`.
+    pub fn allocate_mpu_regions(&self, regions: &[MemoryRegion]) -> Result<(), FreeRtosError> {
+        let failed = unsafe {
+            freertos_rs_allocate_mpu_regions(
+                self.raw_handle(),
+                regions.as_ptr(),
+                regions.len() as FreeRtosUBaseType,
+            )
+        };
+        if failed != 0 {
+            return Err(FreeRtosError::TooManyRegions);
+        }
+        Ok(())
+    }
+}
@@ -1,50 +1,108 @@
 use crate::base::*;
 use crate::isr::*;
+use crate::no_block::check_blocking;
 use crate::operating_system::*;
+use crate::prelude::v1::*;
+#[cfg(feature = "static_allocation")]
+use crate::queue::{static_control_block_fits, STATIC_QUEUE_CONTROL_WORDS};
+use crate::queue_registry::*;
+use crate::queue_set::SetMembership;
 use crate::shim::*;
+use crate::task::check_name_nul;
 use crate::units::*;
 use core::fmt::Debug;
 
-pub trait Semaphore<D: DurationTicks>: Send + Sync + Debug {
+/// The operations binary and counting semaphores share.
+///
+/// The trait isn't generic over the duration type, so `dyn Semaphore` works, e.g. to keep
+/// semaphores of both kinds in one `Vec<&dyn Semaphore>`. Waiting with any
+/// `DurationTicks` comes from `SemaphoreExt`, which every semaphore implements, trait
+/// objects included.
+pub trait Semaphore: Send + Sync + Debug {
     fn raw_handle(&self) -> FreeRtosSemaphoreHandle;
 
+    /// Take the semaphore, waiting up to `max_wait` ticks. Fails with `Timeout`.
+    #[inline]
+    fn take_ticks(&self, max_wait: FreeRtosTickType) -> Result<(), FreeRtosError> {
+        semaphore_take("Semaphore::take", self.raw_handle(), max_wait)
+    }
+
+    fn give(&self) {
+        unsafe {
+            freertos_rs_give_semaphore(self.raw_handle());
+        }
+    }
+}
+
+/// `take` and `lock` with the wait as any `DurationTicks`, for every `Semaphore`.
+pub trait SemaphoreExt: Semaphore {
     /// Lock this semaphore in a RAII fashion
-    fn lock(&self, max_wait: D) -> Result<SemaphoreGuard<D>, FreeRtosError>
-    where
-        Self: Sized,
-    {
-        self.take(max_wait)?;
+    fn lock<D: DurationTicks>(&self, max_wait: D) -> Result<SemaphoreGuard<'_>, FreeRtosError>;
 
-        Ok(SemaphoreGuard { __semaphore: self })
+    fn take<D: DurationTicks>(&self, max_wait: D) -> Result<(), FreeRtosError>;
+}
+
+impl<S: Semaphore + ?Sized> SemaphoreExt for S {
+    fn lock<D: DurationTicks>(&self, max_wait: D) -> Result<SemaphoreGuard<'_>, FreeRtosError> {
+        self.take_ticks(max_wait.to_ticks())?;
+
+        Ok(SemaphoreGuard {
+            semaphore: self.raw_handle(),
+            _borrow: PhantomData,
+        })
     }
 
-    fn take(&self, max_wait: D) -> Result<(), FreeRtosError> {
-        unsafe {
-            let res = freertos_rs_take_semaphore(self.raw_handle(), max_wait.to_ticks());
+    #[inline]
+    fn take<D: DurationTicks>(&self, max_wait: D) -> Result<(), FreeRtosError> {
+        self.take_ticks(max_wait.to_ticks())
+    }
+}
 
-            if res == 0 {
-                Ok(())
-            } else {
-                Err(FreeRtosError::Timeout)
-            }
-        }
+/// The body of `Semaphore::take`, shared by all semaphores and durations.
+pub(crate) fn semaphore_take(
+    api: &'static str,
+    semaphore: FreeRtosSemaphoreHandle,
+    max_wait: FreeRtosTickType,
+) -> Result<(), FreeRtosError> {
+    check_blocking(api, semaphore, max_wait);
+
+    if unsafe { freertos_rs_take_semaphore(semaphore, max_wait) } != 0 {
+        return Err(timed_out());
     }
+    Ok(())
+}
 
-    fn give(&self) {
+/// Holds the lock to the semaphore until we are dropped
+pub struct SemaphoreGuard<'a> {
+    semaphore: FreeRtosSemaphoreHandle,
+    _borrow: PhantomData<&'a ()>,
+}
+
+impl<'a> Drop for SemaphoreGuard<'a> {
+    fn drop(&mut self) {
         unsafe {
-            freertos_rs_give_semaphore(self.raw_handle());
+            freertos_rs_give_semaphore(self.semaphore);
         }
     }
 }
 
-/// Holds the lock to the semaphore until we are dropped
-pub struct SemaphoreGuard<'a, D: DurationTicks + Sized> {
-    __semaphore: &'a dyn Semaphore<D>,
+/// Memory for a statically allocated semaphore or mutex, for `BinarySemaphore::new_static`
+/// and `Mutex::new_static`.
+#[cfg(feature = "static_allocation")]
+pub struct StaticSemaphoreStorage {
+    control: [usize; STATIC_QUEUE_CONTROL_WORDS],
 }
 
-impl<'a, D: DurationTicks> Drop for SemaphoreGuard<'a, D> {
-    fn drop(&mut self) {
-        self.__semaphore.give();
+#[cfg(feature = "static_allocation")]
+impl StaticSemaphoreStorage {
+    pub const fn new() -> StaticSemaphoreStorage {
+        StaticSemaphoreStorage {
+            control: [0; STATIC_QUEUE_CONTROL_WORDS],
+        }
+    }
+
+    pub(crate) fn as_mut_ptr(&mut self) -> FreeRtosMutVoidPtr {
+        self.control.as_mut_ptr() as FreeRtosMutVoidPtr
     }
 }
 
@@ -52,6 +110,8 @@ impl<'a, D: DurationTicks> Drop for SemaphoreGuard<'a, D> {
 #[derive(Debug)]
 pub struct BinarySemaphore {
     semaphore: FreeRtosSemaphoreHandle,
+    registry: Option<RegistryEntry>,
+    pub(crate) set: SetMembership,
 }
 
 unsafe impl Send for BinarySemaphore {}
@@ -59,14 +119,24 @@ unsafe impl Sync for BinarySemaphore {}
 
 impl !ISRSafe for BinarySemaphore {}
 
-impl<D: DurationTicks> Semaphore<D> for BinarySemaphore {
+impl Semaphore for BinarySemaphore {
     fn raw_handle(&self) -> FreeRtosSemaphoreHandle {
         self.semaphore
     }
+
+    /// Fails with `InQueueSet` for a blocking take while in a `QueueSet`.
+    fn take_ticks(&self, max_wait: FreeRtosTickType) -> Result<(), FreeRtosError> {
+        self.set.check_read(max_wait)?;
+        semaphore_take("Semaphore::take", self.semaphore, max_wait)
+    }
 }
 
 impl Drop for BinarySemaphore {
     fn drop(&mut self) {
+        self.registry = None;
+        if !self.set.leave(self.semaphore) {
+            return;
+        }
         unsafe {
             freertos_rs_delete_semaphore(self.semaphore);
         }
@@ -81,10 +151,52 @@ impl BinarySemaphore {
             if s == 0 as *const _ {
                 return Err(FreeRtosError::OutOfMemory);
             }
-            Ok(BinarySemaphore { semaphore: s })
+            Ok(BinarySemaphore {
+                semaphore: s,
+                registry: None,
+                set: SetMembership::new(),
+            })
         }
     }
 
+    /// Create a new binary semaphore in caller-provided storage instead of the FreeRTOS heap.
+    #[cfg(feature = "static_allocation")]
+    pub fn new_static(
+        _os: FreeRTOS,
+        storage: &'static mut StaticSemaphoreStorage,
+    ) -> Result<BinarySemaphore, FreeRtosError> {
+        if !static_control_block_fits() {
+            return Err(FreeRtosError::OutOfMemory);
+        }
+
+        unsafe {
+            let s = freertos_rs_create_binary_semaphore_static(storage.as_mut_ptr());
+            if s == 0 as *const _ {
+                return Err(FreeRtosError::OutOfMemory);
+            }
+            Ok(BinarySemaphore {
+                semaphore: s,
+                registry: None,
+                set: SetMembership::new(),
+            })
+        }
+    }
+
+    /// Create a new binary semaphore and add it to the kernel queue registry under
+    /// `name`, see `Queue::new_named`.
+    pub fn new_named(os: FreeRTOS, name: &str) -> Result<BinarySemaphore, FreeRtosError> {
+        check_name_nul(name)?;
+        let mut semaphore = BinarySemaphore::new(os)?;
+        semaphore.registry = RegistryEntry::new(semaphore.semaphore, name);
+        Ok(semaphore)
+    }
+
+    /// The name this semaphore has in the kernel queue registry, see
+    /// `Queue::registered_name`.
+    pub fn registered_name(&self) -> Option<String> {
+        registered_name(self.semaphore)
+    }
+
     pub fn is_taken(&self) -> bool {
         unsafe { freertos_rs_semaphore_get_count(self.semaphore) == 0 }
     }
@@ -103,7 +215,7 @@ impl ISRBinarySemaphore {
         if self.take_isr(context) {
             closure();
 
-            self.give_isr(context);
+            self.give(context);
             true
         } else {
             false
@@ -119,10 +231,10 @@ impl ISRBinarySemaphore {
         }
     }
 
-    fn give_isr(&self, context: &mut InterruptContext) {
-        unsafe {
-            freertos_rs_give_semaphore_isr(self.semaphore, context.get_task_field_mut());
-        }
+    /// Give the semaphore, waking a task waiting for it. Returns false if it was
+    /// already available.
+    pub fn give(&self, context: &mut InterruptContext) -> bool {
+        unsafe { freertos_rs_give_semaphore_isr(self.semaphore, context.get_task_field_mut()) == 0 }
     }
 }
 
@@ -138,6 +250,8 @@ impl ISRSafeHandle<ISRBinarySemaphore> for BinarySemaphore {
 #[derive(Debug)]
 pub struct CountingSemaphore {
     semaphore: FreeRtosSemaphoreHandle,
+    registry: Option<RegistryEntry>,
+    pub(crate) set: SetMembership,
 }
 
 unsafe impl Send for CountingSemaphore {}
@@ -145,14 +259,24 @@ unsafe impl Sync for CountingSemaphore {}
 
 impl !ISRSafe for CountingSemaphore {}
 
-impl<D: DurationTicks> Semaphore<D> for CountingSemaphore {
+impl Semaphore for CountingSemaphore {
     fn raw_handle(&self) -> FreeRtosSemaphoreHandle {
         self.semaphore
     }
+
+    /// Fails with `InQueueSet` for a blocking take while in a `QueueSet`.
+    fn take_ticks(&self, max_wait: FreeRtosTickType) -> Result<(), FreeRtosError> {
+        self.set.check_read(max_wait)?;
+        semaphore_take("Semaphore::take", self.semaphore, max_wait)
+    }
 }
 
 impl Drop for CountingSemaphore {
     fn drop(&mut self) {
+        self.registry = None;
+        if !self.set.leave(self.semaphore) {
+            return;
+        }
         unsafe {
             freertos_rs_delete_semaphore(self.semaphore);
         }
@@ -167,11 +291,74 @@ impl CountingSemaphore {
             if s == 0 as *const _ {
                 return Err(FreeRtosError::OutOfMemory);
             }
-            Ok(CountingSemaphore { semaphore: s })
+            Ok(CountingSemaphore {
+                semaphore: s,
+                registry: None,
+                set: SetMembership::new(),
+            })
         }
     }
 
+    /// Create a new counting semaphore and add it to the kernel queue registry under
+    /// `name`, see `Queue::new_named`.
+    pub fn new_named(
+        os: FreeRTOS,
+        max: u32,
+        initial: u32,
+        name: &str,
+    ) -> Result<CountingSemaphore, FreeRtosError> {
+        check_name_nul(name)?;
+        let mut semaphore = CountingSemaphore::new(os, max, initial)?;
+        semaphore.registry = RegistryEntry::new(semaphore.semaphore, name);
+        Ok(semaphore)
+    }
+
+    /// The name this semaphore has in the kernel queue registry, see
+    /// `Queue::registered_name`.
+    pub fn registered_name(&self) -> Option<String> {
+        registered_name(self.semaphore)
+    }
+
     pub fn get_count(&self) -> u32 {
         unsafe { freertos_rs_semaphore_get_count(self.semaphore) }
     }
+
+    /// Give the semaphore. Returns false if it was already at its maximum count.
+    pub fn try_give(&self) -> bool {
+        unsafe { freertos_rs_give_semaphore(self.semaphore) == 0 }
+    }
+}
+
+/// An ISR safe handle to a counting semaphore.
+pub struct ISRCountingSemaphore {
+    semaphore: FreeRtosSemaphoreHandle,
+}
+
+unsafe impl Send for ISRCountingSemaphore {}
+unsafe impl Sync for ISRCountingSemaphore {}
+
+impl ISRCountingSemaphore {
+    /// Give the semaphore, waking a task waiting for it. Returns false if it was
+    /// already at its maximum count.
+    pub fn give(&self, context: &mut InterruptContext) -> bool {
+        unsafe { freertos_rs_give_semaphore_isr(self.semaphore, context.get_task_field_mut()) == 0 }
+    }
+
+    /// Take one from the count. Returns false if it was zero, an interrupt can't wait.
+    pub fn take(&self, context: &mut InterruptContext) -> bool {
+        unsafe { freertos_rs_take_semaphore_isr(self.semaphore, context.get_task_field_mut()) == 0 }
+    }
+
+    /// The count of the semaphore, read from an interrupt.
+    pub fn count_from_isr(&self, _context: &InterruptContext<KernelIsr>) -> u32 {
+        unsafe { freertos_rs_semaphore_get_count_isr(self.semaphore) }
+    }
+}
+
+impl ISRSafeHandle<ISRCountingSemaphore> for CountingSemaphore {
+    unsafe fn new_isr_safe_handle(&self) -> ISRCountingSemaphore {
+        ISRCountingSemaphore {
+            semaphore: self.semaphore,
+        }
+    }
 }
@@ -0,0 +1,170 @@
+use crate::base::*;
+use crate::critical::*;
+use crate::operating_system::*;
+use crate::prelude::v1::*;
+use crate::shim::*;
+use crate::stats::*;
+use crate::sync::atomic::{AtomicBool, AtomicU32, Ordering};
+use crate::task::*;
+use crate::ticks::*;
+use crate::units::*;
+
+/// Reported when a task took longer than its budget to service an item sent from an interrupt.
+#[derive(Debug)]
+pub struct ServiceBudgetViolation {
+    /// How long the item waited before being received.
+    pub observed: Duration,
+    pub budget: Duration,
+    /// The task that received the item late.
+    pub consumer: TaskRemoteHandle,
+    /// The consumer's priority at the time it received the item. If this is lower than
+    /// the tasks that kept it from running, it is a candidate for raising.
+    pub consumer_priority: TaskPriority,
+}
+
+/// The part of a service budget shared with ISR handles.
+pub(crate) struct ServiceBudgetState {
+    /// Budget in ticks, zero when disabled.
+    budget: AtomicU32,
+    pending: AtomicBool,
+    stamp: AtomicU32,
+    violations: AtomicU32,
+    /// Latencies seen by the consumer task.
+    observed: ExclusiveData<RunningStats>,
+    reported: AtomicBool,
+    last_report: AtomicU32,
+}
+
+impl ServiceBudgetState {
+    /// Stamp the time an item was sent from an interrupt, unless an earlier stamp is
+    /// still waiting to be serviced.
+    pub(crate) fn stamp_isr(&self) {
+        if self.budget.load(Ordering::Relaxed) == 0 || self.pending.load(Ordering::Relaxed) {
+            return;
+        }
+
+        self.stamp.store(
+            unsafe { freertos_rs_xTaskGetTickCountFromISR() },
+            Ordering::Relaxed,
+        );
+        self.pending.store(true, Ordering::Release);
+    }
+}
+
+/// Tracks how long items sent from interrupts wait before a task picks them up.
+///
+/// Only the oldest unserviced item is stamped, so the cost is one tick read per ISR send
+/// and one compare per receive.
+pub(crate) struct ServiceBudget {
+    state: Box<ServiceBudgetState>,
+    on_violation: Box<dyn Fn(&ServiceBudgetViolation) + Send + Sync>,
+}
+
+impl ServiceBudget {
+    /// Violations are reported to the callback at most this often.
+    const REPORT_INTERVAL_MS: u32 = 1000;
+
+    pub(crate) fn new<F>(budget: FreeRtosTickType, on_violation: F) -> ServiceBudget
+    where
+        F: Fn(&ServiceBudgetViolation) + Send + Sync + 'static,
+    {
+        ServiceBudget {
+            state: Box::new(ServiceBudgetState {
+                budget: AtomicU32::new(budget),
+                pending: AtomicBool::new(false),
+                stamp: AtomicU32::new(0),
+                violations: AtomicU32::new(0),
+                observed: ExclusiveData::new(RunningStats::new()),
+                reported: AtomicBool::new(false),
+                last_report: AtomicU32::new(0),
+            }),
+            on_violation: Box::new(on_violation),
+        }
+    }
+
+    /// Change the budget and callback. The state stays where it is, so existing ISR
+    /// handles keep working.
+    pub(crate) fn set<F>(&mut self, budget: FreeRtosTickType, on_violation: F)
+    where
+        F: Fn(&ServiceBudgetViolation) + Send + Sync + 'static,
+    {
+        self.state.budget.store(budget, Ordering::Relaxed);
+        self.on_violation = Box::new(on_violation);
+    }
+
+    pub(crate) fn disable(&self) {
+        self.state.budget.store(0, Ordering::Relaxed);
+        self.state.pending.store(false, Ordering::Relaxed);
+    }
+
+    pub(crate) fn state(&self) -> *const ServiceBudgetState {
+        &*self.state as *const _
+    }
+
+    /// Called by the consumer task after it received an item.
+    pub(crate) fn serviced(&self) {
+        let state = &*self.state;
+        if !state.pending.swap(false, Ordering::Acquire) {
+            return;
+        }
+
+        let budget = state.budget.load(Ordering::Relaxed);
+        // The stamp is read first, so it can't be later than `now`.
+        let stamp = state.stamp.load(Ordering::Relaxed);
+        let now = unsafe { freertos_rs_xTaskGetTickCount() };
+        let observed = tick_elapsed(now, stamp);
+        let os = unsafe { FreeRTOS::assume_init() };
+        if let Ok(mut stats) = state.observed.lock(&os) {
+            stats.record(observed);
+        }
+
+        if budget == 0 || observed <= budget {
+            return;
+        }
+
+        state.violations.fetch_add(1, Ordering::Relaxed);
+
+        let interval = Duration::ms(Self::REPORT_INTERVAL_MS).to_ticks();
+        if state.reported.load(Ordering::Relaxed)
+            && tick_elapsed(now, state.last_report.load(Ordering::Relaxed)) < interval
+        {
+            return;
+        }
+        state.reported.store(true, Ordering::Relaxed);
+        state.last_report.store(now, Ordering::Relaxed);
+
+        unsafe {
+            let consumer = freertos_rs_get_current_task();
+            (self.on_violation)(&ServiceBudgetViolation {
+                observed: Duration::ticks(observed),
+                budget: Duration::ticks(budget),
+                consumer: TaskRemoteHandle::from_raw(consumer),
+                consumer_priority: TaskPriority(freertos_rs_task_priority_get(consumer) as u8),
+            });
+        }
+    }
+
+    pub(crate) fn violations(&self) -> u32 {
+        self.state.violations.load(Ordering::Relaxed)
+    }
+
+    pub(crate) fn observed(&self) -> RunningStats {
+        let os = unsafe { FreeRTOS::assume_init() };
+        self.state
+            .observed
+            .lock(&os)
+            .map(|s| *s)
+            .unwrap_or_default()
+    }
+}
+
+impl fmt::Debug for ServiceBudget {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(
+            f,
+            "ServiceBudget {{ budget: {}, violations: {} }}",
+            self.state.budget.load(Ordering::Relaxed),
+            self.violations()
+        )
+    }
+}
@@ -1,12 +1,34 @@
 #![allow(non_snake_case)]
 
+#[cfg(feature = "heap_5")]
+use crate::allocator::HeapRegion;
+use crate::allocator::HeapStats;
 use crate::base::*;
 
+/// Decides whether an intercepted shim call fails, see `fault_inject`.
+#[cfg(feature = "fault_inject")]
+pub type FaultHook = extern "C" fn(target: u8, object: FreeRtosVoidPtr, arg: usize) -> u8;
+
 extern "C" {
     pub fn freertos_rs_invoke_configASSERT();
-    pub fn freertos_rs_vTaskStartScheduler() -> !;
+    pub fn freertos_rs_vTaskStartScheduler();
+    #[cfg(feature = "end_scheduler")]
+    pub fn freertos_rs_vTaskEndScheduler() -> !;
+    #[cfg(feature = "end_scheduler")]
+    pub fn freertos_rs_task_keep_thread();
     pub fn freertos_rs_pvPortMalloc(xWantedSize: FreeRtosUBaseType) -> FreeRtosVoidPtr;
     pub fn freertos_rs_vPortFree(pv: FreeRtosVoidPtr);
+    pub fn freertos_rs_xPortGetFreeHeapSize() -> usize;
+    pub fn freertos_rs_xPortGetMinimumEverFreeHeapSize() -> usize;
+    pub fn freertos_rs_get_heap_stats(stats: *mut HeapStats) -> FreeRtosUBaseType;
+    #[cfg(feature = "heap_5")]
+    pub fn freertos_rs_define_heap_regions(regions: *const HeapRegion);
+    pub fn freertos_rs_get_portBYTE_ALIGNMENT() -> FreeRtosUBaseType;
+
+    #[cfg(feature = "heap_4")]
+    pub fn freertos_rs_heap_4_resize_in_place(pv: FreeRtosVoidPtr, xWantedSize: usize) -> u8;
+    #[cfg(feature = "fault_inject")]
+    pub fn freertos_rs_set_fault_hook(target: u8, hook: Option<FaultHook>);
 
     pub fn freertos_rs_sizeof(_type: u8) -> u8;
 
@@ -18,8 +40,16 @@ extern "C" {
     pub fn freertos_rs_get_portTICK_PERIOD_MS() -> FreeRtosTickType;
 
     pub fn freertos_rs_get_number_of_tasks() -> FreeRtosUBaseType;
+    pub fn freertos_rs_get_max_priorities() -> FreeRtosUBaseType;
+    pub fn freertos_rs_get_timer_task_priority() -> FreeRtosUBaseType;
 
     pub fn freertos_rs_xTaskGetTickCount() -> FreeRtosTickType;
+    pub fn freertos_rs_xTaskGetTickCountFromISR() -> FreeRtosTickType;
+    pub fn freertos_rs_is_inside_interrupt() -> FreeRtosUBaseType;
+
+    pub fn freertos_rs_vTaskSuspendAll();
+    pub fn freertos_rs_xTaskResumeAll() -> FreeRtosBaseType;
+    pub fn freertos_rs_xTaskGetSchedulerState() -> FreeRtosBaseType;
 
     pub fn freertos_rs_create_recursive_semaphore() -> FreeRtosQueueHandle;
     pub fn freertos_rs_create_semaphore() -> FreeRtosQueueHandle;
@@ -35,6 +65,7 @@ extern "C" {
     pub fn freertos_rs_give_semaphore(semaphore: FreeRtosQueueHandle) -> FreeRtosBaseType;
     pub fn freertos_rs_give_recursive_semaphore(semaphore: FreeRtosQueueHandle)
         -> FreeRtosBaseType;
+    pub fn freertos_rs_get_mutex_holder(mutex: FreeRtosQueueHandle) -> FreeRtosTaskHandle;
 
     pub fn freertos_rs_take_semaphore_isr(
         semaphore: FreeRtosQueueHandle,
@@ -54,35 +85,167 @@ extern "C" {
     ) -> FreeRtosQueueHandle;
 
     pub fn freertos_rs_semaphore_get_count(xSemaphore: FreeRtosQueueHandle) -> FreeRtosUBaseType;
+    pub fn freertos_rs_semaphore_get_count_isr(
+        xSemaphore: FreeRtosQueueHandle,
+    ) -> FreeRtosUBaseType;
+
+    #[cfg(feature = "static_allocation")]
+    pub fn freertos_rs_static_queue_size() -> u32;
+    #[cfg(feature = "static_allocation")]
+    pub fn freertos_rs_create_semaphore_static(control: FreeRtosMutVoidPtr) -> FreeRtosQueueHandle;
+    #[cfg(feature = "static_allocation")]
+    pub fn freertos_rs_create_binary_semaphore_static(
+        control: FreeRtosMutVoidPtr,
+    ) -> FreeRtosQueueHandle;
+    #[cfg(feature = "static_allocation")]
+    pub fn freertos_rs_queue_create_static(
+        length: FreeRtosUBaseType,
+        item_size: FreeRtosUBaseType,
+        storage: FreeRtosMutVoidPtr,
+        control: FreeRtosMutVoidPtr,
+    ) -> FreeRtosQueueHandle;
 
     pub fn freertos_rs_queue_create(
         length: FreeRtosUBaseType,
         item_size: FreeRtosUBaseType,
     ) -> FreeRtosQueueHandle;
     pub fn freertos_rs_queue_delete(queue: FreeRtosQueueHandle);
+    pub fn freertos_rs_queue_add_to_registry(queue: FreeRtosQueueHandle, name: FreeRtosCharPtr);
+    pub fn freertos_rs_queue_unregister(queue: FreeRtosQueueHandle);
+    pub fn freertos_rs_queue_get_name(queue: FreeRtosQueueHandle) -> FreeRtosCharPtr;
+    pub fn freertos_rs_get_queue_registry_size() -> FreeRtosUBaseType;
     pub fn freertos_rs_queue_send(
         queue: FreeRtosQueueHandle,
         item: FreeRtosVoidPtr,
         max_wait: FreeRtosTickType,
     ) -> FreeRtosUBaseType;
+    pub fn freertos_rs_queue_send_to_front(
+        queue: FreeRtosQueueHandle,
+        item: FreeRtosVoidPtr,
+        max_wait: FreeRtosTickType,
+    ) -> FreeRtosUBaseType;
     pub fn freertos_rs_queue_receive(
         queue: FreeRtosQueueHandle,
         item: FreeRtosMutVoidPtr,
         max_wait: FreeRtosTickType,
     ) -> FreeRtosUBaseType;
+    pub fn freertos_rs_queue_peek(
+        queue: FreeRtosQueueHandle,
+        item: FreeRtosMutVoidPtr,
+        max_wait: FreeRtosTickType,
+    ) -> FreeRtosUBaseType;
+    pub fn freertos_rs_queue_overwrite(
+        queue: FreeRtosQueueHandle,
+        item: FreeRtosVoidPtr,
+    ) -> FreeRtosUBaseType;
 
     pub fn freertos_rs_queue_send_isr(
         queue: FreeRtosQueueHandle,
         item: FreeRtosVoidPtr,
         xHigherPriorityTaskWoken: FreeRtosBaseTypeMutPtr,
     ) -> FreeRtosUBaseType;
+    pub fn freertos_rs_queue_send_to_front_isr(
+        queue: FreeRtosQueueHandle,
+        item: FreeRtosVoidPtr,
+        xHigherPriorityTaskWoken: FreeRtosBaseTypeMutPtr,
+    ) -> FreeRtosUBaseType;
+    pub fn freertos_rs_queue_overwrite_isr(
+        queue: FreeRtosQueueHandle,
+        item: FreeRtosVoidPtr,
+        xHigherPriorityTaskWoken: FreeRtosBaseTypeMutPtr,
+    ) -> FreeRtosUBaseType;
     pub fn freertos_rs_queue_receive_isr(
         queue: FreeRtosQueueHandle,
         item: FreeRtosVoidPtr,
         pxHigherPriorityTaskWoken: FreeRtosBaseTypeMutPtr,
     ) -> FreeRtosUBaseType;
+    pub fn freertos_rs_queue_messages_waiting(queue: FreeRtosQueueHandle) -> FreeRtosUBaseType;
+    pub fn freertos_rs_queue_blocked_tasks(queue: FreeRtosQueueHandle) -> FreeRtosUBaseType;
+    pub fn freertos_rs_queue_messages_waiting_isr(queue: FreeRtosQueueHandle) -> FreeRtosUBaseType;
+    pub fn freertos_rs_queue_spaces_available(queue: FreeRtosQueueHandle) -> FreeRtosUBaseType;
+
+    pub fn freertos_rs_queue_set_create(length: FreeRtosUBaseType) -> FreeRtosQueueHandle;
+    pub fn freertos_rs_queue_set_add(
+        member: FreeRtosQueueHandle,
+        set: FreeRtosQueueHandle,
+    ) -> FreeRtosUBaseType;
+    pub fn freertos_rs_queue_set_remove(
+        member: FreeRtosQueueHandle,
+        set: FreeRtosQueueHandle,
+    ) -> FreeRtosUBaseType;
+    pub fn freertos_rs_queue_set_select(
+        set: FreeRtosQueueHandle,
+        max_wait: FreeRtosTickType,
+    ) -> FreeRtosQueueHandle;
     pub fn freertos_rs_isr_yield();
 
+    pub fn freertos_rs_stream_buffer_create(
+        size: usize,
+        trigger_level: usize,
+    ) -> FreeRtosStreamBufferHandle;
+    pub fn freertos_rs_stream_buffer_delete(stream_buffer: FreeRtosStreamBufferHandle);
+    pub fn freertos_rs_stream_buffer_send(
+        stream_buffer: FreeRtosStreamBufferHandle,
+        data: FreeRtosVoidPtr,
+        len: usize,
+        max_wait: FreeRtosTickType,
+    ) -> usize;
+    pub fn freertos_rs_stream_buffer_send_isr(
+        stream_buffer: FreeRtosStreamBufferHandle,
+        data: FreeRtosVoidPtr,
+        len: usize,
+        xHigherPriorityTaskWoken: FreeRtosBaseTypeMutPtr,
+    ) -> usize;
+    pub fn freertos_rs_stream_buffer_receive(
+        stream_buffer: FreeRtosStreamBufferHandle,
+        buf: FreeRtosMutVoidPtr,
+        len: usize,
+        max_wait: FreeRtosTickType,
+    ) -> usize;
+    pub fn freertos_rs_stream_buffer_receive_isr(
+        stream_buffer: FreeRtosStreamBufferHandle,
+        buf: FreeRtosMutVoidPtr,
+        len: usize,
+        xHigherPriorityTaskWoken: FreeRtosBaseTypeMutPtr,
+    ) -> usize;
+    pub fn freertos_rs_stream_buffer_bytes_available(
+        stream_buffer: FreeRtosStreamBufferHandle,
+    ) -> usize;
+    pub fn freertos_rs_stream_buffer_spaces_available(
+        stream_buffer: FreeRtosStreamBufferHandle,
+    ) -> usize;
+
+    pub fn freertos_rs_event_group_create() -> FreeRtosEventGroupHandle;
+    pub fn freertos_rs_event_group_delete(event_group: FreeRtosEventGroupHandle);
+    pub fn freertos_rs_event_group_set_bits(
+        event_group: FreeRtosEventGroupHandle,
+        bits: u32,
+    ) -> u32;
+    pub fn freertos_rs_event_group_set_bits_isr(
+        event_group: FreeRtosEventGroupHandle,
+        bits: u32,
+        xHigherPriorityTaskWoken: FreeRtosBaseTypeMutPtr,
+    ) -> FreeRtosUBaseType;
+    pub fn freertos_rs_event_group_clear_bits(
+        event_group: FreeRtosEventGroupHandle,
+        bits: u32,
+    ) -> u32;
+    pub fn freertos_rs_event_group_get_bits(event_group: FreeRtosEventGroupHandle) -> u32;
+    pub fn freertos_rs_event_group_get_bits_isr(event_group: FreeRtosEventGroupHandle) -> u32;
+    pub fn freertos_rs_event_group_wait_bits(
+        event_group: FreeRtosEventGroupHandle,
+        bits: u32,
+        clear_on_exit: u8,
+        wait_for_all: u8,
+        max_wait: FreeRtosTickType,
+    ) -> u32;
+    pub fn freertos_rs_event_group_sync(
+        event_group: FreeRtosEventGroupHandle,
+        set_bits: u32,
+        wait_bits: u32,
+        max_wait: FreeRtosTickType,
+    ) -> u32;
+
     pub fn freertos_rs_task_notify_take(clear_count: u8, wait: FreeRtosTickType) -> u32;
     pub fn freertos_rs_task_notify_wait(
         ulBitsToClearOnEntry: u32,
@@ -102,20 +265,91 @@ extern "C" {
         action: u8,
         xHigherPriorityTaskWoken: FreeRtosBaseTypeMutPtr,
     ) -> FreeRtosBaseType;
+    pub fn freertos_rs_task_notify_give_isr(
+        task: FreeRtosTaskHandle,
+        xHigherPriorityTaskWoken: FreeRtosBaseTypeMutPtr,
+    );
+
+    #[cfg(feature = "notify_indexed")]
+    pub fn freertos_rs_get_notification_array_entries() -> FreeRtosUBaseType;
+    #[cfg(feature = "notify_indexed")]
+    pub fn freertos_rs_task_notify_take_indexed(
+        index: FreeRtosUBaseType,
+        clear_count: u8,
+        wait: FreeRtosTickType,
+    ) -> u32;
+    #[cfg(feature = "notify_indexed")]
+    pub fn freertos_rs_task_notify_wait_indexed(
+        index: FreeRtosUBaseType,
+        ulBitsToClearOnEntry: u32,
+        ulBitsToClearOnExit: u32,
+        pulNotificationValue: *mut u32,
+        xTicksToWait: FreeRtosTickType,
+    ) -> FreeRtosBaseType;
+    #[cfg(feature = "notify_indexed")]
+    pub fn freertos_rs_task_notify_indexed(
+        task: FreeRtosTaskHandle,
+        index: FreeRtosUBaseType,
+        value: u32,
+        action: u8,
+    ) -> FreeRtosBaseType;
+    #[cfg(feature = "notify_indexed")]
+    pub fn freertos_rs_task_notify_indexed_isr(
+        task: FreeRtosTaskHandle,
+        index: FreeRtosUBaseType,
+        value: u32,
+        action: u8,
+        xHigherPriorityTaskWoken: FreeRtosBaseTypeMutPtr,
+    ) -> FreeRtosBaseType;
 
     pub fn freertos_rs_spawn_task(
         f: extern "C" fn(FreeRtosMutVoidPtr) -> FreeRtosMutVoidPtr,
         value: FreeRtosMutVoidPtr,
         name: FreeRtosCharPtr,
         name_len: u8,
-        stack_size: u16,
+        stack_size: FreeRtosStackDepthType,
+        priority: FreeRtosUBaseType,
+        task_handle: *mut FreeRtosTaskHandle,
+    ) -> FreeRtosUBaseType;
+    #[cfg(feature = "static_allocation")]
+    pub fn freertos_rs_static_task_size() -> u32;
+    pub fn freertos_rs_stack_type_size() -> u32;
+    #[cfg(feature = "static_allocation")]
+    pub fn freertos_rs_spawn_task_static(
+        f: extern "C" fn(FreeRtosMutVoidPtr) -> FreeRtosMutVoidPtr,
+        value: FreeRtosMutVoidPtr,
+        name: FreeRtosCharPtr,
+        name_len: u8,
+        stack_size: FreeRtosStackDepthType,
         priority: FreeRtosUBaseType,
-        task_handle: FreeRtosMutTaskHandle,
+        stack: FreeRtosMutVoidPtr,
+        tcb: FreeRtosMutVoidPtr,
+    ) -> FreeRtosTaskHandle;
+    #[cfg(feature = "mpu")]
+    pub fn freertos_rs_get_configurable_regions() -> FreeRtosUBaseType;
+    #[cfg(feature = "mpu")]
+    pub fn freertos_rs_spawn_task_restricted(
+        task: *const FreeRtosRestrictedTaskFfi,
+        task_handle: *mut FreeRtosTaskHandle,
+    ) -> FreeRtosUBaseType;
+    #[cfg(feature = "mpu")]
+    pub fn freertos_rs_allocate_mpu_regions(
+        task: FreeRtosTaskHandle,
+        regions: *const MemoryRegion,
+        region_count: FreeRtosUBaseType,
     ) -> FreeRtosUBaseType;
     pub fn freertos_rs_delete_task(task: FreeRtosTaskHandle);
     pub fn freertos_rs_task_get_name(task: FreeRtosTaskHandle) -> FreeRtosCharPtr;
+    pub fn freertos_rs_task_get_task_number(task: FreeRtosTaskHandle) -> FreeRtosUBaseType;
     pub fn freertos_rs_task_suspend(xTaskToSuspend: FreeRtosTaskHandle);
     pub fn freertos_rs_task_resume(xTaskToResume: FreeRtosTaskHandle);
+    pub fn freertos_rs_task_abort_delay(xTask: FreeRtosTaskHandle) -> FreeRtosBaseType;
+    pub fn freertos_rs_task_priority_get(task: FreeRtosTaskHandle) -> FreeRtosUBaseType;
+    pub fn freertos_rs_task_priority_get_isr(task: FreeRtosTaskHandle) -> FreeRtosUBaseType;
+    pub fn freertos_rs_task_priority_set(
+        task: FreeRtosTaskHandle,
+        priority: FreeRtosUBaseType,
+    ) -> FreeRtosUBaseType;
     pub fn freertos_rs_get_stack_high_water_mark(task: FreeRtosTaskHandle) -> FreeRtosBaseType;
 
     pub fn freertos_rs_get_current_task() -> FreeRtosTaskHandle;
@@ -126,10 +360,10 @@ extern "C" {
     ) -> FreeRtosUBaseType;
 
     pub fn freertos_rs_max_wait() -> FreeRtosTickType;
+    pub fn freertos_rs_max_task_name_len() -> FreeRtosUBaseType;
 
     pub fn freertos_rs_timer_create(
         name: FreeRtosCharPtr,
-        name_len: u8,
         period: FreeRtosTickType,
         auto_reload: u8,
         timer_id: FreeRtosVoidPtr,
@@ -174,7 +408,36 @@ extern "C" {
         pxHigherPriorityTaskWoken: FreeRtosBaseTypeMutPtr,
     ) -> FreeRtosBaseType;
     pub fn freertos_rs_timer_get_id(timer: FreeRtosTimerHandle) -> FreeRtosVoidPtr;
+    pub fn freertos_rs_timer_get_expiry_time(timer: FreeRtosTimerHandle) -> FreeRtosTickType;
+    pub fn freertos_rs_timer_is_active(timer: FreeRtosTimerHandle) -> u8;
+    pub fn freertos_rs_timer_get_name(timer: FreeRtosTimerHandle) -> FreeRtosCharPtr;
+    pub fn freertos_rs_timer_get_period(timer: FreeRtosTimerHandle) -> FreeRtosTickType;
+    pub fn freertos_rs_timer_get_daemon_task_handle() -> FreeRtosTaskHandle;
+    pub fn freertos_rs_pend_function_call(
+        function: extern "C" fn(FreeRtosMutVoidPtr, u32),
+        parameter1: FreeRtosMutVoidPtr,
+        parameter2: u32,
+        block_time: FreeRtosTickType,
+    ) -> FreeRtosBaseType;
+    pub fn freertos_rs_pend_function_call_isr(
+        function: extern "C" fn(FreeRtosMutVoidPtr, u32),
+        parameter1: FreeRtosMutVoidPtr,
+        parameter2: u32,
+        pxHigherPriorityTaskWoken: FreeRtosBaseTypeMutPtr,
+    ) -> FreeRtosBaseType;
 
     pub fn freertos_rs_enter_critical();
     pub fn freertos_rs_exit_critical();
+    pub fn freertos_rs_enter_critical_from_isr() -> FreeRtosUBaseType;
+    pub fn freertos_rs_exit_critical_from_isr(saved_interrupt_status: FreeRtosUBaseType);
+}
+
+// The virtual time glue of the hosted port, freertos/ports/linux/virtual_time.c.
+#[cfg(feature = "test_support")]
+extern "C" {
+    pub fn freertos_rs_virtual_time_enable(deterministic: FreeRtosBaseType);
+    pub fn freertos_rs_virtual_time_enabled() -> FreeRtosBaseType;
+    pub fn freertos_rs_virtual_time_wake_at(tick: FreeRtosTickType);
+    pub fn vPortVirtualTimeStart();
+    pub fn vPortVirtualTimeIdle();
 }
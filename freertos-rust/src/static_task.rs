@@ -0,0 +1,138 @@
+use crate::base::*;
+use crate::critical::*;
+use crate::isr::*;
+use crate::operating_system::*;
+use crate::prelude::v1::*;
+use crate::shim::*;
+use crate::task::*;
+use core::cell::UnsafeCell;
+
+/// Words reserved in every `StaticTask` for the kernel's `StaticTask_t`. Spawning fails
+/// with `OutOfMemory` if the configuration needs more.
+pub const STATIC_TASK_TCB_WORDS: usize = 64;
+
+/// The entry point of a statically allocated task, with the context it was spawned with.
+pub type StaticTaskFn<C> = fn(&TaskSelfHandle, FreeRTOS, &'static C) -> !;
+
+impl<const STACK_WORDS: usize> !ISRSafe for StaticTask<STACK_WORDS> {}
+unsafe impl<const STACK_WORDS: usize> Sync for StaticTask<STACK_WORDS> {}
+
+/// The stack and control block of a task, for creating it with `xTaskCreateStatic`
+/// instead of allocating them from the FreeRTOS heap.
+///
+/// Declare it as a `static` and spawn it once. Instead of a boxed closure, the task runs
+/// a plain function with a `&'static` context, so nothing is allocated at all:
+///
+///     static BLINKY: StaticTask<256> = StaticTask::new();
+///     static LED: Led = Led::new();
+///
+///     BLINKY.spawn_static(&os, "blinky", TaskPriority(2), blink, &LED)?;
+///
+/// Requires `configSUPPORT_STATIC_ALLOCATION`.
+pub struct StaticTask<const STACK_WORDS: usize> {
+    stack: UnsafeCell<[usize; STACK_WORDS]>,
+    tcb: UnsafeCell<[usize; STATIC_TASK_TCB_WORDS]>,
+    entry: UnsafeCell<(usize, usize)>,
+    spawned: UnsafeCell<bool>,
+}
+
+impl<const STACK_WORDS: usize> StaticTask<STACK_WORDS> {
+    pub const fn new() -> StaticTask<STACK_WORDS> {
+        StaticTask {
+            stack: UnsafeCell::new([0; STACK_WORDS]),
+            tcb: UnsafeCell::new([0; STATIC_TASK_TCB_WORDS]),
+            entry: UnsafeCell::new((0, 0)),
+            spawned: UnsafeCell::new(false),
+        }
+    }
+
+    /// Create the task in this storage. Fails with `StorageInUse` if it was spawned before;
+    /// the storage is never handed back, so the task must not be deleted. Fails with
+    /// `StackTooLarge` if the stack doesn't fit in `FreeRtosStackDepthType`.
+    pub fn spawn_static<C>(
+        &'static self,
+        _os: &FreeRTOS,
+        name: &str,
+        priority: impl Into<TaskPriority>,
+        func: StaticTaskFn<C>,
+        context: &'static C,
+    ) -> Result<TaskRemoteHandle, FreeRtosError>
+    where
+        C: Sync + 'static,
+    {
+        let (tcb_size, stack_type_size) = unsafe {
+            (
+                freertos_rs_static_task_size(),
+                freertos_rs_stack_type_size(),
+            )
+        };
+        if tcb_size as usize > STATIC_TASK_TCB_WORDS * mem::size_of::<usize>()
+            || stack_type_size as usize > mem::size_of::<usize>()
+        {
+            return Err(FreeRtosError::OutOfMemory);
+        }
+        check_name(name)?;
+        let stack_depth = STACK_WORDS * mem::size_of::<usize>() / stack_type_size as usize;
+        if stack_depth > FreeRtosStackDepthType::MAX as usize {
+            return Err(FreeRtosError::StackTooLarge);
+        }
+
+        {
+            let _lock = CriticalRegion::enter();
+            unsafe {
+                if *self.spawned.get() {
+                    return Err(FreeRtosError::StorageInUse);
+                }
+                *self.spawned.get() = true;
+                *self.entry.get() = (func as usize, context as *const C as usize);
+            }
+        }
+
+        extern "C" fn static_thread_start<C: 'static>(entry: *mut CVoid) -> *mut CVoid {
+            unsafe {
+                #[cfg(feature = "end_scheduler")]
+                freertos_rs_task_keep_thread();
+
+                let (func, context) = *(entry as *const (usize, usize));
+                let func: StaticTaskFn<C> = mem::transmute(func);
+
+                func(
+                    &TaskSelfHandle::current(),
+                    FreeRTOS::assume_init(),
+                    &*(context as *const C),
+                );
+            }
+        }
+
+        let name = name.as_bytes();
+
+        let task_handle = unsafe {
+            freertos_rs_spawn_task_static(
+                static_thread_start::<C>,
+                self.entry.get() as FreeRtosMutVoidPtr,
+                name.as_ptr(),
+                name.len().min(u8::MAX as usize) as u8,
+                stack_depth as FreeRtosStackDepthType,
+                priority.into().to_freertos(),
+                self.stack.get() as FreeRtosMutVoidPtr,
+                self.tcb.get() as FreeRtosMutVoidPtr,
+            )
+        };
+
+        if task_handle.is_null() {
+            let _lock = CriticalRegion::enter();
+            unsafe {
+                *self.spawned.get() = false;
+            }
+            return Err(FreeRtosError::OutOfMemory);
+        }
+
+        Ok(unsafe { TaskRemoteHandle::from_raw(task_handle) })
+    }
+
+    /// Has a task been created in this storage?
+    pub fn is_spawned(&self) -> bool {
+        let _lock = CriticalRegion::enter();
+        unsafe { *self.spawned.get() }
+    }
+}
@@ -0,0 +1,457 @@
+//! Allocation-free statistics without floating point, used for the crate's own metrics.
+//!
+//! Means and averages are kept in fixed point with `STATS_FRACTION_BITS` fractional bits.
+//! Nothing wraps around: counters saturate and set a sticky overflow flag instead.
+//! Every type can be written to and read from a compact little-endian encoding, for
+//! sending diagnostics over a link.
+
+/// Fractional bits of the fixed-point values, so they are in units of 1/256.
+pub const STATS_FRACTION_BITS: u32 = 8;
+
+const HALF: u64 = 1 << (STATS_FRACTION_BITS - 1);
+
+fn put_u32(out: &mut [u8], at: &mut usize, value: u32) {
+    out[*at..*at + 4].copy_from_slice(&value.to_le_bytes());
+    *at += 4;
+}
+
+fn put_u64(out: &mut [u8], at: &mut usize, value: u64) {
+    out[*at..*at + 8].copy_from_slice(&value.to_le_bytes());
+    *at += 8;
+}
+
+fn get_u32(bytes: &[u8], at: &mut usize) -> u32 {
+    let mut value = [0; 4];
+    value.copy_from_slice(&bytes[*at..*at + 4]);
+    *at += 4;
+    u32::from_le_bytes(value)
+}
+
+fn get_u64(bytes: &[u8], at: &mut usize) -> u64 {
+    let mut value = [0; 8];
+    value.copy_from_slice(&bytes[*at..*at + 8]);
+    *at += 8;
+    u64::from_le_bytes(value)
+}
+
+/// Count, mean, minimum and maximum of a series of samples, like tick durations.
+///
+/// The mean is computed from an exact sum, so it is exact to 1/256 as long as the sum
+/// fits in a `u64`. Once the count or the sum saturates, `overflowed` is set and the
+/// count, sum and mean stay where they were, while the minimum and maximum keep
+/// tracking new samples.
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub struct RunningStats {
+    count: u32,
+    sum: u64,
+    min: u64,
+    max: u64,
+    overflowed: bool,
+}
+
+impl Default for RunningStats {
+    fn default() -> Self {
+        RunningStats::new()
+    }
+}
+
+impl RunningStats {
+    /// Bytes written by `encode`.
+    pub const ENCODED_LEN: usize = 4 + 8 + 8 + 8 + 1;
+
+    pub const fn new() -> RunningStats {
+        RunningStats {
+            count: 0,
+            sum: 0,
+            min: u64::MAX,
+            max: 0,
+            overflowed: false,
+        }
+    }
+
+    /// Add a sample. Takes `u32` ticks as well as `u64` values.
+    pub fn record(&mut self, sample: impl Into<u64>) {
+        let sample = sample.into();
+        self.min = self.min.min(sample);
+        self.max = self.max.max(sample);
+
+        if self.overflowed {
+            return;
+        }
+        match (self.count.checked_add(1), self.sum.checked_add(sample)) {
+            (Some(count), Some(sum)) => {
+                self.count = count;
+                self.sum = sum;
+            }
+            _ => self.overflowed = true,
+        }
+    }
+
+    /// Combine with the samples of `other`, as if they had been recorded here.
+    pub fn merge(&mut self, other: &RunningStats) {
+        self.min = self.min.min(other.min);
+        self.max = self.max.max(other.max);
+
+        if self.overflowed || other.overflowed {
+            self.overflowed = true;
+            return;
+        }
+        match (
+            self.count.checked_add(other.count),
+            self.sum.checked_add(other.sum),
+        ) {
+            (Some(count), Some(sum)) => {
+                self.count = count;
+                self.sum = sum;
+            }
+            _ => self.overflowed = true,
+        }
+    }
+
+    pub fn reset(&mut self) {
+        *self = RunningStats::new();
+    }
+
+    pub fn count(&self) -> u32 {
+        self.count
+    }
+
+    pub fn sum(&self) -> u64 {
+        self.sum
+    }
+
+    /// The mean, rounded to the nearest integer. 0 without samples.
+    pub fn mean(&self) -> u64 {
+        (self.mean_fixed().saturating_add(HALF)) >> STATS_FRACTION_BITS
+    }
+
+    /// The mean in units of 1/256, rounded to the nearest unit.
+    pub fn mean_fixed(&self) -> u64 {
+        if self.count == 0 {
+            return 0;
+        }
+        let count = self.count as u128;
+        let mean = (((self.sum as u128) << STATS_FRACTION_BITS) + count / 2) / count;
+        mean.min(u64::MAX as u128) as u64
+    }
+
+    pub fn min(&self) -> Option<u64> {
+        if self.min <= self.max {
+            Some(self.min)
+        } else {
+            None
+        }
+    }
+
+    pub fn max(&self) -> Option<u64> {
+        self.min().map(|_| self.max)
+    }
+
+    /// Did the count or the sum saturate?
+    pub fn overflowed(&self) -> bool {
+        self.overflowed
+    }
+
+    /// Write the stats to `out`, returning the number of bytes written, or `None` if
+    /// `out` is shorter than `ENCODED_LEN`.
+    pub fn encode(&self, out: &mut [u8]) -> Option<usize> {
+        if out.len() < Self::ENCODED_LEN {
+            return None;
+        }
+        let mut at = 0;
+        put_u32(out, &mut at, self.count);
+        put_u64(out, &mut at, self.sum);
+        put_u64(out, &mut at, self.min);
+        put_u64(out, &mut at, self.max);
+        out[at] = self.overflowed as u8;
+        Some(at + 1)
+    }
+
+    /// Read stats written by `encode`.
+    pub fn decode(bytes: &[u8]) -> Option<RunningStats> {
+        if bytes.len() < Self::ENCODED_LEN || bytes[Self::ENCODED_LEN - 1] > 1 {
+            return None;
+        }
+        let mut at = 0;
+        Some(RunningStats {
+            count: get_u32(bytes, &mut at),
+            sum: get_u64(bytes, &mut at),
+            min: get_u64(bytes, &mut at),
+            max: get_u64(bytes, &mut at),
+            overflowed: bytes[at] != 0,
+        })
+    }
+}
+
+/// An exponentially weighted moving average with a rational smoothing factor.
+///
+/// Each sample moves the average by `alpha = numerator / denominator` of the distance to
+/// it, rounded to 1/256. The first sample sets the average directly. Samples too large
+/// for the fixed-point value are clamped and set `overflowed`.
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub struct Ewma {
+    numerator: u32,
+    denominator: u32,
+    value: u64,
+    primed: bool,
+    overflowed: bool,
+}
+
+impl Ewma {
+    /// Bytes written by `encode`.
+    pub const ENCODED_LEN: usize = 4 + 4 + 8 + 1;
+
+    /// Panics unless `0 < numerator <= denominator`.
+    pub fn new(numerator: u32, denominator: u32) -> Ewma {
+        assert!(
+            numerator > 0 && numerator <= denominator,
+            "EWMA alpha must be in (0, 1]."
+        );
+        Ewma {
+            numerator,
+            denominator,
+            value: 0,
+            primed: false,
+            overflowed: false,
+        }
+    }
+
+    pub fn record(&mut self, sample: impl Into<u64>) {
+        let sample = (sample.into() as u128) << STATS_FRACTION_BITS;
+        let sample = if sample > u64::MAX as u128 {
+            self.overflowed = true;
+            u64::MAX
+        } else {
+            sample as u64
+        };
+
+        if !self.primed {
+            self.value = sample;
+            self.primed = true;
+            return;
+        }
+
+        let delta = sample as i128 - self.value as i128;
+        let step = delta * self.numerator as i128;
+        let denominator = self.denominator as i128;
+        // Round half away from zero, so the average reaches a constant input.
+        let step = if step >= 0 {
+            (step + denominator / 2) / denominator
+        } else {
+            (step - denominator / 2) / denominator
+        };
+        self.value = (self.value as i128 + step) as u64;
+    }
+
+    pub fn reset(&mut self) {
+        self.value = 0;
+        self.primed = false;
+        self.overflowed = false;
+    }
+
+    /// The average, rounded to the nearest integer. 0 without samples.
+    pub fn value(&self) -> u64 {
+        self.value.saturating_add(HALF) >> STATS_FRACTION_BITS
+    }
+
+    /// The average in units of 1/256.
+    pub fn value_fixed(&self) -> u64 {
+        self.value
+    }
+
+    /// Has a sample been recorded since the average was created or reset?
+    pub fn is_primed(&self) -> bool {
+        self.primed
+    }
+
+    pub fn overflowed(&self) -> bool {
+        self.overflowed
+    }
+
+    /// Write the average to `out`, returning the number of bytes written, or `None` if
+    /// `out` is shorter than `ENCODED_LEN`.
+    pub fn encode(&self, out: &mut [u8]) -> Option<usize> {
+        if out.len() < Self::ENCODED_LEN {
+            return None;
+        }
+        let mut at = 0;
+        put_u32(out, &mut at, self.numerator);
+        put_u32(out, &mut at, self.denominator);
+        put_u64(out, &mut at, self.value);
+        out[at] = self.primed as u8 | (self.overflowed as u8) << 1;
+        Some(at + 1)
+    }
+
+    /// Read an average written by `encode`.
+    pub fn decode(bytes: &[u8]) -> Option<Ewma> {
+        if bytes.len() < Self::ENCODED_LEN {
+            return None;
+        }
+        let mut at = 0;
+        let numerator = get_u32(bytes, &mut at);
+        let denominator = get_u32(bytes, &mut at);
+        let value = get_u64(bytes, &mut at);
+        let flags = bytes[at];
+        if numerator == 0 || numerator > denominator || flags > 3 {
+            return None;
+        }
+        Some(Ewma {
+            numerator,
+            denominator,
+            value,
+            primed: flags & 1 != 0,
+            overflowed: flags & 2 != 0,
+        })
+    }
+}
+
+/// The inclusive bound of preset bucket `i` ending before `end`. Buckets past the end
+/// of the `u64` range get the highest bounds that keep the bounds ascending.
+fn clamp_bound<const BUCKETS: usize>(i: usize, end: Option<u64>) -> u64 {
+    let top = u64::MAX - (BUCKETS - 1 - i) as u64;
+    end.map_or(top, |end| (end - 1).min(top))
+}
+
+/// Counts of samples per bucket. Bucket `i` counts the samples above `bounds[i - 1]` up
+/// to and including `bounds[i]`; the last bucket also counts everything above its
+/// bound. A bucket count that would overflow saturates and sets `overflowed`.
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub struct Histogram<const BUCKETS: usize> {
+    bounds: [u64; BUCKETS],
+    counts: [u32; BUCKETS],
+    overflowed: bool,
+}
+
+impl<const BUCKETS: usize> Histogram<BUCKETS> {
+    /// Bytes written by `encode`.
+    pub const ENCODED_LEN: usize = BUCKETS * (8 + 4) + 1;
+
+    /// A histogram with the given inclusive upper bounds, which must be ascending.
+    pub fn with_bounds(bounds: [u64; BUCKETS]) -> Histogram<BUCKETS> {
+        assert!(BUCKETS > 0, "A histogram needs at least one bucket.");
+        assert!(
+            bounds.windows(2).all(|w| w[0] < w[1]),
+            "Histogram bounds must be ascending."
+        );
+        Histogram {
+            bounds,
+            counts: [0; BUCKETS],
+            overflowed: false,
+        }
+    }
+
+    /// Buckets `width` wide, starting at 0.
+    pub fn linear(width: u64) -> Histogram<BUCKETS> {
+        assert!(width > 0, "Histogram buckets must be at least 1 wide.");
+        let mut bounds = [0; BUCKETS];
+        for (i, bound) in bounds.iter_mut().enumerate() {
+            let end = width.checked_mul(i as u64 + 1);
+            *bound = clamp_bound::<BUCKETS>(i, end);
+        }
+        Histogram::with_bounds(bounds)
+    }
+
+    /// Buckets doubling in width: `0..first`, `first..2 * first`, `2 * first..4 * first`
+    /// and so on.
+    pub fn log2(first: u64) -> Histogram<BUCKETS> {
+        assert!(first > 0, "Histogram buckets must be at least 1 wide.");
+        let mut bounds = [0; BUCKETS];
+        for (i, bound) in bounds.iter_mut().enumerate() {
+            let end = 1u64
+                .checked_shl(i as u32)
+                .and_then(|scale| first.checked_mul(scale));
+            *bound = clamp_bound::<BUCKETS>(i, end);
+        }
+        Histogram::with_bounds(bounds)
+    }
+
+    pub fn record(&mut self, sample: impl Into<u64>) {
+        let sample = sample.into();
+        let index = self
+            .bounds
+            .iter()
+            .position(|bound| sample <= *bound)
+            .unwrap_or(BUCKETS - 1);
+
+        match self.counts[index].checked_add(1) {
+            Some(count) => self.counts[index] = count,
+            None => self.overflowed = true,
+        }
+    }
+
+    /// Add the counts of `other`. Returns false, changing nothing, if the bounds differ.
+    pub fn merge(&mut self, other: &Histogram<BUCKETS>) -> bool {
+        if self.bounds != other.bounds {
+            return false;
+        }
+        for (count, other) in self.counts.iter_mut().zip(other.counts.iter()) {
+            match count.checked_add(*other) {
+                Some(sum) => *count = sum,
+                None => {
+                    *count = u32::MAX;
+                    self.overflowed = true;
+                }
+            }
+        }
+        self.overflowed |= other.overflowed;
+        true
+    }
+
+    pub fn reset(&mut self) {
+        self.counts = [0; BUCKETS];
+        self.overflowed = false;
+    }
+
+    pub fn bounds(&self) -> &[u64; BUCKETS] {
+        &self.bounds
+    }
+
+    pub fn counts(&self) -> &[u32; BUCKETS] {
+        &self.counts
+    }
+
+    /// The number of samples in all buckets.
+    pub fn total(&self) -> u64 {
+        self.counts.iter().map(|c| *c as u64).sum()
+    }
+
+    pub fn overflowed(&self) -> bool {
+        self.overflowed
+    }
+
+    /// Write the histogram to `out`, returning the number of bytes written, or `None` if
+    /// `out` is shorter than `ENCODED_LEN`.
+    pub fn encode(&self, out: &mut [u8]) -> Option<usize> {
+        if out.len() < Self::ENCODED_LEN {
+            return None;
+        }
+        let mut at = 0;
+        for (bound, count) in self.bounds.iter().zip(self.counts.iter()) {
+            put_u64(out, &mut at, *bound);
+            put_u32(out, &mut at, *count);
+        }
+        out[at] = self.overflowed as u8;
+        Some(at + 1)
+    }
+
+    /// Read a histogram written by `encode` with the same number of buckets.
+    pub fn decode(bytes: &[u8]) -> Option<Histogram<BUCKETS>> {
+        if bytes.len() < Self::ENCODED_LEN || bytes[Self::ENCODED_LEN - 1] > 1 {
+            return None;
+        }
+        let mut histogram = Histogram {
+            bounds: [0; BUCKETS],
+            counts: [0; BUCKETS],
+            overflowed: bytes[Self::ENCODED_LEN - 1] != 0,
+        };
+        let mut at = 0;
+        for i in 0..BUCKETS {
+            histogram.bounds[i] = get_u64(bytes, &mut at);
+            histogram.counts[i] = get_u32(bytes, &mut at);
+        }
+        if !histogram.bounds.windows(2).all(|w| w[0] < w[1]) {
+            return None;
+        }
+        Some(histogram)
+    }
+}
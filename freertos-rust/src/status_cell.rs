@@ -0,0 +1,131 @@
+use crate::critical::*;
+use crate::isr::*;
+use crate::prelude::v1::*;
+#[cfg(loom)]
+use crate::sync::atomic::AtomicUsize;
+use crate::sync::atomic::{fence, AtomicU32, Ordering};
+use crate::sync::hint;
+
+/// The latest value of a status, written by tasks and interrupts and read by any of them
+/// without blocking.
+///
+/// Writes take a critical section and bump a sequence number around the value, so a
+/// reader never sees half of a write: it reads again while one is in progress. For small
+/// `Copy` values, like the reason and tick of an event.
+pub struct StatusCell<T: Copy> {
+    /// Twice the number of writes, plus one while a write is in progress.
+    sequence: AtomicU32,
+    value: Slot<T>,
+}
+
+unsafe impl<T: Copy + Send> Send for StatusCell<T> {}
+unsafe impl<T: Copy + Send> Sync for StatusCell<T> {}
+
+impl<T: Copy> StatusCell<T> {
+    pub fn new(value: T) -> StatusCell<T> {
+        StatusCell {
+            sequence: AtomicU32::new(0),
+            value: Slot::new(value),
+        }
+    }
+
+    /// The value of the last write. Can be called from tasks and interrupts at or below
+    /// `configMAX_SYSCALL_INTERRUPT_PRIORITY`, which writes can't preempt.
+    pub fn get(&self) -> T {
+        loop {
+            let before = self.sequence.load(Ordering::Acquire);
+            if before & 1 == 0 {
+                let value = self.value.read();
+                fence(Ordering::Acquire);
+
+                if self.sequence.load(Ordering::Relaxed) == before {
+                    return value;
+                }
+            }
+            // A write is in progress.
+            hint::spin_loop();
+        }
+    }
+
+    /// Write `value` from a task.
+    pub fn set(&self, value: T) {
+        let _lock = CriticalRegion::enter();
+        self.write(value);
+    }
+
+    /// Write `value` from an interrupt.
+    pub fn set_from_isr(&self, context: &InterruptContext, value: T) {
+        let _lock = CriticalRegionIsr::enter(context);
+        self.write(value);
+    }
+
+    fn write(&self, value: T) {
+        let sequence = self.sequence.load(Ordering::Relaxed);
+        self.sequence
+            .store(sequence.wrapping_add(1), Ordering::Relaxed);
+        fence(Ordering::Release);
+
+        unsafe { self.value.write(value) };
+
+        self.sequence
+            .store(sequence.wrapping_add(2), Ordering::Release);
+    }
+}
+
+/// Where the value of a `StatusCell` lives.
+#[cfg(not(loom))]
+struct Slot<T>(UnsafeCell<T>);
+
+#[cfg(not(loom))]
+impl<T: Copy> Slot<T> {
+    fn new(value: T) -> Slot<T> {
+        Slot(UnsafeCell::new(value))
+    }
+
+    fn read(&self) -> T {
+        unsafe { ptr::read_volatile(self.0.get()) }
+    }
+
+    /// Only in the critical section of a write.
+    unsafe fn write(&self, value: T) {
+        ptr::write_volatile(self.0.get(), value)
+    }
+}
+
+/// Under loom, a read of the value is a relaxed load of one of the writes the reader may
+/// see, so loom checks the fences around it. Loom doesn't track a plain cell, which is
+/// read while it's written.
+#[cfg(loom)]
+struct Slot<T> {
+    /// Every value written. Loom runs the threads of a model one at a time.
+    values: UnsafeCell<Vec<T>>,
+    latest: AtomicUsize,
+}
+
+#[cfg(loom)]
+impl<T: Copy> Slot<T> {
+    fn new(value: T) -> Slot<T> {
+        Slot {
+            values: UnsafeCell::new(alloc::vec![value]),
+            latest: AtomicUsize::new(0),
+        }
+    }
+
+    fn read(&self) -> T {
+        let index = self.latest.load(Ordering::Relaxed);
+        let values = unsafe { &*self.values.get() };
+        values[index]
+    }
+
+    unsafe fn write(&self, value: T) {
+        let values = &mut *self.values.get();
+        values.push(value);
+        self.latest.store(values.len() - 1, Ordering::Relaxed);
+    }
+}
+
+impl<T: Copy + fmt::Debug> fmt::Debug for StatusCell<T> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_tuple("StatusCell").field(&self.get()).finish()
+    }
+}
@@ -0,0 +1,180 @@
+use crate::base::*;
+use crate::framing::*;
+use crate::isr::*;
+use crate::no_block::check_blocking;
+use crate::operating_system::*;
+use crate::shim::*;
+use crate::units::*;
+
+unsafe impl Send for StreamBuffer {}
+unsafe impl Sync for StreamBuffer {}
+
+impl !ISRSafe for StreamBuffer {}
+
+/// A FreeRTOS stream buffer, passing bytes from one writer to one reader.
+///
+/// Only one task or interrupt may write and only one may read at a time. Use a mutex
+/// around the buffer if there can be several writers or readers.
+#[derive(Debug)]
+pub struct StreamBuffer {
+    stream_buffer: FreeRtosStreamBufferHandle,
+    size: usize,
+}
+
+impl StreamBuffer {
+    /// Create a stream buffer holding `size` bytes. A blocked reader is woken once
+    /// `trigger_level` bytes are available.
+    pub fn new(
+        _os: FreeRTOS,
+        size: usize,
+        trigger_level: usize,
+    ) -> Result<StreamBuffer, FreeRtosError> {
+        let handle = unsafe { freertos_rs_stream_buffer_create(size, trigger_level) };
+
+        if handle.is_null() {
+            Err(FreeRtosError::OutOfMemory)
+        } else {
+            Ok(StreamBuffer {
+                stream_buffer: handle,
+                size,
+            })
+        }
+    }
+
+    /// Send as much of `data` as fits, waiting up to `max_wait` for space.
+    /// Returns how many bytes were sent.
+    #[inline]
+    pub fn send<D: DurationTicks>(&self, data: &[u8], max_wait: D) -> Result<usize, FreeRtosError> {
+        self.send_ticks(data, max_wait.to_ticks())
+    }
+
+    fn send_ticks(&self, data: &[u8], max_wait: FreeRtosTickType) -> Result<usize, FreeRtosError> {
+        check_blocking("StreamBuffer::send", self.stream_buffer, max_wait);
+
+        let sent = unsafe {
+            freertos_rs_stream_buffer_send(
+                self.stream_buffer,
+                data.as_ptr() as FreeRtosVoidPtr,
+                data.len(),
+                max_wait,
+            )
+        };
+
+        if sent == 0 && !data.is_empty() {
+            return Err(timed_out());
+        }
+        Ok(sent)
+    }
+
+    /// Wait up to `max_wait` for data and read up to `buf.len()` bytes.
+    /// Returns how many bytes were read, which may be less than requested.
+    #[inline]
+    pub fn receive<D: DurationTicks>(
+        &self,
+        buf: &mut [u8],
+        max_wait: D,
+    ) -> Result<usize, FreeRtosError> {
+        self.receive_ticks(buf, max_wait.to_ticks())
+    }
+
+    fn receive_ticks(
+        &self,
+        buf: &mut [u8],
+        max_wait: FreeRtosTickType,
+    ) -> Result<usize, FreeRtosError> {
+        check_blocking("StreamBuffer::receive", self.stream_buffer, max_wait);
+
+        let received = unsafe {
+            freertos_rs_stream_buffer_receive(
+                self.stream_buffer,
+                buf.as_mut_ptr() as FreeRtosMutVoidPtr,
+                buf.len(),
+                max_wait,
+            )
+        };
+
+        if received == 0 && !buf.is_empty() {
+            return Err(timed_out());
+        }
+        Ok(received)
+    }
+
+    /// The number of bytes waiting to be read.
+    pub fn len(&self) -> usize {
+        unsafe { freertos_rs_stream_buffer_bytes_available(self.stream_buffer) }
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    /// The number of bytes that can be sent before the buffer is full.
+    pub fn spaces_available(&self) -> usize {
+        unsafe { freertos_rs_stream_buffer_spaces_available(self.stream_buffer) }
+    }
+
+    /// The number of bytes the stream buffer was created to hold.
+    pub fn size(&self) -> usize {
+        self.size
+    }
+}
+
+impl Drop for StreamBuffer {
+    fn drop(&mut self) {
+        unsafe {
+            freertos_rs_stream_buffer_delete(self.stream_buffer);
+        }
+    }
+}
+
+impl ByteTransport for StreamBuffer {
+    fn write(&self, mut bytes: &[u8], max_wait: Duration) -> Result<(), FreeRtosError> {
+        while !bytes.is_empty() {
+            let sent = self.send(bytes, max_wait)?;
+            bytes = &bytes[sent..];
+        }
+        Ok(())
+    }
+
+    fn read(&self, buf: &mut [u8], max_wait: Duration) -> Result<usize, FreeRtosError> {
+        self.receive(buf, max_wait)
+    }
+}
+
+pub struct StreamBufferISRHandle {
+    stream_buffer: FreeRtosStreamBufferHandle,
+}
+
+impl ISRSafeHandle<StreamBufferISRHandle> for StreamBuffer {
+    unsafe fn new_isr_safe_handle(&self) -> StreamBufferISRHandle {
+        StreamBufferISRHandle {
+            stream_buffer: self.stream_buffer,
+        }
+    }
+}
+
+impl StreamBufferISRHandle {
+    /// Send as much of `data` as fits, from an interrupt. Returns how many bytes were sent.
+    pub fn send(&self, context: &mut InterruptContext, data: &[u8]) -> usize {
+        unsafe {
+            freertos_rs_stream_buffer_send_isr(
+                self.stream_buffer,
+                data.as_ptr() as FreeRtosVoidPtr,
+                data.len(),
+                context.get_task_field_mut(),
+            )
+        }
+    }
+
+    /// Read up to `buf.len()` bytes, from an interrupt. Returns how many bytes were read.
+    pub fn receive(&self, context: &mut InterruptContext, buf: &mut [u8]) -> usize {
+        unsafe {
+            freertos_rs_stream_buffer_receive_isr(
+                self.stream_buffer,
+                buf.as_mut_ptr() as FreeRtosMutVoidPtr,
+                buf.len(),
+                context.get_task_field_mut(),
+            )
+        }
+    }
+}
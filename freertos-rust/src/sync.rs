@@ -0,0 +1,70 @@
+//! Atomics used by the crate's lock-free internals.
+//!
+//! Everything that hand-rolls memory ordering should import its atomics from here rather
+//! than from `core`, so the same code can be model checked with `loom`. The models are in
+//! `tests/loom.rs`:
+//!
+//!     RUSTFLAGS="--cfg loom" cargo test -p freertos-rust --release --test loom
+//!
+//! Loom's atomics can't be built in a `const fn`, so statics and the types made by one
+//! keep `core`'s atomics, and loom doesn't check them. The models run without the kernel:
+//! critical regions don't exclude anything under loom, so a model has a single writer of
+//! what they guard.
+//!
+//! What a task writes before it signals another is visible to that task once its wait
+//! for the signal returned, on weakly ordered cores too:
+//!
+//! * queues, stream buffers, semaphores and mutexes synchronize in the kernel,
+//! * the notification ends and the notify calls of task handles fence around the
+//!   kernel call, as the kernel only orders the notification value,
+//! * `StatusCell`, `StateMachinePump` and the other lock-free types pair a
+//!   release with an acquire.
+//!
+//! Signals of the application, which the crate doesn't see, use `publish_with_payload`
+//! and `consume_payload`.
+
+use atomic::{fence, Ordering};
+
+#[cfg(not(loom))]
+pub(crate) use core::sync::atomic;
+
+#[cfg(loom)]
+pub(crate) use loom::sync::atomic;
+
+/// `spin_loop`, which lets the other threads of a model run under loom.
+#[cfg(not(loom))]
+pub(crate) use core::hint;
+
+#[cfg(loom)]
+pub(crate) use loom::hint;
+
+/// Run `write`, which writes a payload, and make what it wrote visible to a task that
+/// sees a signal stored after it returned.
+///
+/// For signals the crate doesn't order, an index in an atomic stored `Relaxed`, a flag
+/// in memory a DMA controller reads, or a hook of the kernel. The task reading the
+/// payload reads it with `consume_payload` once it saw the signal:
+///
+///     let slot = publish_with_payload(|| {
+///         buffers[next].copy_from_slice(&frame);
+///         next
+///     });
+///     READY.store(slot as u32, Ordering::Relaxed);
+///
+///     // In the task reading it.
+///     let slot = READY.load(Ordering::Relaxed) as usize;
+///     let frame = consume_payload(|| buffers[slot]);
+#[inline]
+pub fn publish_with_payload<R, F: FnOnce() -> R>(write: F) -> R {
+    let published = write();
+    fence(Ordering::Release);
+    published
+}
+
+/// Run `read`, which reads a payload published with `publish_with_payload`, after the
+/// signal of the payload was seen.
+#[inline]
+pub fn consume_payload<R, F: FnOnce() -> R>(read: F) -> R {
+    fence(Ordering::Acquire);
+    read()
+}
@@ -1,8 +1,12 @@
 use crate::base::*;
 use crate::isr::*;
+use crate::no_block::check_blocking;
 use crate::operating_system::*;
 use crate::prelude::v1::*;
+#[cfg(feature = "fmt")]
+use crate::render::*;
 use crate::shim::*;
+use crate::sync::atomic::{fence, Ordering};
 use crate::units::*;
 use crate::utils::*;
 
@@ -12,17 +16,20 @@ impl !ISRSafe for TaskRemoteHandle {}
 pub trait TaskHandle {
     fn raw_handle(&self) -> FreeRtosTaskHandle;
 
-    /// Get the name of the current task.
-    fn get_name(&self) -> Result<String, ()> {
-        unsafe {
-            let name_ptr = freertos_rs_task_get_name(self.raw_handle());
-            let name = str_from_c_string(name_ptr);
-            if let Ok(name) = name {
-                return Ok(name);
-            }
+    /// Get the name of the task. Fails with `StringConversionError` if it isn't UTF-8.
+    fn get_name(&self) -> Result<String, FreeRtosError> {
+        unsafe { str_from_c_string(freertos_rs_task_get_name(self.raw_handle())) }
+    }
 
-            Err(())
-        }
+    /// Get the name of the current task as raw bytes, without UTF-8 validation.
+    fn get_name_bytes(&self) -> &[u8] {
+        unsafe { bytes_from_c_string(freertos_rs_task_get_name(self.raw_handle())) }
+    }
+
+    /// The number the kernel gave the task when it was created, the `task_number` of its
+    /// `FreeRtosTaskStatus`. Needs `configUSE_TRACE_FACILITY`.
+    fn get_task_number(&self) -> FreeRtosUBaseType {
+        unsafe { freertos_rs_task_get_task_number(self.raw_handle()) }
     }
 
     /// Get the minimum amount of stack that was ever left on this task.
@@ -30,6 +37,27 @@ pub trait TaskHandle {
         unsafe { freertos_rs_get_stack_high_water_mark(self.raw_handle()) as u32 }
     }
 
+    /// Get the task's current priority. While the task holds a mutex this may be a
+    /// priority inherited from a task waiting for the mutex.
+    fn get_priority(&self) -> TaskPriority {
+        unsafe { TaskPriority(freertos_rs_task_priority_get(self.raw_handle()) as u8) }
+    }
+
+    /// Change the task's priority, given as a `TaskPriority` or a `PriorityBand`.
+    /// Priorities of `configMAX_PRIORITIES` and above are clamped to
+    /// `configMAX_PRIORITIES - 1`. Returns the priority that was set.
+    fn set_priority<P: Into<TaskPriority>>(&self, priority: P) -> TaskPriority
+    where
+        Self: Sized,
+    {
+        unsafe {
+            TaskPriority(freertos_rs_task_priority_set(
+                self.raw_handle(),
+                priority.into().to_freertos(),
+            ) as u8)
+        }
+    }
+
     /// Get an ISR safe handle.
     /// This is safe because tasks never terminate.
     fn new_isr_safe_handle(&self) -> TaskISRHandle {
@@ -47,8 +75,84 @@ impl ISRSafeHandle<TaskISRHandle> for dyn TaskHandle {
     }
 }
 
+/// A task name copied into a fixed size buffer, so it can be sent through queues.
+/// Longer names are truncated.
+#[derive(Copy, Clone, PartialEq, Eq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub struct TaskName {
+    bytes: [u8; TaskName::MAX_LEN],
+    len: u8,
+}
+
+impl TaskName {
+    pub const MAX_LEN: usize = 16;
+
+    pub fn from_bytes(name: &[u8]) -> TaskName {
+        let len = name.len().min(Self::MAX_LEN);
+        let mut bytes = [0; Self::MAX_LEN];
+        bytes[..len].copy_from_slice(&name[..len]);
+
+        TaskName {
+            bytes,
+            len: len as u8,
+        }
+    }
+
+    pub fn as_bytes(&self) -> &[u8] {
+        &self.bytes[..self.len as usize]
+    }
+
+    pub fn as_str(&self) -> Result<&str, FreeRtosError> {
+        core::str::from_utf8(self.as_bytes()).map_err(|_| FreeRtosError::StringConversionError)
+    }
+}
+
+impl fmt::Debug for TaskName {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self.as_str() {
+            Ok(name) => write!(f, "{:?}", name),
+            Err(_) => write!(f, "{:?}", self.as_bytes()),
+        }
+    }
+}
+
+/// The longest name the kernel keeps for a task or timer, in bytes:
+/// `configMAX_TASK_NAME_LEN` less the nul terminator.
+pub fn max_name_len() -> usize {
+    (unsafe { freertos_rs_max_task_name_len() } as usize).saturating_sub(1)
+}
+
+/// `name` up to its first nul byte, cut to `max_name_len` bytes at a char boundary, so it
+/// stays valid UTF-8. For names that come from elsewhere, which could otherwise fail to
+/// spawn a task with `NameTooLong` or `InvalidName`.
+pub fn truncate_name(name: &str) -> &str {
+    let name = name.split('\0').next().unwrap_or("");
+    let mut len = name.len().min(max_name_len());
+    while !name.is_char_boundary(len) {
+        len -= 1;
+    }
+    &name[..len]
+}
+
+/// Fails with `InvalidName` if `name` has a nul byte.
+pub(crate) fn check_name_nul(name: &str) -> Result<(), FreeRtosError> {
+    if name.as_bytes().contains(&0) {
+        return Err(FreeRtosError::InvalidName);
+    }
+    Ok(())
+}
+
+/// Fails if the kernel wouldn't keep `name` as it is, as a task or timer name.
+pub(crate) fn check_name(name: &str) -> Result<(), FreeRtosError> {
+    check_name_nul(name)?;
+    if name.len() > max_name_len() {
+        return Err(FreeRtosError::NameTooLong);
+    }
+    Ok(())
+}
+
 /// Task's execution priority. Low priority numbers denote low priority tasks.
-#[derive(Debug, Copy, Clone)]
+#[derive(Debug, Copy, Clone, PartialEq, Eq, PartialOrd, Ord, Hash)]
 pub struct TaskPriority(pub u8);
 
 /// Notification to be sent to a task.
@@ -69,7 +173,7 @@ pub enum TaskNotification {
 }
 
 impl TaskNotification {
-    fn to_freertos(&self) -> (u32, u8) {
+    pub(crate) fn to_freertos(&self) -> (u32, u8) {
         match *self {
             TaskNotification::NoAction => (0, 0),
             TaskNotification::SetBits(v) => (v, 1),
@@ -81,11 +185,98 @@ impl TaskNotification {
 }
 
 impl TaskPriority {
-    fn to_freertos(&self) -> FreeRtosUBaseType {
+    pub(crate) fn to_freertos(&self) -> FreeRtosUBaseType {
         self.0 as FreeRtosUBaseType
     }
 }
 
+/// A task that couldn't be created, with the closure it would have run.
+///
+/// Converts into `FreeRtosError`, dropping the closure, so `?` still works in functions
+/// returning `FreeRtosError`.
+pub struct TaskSpawnError<F> {
+    pub error: FreeRtosError,
+    pub func: F,
+}
+
+impl<F> fmt::Debug for TaskSpawnError<F> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_struct("TaskSpawnError")
+            .field("error", &self.error)
+            .finish()
+    }
+}
+
+impl<F> From<TaskSpawnError<F>> for FreeRtosError {
+    fn from(e: TaskSpawnError<F>) -> FreeRtosError {
+        e.error
+    }
+}
+
+/// Helper builder for a new task.
+pub struct TaskBuilder {
+    name: String,
+    stack_size: usize,
+    priority: TaskPriority,
+}
+
+impl TaskBuilder {
+    pub const DEFAULT_NAME: &'static str = "unnamed";
+    /// In words.
+    pub const DEFAULT_STACK_SIZE: usize = 512;
+    pub const DEFAULT_PRIORITY: TaskPriority = TaskPriority(1);
+
+    /// Create a new task builder with the default name, stack size and priority.
+    pub fn new(_os: FreeRTOS) -> TaskBuilder {
+        TaskBuilder {
+            name: Self::DEFAULT_NAME.into(),
+            stack_size: Self::DEFAULT_STACK_SIZE,
+            priority: Self::DEFAULT_PRIORITY,
+        }
+    }
+
+    /// Set the name of the task. Starting the task fails with `NameTooLong` if it is
+    /// longer than `max_name_len`, or with `InvalidName` if it has a nul byte.
+    pub fn name(&mut self, name: &str) -> &mut Self {
+        self.name = name.into();
+        self
+    }
+
+    /// Set the stack size of the task, in words. Starting the task fails with
+    /// `StackTooLarge` if it doesn't fit in `FreeRtosStackDepthType`.
+    pub fn stack_size(&mut self, stack_size: usize) -> &mut Self {
+        self.stack_size = stack_size;
+        self
+    }
+
+    /// Set the priority of the task, as a `TaskPriority` or a `PriorityBand`.
+    pub fn priority<P: Into<TaskPriority>>(&mut self, priority: P) -> &mut Self {
+        self.priority = priority.into();
+        self
+    }
+
+    pub fn get_name(&self) -> &str {
+        &self.name
+    }
+
+    pub fn get_stack_size(&self) -> usize {
+        self.stack_size
+    }
+
+    pub fn get_priority(&self) -> TaskPriority {
+        self.priority
+    }
+
+    /// Spawn the task. If it can't be created, the closure is handed back in the error.
+    pub fn start<F>(&self, func: F) -> Result<TaskRemoteHandle, TaskSpawnError<F>>
+    where
+        F: FnOnce(&TaskSelfHandle, FreeRTOS) -> !,
+        F: Send + 'static,
+    {
+        TaskRemoteHandle::spawn(&self.name, self.stack_size, self.priority, func)
+    }
+}
+
 pub struct TaskSelfHandle {
     task_handle: FreeRtosTaskHandle,
 }
@@ -113,32 +304,72 @@ impl TaskSelfHandle {
     }
 
     /// Take the notification and either clear the notification value or decrement it by one.
+    #[inline]
     pub fn take_notification<D: DurationTicks>(&self, clear: bool, wait_for: D) -> u32 {
-        unsafe { freertos_rs_task_notify_take(if clear { 1 } else { 0 }, wait_for.to_ticks()) }
+        self.take_notification_ticks(clear, wait_for.to_ticks())
+    }
+
+    /// `take_notification`, failing with `Timeout` instead of returning 0 when no
+    /// notification came within `wait_for`. Returns the notification value before it was
+    /// cleared or decremented.
+    #[inline]
+    pub fn try_take_notification<D: DurationTicks>(
+        &self,
+        clear: bool,
+        wait_for: D,
+    ) -> Result<u32, FreeRtosError> {
+        match self.take_notification_ticks(clear, wait_for.to_ticks()) {
+            0 => Err(timed_out()),
+            value => Ok(value),
+        }
+    }
+
+    fn take_notification_ticks(&self, clear: bool, wait_for: FreeRtosTickType) -> u32 {
+        check_blocking("TaskSelfHandle::take_notification", ptr::null(), wait_for);
+
+        let value = unsafe { freertos_rs_task_notify_take(if clear { 1 } else { 0 }, wait_for) };
+        fence(Ordering::Acquire);
+        value
     }
 
     /// Wait for a notification to be posted.
+    #[inline]
     pub fn wait_for_notification<D: DurationTicks>(
         &self,
         clear_bits_enter: u32,
         clear_bits_exit: u32,
         wait_for: D,
     ) -> Result<u32, FreeRtosError> {
+        self.wait_for_notification_ticks(clear_bits_enter, clear_bits_exit, wait_for.to_ticks())
+    }
+
+    fn wait_for_notification_ticks(
+        &self,
+        clear_bits_enter: u32,
+        clear_bits_exit: u32,
+        wait_for: FreeRtosTickType,
+    ) -> Result<u32, FreeRtosError> {
+        check_blocking(
+            "TaskSelfHandle::wait_for_notification",
+            ptr::null(),
+            wait_for,
+        );
+
         let mut val = 0;
         let r = unsafe {
             freertos_rs_task_notify_wait(
                 clear_bits_enter,
                 clear_bits_exit,
                 &mut val as *mut _,
-                wait_for.to_ticks(),
+                wait_for,
             )
         };
 
-        if r == 0 {
-            Ok(val)
-        } else {
-            Err(FreeRtosError::Timeout)
+        if r != 0 {
+            return Err(timed_out());
         }
+        fence(Ordering::Acquire);
+        Ok(val)
     }
 
     pub fn new_remote_handle(&self) -> TaskRemoteHandle {
@@ -146,10 +377,17 @@ impl TaskSelfHandle {
             task_handle: self.task_handle,
         }
     }
+
+    /// The handle of the task this is called from, for task entry points.
+    pub(crate) unsafe fn current() -> TaskSelfHandle {
+        TaskSelfHandle {
+            task_handle: freertos_rs_get_current_task(),
+        }
+    }
 }
 
-/// Handle for a FreeRTOS task
-#[derive(Debug)]
+/// Handle for a FreeRTOS task. Handles to the same task compare equal.
+#[derive(Debug, PartialEq, Eq, Hash)]
 pub struct TaskRemoteHandle {
     task_handle: FreeRtosTaskHandle,
 }
@@ -161,19 +399,44 @@ impl TaskHandle for TaskRemoteHandle {
 }
 
 impl TaskRemoteHandle {
-    /// Spawn a new independent task.
+    /// Spawn a new independent task. Fails with `NameTooLong` if `name` is longer than
+    /// `max_name_len`, instead of having the kernel cut it short, or with `InvalidName` if
+    /// it has a nul byte. `stack_depth` is in words and fails with `StackTooLarge` if it
+    /// doesn't fit in `FreeRtosStackDepthType`, which is `u16` unless the
+    /// `stack_depth_u32` feature is enabled.
     pub fn new<F>(
         _os: FreeRTOS,
         name: &str,
-        stack_depth: u16,
-        priority: TaskPriority,
+        stack_depth: usize,
+        priority: impl Into<TaskPriority>,
         func: F,
     ) -> Result<TaskRemoteHandle, FreeRtosError>
     where
         F: FnOnce(&TaskSelfHandle, FreeRTOS) -> !,
         F: Send + 'static,
     {
-        TaskRemoteHandle::spawn(name, stack_depth, priority, func)
+        Ok(TaskRemoteHandle::spawn(
+            name,
+            stack_depth,
+            priority.into(),
+            func,
+        )?)
+    }
+
+    /// Spawn a new independent task, handing the closure back if the task couldn't be
+    /// created, so whatever it captured isn't lost.
+    pub fn try_new<F>(
+        _os: FreeRTOS,
+        name: &str,
+        stack_depth: usize,
+        priority: impl Into<TaskPriority>,
+        func: F,
+    ) -> Result<TaskRemoteHandle, TaskSpawnError<F>>
+    where
+        F: FnOnce(&TaskSelfHandle, FreeRTOS) -> !,
+        F: Send + 'static,
+    {
+        TaskRemoteHandle::spawn(name, stack_depth, priority.into(), func)
     }
 
     /// Construct task from raw FreeRTOS handle.
@@ -181,26 +444,62 @@ impl TaskRemoteHandle {
         TaskRemoteHandle { task_handle }
     }
 
-    unsafe fn spawn_inner(
-        f: Box<dyn FnOnce(&TaskSelfHandle, FreeRTOS) -> !>,
+    /// Delete the task, wherever it is in its code. Nothing on its stack is dropped and the
+    /// closure it was spawned with is leaked with everything it owns, so mutexes it holds
+    /// stay locked and its heap allocations are never freed. Needs `INCLUDE_vTaskDelete`.
+    ///
+    /// # Safety
+    ///
+    /// Only this handle is consumed. Other handles to the same task, from
+    /// `new_remote_handle`, `from_raw`, `get_all_tasks` or the task itself, aren't
+    /// invalidated, and using one afterwards is undefined: the kernel frees the task and
+    /// may give its memory, and so its handle, to a new task. Nothing may borrow from the
+    /// task's stack either.
+    ///
+    /// Deleting the calling task this way doesn't return, see `TaskSelfHandle::delete`.
+    pub unsafe fn delete(self) {
+        freertos_rs_delete_task(self.task_handle);
+    }
+
+    /// Move the task out of the Blocked state right away, with `xTaskAbortDelay`. Fails
+    /// with `TaskNotBlocked` if it wasn't blocked, e.g. when it is running, ready or
+    /// suspended.
+    ///
+    /// The task is unblocked whatever it waits for. A `delay` just returns early, the task
+    /// has to check on its own why it woke up. A call blocked on a queue, a semaphore, a
+    /// mutex or a notification returns as if its timeout had expired, so with its timeout
+    /// error, even with an infinite timeout.
+    pub fn abort_delay(&self) -> Result<(), FreeRtosError> {
+        match unsafe { freertos_rs_task_abort_delay(self.task_handle) } {
+            0 => Ok(()),
+            _ => Err(FreeRtosError::TaskNotBlocked),
+        }
+    }
+
+    unsafe fn spawn_inner<F>(
+        f: F,
         name: &str,
-        stack_size: u16,
+        stack_depth: FreeRtosStackDepthType,
         priority: TaskPriority,
-    ) -> Result<TaskRemoteHandle, FreeRtosError> {
-        let f = Box::new(f);
-        let param_ptr = &*f as *const _ as *mut _;
+    ) -> Result<TaskRemoteHandle, TaskSpawnError<F>>
+    where
+        F: FnOnce(&TaskSelfHandle, FreeRTOS) -> !,
+        F: Send + 'static,
+    {
+        // Ownership of the box only passes to the task once it was created.
+        let param_ptr = Box::into_raw(Box::new(f));
 
         let (success, task_handle) = {
             let name = name.as_bytes();
             let name_len = name.len();
-            let mut task_handle = mem::zeroed::<CVoid>();
+            let mut task_handle: FreeRtosTaskHandle = ptr::null();
 
             let ret = freertos_rs_spawn_task(
-                thread_start,
-                param_ptr,
+                thread_start::<F>,
+                param_ptr as FreeRtosMutVoidPtr,
                 name.as_ptr(),
                 name_len as u8,
-                stack_size,
+                stack_depth,
                 priority.to_freertos(),
                 &mut task_handle,
             );
@@ -208,43 +507,66 @@ impl TaskRemoteHandle {
             (ret == 0, task_handle)
         };
 
-        if success {
-            mem::forget(f);
-        } else {
-            return Err(FreeRtosError::OutOfMemory);
+        if !success {
+            return Err(TaskSpawnError {
+                error: FreeRtosError::OutOfMemory,
+                func: *Box::from_raw(param_ptr),
+            });
         }
 
-        extern "C" fn thread_start(main: *mut CVoid) -> *mut CVoid {
+        extern "C" fn thread_start<F>(main: *mut CVoid) -> *mut CVoid
+        where
+            F: FnOnce(&TaskSelfHandle, FreeRTOS) -> !,
+        {
             unsafe {
-                let b = Box::from_raw(main as *mut Box<dyn FnOnce(&TaskSelfHandle, FreeRTOS) -> !>);
+                #[cfg(feature = "end_scheduler")]
+                freertos_rs_task_keep_thread();
+
+                let b = Box::from_raw(main as *mut F);
 
-                let self_handle = TaskSelfHandle {
-                    task_handle: freertos_rs_get_current_task(),
-                };
                 let os = FreeRTOS {};
 
-                b(&self_handle, os);
+                b(&TaskSelfHandle::current(), os);
             }
         }
 
-        Ok(TaskRemoteHandle {
-            task_handle: task_handle as usize as *const _,
-        })
+        Ok(TaskRemoteHandle { task_handle })
     }
 
     fn spawn<F>(
         name: &str,
-        stack_size: u16,
+        stack_size: usize,
         priority: TaskPriority,
         f: F,
-    ) -> Result<TaskRemoteHandle, FreeRtosError>
+    ) -> Result<TaskRemoteHandle, TaskSpawnError<F>>
     where
         F: FnOnce(&TaskSelfHandle, FreeRTOS) -> !,
         F: Send + 'static,
     {
-        unsafe {
-            return TaskRemoteHandle::spawn_inner(Box::new(f), name, stack_size, priority);
+        if let Err(error) = check_name(name) {
+            return Err(TaskSpawnError { error, func: f });
+        }
+        if stack_size > FreeRtosStackDepthType::MAX as usize {
+            return Err(TaskSpawnError {
+                error: FreeRtosError::StackTooLarge,
+                func: f,
+            });
         }
+        let stack_depth = stack_size as FreeRtosStackDepthType;
+
+        #[cfg(feature = "footprint_diag")]
+        let footprint = crate::footprint::ClosureFootprint::of(&f);
+
+        let task = unsafe { TaskRemoteHandle::spawn_inner(f, name, stack_depth, priority)? };
+
+        #[cfg(feature = "footprint_diag")]
+        crate::footprint::record(
+            crate::footprint::ClosureOwner::Task(task.raw_handle()),
+            name,
+            footprint,
+        );
+
+        Ok(task)
     }
 
     /// Forcibly set the notification value for this task.
@@ -252,8 +574,10 @@ impl TaskRemoteHandle {
         self.notify(TaskNotification::OverwriteValue(val))
     }
 
-    /// Notify this task.
+    /// Notify this task. What the calling task wrote before is visible to this task once
+    /// its wait for the notification returned.
     pub fn notify(&self, notification: TaskNotification) {
+        fence(Ordering::Release);
         unsafe {
             let n = notification.to_freertos();
             freertos_rs_task_notify(self.raw_handle(), n.0, n.1);
@@ -269,9 +593,10 @@ impl TaskISRHandle {
     /// Notify this task from an interrupt.
     pub fn notify(
         &self,
-        context: &InterruptContext,
+        context: &mut InterruptContext,
         notification: TaskNotification,
     ) -> Result<(), FreeRtosError> {
+        fence(Ordering::Release);
         unsafe {
             let n = notification.to_freertos();
             let t = freertos_rs_task_notify_isr(
@@ -287,55 +612,254 @@ impl TaskISRHandle {
             }
         }
     }
+
+    /// Increment the task's notification value, like `notify` with
+    /// `TaskNotification::Increment` but cheaper, for a task waiting in `take_notification`.
+    pub fn notify_give(&self, context: &mut InterruptContext) {
+        fence(Ordering::Release);
+        unsafe {
+            freertos_rs_task_notify_give_isr(self.task_handle, context.get_task_field_mut());
+        }
+    }
+
+    /// Get the task's current priority from an interrupt.
+    pub fn get_priority_isr(&self, _context: &mut InterruptContext) -> TaskPriority {
+        unsafe { TaskPriority(freertos_rs_task_priority_get_isr(self.task_handle) as u8) }
+    }
 }
 
 #[derive(Debug)]
 pub struct FreeRtosSchedulerState {
     pub tasks: Vec<FreeRtosTaskStatus>,
     pub total_run_time: u32,
+    /// The tick count when the state was taken, the time the run time counters add up to.
+    pub tick_count: FreeRtosTickType,
 }
 
-impl fmt::Display for FreeRtosSchedulerState {
-    fn fmt(&self, fmt: &mut fmt::Formatter) -> Result<(), fmt::Error> {
-        fmt.write_str("FreeRTOS tasks\r\n")?;
-
-        write!(fmt, "{id: <6} | {name: <16} | {state: <9} | {priority: <8} | {stack: >10} | {cpu_abs: >10} | {cpu_rel: >4}\r\n",
-               id = "ID",
-               name = "Name",
-               state = "State",
-               priority = "Priority",
-               stack = "Stack left",
-               cpu_abs = "CPU",
-               cpu_rel = "%"
-        )?;
+/// The share of `total` that `run_time` is, in permille, rounded down. `None` without
+/// run time stats, or for a counter that doesn't fit in the total.
+fn permille(run_time: u32, total: u32) -> Option<u32> {
+    if total == 0 || run_time > total {
+        None
+    } else {
+        Some((run_time as u64 * 1000 / total as u64) as u32)
+    }
+}
 
-        for task in &self.tasks {
-            write!(fmt, "{id: <6} | {name: <16} | {state: <9} | {priority: <8} | {stack: >10} | {cpu_abs: >10} | {cpu_rel: >4}\r\n",
-                   id = task.task_number,
-                   name = task.name,
-                   state = format!("{:?}", task.task_state),
-                   priority = task.current_priority.0,
-                   stack = task.stack_high_water_mark,
-                   cpu_abs = task.run_time_counter,
-                   cpu_rel = if self.total_run_time > 0 && task.run_time_counter <= self.total_run_time {
-                       let p = (((task.run_time_counter as u64) * 100) / self.total_run_time as u64) as u32;
-                       let ps = if p == 0 && task.run_time_counter > 0 {
-                           "<1".to_string()
-                       } else {
-                           p.to_string()
-                       };
-                       format!("{: >3}%", ps)
-                   } else {
-                       "-".to_string()
-                   }
-            )?;
+/// CPU usage, in permille to stay clear of floating point. With
+/// `configGENERATE_RUN_TIME_STATS` 0 the run time counters are all 0, and there is none.
+impl FreeRtosSchedulerState {
+    /// The task called `name`, the first one if there are several.
+    pub fn find_task(&self, name: &str) -> Option<&FreeRtosTaskStatus> {
+        self.tasks.iter().find(|task| task.name == name)
+    }
+
+    /// The share of the run time since boot that `task` ran for.
+    pub fn cpu_permille(&self, task: &FreeRtosTaskStatus) -> Option<u32> {
+        permille(task.run_time_counter, self.total_run_time)
+    }
+
+    /// The time `task` ran for since boot: its share of the run time, of the ticks the
+    /// scheduler ran for. The run time counters count at their own rate, which the crate
+    /// doesn't know, so this is as accurate as the share. Once the total run time wrapped
+    /// around it is no longer since boot.
+    pub fn run_time(&self, task: &FreeRtosTaskStatus) -> Option<Duration> {
+        if self.total_run_time == 0 || task.run_time_counter > self.total_run_time {
+            return None;
         }
+        let ticks = task.run_time_counter as u64 * self.tick_count as u64
+            / self.total_run_time as u64;
+        Some(Duration::ticks(ticks as FreeRtosTickType))
+    }
 
-        if self.total_run_time > 0 {
-            write!(fmt, "Total run time: {}\r\n", self.total_run_time)?;
+    /// The name and share of the run time since boot of every task, empty without run
+    /// time stats.
+    pub fn cpu_usage(&self) -> Vec<(String, u32)> {
+        self.tasks
+            .iter()
+            .filter_map(|task| Some((task.name.clone(), self.cpu_permille(task)?)))
+            .collect()
+    }
+
+    /// The run time of each task between `previous` and this state, both from
+    /// `FreeRTOS::get_all_tasks`.
+    ///
+    /// Tasks are matched by task number. A task that isn't in `previous` was created in
+    /// between, and all of its run time counts. The counters may wrap around once in
+    /// between, more often can't be told apart.
+    pub fn delta(&self, previous: &FreeRtosSchedulerState) -> SchedulerStateDelta {
+        let tasks = self
+            .tasks
+            .iter()
+            .map(|task| {
+                let before = previous
+                    .tasks
+                    .iter()
+                    .find(|p| p.task_number == task.task_number)
+                    .map_or(0, |p| p.run_time_counter);
+                TaskRunTime {
+                    name: task.name.clone(),
+                    task_number: task.task_number,
+                    run_time: task.run_time_counter.wrapping_sub(before),
+                }
+            })
+            .collect();
+
+        SchedulerStateDelta {
+            tasks,
+            total_run_time: self.total_run_time.wrapping_sub(previous.total_run_time),
+        }
+    }
+}
+
+/// The run time of the tasks over an interval, from `FreeRtosSchedulerState::delta`.
+#[derive(Debug, Clone)]
+pub struct SchedulerStateDelta {
+    pub tasks: Vec<TaskRunTime>,
+    pub total_run_time: u32,
+}
+
+/// A task's run time over an interval.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct TaskRunTime {
+    pub name: String,
+    pub task_number: FreeRtosUBaseType,
+    pub run_time: FreeRtosUnsignedLong,
+}
+
+impl SchedulerStateDelta {
+    /// The task called `name`, the first one if there are several.
+    pub fn find_task(&self, name: &str) -> Option<&TaskRunTime> {
+        self.tasks.iter().find(|task| task.name == name)
+    }
+
+    /// The share of the interval that `task` ran for.
+    pub fn cpu_permille(&self, task: &TaskRunTime) -> Option<u32> {
+        permille(task.run_time, self.total_run_time)
+    }
+
+    /// The name and share of the interval of every task, empty without run time stats.
+    pub fn cpu_usage(&self) -> Vec<(String, u32)> {
+        self.tasks
+            .iter()
+            .filter_map(|task| Some((task.name.clone(), self.cpu_permille(task)?)))
+            .collect()
+    }
+}
+
+/// The table is a title, a column header, a line per task and, with run time stats, a
+/// total.
+#[cfg(feature = "fmt")]
+impl Lines for FreeRtosSchedulerState {
+    fn line_count(&self) -> usize {
+        2 + self.tasks.len() + (self.total_run_time > 0) as usize
+    }
+
+    fn write_line(&self, index: usize, opts: RenderOptions, w: &mut dyn fmt::Write) -> fmt::Result {
+        if index == 0 {
+            return w.write_str("FreeRTOS tasks\r\n");
+        }
+        let task = if index == 1 {
+            None
+        } else {
+            match self.tasks.get(index - 2) {
+                Some(task) => Some(task),
+                None => {
+                    return write!(
+                        w,
+                        "Total run time: {}\r\n",
+                        Duration::ticks(self.tick_count)
+                    )
+                }
+            }
+        };
+
+        #[cfg(feature = "footprint_diag")]
+        write!(
+            w,
+            "{closure: >7} | ",
+            closure = match task.map(|t| t.closure) {
+                None => Cell::Text("Closure"),
+                Some(Some(c)) => Cell::Number(c.size as u64),
+                Some(None) => Cell::Text("-"),
+            }
+        )?;
+
+        let task = match task {
+            Some(task) => task,
+            None => {
+                return write!(w, "{id: <6} | {name: <16} | {state: <9} | {priority: <8} | {stack: >10} | {run_time: >width$} | {cpu_rel: >4}\r\n",
+                    id = "ID",
+                    name = "Name",
+                    state = "State",
+                    priority = "Priority",
+                    stack = "Stack left",
+                    run_time = "Run time",
+                    cpu_rel = "%",
+                    width = CompactDuration::<FreeRtosTimeUnitsShimmed>::WIDTH
+                )
+            }
+        };
+
+        write!(w, "{id: <6} | {name: <16} | {state: <9} | {priority: <8} | {stack: >10} | ",
+               id = task.task_number,
+               name = task.name,
+               state = Cell::Text(task.task_state.name()),
+               priority = match task.current_priority.band() {
+                   Some(band) if opts.alternate => Cell::Text(band.name()),
+                   _ => Cell::Number(task.current_priority.0 as u64),
+               },
+               stack = task.stack_high_water_mark,
+        )?;
+        match self.run_time(task) {
+            Some(run_time) => write!(w, "{} | ", run_time.display_compact())?,
+            None => write!(
+                w,
+                "{: >width$} | ",
+                "-",
+                width = CompactDuration::<FreeRtosTimeUnitsShimmed>::WIDTH
+            )?,
+        }
+        match self.cpu_permille(task) {
+            Some(p) if p < 10 && task.run_time_counter > 0 => w.write_str(" <1%\r\n"),
+            Some(p) => write!(w, "{: >3}%\r\n", p / 10),
+            None => write!(w, "{: >4}\r\n", "-"),
         }
+    }
+}
+
+/// Rendering the table a chunk at a time, without allocating, for links too slow to
+/// take it at once. See `RenderCursor`.
+#[cfg(feature = "fmt")]
+impl FreeRtosSchedulerState {
+    /// Fill `buf` with the next bytes of the table. Returns the number of bytes
+    /// written, which is less than `buf.len()` only for the last chunk, or `None` once
+    /// the whole table was rendered.
+    ///
+    /// The chunks add up to the output of `Display` with the same options.
+    pub fn render_next(
+        &self,
+        opts: RenderOptions,
+        cursor: &mut RenderCursor,
+        buf: &mut [u8],
+    ) -> Option<usize> {
+        render_next(self, opts, cursor, buf)
+    }
 
-        Ok(())
+    /// The size of the rendered table in bytes, to size buffers with.
+    pub fn estimate_rendered_size(&self, opts: RenderOptions) -> usize {
+        rendered_size(self, opts)
+    }
+}
+
+/// With `{:#}`, priorities that match a `PriorityBand` are shown by name.
+#[cfg(feature = "fmt")]
+impl fmt::Display for FreeRtosSchedulerState {
+    fn fmt(&self, fmt: &mut fmt::Formatter) -> Result<(), fmt::Error> {
+        let opts = RenderOptions {
+            alternate: fmt.alternate(),
+        };
+        self.write_all(opts, fmt)
     }
 }
 
@@ -348,5 +872,8 @@ pub struct FreeRtosTaskStatus {
     pub current_priority: TaskPriority,
     pub base_priority: TaskPriority,
     pub run_time_counter: FreeRtosUnsignedLong,
-    pub stack_high_water_mark: FreeRtosUnsignedShort,
+    pub stack_high_water_mark: FreeRtosStackDepthType,
+    /// The size of the task's closure, if it was spawned by this crate.
+    #[cfg(feature = "footprint_diag")]
+    pub closure: Option<crate::footprint::ClosureFootprint>,
 }
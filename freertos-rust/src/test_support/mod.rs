@@ -0,0 +1,6 @@
+//! Helpers for testing applications and the crate itself on a running kernel.
+
+pub mod stress;
+mod virtual_time;
+
+pub use self::virtual_time::*;
@@ -0,0 +1,653 @@
+//! Randomized stress runs over the crate's primitives, for soak tests.
+//!
+//! A `StressScenario` spawns worker tasks that pick operations from a seeded random mix
+//! and run them against one shared set of objects: a queue, a mutex, a counting
+//! semaphore, a task counting notifications and a one-shot timer. Every operation waits
+//! a few ticks at most, and the run checks that:
+//!
+//! - queue items are conserved: each item sent is received once and unchanged, by the
+//!   sequence numbers and checksums of every sender,
+//! - the mutex is exclusive: no two workers are ever inside it at once, and the counter
+//!   it guards matches the number of locks,
+//! - semaphore permits are conserved: no more are held than exist, and all of them are
+//!   back at the end,
+//! - notifications are conserved: every increment sent is taken,
+//! - the timer fires at most once per successful start,
+//! - no worker stalls: each operation ends within the watchdog bound.
+//!
+//! The operation sequences follow from the seed, which a failed `StressReport` shows for
+//! a rerun. The interleaving is up to the scheduler, so a rerun repeats the same load
+//! rather than the exact same run. Durations are in ticks, virtual time on the POSIX
+//! port.
+//!
+//! ```rust
+//! # use freertos_rs::*;
+//! use freertos_rs::test_support::stress::StressScenario;
+//!
+//! // From a task at a higher priority than the workers.
+//! let report = StressScenario::smoke(0x1234).run(FreeRTOS {})?;
+//! assert!(report.passed(), "{}", report);
+//! ```
+
+use crate::base::*;
+use crate::mutex::*;
+use crate::operating_system::*;
+use crate::prelude::v1::*;
+use crate::queue::*;
+use crate::semaphore::*;
+use crate::shim::*;
+use crate::sync::atomic::{AtomicBool, AtomicU32, AtomicU8, Ordering};
+use crate::task::*;
+use crate::ticks::*;
+use crate::timers::*;
+use crate::units::*;
+
+/// The kinds of operations the workers pick from.
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum Operation {
+    /// Send an item to the queue or receive one, half of the time each.
+    Queue,
+    /// Lock the mutex and increment the counter it guards.
+    Mutex,
+    /// Take a permit of the semaphore and give it back.
+    Semaphore,
+    /// Increment the notification value of the counting task.
+    Notify,
+    /// Start, reset, stop or change the period of the timer.
+    Timer,
+}
+
+impl Operation {
+    pub const ALL: [Operation; 5] = [
+        Operation::Queue,
+        Operation::Mutex,
+        Operation::Semaphore,
+        Operation::Notify,
+        Operation::Timer,
+    ];
+
+    fn from_index(index: u8) -> Operation {
+        Operation::ALL[index as usize]
+    }
+}
+
+/// An invariant a stress run found broken.
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum StressFailure {
+    /// A received item didn't match its checksum. The first one is reported.
+    QueueItemCorrupted { sender: u32, seq: u32, count: u32 },
+    /// The items received from a sender aren't the ones it sent, by count or by the
+    /// sum of their sequence numbers.
+    QueueItemsLost {
+        sender: u32,
+        sent: u32,
+        received: u32,
+    },
+    /// Workers were inside the mutex at the same time.
+    MutualExclusion { overlaps: u32 },
+    /// The counter guarded by the mutex doesn't match the number of locks.
+    MutexCount { locks: u32, counter: u32 },
+    /// More permits were held than the semaphore has.
+    PermitsOverdrawn { times: u32 },
+    /// The semaphore didn't have all of its permits back at the end.
+    PermitLeak { permits: u32, count: u32 },
+    /// The counting task didn't take every notification sent.
+    NotificationsLost { sent: u32, taken: u32 },
+    /// The timer fired more often than it was started.
+    TimerOverfired { starts: u32, fires: u32 },
+    /// A worker was in an operation for longer than the watchdog bound.
+    Stalled {
+        worker: usize,
+        operation: Operation,
+        ticks: FreeRtosTickType,
+    },
+}
+
+/// The outcome of `StressScenario::run`.
+#[derive(Debug, Clone)]
+pub struct StressReport {
+    pub seed: u32,
+    /// The operations the workers completed.
+    pub operations: u32,
+    pub failures: Vec<StressFailure>,
+}
+
+impl StressReport {
+    pub fn passed(&self) -> bool {
+        self.failures.is_empty()
+    }
+}
+
+#[cfg(feature = "fmt")]
+impl fmt::Display for StressReport {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "stress seed {:#010x}: {} operations, ",
+            self.seed, self.operations
+        )?;
+        if self.passed() {
+            return f.write_str("passed");
+        }
+        write!(f, "{} failures", self.failures.len())?;
+        for failure in &self.failures {
+            write!(f, "\n  {:?}", failure)?;
+        }
+        write!(f, "\n  rerun with StressScenario::new({:#010x})", self.seed)
+    }
+}
+
+/// A stress run, configured like a `TaskBuilder`. See the module documentation.
+#[derive(Debug, Clone)]
+pub struct StressScenario {
+    seed: u32,
+    tasks: usize,
+    duration: FreeRtosTickType,
+    watchdog: FreeRtosTickType,
+    max_wait: FreeRtosTickType,
+    stack_size: usize,
+    priority: TaskPriority,
+    queue_size: usize,
+    permits: u32,
+    weights: [u8; 5],
+}
+
+impl StressScenario {
+    /// Three workers for a second, with every operation equally likely.
+    pub fn new(seed: u32) -> StressScenario {
+        StressScenario {
+            seed,
+            tasks: 3,
+            duration: Duration::ms(1000).to_ticks(),
+            watchdog: Duration::ms(200).to_ticks(),
+            max_wait: 2,
+            stack_size: 256,
+            priority: TaskPriority(1),
+            queue_size: 4,
+            permits: 2,
+            weights: [1; 5],
+        }
+    }
+
+    /// A run of a few seconds, for the normal test suite.
+    pub fn smoke(seed: u32) -> StressScenario {
+        StressScenario::new(seed)
+    }
+
+    /// A long run of more workers with an operation mix of `weights`, in the order of
+    /// `Operation::ALL`.
+    pub fn soak(seed: u32, weights: [u8; 5]) -> StressScenario {
+        let mut scenario = StressScenario::new(seed);
+        scenario
+            .tasks(6)
+            .duration(Duration::ms(10 * 60 * 1000))
+            .weights(weights);
+        scenario
+    }
+
+    pub fn tasks(&mut self, tasks: usize) -> &mut Self {
+        self.tasks = tasks.max(1);
+        self
+    }
+
+    /// How long the workers run.
+    pub fn duration<D: DurationTicks>(&mut self, duration: D) -> &mut Self {
+        self.duration = duration.to_ticks();
+        self
+    }
+
+    /// How long an operation may take before its worker counts as stalled. Must be
+    /// well above `max_wait` and the time slices of the workers.
+    pub fn watchdog<D: DurationTicks>(&mut self, watchdog: D) -> &mut Self {
+        self.watchdog = watchdog.to_ticks();
+        self
+    }
+
+    /// The longest an operation waits for a kernel object.
+    pub fn max_wait<D: DurationTicks>(&mut self, max_wait: D) -> &mut Self {
+        self.max_wait = max_wait.to_ticks();
+        self
+    }
+
+    pub fn stack_size(&mut self, stack_size: usize) -> &mut Self {
+        self.stack_size = stack_size;
+        self
+    }
+
+    /// The priority of the workers. The task calling `run` must have a higher one.
+    pub fn priority<P: Into<TaskPriority>>(&mut self, priority: P) -> &mut Self {
+        self.priority = priority.into();
+        self
+    }
+
+    pub fn queue_size(&mut self, queue_size: usize) -> &mut Self {
+        self.queue_size = queue_size.max(1);
+        self
+    }
+
+    pub fn permits(&mut self, permits: u32) -> &mut Self {
+        self.permits = permits.max(1);
+        self
+    }
+
+    /// How likely each operation is, in the order of `Operation::ALL`. All zero means
+    /// all equally likely.
+    pub fn weights(&mut self, weights: [u8; 5]) -> &mut Self {
+        self.weights = if weights.iter().all(|&w| w == 0) {
+            [1; 5]
+        } else {
+            weights
+        };
+        self
+    }
+
+    /// Run the scenario and check its invariants. Blocks for its duration, and must be
+    /// called from a task at a higher priority than the workers, which it watches.
+    ///
+    /// Fails only if the objects or tasks can't be created. Workers that stall are
+    /// left behind, with the objects they use.
+    pub fn run(&self, os: FreeRTOS) -> Result<StressReport, FreeRtosError> {
+        let taken = Arc::new(AtomicU32::new(0));
+        let sink_stop = Arc::new(AtomicBool::new(false));
+        let sink_done = Arc::new(AtomicBool::new(false));
+        let sink = {
+            let (taken, stop, done) = (taken.clone(), sink_stop.clone(), sink_done.clone());
+            os.new_task(
+                "stress sink",
+                self.stack_size,
+                self.priority,
+                move |task, _| {
+                    {
+                        let (taken, stop, done) = (taken, stop, done);
+                        loop {
+                            let stopping = stop.load(Ordering::SeqCst);
+                            let n = task.take_notification(true, Duration::ticks(1));
+                            taken.fetch_add(n, Ordering::SeqCst);
+                            if stopping && n == 0 {
+                                break;
+                            }
+                        }
+                        done.store(true, Ordering::SeqCst);
+                    }
+                    unsafe { task.delete() }
+                },
+            )?
+        };
+
+        let fires = Arc::new(AtomicU32::new(0));
+        let timer = {
+            let fires = fires.clone();
+            TimerBuilder::new(os, Duration::ticks(1))
+                .set_name("stress")
+                .one_shot()
+                .create(move |_| {
+                    fires.fetch_add(1, Ordering::SeqCst);
+                })?
+        };
+
+        let shared = Arc::new(Shared {
+            scenario: self.clone(),
+            stop: AtomicBool::new(false),
+            done: AtomicU32::new(0),
+            operations: AtomicU32::new(0),
+            busy_since: (0..self.tasks).map(|_| AtomicU32::new(0)).collect(),
+            busy_op: (0..self.tasks).map(|_| AtomicU8::new(0)).collect(),
+            queue: Queue::new(os, self.queue_size)?,
+            sent: (0..self.tasks).map(|_| Tally::new()).collect(),
+            received: (0..self.tasks).map(|_| Tally::new()).collect(),
+            corrupted: AtomicU32::new(0),
+            first_corrupted: AtomicU32::new(0),
+            mutex: Mutex::new(os, 0)?,
+            inside: AtomicU32::new(0),
+            overlaps: AtomicU32::new(0),
+            locks: AtomicU32::new(0),
+            semaphore: CountingSemaphore::new(os, self.permits, self.permits)?,
+            held: AtomicU32::new(0),
+            overdrawn: AtomicU32::new(0),
+            notified: AtomicU32::new(0),
+            timer,
+            starts: AtomicU32::new(0),
+        });
+
+        for index in 0..self.tasks {
+            let shared = shared.clone();
+            let sink = unsafe { TaskRemoteHandle::from_raw(sink.raw_handle()) };
+            os.new_task("stress", self.stack_size, self.priority, move |task, os| {
+                {
+                    let shared = shared;
+                    shared.work(index, &sink, os);
+                    shared.done.fetch_add(1, Ordering::SeqCst);
+                }
+                unsafe { task.delete() }
+            })?;
+        }
+
+        let mut failures = Vec::new();
+        let mut stalled = vec![false; self.tasks];
+        let interval = Duration::ticks((self.watchdog / 4).max(1));
+        let start = os.get_tick_count();
+        while tick_elapsed(os.get_tick_count(), start) < self.duration {
+            os.delay(interval);
+            shared.check_stalls(&mut stalled, &mut failures);
+        }
+
+        shared.stop.store(true, Ordering::SeqCst);
+        let stopped = os.get_tick_count();
+        while shared.done.load(Ordering::SeqCst) < self.tasks as u32 {
+            shared.check_stalls(&mut stalled, &mut failures);
+            if tick_elapsed(os.get_tick_count(), stopped) > 2 * self.watchdog {
+                // Without all workers out, nothing else is settled enough to check.
+                return Ok(self.report(&shared, failures));
+            }
+            os.delay(interval);
+        }
+
+        sink_stop.store(true, Ordering::SeqCst);
+        while !sink_done.load(Ordering::SeqCst) {
+            os.delay(Duration::ticks(1));
+        }
+        let _ = shared.timer.stop(Duration::infinite());
+        // Let a timer that expired before the stop fire.
+        os.delay(Duration::ticks(2));
+
+        while let Ok(item) = shared.queue.receive(Duration::zero()) {
+            shared.receive_item(item);
+        }
+        shared.check_end(&taken, &fires, &mut failures);
+        Ok(self.report(&shared, failures))
+    }
+
+    fn report(&self, shared: &Shared, failures: Vec<StressFailure>) -> StressReport {
+        StressReport {
+            seed: self.seed,
+            operations: shared.operations.load(Ordering::SeqCst),
+            failures,
+        }
+    }
+}
+
+/// An item of the stress queue.
+#[derive(Debug, Copy, Clone)]
+struct Item {
+    sender: u32,
+    seq: u32,
+    check: u32,
+}
+
+fn checksum(sender: u32, seq: u32) -> u32 {
+    (sender.wrapping_mul(0x9e37_79b9) ^ seq).wrapping_mul(0x85eb_ca6b) ^ 0xa5a5_a5a5
+}
+
+/// The count and the sum of the sequence numbers of the items of one sender.
+struct Tally {
+    count: AtomicU32,
+    sum: AtomicU32,
+}
+
+impl Tally {
+    fn new() -> Tally {
+        Tally {
+            count: AtomicU32::new(0),
+            sum: AtomicU32::new(0),
+        }
+    }
+
+    fn record(&self, seq: u32) {
+        self.count.fetch_add(1, Ordering::SeqCst);
+        // Wraps around, like on both sides.
+        self.sum.fetch_add(seq, Ordering::SeqCst);
+    }
+}
+
+/// A xorshift32 generator, seeded per worker.
+struct Rng(u32);
+
+impl Rng {
+    fn new(seed: u32, worker: usize) -> Rng {
+        let state = (seed ^ (worker as u32).wrapping_mul(0x9e37_79b9)).wrapping_mul(0x85eb_ca6b);
+        Rng(if state == 0 { 0x1234_5678 } else { state })
+    }
+
+    fn next(&mut self) -> u32 {
+        self.0 ^= self.0 << 13;
+        self.0 ^= self.0 >> 17;
+        self.0 ^= self.0 << 5;
+        self.0
+    }
+}
+
+/// What the workers and the task running the scenario share.
+struct Shared {
+    scenario: StressScenario,
+    stop: AtomicBool,
+    done: AtomicU32,
+    operations: AtomicU32,
+    /// The tick count at which each worker started its operation, plus one, or 0 between
+    /// operations.
+    busy_since: Vec<AtomicU32>,
+    busy_op: Vec<AtomicU8>,
+
+    queue: Queue<Item>,
+    sent: Vec<Tally>,
+    received: Vec<Tally>,
+    corrupted: AtomicU32,
+    /// The sender and sequence number of the first corrupted item, 16 bits each.
+    first_corrupted: AtomicU32,
+
+    mutex: Mutex<u32>,
+    inside: AtomicU32,
+    overlaps: AtomicU32,
+    locks: AtomicU32,
+
+    semaphore: CountingSemaphore,
+    held: AtomicU32,
+    overdrawn: AtomicU32,
+
+    notified: AtomicU32,
+
+    timer: Timer,
+    starts: AtomicU32,
+}
+
+// Timer commands go through the timer queue, which any task may use.
+unsafe impl Send for Shared {}
+unsafe impl Sync for Shared {}
+
+impl Shared {
+    fn pick(&self, random: u32) -> Operation {
+        let weights = &self.scenario.weights;
+        let total: u32 = weights.iter().map(|&w| w as u32).sum();
+        let mut point = random % total;
+        for (index, &weight) in weights.iter().enumerate() {
+            if point < weight as u32 {
+                return Operation::from_index(index as u8);
+            }
+            point -= weight as u32;
+        }
+        unreachable!()
+    }
+
+    fn work(&self, index: usize, sink: &TaskRemoteHandle, os: FreeRTOS) {
+        let wait = Duration::ticks(self.scenario.max_wait);
+        let mut rng = Rng::new(self.scenario.seed, index);
+        let mut seq = 0;
+
+        while !self.stop.load(Ordering::SeqCst) {
+            let operation = self.pick(rng.next());
+            self.busy_op[index].store(operation as u8, Ordering::SeqCst);
+            self.busy_since[index].store(os.get_tick_count().wrapping_add(1), Ordering::SeqCst);
+
+            match operation {
+                Operation::Queue if rng.next() & 1 == 0 => {
+                    let item = Item {
+                        sender: index as u32,
+                        seq,
+                        check: checksum(index as u32, seq),
+                    };
+                    if self.queue.send(item, wait).is_ok() {
+                        self.sent[index].record(seq);
+                        seq = seq.wrapping_add(1);
+                    }
+                }
+                Operation::Queue => {
+                    if let Ok(item) = self.queue.receive(wait) {
+                        self.receive_item(item);
+                    }
+                }
+                Operation::Mutex => {
+                    if let Ok(mut counter) = self.mutex.lock(wait) {
+                        if self.inside.fetch_add(1, Ordering::SeqCst) != 0 {
+                            self.overlaps.fetch_add(1, Ordering::SeqCst);
+                        }
+                        *counter += 1;
+                        self.locks.fetch_add(1, Ordering::SeqCst);
+                        if rng.next() & 7 == 0 {
+                            os.delay(Duration::ticks(1));
+                        }
+                        self.inside.fetch_sub(1, Ordering::SeqCst);
+                    }
+                }
+                Operation::Semaphore => {
+                    if self.semaphore.take(wait).is_ok() {
+                        if self.held.fetch_add(1, Ordering::SeqCst) >= self.scenario.permits {
+                            self.overdrawn.fetch_add(1, Ordering::SeqCst);
+                        }
+                        if rng.next() & 7 == 0 {
+                            os.delay(Duration::ticks(1));
+                        }
+                        self.held.fetch_sub(1, Ordering::SeqCst);
+                        self.semaphore.give();
+                    }
+                }
+                Operation::Notify => {
+                    sink.notify(TaskNotification::Increment);
+                    self.notified.fetch_add(1, Ordering::SeqCst);
+                }
+                Operation::Timer => {
+                    let zero = Duration::zero();
+                    let started = match rng.next() % 4 {
+                        0 => self.timer.start(zero).is_ok(),
+                        1 => self.timer.reset(zero).is_ok(),
+                        2 => {
+                            let _ = self.timer.stop(zero);
+                            false
+                        }
+                        _ => {
+                            let period = Duration::ticks(1 + rng.next() % 4);
+                            self.timer.change_period(zero, period).is_ok()
+                        }
+                    };
+                    if started {
+                        self.starts.fetch_add(1, Ordering::SeqCst);
+                    }
+                }
+            }
+
+            self.busy_since[index].store(0, Ordering::SeqCst);
+            self.operations.fetch_add(1, Ordering::SeqCst);
+            if rng.next() & 3 == 0 {
+                os.delay(Duration::ticks(rng.next() % 2));
+            }
+        }
+    }
+
+    fn receive_item(&self, item: Item) {
+        if (item.sender as usize) < self.received.len()
+            && item.check == checksum(item.sender, item.seq)
+        {
+            self.received[item.sender as usize].record(item.seq);
+            return;
+        }
+        if self.corrupted.fetch_add(1, Ordering::SeqCst) == 0 {
+            self.first_corrupted.store(
+                (item.sender & 0xffff) << 16 | (item.seq & 0xffff),
+                Ordering::SeqCst,
+            );
+        }
+    }
+
+    fn check_stalls(&self, stalled: &mut [bool], failures: &mut Vec<StressFailure>) {
+        let now = unsafe { freertos_rs_xTaskGetTickCount() };
+        for (worker, since) in self.busy_since.iter().enumerate() {
+            let since = since.load(Ordering::SeqCst);
+            if since == 0 || stalled[worker] {
+                continue;
+            }
+            let ticks = tick_elapsed(now, since.wrapping_sub(1));
+            if ticks > self.scenario.watchdog {
+                stalled[worker] = true;
+                failures.push(StressFailure::Stalled {
+                    worker,
+                    operation: Operation::from_index(self.busy_op[worker].load(Ordering::SeqCst)),
+                    ticks,
+                });
+            }
+        }
+    }
+
+    fn check_end(&self, taken: &AtomicU32, fires: &AtomicU32, failures: &mut Vec<StressFailure>) {
+        let corrupted = self.corrupted.load(Ordering::SeqCst);
+        if corrupted != 0 {
+            let first = self.first_corrupted.load(Ordering::SeqCst);
+            failures.push(StressFailure::QueueItemCorrupted {
+                sender: first >> 16,
+                seq: first & 0xffff,
+                count: corrupted,
+            });
+        }
+        for (sender, (sent, received)) in self.sent.iter().zip(&self.received).enumerate() {
+            let sent_count = sent.count.load(Ordering::SeqCst);
+            let received_count = received.count.load(Ordering::SeqCst);
+            if sent_count != received_count
+                || sent.sum.load(Ordering::SeqCst) != received.sum.load(Ordering::SeqCst)
+            {
+                failures.push(StressFailure::QueueItemsLost {
+                    sender: sender as u32,
+                    sent: sent_count,
+                    received: received_count,
+                });
+            }
+        }
+
+        let overlaps = self.overlaps.load(Ordering::SeqCst);
+        if overlaps != 0 {
+            failures.push(StressFailure::MutualExclusion { overlaps });
+        }
+        let locks = self.locks.load(Ordering::SeqCst);
+        match self.mutex.lock(Duration::zero()) {
+            Ok(counter) if *counter == locks => {}
+            counter => failures.push(StressFailure::MutexCount {
+                locks,
+                counter: counter.map_or(u32::MAX, |c| *c),
+            }),
+        }
+
+        let overdrawn = self.overdrawn.load(Ordering::SeqCst);
+        if overdrawn != 0 {
+            failures.push(StressFailure::PermitsOverdrawn { times: overdrawn });
+        }
+        let count = self.semaphore.get_count();
+        if count != self.scenario.permits {
+            failures.push(StressFailure::PermitLeak {
+                permits: self.scenario.permits,
+                count,
+            });
+        }
+
+        let (sent, taken) = (
+            self.notified.load(Ordering::SeqCst),
+            taken.load(Ordering::SeqCst),
+        );
+        if sent != taken {
+            failures.push(StressFailure::NotificationsLost { sent, taken });
+        }
+
+        let (starts, fires) = (
+            self.starts.load(Ordering::SeqCst),
+            fires.load(Ordering::SeqCst),
+        );
+        if fires > starts {
+            failures.push(StressFailure::TimerOverfired { starts, fires });
+        }
+    }
+}
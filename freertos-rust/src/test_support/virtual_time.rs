@@ -0,0 +1,127 @@
+//! Virtual time for the hosted port.
+//!
+//! In virtual time the idle task doesn't wait for the tick when every task is blocked on
+//! time: it steps the tick count to the earliest unblock time, or to the next ISR
+//! scheduled with `schedule_isr`, so delays and timeouts pass at CPU speed and tasks
+//! still unblock in the order of their timeouts. While tasks run, the host timer keeps
+//! ticking, unless virtual time is deterministic.
+//!
+//! The glue is in `src/freertos/ports/linux/virtual_time.c`, which lists what
+//! `FreeRTOSConfig.h` needs, as in the linux example. The idle, tick and daemon startup
+//! hooks of the shim call into it. Setting `FREERTOS_VIRTUAL_TIME=1` in the environment enables virtual time
+//! without changing the application, `FREERTOS_VIRTUAL_TIME=deterministic` enables it
+//! deterministic.
+
+use crate::base::*;
+use crate::critical::*;
+use crate::isr::*;
+use crate::prelude::v1::*;
+use crate::shim::*;
+use crate::ticks::*;
+use crate::units::*;
+
+/// How many ISRs can be scheduled at once.
+pub const MAX_SCHEDULED_ISRS: usize = 8;
+
+#[derive(Clone, Copy)]
+struct ScheduledIsr {
+    at: FreeRtosTickType,
+    isr: fn(&mut InterruptContext),
+}
+
+// Written by tasks in a critical region, and by the tick hook, which doesn't run
+// while a task is in one.
+static mut SCHEDULED: [Option<ScheduledIsr>; MAX_SCHEDULED_ISRS] = [None; MAX_SCHEDULED_ISRS];
+
+/// Enable virtual time. Call it before the scheduler starts.
+pub fn enable_virtual_time() {
+    unsafe { freertos_rs_virtual_time_enable(0) }
+}
+
+/// Enable virtual time, and stop the host timer once the scheduler started. The tick
+/// then only advances while the idle task runs, so each run unblocks the tasks in the
+/// same order however loaded the host is. A task that never blocks stops the clock,
+/// and tasks of the same priority only take turns when they block or yield.
+///
+/// Call it before the scheduler starts.
+pub fn enable_deterministic_virtual_time() {
+    unsafe { freertos_rs_virtual_time_enable(1) }
+}
+
+/// Whether virtual time is enabled, by the application or by the environment.
+pub fn is_virtual_time() -> bool {
+    unsafe { freertos_rs_virtual_time_enabled() != 0 }
+}
+
+/// The time since the scheduler started. In virtual time this includes the stretches
+/// the idle task stepped over.
+pub fn virtual_now() -> Duration {
+    Duration::ticks(unsafe { freertos_rs_xTaskGetTickCount() })
+}
+
+/// Run `isr` from the tick interrupt `after` from now, like an interrupt of a
+/// peripheral. Returns the tick it runs at.
+///
+/// Virtual time doesn't step past a scheduled ISR, so it runs at that tick unless the
+/// scheduler was suspended over it. Then it runs on the first tick after.
+pub fn schedule_isr<D: DurationTicks>(
+    after: D,
+    isr: fn(&mut InterruptContext),
+) -> Result<FreeRtosTickType, FreeRtosError> {
+    let _critical = CriticalRegion::enter();
+
+    let now = unsafe { freertos_rs_xTaskGetTickCount() };
+    let at = tick_add(now, after.to_ticks());
+    let scheduled = unsafe { &mut *core::ptr::addr_of_mut!(SCHEDULED) };
+    let slot = scheduled
+        .iter_mut()
+        .find(|s| s.is_none())
+        .ok_or(FreeRtosError::OutOfMemory)?;
+    *slot = Some(ScheduledIsr { at, isr });
+
+    unsafe { freertos_rs_virtual_time_wake_at(earliest(scheduled, now)) }
+
+    Ok(at)
+}
+
+fn earliest(scheduled: &[Option<ScheduledIsr>], now: FreeRtosTickType) -> FreeRtosTickType {
+    scheduled
+        .iter()
+        .flatten()
+        .map(|s| s.at)
+        .min_by_key(|&at| tick_remaining(now, at))
+        .unwrap_or_else(|| unsafe { freertos_rs_max_wait() })
+}
+
+/// Called by the daemon startup hook, reads the environment and stops the host timer
+/// in deterministic virtual time.
+pub(crate) fn virtual_time_start_hook() {
+    unsafe { vPortVirtualTimeStart() }
+}
+
+/// Called by the idle hook, raises the tick after a stretch the idle task stepped over.
+pub(crate) fn virtual_time_idle_hook() {
+    unsafe { vPortVirtualTimeIdle() }
+}
+
+/// Called by the tick hook, runs the scheduled ISRs that are due.
+pub(crate) fn virtual_time_tick_hook() {
+    let now = unsafe { freertos_rs_xTaskGetTickCountFromISR() };
+    let scheduled = unsafe { &mut *core::ptr::addr_of_mut!(SCHEDULED) };
+
+    for slot in scheduled.iter_mut() {
+        if let Some(s) = *slot {
+            if tick_deadline_reached(now, s.at) {
+                *slot = None;
+
+                let mut context = InterruptContext::new(unsafe { KernelIsr::claim() });
+                (s.isr)(&mut context);
+                // The tick interrupt switches to a woken task by itself, a yield from
+                // inside it would wait for the tick to finish.
+                mem::forget(context);
+            }
+        }
+    }
+
+    unsafe { freertos_rs_virtual_time_wake_at(earliest(scheduled, now)) }
+}
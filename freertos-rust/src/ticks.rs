@@ -0,0 +1,157 @@
+//! Wrap-safe arithmetic on raw tick counts.
+//!
+//! The tick counter wraps around, after about 50 days with 32-bit ticks at 1 kHz and
+//! after a minute with 16-bit ticks, so `now - earlier` and `now >= deadline` on raw
+//! values go wrong eventually. These helpers work across one wraparound, as long as
+//! the distances involved stay below half the counter range.
+//!
+//! `FreeRtosTickType` is 32 bits, matching `configUSE_16_BIT_TICKS` 0, and the helpers
+//! without a suffix use it. The `_u16` and `_u32` variants are for tick values of a
+//! known width, like ones received from another node.
+//!
+//! In debug builds, distances in the top quarter of the range panic: they are almost
+//! always a small negative distance, from swapped arguments or an `earlier` value that
+//! was read after `now`.
+//!
+//! `TickInstant` wraps a tick count taken from the scheduler, for code that measures time
+//! between two points with `Duration`s rather than raw ticks.
+
+use crate::base::*;
+use crate::operating_system::*;
+use crate::units::*;
+
+macro_rules! tick_helpers {
+    ($t:ty, $signed:ty, $elapsed:ident, $reached:ident, $add:ident, $remaining:ident) => {
+        /// Ticks from `earlier` to `now`.
+        #[must_use]
+        #[inline]
+        #[track_caller]
+        pub fn $elapsed(now: $t, earlier: $t) -> $t {
+            let elapsed = now.wrapping_sub(earlier);
+            debug_assert!(
+                elapsed <= <$t>::MAX - <$t>::MAX / 4,
+                "{} ticks from {} to {}, is `earlier` after `now`?",
+                elapsed,
+                earlier,
+                now
+            );
+            elapsed
+        }
+
+        /// Whether `deadline` is now or in the past. Deadlines more than half the counter
+        /// range ahead read as reached.
+        #[must_use]
+        #[inline]
+        pub fn $reached(now: $t, deadline: $t) -> bool {
+            now.wrapping_sub(deadline) as $signed >= 0
+        }
+
+        /// The tick `delta` ticks after `base`.
+        #[must_use]
+        #[inline]
+        #[track_caller]
+        pub fn $add(base: $t, delta: $t) -> $t {
+            debug_assert!(
+                delta <= <$t>::MAX / 2,
+                "a deadline {} ticks ahead can't be told from one in the past",
+                delta
+            );
+            base.wrapping_add(delta)
+        }
+
+        /// Ticks left until `deadline`, 0 once it is reached.
+        #[must_use]
+        #[inline]
+        pub fn $remaining(now: $t, deadline: $t) -> $t {
+            if $reached(now, deadline) {
+                0
+            } else {
+                deadline.wrapping_sub(now)
+            }
+        }
+    };
+}
+
+tick_helpers!(
+    u16,
+    i16,
+    tick_elapsed_u16,
+    tick_deadline_reached_u16,
+    tick_add_u16,
+    tick_remaining_u16
+);
+tick_helpers!(
+    u32,
+    i32,
+    tick_elapsed_u32,
+    tick_deadline_reached_u32,
+    tick_add_u32,
+    tick_remaining_u32
+);
+
+/// Ticks from `earlier` to `now`.
+#[must_use]
+#[inline]
+#[track_caller]
+pub fn tick_elapsed(now: FreeRtosTickType, earlier: FreeRtosTickType) -> FreeRtosTickType {
+    tick_elapsed_u32(now, earlier)
+}
+
+/// Whether `deadline` is now or in the past.
+#[must_use]
+#[inline]
+pub fn tick_deadline_reached(now: FreeRtosTickType, deadline: FreeRtosTickType) -> bool {
+    tick_deadline_reached_u32(now, deadline)
+}
+
+/// The tick `delta` ticks after `base`.
+#[must_use]
+#[inline]
+#[track_caller]
+pub fn tick_add(base: FreeRtosTickType, delta: FreeRtosTickType) -> FreeRtosTickType {
+    tick_add_u32(base, delta)
+}
+
+/// Ticks left until `deadline`, 0 once it is reached.
+#[must_use]
+#[inline]
+pub fn tick_remaining(now: FreeRtosTickType, deadline: FreeRtosTickType) -> FreeRtosTickType {
+    tick_remaining_u32(now, deadline)
+}
+
+/// A point in time, as the tick count at that point, from `FreeRTOS::now`.
+///
+/// Differences are computed with wrapping arithmetic, so they are right across one
+/// wraparound of the tick count, for instants less than half the counter range apart.
+#[derive(Copy, Clone, Debug, PartialEq, Eq, Hash)]
+pub struct TickInstant(pub(crate) FreeRtosTickType);
+
+impl TickInstant {
+    /// The instant at tick count `ticks`.
+    #[inline]
+    pub fn from_ticks(ticks: FreeRtosTickType) -> TickInstant {
+        TickInstant(ticks)
+    }
+
+    /// The tick count at this instant.
+    #[inline]
+    pub fn ticks(&self) -> FreeRtosTickType {
+        self.0
+    }
+
+    /// The time since this instant.
+    #[inline]
+    pub fn elapsed(&self, os: &FreeRTOS) -> Duration {
+        os.now().saturating_duration_since(*self)
+    }
+
+    /// The time from `earlier` to this instant, zero if `earlier` is actually later.
+    #[inline]
+    pub fn saturating_duration_since(&self, earlier: TickInstant) -> Duration {
+        if tick_deadline_reached(self.0, earlier.0) {
+            Duration::ticks(self.0.wrapping_sub(earlier.0))
+        } else {
+            Duration::zero()
+        }
+    }
+}
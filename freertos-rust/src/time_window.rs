@@ -0,0 +1,309 @@
+use crate::base::*;
+use crate::infra::*;
+use crate::isr::*;
+use crate::mutex::*;
+use crate::operating_system::*;
+use crate::prelude::v1::*;
+use crate::shim::*;
+use crate::sync::atomic::{AtomicBool, AtomicU32, Ordering};
+use crate::task::*;
+use crate::units::*;
+
+// The enforcer's handle is only used when the group is dropped.
+unsafe impl Sync for WindowGroup {}
+
+impl !ISRSafe for WindowGroup {}
+
+/// Stack of the task enforcing a `WindowPolicy::Enforced` group, in words.
+const ENFORCER_STACK_SIZE: usize = 256;
+
+/// Windows of `duration` ticks repeating every `period` ticks, the first one starting at
+/// tick `offset`.
+///
+/// The windows are aligned to the tick counter when a `WindowGroup` is created, and
+/// after that every window starts one `period` after the one before, so the schedule
+/// carries on unchanged across a tick counter wrap, even when `period` doesn't divide
+/// the counter range.
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub struct TimeWindow {
+    offset: FreeRtosTickType,
+    duration: FreeRtosTickType,
+    period: FreeRtosTickType,
+}
+
+/// Where a tick is relative to the windows of a `TimeWindow`.
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum WindowPhase {
+    /// In a window, which closes in this many ticks.
+    Open { closes_in: FreeRtosTickType },
+    /// Between windows, the next one opens in this many ticks.
+    Closed { opens_in: FreeRtosTickType },
+}
+
+impl TimeWindow {
+    /// Panics if `period` is zero ticks or `duration` is longer than `period`.
+    pub fn new(offset: Duration, duration: Duration, period: Duration) -> TimeWindow {
+        let window = TimeWindow {
+            offset: offset.to_ticks(),
+            duration: duration.to_ticks(),
+            period: period.to_ticks(),
+        };
+        assert!(window.period > 0, "TimeWindow period of zero ticks");
+        assert!(
+            window.duration <= window.period,
+            "TimeWindow duration longer than its period"
+        );
+        window
+    }
+
+    pub fn duration(&self) -> Duration {
+        Duration::ticks(self.duration)
+    }
+
+    pub fn period(&self) -> Duration {
+        Duration::ticks(self.period)
+    }
+
+    /// The tick at which the latest window up to `now` started, counting windows from
+    /// tick `offset` on. Before `offset`, that is a tick before it, which wraps around
+    /// below tick 0.
+    pub fn first_start(&self, now: FreeRtosTickType) -> FreeRtosTickType {
+        let since = now as i64 - self.offset as i64;
+        let windows = since.div_euclid(self.period as i64);
+        (self.offset as i64 + windows * self.period as i64) as FreeRtosTickType
+    }
+
+    /// The phase at `now` of the windows that started at `start`. `start` has to be a tick
+    /// at which a window started, less than half the tick counter range before `now`.
+    pub fn phase(&self, start: FreeRtosTickType, now: FreeRtosTickType) -> WindowPhase {
+        let phase = now.wrapping_sub(start) % self.period;
+        if phase < self.duration {
+            WindowPhase::Open {
+                closes_in: self.duration - phase,
+            }
+        } else {
+            WindowPhase::Closed {
+                opens_in: self.period - phase,
+            }
+        }
+    }
+}
+
+/// What happens to the tasks of a `WindowGroup` outside of their windows.
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum WindowPolicy {
+    /// Nothing, the tasks check `WindowGroup::in_window` or wait with
+    /// `WindowGroup::wait_for_window` themselves.
+    Cooperative,
+    /// The tasks are suspended when their window closes and resumed when it opens.
+    Enforced,
+}
+
+#[derive(Copy, Clone, PartialEq, Eq)]
+struct WindowMember(FreeRtosTaskHandle);
+
+unsafe impl Send for WindowMember {}
+unsafe impl Sync for WindowMember {}
+
+struct WindowState {
+    window: TimeWindow,
+    policy: WindowPolicy,
+    /// A tick at which a window started, moved forward by whole periods as time goes by.
+    start: AtomicU32,
+    /// Whether the enforcer last opened or closed the window.
+    open: AtomicBool,
+    members: Mutex<Vec<WindowMember>>,
+}
+
+impl WindowState {
+    fn phase(&self, now: FreeRtosTickType) -> WindowPhase {
+        let mut start = self.start.load(Ordering::Relaxed);
+        let elapsed = now.wrapping_sub(start);
+        if elapsed >= self.window.period {
+            start = start.wrapping_add(elapsed - elapsed % self.window.period);
+            self.start.store(start, Ordering::Relaxed);
+        }
+        self.window.phase(start, now)
+    }
+
+    /// Suspend or resume every member when the window closes or opens.
+    fn apply(&self, open: bool) {
+        if let Ok(members) = self.members.lock(Duration::infinite()) {
+            if self.open.swap(open, Ordering::Relaxed) == open {
+                return;
+            }
+            for member in members.iter() {
+                unsafe {
+                    if open {
+                        freertos_rs_task_resume(member.0);
+                    } else {
+                        freertos_rs_task_suspend(member.0);
+                    }
+                }
+            }
+        }
+    }
+}
+
+/// A group of tasks that may only run in the windows of a `TimeWindow`, like noisy
+/// housekeeping tasks that have to be silent while a measurement runs.
+///
+/// With `WindowPolicy::Enforced`, an infrastructure task at the highest priority,
+/// `configMAX_PRIORITIES - 1`, wakes up at every window boundary to suspend the members
+/// when their window closes and resume them when it opens. Members have to be below that
+/// priority. Then:
+///
+/// * Suspension takes effect within the tick the window closes in. A member in the middle
+///   of a critical section or with the scheduler suspended stops when it leaves it.
+/// * A member blocked on a queue, semaphore, mutex or notification keeps waiting once it
+///   is resumed, for what is left of its timeout. What arrives while it is suspended is
+///   only received when its window opens, and a timeout that expired in between is
+///   reported then.
+/// * A `delay` of a member ends when it is resumed at the latest, so a member waiting
+///   across a closed window wakes up at the start of the next window.
+/// * Members suspended or resumed by others are suspended and resumed again at the next
+///   boundary.
+///
+/// With `WindowPolicy::Cooperative` nothing is suspended, and members check
+/// `in_window` or wait with `wait_for_window`. The window schedule is kept up to date by
+/// these calls, so a cooperative group has to be used at least once per tick counter
+/// wrap.
+///
+/// Tasks have to be removed from the group before they are deleted.
+pub struct WindowGroup {
+    state: Arc<WindowState>,
+    enforcer: Option<InfraTask>,
+}
+
+impl WindowGroup {
+    pub fn new(
+        os: FreeRTOS,
+        window: TimeWindow,
+        policy: WindowPolicy,
+    ) -> Result<WindowGroup, FreeRtosError> {
+        let now = os.get_tick_count();
+        let state = Arc::new(WindowState {
+            window,
+            policy,
+            start: AtomicU32::new(window.first_start(now)),
+            open: AtomicBool::new(true),
+            members: Mutex::new(os, Vec::new())?,
+        });
+
+        let enforcer = match policy {
+            WindowPolicy::Cooperative => None,
+            WindowPolicy::Enforced => {
+                let state = state.clone();
+                let priority = TaskPriority((os.get_max_priorities().max(1) - 1) as u8);
+
+                Some(InfraTask::spawn(
+                    os,
+                    "window",
+                    ENFORCER_STACK_SIZE,
+                    priority,
+                    move |ctx, os| {
+                        while !ctx.should_stop() {
+                            let wait = match state.phase(os.get_tick_count()) {
+                                WindowPhase::Open { closes_in } => {
+                                    state.apply(true);
+                                    closes_in
+                                }
+                                WindowPhase::Closed { opens_in } => {
+                                    state.apply(false);
+                                    opens_in
+                                }
+                            };
+                            ctx.sleep(Duration::ticks(wait));
+                        }
+                        state.apply(true);
+                    },
+                )?)
+            }
+        };
+
+        Ok(WindowGroup { state, enforcer })
+    }
+
+    pub fn window(&self) -> TimeWindow {
+        self.state.window
+    }
+
+    pub fn policy(&self) -> WindowPolicy {
+        self.state.policy
+    }
+
+    /// Add a task to the group. In an enforced group, it is suspended right away if its
+    /// window is closed, unless it is the calling task, which keeps running until the
+    /// window closes the next time.
+    pub fn add<T: TaskHandle>(&self, task: &T) -> Result<(), FreeRtosError> {
+        let member = WindowMember(task.raw_handle());
+        let mut members = self.state.members.lock(Duration::infinite())?;
+        if members.contains(&member) {
+            return Ok(());
+        }
+        members.push(member);
+
+        let closed = self.enforcer.is_some() && !self.state.open.load(Ordering::Relaxed);
+        if closed && member.0 != unsafe { freertos_rs_get_current_task() } {
+            unsafe { freertos_rs_task_suspend(member.0) };
+        }
+        Ok(())
+    }
+
+    /// Remove a task from the group, resuming it if the group suspended it.
+    pub fn remove<T: TaskHandle>(&self, task: &T) -> Result<(), FreeRtosError> {
+        let member = WindowMember(task.raw_handle());
+        let mut members = self.state.members.lock(Duration::infinite())?;
+        let index = match members.iter().position(|m| *m == member) {
+            Some(index) => index,
+            None => return Ok(()),
+        };
+        members.remove(index);
+
+        if self.enforcer.is_some() && !self.state.open.load(Ordering::Relaxed) {
+            unsafe { freertos_rs_task_resume(member.0) };
+        }
+        Ok(())
+    }
+
+    /// Whether a window of the group is open right now.
+    pub fn in_window(&self) -> bool {
+        let now = unsafe { freertos_rs_xTaskGetTickCount() };
+        match self.state.phase(now) {
+            WindowPhase::Open { .. } => true,
+            WindowPhase::Closed { .. } => false,
+        }
+    }
+
+    /// Return right away in a window, or wait for the next one to open. Fails with
+    /// `Timeout` if it doesn't open within `timeout`.
+    ///
+    /// The calling task wakes up in the tick the window opens, or the one after if
+    /// the tick counter moves on while this is called.
+    pub fn wait_for_window<D: DurationTicks>(&self, timeout: D) -> Result<(), FreeRtosError> {
+        let now = unsafe { freertos_rs_xTaskGetTickCount() };
+        let opens_in = match self.state.phase(now) {
+            WindowPhase::Open { .. } => return Ok(()),
+            WindowPhase::Closed { opens_in } => opens_in,
+        };
+
+        let timeout = timeout.to_ticks();
+        if opens_in > timeout {
+            FreeRTOS {}.delay(Duration::ticks(timeout));
+            return Err(FreeRtosError::Timeout);
+        }
+        FreeRTOS {}.delay(Duration::ticks(opens_in));
+        Ok(())
+    }
+}
+
+impl Drop for WindowGroup {
+    /// Stops the enforcer and resumes the members it suspended.
+    fn drop(&mut self) {
+        if let Some(enforcer) = self.enforcer.take() {
+            // A clean stop resumes them already, but not a forced one.
+            enforcer.stop(&FreeRTOS {}, Duration::ms(100));
+            self.state.apply(true);
+        }
+    }
+}
@@ -0,0 +1,261 @@
+use crate::base::*;
+use crate::isr::*;
+use crate::operating_system::*;
+use crate::prelude::v1::*;
+use crate::queue::*;
+use crate::sync::atomic::{AtomicBool, AtomicU32, Ordering};
+use crate::task::*;
+use crate::ticks::*;
+use crate::timers::*;
+use crate::units::*;
+use core::cell::UnsafeCell;
+
+impl !ISRSafe for TimerService {}
+
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub(crate) enum ServiceCommandKind {
+    /// Hand a new timer to the service. Carries the service's reference to it.
+    Add,
+    Start,
+    Stop,
+    Reset,
+    ChangePeriod,
+    Delete,
+    Shutdown,
+}
+
+#[derive(Debug, Copy, Clone)]
+pub(crate) struct ServiceCommand {
+    pub(crate) kind: ServiceCommandKind,
+    /// The address of the timer. Only used to find it in the service's list, never
+    /// dereferenced before that.
+    pub(crate) timer: usize,
+    pub(crate) period: FreeRtosTickType,
+}
+
+/// A timer run by a `TimerService`. The callback is only touched by the service task.
+pub(crate) struct ServiceTimer {
+    name: String,
+    auto_reload: bool,
+    callback: UnsafeCell<TimerClosure>,
+    period: AtomicU32,
+    expiry: AtomicU32,
+    active: AtomicBool,
+}
+
+unsafe impl Send for ServiceTimer {}
+unsafe impl Sync for ServiceTimer {}
+
+impl ServiceTimer {
+    pub(crate) fn name(&self) -> &str {
+        &self.name
+    }
+
+    pub(crate) fn period(&self) -> FreeRtosTickType {
+        self.period.load(Ordering::Relaxed)
+    }
+
+    pub(crate) fn expiry(&self) -> FreeRtosTickType {
+        self.expiry.load(Ordering::Relaxed)
+    }
+
+    pub(crate) fn is_active(&self) -> bool {
+        self.active.load(Ordering::Relaxed)
+    }
+
+    fn arm(&self, now: FreeRtosTickType) {
+        self.expiry
+            .store(tick_add(now, self.period()), Ordering::Relaxed);
+        self.active.store(true, Ordering::Relaxed);
+    }
+}
+
+/// The part of a service shared with its timers.
+pub(crate) struct ServiceShared {
+    pub(crate) commands: Queue<ServiceCommand>,
+}
+
+unsafe impl Send for ServiceShared {}
+unsafe impl Sync for ServiceShared {}
+
+impl ServiceShared {
+    pub(crate) fn send(
+        &self,
+        kind: ServiceCommandKind,
+        timer: *const ServiceTimer,
+        period: FreeRtosTickType,
+        block_time: FreeRtosTickType,
+    ) -> Result<(), FreeRtosError> {
+        self.commands
+            .send(
+                ServiceCommand {
+                    kind,
+                    timer: timer as usize,
+                    period,
+                },
+                Duration::ticks(block_time),
+            )
+            .map_err(|_| FreeRtosError::Timeout)
+    }
+
+    pub(crate) fn create_timer(
+        &self,
+        name: &str,
+        period: FreeRtosTickType,
+        auto_reload: bool,
+        callback: TimerClosure,
+    ) -> Result<Arc<ServiceTimer>, FreeRtosError> {
+        let timer = Arc::new(ServiceTimer {
+            name: name.into(),
+            auto_reload,
+            callback: UnsafeCell::new(callback),
+            period: AtomicU32::new(period.max(1)),
+            expiry: AtomicU32::new(0),
+            active: AtomicBool::new(false),
+        });
+
+        let reference = Arc::into_raw(timer.clone());
+        let block_time = Duration::ms(1000).to_ticks();
+        if let Err(e) = self.send(ServiceCommandKind::Add, reference, 0, block_time) {
+            unsafe { drop(Arc::from_raw(reference)) };
+            return Err(e);
+        }
+
+        Ok(timer)
+    }
+}
+
+/// A timer service task run by the crate, as an alternative to the kernel's timer
+/// daemon.
+///
+/// Timers created with `TimerBuilder::service` have their callbacks run by this task
+/// and their commands go through its own command queue, so a slow callback only delays
+/// the other timers of the same service. Critical timers can be given a service of
+/// their own at a high priority while third-party callbacks share a low priority one.
+///
+/// Timers keep the same API as kernel timers. Expiry times are counted from when the
+/// service processed a command rather than when it was sent.
+pub struct TimerService {
+    shared: Arc<ServiceShared>,
+    task: TaskRemoteHandle,
+}
+
+impl TimerService {
+    /// Spawn the service task. `command_queue_depth` is how many commands can wait
+    /// before senders have to block, like `configTIMER_QUEUE_LENGTH`.
+    pub fn new(
+        os: FreeRTOS,
+        name: &str,
+        stack_size: usize,
+        priority: impl Into<TaskPriority>,
+        command_queue_depth: usize,
+    ) -> Result<TimerService, FreeRtosError> {
+        let shared = Arc::new(ServiceShared {
+            commands: Queue::new(os.clone(), command_queue_depth)?,
+        });
+
+        let task = {
+            let shared = shared.clone();
+            os.new_task(name, stack_size, priority, move |this, os| {
+                run_service(&shared, &os);
+                drop(shared);
+                unsafe { this.delete() }
+            })?
+        };
+
+        Ok(TimerService { shared, task })
+    }
+
+    /// The task running the timer callbacks.
+    pub fn task(&self) -> &TaskRemoteHandle {
+        &self.task
+    }
+
+    /// How many commands are waiting to be processed.
+    pub fn pending_commands(&self) -> usize {
+        self.shared.commands.len()
+    }
+
+    pub(crate) fn shared(&self) -> &Arc<ServiceShared> {
+        &self.shared
+    }
+}
+
+impl Drop for TimerService {
+    /// Stop the service task once it processed the commands before this one. Timers of
+    /// the service stop firing, and commands sent to them afterwards time out.
+    fn drop(&mut self) {
+        let block_time = Duration::ms(1000).to_ticks();
+        let _ = self
+            .shared
+            .send(ServiceCommandKind::Shutdown, ptr::null(), 0, block_time);
+    }
+}
+
+fn run_service(shared: &ServiceShared, os: &FreeRTOS) {
+    let mut timers: Vec<Arc<ServiceTimer>> = Vec::new();
+
+    loop {
+        let now = os.get_tick_count();
+        for timer in timers.iter() {
+            if !timer.is_active() || !tick_deadline_reached(now, timer.expiry()) {
+                continue;
+            }
+
+            if timer.auto_reload {
+                // Count from the planned expiry so the period doesn't drift.
+                timer
+                    .expiry
+                    .store(tick_add(timer.expiry(), timer.period()), Ordering::Relaxed);
+            } else {
+                timer.active.store(false, Ordering::Relaxed);
+            }
+
+            let handle = TimerCallbackHandle::for_service(shared, timer);
+            unsafe { (*timer.callback.get())(&handle) };
+        }
+
+        let now = os.get_tick_count();
+        let wait = timers
+            .iter()
+            .filter(|t| t.is_active())
+            .map(|t| tick_remaining(now, t.expiry()))
+            .min()
+            .unwrap_or(Duration::infinite().to_ticks());
+
+        let command = match shared.commands.receive(Duration::ticks(wait)) {
+            Ok(command) => command,
+            Err(_) => continue,
+        };
+
+        let now = os.get_tick_count();
+        match command.kind {
+            ServiceCommandKind::Add => {
+                timers.push(unsafe { Arc::from_raw(command.timer as *const ServiceTimer) });
+            }
+            ServiceCommandKind::Shutdown => return,
+            ServiceCommandKind::Delete => {
+                timers.retain(|t| Arc::as_ptr(t) as usize != command.timer);
+            }
+            kind => {
+                let timer = match timers
+                    .iter()
+                    .find(|t| Arc::as_ptr(t) as usize == command.timer)
+                {
+                    Some(timer) => timer,
+                    None => continue,
+                };
+
+                match kind {
+                    ServiceCommandKind::Start | ServiceCommandKind::Reset => timer.arm(now),
+                    ServiceCommandKind::Stop => timer.active.store(false, Ordering::Relaxed),
+                    ServiceCommandKind::ChangePeriod => {
+                        timer.period.store(command.period.max(1), Ordering::Relaxed);
+                        timer.arm(now);
+                    }
+                    _ => {}
+                }
+            }
+        }
+    }
+}
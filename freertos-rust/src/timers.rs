@@ -2,19 +2,37 @@ use crate::base::*;
 use crate::isr::*;
 use crate::operating_system::*;
 use crate::prelude::v1::*;
+use crate::queue::*;
 use crate::shim::*;
+use crate::task::*;
+use crate::timer_service::*;
 use crate::units::*;
+use crate::utils::*;
 
 impl !ISRSafe for Timer {}
+impl !ISRSafe for TimerCallbackHandle {}
+impl !Send for TimerCallbackHandle {}
+impl !Sync for TimerCallbackHandle {}
+
+pub(crate) type TimerClosure = Box<dyn FnMut(&TimerCallbackHandle) + Send>;
+
+/// What the timer id points at until the timer is deleted.
+struct TimerData {
+    callback: TimerClosure,
+    /// The kernel doesn't copy timer names, so the nul terminated name lives here.
+    name: Box<[u8]>,
+}
 
 /// A FreeRTOS software timer.
 ///
 /// Note that all operations on a timer are processed by a FreeRTOS internal task
 /// that receives messages in a queue. Every operation has an associated waiting time
-/// for that queue to get unblocked.
+/// for that queue to get unblocked. Timers created for a `TimerService` use that
+/// service's task and queue instead.
 pub struct Timer {
     handle: FreeRtosTimerHandle,
     detached: bool,
+    service: Option<(Arc<ServiceShared>, Arc<ServiceTimer>)>,
 }
 
 /// Helper builder for a new software timer.
@@ -22,6 +40,7 @@ pub struct TimerBuilder<D: DurationTicks> {
     name: String,
     period: D,
     auto_reload: bool,
+    service: Option<Arc<ServiceShared>>,
 }
 
 impl<D: DurationTicks> TimerBuilder<D> {
@@ -31,10 +50,13 @@ impl<D: DurationTicks> TimerBuilder<D> {
             name: "timer".into(),
             period: period,
             auto_reload: true,
+            service: None,
         }
     }
 
-    /// Set the name of the timer.
+    /// Set the name of the timer. Creating the timer fails with `NameTooLong` if it is
+    /// longer than `max_name_len`, or with `InvalidName` if it has a nul byte, also on a
+    /// `TimerService`.
     pub fn set_name(&mut self, name: &str) -> &mut Self {
         self.name = name.into();
         self
@@ -52,68 +74,85 @@ impl<D: DurationTicks> TimerBuilder<D> {
         self
     }
 
+    /// Restart the timer every time it expires. This is the default.
+    pub fn auto_reload(&mut self, auto_reload: bool) -> &mut Self {
+        self.set_auto_reload(auto_reload)
+    }
+
+    /// Fire once per start, then become inactive until started again.
+    pub fn one_shot(&mut self) -> &mut Self {
+        self.set_auto_reload(false)
+    }
+
+    /// Run the timer on `service` instead of the kernel's timer daemon.
+    pub fn service(&mut self, service: &TimerService) -> &mut Self {
+        self.service = Some(service.shared().clone());
+        self
+    }
+
     /// Try to create the new timer.
     ///
     /// Note that the newly created timer must be started.
     pub fn create<F>(&self, callback: F) -> Result<Timer, FreeRtosError>
     where
-        F: Fn(Timer) -> (),
+        F: FnMut(&TimerCallbackHandle),
         F: Send + 'static,
     {
         Timer::spawn(
             self.name.as_str(),
             self.period.to_ticks(),
             self.auto_reload,
+            self.service.as_ref(),
             callback,
         )
     }
 }
 
 impl Timer {
-    unsafe fn spawn_inner<'a>(
+    /// `name` passed `check_name`, so the kernel keeps it whole.
+    unsafe fn spawn_inner(
         name: &str,
         period_ticks: FreeRtosTickType,
         auto_reload: bool,
-        callback: Box<dyn Fn(Timer) + Send + 'a>,
+        callback: TimerClosure,
     ) -> Result<Timer, FreeRtosError> {
-        let f = Box::new(callback);
-        let param_ptr = &*f as *const _ as *mut _;
+        let mut name_buf = Vec::with_capacity(name.len() + 1);
+        name_buf.extend_from_slice(name.as_bytes());
+        name_buf.push(0);
 
-        let (success, timer_handle) = {
-            let name = name.as_bytes();
-            let name_len = name.len();
-            let mut _timer_handle = mem::zeroed::<CVoid>();
+        // The timer id points at the closure and name until the timer is deleted.
+        let data = Box::into_raw(Box::new(TimerData {
+            callback,
+            name: name_buf.into_boxed_slice(),
+        }));
 
+        let (success, timer_handle) = {
             let ret = freertos_rs_timer_create(
-                name.as_ptr(),
-                name_len as u8,
+                (*data).name.as_ptr(),
                 period_ticks,
                 if auto_reload { 1 } else { 0 },
-                param_ptr,
+                data as FreeRtosVoidPtr,
                 timer_callback,
             );
 
             ((ret as usize) != 0, ret)
         };
 
-        if success {
-            mem::forget(f);
-        } else {
+        if !success {
+            drop(Box::from_raw(data));
             return Err(FreeRtosError::OutOfMemory);
         }
 
         extern "C" fn timer_callback(handle: FreeRtosTimerHandle) -> () {
             unsafe {
-                {
-                    let timer = Timer {
-                        handle: handle,
-                        detached: true,
-                    };
-                    if let Ok(callback_ptr) = timer.get_id() {
-                        let b = Box::from_raw(callback_ptr as *mut Box<dyn Fn(Timer)>);
-                        b(timer);
-                        Box::into_raw(b);
-                    }
+                let data = freertos_rs_timer_get_id(handle) as *mut TimerData;
+                if !data.is_null() {
+                    // Only the timer service task runs the callback, so this is the only
+                    // reference to the closure while it runs.
+                    ((*data).callback)(&TimerCallbackHandle {
+                        handle,
+                        service: ptr::null(),
+                    });
                 }
             }
         }
@@ -121,6 +160,7 @@ impl Timer {
         Ok(Timer {
             handle: timer_handle as *const _,
             detached: false,
+            service: None,
         })
     }
 
@@ -128,17 +168,54 @@ impl Timer {
         name: &str,
         period_tick: FreeRtosTickType,
         auto_reload: bool,
+        service: Option<&Arc<ServiceShared>>,
         callback: F,
     ) -> Result<Timer, FreeRtosError>
     where
-        F: Fn(Timer) -> (),
+        F: FnMut(&TimerCallbackHandle),
         F: Send + 'static,
     {
-        unsafe { Timer::spawn_inner(name, period_tick, auto_reload, Box::new(callback)) }
+        check_name(name)?;
+
+        #[cfg(feature = "footprint_diag")]
+        let footprint = crate::footprint::ClosureFootprint::of(&callback);
+
+        let timer = match service {
+            Some(service) => {
+                let timer =
+                    service.create_timer(name, period_tick, auto_reload, Box::new(callback))?;
+                Timer {
+                    handle: Arc::as_ptr(&timer) as FreeRtosTimerHandle,
+                    detached: false,
+                    service: Some((service.clone(), timer)),
+                }
+            }
+            None => unsafe {
+                Timer::spawn_inner(name, period_tick, auto_reload, Box::new(callback))?
+            },
+        };
+
+        #[cfg(feature = "footprint_diag")]
+        crate::footprint::record(
+            crate::footprint::ClosureOwner::Timer(timer.handle),
+            name,
+            footprint,
+        );
+
+        Ok(timer)
     }
 
     // Reset the timer's count.
     pub fn reset<D: DurationTicks>(&self, block_time: D) -> Result<(), FreeRtosError> {
+        if let Some((service, timer)) = &self.service {
+            return service.send(
+                ServiceCommandKind::Reset,
+                Arc::as_ptr(timer),
+                0,
+                block_time.to_ticks(),
+            );
+        }
+
         unsafe {
             if freertos_rs_timer_reset(self.handle, block_time.to_ticks()) == 0 {
                 Ok(())
@@ -150,6 +227,15 @@ impl Timer {
 
     /// Start the timer.
     pub fn start<D: DurationTicks>(&self, block_time: D) -> Result<(), FreeRtosError> {
+        if let Some((service, timer)) = &self.service {
+            return service.send(
+                ServiceCommandKind::Start,
+                Arc::as_ptr(timer),
+                0,
+                block_time.to_ticks(),
+            );
+        }
+
         unsafe {
             if freertos_rs_timer_start(self.handle, block_time.to_ticks()) == 0 {
                 Ok(())
@@ -161,6 +247,15 @@ impl Timer {
 
     /// Stop the timer.
     pub fn stop<D: DurationTicks>(&self, block_time: D) -> Result<(), FreeRtosError> {
+        if let Some((service, timer)) = &self.service {
+            return service.send(
+                ServiceCommandKind::Stop,
+                Arc::as_ptr(timer),
+                0,
+                block_time.to_ticks(),
+            );
+        }
+
         unsafe {
             if freertos_rs_timer_stop(self.handle, block_time.to_ticks()) == 0 {
                 Ok(())
@@ -176,6 +271,15 @@ impl Timer {
         block_time: D,
         new_period: D,
     ) -> Result<(), FreeRtosError> {
+        if let Some((service, timer)) = &self.service {
+            return service.send(
+                ServiceCommandKind::ChangePeriod,
+                Arc::as_ptr(timer),
+                new_period.to_ticks(),
+                block_time.to_ticks(),
+            );
+        }
+
         unsafe {
             if freertos_rs_timer_change_period(
                 self.handle,
@@ -190,6 +294,52 @@ impl Timer {
         }
     }
 
+    /// The tick count at which the timer will next expire.
+    pub fn get_expiry_time(&self) -> Duration {
+        if let Some((_, timer)) = &self.service {
+            return Duration::ticks(timer.expiry());
+        }
+
+        unsafe { Duration::ticks(freertos_rs_timer_get_expiry_time(self.handle)) }
+    }
+
+    /// The name of the timer. Kernel timers truncate it to `configMAX_TASK_NAME_LEN - 1`
+    /// bytes like a task name. Fails with `StringConversionError` if it isn't UTF-8.
+    pub fn get_name(&self) -> Result<String, FreeRtosError> {
+        if let Some((_, timer)) = &self.service {
+            return Ok(timer.name().into());
+        }
+
+        unsafe { str_from_c_string(freertos_rs_timer_get_name(self.handle)) }
+    }
+
+    /// The period the timer was created with or last changed to.
+    pub fn get_period(&self) -> Duration {
+        if let Some((_, timer)) = &self.service {
+            return Duration::ticks(timer.period());
+        }
+
+        unsafe { Duration::ticks(freertos_rs_timer_get_period(self.handle)) }
+    }
+
+    /// The timer service task, which runs all timer callbacks. Only valid once the
+    /// scheduler was started.
+    pub fn get_timer_daemon_task_handle() -> TaskRemoteHandle {
+        unsafe { TaskRemoteHandle::from_raw(freertos_rs_timer_get_daemon_task_handle()) }
+    }
+
+    /// Is the timer running? A one-shot timer stops being active once it has fired.
+    ///
+    /// Commands still waiting in the timer command queue aren't taken into account, so
+    /// right after `start` this can still be false.
+    pub fn is_active(&self) -> bool {
+        if let Some((_, timer)) = &self.service {
+            return timer.is_active();
+        }
+
+        unsafe { freertos_rs_timer_is_active(self.handle) != 0 }
+    }
+
     /// Detach this timer from Rust's memory management. The timer will still be active and
     /// will consume the memory.
     ///
@@ -198,42 +348,181 @@ impl Timer {
         self.detached = true;
     }
 
-    fn get_id(&self) -> Result<FreeRtosVoidPtr, FreeRtosError> {
-        unsafe { Ok(freertos_rs_timer_get_id(self.handle)) }
+    /// The kernel's handle of the timer. For a timer of a `TimerService` this only
+    /// identifies the timer and can't be passed to the kernel.
+    pub fn raw_handle(&self) -> FreeRtosTimerHandle {
+        self.handle
     }
 }
 
+extern "C" fn drop_timer_data(data: FreeRtosMutVoidPtr, _: u32) {
+    unsafe { drop(Box::from_raw(data as *mut TimerData)) }
+}
+
 impl Drop for Timer {
-    #[allow(unused_must_use)]
     fn drop(&mut self) {
         if self.detached == false {
+            if let Some((service, timer)) = &self.service {
+                // The service drops its reference, and with it the closure, once it
+                // processed the delete. If the queue stays full the timer keeps running.
+                let block_time = Duration::ms(1000).to_ticks();
+                let _ = service.send(
+                    ServiceCommandKind::Delete,
+                    Arc::as_ptr(timer),
+                    0,
+                    block_time,
+                );
+                return;
+            }
+
             unsafe {
-                if let Ok(callback_ptr) = self.get_id() {
-                    // free the memory
-                    Box::from_raw(callback_ptr as *mut Box<dyn Fn(Timer)>);
-                }
+                let data = freertos_rs_timer_get_id(self.handle) as FreeRtosMutVoidPtr;
+
                 // todo: configurable timeout?
-                freertos_rs_timer_delete(self.handle, Duration::ms(1000).to_ticks());
+                let block_time = Duration::ms(1000).to_ticks();
+                if freertos_rs_timer_delete(self.handle, block_time) != 0 {
+                    // The timer may still fire, so its closure and name have to stay.
+                    return;
+                }
+
+                // The timer service task frees the closure after it processed the delete,
+                // so a callback it is running right now can't lose its closure.
+                if freertos_rs_pend_function_call(drop_timer_data, data, 0, block_time) != 0 {
+                    drop_timer_data(data, 0);
+                }
             }
         }
     }
 }
 
+/// The timer that fired, passed to its callback.
+///
+/// Callbacks run in the timer service task, so these operations don't wait for room in
+/// the timer command queue and fail with `Timeout` if it is full.
+pub struct TimerCallbackHandle {
+    handle: FreeRtosTimerHandle,
+    /// The service running the callback, or null for the kernel's timer daemon.
+    service: *const ServiceShared,
+}
+
+impl TimerCallbackHandle {
+    pub(crate) fn for_service(service: &ServiceShared, timer: &ServiceTimer) -> Self {
+        TimerCallbackHandle {
+            handle: timer as *const ServiceTimer as FreeRtosTimerHandle,
+            service,
+        }
+    }
+
+    fn service_command(
+        &self,
+        kind: ServiceCommandKind,
+        period: FreeRtosTickType,
+    ) -> Option<Result<(), FreeRtosError>> {
+        if self.service.is_null() {
+            return None;
+        }
+
+        let timer = self.handle as *const ServiceTimer;
+        Some(unsafe { (*self.service).send(kind, timer, period, 0) })
+    }
+
+    /// Stop the timer.
+    pub fn stop(&self) -> Result<(), FreeRtosError> {
+        if let Some(result) = self.service_command(ServiceCommandKind::Stop, 0) {
+            return result;
+        }
+
+        unsafe {
+            if freertos_rs_timer_stop(self.handle, 0) == 0 {
+                Ok(())
+            } else {
+                Err(FreeRtosError::Timeout)
+            }
+        }
+    }
+
+    /// Reset the timer's count.
+    pub fn reset(&self) -> Result<(), FreeRtosError> {
+        if let Some(result) = self.service_command(ServiceCommandKind::Reset, 0) {
+            return result;
+        }
+
+        unsafe {
+            if freertos_rs_timer_reset(self.handle, 0) == 0 {
+                Ok(())
+            } else {
+                Err(FreeRtosError::Timeout)
+            }
+        }
+    }
+
+    /// Change the period of the timer. This also starts a stopped timer.
+    pub fn change_period<D: DurationTicks>(&self, new_period: D) -> Result<(), FreeRtosError> {
+        let new_period = new_period.to_ticks();
+        if let Some(result) = self.service_command(ServiceCommandKind::ChangePeriod, new_period) {
+            return result;
+        }
+
+        unsafe {
+            if freertos_rs_timer_change_period(self.handle, 0, new_period) == 0 {
+                Ok(())
+            } else {
+                Err(FreeRtosError::Timeout)
+            }
+        }
+    }
+
+    /// The handle of the timer, the same as `Timer::raw_handle`.
+    pub fn get_id(&self) -> FreeRtosTimerHandle {
+        self.handle
+    }
+}
+
 pub struct TimerISRHandle {
     handle: FreeRtosTimerHandle,
+    /// The command queue of the timer's `TimerService`, if it has one.
+    service: Option<QueueISRHandle<ServiceCommand>>,
 }
 
 impl ISRSafeHandle<TimerISRHandle> for Timer {
     unsafe fn new_isr_safe_handle(&self) -> TimerISRHandle {
         TimerISRHandle {
             handle: self.handle,
+            service: self
+                .service
+                .as_ref()
+                .map(|(service, _)| service.commands.new_isr_safe_handle()),
         }
     }
 }
 
 impl TimerISRHandle {
+    fn service_command(
+        &self,
+        context: &mut InterruptContext,
+        kind: ServiceCommandKind,
+        period: FreeRtosTickType,
+    ) -> Option<Result<(), FreeRtosError>> {
+        let commands = self.service.as_ref()?;
+        let command = ServiceCommand {
+            kind,
+            timer: self.handle as usize,
+            period,
+        };
+
+        Some(
+            commands
+                .send(context, command)
+                .map_err(|_| FreeRtosError::Timeout),
+        )
+    }
+
     // Reset the timer's count.
     pub fn reset(&self, context: &mut InterruptContext) -> Result<(), FreeRtosError> {
+        if let Some(result) = self.service_command(context, ServiceCommandKind::Reset, 0) {
+            return result;
+        }
+
         unsafe {
             if freertos_rs_timer_reset_isr(self.handle, context.get_task_field_mut()) == 0 {
                 Ok(())
@@ -245,6 +534,10 @@ impl TimerISRHandle {
 
     /// Start the timer.
     pub fn start(&self, context: &mut InterruptContext) -> Result<(), FreeRtosError> {
+        if let Some(result) = self.service_command(context, ServiceCommandKind::Start, 0) {
+            return result;
+        }
+
         unsafe {
             if freertos_rs_timer_start_isr(self.handle, context.get_task_field_mut()) == 0 {
                 Ok(())
@@ -256,6 +549,10 @@ impl TimerISRHandle {
 
     /// Stop the timer.
     pub fn stop(&self, context: &mut InterruptContext) -> Result<(), FreeRtosError> {
+        if let Some(result) = self.service_command(context, ServiceCommandKind::Stop, 0) {
+            return result;
+        }
+
         unsafe {
             if freertos_rs_timer_stop_isr(self.handle, context.get_task_field_mut()) == 0 {
                 Ok(())
@@ -271,10 +568,17 @@ impl TimerISRHandle {
         context: &mut InterruptContext,
         new_period: D,
     ) -> Result<(), FreeRtosError> {
+        let new_period = new_period.to_ticks();
+        if let Some(result) =
+            self.service_command(context, ServiceCommandKind::ChangePeriod, new_period)
+        {
+            return result;
+        }
+
         unsafe {
             if freertos_rs_timer_change_period_isr(
                 self.handle,
-                new_period.to_ticks(),
+                new_period,
                 context.get_task_field_mut(),
             ) == 0
             {
@@ -0,0 +1,32 @@
+use crate::prelude::v1::*;
+
+/// Where trace records go, see `set_trace_sink`.
+///
+/// A record is a source id picked by the application and a short payload. `record` is
+/// called from tasks and from ISRs, so it must not block.
+pub trait TraceSink: Sync {
+    fn record(&self, source: u16, payload: &[u8]);
+}
+
+static mut TRACE_SINK: Option<&'static dyn TraceSink> = None;
+
+/// Send trace records to `sink`.
+///
+/// # Safety
+///
+/// Must be called before anything traces, and not while anything does.
+pub unsafe fn set_trace_sink(sink: &'static dyn TraceSink) {
+    TRACE_SINK = Some(sink);
+}
+
+/// Pass a record to the trace sink. Returns false when no sink is set. Can be called
+/// from ISRs.
+pub fn trace_record(source: u16, payload: &[u8]) -> bool {
+    match unsafe { *ptr::addr_of!(TRACE_SINK) } {
+        Some(sink) => {
+            sink.record(source, payload);
+            true
+        }
+        None => false,
+    }
+}
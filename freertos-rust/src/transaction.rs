@@ -0,0 +1,230 @@
+use crate::base::*;
+use crate::isr::*;
+use crate::mutex::*;
+use crate::prelude::v1::*;
+use crate::units::*;
+
+impl<'a, E> !ISRSafe for Transaction<'a, E> {}
+
+/// Why a transaction was not committed.
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum TransactionError<E> {
+    /// One of the mutexes could not be taken in time. Nothing was prepared.
+    Lock(FreeRtosError),
+    /// The stage at `index` (in the order stages were added) failed to prepare.
+    /// Nothing was applied and every in-place stage before it was rolled back.
+    Stage { index: usize, error: E },
+}
+
+trait TransactionStage<E> {
+    fn mutex_address(&self) -> usize;
+    fn take(&self, max_wait: Duration) -> Result<(), FreeRtosError>;
+    fn give(&self);
+    /// Only called while the mutex is held.
+    unsafe fn prepare(&mut self) -> Result<(), E>;
+    unsafe fn apply(&mut self);
+    unsafe fn rollback(&mut self);
+}
+
+struct SwapStage<'a, T, M, F>
+where
+    M: MutexInnerImpl,
+{
+    mutex: &'a MutexImpl<T, M>,
+    prepare: Option<F>,
+    staged: Option<T>,
+}
+
+impl<'a, T, M, F, E> TransactionStage<E> for SwapStage<'a, T, M, F>
+where
+    M: MutexInnerImpl,
+    F: FnOnce(&T) -> Result<T, E>,
+{
+    fn mutex_address(&self) -> usize {
+        self.mutex as *const _ as usize
+    }
+
+    fn take(&self, max_wait: Duration) -> Result<(), FreeRtosError> {
+        self.mutex.inner().take(max_wait)
+    }
+
+    fn give(&self) {
+        self.mutex.inner().give()
+    }
+
+    unsafe fn prepare(&mut self) -> Result<(), E> {
+        if let Some(prepare) = self.prepare.take() {
+            self.staged = Some(prepare(&*self.mutex.data_ptr())?);
+        }
+        Ok(())
+    }
+
+    unsafe fn apply(&mut self) {
+        if let Some(staged) = self.staged.take() {
+            *self.mutex.data_ptr() = staged;
+        }
+    }
+
+    unsafe fn rollback(&mut self) {
+        self.staged = None;
+    }
+}
+
+struct InPlaceStage<'a, T, M, F, R, U>
+where
+    M: MutexInnerImpl,
+{
+    mutex: &'a MutexImpl<T, M>,
+    prepare: Option<F>,
+    rollback: Option<R>,
+    undo: Option<U>,
+}
+
+impl<'a, T, M, F, R, U, E> TransactionStage<E> for InPlaceStage<'a, T, M, F, R, U>
+where
+    M: MutexInnerImpl,
+    F: FnOnce(&mut T) -> Result<U, E>,
+    R: FnOnce(&mut T, U),
+{
+    fn mutex_address(&self) -> usize {
+        self.mutex as *const _ as usize
+    }
+
+    fn take(&self, max_wait: Duration) -> Result<(), FreeRtosError> {
+        self.mutex.inner().take(max_wait)
+    }
+
+    fn give(&self) {
+        self.mutex.inner().give()
+    }
+
+    unsafe fn prepare(&mut self) -> Result<(), E> {
+        if let Some(prepare) = self.prepare.take() {
+            self.undo = Some(prepare(&mut *self.mutex.data_ptr())?);
+        }
+        Ok(())
+    }
+
+    unsafe fn apply(&mut self) {
+        self.undo = None;
+    }
+
+    unsafe fn rollback(&mut self) {
+        if let (Some(rollback), Some(undo)) = (self.rollback.take(), self.undo.take()) {
+            rollback(&mut *self.mutex.data_ptr(), undo);
+        }
+    }
+}
+
+/// Update several mutex protected values so that either all of them change or none do.
+///
+/// All involved mutexes are taken up front, in address order, so two transactions over
+/// overlapping sets of mutexes can't deadlock each other. Stages are then prepared in the
+/// order they were added. Only when every stage succeeded are the staged values applied,
+/// all before any mutex is released, so other tasks see either all old or all new values.
+///
+/// ```ignore
+/// Transaction::new()
+///     .stage(&routes, |r| r.with_route(dest, via))
+///     .stage(&limits, |l| l.with_limit(dest, 100))
+///     .commit(Duration::ms(50))?;
+/// ```
+pub struct Transaction<'a, E> {
+    stages: Vec<Box<dyn TransactionStage<E> + 'a>>,
+}
+
+impl<'a, E: 'a> Transaction<'a, E> {
+    pub fn new() -> Transaction<'a, E> {
+        Transaction { stages: Vec::new() }
+    }
+
+    /// Add a clone-and-swap stage. `prepare` builds the new value from the current one,
+    /// which replaces the current value on commit.
+    pub fn stage<T, M, F>(mut self, mutex: &'a MutexImpl<T, M>, prepare: F) -> Self
+    where
+        T: 'a,
+        M: MutexInnerImpl + 'a,
+        F: FnOnce(&T) -> Result<T, E> + 'a,
+    {
+        self.stages.push(Box::new(SwapStage {
+            mutex,
+            prepare: Some(prepare),
+            staged: None,
+        }));
+        self
+    }
+
+    /// Add a stage that modifies the value in place, for data that can't be cloned.
+    ///
+    /// `prepare` returns an undo token. If a later stage fails, `rollback` is called with
+    /// the token to restore the value.
+    pub fn stage_in_place<T, M, F, R, U>(
+        mut self,
+        mutex: &'a MutexImpl<T, M>,
+        prepare: F,
+        rollback: R,
+    ) -> Self
+    where
+        T: 'a,
+        M: MutexInnerImpl + 'a,
+        F: FnOnce(&mut T) -> Result<U, E> + 'a,
+        R: FnOnce(&mut T, U) + 'a,
+        U: 'a,
+    {
+        self.stages.push(Box::new(InPlaceStage {
+            mutex,
+            prepare: Some(prepare),
+            rollback: Some(rollback),
+            undo: None,
+        }));
+        self
+    }
+
+    /// Take all mutexes, waiting up to `max_wait` for each, and run the transaction.
+    pub fn commit<D: DurationTicks>(mut self, max_wait: D) -> Result<(), TransactionError<E>> {
+        let max_wait = Duration::ticks(max_wait.to_ticks());
+
+        // Lock order, with stages sharing a mutex locking it only once.
+        let mut order: Vec<usize> = (0..self.stages.len()).collect();
+        order.sort_by_key(|&i| self.stages[i].mutex_address());
+        order.dedup_by_key(|i| self.stages[*i].mutex_address());
+
+        for (taken, &i) in order.iter().enumerate() {
+            if let Err(e) = self.stages[i].take(max_wait) {
+                for &j in order[..taken].iter().rev() {
+                    self.stages[j].give();
+                }
+                return Err(TransactionError::Lock(e));
+            }
+        }
+
+        let mut result = Ok(());
+        for index in 0..self.stages.len() {
+            if let Err(error) = unsafe { self.stages[index].prepare() } {
+                for stage in self.stages[..index].iter_mut().rev() {
+                    unsafe { stage.rollback() };
+                }
+                result = Err(TransactionError::Stage { index, error });
+                break;
+            }
+        }
+
+        if result.is_ok() {
+            for stage in self.stages.iter_mut() {
+                unsafe { stage.apply() };
+            }
+        }
+
+        for &i in order.iter().rev() {
+            self.stages[i].give();
+        }
+
+        result
+    }
+}
+
+impl<'a, E: 'a> Default for Transaction<'a, E> {
+    fn default() -> Self {
+        Transaction::new()
+    }
+}
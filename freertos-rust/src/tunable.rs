@@ -0,0 +1,76 @@
+use crate::base::*;
+use crate::capacities::*;
+use crate::prelude::v1::*;
+// Tunables live in statics, so their atomics have to be const-initialised.
+use core::sync::atomic::{AtomicU32, Ordering};
+
+/// A named `u32` setting that can be changed at runtime and read from tasks or ISRs.
+///
+/// Tunables are statics, found by name once registered:
+///
+/// ```rust
+/// # use freertos_rs::*;
+/// static BAUD: Tunable = Tunable::new("uart.baud", 115_200);
+/// BAUD.register().unwrap();
+/// assert_eq!(find_tunable("uart.baud").unwrap().get(), 115_200);
+/// ```
+pub struct Tunable {
+    name: &'static str,
+    value: AtomicU32,
+}
+
+impl Tunable {
+    pub const fn new(name: &'static str, value: u32) -> Self {
+        Tunable {
+            name,
+            value: AtomicU32::new(value),
+        }
+    }
+
+    pub fn name(&self) -> &'static str {
+        self.name
+    }
+
+    pub fn get(&self) -> u32 {
+        self.value.load(Ordering::Relaxed)
+    }
+
+    pub fn set(&self, value: u32) {
+        self.value.store(value, Ordering::Relaxed);
+    }
+
+    /// Make the tunable findable by name. Registering it again does nothing, another
+    /// tunable of the same name fails with `StorageInUse`.
+    pub fn register(&'static self) -> Result<(), FreeRtosError> {
+        let index = TUNABLES.push(RegistryKind::Tunables, self, |t| t.name == self.name)?;
+        match TUNABLES.get(index) {
+            Some(t) if ptr::eq(t, self) => Ok(()),
+            _ => Err(FreeRtosError::StorageInUse),
+        }
+    }
+}
+
+impl fmt::Debug for Tunable {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_struct("Tunable")
+            .field("name", &self.name)
+            .field("value", &self.get())
+            .finish()
+    }
+}
+
+static TUNABLES: AppendOnlySlots<&'static Tunable, TUNABLE_CAPACITY> = AppendOnlySlots::new();
+
+/// The registered tunable called `name`. Can be called from ISRs.
+pub fn find_tunable(name: &str) -> Option<&'static Tunable> {
+    TUNABLES.iter().find(|t| t.name == name)
+}
+
+/// The registered tunables, in the order they were registered.
+pub fn tunables() -> Vec<&'static Tunable> {
+    TUNABLES.iter().collect()
+}
+
+pub(crate) fn tunable_fill() -> (usize, usize) {
+    (TUNABLES.len(), TUNABLES.capacity())
+}
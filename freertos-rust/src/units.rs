@@ -1,13 +1,14 @@
 use crate::base::FreeRtosTickType;
 use crate::prelude::v1::*;
 use crate::shim::*;
+use crate::ticks::tick_elapsed;
 
 pub trait FreeRtosTimeUnits {
     fn get_tick_period_ms() -> u32;
     fn get_max_wait() -> u32;
 }
 
-#[derive(Copy, Clone, Default)]
+#[derive(Copy, Clone, Default, Debug)]
 pub struct FreeRtosTimeUnitsShimmed;
 impl FreeRtosTimeUnits for FreeRtosTimeUnitsShimmed {
     #[inline]
@@ -78,3 +79,157 @@ where
         self.ticks
     }
 }
+
+/// Rounded up to whole ticks, so a timeout never ends early. Durations of
+/// `freertos_rs_max_wait()` ticks or more saturate there, which blocking calls treat as
+/// `Duration::infinite()`.
+impl DurationTicks for core::time::Duration {
+    fn to_ticks(&self) -> FreeRtosTickType {
+        ns_to_ticks(self.as_nanos())
+    }
+}
+
+/// Nanoseconds to ticks, rounded up and saturating at `freertos_rs_max_wait()`.
+pub(crate) fn ns_to_ticks(ns: u128) -> FreeRtosTickType {
+    let ns_per_tick = FreeRtosTimeUnitsShimmed::get_tick_period_ms() as u128 * 1_000_000;
+    let max_wait = FreeRtosTimeUnitsShimmed::get_max_wait();
+    let ticks = ns.div_ceil(ns_per_tick);
+    ticks.min(max_wait as u128) as FreeRtosTickType
+}
+
+/// The ticks to delay for so a sleep of `ns` never ends early: rounded up, plus one for
+/// the tick already under way.
+pub(crate) fn sleep_ticks(ns: u128) -> FreeRtosTickType {
+    match ns_to_ticks(ns) {
+        0 => 0,
+        ticks => ticks
+            .saturating_add(1)
+            .min(FreeRtosTimeUnitsShimmed::get_max_wait()),
+    }
+}
+
+/// Time left of `timeout` ticks counted from `start`, or `None` once it has passed.
+pub(crate) fn remaining_wait(
+    start: FreeRtosTickType,
+    timeout: FreeRtosTickType,
+) -> Option<Duration> {
+    if timeout == Duration::infinite().to_ticks() {
+        return Some(Duration::infinite());
+    }
+
+    let elapsed = tick_elapsed(unsafe { freertos_rs_xTaskGetTickCount() }, start);
+    if elapsed > timeout {
+        None
+    } else {
+        Some(Duration::ticks(timeout - elapsed))
+    }
+}
+
+impl<T> DurationImpl<T>
+where
+    T: FreeRtosTimeUnits + Copy,
+{
+    /// Format the duration right aligned in a fixed width column, for tables.
+    #[cfg(feature = "fmt")]
+    pub fn display_compact(&self) -> CompactDuration<T> {
+        CompactDuration(*self)
+    }
+}
+
+/// Formats with adaptive units, e.g. "350ms", "2.5s", "1m05s" or "∞" for an infinite wait.
+///
+/// Only integer math is used and nothing is allocated.
+#[cfg(feature = "fmt")]
+impl<T> fmt::Display for DurationImpl<T>
+where
+    T: FreeRtosTimeUnits + Copy,
+{
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        use core::fmt::Write;
+
+        if self.ticks == T::get_max_wait() {
+            return f.pad("∞");
+        }
+
+        let ms = self.ticks as u64 * T::get_tick_period_ms() as u64;
+        let mut buf = FmtBuffer::new();
+
+        if ms < 1000 {
+            write!(buf, "{}ms", ms)?;
+        } else if ms < 60 * 1000 {
+            let tenths = (ms % 1000) / 100;
+            if tenths == 0 {
+                write!(buf, "{}s", ms / 1000)?;
+            } else {
+                write!(buf, "{}.{}s", ms / 1000, tenths)?;
+            }
+        } else if ms < 60 * 60 * 1000 {
+            write!(buf, "{}m{:02}s", ms / (60 * 1000), (ms / 1000) % 60)?;
+        } else {
+            write!(
+                buf,
+                "{}h{:02}m",
+                ms / (60 * 60 * 1000),
+                (ms / (60 * 1000)) % 60
+            )?;
+        }
+
+        f.pad(buf.as_str())
+    }
+}
+
+/// A duration formatted right aligned in a fixed width column.
+#[cfg(feature = "fmt")]
+#[derive(Copy, Clone)]
+pub struct CompactDuration<T>(DurationImpl<T>);
+
+#[cfg(feature = "fmt")]
+impl<T> CompactDuration<T> {
+    pub const WIDTH: usize = 8;
+}
+
+#[cfg(feature = "fmt")]
+impl<T> fmt::Display for CompactDuration<T>
+where
+    T: FreeRtosTimeUnits + Copy,
+{
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{: >width$}", self.0, width = Self::WIDTH)
+    }
+}
+
+/// Stack buffer so a formatted value can be padded as a whole.
+#[cfg(feature = "fmt")]
+struct FmtBuffer {
+    buf: [u8; 24],
+    len: usize,
+}
+
+#[cfg(feature = "fmt")]
+impl FmtBuffer {
+    fn new() -> FmtBuffer {
+        FmtBuffer {
+            buf: [0; 24],
+            len: 0,
+        }
+    }
+
+    fn as_str(&self) -> &str {
+        // Only ever written to through write_str.
+        unsafe { core::str::from_utf8_unchecked(&self.buf[..self.len]) }
+    }
+}
+
+#[cfg(feature = "fmt")]
+impl fmt::Write for FmtBuffer {
+    fn write_str(&mut self, s: &str) -> fmt::Result {
+        let end = self.len + s.len();
+        if end > self.buf.len() {
+            return Err(fmt::Error);
+        }
+
+        self.buf[self.len..end].copy_from_slice(s.as_bytes());
+        self.len = end;
+        Ok(())
+    }
+}
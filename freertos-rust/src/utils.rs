@@ -28,6 +28,7 @@ pub fn shim_sanity_check() -> Result<(), TypeSizeError> {
         (31, mem::size_of::<FreeRtosTaskState>()),
         (32, mem::size_of::<FreeRtosUnsignedLong>()),
         (33, mem::size_of::<FreeRtosUnsignedShort>()),
+        (34, mem::size_of::<FreeRtosStackDepthType>()),
     ];
 
     for check in &checks {
@@ -45,20 +46,46 @@ pub fn shim_sanity_check() -> Result<(), TypeSizeError> {
     Ok(())
 }
 
-pub unsafe fn str_from_c_string(str: *const u8) -> Result<String, FreeRtosError> {
-    let mut buf = Vec::new();
-
-    let mut p = str;
-    loop {
-        if *p == 0 {
-            break;
-        }
-        buf.push(*p);
-        p = p.offset(1);
+pub unsafe fn bytes_from_c_string<'a>(str: *const u8) -> &'a [u8] {
+    let mut len = 0;
+    while *str.add(len) != 0 {
+        len += 1;
     }
 
-    match String::from_utf8(buf) {
+    core::slice::from_raw_parts(str, len)
+}
+
+pub unsafe fn str_from_c_string(str: *const u8) -> Result<String, FreeRtosError> {
+    match String::from_utf8(bytes_from_c_string(str).to_vec()) {
         Ok(s) => Ok(s),
         Err(_) => Err(FreeRtosError::StringConversionError),
     }
 }
+
+/// Fail compilation if a closure captures more than `max_bytes`, e.g. to keep large
+/// buffers from being moved into a task closure by accident. Evaluates to the closure.
+///
+/// ```ignore
+/// os.new_task("worker", 512, TaskPriority(2), assert_closure_size!(64, move |_, os| loop {
+///     // ...
+/// }))?;
+/// ```
+#[macro_export]
+macro_rules! assert_closure_size {
+    ($max_bytes:expr, $closure:expr) => {{
+        struct ClosureSize<F>(F);
+        impl<F> ClosureSize<F> {
+            const CHECK: () = assert!(
+                core::mem::size_of::<F>() <= $max_bytes,
+                "closure captures more than the allowed number of bytes"
+            );
+
+            fn checked(self) -> F {
+                let () = Self::CHECK;
+                self.0
+            }
+        }
+
+        ClosureSize($closure).checked()
+    }};
+}
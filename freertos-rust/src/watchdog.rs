@@ -0,0 +1,181 @@
+use crate::base::*;
+use crate::isr::*;
+use crate::mutex::*;
+use crate::operating_system::*;
+use crate::prelude::v1::*;
+use crate::shim::*;
+use crate::sync::atomic::{AtomicU32, Ordering};
+use crate::task::*;
+use crate::ticks::*;
+use crate::units::*;
+
+impl !ISRSafe for TaskWatchdog {}
+impl !ISRSafe for WatchdogHandle {}
+
+/// A registered task, shared by its `WatchdogHandle` and the monitor.
+struct WatchdogSlot {
+    name: TaskName,
+    period: FreeRtosTickType,
+    last_fed: AtomicU32,
+}
+
+struct WatchdogState {
+    slots: Mutex<Vec<Arc<WatchdogSlot>>>,
+    on_starved: Box<dyn Fn(&str) + Send + Sync>,
+}
+
+/// A software watchdog for tasks: each registered task has to `feed` its handle at least
+/// once per period, or the monitor task calls `on_starved` with its name.
+///
+/// Feeding is one atomic store. The monitor checks the registrations every tick, and
+/// reports a task once per miss: after a report, it is only reported again once it was
+/// fed and then missed a deadline again. Deadlines are kept as tick counts and compared
+/// with `tick_elapsed`, so they work across the tick count wrapping around, for periods
+/// up to half of its range.
+///
+/// ```rust
+/// # use freertos_rs::*;
+/// let watchdog = TaskWatchdog::start_monitor(os, TaskPriority(4), |name| {
+///     println!("task {} is stuck", name);
+/// })?;
+///
+/// os.new_task("worker", 512, TaskPriority(2), move |task, _| {
+///     let handle = watchdog.register(task, Duration::ms(500)).unwrap();
+///     loop {
+///         handle.feed();
+///         do_work();
+///     }
+/// })?;
+/// ```
+#[derive(Clone)]
+pub struct TaskWatchdog {
+    state: Arc<WatchdogState>,
+}
+
+impl TaskWatchdog {
+    /// Start the monitor task at `priority`, which calls `on_starved` for the tasks that
+    /// miss a deadline. It runs for as long as the program does.
+    pub fn start_monitor<P, F>(
+        os: FreeRTOS,
+        priority: P,
+        on_starved: F,
+    ) -> Result<TaskWatchdog, FreeRtosError>
+    where
+        P: Into<TaskPriority>,
+        F: Fn(&str) + Send + Sync + 'static,
+    {
+        let state = Arc::new(WatchdogState {
+            slots: Mutex::new(os, Vec::new())?,
+            on_starved: Box::new(on_starved),
+        });
+
+        let monitor = state.clone();
+        os.new_task("watchdog", 512, priority, move |_, os| {
+            // The slots reported and not fed since, with when they were last fed.
+            let mut reported: Vec<(Arc<WatchdogSlot>, FreeRtosTickType)> = Vec::new();
+            let mut starved = Vec::new();
+            loop {
+                os.delay(Duration::ticks(1));
+                monitor.scan(os.get_tick_count(), &mut reported, &mut starved);
+                for slot in starved.drain(..) {
+                    (monitor.on_starved)(slot_name(&slot));
+                }
+            }
+        })?;
+
+        Ok(TaskWatchdog { state })
+    }
+
+    /// Register `task`, which has to feed the returned handle at least every `period`.
+    /// The period starts now. Dropping the handle unregisters the task.
+    pub fn register<T: TaskHandle, D: DurationTicks>(
+        &self,
+        task: &T,
+        period: D,
+    ) -> Result<WatchdogHandle, FreeRtosError> {
+        let slot = Arc::new(WatchdogSlot {
+            name: TaskName::from_bytes(task.get_name_bytes()),
+            period: period.to_ticks(),
+            last_fed: AtomicU32::new(unsafe { freertos_rs_xTaskGetTickCount() }),
+        });
+        self.state
+            .slots
+            .lock(Duration::infinite())?
+            .push(slot.clone());
+
+        Ok(WatchdogHandle {
+            state: self.state.clone(),
+            slot,
+        })
+    }
+
+    /// The number of registered tasks.
+    pub fn registered(&self) -> usize {
+        self.state
+            .slots
+            .lock(Duration::infinite())
+            .map_or(0, |slots| slots.len())
+    }
+}
+
+impl WatchdogState {
+    /// Move the slots that newly missed their deadline at `now` to `starved`.
+    fn scan(
+        &self,
+        now: FreeRtosTickType,
+        reported: &mut Vec<(Arc<WatchdogSlot>, FreeRtosTickType)>,
+        starved: &mut Vec<Arc<WatchdogSlot>>,
+    ) {
+        let slots = match self.slots.lock(Duration::infinite()) {
+            Ok(slots) => slots,
+            Err(_) => return,
+        };
+        // Fed since they were reported, or unregistered.
+        reported.retain(|(slot, fed)| {
+            slot.last_fed.load(Ordering::Relaxed) == *fed
+                && slots.iter().any(|s| Arc::ptr_eq(s, slot))
+        });
+
+        for slot in slots.iter() {
+            let fed = slot.last_fed.load(Ordering::Relaxed);
+            if tick_elapsed(now, fed) <= slot.period
+                || reported.iter().any(|(s, _)| Arc::ptr_eq(s, slot))
+            {
+                continue;
+            }
+            reported.push((slot.clone(), fed));
+            starved.push(slot.clone());
+        }
+    }
+}
+
+fn slot_name(slot: &WatchdogSlot) -> &str {
+    slot.name.as_str().unwrap_or("")
+}
+
+/// The registration of a task with a `TaskWatchdog`. Unregisters the task when dropped.
+pub struct WatchdogHandle {
+    state: Arc<WatchdogState>,
+    slot: Arc<WatchdogSlot>,
+}
+
+impl WatchdogHandle {
+    /// Check in, starting a new period.
+    #[inline]
+    pub fn feed(&self) {
+        let now = unsafe { freertos_rs_xTaskGetTickCount() };
+        self.slot.last_fed.store(now, Ordering::Relaxed);
+    }
+
+    /// Unregister the task, e.g. before it shuts down or blocks for longer than its
+    /// period on purpose. The same as dropping the handle.
+    pub fn unregister(self) {}
+}
+
+impl Drop for WatchdogHandle {
+    fn drop(&mut self) {
+        if let Ok(mut slots) = self.state.slots.lock(Duration::infinite()) {
+            slots.retain(|s| !Arc::ptr_eq(s, &self.slot));
+        }
+    }
+}
@@ -0,0 +1,264 @@
+use crate::base::*;
+use crate::critical::*;
+use crate::isr::*;
+use crate::operating_system::*;
+use crate::prelude::v1::*;
+use crate::semaphore::*;
+use crate::ticks::*;
+use crate::units::*;
+
+impl !ISRSafe for WipLimiter {}
+impl !ISRSafe for Credit {}
+
+struct ParkedSlot {
+    generation: u16,
+    credit: Option<Credit>,
+}
+
+struct WipShared {
+    os: FreeRTOS,
+    semaphore: CountingSemaphore,
+    limit: u32,
+    /// When each outstanding credit was acquired, indexed by its token slot.
+    tokens: ExclusiveData<Vec<Option<FreeRtosTickType>>>,
+    parked: ExclusiveData<Vec<ParkedSlot>>,
+}
+
+unsafe impl Send for WipShared {}
+unsafe impl Sync for WipShared {}
+
+/// One acquired credit. Returned to the limiter when the last `Credit` holding it is
+/// dropped.
+struct Token {
+    shared: Arc<WipShared>,
+    slot: usize,
+}
+
+impl Drop for Token {
+    fn drop(&mut self) {
+        if let Ok(mut tokens) = self.shared.tokens.lock(&self.shared.os) {
+            tokens[self.slot] = None;
+        }
+        self.shared.semaphore.try_give();
+    }
+}
+
+/// Permission for one item to be in flight, attached to the item as it goes through
+/// the pipeline. The credit is returned when the `Credit` is dropped, whichever stage
+/// that happens in, so error paths that drop the item can't leak it.
+///
+/// `Credit` isn't `Copy`, so it can't go through a `Queue` itself. Park it with `park`
+/// and send the `CreditId` instead.
+pub struct Credit {
+    token: Arc<Token>,
+    /// Credits merged into this one.
+    merged: Vec<Arc<Token>>,
+}
+
+impl Credit {
+    fn holds(&self, token: &Arc<Token>) -> bool {
+        Arc::ptr_eq(&self.token, token) || self.merged.iter().any(|t| Arc::ptr_eq(t, token))
+    }
+
+    /// Split into `n` pieces for a fan-out stage. The credits are returned when every
+    /// piece was dropped.
+    pub fn split(self, n: usize) -> Vec<Credit> {
+        let mut pieces = Vec::with_capacity(n);
+        for _ in 0..n {
+            pieces.push(Credit {
+                token: self.token.clone(),
+                merged: self.merged.clone(),
+            });
+        }
+        pieces
+    }
+
+    /// Combine the credits of two items joined into one. Pieces of the same split are
+    /// only counted once.
+    pub fn merge(mut self, other: Credit) -> Credit {
+        for token in Some(other.token).into_iter().chain(other.merged) {
+            if !self.holds(&token) {
+                self.merged.push(token);
+            }
+        }
+        self
+    }
+
+    /// How many credits this holds, more than one after a `merge`.
+    pub fn count(&self) -> usize {
+        1 + self.merged.len()
+    }
+
+    /// Store the credit in its limiter, for the `CreditId` to travel through queues
+    /// along with the item. Get it back with `WipLimiter::redeem`.
+    pub fn park(self) -> CreditId {
+        let shared = self.token.shared.clone();
+        let mut parked = shared.parked.lock(&shared.os).unwrap();
+
+        let index = match parked.iter().position(|p| p.credit.is_none()) {
+            Some(index) => index,
+            None => {
+                parked.push(ParkedSlot {
+                    generation: 0,
+                    credit: None,
+                });
+                parked.len() - 1
+            }
+        };
+
+        let slot = &mut parked[index];
+        slot.credit = Some(self);
+        CreditId {
+            index: index as u16,
+            generation: slot.generation,
+        }
+    }
+}
+
+/// A parked `Credit`, small and `Copy` so it fits in queue items.
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub struct CreditId {
+    index: u16,
+    generation: u16,
+}
+
+/// A credit that has been outstanding for longer than expected, see `WipLimiter::reconcile`.
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub struct StaleCredit {
+    pub slot: usize,
+    pub age: FreeRtosTickType,
+}
+
+/// The result of `WipLimiter::reconcile`.
+#[derive(Debug, Clone)]
+pub struct WipReconciliation {
+    /// Credits taken from the semaphore and not given back.
+    pub outstanding: u32,
+    /// Credits held by live `Credit`s, parked or not.
+    pub live: u32,
+    /// Live credits older than the age given to `reconcile`: leaked with `mem::forget`,
+    /// parked and never redeemed, or stuck in a stage.
+    pub stale: Vec<StaleCredit>,
+}
+
+impl WipReconciliation {
+    /// Every outstanding credit is held by a live `Credit`, and none is stale.
+    pub fn is_consistent(&self) -> bool {
+        self.outstanding == self.live && self.stale.is_empty()
+    }
+}
+
+/// Limits how many items are in flight across a pipeline of queues and stages.
+///
+/// The producer acquires a `Credit` per item, blocking while `limit` credits are out,
+/// and the credit comes back once the item is dropped anywhere down the line.
+#[derive(Clone)]
+pub struct WipLimiter {
+    shared: Arc<WipShared>,
+}
+
+impl WipLimiter {
+    pub fn new(os: FreeRTOS, limit: u32) -> Result<WipLimiter, FreeRtosError> {
+        let mut tokens = Vec::with_capacity(limit as usize);
+        tokens.resize(limit as usize, None);
+
+        Ok(WipLimiter {
+            shared: Arc::new(WipShared {
+                os,
+                semaphore: CountingSemaphore::new(os, limit, limit)?,
+                limit,
+                tokens: ExclusiveData::new(tokens),
+                parked: ExclusiveData::new(Vec::new()),
+            }),
+        })
+    }
+
+    /// Take a credit, waiting up to `timeout` for one to be returned.
+    pub fn acquire_credit<D: DurationTicks>(&self, timeout: D) -> Result<Credit, FreeRtosError> {
+        self.shared.semaphore.take(timeout)?;
+
+        let now = self.shared.os.get_tick_count();
+        let slot = {
+            let mut tokens = self.shared.tokens.lock(&self.shared.os)?;
+            match tokens.iter().position(|t| t.is_none()) {
+                Some(slot) => {
+                    tokens[slot] = Some(now);
+                    slot
+                }
+                // Only if the semaphore was given outside of a `Credit`.
+                None => {
+                    tokens.push(Some(now));
+                    tokens.len() - 1
+                }
+            }
+        };
+
+        Ok(Credit {
+            token: Arc::new(Token {
+                shared: self.shared.clone(),
+                slot,
+            }),
+            merged: Vec::new(),
+        })
+    }
+
+    /// Take a parked credit back. `None` if it was already redeemed.
+    pub fn redeem(&self, id: CreditId) -> Option<Credit> {
+        let mut parked = self.shared.parked.lock(&self.shared.os).ok()?;
+        let slot = parked.get_mut(id.index as usize)?;
+        if slot.generation != id.generation {
+            return None;
+        }
+
+        let credit = slot.credit.take()?;
+        slot.generation = slot.generation.wrapping_add(1);
+        Some(credit)
+    }
+
+    pub fn limit(&self) -> u32 {
+        self.shared.limit
+    }
+
+    /// Credits that can be acquired right now.
+    pub fn available(&self) -> u32 {
+        self.shared.semaphore.get_count()
+    }
+
+    /// Credits acquired and not returned yet.
+    pub fn outstanding(&self) -> u32 {
+        self.shared.limit - self.available()
+    }
+
+    /// Compare the outstanding credits with the live ones, and list the credits that
+    /// were acquired more than `max_age` ago. Meant to be called periodically, with an
+    /// age well above the longest time an item takes through the pipeline.
+    pub fn reconcile<D: DurationTicks>(&self, max_age: D) -> WipReconciliation {
+        let max_age = max_age.to_ticks();
+
+        let (outstanding, live, stale) = {
+            let tokens = self.shared.tokens.lock(&self.shared.os).unwrap();
+            // Read under the lock, so no token in the table was acquired after it.
+            let now = self.shared.os.get_tick_count();
+            let live = tokens.iter().filter(|t| t.is_some()).count() as u32;
+            let stale = tokens
+                .iter()
+                .enumerate()
+                .filter_map(|(slot, acquired)| {
+                    let age = tick_elapsed(now, (*acquired)?);
+                    if age > max_age {
+                        Some(StaleCredit { slot, age })
+                    } else {
+                        None
+                    }
+                })
+                .collect();
+            (self.outstanding(), live, stale)
+        };
+
+        WipReconciliation {
+            outstanding,
+            live,
+            stale,
+        }
+    }
+}
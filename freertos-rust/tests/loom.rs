@@ -0,0 +1,140 @@
+//! Loom models of the crate's lock-free internals, see the `sync` module.
+//!
+//!     RUSTFLAGS="--cfg loom" cargo test -p freertos-rust --release --test loom
+//!
+//! Payloads are `loom::cell::UnsafeCell`s, so loom fails a model when a read of one
+//! isn't ordered after the write it should see. Readers run on threads of their own:
+//! loom misses some interleavings of a spawned thread with the one that spawned it.
+//!
+//! The fences of the notify calls need the kernel, the `notification_payload` example
+//! checks them on the simulator.
+#![cfg(loom)]
+
+use freertos_rust::*;
+use loom::cell::UnsafeCell;
+use loom::sync::atomic::{AtomicUsize, Ordering};
+use loom::sync::Arc;
+use loom::thread;
+
+/// A writer fills a payload, then sets the cell. A reader that sees the new value reads
+/// the whole payload.
+#[test]
+fn status_cell_publishes_the_payload_written_before_set() {
+    loom::model(|| {
+        let cell = Arc::new(StatusCell::new(0u32));
+        let payload = Arc::new(UnsafeCell::new(0u32));
+
+        let writer = {
+            let (cell, payload) = (cell.clone(), payload.clone());
+            thread::spawn(move || {
+                payload.with_mut(|p| unsafe { *p = 42 });
+                cell.set(1);
+            })
+        };
+
+        let reader = thread::spawn(move || {
+            if cell.get() == 1 {
+                assert_eq!(payload.with(|p| unsafe { *p }), 42);
+            }
+        });
+        writer.join().unwrap();
+        reader.join().unwrap();
+    });
+}
+
+/// Values are read whole, and a reader never goes back to an older one.
+#[test]
+fn status_cell_reads_are_whole_and_in_order() {
+    loom::model(|| {
+        let cell = Arc::new(StatusCell::new((0u32, 0u32)));
+
+        let writer = {
+            let cell = cell.clone();
+            thread::spawn(move || {
+                cell.set((1, 1));
+                cell.set((2, 2));
+            })
+        };
+
+        let reader = {
+            let cell = cell.clone();
+            thread::spawn(move || {
+                let first = cell.get();
+                let second = cell.get();
+                assert_eq!(first.0, first.1);
+                assert_eq!(second.0, second.1);
+                assert!(second.0 >= first.0);
+            })
+        };
+        writer.join().unwrap();
+        reader.join().unwrap();
+
+        assert_eq!(cell.get(), (2, 2));
+    });
+}
+
+/// A ring of payloads, and the number of them published, which a writer stores `Relaxed`
+/// after `publish_with_payload`.
+struct Ring {
+    slots: [UnsafeCell<u32>; 2],
+    published: AtomicUsize,
+}
+
+impl Ring {
+    fn new() -> Ring {
+        Ring {
+            slots: [UnsafeCell::new(0), UnsafeCell::new(0)],
+            published: AtomicUsize::new(0),
+        }
+    }
+}
+
+/// Publish two payloads to a reader, with or without the helpers.
+fn publish_two_payloads(helpers: bool) {
+    loom::model(move || {
+        let ring = Arc::new(Ring::new());
+
+        let writer = {
+            let ring = ring.clone();
+            thread::spawn(move || {
+                for (index, slot) in ring.slots.iter().enumerate() {
+                    let write = || slot.with_mut(|p| unsafe { *p = 42 + index as u32 });
+                    if helpers {
+                        publish_with_payload(write);
+                    } else {
+                        write();
+                    }
+                    ring.published.store(index + 1, Ordering::Relaxed);
+                }
+            })
+        };
+
+        let reader = thread::spawn(move || {
+            let published = ring.published.load(Ordering::Relaxed);
+            for (index, slot) in ring.slots.iter().enumerate().take(published) {
+                let read = || slot.with(|p| unsafe { *p });
+                let payload = if helpers {
+                    consume_payload(read)
+                } else {
+                    read()
+                };
+                assert_eq!(payload, 42 + index as u32);
+            }
+        });
+        writer.join().unwrap();
+        reader.join().unwrap();
+    });
+}
+
+/// The reader of a relaxed index reads every payload published before it.
+#[test]
+fn publish_with_payload_orders_a_relaxed_index() {
+    publish_two_payloads(true);
+}
+
+/// Without the helpers, reading the payloads of a relaxed index is a race.
+#[test]
+#[should_panic(expected = "Causality violation")]
+fn a_relaxed_index_without_the_helpers_is_a_race() {
+    publish_two_payloads(false);
+}